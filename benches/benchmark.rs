@@ -2,7 +2,14 @@ use std::{collections::BTreeMap, iter::repeat};
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use evalexpr::{build_operator_tree, ContextWithMutableVariables, HashMapContext, Node, Value};
-use exmex::{parse_with_default_ops, BinOp, FlatEx, Operator};
+#[cfg(feature = "rayon")]
+use exmex::parse_batch;
+#[cfg(feature = "simd")]
+use exmex::simd::{parse_f64x4, F64x4};
+use exmex::{
+    make_default_operators, parse_with_default_ops, BinOp, EvalBuffer, FlatEx, LargeFlatEx,
+    Operator,
+};
 use fasteval::{Compiler, Evaler, Instruction, Slab};
 use itertools::{izip, Itertools};
 
@@ -92,51 +99,44 @@ fn exmex_bench_parse(c: &mut Criterion) {
 
 fn exmex_parse_optimized<'a>(strings: &'a [&str]) -> Vec<FlatEx<'a, f64>> {
     let ops = vec![
-        Operator {
-            repr: "^",
-            bin_op: Some(BinOp {
+        Operator::make_bin(
+            "^",
+            BinOp {
                 apply: |a: f64, b| a.powf(b),
                 prio: 2,
-            }),
-            unary_op: None,
-        },
-        Operator {
-            repr: "*",
-            bin_op: Some(BinOp {
+            },
+        ),
+        Operator::make_bin(
+            "*",
+            BinOp {
                 apply: |a, b| a * b,
                 prio: 1,
-            }),
-            unary_op: None,
-        },
-        Operator {
-            repr: "/",
-            bin_op: Some(BinOp {
+            },
+        ),
+        Operator::make_bin(
+            "/",
+            BinOp {
                 apply: |a, b| a / b,
                 prio: 1,
-            }),
-            unary_op: None,
-        },
-        Operator {
-            repr: "+",
-            bin_op: Some(BinOp {
+            },
+        ),
+        Operator::make_bin_unary(
+            "+",
+            BinOp {
                 apply: |a, b| a + b,
                 prio: 0,
-            }),
-            unary_op: Some(|a| a),
-        },
-        Operator {
-            repr: "-",
-            bin_op: Some(BinOp {
+            },
+            |a| a,
+        ),
+        Operator::make_bin_unary(
+            "-",
+            BinOp {
                 apply: |a, b| a - b,
                 prio: 0,
-            }),
-            unary_op: Some(|a| (-a)),
-        },
-        Operator {
-            repr: "sin",
-            bin_op: None,
-            unary_op: Some(|a| a.sin()),
-        },
+            },
+            |a| -a,
+        ),
+        Operator::make_unary("sin", |a| a.sin()),
     ];
     strings
         .iter()
@@ -157,6 +157,244 @@ fn exmex_bench_eval(c: &mut Criterion) {
     run_benchmark(funcs, "exmex", c);
 }
 
+fn exmex_bench_eval_unchecked(c: &mut Criterion) {
+    let parsed_exprs = exmex_parse(&BENCH_EXPRESSIONS_STRS);
+    for expr in &parsed_exprs {
+        expr.check_vars(3).unwrap();
+    }
+    let funcs = parsed_exprs
+        .iter()
+        .map(|expr| move |x: f64| expr.eval_unchecked(&[x, BENCH_Y, BENCH_Z]))
+        .collect::<Vec<_>>();
+    run_benchmark(funcs, "exmex_unchecked", c);
+}
+
+fn exmex_bench_eval_vectorized(c: &mut Criterion) {
+    const N_POINTS: usize = 10_000;
+    let nested = BENCH_EXPRESSIONS_NAMES
+        .iter()
+        .position(|name| *name == "nested")
+        .unwrap();
+    let expr = parse_with_default_ops::<f64>(BENCH_EXPRESSIONS_STRS[nested]).unwrap();
+    let xs = (0..N_POINTS)
+        .map(|i| (BENCH_X_RANGE.1 * i) as f64 / N_POINTS as f64)
+        .collect::<Vec<_>>();
+    let ys = vec![BENCH_Y; N_POINTS];
+    let zs = vec![BENCH_Z; N_POINTS];
+    let mut out = vec![0.0; N_POINTS];
+    c.bench_function("exmex_per_point_nested", |b| {
+        b.iter(|| {
+            for (res, x) in out.iter_mut().zip(xs.iter()) {
+                *res = expr.eval(&[black_box(*x), BENCH_Y, BENCH_Z]).unwrap();
+            }
+        })
+    });
+    c.bench_function("exmex_vectorized_nested", |b| {
+        b.iter(|| {
+            expr.eval_vectorized(&[black_box(&xs), &ys, &zs], &mut out)
+                .unwrap()
+        })
+    });
+}
+
+#[cfg(feature = "simd")]
+fn exmex_bench_eval_simd(c: &mut Criterion) {
+    const N_POINTS: usize = 10_000;
+    let sin = BENCH_EXPRESSIONS_NAMES
+        .iter()
+        .position(|name| *name == "sin")
+        .unwrap();
+    let (expr, expr_simd) = parse_f64x4(BENCH_EXPRESSIONS_STRS[sin]).unwrap();
+    let xs = (0..N_POINTS)
+        .map(|i| (BENCH_X_RANGE.1 * i) as f64 / N_POINTS as f64)
+        .collect::<Vec<_>>();
+    let mut out = vec![0.0; N_POINTS];
+    c.bench_function("exmex_per_point_sin", |b| {
+        b.iter(|| {
+            for (res, x) in out.iter_mut().zip(xs.iter()) {
+                *res = expr.eval(&[black_box(*x), BENCH_Y, BENCH_Z]).unwrap();
+            }
+        })
+    });
+    let (ys, zs) = (F64x4::splat(BENCH_Y), F64x4::splat(BENCH_Z));
+    c.bench_function("exmex_simd_f64x4_sin", |b| {
+        b.iter(|| {
+            for (res, x) in out.chunks_exact_mut(4).zip(xs.chunks_exact(4)) {
+                let x = F64x4::from([x[0], x[1], x[2], x[3]]);
+                let batch = expr_simd.eval(&[black_box(x), ys, zs]).unwrap();
+                res.copy_from_slice(&batch.to_array());
+            }
+        })
+    });
+}
+
+#[cfg(not(feature = "simd"))]
+fn exmex_bench_eval_simd(_: &mut Criterion) {}
+
+fn exmex_bench_eval_buffer(c: &mut Criterion) {
+    // synthetic expression with 100 nodes that do not fit onto the stack
+    let text = (0..50)
+        .map(|i| format!("x^{}*sin(y-{})", i % 4, i))
+        .join("+");
+    let expr = parse_with_default_ops::<f64>(&text).unwrap();
+    let mut buffer = EvalBuffer::new();
+    for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+        let vars = [i as f64, BENCH_Y];
+        assert_eq!(
+            expr.eval(&vars).unwrap().to_bits(),
+            expr.eval_with_buffer(&vars, &mut buffer).unwrap().to_bits()
+        );
+    }
+    c.bench_function("exmex_100_nodes", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                black_box(expr.eval(&[black_box(i as f64), BENCH_Y]).unwrap());
+            }
+        })
+    });
+    c.bench_function("exmex_buffer_100_nodes", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                black_box(
+                    expr.eval_with_buffer(&[black_box(i as f64), BENCH_Y], &mut buffer)
+                        .unwrap(),
+                );
+            }
+        })
+    });
+}
+
+fn exmex_bench_large(c: &mut Criterion) {
+    // synthetic expression with 500 nodes
+    let text = (0..250)
+        .map(|i| format!("{}*sin(x-{})", i % 7 + 1, i))
+        .join("-");
+    let expr = parse_with_default_ops::<f64>(&text).unwrap();
+    let large: LargeFlatEx<f64> = expr.clone().with_nodes_on_stack();
+    for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+        let vars = [i as f64];
+        assert_eq!(
+            expr.eval(&vars).unwrap().to_bits(),
+            large.eval(&vars).unwrap().to_bits()
+        );
+    }
+    c.bench_function("exmex_500_nodes", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                black_box(expr.eval(&[black_box(i as f64)]).unwrap());
+            }
+        })
+    });
+    c.bench_function("exmex_large_500_nodes", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                black_box(large.eval(&[black_box(i as f64)]).unwrap());
+            }
+        })
+    });
+    c.bench_function("exmex_clone_500_nodes", |b| {
+        b.iter(|| black_box(black_box(&expr).clone()))
+    });
+    c.bench_function("exmex_large_clone_500_nodes", |b| {
+        b.iter(|| black_box(black_box(&large).clone()))
+    });
+}
+
+fn exmex_bench_partial_large(c: &mut Criterion) {
+    // synthetic polynomial with about 2000 nodes
+    let text = (0..667)
+        .map(|i| format!("{}*x^{}", i % 9 + 1, i % 4))
+        .join("+");
+    let expr = parse_with_default_ops::<f64>(&text).unwrap();
+    c.bench_function("exmex_partial_2000_nodes", |b| {
+        b.iter(|| black_box(black_box(&expr).clone().partial(0).unwrap()))
+    });
+}
+
+fn exmex_bench_parse_batch(c: &mut Criterion) {
+    // the bench expressions repeated like a large set of formulas loaded at once
+    let texts = BENCH_EXPRESSIONS_STRS
+        .iter()
+        .copied()
+        .cycle()
+        .take(N * 1000)
+        .collect::<Vec<_>>();
+    let ops = make_default_operators::<f64>();
+    c.bench_function("exmex_parse_4000", |b| {
+        b.iter(|| {
+            black_box(&texts)
+                .iter()
+                .map(|text| exmex::parse(text, &ops))
+                .collect::<Vec<_>>()
+        })
+    });
+    #[cfg(feature = "rayon")]
+    c.bench_function("exmex_parse_batch_4000", |b| {
+        b.iter(|| parse_batch(black_box(&texts), &ops))
+    });
+}
+
+fn exmex_bench_eval_cse(c: &mut Criterion) {
+    // the same expensive sub-expressions appear in every summand
+    let text = (0..20)
+        .map(|i| format!("sin(x*y)^{}*cos(x-y)+exp(-(x*x+y*y))*{}", i % 3, i))
+        .join("+");
+    let expr = parse_with_default_ops::<f64>(&text).unwrap();
+    let optimized = expr.clone().optimize_cse().unwrap();
+    for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+        let vars = [i as f64, BENCH_Y];
+        let reference = expr.eval(&vars).unwrap();
+        assert!((optimized.eval(&vars).unwrap() - reference).abs() <= 1e-12 * reference.abs());
+    }
+    c.bench_function("exmex_repeated", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                black_box(expr.eval(&[black_box(i as f64), BENCH_Y]).unwrap());
+            }
+        })
+    });
+    c.bench_function("exmex_cse_repeated", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                black_box(optimized.eval(&[black_box(i as f64), BENCH_Y]).unwrap());
+            }
+        })
+    });
+}
+
+fn exmex_bench_eval_with_grad(c: &mut Criterion) {
+    let expr = parse_with_default_ops::<f64>(BENCH_EXPRESSIONS_STRS[2]).unwrap();
+    let grad = expr.grad().unwrap();
+    for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+        let vars = [i as f64, BENCH_Y, BENCH_Z];
+        let (val, g) = expr.eval_with_grad(&vars).unwrap();
+        assert_float_eq(val, expr.eval(&vars).unwrap());
+        for (g_i, d_i) in g.iter().zip(grad.iter()) {
+            let reference = d_i.eval(&vars).unwrap();
+            assert!((g_i - reference).abs() <= 1e-12 * reference.abs().max(1.0));
+        }
+    }
+    c.bench_function("exmex_eval_and_partials", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                let vars = [black_box(i as f64), BENCH_Y, BENCH_Z];
+                black_box(expr.eval(&vars).unwrap());
+                for d_i in &grad {
+                    black_box(d_i.eval(&vars).unwrap());
+                }
+            }
+        })
+    });
+    c.bench_function("exmex_eval_with_grad", |b| {
+        b.iter(|| {
+            for i in BENCH_X_RANGE.0..BENCH_X_RANGE.1 {
+                let vars = [black_box(i as f64), BENCH_Y, BENCH_Z];
+                black_box(expr.eval_with_grad(&vars).unwrap());
+            }
+        })
+    });
+}
+
 fn evalexpr_parse(strings: &[&str]) -> Vec<(Node, HashMapContext)> {
     let parsed_exprs = strings.iter().map(|expr_str| {
         build_operator_tree(expr_str.replace("sin", "math::sin").as_str()).unwrap()
@@ -296,12 +534,21 @@ criterion_group!(
     benches,
     fasteval_bench_eval,
     exmex_bench_eval,
+    exmex_bench_eval_unchecked,
+    exmex_bench_eval_vectorized,
+    exmex_bench_eval_simd,
+    exmex_bench_eval_buffer,
+    exmex_bench_large,
+    exmex_bench_partial_large,
+    exmex_bench_eval_cse,
+    exmex_bench_eval_with_grad,
     meval_bench_eval,
     rsc_bench_eval,
     evalexpr_bench_eval,
     fasteval_bench_parse,
     exmex_bench_parse,
     exmex_bench_parse_optimized,
+    exmex_bench_parse_batch,
     meval_bench_parse,
     rsc_bench_parse,
     evalexpr_bench_parse,
@@ -0,0 +1,44 @@
+//! Compile-time parsing for [exmex](https://docs.rs/exmex). The macro
+//! [`static_expr!`](static_expr) parses a string literal with the default operators of
+//! exmex for `f64` while compiling and expands to an
+//! [`exmex::StaticEx`](https://docs.rs/exmex/latest/exmex/struct.StaticEx.html) constant.
+//! Hence, invalid expressions are compile errors and no parsing happens at runtime.
+//!
+//! ```rust
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! #
+//! use exmex::StaticEx;
+//! use exmex_macros::static_expr;
+//!
+//! const EXPR: StaticEx = static_expr!("x^2 + sin(y)");
+//! const VAR_NAMES: &[&str] = EXPR.var_names();
+//! assert_eq!(VAR_NAMES, ["x", "y"]);
+//! let flatex = EXPR.to_flatex()?;
+//! assert!((flatex.eval(&[2.0, 0.0])? - 4.0).abs() < 1e-12);
+//! #
+//! #     Ok(())
+//! # }
+//! ```
+//!
+//! The expansion refers to the crate `exmex`, which needs to be a dependency of the
+//! calling crate, too.
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, LitStr};
+
+/// Parses the string literal with the default operators of exmex for `f64` and expands
+/// to the corresponding `exmex::StaticEx`. Parse errors are reported as compile errors at
+/// the literal including the position in the expression.
+#[proc_macro]
+pub fn static_expr(input: TokenStream) -> TokenStream {
+    let literal = parse_macro_input!(input as LitStr);
+    match exmex::static_ex_source(&literal.value()) {
+        Ok(source) => source
+            .parse()
+            .expect("exmex generated invalid source for static expression"),
+        // messages of parse errors contain the position in the expression
+        Err(e) => syn::Error::new(literal.span(), e.to_string())
+            .to_compile_error()
+            .into(),
+    }
+}
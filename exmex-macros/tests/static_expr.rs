@@ -0,0 +1,43 @@
+use exmex::{parse_with_default_ops, StaticEx};
+use exmex_macros::static_expr;
+
+#[test]
+fn test_static_expr() {
+    const EXPR: StaticEx = static_expr!("x^2 + sin(y)");
+    const VAR_NAMES: &[&str] = EXPR.var_names();
+    assert_eq!(VAR_NAMES, ["x", "y"]);
+    let flatex = EXPR.to_flatex().unwrap();
+    assert!((flatex.eval(&[2.0, 0.0]).unwrap() - 4.0).abs() < 1e-12);
+
+    fn test(expr: StaticEx, text: &str, vars: &[f64]) {
+        let flatex = expr.to_flatex().unwrap();
+        let reference = parse_with_default_ops::<f64>(text).unwrap();
+        assert!(flatex.structure_eq(&reference, false).unwrap());
+        assert_eq!(
+            flatex.structure_hash(false).unwrap(),
+            reference.structure_hash(false).unwrap()
+        );
+        assert_eq!(flatex.var_names(), reference.var_names());
+        assert_eq!(
+            flatex.eval(vars).unwrap().to_bits(),
+            reference.eval(vars).unwrap().to_bits()
+        );
+    }
+    test(static_expr!("x^2 + sin(y)"), "x^2 + sin(y)", &[0.3, 1.7]);
+    test(static_expr!("2*3+z"), "2*3+z", &[0.5]);
+    test(
+        static_expr!("-(-(x*exp(y)))/{z}^0.1"),
+        "-(-(x*exp(y)))/{z}^0.1",
+        &[1.2, 0.4, 2.5],
+    );
+    test(static_expr!("1/0 - b"), "1/0 - b", &[1.0]);
+    test(static_expr!("cos(pi_half)"), "cos(pi_half)", &[1.5]);
+    test(
+        static_expr!("logb(2, x^2)*logb(logb(2, 8), -y+4)"),
+        "logb(2, x^2)*logb(logb(2, 8), -y+4)",
+        &[1.5, 0.5],
+    );
+
+    let grad = static_expr!("x*y").to_flatex().unwrap().grad().unwrap();
+    assert!((grad[0].eval(&[2.0, 3.0]).unwrap() - 3.0).abs() < 1e-12);
+}
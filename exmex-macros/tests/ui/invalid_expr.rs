@@ -0,0 +1,8 @@
+use exmex::StaticEx;
+use exmex_macros::static_expr;
+
+const EXPR: StaticEx = static_expr!("x^2 + sin(y) *");
+
+fn main() {
+    let _ = EXPR;
+}
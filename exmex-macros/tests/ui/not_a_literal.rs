@@ -0,0 +1,9 @@
+use exmex::StaticEx;
+use exmex_macros::static_expr;
+
+const TEXT: &str = "x + 2";
+const EXPR: StaticEx = static_expr!(TEXT);
+
+fn main() {
+    let _ = EXPR;
+}
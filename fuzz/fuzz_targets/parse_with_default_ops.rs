@@ -6,6 +6,16 @@ use exmex::parse_with_default_ops;
 
 fuzz_target!(|data: &[u8]| {
     if let Ok(s) = std::str::from_utf8(data){
-        let _ = parse_with_default_ops::<f64>(s);
+        if let Ok(expr) = parse_with_default_ops::<f64>(s) {
+            // parsed expressions are evaluated and written without panics
+            let vars = vec![0.5; expr.var_names().len()];
+            let _ = expr.eval(&vars);
+            // unparsing a re-parsed unparsed expression does not change the string
+            if let Ok(unparsed) = expr.unparse() {
+                if let Ok(reparsed) = parse_with_default_ops::<f64>(&unparsed) {
+                    assert_eq!(reparsed.unparse().ok(), Some(unparsed));
+                }
+            }
+        }
     }
 });
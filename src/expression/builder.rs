@@ -0,0 +1,268 @@
+use core::{fmt::Debug, mem};
+
+use smallvec::smallvec;
+
+use super::{
+    deep::{reset_vars, BinOpsWithReprs, DeepEx, DeepNode, UnaryOpWithReprs},
+    deep_details::find_overloaded_ops,
+    flat::{self, FlatEx},
+};
+use crate::{
+    alloc_prelude::*,
+    operators::{Operator, UnaryOp},
+    ExError,
+};
+
+#[derive(Clone, Debug)]
+enum BuilderNode<'a, T: Copy + Debug> {
+    Num(T),
+    Var(&'a str),
+    Unary {
+        repr: &'a str,
+        operand: Box<ExprBuilder<'a, T>>,
+    },
+    Bin {
+        repr: &'a str,
+        left: Box<ExprBuilder<'a, T>>,
+        right: Box<ExprBuilder<'a, T>>,
+    },
+}
+
+/// Constructs an expression from numbers, variables, and operators without a string,
+/// e.g., for code generation from an existing syntax tree. Operators are referred to by
+/// their representations and looked up when the expression is [`build`](ExprBuilder::build)
+/// against a list of operators. The structure of the builder defines the order of
+/// evaluation, i.e., operator priorities are not considered.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// // sin(x) + 2*y
+/// let builder = ExprBuilder::var("x")
+///     .apply_unary("sin")
+///     .combine(ExprBuilder::num(2.0).combine(ExprBuilder::var("y"), "*"), "+");
+/// let expr = builder.build(&make_default_operators::<f64>())?;
+/// assert_eq!(expr.var_names(), ["x", "y"]);
+/// assert!((expr.eval(&[0.0, 1.5])? - 3.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExprBuilder<'a, T: Copy + Debug> {
+    node: BuilderNode<'a, T>,
+}
+
+impl<'a, T: Copy + Debug> ExprBuilder<'a, T> {
+    /// Creates an expression that consists of the number `num`.
+    pub fn num(num: T) -> Self {
+        ExprBuilder {
+            node: BuilderNode::Num(num),
+        }
+    }
+
+    /// Creates an expression that consists of the variable `name`. Variables with the same
+    /// name are the same variable.
+    pub fn var(name: &'a str) -> Self {
+        ExprBuilder {
+            node: BuilderNode::Var(name),
+        }
+    }
+
+    /// Applies the unary operator with representation `repr` to the expression.
+    pub fn apply_unary(self, repr: &'a str) -> Self {
+        ExprBuilder {
+            node: BuilderNode::Unary {
+                repr,
+                operand: Box::new(self),
+            },
+        }
+    }
+
+    /// Applies the binary operator with representation `repr` to the expression and
+    /// `other` like [`FlatEx::combine`](FlatEx::combine), i.e., `self` is the left operand.
+    pub fn combine(self, other: Self, repr: &'a str) -> Self {
+        ExprBuilder {
+            node: BuilderNode::Bin {
+                repr,
+                left: Box::new(self),
+                right: Box::new(other),
+            },
+        }
+    }
+
+    /// Creates the expression with the operators `ops`. The variables are sorted
+    /// alphabetically as for parsed expressions.
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if the operators are invalid or if an operator
+    /// representation is not found in `ops` or is used as binary or unary operator while
+    /// the corresponding operation is missing.
+    ///
+    pub fn build(&self, ops: &[Operator<'a, T>]) -> Result<FlatEx<'a, T>, ExError> {
+        crate::validation::check_operators(ops)?;
+        flat::flatten(self.to_deepex(ops)?)
+    }
+
+    fn to_deepex(&self, ops: &[Operator<'a, T>]) -> Result<DeepEx<'a, T>, ExError> {
+        // nested operands are converted on an explicit stack such that deeply nested
+        // builders cannot overflow the call stack, each builder is converted once the
+        // expressions of all its nested operands are known
+        let mut stack = vec![(self, vec![])];
+        while let Some((builder, deepexes)) = stack.last_mut() {
+            let builder = *builder;
+            if let Some(operand) = builder.nested_operands().nth(deepexes.len()) {
+                stack.push((operand, vec![]));
+                continue;
+            }
+            let deepexes = mem::take(deepexes);
+            let deepex = builder.combine_operands(deepexes, ops)?;
+            stack.pop();
+            match stack.last_mut() {
+                Some((_, parent_deepexes)) => parent_deepexes.push(deepex),
+                None => return Ok(deepex),
+            }
+        }
+        Err(ExError::Internal(
+            "stack of expression builder ran empty".to_string(),
+        ))
+    }
+
+    /// Returns the operands that are converted to expressions before this builder, i.e.,
+    /// the operand of a unary operator and the operands of a binary operator that are
+    /// neither numbers nor variables.
+    fn nested_operands(&self) -> impl Iterator<Item = &Self> {
+        let (first, second) = match &self.node {
+            BuilderNode::Num(_) | BuilderNode::Var(_) => (None, None),
+            BuilderNode::Unary { operand, .. } => (Some(&**operand), None),
+            BuilderNode::Bin { left, right, .. } => (Some(&**left), Some(&**right)),
+        };
+        let is_unary = matches!(self.node, BuilderNode::Unary { .. });
+        first
+            .into_iter()
+            .chain(second)
+            .filter(move |operand| is_unary || operand.to_leaf().is_none())
+    }
+
+    /// Creates the expression of this builder from the expressions of its
+    /// [`nested_operands`](ExprBuilder::nested_operands).
+    fn combine_operands(
+        &self,
+        deepexes: Vec<DeepEx<'a, T>>,
+        ops: &[Operator<'a, T>],
+    ) -> Result<DeepEx<'a, T>, ExError> {
+        let find_op = |repr: &str| {
+            ops.iter()
+                .find(|op| op.repr == repr)
+                .ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))
+        };
+        let missing = || ExError::Internal("missing expression of nested operand".to_string());
+        let mut deepexes = deepexes.into_iter();
+        match &self.node {
+            BuilderNode::Num(_) | BuilderNode::Var(_) => {
+                let node = self.to_leaf().ok_or_else(missing)?;
+                let mut deepex =
+                    DeepEx::new(vec![node], BinOpsWithReprs::new(), UnaryOpWithReprs::new())?;
+                deepex.set_overloaded_ops(find_overloaded_ops(ops));
+                deepex.set_op_reprs(ops);
+                Ok(deepex)
+            }
+            BuilderNode::Unary { repr, .. } => {
+                let op = find_op(repr)?;
+                let unary_op = op
+                    .unary_op
+                    .ok_or_else(|| ExError::OperatorNotUnary(repr.to_string()))?;
+                let unary_op = UnaryOpWithReprs {
+                    reprs: vec![op.repr],
+                    positions: vec![op.unary_position],
+                    op: UnaryOp::from_vec(smallvec![unary_op]),
+                };
+                Ok(deepexes.next().ok_or_else(missing)?.operate_unary(unary_op))
+            }
+            BuilderNode::Bin { repr, left, right } => {
+                let op = find_op(repr)?;
+                let bin_op = op
+                    .bin_op
+                    .ok_or_else(|| ExError::OperatorNotBinary(repr.to_string()))?;
+                let bin_op = BinOpsWithReprs {
+                    reprs: vec![op.repr],
+                    ops: smallvec![bin_op],
+                };
+                // numbers and variables become nodes of this expression, everything else
+                // a sub-expression
+                let mut to_node = |operand: &Self| match operand.to_leaf() {
+                    Some(node) => Ok(node),
+                    None => Ok(DeepNode::expr(deepexes.next().ok_or_else(missing)?)),
+                };
+                let nodes = vec![to_node(left)?, to_node(right)?];
+                let mut deepex = DeepEx::new(nodes, bin_op, UnaryOpWithReprs::new())?;
+                deepex.set_overloaded_ops(find_overloaded_ops(ops));
+                deepex.set_op_reprs(ops);
+                let var_names = deepex.var_names().iter().copied().collect();
+                reset_vars(&mut deepex, var_names);
+                Ok(deepex)
+            }
+        }
+    }
+
+    /// Returns the node of a number or a variable.
+    fn to_leaf(&self) -> Option<DeepNode<'a, T>> {
+        match &self.node {
+            BuilderNode::Num(num) => Some(DeepNode::Num(*num, None)),
+            BuilderNode::Var(name) => Some(DeepNode::Var((0, *name))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{make_default_operators, parse, util::assert_float_eq_f64};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_builder() {
+    let ops = make_default_operators::<f64>();
+    let builder = ExprBuilder::var("x").apply_unary("sin").combine(
+        ExprBuilder::num(2.0).combine(ExprBuilder::var("y"), "*"),
+        "+",
+    );
+    let built = builder.build(&ops).unwrap();
+    let parsed = parse("sin(x) + 2*y", &ops).unwrap();
+    assert_eq!(built.var_names(), parsed.var_names());
+    for vars in [[0.0, 0.0], [1.5, -2.0], [-0.3, 4.25]] {
+        assert_float_eq_f64(built.eval(&vars).unwrap(), parsed.eval(&vars).unwrap());
+    }
+    assert_eq!(built.unparse().unwrap(), "sin(x)+2.0*y");
+
+    // variables are registered by name and the builder can be reused
+    let builder = ExprBuilder::var("z")
+        .combine(ExprBuilder::var("a"), "-")
+        .combine(ExprBuilder::var("z"), "*")
+        .apply_unary("-");
+    let built = builder.build(&ops).unwrap();
+    assert_eq!(built.var_names(), ["a", "z"]);
+    assert_float_eq_f64(built.eval(&[1.0, 3.0]).unwrap(), -6.0);
+    assert_float_eq_f64(built.partial(1).unwrap().eval(&[1.0, 3.0]).unwrap(), -5.0);
+    let built = ExprBuilder::num(2.0)
+        .combine(ExprBuilder::num(3.0), "^")
+        .build(&ops)
+        .unwrap();
+    assert_float_eq_f64(built.eval(&[]).unwrap(), 8.0);
+
+    let err = |builder: ExprBuilder<'_, f64>| builder.build(&ops).unwrap_err();
+    assert_eq!(
+        err(ExprBuilder::var("x").apply_unary("nope")),
+        ExError::OperatorNotFound("nope".to_string())
+    );
+    assert_eq!(
+        err(ExprBuilder::var("x").combine(ExprBuilder::var("y"), "sin")),
+        ExError::OperatorNotBinary("sin".to_string())
+    );
+    assert_eq!(
+        err(ExprBuilder::var("x").apply_unary("*")),
+        ExError::OperatorNotUnary("*".to_string())
+    );
+}
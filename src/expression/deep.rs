@@ -1,56 +1,92 @@
 use super::deep_details::{
-    self, find_overloaded_ops, OverloadedOps, ADD_REPR, DIV_REPR, MUL_REPR, SUB_REPR,
+    self, find_overloaded_ops, Definitions, OverloadedOps, ADD_REPR, DIV_REPR, MUL_REPR, POW_REPR,
+    SUB_REPR,
 };
 use crate::definitions::{N_NODES_ON_STACK, N_VARS_ON_STACK};
 
 use crate::{
-    operators,
-    operators::{BinOp, UnaryOp},
-    parser, ExParseError, Operator,
+    alloc_prelude::*,
+    operators::{BinOp, NaryOp, UnaryOp, UnaryPosition},
+    parser::{self, Optimizations, Span},
+    util::spilled_bytes,
+    ExError, FallibleOp, Operator, ParseOptions, ParseWarning, VarOrder,
 };
-use num::Float;
-use regex::Regex;
-use smallvec::{smallvec, SmallVec};
-use std::{
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use core::ops::Range;
+use core::{
+    cmp::Reverse,
     fmt,
     fmt::{Debug, Display, Formatter},
-    iter::repeat,
+    mem,
+    num::NonZeroU32,
     ops::{Add, Div, Mul, Sub},
     str::FromStr,
 };
+#[cfg(feature = "std")]
+use num::{Float, One, Zero};
+#[cfg(feature = "std")]
+use regex::Regex;
+use smallvec::{smallvec, SmallVec};
 
 pub type ExprIdxVec = SmallVec<[usize; N_NODES_ON_STACK]>;
 
 /// Container of binary operators of one expression.
 pub type BinOpVec<T> = SmallVec<[BinOp<T>; N_NODES_ON_STACK]>;
 
-/// A deep node can be an expression, a number, or
-/// a variable.
+/// A deep node can be an expression, a number, a variable, or a call of an n-ary
+/// operator. Sub-expressions are shared between clones and copied when they are changed,
+/// such that cloning an expression does not copy its sub-expressions.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum DeepNode<'a, T: Copy + Debug> {
-    Expr(DeepEx<'a, T>),
-    Num(T),
+    Expr(Arc<DeepEx<'a, T>>),
+    /// A number with the index of its spelling in the `literal_texts` of the containing
+    /// expression starting at 1, see
+    /// [`preserve_literal_text`](crate::ParseOptions::preserve_literal_text). Numbers that
+    /// result from constant folding are synthetic and have no spelling.
+    Num(T, Option<NonZeroU32>),
     /// The contained integer points to the index of the variable in the slice of
     /// variables passed to [`eval`](Expression::eval).
     Var((usize, &'a str)),
+    /// Call of the n-ary operator with representation `repr`. The arguments are kept as
+    /// separate expressions, since the operator needs all of their values at once.
+    NaryCall {
+        repr: &'a str,
+        op: NaryOp<T>,
+        args: Vec<DeepEx<'a, T>>,
+    },
 }
-impl<'a, T: Copy + Debug> DeepNode<'a, T>
-where
-    T: Float,
-{
-    pub fn zero() -> Self {
-        DeepNode::Num(T::from(0.0).unwrap())
+#[cfg(feature = "std")]
+impl<'a, T: Copy + Debug> DeepNode<'a, T> {
+    pub fn zero() -> Self
+    where
+        T: Zero,
+    {
+        DeepNode::Num(T::zero(), None)
     }
-    pub fn one() -> Self {
-        DeepNode::Num(T::from(1.0).unwrap())
+    pub fn one() -> Self
+    where
+        T: One,
+    {
+        DeepNode::Num(T::one(), None)
+    }
+}
+impl<'a, T: Copy + Debug> DeepNode<'a, T> {
+    /// Creates a node of the sub-expression `deepex`.
+    pub fn expr(deepex: DeepEx<'a, T>) -> Self {
+        DeepNode::Expr(Arc::new(deepex))
     }
 }
 impl<'a, T: Copy + Debug> Debug for DeepNode<'a, T> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             DeepNode::Expr(e) => write!(f, "{}", e),
-            DeepNode::Num(n) => write!(f, "{:?}", n),
+            DeepNode::Num(n, _) => write!(f, "{:?}", n),
             DeepNode::Var((_, var_name)) => write!(f, "{}", var_name),
+            DeepNode::NaryCall { repr, args, .. } => {
+                let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", repr, args.join(","))
+            }
         }
     }
 }
@@ -71,16 +107,28 @@ impl<'a, T: Copy> BinOpsWithReprs<'a, T> {
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct UnaryOpWithReprs<'a, T: Copy> {
     pub reprs: Vec<&'a str>,
+    /// Positions of the operators with the same index in `reprs`, used for unparsing.
+    pub positions: Vec<UnaryPosition>,
     pub op: UnaryOp<T>,
 }
 impl<'a, T: Copy> UnaryOpWithReprs<'a, T> {
     pub fn new() -> UnaryOpWithReprs<'a, T> {
         UnaryOpWithReprs {
             reprs: vec![],
+            positions: vec![],
             op: UnaryOp::new(),
         }
     }
 
+    /// Creates prefix unary operators that are applied starting with the last one.
+    pub fn prefix(reprs: Vec<&'a str>, op: UnaryOp<T>) -> UnaryOpWithReprs<'a, T> {
+        UnaryOpWithReprs {
+            positions: vec![UnaryPosition::Prefix; reprs.len()],
+            reprs,
+            op,
+        }
+    }
+
     pub fn append_front(&mut self, other: &mut UnaryOpWithReprs<'a, T>) {
         self.op.append_front(&mut other.op);
         self.reprs = other
@@ -89,47 +137,340 @@ impl<'a, T: Copy> UnaryOpWithReprs<'a, T> {
             .chain(self.reprs.iter())
             .copied()
             .collect();
+        self.positions = other
+            .positions
+            .iter()
+            .chain(self.positions.iter())
+            .copied()
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.op.clear();
+        self.reprs.clear();
+        self.positions.clear();
+    }
+
+    /// Returns the operators with indices in `range`, e.g., `1..len` are all operators
+    /// except the outermost one.
+    #[cfg(feature = "std")]
+    pub fn slice(&self, range: Range<usize>) -> UnaryOpWithReprs<'a, T> {
+        UnaryOpWithReprs {
+            reprs: self.reprs[range.clone()].to_vec(),
+            positions: self.positions[range.clone()].to_vec(),
+            op: self.op.slice(range),
+        }
+    }
+}
+
+/// Summary of a sub-expression that is passed to the predicate of
+/// [`extract_subexprs`](crate::FlatEx::extract_subexprs).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubExprInfo<'a> {
+    /// Names of the variables occurring in the sub-expression in alphabetical order.
+    pub var_names: Vec<&'a str>,
+    /// Number of numbers and variables in the sub-expression.
+    pub n_nodes: usize,
+    /// Nesting depth of the sub-expression, 1 if it does not contain further
+    /// sub-expressions.
+    pub depth: usize,
+    /// Representation of the operator that is applied last, i.e., the outermost unary
+    /// operator or the binary operator with the lowest priority. `None` for a single
+    /// number or variable.
+    pub root_op_repr: Option<&'a str>,
+}
+
+pub fn reset_vars<'a, T: Copy + Debug>(
+    deepex: &mut DeepEx<'a, T>,
+    new_var_names: SmallVec<[&'a str; N_VARS_ON_STACK]>,
+) {
+    // sub-expressions are visited on an explicit stack such that deeply nested expressions
+    // cannot overflow the call stack
+    let mut stack = vec![deepex];
+    while let Some(deepex) = stack.pop() {
+        for node in &mut deepex.nodes {
+            match node {
+                DeepNode::Expr(e) => stack.push(Arc::make_mut(e)),
+                DeepNode::NaryCall { args, .. } => stack.extend(args.iter_mut()),
+                DeepNode::Var((i, var_name)) => {
+                    for (new_idx, new_name) in new_var_names.iter().enumerate() {
+                        if var_name == new_name {
+                            *i = new_idx;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        deepex.var_names = new_var_names.clone();
+    }
+}
+
+/// Applies `f` to `deepex` and all its sub-expressions including the arguments of calls,
+/// where the sub-expressions of an expression are visited before the expression itself.
+/// Sub-expressions are moved out of their parents onto an explicit stack while they are
+/// visited such that deeply nested expressions cannot overflow the call stack.
+fn for_each_post_order<'a, T: Copy + Debug>(
+    deepex: &mut DeepEx<'a, T>,
+    mut f: impl FnMut(&mut DeepEx<'a, T>),
+) {
+    /// Sub-expression that has been moved out of its parent.
+    enum Sub<'a, T: Copy + Debug> {
+        Expr(Arc<DeepEx<'a, T>>),
+        Arg(Box<DeepEx<'a, T>>),
+    }
+    /// Remaining and visited arguments of a call.
+    type CallArgs<'a, T> = (vec::IntoIter<DeepEx<'a, T>>, Vec<DeepEx<'a, T>>);
+    /// Expression whose sub-expressions are visited, where `sub` is `None` for the root.
+    struct Frame<'a, T: Copy + Debug> {
+        sub: Option<Sub<'a, T>>,
+        /// Index of the next node to be visited.
+        node_idx: usize,
+        /// Arguments of the call before `node_idx`.
+        call: Option<CallArgs<'a, T>>,
+    }
+    fn deepex_of<'b, 'a, T: Copy + Debug>(
+        sub: &'b mut Option<Sub<'a, T>>,
+        root: &'b mut DeepEx<'a, T>,
+    ) -> &'b mut DeepEx<'a, T> {
+        match sub {
+            None => root,
+            Some(Sub::Expr(e)) => Arc::make_mut(e),
+            Some(Sub::Arg(arg)) => arg,
+        }
+    }
+    let new_frame = |sub| Frame {
+        sub,
+        node_idx: 0,
+        call: None,
+    };
+    let mut frames = vec![new_frame(None)];
+    while let Some(frame) = frames.last_mut() {
+        let current = deepex_of(&mut frame.sub, deepex);
+        if let Some((args, visited_args)) = &mut frame.call {
+            if let Some(arg) = args.next() {
+                frames.push(new_frame(Some(Sub::Arg(Box::new(arg)))));
+            } else {
+                if let Some(DeepNode::NaryCall { args, .. }) =
+                    current.nodes.get_mut(frame.node_idx - 1)
+                {
+                    *args = mem::take(visited_args);
+                }
+                frame.call = None;
+            }
+            continue;
+        }
+        let next_sub = current.nodes[frame.node_idx..]
+            .iter()
+            .position(|node| matches!(node, DeepNode::Expr(_) | DeepNode::NaryCall { .. }));
+        if let Some(offset) = next_sub {
+            let node = &mut current.nodes[frame.node_idx + offset];
+            frame.node_idx += offset + 1;
+            match node {
+                DeepNode::NaryCall { args, .. } => {
+                    frame.call = Some((mem::take(args).into_iter(), vec![]));
+                }
+                _ => {
+                    if let DeepNode::Expr(e) = mem::replace(node, DeepNode::Var((0, ""))) {
+                        frames.push(new_frame(Some(Sub::Expr(e))));
+                    }
+                }
+            }
+            continue;
+        }
+        f(current);
+        let sub = frames.pop().and_then(|frame| frame.sub);
+        let (Some(sub), Some(parent)) = (sub, frames.last_mut()) else {
+            break;
+        };
+        match sub {
+            Sub::Arg(arg) => {
+                if let Some((_, visited_args)) = &mut parent.call {
+                    visited_args.push(*arg);
+                }
+            }
+            Sub::Expr(e) => {
+                deepex_of(&mut parent.sub, deepex).nodes[parent.node_idx - 1] = DeepNode::Expr(e);
+            }
+        }
     }
 }
 
 /// A deep expression evaluates co-recursively since its nodes can contain other deep
-/// expressions.
+/// expressions. In contrast to a [`FlatEx`](crate::FlatEx), it keeps the nested structure
+/// of the parsed string and can be combined with other expressions via `+`, `-`, `*`, `/`,
+/// and [`pow`](DeepEx::pow). For evaluation, it is turned into a [`FlatEx`](crate::FlatEx)
+/// with [`flatten`](crate::flatten).
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, flatten};
+/// let x_squared = DeepEx::<f64>::from_str("x^2")?;
+/// let y_plus_one = DeepEx::<f64>::from_str("y+1")?;
+/// let expr = (x_squared * y_plus_one).pow(DeepEx::from_str("0.5")?)?;
+/// assert_eq!(expr.var_names(), ["x", "y"]);
+/// assert_eq!(expr.unparse(), "(x^2.0*(y+1.0))^0.5");
+/// let flatex = flatten(expr)?;
+/// assert!((flatex.eval(&[3.0, 3.0])? - 6.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Variables
+///
+/// Variables are identified by their names. The variables of a combined expression are
+/// the union of the variables of its operands and the n-th value passed to
+/// [`eval`](crate::FlatEx::eval) belongs to the n-th of its [`var_names`](DeepEx::var_names).
+/// Hence, the index of a variable in a combined expression can differ from its index in
+/// the operands, e.g., `y` has index 0 in `y+1` but index 1 in the product above.
+///
+/// # Panics
+///
+/// The operators `+`, `-`, `*`, and `/` panic if the left operand has been parsed with
+/// operators that do not contain them. Use [`try_operate_overloaded`](DeepEx::try_operate_overloaded)
+/// to obtain an error instead.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct DeepEx<'a, T: Copy + Debug> {
     /// Nodes can be numbers, variables, or other expressions.
     nodes: Vec<DeepNode<'a, T>>,
     /// Binary operators applied to the nodes according to their priority.
-    pub bin_ops: BinOpsWithReprs<'a, T>,
+    pub(crate) bin_ops: BinOpsWithReprs<'a, T>,
     /// Unary operators are applied to the result of evaluating all nodes with all
     /// binary operators.
     unary_op: UnaryOpWithReprs<'a, T>,
-    overloaded_ops: Option<OverloadedOps<'a, T>>,
+    overloaded_ops: Option<Box<OverloadedOps<'a, T>>>,
     var_names: SmallVec<[&'a str; N_VARS_ON_STACK]>,
+    /// Range of the parsed string this expression stems from including its unary
+    /// operators. Only set for expressions with unary operators created by the parser.
+    span: Option<Span>,
+    /// Spellings of the numbers of this expression in the parsed string. Empty unless
+    /// the expression has been parsed with
+    /// [`preserve_literal_text`](crate::ParseOptions::preserve_literal_text).
+    literal_texts: Vec<&'a str>,
+    /// Optimizations that are applied when compiling, see
+    /// [`Semantics`](crate::Semantics).
+    optimizations: Optimizations,
+    /// Order of `var_names`, which is kept by operations that change the variables.
+    var_order: VarOrder,
+    /// Representations of all operators the expression has been parsed with. Empty if
+    /// unknown. Used to decide whether variable names need curly braces when unparsing.
+    op_reprs: Arc<[&'a str]>,
+    /// Fallible operations of the operators the expression has been parsed with by their
+    /// representations, see [`FallibleOp`](FallibleOp).
+    fallible_ops: Arc<[(&'a str, FallibleOp<T>)]>,
+    /// Priorities of the binary variants of the operators the expression has been parsed
+    /// with by their representations. Used to decide where prefix operators need
+    /// parentheses when unparsing.
+    bin_prios: Arc<[(&'a str, i32)]>,
+}
+
+/// Returns the index of `text` in `literal_texts` starting at 1 and adds it if necessary.
+pub fn intern_literal<'a>(literal_texts: &mut Vec<&'a str>, text: &'a str) -> NonZeroU32 {
+    let idx = match literal_texts.iter().position(|t| *t == text) {
+        Some(idx) => idx,
+        None => {
+            literal_texts.push(text);
+            literal_texts.len() - 1
+        }
+    };
+    NonZeroU32::new(idx as u32 + 1).unwrap()
+}
+
+/// Returns the priority of the binary variant of the prefix operator `repr` in `bin_prios`.
+/// Without known priorities, symbolic operators such as `-` are assumed to have the lowest
+/// priority.
+fn bin_prio_of_prefix_op(repr: &str, bin_prios: &[(&str, i32)]) -> Option<i32> {
+    if bin_prios.is_empty() {
+        repr.starts_with(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .then_some(i32::MIN)
+    } else {
+        bin_prios
+            .iter()
+            .find(|(r, _)| *r == repr)
+            .map(|(_, prio)| *prio)
+    }
+}
+
+/// Returns the lowest priority of the binary variants of the prefix operators the string of
+/// a node starts with. Without known priorities, a leading symbolic operator such as `-` is
+/// assumed to have the lowest priority.
+fn leading_prefix_op_prio(node_str: &str, bin_prios: &[(&str, i32)]) -> Option<i32> {
+    if bin_prios.is_empty() {
+        return node_str
+            .starts_with(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '(' | '{')))
+            .then_some(i32::MIN);
+    }
+    let mut rest = node_str;
+    let mut min_prio = None;
+    while let Some((repr, prio)) = bin_prios
+        .iter()
+        .filter(|(repr, _)| !repr.is_empty() && rest.starts_with(repr))
+        .max_by_key(|(repr, _)| repr.len())
+    {
+        min_prio = Some(min_prio.map_or(*prio, |min_prio: i32| min_prio.min(*prio)));
+        rest = &rest[repr.len()..];
+    }
+    min_prio
 }
 
 impl<'a, T: Copy + Debug> DeepEx<'a, T> {
-    /// Evaluates all operators with numbers as operands.
+    /// Evaluates all operators with numbers as operands. Sub-expressions that consist of
+    /// a single number are replaced by the number with their unary operators applied.
     pub fn compile(&mut self) {
+        if !self.optimizations.fold_constants {
+            return;
+        }
         // change from expression to number if an expression contains only a number
         for node in &mut self.nodes {
+            if let DeepNode::NaryCall { op, args, .. } = node {
+                let values = args
+                    .iter()
+                    .map(|arg| match arg.nodes.as_slice() {
+                        [DeepNode::Num(n, _)] if arg.unary_op.op.len() == 0 => Some(*n),
+                        _ => None,
+                    })
+                    .collect::<Option<SmallVec<[T; N_NODES_ON_STACK]>>>();
+                if let Some(values) = values {
+                    *node = DeepNode::Num((op.apply)(&values), None);
+                }
+            }
             if let DeepNode::Expr(ref e) = node {
                 if e.nodes.len() == 1 {
-                    if let DeepNode::Num(n) = e.nodes[0] {
-                        *node = DeepNode::Num(n);
+                    if let DeepNode::Num(n, literal) = e.nodes[0] {
+                        // the spelling is kept if no unary operator changes the value
+                        let literal = match (literal, e.unary_op.op.len()) {
+                            (Some(idx), 0) => Some(intern_literal(
+                                &mut self.literal_texts,
+                                e.literal_texts[idx.get() as usize - 1],
+                            )),
+                            _ => None,
+                        };
+                        *node = DeepNode::Num(e.unary_op.op.apply(n), literal);
                     }
                 }
             };
         }
-        let prio_indices = deep_details::prioritized_indices(&self.bin_ops.ops, &self.nodes);
+        let prio_indices =
+            deep_details::prioritized_indices(&self.bin_ops, &self.nodes, self.optimizations);
+        #[cfg(debug_assertions)]
+        deep_details::assert_prioritized_indices(
+            &prio_indices,
+            &self.bin_ops,
+            &self.nodes,
+            self.optimizations,
+        );
         let mut num_inds = prio_indices.clone();
         let mut used_prio_indices = ExprIdxVec::new();
         for (i, &bin_op_idx) in prio_indices.iter().enumerate() {
             let num_idx = num_inds[i];
             let node_1 = &self.nodes[num_idx];
             let node_2 = &self.nodes[num_idx + 1];
-            if let (DeepNode::Num(num_1), DeepNode::Num(num_2)) = (node_1, node_2) {
+            if let (DeepNode::Num(num_1, _), DeepNode::Num(num_2, _)) = (node_1, node_2) {
                 let bin_op_result = (self.bin_ops.ops[bin_op_idx].apply)(*num_1, *num_2);
-                self.nodes[num_idx] = DeepNode::Num(bin_op_result);
+                self.nodes[num_idx] = DeepNode::Num(bin_op_result, None);
                 self.nodes.remove(num_idx + 1);
                 // reduce indices after removed position
                 for num_idx_after in num_inds.iter_mut() {
@@ -158,38 +499,462 @@ impl<'a, T: Copy + Debug> DeepEx<'a, T> {
         self.bin_ops.reprs = resulting_reprs;
 
         if self.nodes.len() == 1 {
-            if let DeepNode::Num(n) = self.nodes[0] {
-                self.nodes[0] = DeepNode::Num(self.unary_op.op.apply(n));
-                self.unary_op.op.clear();
-                self.unary_op.reprs.clear();
+            if let DeepNode::Num(n, literal) = self.nodes[0] {
+                let literal = literal.filter(|_| self.unary_op.op.len() == 0);
+                self.nodes[0] = DeepNode::Num(self.unary_op.op.apply(n), literal);
+                self.unary_op.clear();
             }
         }
     }
 
+    /// Replaces each variable that has a value in `values`, which contains an entry for
+    /// each variable, by the value and folds the resulting constants. The remaining
+    /// variables are re-indexed densely.
+    pub(crate) fn bind(&self, values: &[Option<T>]) -> DeepEx<'a, T> {
+        let mut bound = self.clone();
+        for_each_post_order(&mut bound, |deepex| {
+            for node in &mut deepex.nodes {
+                if let DeepNode::Var((idx, _)) = node {
+                    if let Some(value) = values[*idx] {
+                        *node = DeepNode::Num(value, None);
+                    }
+                }
+            }
+            // sub-expressions are compiled first such that they can be folded
+            deepex.compile();
+        });
+        let var_names = self
+            .var_names
+            .iter()
+            .zip(values.iter())
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| *name)
+            .collect();
+        reset_vars(&mut bound, var_names);
+        bound
+    }
+
+    /// Replaces each occurrence of the variable with index `var_idx` by the expression
+    /// `sub` and folds the resulting constants. The variables of the result are the
+    /// remaining variables of `self` and the variables of `sub`. Variables of `sub` are not
+    /// substituted, even if one of them has the replaced name.
+    pub(crate) fn substitute(&self, var_idx: usize, sub: &DeepEx<'a, T>) -> DeepEx<'a, T> {
+        let mut var_names = SmallVec::<[&'a str; N_VARS_ON_STACK]>::new();
+        for (idx, name) in self.var_names.iter().enumerate() {
+            let names = if idx == var_idx {
+                &sub.var_names[..]
+            } else {
+                core::slice::from_ref(name)
+            };
+            for name in names {
+                if !var_names.contains(name) {
+                    var_names.push(*name);
+                }
+            }
+        }
+        if self.var_order == VarOrder::Alphabetical {
+            var_names.sort_unstable();
+        }
+        // a single node is inserted without the surrounding expression to avoid redundant
+        // parentheses, unless it is a number whose spelling is stored in `sub`
+        let sub_node = match &sub.nodes[..] {
+            [node @ (DeepNode::Var(_) | DeepNode::Expr(_) | DeepNode::NaryCall { .. })]
+                if sub.bin_ops.ops.is_empty() && sub.unary_op.op.len() == 0 =>
+            {
+                node.clone()
+            }
+            _ => DeepNode::expr(sub.clone()),
+        };
+        let mut substituted = self.clone();
+        for_each_post_order(&mut substituted, |deepex| {
+            for node in &mut deepex.nodes {
+                if matches!(node, DeepNode::Var((idx, _)) if *idx == var_idx) {
+                    *node = sub_node.clone();
+                }
+            }
+            deepex.compile();
+        });
+        let mut op_reprs = self.op_reprs.to_vec();
+        for repr in sub.op_reprs.iter() {
+            if !op_reprs.contains(repr) {
+                op_reprs.push(repr);
+            }
+        }
+        substituted.op_reprs = op_reprs.into();
+        let mut fallible_ops = self.fallible_ops.to_vec();
+        for (repr, fallible) in sub.fallible_ops.iter() {
+            if self.fallible_op(repr).is_none() {
+                fallible_ops.push((repr, *fallible));
+            }
+        }
+        substituted.fallible_ops = fallible_ops.into();
+        let mut bin_prios = self.bin_prios.to_vec();
+        for (repr, prio) in sub.bin_prios.iter() {
+            if !bin_prios.iter().any(|(r, _)| r == repr) {
+                bin_prios.push((repr, *prio));
+            }
+        }
+        substituted.bin_prios = bin_prios.into();
+        reset_vars(&mut substituted, var_names);
+        substituted
+    }
+
+    /// Returns the number of variables of the expression.
     pub fn n_vars(&self) -> usize {
         self.var_names.len()
     }
 
-    pub fn new(
+    /// Returns the names of the variables in the order that is expected by
+    /// [`eval`](crate::FlatEx::eval) after [`flatten`](crate::flatten)ing.
+    pub fn var_names(&self) -> &[&'a str] {
+        &self.var_names
+    }
+
+    /// Returns approximately the number of bytes the expression occupies on the heap.
+    /// Sub-expressions that are shared with clones are counted for each of their owners.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        let size_of_deepex = mem::size_of::<DeepEx<'a, T>>();
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| match node {
+                DeepNode::Expr(expr) => size_of_deepex + expr.heap_bytes(),
+                DeepNode::NaryCall { args, .. } => {
+                    args.capacity() * size_of_deepex
+                        + args.iter().map(DeepEx::heap_bytes).sum::<usize>()
+                }
+                DeepNode::Num(..) | DeepNode::Var(_) => 0,
+            })
+            .sum::<usize>();
+        let size_of_str = mem::size_of::<&str>();
+        self.nodes.capacity() * mem::size_of::<DeepNode<'a, T>>()
+            + nodes
+            + self.bin_ops.reprs.capacity() * size_of_str
+            + spilled_bytes(&self.bin_ops.ops)
+            + self.unary_op.reprs.capacity() * size_of_str
+            + self.unary_op.positions.capacity() * mem::size_of::<UnaryPosition>()
+            + self
+                .overloaded_ops
+                .as_ref()
+                .map_or(0, |_| mem::size_of::<OverloadedOps<'a, T>>())
+            + spilled_bytes(&self.var_names)
+            + self.literal_texts.capacity() * size_of_str
+            + self.op_reprs.len() * size_of_str
+            + self.fallible_ops.len() * mem::size_of::<(&str, FallibleOp<T>)>()
+            + self.bin_prios.len() * mem::size_of::<(&str, i32)>()
+    }
+
+    /// Collects name and index of each variable occurring in this expression.
+    fn occurring_vars(&self, found: &mut SmallVec<[(&'a str, usize); N_VARS_ON_STACK]>) {
+        // the nodes are visited in the order of the string on an explicit stack
+        let mut stack = self.nodes.iter().rev().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            match node {
+                DeepNode::Num(..) => (),
+                DeepNode::Var((idx, name)) => {
+                    if !found.iter().any(|(n, _)| n == name) {
+                        found.push((name, *idx));
+                    }
+                }
+                DeepNode::Expr(e) => stack.extend(e.nodes.iter().rev()),
+                DeepNode::NaryCall { args, .. } => {
+                    stack.extend(args.iter().rev().flat_map(|arg| arg.nodes.iter().rev()))
+                }
+            }
+        }
+    }
+
+    /// Returns the nesting depth of the sub-expressions, e.g., 0 for `x+1`, 1 for `(x+1)*y`,
+    /// and 2 for `((x+1)*y)^2`. Operands of unary operators and arguments of n-ary
+    /// operators are sub-expressions as well, e.g., `sin(cos(x))` has depth 2.
+    pub fn depth(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| match node {
+                DeepNode::Expr(e) => 1 + e.depth(),
+                DeepNode::NaryCall { args, .. } => {
+                    1 + args.iter().map(DeepEx::depth).max().unwrap_or(0)
+                }
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns whether the variable with index `var_idx` occurs in this expression.
+    pub fn contains_var(&self, var_idx: usize) -> bool {
+        let mut found = SmallVec::<[(&'a str, usize); N_VARS_ON_STACK]>::new();
+        self.occurring_vars(&mut found);
+        found.iter().any(|(_, idx)| *idx == var_idx)
+    }
+
+    /// Evaluates the nested expression directly without [`flatten`](crate::flatten)ing it.
+    /// The binary operators of each sub-expression are applied by priority and from left
+    /// to right for equal priorities, then its unary operators. This is slower than
+    /// [`FlatEx::eval`](crate::FlatEx::eval) but simple, e.g., for cross-checking custom
+    /// operators. Flat expressions with [`Semantics::Fast`](crate::Semantics::Fast) may
+    /// evaluate pairs of constants earlier and thereby round differently.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let deepex = DeepEx::<f64>::from_str("2*(x-y)^2")?;
+    /// assert!((deepex.eval(&[1.0, 4.0])? - 18.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the length of `vars` differs from the number of variables or a
+    /// [fallible operation](FallibleOp) fails, we return an [`ExError`](ExError).
+    ///
+    pub fn eval(&self, vars: &[T]) -> Result<T, ExError> {
+        if vars.len() != self.n_vars() {
+            return Err(ExError::WrongNumberOfVars {
+                expected: self.n_vars(),
+                given: vars.len(),
+            });
+        }
+        // sub-expressions are evaluated on an explicit stack of expressions, the values of
+        // their first nodes, and the values of the first arguments of the call at their next
+        // node, such that deeply nested expressions cannot overflow the call stack
+        type Values<T> = SmallVec<[T; N_NODES_ON_STACK]>;
+        let mut stack = vec![(self, Values::new(), Values::new())];
+        while let Some((deepex, values, arg_values)) = stack.last_mut() {
+            let deepex = *deepex;
+            match deepex.nodes.get(values.len()) {
+                Some(DeepNode::Num(n, _)) => values.push(*n),
+                Some(DeepNode::Var((idx, _))) => values.push(vars[*idx]),
+                Some(DeepNode::Expr(e)) => stack.push((e, Values::new(), Values::new())),
+                Some(DeepNode::NaryCall { op, args, .. }) => match args.get(arg_values.len()) {
+                    Some(arg) => stack.push((arg, Values::new(), Values::new())),
+                    None => {
+                        let value = (op.apply)(arg_values);
+                        arg_values.clear();
+                        values.push(value);
+                    }
+                },
+                None => {
+                    let values = mem::take(values);
+                    let value = deepex.apply_ops(values, &self.fallible_ops)?;
+                    stack.pop();
+                    let Some((parent, parent_values, parent_arg_values)) = stack.last_mut() else {
+                        return Ok(value);
+                    };
+                    match parent.nodes.get(parent_values.len()) {
+                        Some(DeepNode::NaryCall { .. }) => parent_arg_values.push(value),
+                        _ => parent_values.push(value),
+                    }
+                }
+            }
+        }
+        Err(ExError::Internal(
+            "stack of evaluation ran empty".to_string(),
+        ))
+    }
+
+    /// Applies the binary and unary operators to the values of the nodes with the fallible
+    /// operations of the root expression, since sub-expressions do not necessarily know
+    /// them.
+    fn apply_ops(
+        &self,
+        mut values: SmallVec<[T; N_NODES_ON_STACK]>,
+        fallible_ops: &[(&'a str, FallibleOp<T>)],
+    ) -> Result<T, ExError> {
+        let failed = |repr: &str, operands: &[T], reason| ExError::OperatorFailed {
+            op: repr.to_string(),
+            operands: operands.iter().map(|x| format!("{:?}", x)).collect(),
+            reason,
+        };
+        let fallible = |repr| {
+            fallible_ops
+                .iter()
+                .find(|(r, _)| *r == repr)
+                .map(|(_, fallible)| *fallible)
+        };
+        let mut op_indices = (0..self.bin_ops.ops.len()).collect::<ExprIdxVec>();
+        while !op_indices.is_empty() {
+            // leftmost operator with the highest priority
+            let pos = (0..op_indices.len())
+                .max_by_key(|i| (self.bin_ops.ops[op_indices[*i]].prio, Reverse(*i)))
+                .unwrap_or(0);
+            let op_idx = op_indices.remove(pos);
+            let (a, b) = (values[pos], values.remove(pos + 1));
+            let repr = self.bin_ops.reprs[op_idx];
+            values[pos] = match fallible(repr).and_then(|f| f.bin_op) {
+                Some(apply) => apply(a, b).map_err(|reason| failed(repr, &[a, b], reason))?,
+                None => (self.bin_ops.ops[op_idx].apply)(a, b),
+            };
+        }
+        let x = values[0];
+        if fallible_ops.is_empty() {
+            return Ok(self.unary_op.op.apply(x));
+        }
+        // rev, since the last uop is applied first by convention
+        self.unary_op
+            .op
+            .funcs()
+            .iter()
+            .zip(self.unary_op.reprs.iter())
+            .rev()
+            .try_fold(x, |x, (f, repr)| {
+                match fallible(repr).and_then(|f| f.unary_op) {
+                    Some(apply) => apply(x).map_err(|reason| failed(repr, &[x], reason)),
+                    None => Ok(f(x)),
+                }
+            })
+    }
+
+    /// Returns the sub-expressions and the arguments of calls of this expression.
+    fn sub_deepexes(&self) -> impl Iterator<Item = &DeepEx<'a, T>> {
+        self.nodes.iter().flat_map(|node| match node {
+            DeepNode::Expr(e) => core::slice::from_ref(&**e),
+            DeepNode::NaryCall { args, .. } => args.as_slice(),
+            _ => &[],
+        })
+    }
+
+    pub(crate) fn sub_expr_info(&self) -> SubExprInfo<'a> {
+        type Subs<'b, 'a, T> = SmallVec<[&'b DeepEx<'a, T>; N_NODES_ON_STACK]>;
+        type Infos<'a> = SmallVec<[SubExprInfo<'a>; N_NODES_ON_STACK]>;
+        // the infos of sub-expressions are computed before the infos of their parents on an
+        // explicit stack such that deeply nested expressions cannot overflow the call stack
+        fn new_frame<'b, 'a, T: Copy + Debug>(
+            deepex: &'b DeepEx<'a, T>,
+        ) -> (&'b DeepEx<'a, T>, Subs<'b, 'a, T>, Infos<'a>) {
+            (deepex, deepex.sub_deepexes().collect(), Infos::new())
+        }
+        let mut stack = vec![new_frame(self)];
+        loop {
+            let (deepex, subs, sub_infos) = stack
+                .last_mut()
+                .expect("the info of the root is returned before the stack runs empty");
+            if let Some(sub) = subs.get(sub_infos.len()) {
+                let sub_frame = new_frame(sub);
+                stack.push(sub_frame);
+                continue;
+            }
+            let info = deepex.sub_expr_info_with(mem::take(sub_infos));
+            stack.pop();
+            match stack.last_mut() {
+                Some((_, _, parent_infos)) => parent_infos.push(info),
+                None => return info,
+            }
+        }
+    }
+
+    /// Returns the info of this expression from the infos of its
+    /// [`sub_deepexes`](DeepEx::sub_deepexes).
+    fn sub_expr_info_with(
+        &self,
+        sub_infos: SmallVec<[SubExprInfo<'a>; N_NODES_ON_STACK]>,
+    ) -> SubExprInfo<'a> {
+        let mut found = SmallVec::<[(&'a str, usize); N_VARS_ON_STACK]>::new();
+        self.occurring_vars(&mut found);
+        found.sort_unstable();
+        let n_leaves = self
+            .nodes
+            .iter()
+            .filter(|node| matches!(node, DeepNode::Num(..) | DeepNode::Var(_)))
+            .count();
+        let n_nodes = n_leaves + sub_infos.iter().map(|info| info.n_nodes).sum::<usize>();
+        let max_sub_depth = sub_infos.iter().map(|info| info.depth).max().unwrap_or(0);
+        // the last of the binary operators with the lowest priority is executed last
+        let root_op_repr = self.unary_op.reprs.first().copied().or_else(|| {
+            if let [DeepNode::NaryCall { repr, .. }] = self.nodes.as_slice() {
+                Some(*repr)
+            } else if self.nodes.len() == 1 {
+                sub_infos.first().and_then(|info| info.root_op_repr)
+            } else {
+                self.bin_ops
+                    .ops
+                    .iter()
+                    .zip(self.bin_ops.reprs.iter())
+                    .rev()
+                    .min_by_key(|(op, _)| op.prio)
+                    .map(|(_, repr)| *repr)
+            }
+        });
+        SubExprInfo {
+            var_names: found.iter().map(|(name, _)| *name).collect(),
+            n_nodes,
+            depth: max_sub_depth + 1,
+            root_op_repr,
+        }
+    }
+
+    /// Returns clones of all maximal sub-expressions that fulfill the predicate. The
+    /// variables of each clone are re-indexed densely. The accompanying vector maps the
+    /// new variable indices to the indices of `self`.
+    pub(crate) fn extract_subexprs<F>(&self, predicate: &F) -> Vec<(DeepEx<'a, T>, Vec<usize>)>
+    where
+        F: Fn(&SubExprInfo) -> bool,
+    {
+        let var_order = self.var_order;
+        let mut extracted = vec![];
+        // sub-expressions are visited in the order of the string on an explicit stack
+        let mut stack = vec![self];
+        while let Some(deepex) = stack.pop() {
+            if predicate(&deepex.sub_expr_info()) {
+                let mut found = SmallVec::<[(&'a str, usize); N_VARS_ON_STACK]>::new();
+                deepex.occurring_vars(&mut found);
+                match var_order {
+                    VarOrder::Alphabetical => found.sort_unstable(),
+                    VarOrder::Occurrence => found.sort_unstable_by_key(|(_, idx)| *idx),
+                }
+                let mut sub = deepex.clone();
+                reset_vars(&mut sub, found.iter().map(|(name, _)| *name).collect());
+                sub.var_order = var_order;
+                extracted.push((sub, found.iter().map(|(_, idx)| *idx).collect()));
+            } else {
+                let n_stacked = stack.len();
+                stack.extend(deepex.sub_deepexes());
+                stack[n_stacked..].reverse();
+            }
+        }
+        for (sub, _) in &mut extracted {
+            sub.overloaded_ops = self.overloaded_ops.clone();
+            sub.op_reprs = self.op_reprs.clone();
+            sub.fallible_ops = self.fallible_ops.clone();
+            sub.bin_prios = self.bin_prios.clone();
+        }
+        extracted
+    }
+
+    pub(crate) fn new(
+        nodes: Vec<DeepNode<'a, T>>,
+        bin_ops: BinOpsWithReprs<'a, T>,
+        unary_op: UnaryOpWithReprs<'a, T>,
+    ) -> Result<DeepEx<'a, T>, ExError> {
+        DeepEx::new_with_literals(nodes, bin_ops, unary_op, vec![], Optimizations::default())
+    }
+
+    /// Creates an expression like [`new`](DeepEx::new) whose numbers may point to their
+    /// spellings in `literal_texts` and that is compiled with the given optimizations.
+    pub(crate) fn new_with_literals(
         nodes: Vec<DeepNode<'a, T>>,
         bin_ops: BinOpsWithReprs<'a, T>,
         unary_op: UnaryOpWithReprs<'a, T>,
-    ) -> Result<DeepEx<'a, T>, ExParseError> {
+        literal_texts: Vec<&'a str>,
+        optimizations: Optimizations,
+    ) -> Result<DeepEx<'a, T>, ExError> {
         if nodes.len() != bin_ops.ops.len() + 1 {
-            Err(ExParseError {
-                msg: format!(
-                    "mismatch between number of nodes {:?} and binary operators {:?} ({} vs {})",
-                    nodes,
-                    bin_ops.ops,
-                    nodes.len(),
-                    bin_ops.ops.len()
-                ),
-            })
+            Err(ExError::Internal(format!(
+                "mismatch between number of nodes {:?} and binary operators {:?} ({} vs {})",
+                nodes,
+                bin_ops.ops,
+                nodes.len(),
+                bin_ops.ops.len()
+            )))
         } else {
             let mut found_vars = SmallVec::<[&str; N_VARS_ON_STACK]>::new();
             for node in &nodes {
                 match node {
-                    DeepNode::Num(_) => (),
+                    DeepNode::Num(..) => (),
                     DeepNode::Var((_, name)) => {
                         if !found_vars.contains(name) {
                             found_vars.push(name);
@@ -202,8 +967,18 @@ impl<'a, T: Copy + Debug> DeepEx<'a, T> {
                             }
                         }
                     }
+                    DeepNode::NaryCall { args, .. } => {
+                        for name in args.iter().flat_map(|arg| arg.var_names.iter()) {
+                            if !found_vars.contains(name) {
+                                found_vars.push(name);
+                            }
+                        }
+                    }
                 }
             }
+            // variable indices are assigned in alphabetical order, such that the i-th name
+            // belongs to the variable with index i
+            found_vars.sort_unstable();
 
             let mut expr = DeepEx {
                 nodes,
@@ -211,244 +986,922 @@ impl<'a, T: Copy + Debug> DeepEx<'a, T> {
                 unary_op,
                 overloaded_ops: None,
                 var_names: found_vars,
+                span: None,
+                literal_texts,
+                optimizations,
+                var_order: VarOrder::default(),
+                op_reprs: Arc::from([]),
+                fallible_ops: Arc::from([]),
+                bin_prios: Arc::from([]),
             };
             expr.compile();
             Ok(expr)
         }
     }
 
+    /// Writes the expression such that parsing it again yields an expression with the same
+    /// value. Parentheses are only written where operator priorities or unary operators
+    /// require them, such that unparsing the re-parsed expression yields the same string.
+    /// Prefix operators followed by stronger binary operators are parenthesized such that
+    /// the value is the same also with [`unary_minus_binds_weaker_than_pow`](crate::ParseOptions::unary_minus_binds_weaker_than_pow).
+    /// Variable names are put into curly braces if they could not be parsed otherwise.
+    /// Numbers without a spelling are written with [`Debug`](Debug). Infinite and NaN
+    /// numbers, e.g., folded from `0.5/0`, are written as `(1/0)`, `(-1/0)`, and `(0/0)`.
     pub fn unparse(&self) -> String {
-        let mut node_strings = self.nodes.iter().map(|n| match n {
-            DeepNode::Num(n) => format!("{:?}", n),
-            DeepNode::Var((_, var_name)) => format!("{{{}}}", var_name),
-            DeepNode::Expr(e) => {
-                if e.unary_op.op.len() == 0 {
-                    format!("({})", e.unparse())
+        self.unparse_readable(&|n| format!("{:?}", n))
+    }
+
+    /// Writes the expression like [`unparse`](DeepEx::unparse) but numbers without a
+    /// spelling with [`Display`](core::fmt::Display).
+    pub fn unparse_with_display(&self) -> String
+    where
+        T: core::fmt::Display,
+    {
+        self.unparse_readable(&|n| format!("{}", n))
+    }
+
+    fn unparse_readable(&self, num_str: &dyn Fn(T) -> String) -> String {
+        let op_reprs = &self.op_reprs;
+        self.unparse_with(
+            &mut |n, literal| match literal {
+                Some(text) => text.to_string(),
+                // the names of non-finite numbers would be parsed as variables
+                None => match num_str(n) {
+                    s if s == "inf" => "(1/0)".to_string(),
+                    s if s == "-inf" => "(-1/0)".to_string(),
+                    s if s == "NaN" => "(0/0)".to_string(),
+                    s => s,
+                },
+            },
+            // without known operators, each name could start with an operator
+            &mut |var_name| {
+                if !op_reprs.is_empty() && parser::is_plain_var_name(var_name, op_reprs) {
+                    var_name.to_string()
                 } else {
-                    e.unparse()
+                    format!("{{{}}}", var_name)
+                }
+            },
+            op_reprs,
+            &self.bin_prios,
+            true,
+        )
+    }
+
+    /// Unparses the expression like [`unparse`](DeepEx::unparse) but without the spellings
+    /// of numbers and with all variables in curly braces. If `ignore_var_names` is true,
+    /// variables are replaced by the index of their first occurrence, e.g., `x*y+x` is
+    /// written as `{0}*{1}+{0}`.
+    pub(crate) fn structure_string(&self, ignore_var_names: bool) -> String {
+        let mut occurred = SmallVec::<[&'a str; N_VARS_ON_STACK]>::new();
+        self.unparse_with(
+            &mut |n, _| format!("{:?}", n),
+            &mut |var_name| {
+                if !ignore_var_names {
+                    return format!("{{{}}}", var_name);
                 }
+                let idx = match occurred.iter().position(|name| *name == var_name) {
+                    Some(idx) => idx,
+                    None => {
+                        occurred.push(var_name);
+                        occurred.len() - 1
+                    }
+                };
+                format!("{{{}}}", idx)
+            },
+            &[],
+            &[],
+            false,
+        )
+    }
+
+    /// Returns whether the first or the last leaf is a number if the parentheses of
+    /// sub-expressions without unary operators are dropped.
+    fn has_num_at_edge(&self, first: bool) -> bool {
+        let mut deepex = self;
+        while deepex.unary_op.op.len() == 0 {
+            let node = if first {
+                deepex.nodes.first()
+            } else {
+                deepex.nodes.last()
+            };
+            match node {
+                Some(DeepNode::Num(..)) => return true,
+                Some(DeepNode::Expr(e)) => deepex = e,
+                _ => return false,
             }
-        });
+        }
+        false
+    }
+
+    /// Returns the lowest priority of the binary operators that are written at the top
+    /// level if the parentheses of sub-expressions without unary operators are dropped.
+    fn min_top_level_prio(&self) -> Option<i32> {
+        let mut deepex = self;
+        loop {
+            match (deepex.bin_ops.ops.is_empty(), deepex.nodes.first()) {
+                (true, Some(DeepNode::Expr(e))) if e.unary_op.op.len() == 0 => deepex = e,
+                _ => return deepex.bin_ops.ops.iter().map(|op| op.prio).min(),
+            }
+        }
+    }
+
+    /// Returns the lowest priority of the binary variants of the prefix operators the last
+    /// operand starts with, e.g., the exponent in `2^-x`, if the expression is written
+    /// without parentheses. With
+    /// [`unary_minus_binds_weaker_than_pow`](crate::ParseOptions::unary_minus_binds_weaker_than_pow),
+    /// these operators are applied after following binary operators with a higher priority.
+    fn trailing_prefix_op_prio(&self, bin_prios: &[(&str, i32)]) -> Option<i32> {
+        if self.unary_op.op.len() > 0 || self.bin_ops.ops.is_empty() {
+            return None;
+        }
+        match self.nodes.last() {
+            Some(DeepNode::Expr(e)) => e
+                .unary_op
+                .reprs
+                .iter()
+                .zip(e.unary_op.positions.iter())
+                .take_while(|(_, position)| **position == UnaryPosition::Prefix)
+                .map_while(|(repr, _)| bin_prio_of_prefix_op(repr, bin_prios))
+                .min(),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the parentheses around the sub-expression `sub` without unary
+    /// operators at node index `idx` can be dropped without changing the value.
+    fn are_parens_redundant(
+        &self,
+        idx: usize,
+        sub: &DeepEx<'a, T>,
+        bin_prios: &[(&str, i32)],
+    ) -> bool {
+        let min_prio = match sub.min_top_level_prio() {
+            Some(prio) => prio,
+            None => return true,
+        };
+        let node_has_num_at_edge = |node: &DeepNode<'a, T>, first: bool| match node {
+            DeepNode::Num(..) => true,
+            DeepNode::Expr(e) => e.has_num_at_edge(first),
+            _ => false,
+        };
+        // operators of the same priority are applied from left to right, and adjacent
+        // numbers would be evaluated first with fast semantics
+        let is_left_fine = idx == 0
+            || min_prio > self.bin_ops.ops[idx - 1].prio
+                && !(node_has_num_at_edge(&self.nodes[idx - 1], false)
+                    && sub.has_num_at_edge(true));
+        let is_right_fine = idx + 1 == self.nodes.len()
+            || min_prio >= self.bin_ops.ops[idx].prio
+                && !(node_has_num_at_edge(&self.nodes[idx + 1], true)
+                    && sub.has_num_at_edge(false))
+                // the last operand would not be parenthesized
+                && sub
+                    .trailing_prefix_op_prio(bin_prios)
+                    .is_none_or(|prio| self.bin_ops.ops[idx].prio <= prio);
+        is_left_fine && is_right_fine
+    }
+
+    /// Wraps the string of the node at `idx` in parentheses if it starts with prefix operators
+    /// that have binary variants, such as `-`, and would otherwise be applied after the
+    /// following binary operator with
+    /// [`unary_minus_binds_weaker_than_pow`](crate::ParseOptions::unary_minus_binds_weaker_than_pow).
+    /// This is the case if the following binary operator has a higher priority than the
+    /// binary variants of the prefix operators, e.g., `(-x)^2` instead of `-x^2` or
+    /// `2^(-x)*3` instead of `2^-x*3` with the default operators.
+    fn parenthesize_prefix_op(
+        &self,
+        idx: usize,
+        node_str: String,
+        bin_prios: &[(&str, i32)],
+    ) -> String {
+        let needs_parens = match (
+            leading_prefix_op_prio(&node_str, bin_prios),
+            self.bin_ops.ops.get(idx),
+        ) {
+            (Some(prio), Some(next)) => next.prio > prio,
+            _ => false,
+        };
+        if needs_parens {
+            format!("({})", node_str)
+        } else {
+            node_str
+        }
+    }
+
+    /// Writes the expression with the given representations of numbers including their
+    /// spellings, if available, and variables. If `op_reprs` contains the representations of
+    /// all operators, parentheses around the operands of symbolic unary operators are
+    /// omitted where possible. With `minimal_parens`, redundant parentheses around
+    /// sub-expressions are omitted as well. Sub-expressions are written on an explicit
+    /// stack such that deeply nested expressions cannot overflow the call stack.
+    fn unparse_with(
+        &self,
+        num_str: &mut dyn FnMut(T, Option<&str>) -> String,
+        var_str: &mut dyn FnMut(&'a str) -> String,
+        op_reprs: &[&'a str],
+        bin_prios: &[(&str, i32)],
+        minimal_parens: bool,
+    ) -> String {
+        // expressions with the strings of their nodes and of the arguments of the call of an
+        // n-ary operator that is written next
+        let mut stack = vec![(self, vec![], vec![])];
+        while let Some((deepex, node_strings, arg_strings)) = stack.last_mut() {
+            let deepex = *deepex;
+            let i = node_strings.len();
+            let node_string = match deepex.nodes.get(i) {
+                None => {
+                    let node_strings = mem::take(node_strings);
+                    stack.pop();
+                    let res = deepex.join_node_strings(node_strings, op_reprs);
+                    match stack.last_mut() {
+                        None => return res,
+                        Some((parent, node_strings, arg_strings)) => {
+                            match &parent.nodes[node_strings.len()] {
+                                DeepNode::Expr(e) => {
+                                    let idx = node_strings.len();
+                                    let node_string = if e.unary_op.op.len() > 0
+                                        || minimal_parens
+                                            && parent.are_parens_redundant(idx, e, bin_prios)
+                                    {
+                                        parent.parenthesize_prefix_op(idx, res, bin_prios)
+                                    } else {
+                                        format!("({})", res)
+                                    };
+                                    node_strings.push(node_string);
+                                }
+                                _ => arg_strings.push(res),
+                            }
+                        }
+                    }
+                    continue;
+                }
+                // numbers without a spelling in this expression are formatted
+                Some(DeepNode::Num(n, literal)) => deepex.parenthesize_prefix_op(
+                    i,
+                    num_str(
+                        *n,
+                        literal.and_then(|idx| {
+                            deepex.literal_texts.get(idx.get() as usize - 1).copied()
+                        }),
+                    ),
+                    bin_prios,
+                ),
+                Some(DeepNode::Var((_, var_name))) => var_str(var_name),
+                Some(DeepNode::Expr(e)) => {
+                    stack.push((e, vec![], vec![]));
+                    continue;
+                }
+                Some(DeepNode::NaryCall { repr, args, .. }) => match args.get(arg_strings.len()) {
+                    Some(arg) => {
+                        stack.push((arg, vec![], vec![]));
+                        continue;
+                    }
+                    None => format!("{}({})", repr, mem::take(arg_strings).join(",")),
+                },
+            };
+            node_strings.push(node_string);
+        }
+        // the string of the outermost expression has been returned
+        String::new()
+    }
+
+    /// Writes the expression given the strings of its nodes, see
+    /// [`unparse_with`](DeepEx::unparse_with).
+    fn join_node_strings(&self, node_strings: Vec<String>, op_reprs: &[&'a str]) -> String {
+        let mut node_strings = node_strings.into_iter();
+        // the constructor ensures one more node than binary operators
         let mut bin_op_strings = self.bin_ops.reprs.iter();
-        // a valid expression has at least one node
-        let first_node_str = node_strings.next().unwrap();
+        let first_node_str = node_strings.next().unwrap_or_default();
         let node_with_bin_ops_string = node_strings.fold(first_node_str, |mut res, node_str| {
-            let bin_op_str = bin_op_strings.next().unwrap();
-            res.push_str(bin_op_str);
+            res.push_str(bin_op_strings.next().copied().unwrap_or_default());
             res.push_str(node_str.as_str());
             res
         });
-        let unary_op_string = self
-            .unary_op
+        // A symbolic prefix operator can be written directly in front of a single variable,
+        // a number, or the result of another prefix operator, if this does not form another operator. A
+        // symbolic postfix operator can directly follow a single variable or number.
+        let is_atom = self.bin_ops.ops.is_empty()
+            && match self.nodes.first() {
+                Some(DeepNode::Var(_)) => true,
+                Some(DeepNode::Num(..)) => {
+                    node_with_bin_ops_string.starts_with(|c: char| c.is_alphanumeric() || c == '.')
+                }
+                _ => false,
+            };
+        let is_tight = is_atom
+            || self.bin_ops.ops.is_empty()
+                && matches!(self.nodes.first(), Some(DeepNode::Expr(e))
+                    if e.unary_op.positions.first() == Some(&UnaryPosition::Prefix));
+        let can_omit_parens = |uop_str: &str, inner: &str| {
+            !op_reprs.is_empty()
+                && !uop_str.contains(|c: char| c.is_alphanumeric() || c == '_')
+                && !op_reprs.iter().any(|repr| {
+                    repr.len() > uop_str.len() && format!("{}{}", uop_str, inner).starts_with(repr)
+                })
+        };
+        // the last unary operator is applied first and hence written closest to the nodes
+        self.unary_op
             .reprs
             .iter()
-            .fold(String::new(), |mut res, uop_str| {
-                res.push_str(uop_str);
-                res.push('(');
-                res
-            });
-        let closings =
-            repeat(")")
-                .take(self.unary_op.op.len())
-                .fold(String::new(), |mut res, closing| {
-                    res.push_str(closing);
-                    res
-                });
-        if self.unary_op.op.len() == 0 {
-            node_with_bin_ops_string
-        } else {
-            format!(
-                "{}{}{}",
-                unary_op_string, node_with_bin_ops_string, closings
+            .zip(self.unary_op.positions.iter())
+            .rev()
+            .enumerate()
+            .fold(
+                (node_with_bin_ops_string, is_tight),
+                |(inner, is_tight), (i, (uop_str, position))| match position {
+                    UnaryPosition::Prefix if is_tight && can_omit_parens(uop_str, &inner) => {
+                        (format!("{}{}", uop_str, inner), true)
+                    }
+                    UnaryPosition::Prefix => (format!("{}({})", uop_str, inner), true),
+                    UnaryPosition::Postfix if i == 0 && is_atom && can_omit_parens(uop_str, "") => {
+                        (format!("{}{}", inner, uop_str), false)
+                    }
+                    UnaryPosition::Postfix => (format!("({}){}", inner, uop_str), false),
+                },
             )
-        }
+            .0
+    }
+
+    /// Creates an expression that consists of the number `num`.
+    pub fn from_num(num: T) -> DeepEx<'a, T> {
+        DeepEx::new(
+            vec![DeepNode::Num(num, None)],
+            BinOpsWithReprs::new(),
+            UnaryOpWithReprs::new(),
+        )
+        .unwrap()
+    }
+
+    /// Creates an expression that consists of the number `num` like
+    /// [`from_num`](DeepEx::from_num) but with the operators and variables of `self`, such
+    /// that both can be combined, e.g., in the rules of a
+    /// [`PartialDerivative`](crate::PartialDerivative).
+    pub fn num_like(&self, num: T) -> DeepEx<'a, T> {
+        let mut res = DeepEx::from_num(num);
+        res.overloaded_ops = self.overloaded_ops.clone();
+        res.var_names = self.var_names.clone();
+        res.var_order = self.var_order;
+        res.op_reprs = self.op_reprs.clone();
+        res.fallible_ops = self.fallible_ops.clone();
+        res.bin_prios = self.bin_prios.clone();
+        res
     }
 
-    pub fn from_node(node: DeepNode<'a, T>, overloaded_ops: OverloadedOps<'a, T>) -> DeepEx<'a, T> {
+    /// Converts the numbers with `convert` and replaces each operator by the one with the
+    /// same representation in `ops`. The structure, the variables, and the spellings of
+    /// the numbers are kept.
+    pub(crate) fn with_ops<U: Copy + Debug>(
+        &self,
+        ops: &[Operator<'a, U>],
+        convert: fn(T) -> U,
+    ) -> Result<DeepEx<'a, U>, ExError> {
+        let find_op = |repr: &str| {
+            ops.iter()
+                .find(|op| op.repr == repr)
+                .ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))
+        };
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| -> Result<DeepNode<'a, U>, ExError> {
+                Ok(match node {
+                    DeepNode::Expr(e) => DeepNode::expr(e.with_ops(ops, convert)?),
+                    DeepNode::Num(n, literal) => DeepNode::Num(convert(*n), *literal),
+                    DeepNode::Var(var) => DeepNode::Var(*var),
+                    DeepNode::NaryCall { repr, args, .. } => DeepNode::NaryCall {
+                        repr,
+                        op: find_op(repr)?
+                            .nary_op
+                            .ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))?,
+                        args: args
+                            .iter()
+                            .map(|arg| arg.with_ops(ops, convert))
+                            .collect::<Result<_, _>>()?,
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let bin_ops = self
+            .bin_ops
+            .reprs
+            .iter()
+            .map(|repr| {
+                find_op(repr)?
+                    .bin_op
+                    .ok_or_else(|| ExError::OperatorNotBinary(repr.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        let unary_ops = self
+            .unary_op
+            .reprs
+            .iter()
+            .map(|repr| {
+                find_op(repr)?
+                    .unary_op
+                    .ok_or_else(|| ExError::OperatorNotUnary(repr.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        let fallible_ops = self
+            .fallible_ops
+            .iter()
+            .filter_map(|(repr, _)| {
+                let fallible = find_op(repr).ok()?.fallible?;
+                Some((*repr, fallible))
+            })
+            .collect();
+        Ok(DeepEx {
+            nodes,
+            bin_ops: BinOpsWithReprs {
+                reprs: self.bin_ops.reprs.clone(),
+                ops: bin_ops,
+            },
+            unary_op: UnaryOpWithReprs {
+                reprs: self.unary_op.reprs.clone(),
+                positions: self.unary_op.positions.clone(),
+                op: UnaryOp::from_vec(unary_ops),
+            },
+            overloaded_ops: self
+                .overloaded_ops
+                .as_ref()
+                .and_then(|_| find_overloaded_ops(ops))
+                .map(Box::new),
+            var_names: self.var_names.clone(),
+            span: self.span,
+            literal_texts: self.literal_texts.clone(),
+            optimizations: self.optimizations,
+            var_order: self.var_order,
+            op_reprs: self.op_reprs.clone(),
+            fallible_ops,
+            bin_prios: self.bin_prios.clone(),
+        })
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn from_node(
+        node: DeepNode<'a, T>,
+        overloaded_ops: OverloadedOps<'a, T>,
+    ) -> DeepEx<'a, T> {
         let mut deepex =
             DeepEx::new(vec![node], BinOpsWithReprs::new(), UnaryOpWithReprs::new()).unwrap();
         deepex.set_overloaded_ops(Some(overloaded_ops));
         deepex
     }
 
-    pub fn one(overloaded_ops: OverloadedOps<'a, T>) -> DeepEx<'a, T>
+    #[cfg(feature = "std")]
+    pub(crate) fn one(overloaded_ops: OverloadedOps<'a, T>) -> DeepEx<'a, T>
     where
-        T: Float,
+        T: One,
     {
         DeepEx::from_node(DeepNode::one(), overloaded_ops)
     }
 
-    pub fn zero(overloaded_ops: OverloadedOps<'a, T>) -> DeepEx<'a, T>
+    #[cfg(feature = "std")]
+    pub(crate) fn zero(overloaded_ops: OverloadedOps<'a, T>) -> DeepEx<'a, T>
     where
-        T: Float,
+        T: Zero,
     {
         DeepEx::from_node(DeepNode::zero(), overloaded_ops)
     }
 
-    pub fn with_new_unary_op(self, unary_op: UnaryOpWithReprs<'a, T>) -> Self {
-        Self {
+    pub(crate) fn with_new_unary_op(self, unary_op: UnaryOpWithReprs<'a, T>) -> Self {
+        let mut res = Self {
             nodes: self.nodes,
             overloaded_ops: self.overloaded_ops,
             bin_ops: self.bin_ops,
             var_names: self.var_names,
             unary_op,
-        }
+            span: self.span,
+            literal_texts: self.literal_texts,
+            optimizations: self.optimizations,
+            var_order: self.var_order,
+            op_reprs: self.op_reprs,
+            fallible_ops: self.fallible_ops,
+            bin_prios: self.bin_prios,
+        };
+        res.compile();
+        res
     }
 
-    pub fn from_str(text: &'a str) -> Result<DeepEx<'a, T>, ExParseError>
+    /// Parses `text` with the [default operators](crate::make_default_operators).
+    ///
+    /// # Errors
+    ///
+    /// See [`parse`](crate::parse).
+    ///
+    #[cfg(feature = "std")]
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(text: &'a str) -> Result<DeepEx<'a, T>, ExError>
     where
-        <T as std::str::FromStr>::Err: Debug,
+        <T as core::str::FromStr>::Err: Debug,
         T: Float + FromStr,
     {
-        let ops = operators::make_default_operators::<T>();
+        let ops = crate::make_default_operators::<T>();
         DeepEx::from_ops(text, &ops)
     }
 
-    pub fn from_ops(text: &'a str, ops: &[Operator<'a, T>]) -> Result<DeepEx<'a, T>, ExParseError>
+    /// Parses `text` with the operators `ops`, see [`parse`](crate::parse).
+    ///
+    /// # Errors
+    ///
+    /// See [`parse`](crate::parse).
+    ///
+    pub fn from_ops(text: &'a str, ops: &[Operator<'a, T>]) -> Result<DeepEx<'a, T>, ExError>
+    where
+        <T as core::str::FromStr>::Err: Debug,
+        T: Copy + FromStr + Debug,
+    {
+        DeepEx::from_options(text, ops, &ParseOptions::default(), &[]).map(|(deepex, _)| deepex)
+    }
+
+    /// Parses `text` whereby variables named like one of the definitions `defs` are
+    /// replaced by clones of the defining expressions. Returns also the warnings of the
+    /// tokenizer.
+    pub(crate) fn from_options(
+        text: &'a str,
+        ops: &[Operator<'a, T>],
+        options: &ParseOptions,
+        defs: &Definitions<'a, '_, T>,
+    ) -> Result<(DeepEx<'a, T>, Vec<ParseWarning>), ExError>
     where
-        <T as std::str::FromStr>::Err: Debug,
+        <T as core::str::FromStr>::Err: Debug,
         T: Copy + FromStr + Debug,
     {
-        let parsed_tokens = parser::tokenize_and_analyze(text, ops, parser::is_numeric_text)?;
-        let mut deepex = deep_details::parsed_tokens_to_deepex(&parsed_tokens)?;
+        let is_numeric = if options.decimal_comma {
+            parser::is_numeric_text_decimal_comma
+        } else {
+            parser::is_numeric_text
+        };
+        let (parsed_tokens, warnings) =
+            parser::tokenize_with_diagnostics(text, ops, is_numeric, options)?;
+        let mut deepex =
+            deep_details::parsed_tokens_to_deepex(text, &parsed_tokens, defs, options)?;
         deepex.set_overloaded_ops(find_overloaded_ops(ops));
-        Ok(deepex)
+        deepex.set_op_reprs(ops);
+        Ok((deepex, warnings))
     }
 
+    /// Parses `text` with the operators `ops` and numbers that match
+    /// `number_regex_pattern`, see [`parse_with_number_pattern`](crate::parse_with_number_pattern).
+    ///
+    /// # Errors
+    ///
+    /// See [`parse_with_number_pattern`](crate::parse_with_number_pattern).
+    ///
+    #[cfg(feature = "std")]
     pub fn from_pattern(
         text: &'a str,
         ops: &[Operator<'a, T>],
         number_regex_pattern: &str,
-    ) -> Result<DeepEx<'a, T>, ExParseError>
+    ) -> Result<DeepEx<'a, T>, ExError>
     where
-        <T as std::str::FromStr>::Err: Debug,
+        <T as core::str::FromStr>::Err: Debug,
         T: Copy + FromStr + Debug,
     {
-        let beginning_number_regex_regex = format!("^({})", number_regex_pattern);
+        let patterns = parser::TokenPatterns {
+            number: number_regex_pattern,
+            ..parser::TokenPatterns::default()
+        };
+        DeepEx::from_patterns(text, ops, &patterns)
+    }
+
+    /// Parses `text` with the operators `ops` and numbers and variable names that match
+    /// `patterns`, see [`parse_with_patterns`](crate::parse_with_patterns).
+    ///
+    /// # Errors
+    ///
+    /// See [`parse_with_patterns`](crate::parse_with_patterns).
+    ///
+    #[cfg(feature = "std")]
+    pub fn from_patterns(
+        text: &'a str,
+        ops: &[Operator<'a, T>],
+        patterns: &parser::TokenPatterns,
+    ) -> Result<DeepEx<'a, T>, ExError>
+    where
+        <T as core::str::FromStr>::Err: Debug,
+        T: Copy + FromStr + Debug,
+    {
+        let beginning_number_regex_regex = format!("^({})", patterns.number);
         let re_number = match Regex::new(beginning_number_regex_regex.as_str()) {
             Ok(regex) => regex,
-            Err(_) => {
-                return Err(ExParseError {
-                    msg: "Cannot compile the passed number regex.".to_string(),
-                })
-            }
+            Err(_) => return Err(ExError::InvalidNumberPattern(patterns.number.to_string())),
+        };
+        let re_var = match Regex::new(&format!("^({})", patterns.var)) {
+            Ok(regex) if !regex.is_match("") => regex,
+            _ => return Err(ExError::InvalidVarPattern(patterns.var.to_string())),
         };
         let is_numeric = |text: &'a str| parser::is_numeric_regex(&re_number, text);
-        let parsed_tokens = parser::tokenize_and_analyze(text, ops, is_numeric)?;
-        let mut deepex = deep_details::parsed_tokens_to_deepex(&parsed_tokens)?;
+        let is_name = |text: &'a str| parser::is_name_regex(&re_var, text);
+        let (parsed_tokens, _) =
+            parser::tokenize_with_names(text, ops, is_numeric, is_name, &ParseOptions::default())?;
+        let mut deepex = deep_details::parsed_tokens_to_deepex(
+            text,
+            &parsed_tokens,
+            &[],
+            &ParseOptions::default(),
+        )?;
         deepex.set_overloaded_ops(deep_details::find_overloaded_ops(ops));
+        deepex.set_op_reprs(ops);
         Ok(deepex)
     }
 
-    pub fn set_overloaded_ops(&mut self, ops: Option<OverloadedOps<'a, T>>) {
-        self.overloaded_ops = ops;
+    pub(crate) fn set_overloaded_ops(&mut self, ops: Option<OverloadedOps<'a, T>>) {
+        self.overloaded_ops = ops.map(Box::new);
     }
 
-    pub fn bin_ops(&self) -> &BinOpsWithReprs<T> {
+    pub(crate) fn set_op_reprs(&mut self, ops: &[Operator<'a, T>]) {
+        self.op_reprs = ops
+            .iter()
+            .flat_map(|op| core::iter::once(op.repr).chain(op.aliases.iter().copied()))
+            .collect();
+        self.fallible_ops = ops
+            .iter()
+            .filter_map(|op| op.fallible.map(|fallible| (op.repr, fallible)))
+            .collect();
+        self.bin_prios = ops
+            .iter()
+            .filter_map(|op| op.bin_op.map(|bin_op| (op, bin_op.prio)))
+            .flat_map(|(op, prio)| {
+                core::iter::once(op.repr)
+                    .chain(op.aliases.iter().copied())
+                    .map(move |repr| (repr, prio))
+            })
+            .collect();
+    }
+
+    /// Returns the fallible operation of the operator with representation `repr`, if any.
+    pub(crate) fn fallible_op(&self, repr: &str) -> Option<FallibleOp<T>> {
+        self.fallible_ops
+            .iter()
+            .find(|(r, _)| *r == repr)
+            .map(|(_, fallible)| *fallible)
+    }
+
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub(crate) fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub(crate) fn optimizations(&self) -> Optimizations {
+        self.optimizations
+    }
+
+    /// Returns the order of the variables, see [`VarOrder`](crate::VarOrder).
+    pub fn var_order(&self) -> VarOrder {
+        self.var_order
+    }
+
+    /// Sets the order of the variables without changing `var_names`, which need to be
+    /// ordered accordingly.
+    pub(crate) fn set_var_order(&mut self, var_order: VarOrder) {
+        self.var_order = var_order;
+    }
+
+    pub(crate) fn bin_ops(&self) -> &BinOpsWithReprs<'a, T> {
         &self.bin_ops
     }
 
-    pub fn unary_op(&self) -> &UnaryOpWithReprs<T> {
+    pub(crate) fn unary_op(&self) -> &UnaryOpWithReprs<'a, T> {
         &self.unary_op
     }
 
-    pub fn nodes(&self) -> &Vec<DeepNode<'a, T>> {
+    pub(crate) fn nodes(&self) -> &Vec<DeepNode<'a, T>> {
         &self.nodes
     }
 
-    pub fn unpack_and_clone_overloaded_ops(&self) -> Result<OverloadedOps<'a, T>, ExParseError> {
-        self.overloaded_ops.clone().ok_or(ExParseError {
-            msg: "cannot unpack overloaded ops when there are none".to_string(),
+    #[cfg(feature = "std")]
+    pub(crate) fn unpack_and_clone_overloaded_ops(&self) -> Result<OverloadedOps<'a, T>, ExError> {
+        self.overloaded_ops.as_deref().cloned().ok_or_else(|| {
+            ExError::Internal("cannot unpack overloaded ops when there are none".to_string())
         })
     }
 
-    fn is_num(&self, num: T) -> bool
+    /// Returns the value of the expression if it is a number that is possibly wrapped by
+    /// expressions with a single node and their unary operators, e.g., `-(2)`.
+    #[cfg(feature = "std")]
+    fn const_value(&self) -> Option<T> {
+        let mut wrappers = SmallVec::<[&Self; N_NODES_ON_STACK]>::new();
+        let mut deepex = self;
+        let value = loop {
+            match deepex.nodes.as_slice() {
+                [DeepNode::Num(n, _)] => break *n,
+                [DeepNode::Expr(e)] => {
+                    wrappers.push(deepex);
+                    deepex = e;
+                }
+                _ => return None,
+            }
+        };
+        // the unary operators of inner expressions are applied first
+        let value = deepex.unary_op.op.apply(value);
+        Some(
+            wrappers
+                .iter()
+                .rev()
+                .fold(value, |value, wrapper| wrapper.unary_op.op.apply(value)),
+        )
+    }
+
+    /// Returns whether the expression is the number `val`. Unary operators and expressions
+    /// with a single node are seen through, e.g., `-(-(1))` is one.
+    #[cfg(feature = "std")]
+    pub fn is_const(&self, val: T) -> bool
     where
-        T: Float,
+        T: PartialEq,
     {
-        self.nodes.len() == 1
-            && match &self.nodes[0] {
-                DeepNode::Num(n) => *n == num,
-                DeepNode::Expr(e) => e.is_num(num),
-                _ => false,
-            }
+        self.const_value() == Some(val)
     }
 
+    /// Returns whether the expression is the number one, see [`is_const`](DeepEx::is_const).
+    #[cfg(feature = "std")]
     pub fn is_one(&self) -> bool
     where
-        T: Float,
+        T: One + PartialEq,
     {
-        self.is_num(T::from(1.0).unwrap())
+        self.is_const(T::one())
     }
 
+    /// Returns whether the expression is the number zero, see [`is_const`](DeepEx::is_const).
+    #[cfg(feature = "std")]
     pub fn is_zero(&self) -> bool
     where
-        T: Float,
+        T: Zero + PartialEq,
     {
-        self.is_num(T::from(0.0).unwrap())
+        self.is_const(T::zero())
     }
 
-    pub fn var_names_union(self, other: Self) -> (Self, Self) {
-        fn reset_vars<'a, T: Copy + Debug>(
-            deepex: &mut DeepEx<'a, T>,
-            new_var_names: SmallVec<[&'a str; N_VARS_ON_STACK]>,
-        ) {
-            for node in &mut deepex.nodes {
-                match node {
-                    DeepNode::Expr(e) => reset_vars(e, new_var_names.clone()),
-                    DeepNode::Var((i, var_name)) => {
-                        for (new_idx, new_name) in new_var_names.iter().enumerate() {
-                            if var_name == new_name {
-                                *i = new_idx;
-                            }
-                        }
+    /// Removes operands that do not change the result of their binary operator on all
+    /// levels, i.e., `*1`, `1*`, `/1`, `+0`, `0+`, `-0`, and `^1`. The operators are
+    /// identified by their representations like the
+    /// [overloaded operators](DeepEx::operate_overloaded). Sub-expressions that are left
+    /// with one node and without unary operators are replaced by their node.
+    #[cfg(feature = "std")]
+    pub(crate) fn prune_neutral_operands(&mut self)
+    where
+        T: One + Zero + PartialEq,
+    {
+        for_each_post_order(self, |deepex| {
+            for node in deepex.nodes.iter_mut() {
+                if let DeepNode::Expr(e) = node {
+                    let single_node = match (e.nodes.as_slice(), e.unary_op.op.len()) {
+                        // the spelling refers to the literal texts of the sub-expression
+                        ([DeepNode::Num(n, _)], 0) => Some(DeepNode::Num(*n, None)),
+                        ([single], 0) => Some(single.clone()),
+                        _ => None,
+                    };
+                    if let Some(single_node) = single_node {
+                        *node = single_node;
                     }
-                    _ => (),
                 }
             }
-            deepex.var_names = new_var_names;
-        }
+            while let Some((node_idx, op_idx)) = deepex.find_neutral_operand() {
+                deepex.nodes.remove(node_idx);
+                deepex.bin_ops.ops.remove(op_idx);
+                deepex.bin_ops.reprs.remove(op_idx);
+            }
+        });
+    }
+
+    /// Returns the index of a node that is a neutral operand of an adjacent binary
+    /// operator together with the index of the operator. Operands are only neutral for an
+    /// operator that is applied to them, e.g., the one in `x+1*y` is but the one in
+    /// `x*1^y` is not.
+    #[cfg(feature = "std")]
+    fn find_neutral_operand(&self) -> Option<(usize, usize)>
+    where
+        T: One + Zero + PartialEq,
+    {
+        let reprs = &self.bin_ops.reprs;
+        let prio = |op_idx: usize| self.bin_ops.ops[op_idx].prio;
+        let is_const = |node_idx: usize, val: T| match &self.nodes[node_idx] {
+            DeepNode::Num(n, _) => *n == val,
+            DeepNode::Expr(e) => e.is_const(val),
+            DeepNode::Var(_) | DeepNode::NaryCall { .. } => false,
+        };
+        (0..self.nodes.len()).find_map(|node_idx| {
+            // right operand of the operator on the left, operators of the same priority
+            // are applied from left to right
+            if let Some(op_idx) = node_idx.checked_sub(1) {
+                let is_neutral = match reprs[op_idx] {
+                    MUL_REPR | DIV_REPR | POW_REPR => is_const(node_idx, T::one()),
+                    ADD_REPR | SUB_REPR => is_const(node_idx, T::zero()),
+                    _ => false,
+                };
+                let is_applied = node_idx == reprs.len() || prio(node_idx) <= prio(op_idx);
+                if is_neutral && is_applied {
+                    return Some((node_idx, op_idx));
+                }
+            }
+            // left operand of the operator on the right
+            if node_idx < reprs.len() {
+                let op_idx = node_idx;
+                let is_neutral = match reprs[op_idx] {
+                    MUL_REPR => is_const(node_idx, T::one()),
+                    ADD_REPR => is_const(node_idx, T::zero()),
+                    _ => false,
+                };
+                let is_applied = node_idx == 0 || prio(node_idx - 1) < prio(op_idx);
+                if is_neutral && is_applied {
+                    return Some((node_idx, op_idx));
+                }
+            }
+            None
+        })
+    }
+
+    /// Returns both expressions with the union of their variables, such that their
+    /// variable indices agree. The variables of `self` keep their relative order; in
+    /// alphabetical order, all names are sorted.
+    pub fn var_names_union(self, other: Self) -> (Self, Self) {
         let mut all_var_names = self.var_names.clone();
         for name in other.var_names.clone() {
             if !all_var_names.contains(&name) {
                 all_var_names.push(name);
             }
         }
-        // sort_unstable is much faster
-        all_var_names.sort_unstable();
+        // in occurrence order, the variables of self occur before the new ones of other
+        if self.var_order == VarOrder::Alphabetical {
+            // sort_unstable is much faster
+            all_var_names.sort_unstable();
+        }
         let mut self_vars_updated = self;
         let mut other_vars_updated = other;
-        reset_vars(&mut self_vars_updated, all_var_names.clone());
-        reset_vars(&mut other_vars_updated, all_var_names);
+        // the indices of expressions that already have all variables are up to date
+        if self_vars_updated.var_names != all_var_names {
+            reset_vars(&mut self_vars_updated, all_var_names.clone());
+        }
+        if other_vars_updated.var_names != all_var_names {
+            reset_vars(&mut other_vars_updated, all_var_names);
+        }
         (self_vars_updated, other_vars_updated)
     }
 
-    pub fn var_names_like_other(mut self, other: &Self) -> Self {
+    #[cfg(feature = "std")]
+    pub(crate) fn var_names_like_other(mut self, other: &Self) -> Self {
         self.var_names = other.var_names.clone();
         self
     }
 
     /// Applies a binary operator to self and other
-    pub fn operate_bin(self, other: Self, bin_op: BinOpsWithReprs<'a, T>) -> Self {
+    pub(crate) fn operate_bin(self, other: Self, bin_op: BinOpsWithReprs<'a, T>) -> Self {
         let overloaded_ops = self.overloaded_ops.clone();
+        let optimizations = self.optimizations.both(other.optimizations);
 
-        let (self_vars_updated, other_vars_updated) = self.var_names_union(other);
-        let mut resex = DeepEx::new(
+        let var_order = self.var_order;
+        let op_reprs = self.op_reprs.clone();
+        let fallible_ops = self.fallible_ops.clone();
+        let bin_prios = self.bin_prios.clone();
+        // Operators of equal priority are applied from left to right. Hence, other can be
+        // appended to the nodes of self if no operator of self has a lower priority. This
+        // keeps chains like sums of many terms flat instead of nesting them deeper and
+        // deeper. Two numbers at the seam could be folded too early.
+        let appendable = self.unary_op.op.len() == 0
+            && !(matches!(self.nodes.last(), Some(DeepNode::Num(..)))
+                && matches!(other.nodes.as_slice(), [DeepNode::Num(..)]))
+            && bin_op
+                .ops
+                .first()
+                .is_some_and(|new_op| self.bin_ops.ops.iter().all(|op| op.prio >= new_op.prio));
+        let (mut self_vars_updated, other_vars_updated) = self.var_names_union(other);
+        if appendable {
+            self_vars_updated
+                .nodes
+                .push(DeepNode::expr(other_vars_updated));
+            self_vars_updated.bin_ops.ops.extend(bin_op.ops);
+            self_vars_updated.bin_ops.reprs.extend(bin_op.reprs);
+            self_vars_updated.optimizations = optimizations;
+            self_vars_updated.span = None;
+            self_vars_updated.compile();
+            return self_vars_updated;
+        }
+        let var_names = self_vars_updated.var_names.clone();
+        let mut resex = DeepEx::new_with_literals(
             vec![
-                DeepNode::Expr(self_vars_updated),
-                DeepNode::Expr(other_vars_updated),
+                DeepNode::expr(self_vars_updated),
+                DeepNode::expr(other_vars_updated),
             ],
             bin_op,
             UnaryOpWithReprs::new(),
+            vec![],
+            optimizations,
         )
         .unwrap();
         resex.overloaded_ops = overloaded_ops;
+        resex.var_names = var_names;
+        resex.var_order = var_order;
+        resex.op_reprs = op_reprs;
+        resex.fallible_ops = fallible_ops;
+        resex.bin_prios = bin_prios;
         resex.compile();
         resex
     }
 
     /// Applies a unary operator to self
-    pub fn operate_unary(mut self, mut unary_op: UnaryOpWithReprs<'a, T>) -> Self {
+    pub(crate) fn operate_unary(mut self, mut unary_op: UnaryOpWithReprs<'a, T>) -> Self {
         self.unary_op.append_front(&mut unary_op);
         self.compile();
         self
@@ -460,12 +1913,11 @@ impl<'a, T: Copy + Debug> DeepEx<'a, T> {
     ///
     /// if an overloaded operator has not been defined
     ///
-    pub fn operate_overloaded(self, other: Self, repr: &'a str) -> Self {
+    pub(crate) fn operate_overloaded(self, other: Self, repr: &'a str) -> Self {
         if self.overloaded_ops.is_none() {
             panic!("overloaded operators not available");
         }
-        let overloaded_ops = self.overloaded_ops.clone();
-        let op = overloaded_ops.clone().unwrap().by_repr(repr);
+        let op = self.overloaded_ops.as_ref().unwrap().by_repr(repr);
 
         let ops = smallvec![op.bin_op.unwrap()];
 
@@ -475,6 +1927,36 @@ impl<'a, T: Copy + Debug> DeepEx<'a, T> {
         };
         self.operate_bin(other, bin_op)
     }
+
+    /// Applies one of the overloaded operators to self and other like
+    /// [`operate_overloaded`](DeepEx::operate_overloaded) but returns an error instead of
+    /// panicking.
+    pub fn try_operate_overloaded(self, other: Self, repr: &str) -> Result<Self, ExError> {
+        let op = self
+            .overloaded_ops
+            .as_ref()
+            .and_then(|ops| ops.find(repr))
+            .ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))?;
+        let bin_op = op
+            .bin_op
+            .ok_or_else(|| ExError::OperatorNotBinary(repr.to_string()))?;
+        let bin_op = BinOpsWithReprs {
+            reprs: vec![op.repr],
+            ops: smallvec![bin_op],
+        };
+        Ok(self.operate_bin(other, bin_op))
+    }
+
+    /// Raises the expression to the power of `exponent` with the operator `^`.
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if `self` has been parsed with operators that do
+    /// not contain a binary `^`.
+    ///
+    pub fn pow(self, exponent: Self) -> Result<Self, ExError> {
+        self.try_operate_overloaded(exponent, POW_REPR)
+    }
 }
 
 impl<'a, T: Copy + Debug> Add for DeepEx<'a, T> {
@@ -511,7 +1993,7 @@ impl<'a, T: Copy + Debug> Display for DeepEx<'a, T> {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 use {
     super::flat::flatten,
     crate::{
@@ -520,25 +2002,25 @@ use {
         util::{assert_float_eq, assert_float_eq_f64},
     },
     rand::{thread_rng, Rng},
-    std::ops::Range,
 };
 
+#[cfg(feature = "std")]
 #[test]
 fn test_reset_vars() {
     let deepex = DeepEx::<f64>::from_str("2*z+x+y * .5").unwrap();
     let deepex2 = DeepEx::<f64>::from_str("a*c*b").unwrap();
     let (deepex_, deepex2_) = deepex.clone().var_names_union(deepex2.clone());
     let all_vars = ["a", "b", "c", "x", "y", "z"];
-    for i in 0..all_vars.len() {
-        assert_eq!(deepex_.var_names[i], all_vars[i]);
-        assert_eq!(deepex2_.var_names[i], all_vars[i]);
+    for (i, var) in all_vars.iter().enumerate() {
+        assert_eq!(deepex_.var_names[i], *var);
+        assert_eq!(deepex2_.var_names[i], *var);
     }
     assert_eq!(deepex.unparse(), deepex_.unparse());
     assert_eq!(deepex2.unparse(), deepex2_.unparse());
-    let flatex = flatten(deepex);
-    let flatex2 = flatten(deepex2);
-    let flatex_ = flatten(deepex_);
-    let flatex2_ = flatten(deepex2_);
+    let flatex = flatten(deepex).unwrap();
+    let flatex2 = flatten(deepex2).unwrap();
+    let flatex_ = flatten(deepex_).unwrap();
+    let flatex2_ = flatten(deepex2_).unwrap();
     assert_float_eq_f64(flatex.eval(&[2.0, 6.0, 1.5]).unwrap(), 8.0);
     assert_float_eq_f64(flatex2.eval(&[3.0, 5.0, 4.0]).unwrap(), 60.0);
     assert_float_eq_f64(flatex_.eval(&[3.0, 5.0, 4.0, 2.0, 6.0, 1.5]).unwrap(), 8.0);
@@ -548,9 +2030,10 @@ fn test_reset_vars() {
     );
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_var_name_union() {
-    fn from_str(text: &str) -> DeepEx<f64> {
+    fn from_str(text: &str) -> DeepEx<'_, f64> {
         DeepEx::from_str(text).unwrap()
     }
     fn test(str_1: &str, str_2: &str, var_names: &[&str]) {
@@ -568,17 +2051,18 @@ fn test_var_name_union() {
         }
     }
 
-    test("x", "y", &vec!["x", "y"]);
-    test("x+y*z", "z+y", &vec!["x", "y", "z"]);
+    test("x", "y", &["x", "y"]);
+    test("x+y*z", "z+y", &["x", "y", "z"]);
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_operator_overloading() {
-    fn from_str(text: &str) -> DeepEx<f64> {
+    fn from_str(text: &str) -> DeepEx<'_, f64> {
         DeepEx::from_str(text).unwrap()
     }
     fn eval<'a>(deepex: &DeepEx<'a, f64>, vars: &[f64], val: f64) {
-        assert_float_eq_f64(flatten(deepex.clone()).eval(vars).unwrap(), val);
+        assert_float_eq_f64(flatten(deepex.clone()).unwrap().eval(vars).unwrap(), val);
     }
 
     fn check_shape<'a>(deepex: &DeepEx<'a, f64>, n_nodes: usize) {
@@ -622,6 +2106,61 @@ fn test_operator_overloading() {
     eval(&prod_of_above, &[1.0, 4.0, 8.0], -7.4378625090980925);
 }
 
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_composed() {
+    fn assert_round_trip(deepex: &DeepEx<f64>, points: &[[f64; 2]]) {
+        let unparsed = deepex.unparse();
+        let reparsed = flatten(DeepEx::<f64>::from_str(&unparsed).unwrap()).unwrap();
+        let flatex = flatten(deepex.clone()).unwrap();
+        // variables that vanished from the unparsed string are dropped by re-parsing
+        let var_idxs = reparsed
+            .var_names()
+            .iter()
+            .map(|name| flatex.var_names().iter().position(|n| n == name).unwrap())
+            .collect::<Vec<_>>();
+        for vars in points {
+            let vars = &vars[..flatex.var_names().len()];
+            let reparsed_vars = var_idxs.iter().map(|i| vars[*i]).collect::<Vec<_>>();
+            assert_float_eq_f64(
+                reparsed.eval(&reparsed_vars).unwrap(),
+                flatex.eval(vars).unwrap(),
+            );
+        }
+    }
+    let points = [[0.5, 1.5], [2.0, -0.25], [1.25, 3.0]];
+    let lhs = DeepEx::<f64>::from_str("x+1").unwrap();
+    let rhs = DeepEx::<f64>::from_str("y-2").unwrap();
+    let composed = [
+        (lhs.clone() + rhs.clone(), "+"),
+        (lhs.clone() - rhs.clone(), "-"),
+        (lhs.clone() * rhs.clone(), "*"),
+        (lhs.clone() / rhs.clone(), "/"),
+        (lhs.pow(rhs).unwrap(), "^"),
+    ];
+    for (deepex, repr) in &composed {
+        assert_eq!(deepex.bin_ops.reprs.last(), Some(repr));
+        let lhs_str = if ["+", "-"].contains(repr) {
+            "x+1.0"
+        } else {
+            "(x+1.0)"
+        };
+        assert_eq!(deepex.unparse(), format!("{}{}(y-2.0)", lhs_str, repr));
+        assert_round_trip(deepex, &points);
+    }
+
+    // derivatives are composed with the overloaded operators as well
+    let ops = make_default_operators::<f64>();
+    for text in ["x*y^2", "sin(x)/y", "x^y-log(x*y)", "-(x/y)*cos(x)"] {
+        let deepex = DeepEx::<f64>::from_str(text).unwrap();
+        for var_idx in 0..2 {
+            let partial = partial_deepex(var_idx, deepex.clone(), &ops).unwrap();
+            assert_round_trip(&partial, &points);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 #[test]
 fn test_partial_finite() {
     let ops = make_default_operators::<f64>();
@@ -642,17 +2181,17 @@ fn test_partial_finite() {
                 .enumerate()
                 .map(|(i, x0)| if i == var_idx { x0 + step } else { *x0 })
                 .collect();
-            let flat_dut = flatten(dut.clone());
+            let flat_dut = flatten(dut.clone()).unwrap();
             let f0 = flat_dut.eval(&x0s).unwrap();
             let f1 = flat_dut.eval(&x1s).unwrap();
             let finite_diff = (f1 - f0) / step;
-            let deri = partial_deepex(var_idx, dut.clone(), &ops).unwrap();
+            let deri = partial_deepex(var_idx, dut.clone(), ops).unwrap();
             println!(
                 "test_partial_finite - d_{} is {} for {}",
                 var_name, deri, sut
             );
 
-            let flat_deri = flatten(deri.clone()).eval(&x0s).unwrap();
+            let flat_deri = flatten(deri.clone()).unwrap().eval(&x0s).unwrap();
             assert_float_eq::<f64>(
                 flat_deri,
                 finite_diff,
@@ -676,6 +2215,7 @@ fn test_partial_finite() {
     test("sin(y+x)/((x*2)/y)*(2*x)", &ops, -1.0..1.0);
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_var_names() {
     let deepex = DeepEx::<f64>::from_str("x+y+{x}+z*(-y)").unwrap();
@@ -683,39 +2223,290 @@ fn test_var_names() {
     assert_eq!(deepex.var_names, reference);
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_deep_compile() {
     let ops = make_default_operators();
-    let nodes = vec![DeepNode::Num(4.5), DeepNode::Num(0.5), DeepNode::Num(1.4)];
+    let nodes = vec![
+        DeepNode::Num(4.5, None),
+        DeepNode::Num(0.5, None),
+        DeepNode::Num(1.4, None),
+    ];
     let bin_ops = BinOpsWithReprs {
         reprs: vec![ops[1].repr, ops[3].repr],
         ops: smallvec![ops[1].bin_op.unwrap(), ops[3].bin_op.unwrap()],
     };
-    let unary_op = UnaryOpWithReprs {
-        reprs: vec![ops[6].repr],
-        op: UnaryOp::from_vec(smallvec![ops[6].unary_op.unwrap()]),
-    };
+    let unary_op = UnaryOpWithReprs::prefix(
+        vec![ops[6].repr],
+        UnaryOp::from_vec(smallvec![ops[6].unary_op.unwrap()]),
+    );
     let deep_ex = DeepEx::new(nodes, bin_ops, unary_op).unwrap();
 
     let bin_ops = BinOpsWithReprs {
         reprs: vec![ops[1].repr, ops[3].repr],
         ops: smallvec![ops[1].bin_op.unwrap(), ops[3].bin_op.unwrap()],
     };
-    let unary_op = UnaryOpWithReprs {
-        reprs: vec![ops[6].repr],
-        op: UnaryOp::from_vec(smallvec![ops[6].unary_op.unwrap()]),
-    };
+    let unary_op = UnaryOpWithReprs::prefix(
+        vec![ops[6].repr],
+        UnaryOp::from_vec(smallvec![ops[6].unary_op.unwrap()]),
+    );
     let nodes = vec![
-        DeepNode::Num(4.5),
-        DeepNode::Num(0.5),
-        DeepNode::Expr(deep_ex),
+        DeepNode::Num(4.5, None),
+        DeepNode::Num(0.5, None),
+        DeepNode::expr(deep_ex),
     ];
     let deep_ex = DeepEx::new(nodes, bin_ops, unary_op).unwrap();
     assert_eq!(deep_ex.nodes.len(), 1);
     match deep_ex.nodes[0] {
-        DeepNode::Num(n) => assert_eq!(deep_ex.unary_op.op.apply(n), n),
-        _ => {
-            assert!(false);
+        DeepNode::Num(n, _) => assert_eq!(deep_ex.unary_op.op.apply(n), n),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_compile_unary() {
+    let ops = make_default_operators::<f64>();
+    let minus = || {
+        UnaryOpWithReprs::prefix(
+            vec![ops[4].repr],
+            UnaryOp::from_vec(smallvec![ops[4].unary_op.unwrap()]),
+        )
+    };
+    let two = DeepEx::from_node(DeepNode::Num(2.0, None), find_overloaded_ops(&ops).unwrap());
+    let minus_two = two.with_new_unary_op(minus());
+    assert_eq!(minus_two.nodes, vec![DeepNode::Num(-2.0, None)]);
+    assert_eq!(minus_two.unary_op.op.len(), 0);
+
+    // a sub-expression whose unary operator has not been applied yet
+    let mut sub = DeepEx::new(
+        vec![DeepNode::Num(3.0, None)],
+        BinOpsWithReprs::new(),
+        UnaryOpWithReprs::new(),
+    )
+    .unwrap();
+    sub.unary_op = minus();
+    let bin_ops = BinOpsWithReprs {
+        reprs: vec![ops[3].repr],
+        ops: smallvec![ops[3].bin_op.unwrap()],
+    };
+    let deepex = DeepEx::new(
+        vec![DeepNode::Var((0, "x")), DeepNode::expr(sub)],
+        bin_ops,
+        UnaryOpWithReprs::new(),
+    )
+    .unwrap();
+    assert_eq!(deepex.nodes[1], DeepNode::Num(-3.0, None));
+    assert_float_eq_f64(flatten(deepex).unwrap().eval(&[1.0]).unwrap(), -2.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sub_expr_info() {
+    let deepex = DeepEx::<f64>::from_str("sin(y)*x + cos((y+1)^2) + x").unwrap();
+    let info = deepex.sub_expr_info();
+    assert_eq!(info.var_names, ["x", "y"]);
+    assert_eq!(info.n_nodes, 6);
+    assert_eq!(info.depth, 3);
+    assert_eq!(info.root_op_repr, Some("+"));
+    let info = DeepEx::<f64>::from_str("x^2*y").unwrap().sub_expr_info();
+    assert_eq!(info.root_op_repr, Some("*"));
+    assert_eq!(info.depth, 1);
+    let info = DeepEx::<f64>::from_str("-(x^2*y)").unwrap().sub_expr_info();
+    assert_eq!(info.root_op_repr, Some("-"));
+    assert_eq!(info.n_nodes, 3);
+    let info = DeepEx::<f64>::from_str("z").unwrap().sub_expr_info();
+    assert_eq!(info.root_op_repr, None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_literal_texts() {
+    let ops = make_default_operators::<f64>();
+    let preserve = ParseOptions {
+        preserve_literal_text: true,
+        ..ParseOptions::default()
+    };
+    let parse = |text, options| {
+        DeepEx::<f64>::from_options(text, &ops, options, &[])
+            .unwrap()
+            .0
+    };
+
+    let deepex = parse("x * 1.50 + .5", &preserve);
+    assert_eq!(deepex.unparse(), "x*1.50+.5");
+    assert_eq!(deepex.literal_texts, ["1.50", ".5"]);
+    assert_eq!(parse("(1.50)*x^2.50", &preserve).unparse(), "1.50*x^2.50");
+    assert_eq!(parse("2.0*x + 2.00", &preserve).unparse(), "2.0*x+2.00");
+
+    // folded numbers are synthetic
+    let deepex = parse("2*3*x", &preserve);
+    assert_eq!(deepex.nodes[0], DeepNode::Num(6.0, None));
+    assert_eq!(deepex.unparse(), "6.0*x");
+    assert_eq!(parse("-1.50*x", &preserve).unparse(), "(-1.5)*x");
+
+    let default = parse("x * 1.50 + .5", &ParseOptions::default());
+    assert_eq!(default.unparse(), "x*1.5+0.5");
+    assert!(default.literal_texts.is_empty());
+    assert_eq!(default, DeepEx::from_str("x * 1.50 + .5").unwrap());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_prune_neutral_operands() {
+    let ops = make_default_operators::<f64>();
+    let minus = || {
+        UnaryOpWithReprs::prefix(
+            vec![ops[4].repr],
+            UnaryOp::from_vec(smallvec![ops[4].unary_op.unwrap()]),
+        )
+    };
+    // -(-(1)) with unary operators that have not been applied yet
+    let mut inner = DeepEx::new(
+        vec![DeepNode::Num(1.0, None)],
+        BinOpsWithReprs::new(),
+        UnaryOpWithReprs::new(),
+    )
+    .unwrap();
+    inner.unary_op = minus();
+    let mut outer = DeepEx::new(
+        vec![DeepNode::expr(inner)],
+        BinOpsWithReprs::new(),
+        UnaryOpWithReprs::new(),
+    )
+    .unwrap();
+    outer.unary_op = minus();
+    assert!(outer.is_one());
+    assert!(outer.is_const(1.0));
+    assert!(!outer.is_const(-1.0));
+    assert!(!outer.is_zero());
+
+    let pruned = |text| {
+        let mut deepex = DeepEx::<f64>::from_str(text).unwrap();
+        deepex.prune_neutral_operands();
+        deepex.unparse()
+    };
+    assert_eq!(pruned("x*1"), "x");
+    assert_eq!(pruned("1*x^2"), "x^2.0");
+    assert_eq!(pruned("y+x^1"), "y+x");
+    assert_eq!(pruned("b-0+a"), "b+a");
+    assert_eq!(pruned("a/1*b"), "a*b");
+    assert_eq!(pruned("a+1*b"), "a+b");
+    assert_eq!(pruned("sin((x+0)*1)"), "sin(x)");
+    // operands that the operator is not applied to are kept
+    assert_eq!(pruned("x*1^y"), "x*1.0^y");
+    assert_eq!(pruned("a-0*b"), "a-0.0*b");
+    assert_eq!(pruned("0-x"), "0.0-x");
+    assert_eq!(pruned("1/x"), "1.0/x");
+}
+
+#[cfg(all(test, feature = "std"))]
+use {
+    crate::{operators::make_default_operators_int, Semantics},
+    rand::{rngs::StdRng, SeedableRng},
+};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval() {
+    fn random_expr(rng: &mut StdRng, depth: usize) -> String {
+        const BIN_OPS: [&str; 12] = [
+            "+", "-", "*", "/", "^", "%", "<", "<=", ">", ">=", "==", "!=",
+        ];
+        const UNARY_OPS: [&str; 10] = [
+            "-", "sin", "cos", "tanh", "exp", "sqrt", "abs", "floor", "signum", "cbrt",
+        ];
+        let n_operands = rng.gen_range(1..5);
+        let mut text = String::new();
+        for i in 0..n_operands {
+            if i > 0 {
+                text.push_str(BIN_OPS[rng.gen_range(0..BIN_OPS.len())]);
+            }
+            let operand = match rng.gen_range(0..6) {
+                0 | 1 if depth > 0 => format!("({})", random_expr(rng, depth - 1)),
+                2 if depth > 0 => format!(
+                    "{}({})",
+                    UNARY_OPS[rng.gen_range(0..UNARY_OPS.len())],
+                    random_expr(rng, depth - 1)
+                ),
+                3 if depth > 0 => format!(
+                    "logb({}, {})",
+                    random_expr(rng, depth - 1),
+                    random_expr(rng, depth - 1)
+                ),
+                4 => format!("{}", f64::from(rng.gen_range(0..16)) * 0.25),
+                _ => ["x", "y", "z"][rng.gen_range(0..3)].to_string(),
+            };
+            text.push_str(&operand);
+        }
+        text
+    }
+
+    let ops = make_default_operators::<f64>();
+    let strict = ParseOptions {
+        semantics: Semantics::IeeeStrict,
+        ..ParseOptions::default()
+    };
+    let mut rng = StdRng::seed_from_u64(2081);
+    let mut n_finite = 0;
+    for _ in 0..500 {
+        let text = random_expr(&mut rng, 3);
+        let deepex = DeepEx::<f64>::from_options(&text, &ops, &strict, &[])
+            .unwrap()
+            .0;
+        let flatex = flatten(deepex.clone()).unwrap();
+        for _ in 0..5 {
+            let vars = (0..deepex.n_vars())
+                .map(|_| rng.gen_range(-3.0..3.0))
+                .collect::<Vec<f64>>();
+            let deep_res = deepex.eval(&vars).unwrap();
+            let flat_res = flatex.eval(&vars).unwrap();
+            assert!(
+                deep_res.to_bits() == flat_res.to_bits()
+                    || (deep_res.is_nan() && flat_res.is_nan()),
+                "{} at {:?}: {} vs {}",
+                text,
+                vars,
+                deep_res,
+                flat_res
+            );
+            n_finite += usize::from(deep_res.is_finite());
+        }
+    }
+    // most of the random expressions are not degenerate
+    assert!(n_finite > 1000);
+
+    let deepex = DeepEx::<f64>::from_str("x-1+2^3^(y-1)*sin(-x)").unwrap();
+    assert_float_eq_f64(
+        deepex.eval(&[1.0, 2.0]).unwrap(),
+        1.0 - 1.0 + 8f64.powf(1.0) * (-1f64).sin(),
+    );
+    assert_eq!(
+        deepex.eval(&[1.0]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+
+    // fallible operations are applied also in sub-expressions
+    let mut ops = make_default_operators_int::<i64>();
+    for op in ops.iter_mut() {
+        if op.repr == "/" {
+            op.fallible = Some(FallibleOp {
+                bin_op: Some(|a: i64, b| a.checked_div(b).ok_or_else(|| "division by zero".into())),
+                unary_op: None,
+            });
         }
     }
+    let deepex = DeepEx::<i64>::from_pattern("-(2*(x/y))+1", &ops, "[0-9]+").unwrap();
+    assert_eq!(deepex.eval(&[7, 2]), Ok(-5));
+    assert_eq!(
+        deepex.eval(&[7, 0]),
+        Err(ExError::OperatorFailed {
+            op: "/".to_string(),
+            operands: vec!["7".to_string(), "0".to_string()],
+            reason: "division by zero".to_string()
+        })
+    );
 }
@@ -1,20 +1,38 @@
-use std::{fmt::Debug, iter::once, str::FromStr};
+use alloc::sync::Arc;
+use core::{cmp::Reverse, fmt::Debug, str::FromStr};
 
 use smallvec::SmallVec;
 
 use crate::{
-    definitions::{N_NODES_ON_STACK, N_VARS_ON_STACK},
-    operators::{BinOp, UnaryOp, VecOfUnaryFuncs},
-    parser::{ExParseError, Paren, ParsedToken},
-    Operator,
+    alloc_prelude::*,
+    definitions::N_VARS_ON_STACK,
+    operators::{NaryOp, UnaryOp, UnaryPosition},
+    parser::{self, Optimizations, Paren, ParsedToken, Span, TokenKind},
+    ExError, Operator, PairViolation, ParseOptions, VarOrder,
 };
 
-use super::deep::{BinOpVec, BinOpsWithReprs, DeepEx, DeepNode, ExprIdxVec, UnaryOpWithReprs};
+use super::deep::{
+    intern_literal, reset_vars, BinOpVec, BinOpsWithReprs, DeepEx, DeepNode, ExprIdxVec,
+    UnaryOpWithReprs,
+};
+
+/// Already parsed expressions that replace variables with the given names.
+pub type Definitions<'a, 'b, T> = [(&'b str, &'b DeepEx<'a, T>)];
+
+fn find_definition<'a, 'b, T: Copy + Debug>(
+    name: &str,
+    defs: &Definitions<'a, 'b, T>,
+) -> Option<&'b DeepEx<'a, T>> {
+    defs.iter()
+        .find(|(def_name, _)| *def_name == name)
+        .map(|(_, def)| *def)
+}
 
 pub const ADD_REPR: &str = "+";
 pub const SUB_REPR: &str = "-";
 pub const MUL_REPR: &str = "*";
 pub const DIV_REPR: &str = "/";
+pub const POW_REPR: &str = "^";
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct OverloadedOps<'a, T: Copy> {
@@ -22,15 +40,25 @@ pub struct OverloadedOps<'a, T: Copy> {
     pub sub: Operator<'a, T>,
     pub mul: Operator<'a, T>,
     pub div: Operator<'a, T>,
+    /// Not all operator sets contain a power operator.
+    pub pow: Option<Operator<'a, T>>,
 }
 impl<'a, T: Copy> OverloadedOps<'a, T> {
-    pub fn by_repr(&self, repr: &str) -> Operator<'a, T> {
+    pub fn find(&self, repr: &str) -> Option<Operator<'a, T>> {
         match repr {
-            ADD_REPR => self.add,
-            SUB_REPR => self.sub,
-            MUL_REPR => self.mul,
-            DIV_REPR => self.div,
-            _ => panic!("{} is not a repr of an overloaded operator", repr),
+            ADD_REPR => Some(self.add),
+            SUB_REPR => Some(self.sub),
+            MUL_REPR => Some(self.mul),
+            DIV_REPR => Some(self.div),
+            POW_REPR => self.pow,
+            _ => None,
+        }
+    }
+
+    pub fn by_repr(&self, repr: &str) -> Operator<'a, T> {
+        match self.find(repr) {
+            Some(op) => op,
+            None => panic!("{} is not a repr of an overloaded operator", repr),
         }
     }
 }
@@ -42,7 +70,12 @@ pub fn find_overloaded_ops<'a, T: Copy>(all_ops: &[Operator<T>]) -> Option<Overl
             Some(op) => Some(Operator {
                 bin_op: op.bin_op,
                 unary_op: op.unary_op,
-                repr: repr,
+                repr,
+                domain_check: op.domain_check,
+                unary_position: op.unary_position,
+                nary_op: op.nary_op,
+                fallible: op.fallible,
+                aliases: &[],
             }),
             None => None,
         }
@@ -53,45 +86,614 @@ pub fn find_overloaded_ops<'a, T: Copy>(all_ops: &[Operator<T>]) -> Option<Overl
         sub: find_op(SUB_REPR)?,
         mul: find_op(MUL_REPR)?,
         div: find_op(DIV_REPR)?,
+        pow: find_op(POW_REPR),
     })
 }
 
+/// Returns the optimizations of the [`semantics`](ParseOptions::semantics) except that
+/// constants are not folded if a fallible operator is involved, such that its failures are
+/// reported on evaluation.
+fn optimizations<T: Copy + FromStr>(
+    options: &ParseOptions,
+    parsed_tokens: &[ParsedToken<T>],
+) -> Optimizations {
+    let mut optimizations = options.semantics.optimizations();
+    if parsed_tokens
+        .iter()
+        .any(|token| matches!(&token.kind, TokenKind::Op(op) if op.fallible.is_some()))
+    {
+        optimizations.fold_constants = false;
+    }
+    optimizations
+}
+
 pub fn parsed_tokens_to_deepex<'a, T: Copy + FromStr + Debug>(
+    text: &'a str,
     parsed_tokens: &[ParsedToken<'a, T>],
-) -> Result<DeepEx<'a, T>, ExParseError> {
-    let mut found_vars = SmallVec::<[&str; N_VARS_ON_STACK]>::new();
-    let mut parsed_vars = parsed_tokens
-        .iter()
-        .filter_map(|pt| match pt {
-            ParsedToken::Var(name) => {
-                if !found_vars.contains(name) {
-                    found_vars.push(*name);
-                    Some(*name)
-                } else {
-                    None
+    defs: &Definitions<'a, '_, T>,
+    options: &ParseOptions,
+) -> Result<DeepEx<'a, T>, ExError> {
+    // variables of definitions replace the names of the definitions
+    let mut parsed_vars = SmallVec::<[&str; N_VARS_ON_STACK]>::new();
+    for pt in parsed_tokens {
+        if let TokenKind::Var(name) = &pt.kind {
+            let names = match find_definition(name, defs) {
+                Some(def) => def.var_names(),
+                None => core::slice::from_ref(name),
+            };
+            for name in names {
+                if !parsed_vars.contains(name) {
+                    parsed_vars.push(*name);
                 }
             }
-            _ => None,
-        })
-        .collect::<SmallVec<[_; N_NODES_ON_STACK]>>();
+        }
+    }
+    let vars_by_occurrence = parsed_vars.clone();
     parsed_vars.sort_unstable();
-    let (expr, _) = make_expression(
+    let optimizations = optimizations(options, parsed_tokens);
+    let (mut expr, _) = make_expression(
+        text,
         &parsed_tokens[0..],
         &parsed_vars,
-        UnaryOpWithReprs {
-            reprs: vec![],
-            op: UnaryOp::new(),
-        },
+        defs,
+        options,
+        optimizations,
+        UnaryOpWithReprs::new(),
     )?;
+    if options.var_order == VarOrder::Occurrence {
+        reset_vars(&mut expr, vars_by_occurrence);
+    }
+    expr.set_var_order(options.var_order);
     Ok(expr)
 }
 
-/// Returns an expression that is created recursively and can be evaluated
+/// What happens with a sub-expression once it has been built by
+/// [`make_expression`](make_expression). All variants except `Root` continue the
+/// expression the sub-expression is part of.
+enum Continuation<'a, T: Copy + Debug> {
+    /// The expression is the result.
+    Root,
+    /// The expression is in parentheses that start at `operand_start`.
+    Paren { operand_start: usize },
+    /// The expression is the argument of a call of an n-ary operator.
+    Arg(NaryCall<'a, T>),
+    /// The expression is in parentheses after the unary operators starting at `i`.
+    UnaryParen { i: usize },
+    /// The expression ends at `run_end` and is the operand of the first `n_deferred` unary
+    /// operators starting at `i`, which are applied after stronger binary operators.
+    UnaryRun {
+        i: usize,
+        n_deferred: usize,
+        run_end: usize,
+    },
+}
+
+/// Call of an n-ary operator whose arguments are being built.
+struct NaryCall<'a, T: Copy + Debug> {
+    repr: &'a str,
+    op: NaryOp<T>,
+    args: Vec<DeepEx<'a, T>>,
+    /// Index of the operator.
+    start: usize,
+    /// Index of the first token of the next argument.
+    arg_start: usize,
+    /// Index of the unary operators the call is the operand of, if any.
+    unary_start: Option<usize>,
+}
+
+/// State of an expression that is built from `tokens` by
+/// [`make_expression`](make_expression).
+struct Frame<'a, 't, T: Copy + FromStr + Debug> {
+    tokens: &'t [ParsedToken<'a, T>],
+    idx_tkn: usize,
+    nodes: Vec<DeepNode<'a, T>>,
+    bin_ops: BinOpVec<T>,
+    reprs_bin_ops: Vec<&'a str>,
+    literal_texts: Vec<&'a str>,
+    unary_ops: UnaryOpWithReprs<'a, T>,
+    continuation: Continuation<'a, T>,
+}
+
+impl<'a, 't, T: Copy + FromStr + Debug> Frame<'a, 't, T> {
+    fn new(
+        tokens: &'t [ParsedToken<'a, T>],
+        unary_ops: UnaryOpWithReprs<'a, T>,
+        continuation: Continuation<'a, T>,
+    ) -> Self {
+        Frame {
+            tokens,
+            idx_tkn: 0,
+            nodes: vec![],
+            bin_ops: BinOpVec::new(),
+            reprs_bin_ops: vec![],
+            literal_texts: vec![],
+            unary_ops,
+            continuation,
+        }
+    }
+
+    fn push_node(&mut self, node: DeepNode<'a, T>, n_tokens: usize) {
+        self.nodes.push(node);
+        self.idx_tkn += n_tokens;
+    }
+
+    fn push_bin_op(&mut self, op: &Operator<'a, T>) {
+        match op.bin_op {
+            Some(bo) => self.bin_ops.push(bo),
+            None => panic!("This is probably a bug. Expected binary operator but there was none."),
+        }
+        self.reprs_bin_ops.push(op.repr);
+        self.idx_tkn += 1;
+    }
+}
+
+/// Representations of unary operators together with their functions.
+type UnaryFuncsWithReprs<'a, T> = SmallVec<[(&'a str, fn(T) -> T); 8]>;
+
+/// Unary operators that start at `i` and are applied to the same operand.
+fn prefix_unary_ops<'a, T: Copy + FromStr>(
+    parsed_tokens: &[ParsedToken<'a, T>],
+    i: usize,
+) -> UnaryFuncsWithReprs<'a, T> {
+    parsed_tokens[i..]
+        .iter()
+        .map_while(|token| match token.kind {
+            TokenKind::Op(op) => op.unary_op.map(|uo| (op.repr, uo)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Everything [`make_expression`](make_expression) needs besides the tokens.
+struct ExprContext<'a, 'c, T: Copy + Debug> {
+    text: &'a str,
+    parsed_vars: &'c [&'a str],
+    defs: &'c Definitions<'a, 'c, T>,
+    options: &'c ParseOptions,
+    optimizations: Optimizations,
+}
+
+impl<'a, 'c, T: Copy + FromStr + Debug> ExprContext<'a, 'c, T> {
+    fn make_var_node(&self, name: &'a str) -> DeepNode<'a, T> {
+        match find_definition(name, self.defs) {
+            Some(def) => {
+                let mut def = def.clone();
+                reset_vars(&mut def, self.parsed_vars.iter().copied().collect());
+                DeepNode::expr(def)
+            }
+            None => match self.parsed_vars.iter().position(|n| *n == name) {
+                Some(i) => DeepNode::Var((i, name)),
+                None => panic!("This is probably a bug. I don't know variable {}", name),
+            },
+        }
+    }
+
+    /// Postfix unary operators starting at `idx` are applied to the operand that starts at
+    /// `operand_start` before prefix unary operators, e.g., `-3!` is `-(3!)`.
+    fn apply_postfix(
+        &self,
+        parsed_tokens: &[ParsedToken<'a, T>],
+        node: DeepNode<'a, T>,
+        operand_start: usize,
+        idx: usize,
+    ) -> Result<(DeepNode<'a, T>, usize), ExError> {
+        let n_postfix = n_postfix_ops(parsed_tokens, idx);
+        if n_postfix == 0 {
+            return Ok((node, 0));
+        }
+        let postfix_tokens = &parsed_tokens[idx..idx + n_postfix];
+        let start = parsed_tokens[operand_start].span.start;
+        let expr = DeepEx::new_with_literals(
+            vec![node],
+            BinOpsWithReprs::new(),
+            UnaryOpWithReprs::new(),
+            vec![],
+            self.optimizations,
+        )?;
+        if let (true, [DeepNode::Num(n, _)]) =
+            (self.options.strict_domains, expr.nodes().as_slice())
+        {
+            let uops = postfix_tokens.iter().map(|token| {
+                let span = Span {
+                    start,
+                    end: token.span.end,
+                };
+                (token, span)
+            });
+            check_domains(self.text, uops, *n)?;
+        }
+        let span = Span {
+            start,
+            end: postfix_tokens[n_postfix - 1].span.end,
+        };
+        let expr = expr
+            .with_new_unary_op(postfix_unary_ops(postfix_tokens))
+            .with_span(span);
+        Ok((DeepNode::expr(expr), n_postfix))
+    }
+
+    /// Applies the first `n_applied` unary operators starting at `i` to `expr`, which has
+    /// been compiled such that constant arguments can be checked. The result spans the
+    /// `n_tokens` tokens starting at `i`.
+    fn apply_unary_ops(
+        &self,
+        parsed_tokens: &[ParsedToken<'a, T>],
+        i: usize,
+        expr: DeepEx<'a, T>,
+        n_applied: usize,
+        n_tokens: usize,
+    ) -> Result<(DeepNode<'a, T>, usize), ExError> {
+        let span = Span {
+            start: parsed_tokens[i].span.start,
+            end: parsed_tokens[i + n_tokens - 1].span.end,
+        };
+        if let (true, [DeepNode::Num(n, _)]) =
+            (self.options.strict_domains, expr.nodes().as_slice())
+        {
+            let uops = parsed_tokens[i..i + n_applied].iter().rev().map(|token| {
+                let span = Span {
+                    start: token.span.start,
+                    end: span.end,
+                };
+                (token, span)
+            });
+            check_domains(self.text, uops, *n)?;
+        }
+        let uops = prefix_unary_ops(parsed_tokens, i);
+        let unary_ops = UnaryOpWithReprs::prefix(
+            uops[..n_applied].iter().map(|(repr, _)| *repr).collect(),
+            UnaryOp::from_vec(uops[..n_applied].iter().map(|(_, uo)| *uo).collect()),
+        );
+        let expr = expr.with_new_unary_op(unary_ops).with_span(span);
+        Ok((DeepNode::expr(expr), n_tokens))
+    }
+
+    /// Starts the call of the n-ary operator at `i`, which is followed by its parenthesized
+    /// arguments that are separated by commas. The number of arguments has been checked
+    /// by the tokenizer. Returns the frame of the first argument.
+    fn start_call<'t>(
+        &self,
+        parsed_tokens: &'t [ParsedToken<'a, T>],
+        i: usize,
+        unary_start: Option<usize>,
+    ) -> Result<Frame<'a, 't, T>, ExError> {
+        let (repr, op) = match parsed_tokens[i].kind {
+            TokenKind::Op(Operator {
+                repr,
+                nary_op: Some(op),
+                ..
+            }) => (repr, op),
+            _ => {
+                return Err(ExError::Internal(
+                    "expected n-ary operator at the beginning of a call".to_string(),
+                ))
+            }
+        };
+        // skip the operator and the opening parenthesis
+        let arg_start = i + 2;
+        let call = NaryCall {
+            repr,
+            op,
+            args: vec![],
+            start: i,
+            arg_start,
+            unary_start,
+        };
+        Ok(Frame::new(
+            &parsed_tokens[arg_start..],
+            UnaryOpWithReprs::new(),
+            Continuation::Arg(call),
+        ))
+    }
+
+    /// Handles the unary operator at `i` of `frame` and the operand it is applied to.
+    /// Returns the frame of the operand if it is a sub-expression.
+    fn process_unary<'t>(
+        &self,
+        frame: &mut Frame<'a, 't, T>,
+        i: usize,
+    ) -> Result<Option<Frame<'a, 't, T>>, ExError> {
+        let parsed_tokens = frame.tokens;
+        let n_uops = prefix_unary_ops(parsed_tokens, i).len();
+
+        // leading unary operators with binary variants are applied after stronger binary
+        // operators
+        let bin_prios_of_uops = parsed_tokens[i..i + n_uops]
+            .iter()
+            .map_while(|token| match &token.kind {
+                TokenKind::Op(op) => op.bin_op.map(|bin_op| bin_op.prio),
+                _ => None,
+            })
+            .collect::<SmallVec<[i32; 8]>>();
+        let n_deferred = bin_prios_of_uops.len();
+        if let (true, Some(&prio)) = (
+            self.options.unary_minus_binds_weaker_than_pow,
+            bin_prios_of_uops.iter().min(),
+        ) {
+            let operand_end = end_of_operand(parsed_tokens, i + n_deferred);
+            let run_end = end_of_stronger_run(parsed_tokens, i + n_deferred, prio);
+            if run_end > operand_end {
+                return Ok(Some(Frame::new(
+                    &parsed_tokens[i + n_deferred..run_end],
+                    UnaryOpWithReprs::new(),
+                    Continuation::UnaryRun {
+                        i,
+                        n_deferred,
+                        run_end,
+                    },
+                )));
+            }
+        }
+
+        let operand_idx = i + n_uops;
+        let token = &parsed_tokens[operand_idx];
+        let invalid_pair = |violation| ExError::InvalidPair {
+            position: token.span.start,
+            violation,
+            context: parser::context(self.text, token.span),
+        };
+        match &token.kind {
+            TokenKind::Paren(p) => match p {
+                Paren::Close => Err(invalid_pair(PairViolation::OpBeforeClosingParen)),
+                Paren::Open => Ok(Some(Frame::new(
+                    &parsed_tokens[operand_idx + 1..],
+                    UnaryOpWithReprs::new(),
+                    Continuation::UnaryParen { i },
+                ))),
+            },
+            TokenKind::Var(name) => {
+                self.finish_unary(frame, i, self.make_var_node(name), 1)?;
+                Ok(None)
+            }
+            TokenKind::Num(n) => {
+                self.finish_unary(frame, i, DeepNode::Num(*n, None), 1)?;
+                Ok(None)
+            }
+            TokenKind::Op(op) if op.nary_op.is_some() => self
+                .start_call(parsed_tokens, operand_idx, Some(i))
+                .map(Some),
+            TokenKind::Op(_) => Err(invalid_pair(PairViolation::BinaryAfterUnary)),
+            TokenKind::Comma => Err(invalid_pair(PairViolation::MisplacedComma)),
+        }
+    }
+
+    /// Applies the unary operators starting at `i` of `frame` to their operand `node` that
+    /// consists of `n_operand_tokens` tokens and adds the result to `frame`.
+    fn finish_unary(
+        &self,
+        frame: &mut Frame<'a, '_, T>,
+        i: usize,
+        node: DeepNode<'a, T>,
+        n_operand_tokens: usize,
+    ) -> Result<(), ExError> {
+        let parsed_tokens = frame.tokens;
+        let n_uops = prefix_unary_ops(parsed_tokens, i).len();
+        let operand_idx = i + n_uops;
+        let (node, n_postfix) = self.apply_postfix(
+            parsed_tokens,
+            node,
+            operand_idx,
+            operand_idx + n_operand_tokens,
+        )?;
+        let expr = match node {
+            // parenthesized expressions without postfix operators need no further level
+            DeepNode::Expr(expr)
+                if n_postfix == 0
+                    && matches!(parsed_tokens[operand_idx].kind, TokenKind::Paren(_)) =>
+            {
+                Arc::unwrap_or_clone(expr)
+            }
+            node => DeepEx::new_with_literals(
+                vec![node],
+                BinOpsWithReprs::new(),
+                UnaryOpWithReprs::new(),
+                vec![],
+                self.optimizations,
+            )?,
+        };
+        let (node, n_tokens) = self.apply_unary_ops(
+            parsed_tokens,
+            i,
+            expr,
+            n_uops,
+            n_uops + n_operand_tokens + n_postfix,
+        )?;
+        frame.push_node(node, n_tokens);
+        Ok(())
+    }
+
+    /// Checks one token of `frame` after the next until the expression ends or a
+    /// sub-expression starts whose frame is returned.
+    fn advance<'t>(
+        &self,
+        frame: &mut Frame<'a, 't, T>,
+    ) -> Result<Option<Frame<'a, 't, T>>, ExError> {
+        let parsed_tokens = frame.tokens;
+        while frame.idx_tkn < parsed_tokens.len() {
+            let idx_tkn = frame.idx_tkn;
+            match &parsed_tokens[idx_tkn].kind {
+                TokenKind::Op(op) if op.nary_op.is_some() => {
+                    return self.start_call(parsed_tokens, idx_tkn, None).map(Some);
+                }
+                TokenKind::Op(op) => match op.unary_op {
+                    None => frame.push_bin_op(op),
+                    // if the first element is an operator it must be unary
+                    Some(_) if idx_tkn == 0 => {
+                        if let Some(child) = self.process_unary(frame, idx_tkn)? {
+                            return Ok(Some(child));
+                        }
+                    }
+                    // decide type of operator based on predecessor
+                    Some(_) => match &parsed_tokens[idx_tkn - 1].kind {
+                        // number or variable as predecessor means binary operator
+                        TokenKind::Num(_) | TokenKind::Var(_) => frame.push_bin_op(op),
+                        TokenKind::Paren(p) => match p {
+                            Paren::Open => {
+                                let msg = "This is probably a bug. An opening paren cannot be the predecessor of a binary operator.";
+                                panic!("{}", msg);
+                            }
+                            Paren::Close => frame.push_bin_op(op),
+                        },
+                        // postfix operators are followed by binary operators
+                        TokenKind::Op(op_before)
+                            if op_before.is_postfix() && op.bin_op.is_some() =>
+                        {
+                            frame.push_bin_op(op)
+                        }
+                        TokenKind::Op(_) | TokenKind::Comma => {
+                            if let Some(child) = self.process_unary(frame, idx_tkn)? {
+                                return Ok(Some(child));
+                            }
+                        }
+                    },
+                },
+                TokenKind::Num(n) => {
+                    let (node, n_postfix) = self.apply_postfix(
+                        parsed_tokens,
+                        DeepNode::Num(*n, None),
+                        idx_tkn,
+                        idx_tkn + 1,
+                    )?;
+                    // numbers generated by sums and products have empty spans and no literals
+                    let span = parsed_tokens[idx_tkn].span;
+                    let node = match node {
+                        DeepNode::Num(n, None)
+                            if self.options.preserve_literal_text && span.start < span.end =>
+                        {
+                            let literal = intern_literal(
+                                &mut frame.literal_texts,
+                                &self.text[span.start..span.end],
+                            );
+                            DeepNode::Num(n, Some(literal))
+                        }
+                        node => node,
+                    };
+                    frame.push_node(node, 1 + n_postfix);
+                }
+                TokenKind::Var(name) => {
+                    let (node, n_postfix) = self.apply_postfix(
+                        parsed_tokens,
+                        self.make_var_node(name),
+                        idx_tkn,
+                        idx_tkn + 1,
+                    )?;
+                    frame.push_node(node, 1 + n_postfix);
+                }
+                TokenKind::Paren(p) => match p {
+                    Paren::Open => {
+                        frame.idx_tkn += 1;
+                        return Ok(Some(Frame::new(
+                            &parsed_tokens[idx_tkn + 1..],
+                            UnaryOpWithReprs::new(),
+                            Continuation::Paren {
+                                operand_start: idx_tkn,
+                            },
+                        )));
+                    }
+                    Paren::Close => {
+                        frame.idx_tkn += 1;
+                        break;
+                    }
+                },
+                TokenKind::Comma => {
+                    frame.idx_tkn += 1;
+                    break;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Continues `frame` with its sub-expression `expr` that consists of `n_tokens` tokens
+    /// and is handled according to `continuation`. Returns the frame of the next argument
+    /// of a call of an n-ary operator, if any.
+    fn resume<'t>(
+        &self,
+        frame: &mut Frame<'a, 't, T>,
+        continuation: Continuation<'a, T>,
+        expr: DeepEx<'a, T>,
+        n_tokens: usize,
+    ) -> Result<Option<Frame<'a, 't, T>>, ExError> {
+        let parsed_tokens = frame.tokens;
+        match continuation {
+            Continuation::Root => Err(ExError::Internal(
+                "the root expression has no parent".to_string(),
+            )),
+            Continuation::Paren { operand_start } => {
+                frame.idx_tkn += n_tokens;
+                let (node, n_postfix) = self.apply_postfix(
+                    parsed_tokens,
+                    DeepNode::expr(expr),
+                    operand_start,
+                    frame.idx_tkn,
+                )?;
+                frame.push_node(node, n_postfix);
+                Ok(None)
+            }
+            Continuation::UnaryParen { i } => {
+                self.finish_unary(frame, i, DeepNode::expr(expr), n_tokens + 1)?;
+                Ok(None)
+            }
+            Continuation::UnaryRun {
+                i,
+                n_deferred,
+                run_end,
+            } => {
+                let (node, n_tokens) =
+                    self.apply_unary_ops(parsed_tokens, i, expr, n_deferred, run_end - i)?;
+                frame.push_node(node, n_tokens);
+                Ok(None)
+            }
+            Continuation::Arg(mut call) => {
+                call.args.push(expr);
+                call.arg_start += n_tokens;
+                // each argument ends with a comma or the closing parenthesis of the call
+                if !matches!(
+                    parsed_tokens[call.arg_start - 1].kind,
+                    TokenKind::Paren(Paren::Close)
+                ) {
+                    return Ok(Some(Frame::new(
+                        &parsed_tokens[call.arg_start..],
+                        UnaryOpWithReprs::new(),
+                        Continuation::Arg(call),
+                    )));
+                }
+                let node = DeepNode::NaryCall {
+                    repr: call.repr,
+                    op: call.op,
+                    args: call.args,
+                };
+                let n_call_tokens = call.arg_start - call.start;
+                match call.unary_start {
+                    Some(i) => self.finish_unary(frame, i, node, n_call_tokens)?,
+                    None => {
+                        let (node, n_postfix) = self.apply_postfix(
+                            parsed_tokens,
+                            node,
+                            call.start,
+                            call.start + n_call_tokens,
+                        )?;
+                        frame.push_node(node, n_call_tokens + n_postfix);
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Returns an expression that can be evaluated and the number of tokens it consists of.
+/// Sub-expressions are built on an explicit stack instead of recursively such that deeply
+/// nested expressions cannot overflow the call stack.
 ///
 /// # Arguments
 ///
+/// * `text` - text the tokens have been parsed from
 /// * `parsed_tokens` - parsed tokens created with [`tokenize_and_analyze`](parse::tokenize_and_analyze)
 /// * `parsed_vars` - elements of `parsed_tokens` that are variables
+/// * `defs` - expressions that replace variables with the same names
+/// * `options` - options that change how the tokens are parsed
+/// * `optimizations` - optimizations of the expression, see [`optimizations`](optimizations)
 /// * `unary_ops` - unary operators of the expression to be build
 ///
 /// # Errors
@@ -99,204 +701,254 @@ pub fn parsed_tokens_to_deepex<'a, T: Copy + FromStr + Debug>(
 /// See [`parse_with_number_pattern`](parse_with_number_pattern)
 ///
 pub fn make_expression<'a, T>(
+    text: &'a str,
     parsed_tokens: &[ParsedToken<'a, T>],
     parsed_vars: &[&'a str],
+    defs: &Definitions<'a, '_, T>,
+    options: &ParseOptions,
+    optimizations: Optimizations,
     unary_ops: UnaryOpWithReprs<'a, T>,
-) -> Result<(DeepEx<'a, T>, usize), ExParseError>
+) -> Result<(DeepEx<'a, T>, usize), ExError>
 where
     T: Copy + FromStr + Debug,
 {
-    fn unpack_binop<S>(bo: Option<BinOp<S>>) -> BinOp<S>
-    where
-        S: Copy + FromStr + Debug,
-    {
-        match bo {
-            Some(bo) => bo,
-            None => panic!("This is probably a bug. Expected binary operator but there was none."),
-        }
-    }
-
-    let find_var_index = |name: &str| {
-        let idx = parsed_vars.iter().enumerate().find(|(_, n)| **n == name);
-        match idx {
-            Some((i, _)) => i,
-            None => {
-                panic!("This is probably a bug. I don't know variable {}", name)
-            }
-        }
+    let ctx = ExprContext {
+        text,
+        parsed_vars,
+        defs,
+        options,
+        optimizations,
     };
-    // this closure handles the case that a token is a unary operator and accesses the
-    // variable 'tokens' from the outer scope
-    let process_unary = |i: usize, uo, repr| {
-        // gather subsequent unary operators from the beginning
-        let iter_of_uops = once((repr, uo)).chain(
-            (i + 1..parsed_tokens.len())
-                .map(|j| match parsed_tokens[j] {
-                    ParsedToken::Op(op) => (op.repr, op.unary_op),
-                    _ => ("", None),
-                })
-                .take_while(|(_, uo_)| uo_.is_some())
-                .map(|(repr_, uo_)| (repr_, uo_.unwrap())),
-        );
-        let vec_of_uops = iter_of_uops
-            .clone()
-            .map(|(_, uo_)| uo_)
-            .collect::<VecOfUnaryFuncs<_>>();
-        let vec_of_uop_reprs = iter_of_uops
-            .clone()
-            .map(|(repr_, _)| repr_)
-            .collect::<Vec<_>>();
-        let n_uops = vec_of_uops.len();
-        let uop = UnaryOp::from_vec(vec_of_uops);
-        match &parsed_tokens[i + n_uops] {
-            ParsedToken::Paren(p) => match p {
-                Paren::Close => Err(ExParseError {
-                    msg: "closing parenthesis after an operator".to_string(),
-                }),
-                Paren::Open => {
-                    let (expr, i_forward) = make_expression::<T>(
-                        &parsed_tokens[i + n_uops + 1..],
-                        parsed_vars,
-                        UnaryOpWithReprs {
-                            reprs: vec_of_uop_reprs,
-                            op: uop,
-                        },
-                    )?;
-                    Ok((DeepNode::Expr(expr), i_forward + n_uops + 1))
-                }
+    // the last frame belongs to the innermost sub-expression that is being built
+    let mut frames = vec![Frame::new(parsed_tokens, unary_ops, Continuation::Root)];
+    while let Some(frame) = frames.last_mut() {
+        if let Some(child) = ctx.advance(frame)? {
+            frames.push(child);
+            continue;
+        }
+        let frame = frames.pop().ok_or_else(|| {
+            ExError::Internal("frame of finished expression is missing".to_string())
+        })?;
+        let expr = DeepEx::new_with_literals(
+            frame.nodes,
+            BinOpsWithReprs {
+                reprs: frame.reprs_bin_ops,
+                ops: frame.bin_ops,
             },
-            ParsedToken::Var(name) => {
-                let expr = DeepEx::new(
-                    vec![DeepNode::Var((find_var_index(name), name))],
-                    BinOpsWithReprs {
-                        reprs: Vec::new(),
-                        ops: BinOpVec::new(),
-                    },
-                    UnaryOpWithReprs {
-                        reprs: vec_of_uop_reprs,
-                        op: uop,
-                    },
-                )?;
-                Ok((DeepNode::Expr(expr), n_uops + 1))
+            frame.unary_ops,
+            frame.literal_texts,
+            optimizations,
+        )?;
+        match frames.last_mut() {
+            None => return Ok((expr, frame.idx_tkn)),
+            Some(parent) => {
+                if let Some(child) = ctx.resume(parent, frame.continuation, expr, frame.idx_tkn)? {
+                    frames.push(child);
+                }
             }
-            ParsedToken::Num(n) => Ok((DeepNode::Num(uop.apply(*n)), n_uops + 1)),
-            ParsedToken::Op(_) => Err(ExParseError {
-                msg: "a unary operator cannot be followed by a binary operator".to_string(),
-            }),
         }
-    };
+    }
+    Err(ExError::Internal(
+        "expression ended without result".to_string(),
+    ))
+}
 
-    let mut bin_ops = BinOpVec::new();
-    let mut reprs_bin_ops: Vec<&str> = Vec::new();
-    let mut nodes = Vec::<DeepNode<T>>::new();
-
-    // The main loop checks one token after the next whereby sub-expressions are
-    // handled recursively. Thereby, the token-position-index idx_tkn is increased
-    // according to the length of the sub-expression.
-    let mut idx_tkn: usize = 0;
-    while idx_tkn < parsed_tokens.len() {
-        match &parsed_tokens[idx_tkn] {
-            ParsedToken::Op(op) => match op.unary_op {
-                None => {
-                    bin_ops.push(unpack_binop(op.bin_op));
-                    reprs_bin_ops.push(op.repr);
-                    idx_tkn += 1;
+/// Applies the unary operators of `uop_tokens` in the given order to the constant `value`
+/// and returns an error if an intermediate value is not in the domain of the operator it is
+/// passed to. Each token comes with the span of the application of its operator.
+fn check_domains<'b, 'a: 'b, T: 'b + Copy + FromStr + Debug>(
+    text: &str,
+    mut uop_tokens: impl Iterator<Item = (&'b ParsedToken<'a, T>, Span)>,
+    value: T,
+) -> Result<(), ExError> {
+    uop_tokens
+        .try_fold(value, |value, (token, span)| match &token.kind {
+            TokenKind::Op(op) => {
+                if let Some(reason) = op.domain_check.and_then(|check| check(value)) {
+                    return Err(ExError::DomainViolation {
+                        position: span.start,
+                        end: span.end,
+                        op: op.repr.to_string(),
+                        value: format!("{:?}", value),
+                        reason,
+                        context: parser::context(text, span),
+                    });
                 }
-                Some(uo) => {
-                    // might the operator be unary?
-                    if idx_tkn == 0 {
-                        // if the first element is an operator it must be unary
-                        let (node, idx_forward) = process_unary(idx_tkn, uo, op.repr)?;
-                        nodes.push(node);
-                        idx_tkn += idx_forward;
-                    } else {
-                        // decide type of operator based on predecessor
-                        match &parsed_tokens[idx_tkn - 1] {
-                            ParsedToken::Num(_) | ParsedToken::Var(_) => {
-                                // number or variable as predecessor means binary operator
-                                bin_ops.push(unpack_binop(op.bin_op));
-                                reprs_bin_ops.push(op.repr);
-                                idx_tkn += 1;
-                            }
-                            ParsedToken::Paren(p) => match p {
-                                Paren::Open => {
-                                    let msg = "This is probably a bug. An opening paren cannot be the predecessor of a binary operator.";
-                                    panic!("{}", msg);
-                                }
-                                Paren::Close => {
-                                    bin_ops.push(unpack_binop(op.bin_op));
-                                    reprs_bin_ops.push(op.repr);
-                                    idx_tkn += 1;
-                                }
-                            },
-                            ParsedToken::Op(_) => {
-                                let (node, idx_forward) = process_unary(idx_tkn, uo, op.repr)?;
-                                nodes.push(node);
-                                idx_tkn += idx_forward;
-                            }
-                        }
-                    }
-                }
-            },
-            ParsedToken::Num(n) => {
-                nodes.push(DeepNode::Num(*n));
-                idx_tkn += 1;
-            }
-            ParsedToken::Var(name) => {
-                nodes.push(DeepNode::Var((find_var_index(name), name)));
-                idx_tkn += 1;
+                Ok(op.unary_op.map_or(value, |uo| uo(value)))
             }
-            ParsedToken::Paren(p) => match p {
-                Paren::Open => {
-                    idx_tkn += 1;
-                    let (expr, i_forward) = make_expression::<T>(
-                        &parsed_tokens[idx_tkn..],
-                        parsed_vars,
-                        UnaryOpWithReprs {
-                            reprs: Vec::new(),
-                            op: UnaryOp::new(),
-                        },
-                    )?;
-                    nodes.push(DeepNode::Expr(expr));
-                    idx_tkn += i_forward;
-                }
-                Paren::Close => {
-                    idx_tkn += 1;
-                    break;
+            _ => Ok(value),
+        })
+        .map(|_| ())
+}
+
+/// Returns the number of operators starting at `start` that are used as postfix unary
+/// operators. An operator that is also binary is used as postfix operator only if no
+/// operand follows, e.g., `3!-2` contains a postfix `!` but `3!2` a binary one.
+fn n_postfix_ops<T: Copy + FromStr>(parsed_tokens: &[ParsedToken<T>], start: usize) -> usize {
+    let kind = |idx: usize| parsed_tokens.get(idx).map(|token| &token.kind);
+    let mut idx = start;
+    while let Some(TokenKind::Op(op)) = kind(idx) {
+        let is_operand_next = match kind(idx + 1) {
+            None | Some(TokenKind::Paren(Paren::Close) | TokenKind::Comma) => false,
+            Some(TokenKind::Op(next)) => next.bin_op.is_none() && !next.is_postfix(),
+            Some(_) => true,
+        };
+        if op.is_postfix() && (op.bin_op.is_none() || !is_operand_next) {
+            idx += 1;
+        } else {
+            break;
+        }
+    }
+    idx - start
+}
+
+/// Creates the unary operators of consecutive postfix tokens. The first token is applied
+/// first and is hence the last one of the result.
+fn postfix_unary_ops<'a, T: Copy + FromStr>(
+    postfix_tokens: &[ParsedToken<'a, T>],
+) -> UnaryOpWithReprs<'a, T> {
+    let ops = postfix_tokens
+        .iter()
+        .rev()
+        .filter_map(|token| match token.kind {
+            TokenKind::Op(op) => op.unary_op.map(|uo| (op.repr, uo)),
+            _ => None,
+        })
+        .collect::<SmallVec<[_; 8]>>();
+    UnaryOpWithReprs {
+        reprs: ops.iter().map(|(repr, _)| *repr).collect(),
+        positions: vec![UnaryPosition::Postfix; ops.len()],
+        op: UnaryOp::from_vec(ops.iter().map(|(_, uo)| *uo).collect()),
+    }
+}
+
+/// Returns the index after the operand that starts at `start` including its prefix and
+/// postfix unary operators.
+fn end_of_operand<T: Copy + FromStr>(parsed_tokens: &[ParsedToken<T>], start: usize) -> usize {
+    let mut idx = start;
+    while matches!(parsed_tokens[idx].kind, TokenKind::Op(_)) {
+        idx += 1;
+    }
+    let end = if matches!(parsed_tokens[idx].kind, TokenKind::Paren(Paren::Open)) {
+        let mut depth = 0;
+        parsed_tokens
+            .iter()
+            .enumerate()
+            .skip(idx)
+            .find_map(|(i, token)| {
+                match token.kind {
+                    TokenKind::Paren(Paren::Open) => depth += 1,
+                    TokenKind::Paren(Paren::Close) => depth -= 1,
+                    _ => (),
                 }
-            },
+                (depth == 0).then(|| i + 1)
+            })
+            .unwrap_or(parsed_tokens.len())
+    } else {
+        idx + 1
+    };
+    end + n_postfix_ops(parsed_tokens, end)
+}
+
+/// Returns the index after the operands starting at `start` that are connected by binary
+/// operators with a priority higher than `prio`.
+fn end_of_stronger_run<T: Copy + FromStr>(
+    parsed_tokens: &[ParsedToken<T>],
+    start: usize,
+    prio: i32,
+) -> usize {
+    let mut end = end_of_operand(parsed_tokens, start);
+    while let Some(TokenKind::Op(op)) = parsed_tokens.get(end).map(|token| &token.kind) {
+        match op.bin_op {
+            Some(bin_op) if bin_op.prio > prio => end = end_of_operand(parsed_tokens, end + 1),
+            _ => break,
         }
     }
-    Ok((
-        DeepEx::new(
-            nodes,
-            BinOpsWithReprs {
-                reprs: reprs_bin_ops,
-                ops: bin_ops,
-            },
-            unary_ops,
-        )?,
-        idx_tkn,
-    ))
+    end
+}
+
+/// Returns for each binary operator whether it is applied before the operators of equal
+/// priority on its left since both of its operands are numbers, see
+/// [`prioritize_constant_pairs`](Optimizations::prioritize_constant_pairs). This regroups,
+/// e.g., `a+b-c` as `a+(b-c)`. Hence, it is only done if the nearest operator on the left
+/// that has at most the same priority and is not prioritized itself has a lower priority
+/// or is `+` followed by `+` or `-` or `*` followed by `*` or `/`. For instance, `x-1+2` is
+/// not regrouped as `x-(1+2)`.
+pub fn prioritized_pairs<'b, P: Ord>(
+    n_ops: usize,
+    is_num_pair: impl Fn(usize) -> bool,
+    prio: impl Fn(usize) -> P,
+    repr: impl Fn(usize) -> &'b str,
+) -> Vec<bool> {
+    let mut prioritized: Vec<bool> = Vec::with_capacity(n_ops);
+    for idx in 0..n_ops {
+        let own_prio = prio(idx);
+        let left = (0..idx).rev().find(|&left| {
+            let left_prio = prio(left);
+            left_prio < own_prio || (left_prio == own_prio && !prioritized[left])
+        });
+        let can_regroup = match left {
+            Some(left) if prio(left) == own_prio => matches!(
+                (repr(left), repr(idx)),
+                ("+", "+") | ("+", "-") | ("*", "*") | ("*", "/")
+            ),
+            _ => true,
+        };
+        prioritized.push(can_regroup && is_num_pair(idx));
+    }
+    prioritized
 }
 
 pub fn prioritized_indices<T: Copy + Debug>(
-    bin_ops: &[BinOp<T>],
+    bin_ops: &BinOpsWithReprs<T>,
     nodes: &[DeepNode<T>],
+    optimizations: Optimizations,
 ) -> ExprIdxVec {
-    let prio_increase = |bin_op_idx: usize| match (&nodes[bin_op_idx], &nodes[bin_op_idx + 1]) {
-        (DeepNode::Num(_), DeepNode::Num(_)) => {
-            let prio_inc = 5;
-            &bin_ops[bin_op_idx].prio * 10 + prio_inc
-        }
-        _ => &bin_ops[bin_op_idx].prio * 10,
+    let is_num_pair = |idx: usize| {
+        optimizations.prioritize_constant_pairs
+            && matches!(
+                (&nodes[idx], &nodes[idx + 1]),
+                (DeepNode::Num(..), DeepNode::Num(..))
+            )
     };
+    let prioritized = prioritized_pairs(
+        bin_ops.ops.len(),
+        is_num_pair,
+        |idx| bin_ops.ops[idx].prio,
+        |idx| bin_ops.reprs[idx],
+    );
+    let mut indices: ExprIdxVec = (0..bin_ops.ops.len()).collect();
+    // ties are applied from left to right like in the flat expression
+    indices.sort_unstable_by_key(|&idx| (Reverse((bin_ops.ops[idx].prio, prioritized[idx])), idx));
+    indices
+}
 
-    let mut indices: ExprIdxVec = (0..bin_ops.len()).collect();
-    indices.sort_by(|i1, i2| {
-        let prio_i1 = prio_increase(*i1);
-        let prio_i2 = prio_increase(*i2);
-        prio_i2.partial_cmp(&prio_i1).unwrap()
+/// Recomputes the result of [`prioritized_indices`] with a stable sort by priority and
+/// by whether the operator is one of the [`prioritized_pairs`] and panics if it differs
+/// from `prio_indices`. This is only compiled into debug builds.
+#[cfg(debug_assertions)]
+pub fn assert_prioritized_indices<T: Copy + Debug>(
+    prio_indices: &[usize],
+    bin_ops: &BinOpsWithReprs<T>,
+    nodes: &[DeepNode<T>],
+    optimizations: Optimizations,
+) {
+    let prio = |idx: usize| bin_ops.ops[idx].prio;
+    let is_num_pair = |idx: usize| {
+        optimizations.prioritize_constant_pairs
+            && matches!(
+                (&nodes[idx], &nodes[idx + 1]),
+                (DeepNode::Num(..), DeepNode::Num(..))
+            )
+    };
+    let prioritized = prioritized_pairs(bin_ops.ops.len(), is_num_pair, prio, |idx| {
+        bin_ops.reprs[idx]
     });
-    indices
+    let mut expected = (0..bin_ops.ops.len()).collect::<Vec<_>>();
+    // stable such that operators with equal keys stay in the order of the string
+    expected.sort_by_key(|&idx| Reverse((prio(idx), prioritized[idx])));
+    assert_eq!(
+        prio_indices, expected,
+        "prioritized indices differ from their recomputation"
+    );
 }
@@ -0,0 +1,140 @@
+use num::Float;
+use smallvec::SmallVec;
+
+use crate::ExError;
+
+type BinOpDual<T> = fn(T, T, T) -> (T, T);
+type UnaryOpDual<T> = fn(T, T) -> T;
+type NaryOpDual<T> = fn(&[T], T, &mut [T]);
+
+/// Local derivatives of an operator for forward-mode differentiation, i.e., the
+/// derivatives of its result with respect to its operands. Each function receives the
+/// values of the operands and the result, see [`eval_with_grad`](crate::FlatEx::eval_with_grad).
+#[derive(Copy, Clone)]
+pub struct DualOp<'a, T: Float> {
+    pub repr: &'a str,
+    /// Returns the derivatives with respect to the left and the right operand.
+    pub bin_op: Option<BinOpDual<T>>,
+    pub unary_op: Option<UnaryOpDual<T>>,
+    /// Writes the derivative with respect to the i-th argument to the i-th entry.
+    pub nary_op: Option<NaryOpDual<T>>,
+}
+
+fn unary<'a, T: Float>(repr: &'a str, unary_op: UnaryOpDual<T>) -> DualOp<'a, T> {
+    DualOp {
+        repr,
+        bin_op: None,
+        unary_op: Some(unary_op),
+        nary_op: None,
+    }
+}
+
+/// Returns the local derivatives of the differentiable default operators, which are the
+/// ones [`partial`](crate::FlatEx::partial) knows as well. The lookup is a `match` such that
+/// it is cheap compared to an evaluation.
+fn find_dual_op<T: Float>(repr: &str) -> Option<DualOp<'_, T>> {
+    let op: DualOp<T> = match repr {
+        "^" => DualOp {
+            repr,
+            bin_op: Some(|a, b, res| (b * a.powf(b - T::one()), res * a.ln())),
+            unary_op: None,
+            nary_op: None,
+        },
+        "*" => DualOp {
+            repr,
+            bin_op: Some(|a, b, _| (b, a)),
+            unary_op: None,
+            nary_op: None,
+        },
+        "/" => DualOp {
+            repr,
+            bin_op: Some(|_, b, res| (T::one() / b, -res / b)),
+            unary_op: None,
+            nary_op: None,
+        },
+        // a % b is a - b * trunc(a / b) and trunc is piecewise constant
+        "%" => DualOp {
+            repr,
+            bin_op: Some(|a, b, _| (T::one(), -(a / b).trunc())),
+            unary_op: None,
+            nary_op: None,
+        },
+        "+" => DualOp {
+            repr,
+            bin_op: Some(|_, _, _| (T::one(), T::one())),
+            unary_op: Some(|_, _| T::one()),
+            nary_op: None,
+        },
+        "-" => DualOp {
+            repr,
+            bin_op: Some(|_, _, _| (T::one(), -T::one())),
+            unary_op: Some(|_, _| -T::one()),
+            nary_op: None,
+        },
+        "sin" => unary(repr, |a, _| a.cos()),
+        "cos" => unary(repr, |a, _| -a.sin()),
+        "tan" => unary(repr, |_, res| T::one() + res * res),
+        "asin" => unary(repr, |a, _| T::one() / (T::one() - a * a).sqrt()),
+        "acos" => unary(repr, |a, _| -T::one() / (T::one() - a * a).sqrt()),
+        "atan" => unary(repr, |a, _| T::one() / (T::one() + a * a)),
+        "sinh" => unary(repr, |a, _| a.cosh()),
+        "cosh" => unary(repr, |a, _| a.sinh()),
+        "tanh" => unary(repr, |_, res| T::one() - res * res),
+        "asinh" => unary(repr, |a, _| T::one() / (a * a + T::one()).sqrt()),
+        "acosh" => unary(repr, |a, _| T::one() / (a * a - T::one()).sqrt()),
+        "atanh" => unary(repr, |a, _| T::one() / (T::one() - a * a)),
+        "exp" => unary(repr, |_, res| res),
+        "sqrt" => unary(repr, |_, res| T::one() / (res + res)),
+        "log" | "ln" => unary(repr, |a, _| T::one() / a),
+        "log2" => unary(repr, |a, _| {
+            T::one() / (a * T::from(std::f64::consts::LN_2).unwrap())
+        }),
+        "log10" => unary(repr, |a, _| {
+            T::one() / (a * T::from(std::f64::consts::LN_10).unwrap())
+        }),
+        "exp2" => unary(repr, |_, res| {
+            res * T::from(std::f64::consts::LN_2).unwrap()
+        }),
+        "cbrt" => unary(repr, |_, res| {
+            T::one() / (T::from(3.0).unwrap() * res * res)
+        }),
+        "abs" => unary(repr, |a, _| a.signum()),
+        "floor" | "ceil" | "round" | "sign" => unary(repr, |_, _| T::zero()),
+        "<" | "<=" | ">" | ">=" | "==" | "!=" => DualOp {
+            repr,
+            bin_op: Some(|_, _, _| (T::zero(), T::zero())),
+            unary_op: None,
+            nary_op: None,
+        },
+        "logb" => DualOp {
+            repr,
+            bin_op: None,
+            unary_op: None,
+            // logb(b, x) = ln(x)/ln(b)
+            nary_op: Some(|args, res, ders| {
+                let (base, x) = (args[0], args[1]);
+                let ln_base = base.ln();
+                ders[0] = -res / (base * ln_base);
+                ders[1] = T::one() / (x * ln_base);
+            }),
+        },
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Returns the local derivatives of the operators with the representations `op_reprs` in
+/// the same order.
+///
+/// # Errors
+///
+/// An [`ExError::DerivativeNotFound`](ExError::DerivativeNotFound) is returned for the
+/// first representation that is not a differentiable default operator.
+pub fn find_dual_ops<'a, T: Float>(
+    op_reprs: &[&'a str],
+) -> Result<SmallVec<[DualOp<'a, T>; 16]>, ExError> {
+    op_reprs
+        .iter()
+        .map(|repr| find_dual_op(repr).ok_or_else(|| ExError::DerivativeNotFound(repr.to_string())))
+        .collect()
+}
@@ -1,35 +1,200 @@
-use super::partial_derivatives::partial_deepex;
+use super::deep_details::prioritized_pairs;
+#[cfg(feature = "std")]
+use super::{
+    dual::{find_dual_ops, DualOp},
+    interval::eval_interval,
+    partial_derivatives::{partial_deepex_with_rules, DiffNum, PartialDerivative},
+};
+#[cfg(feature = "std")]
+use crate::make_default_operators;
+#[cfg(feature = "simd")]
+use crate::simd::{F32x8, F64x4};
 use crate::{
-    definitions::N_NODES_ON_STACK,
-    expression::deep::{DeepEx, DeepNode, ExprIdxVec},
-    make_default_operators,
-    operators::UnaryOp,
-    BinOp, ExParseError,
+    alloc_prelude::*,
+    definitions::{N_NODES_ON_STACK, N_VARS_ON_STACK},
+    expression::deep::{reset_vars, DeepEx, DeepNode, ExprIdxVec, SubExprInfo},
+    interner::{intern, shared_bytes},
+    operators::{FallibleOp, NaryOp, UnaryOp},
+    parser::{self, Optimizations, ParseLimits, Span},
+    util::spilled_bytes,
+    ExError, NameResolution, Operator, VarOrder,
+};
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use core::convert::TryFrom;
+use core::{
+    cmp::Reverse,
+    fmt::{self, Debug, Display, Formatter},
+    iter, mem,
+    ops::{Add, Div, Mul, Sub},
 };
+#[cfg(feature = "std")]
 use num::Float;
 use smallvec::{smallvec, SmallVec};
-use std::fmt::{self, Debug, Display, Formatter};
-pub type FlatNodeVec<T> = SmallVec<[FlatNode<T>; N_NODES_ON_STACK]>;
-pub type FlatOpVec<T> = SmallVec<[FlatOp<T>; N_NODES_ON_STACK]>;
+#[cfg(feature = "std")]
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+pub type FlatNodeVec<T, const N: usize = N_NODES_ON_STACK> = SmallVec<[FlatNode<T>; N]>;
+pub type FlatOpVec<T, const N: usize = N_NODES_ON_STACK> = SmallVec<[FlatOp<T>; N]>;
+/// Indices into the operator representations of a [`FlatEx`](FlatEx), one for each
+/// function of a [`UnaryOp`](UnaryOp).
+type ReprIds = SmallVec<[usize; 8]>;
+/// Creates the operators of an expression again, see [`MakeOperators`](crate::MakeOperators).
+pub(crate) type MakeOps<T> = fn() -> Vec<Operator<'static, T>>;
 
 /// A `FlatOp` contains besides a binary operation an optional unary operation that
 /// will be executed after the binary operation in case of its existence.
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct FlatOp<T: Copy> {
     unary_op: UnaryOp<T>,
-    bin_op: BinOp<T>,
+    bin_op: fn(T, T) -> T,
+    /// Priority in the flat expression, see [`flatten_vecs`](flatten_vecs). It grows with
+    /// the nesting depth and is hence wider than the priority of a [`BinOp`](crate::BinOp).
+    prio: i64,
+    /// Representations of the operators for error messages.
+    unary_repr_ids: ReprIds,
+    bin_repr_id: usize,
+    /// Range of the parsed string of the outermost expression whose unary operators have
+    /// been merged into `unary_op`.
+    span: Option<Span>,
 }
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub enum FlatNodeKind<T: Copy> {
     Num(T),
     Var(usize),
+    NaryCall(FlatNaryCall<T>),
+    /// Value of a common sub-expression, see [`optimize_cse`](FlatEx::optimize_cse). The
+    /// index refers to the variable values followed by the values of the sub-expressions.
+    Tmp(usize),
+}
+
+/// Call of an n-ary operator. Each argument is flattened and evaluated on its own, since
+/// the operator needs the values of all arguments at once.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct FlatNaryCall<T: Copy> {
+    op: NaryOp<T>,
+    repr_id: usize,
+    args: Vec<FlatArg<T>>,
+}
+
+impl<T: Copy> FlatNaryCall<T> {
+    fn eval(&self, vars: &[T]) -> T {
+        let values = self
+            .args
+            .iter()
+            .map(|arg| eval_flat(&arg.nodes, &arg.ops, &arg.plan, vars))
+            .collect::<SmallVec<[T; N_NODES_ON_STACK]>>();
+        (self.op.apply)(&values)
+    }
+
+    fn eval_checked<C>(&self, vars: &[T], checks: &Checks<T, C>) -> Result<T, ExError>
+    where
+        T: Debug,
+        C: Fn(T, usize, &[T]) -> Result<T, ExError>,
+    {
+        let values = self
+            .args
+            .iter()
+            .map(|arg| eval_flat_checked(&arg.nodes, &arg.ops, &arg.plan, vars, checks))
+            .collect::<Result<SmallVec<[T; N_NODES_ON_STACK]>, ExError>>()?;
+        (checks.check)((self.op.apply)(&values), self.repr_id, &values)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Float> FlatNaryCall<T> {
+    fn eval_dual(
+        &self,
+        vars: &[T],
+        var_grads: &[T],
+        dual_ops: &[DualOp<T>],
+        grad: &mut [T],
+    ) -> Result<T, ExError> {
+        let n_vars = grad.len();
+        let stride = n_vars.max(1);
+        let mut arg_grads: SmallVec<[T; N_NODES_ON_STACK]> =
+            smallvec![T::zero(); self.args.len() * stride];
+        let values = self
+            .args
+            .iter()
+            .zip(arg_grads.chunks_exact_mut(stride))
+            .map(|(arg, arg_grad)| {
+                let arg_grad = &mut arg_grad[..n_vars];
+                eval_flat_dual(
+                    &arg.nodes, &arg.ops, &arg.plan, vars, var_grads, dual_ops, arg_grad,
+                )
+            })
+            .collect::<Result<SmallVec<[T; N_NODES_ON_STACK]>, ExError>>()?;
+        let val = (self.op.apply)(&values);
+        let dual_op = &dual_ops[self.repr_id];
+        let nary_op = dual_op
+            .nary_op
+            .ok_or_else(|| ExError::DerivativeNotFound(dual_op.repr.to_string()))?;
+        let mut ders: SmallVec<[T; N_NODES_ON_STACK]> = smallvec![T::zero(); values.len()];
+        nary_op(&values, val, &mut ders);
+        for (var_idx, g) in grad.iter_mut().enumerate() {
+            *g = ders
+                .iter()
+                .enumerate()
+                .fold(T::zero(), |g, (arg_idx, der)| {
+                    g + chain(*der, arg_grads[arg_idx * stride + var_idx])
+                });
+        }
+        Ok(val)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+struct FlatArg<T: Copy> {
+    nodes: FlatNodeVec<T>,
+    ops: FlatOpVec<T>,
+    plan: EvalPlan,
+}
+
+/// Returns the number of bytes the nodes, the operators, and the plan of a flat
+/// expression occupy on the heap.
+fn flat_heap_bytes<T: Copy, const N: usize>(
+    nodes: &FlatNodeVec<T, N>,
+    ops: &FlatOpVec<T, N>,
+    plan: &EvalPlan<N>,
+) -> usize {
+    let of_nodes = nodes
+        .iter()
+        .map(|node| {
+            let of_call = match &node.kind {
+                FlatNodeKind::NaryCall(call) => args_heap_bytes(&call.args),
+                _ => 0,
+            };
+            of_call + spilled_bytes(&node.unary_repr_ids)
+        })
+        .sum::<usize>();
+    let of_ops = ops
+        .iter()
+        .map(|op| spilled_bytes(&op.unary_repr_ids))
+        .sum::<usize>();
+    spilled_bytes(nodes) + of_nodes + spilled_bytes(ops) + of_ops + spilled_bytes(plan)
+}
+
+fn args_heap_bytes<T: Copy>(args: &Vec<FlatArg<T>>) -> usize {
+    args.capacity() * mem::size_of::<FlatArg<T>>()
+        + args
+            .iter()
+            .map(|arg| flat_heap_bytes(&arg.nodes, &arg.ops, &arg.plan))
+            .sum::<usize>()
 }
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct FlatNode<T: Copy> {
     kind: FlatNodeKind<T>,
     unary_op: UnaryOp<T>,
+    /// See [`FlatOp`](FlatOp).
+    unary_repr_ids: ReprIds,
+    /// See [`FlatOp`](FlatOp).
+    span: Option<Span>,
 }
 
 impl<T: Copy> FlatNode<T> {
@@ -37,20 +202,135 @@ impl<T: Copy> FlatNode<T> {
         FlatNode {
             kind,
             unary_op: UnaryOp::new(),
+            unary_repr_ids: smallvec![],
+            span: None,
         }
     }
 }
 
-fn flatten_vecs<T: Copy + Debug>(
-    deep_expr: &DeepEx<T>,
-    prio_offset: i32,
-) -> (FlatNodeVec<T>, FlatOpVec<T>) {
-    let mut flat_nodes = FlatNodeVec::<T>::new();
-    let mut flat_ops = FlatOpVec::<T>::new();
+/// Returns the index of `repr` in `op_reprs` and adds it if necessary.
+fn repr_id<'a>(op_reprs: &mut Vec<&'a str>, repr: &'a str) -> usize {
+    match op_reprs.iter().position(|r| *r == repr) {
+        Some(idx) => idx,
+        None => {
+            op_reprs.push(repr);
+            op_reprs.len() - 1
+        }
+    }
+}
 
-    for (node_idx, node) in deep_expr.nodes().iter().enumerate() {
-        match node {
-            DeepNode::Num(num) => {
+/// Structures of sub-expressions that are replaced by [`FlatNodeKind::Tmp`](FlatNodeKind::Tmp)
+/// nodes together with their indices.
+type TmpSlots = [(String, usize)];
+
+fn tmp_slot<T: Copy + Debug>(deepex: &DeepEx<T>, tmp_slots: &TmpSlots) -> Option<usize> {
+    if tmp_slots.is_empty() {
+        return None;
+    }
+    let structure = deepex.structure_string(false);
+    tmp_slots
+        .iter()
+        .find(|(s, _)| *s == structure)
+        .map(|(_, slot)| *slot)
+}
+
+/// Collects the priorities of the binary operators of `deep_expr` including nested
+/// expressions but excluding arguments of n-ary operators, which are flattened separately.
+fn collect_prios<T: Copy + Debug>(deep_expr: &DeepEx<T>, prios: &mut Vec<i32>) {
+    let mut stack = vec![deep_expr];
+    while let Some(deep_expr) = stack.pop() {
+        prios.extend(deep_expr.bin_ops().ops.iter().map(|op| op.prio));
+        for node in deep_expr.nodes() {
+            if let DeepNode::Expr(e) = node {
+                stack.push(e);
+            }
+        }
+    }
+}
+
+/// Argument of a call of an n-ary operator that is flattened on its own after the
+/// expression the call is part of, see [`flatten_with_plan`](flatten_with_plan).
+struct PendingArg<'b, 'a, T: Copy + Debug> {
+    deepex: &'b DeepEx<'a, T>,
+    /// Index of the flat expression that contains the call, where 0 is the outermost one
+    /// and `i > 0` is the `i-1`-th pending argument.
+    parent: usize,
+    /// Index of the call in the nodes of the parent.
+    node_idx: usize,
+}
+
+/// Nested expression that is being flattened by [`flatten_vecs`](flatten_vecs).
+struct FlattenFrame<'b, 'a, T: Copy + Debug> {
+    deep_expr: &'b DeepEx<'a, T>,
+    /// Index of the next node to be flattened.
+    node_idx: usize,
+    prio_offset: i64,
+    nodes_start: usize,
+    ops_start: usize,
+}
+
+/// Appends the nodes and operators of `deep_expr` to `flat_nodes` and `flat_ops`. Nested
+/// expressions are flattened on an explicit stack and append to the same vectors such that
+/// deeply nested expressions cannot overflow the call stack. Calls of n-ary operators are
+/// appended without arguments, which are added to `pending` with the parent index
+/// `parent` instead.
+///
+/// The priority of a flat operator is the rank of its priority in the sorted and
+/// deduplicated `prios` plus `prio_offset`. Each nesting level increases the offset by the
+/// number of distinct priorities, i.e., one more than the highest rank, such that all
+/// operators of a nested expression are applied before the operators around it.
+#[allow(clippy::too_many_arguments)]
+fn flatten_vecs<'b, 'a, T: Copy + Debug, const N_NODES: usize>(
+    deep_expr: &'b DeepEx<'a, T>,
+    prios: &[i32],
+    prio_offset: i64,
+    op_reprs: &mut Vec<&'a str>,
+    tmp_slots: &TmpSlots,
+    flat_nodes: &mut FlatNodeVec<T, N_NODES>,
+    flat_ops: &mut FlatOpVec<T, N_NODES>,
+    pending: &mut Vec<PendingArg<'b, 'a, T>>,
+    parent: usize,
+) -> Result<(), ExError> {
+    let mut stack = vec![FlattenFrame {
+        deep_expr,
+        node_idx: 0,
+        prio_offset,
+        nodes_start: flat_nodes.len(),
+        ops_start: flat_ops.len(),
+    }];
+    while let Some(frame) = stack.last_mut() {
+        let deep_expr = frame.deep_expr;
+        let node_idx = frame.node_idx;
+        let prio_offset = frame.prio_offset;
+        if node_idx == deep_expr.nodes().len() {
+            let (nodes_start, ops_start) = (frame.nodes_start, frame.ops_start);
+            stack.pop();
+            apply_unary_of_deepex(
+                deep_expr,
+                op_reprs,
+                &mut flat_nodes[nodes_start..],
+                &mut flat_ops[ops_start..],
+            )?;
+            continue;
+        }
+        frame.node_idx += 1;
+        // the binary operator between two nodes follows the flattened left node
+        if node_idx > 0 {
+            let bin_op = deep_expr.bin_ops().ops[node_idx - 1];
+            let rank = prios.binary_search(&bin_op.prio).map_err(|_| {
+                ExError::Internal(format!("priority {} has not been collected", bin_op.prio))
+            })?;
+            flat_ops.push(FlatOp {
+                bin_op: bin_op.apply,
+                prio: rank as i64 + prio_offset,
+                unary_op: UnaryOp::new(),
+                unary_repr_ids: smallvec![],
+                bin_repr_id: repr_id(op_reprs, deep_expr.bin_ops().reprs[node_idx - 1]),
+                span: None,
+            });
+        }
+        match &deep_expr.nodes()[node_idx] {
+            DeepNode::Num(num, _) => {
                 let flat_node = FlatNode::from_kind(FlatNodeKind::Num(*num));
                 flat_nodes.push(flat_node);
             }
@@ -58,411 +338,6065 @@ fn flatten_vecs<T: Copy + Debug>(
                 let flat_node = FlatNode::from_kind(FlatNodeKind::Var(*idx));
                 flat_nodes.push(flat_node);
             }
-            DeepNode::Expr(e) => {
-                let (mut sub_nodes, mut sub_ops) = flatten_vecs(e, prio_offset + 100i32);
-                flat_nodes.append(&mut sub_nodes);
-                flat_ops.append(&mut sub_ops);
+            DeepNode::Expr(e) => match tmp_slot(e, tmp_slots) {
+                Some(slot) => flat_nodes.push(FlatNode::from_kind(FlatNodeKind::Tmp(slot))),
+                None => stack.push(FlattenFrame {
+                    deep_expr: e,
+                    node_idx: 0,
+                    prio_offset: prio_offset + prios.len() as i64,
+                    nodes_start: flat_nodes.len(),
+                    ops_start: flat_ops.len(),
+                }),
+            },
+            DeepNode::NaryCall { repr, op, args } => {
+                pending.extend(args.iter().map(|arg| PendingArg {
+                    deepex: arg,
+                    parent,
+                    node_idx: flat_nodes.len(),
+                }));
+                let call = FlatNaryCall {
+                    op: *op,
+                    repr_id: repr_id(op_reprs, repr),
+                    args: Vec::with_capacity(args.len()),
+                };
+                flat_nodes.push(FlatNode::from_kind(FlatNodeKind::NaryCall(call)));
             }
         };
-        if node_idx < deep_expr.bin_ops().ops.len() {
-            let prio_adapted_bin_op = BinOp {
-                apply: deep_expr.bin_ops().ops[node_idx].apply,
-                prio: deep_expr.bin_ops().ops[node_idx].prio + prio_offset,
-            };
-            flat_ops.push(FlatOp {
-                bin_op: prio_adapted_bin_op,
-                unary_op: UnaryOp::new(),
-            });
-        }
     }
+    Ok(())
+}
 
-    if deep_expr.unary_op().op.len() > 0 {
-        if !flat_ops.is_empty() {
-            // find the last binary operator with the lowest priority of this expression,
-            // since this will be executed as the last one
-            let low_prio_op = match flat_ops.iter_mut().rev().min_by_key(|op| op.bin_op.prio) {
-                None => panic!("cannot have more than one flat node but no binary ops"),
-                Some(x) => x,
-            };
-            low_prio_op
-                .unary_op
-                .append_front(&mut deep_expr.unary_op().op.clone());
-        } else {
-            flat_nodes[0]
-                .unary_op
-                .append_front(&mut deep_expr.unary_op().op.clone());
-        }
+/// Merges the unary operators of `deep_expr` into the operator that is applied last among
+/// its flattened operators `flat_ops` or into its only flattened node.
+fn apply_unary_of_deepex<'a, T: Copy + Debug>(
+    deep_expr: &DeepEx<'a, T>,
+    op_reprs: &mut Vec<&'a str>,
+    flat_nodes: &mut [FlatNode<T>],
+    flat_ops: &mut [FlatOp<T>],
+) -> Result<(), ExError> {
+    if deep_expr.unary_op().op.len() == 0 {
+        return Ok(());
     }
-    (flat_nodes, flat_ops)
+    let unary_repr_ids = deep_expr
+        .unary_op()
+        .reprs
+        .iter()
+        .map(|repr| repr_id(op_reprs, repr))
+        .collect::<ReprIds>();
+    // find the last binary operator with the lowest priority of this expression, since
+    // this will be executed as the last one
+    if let Some(low_prio_op) = flat_ops.iter_mut().rev().min_by_key(|op| op.prio) {
+        low_prio_op
+            .unary_op
+            .append_front(&mut deep_expr.unary_op().op.clone());
+        low_prio_op.unary_repr_ids.insert_many(0, unary_repr_ids);
+        low_prio_op.span = deep_expr.span().or(low_prio_op.span);
+    } else {
+        let first = flat_nodes.first_mut().ok_or_else(|| {
+            ExError::Internal("expression without nodes cannot be flattened".to_string())
+        })?;
+        first
+            .unary_op
+            .append_front(&mut deep_expr.unary_op().op.clone());
+        first.unary_repr_ids.insert_many(0, unary_repr_ids);
+        first.span = deep_expr.span().or(first.span);
+    }
+    Ok(())
 }
 
-fn prioritized_indices_flat<T: Copy>(ops: &[FlatOp<T>], nodes: &FlatNodeVec<T>) -> ExprIdxVec {
-    let prio_increase =
-        |bin_op_idx: usize| match (&nodes[bin_op_idx].kind, &nodes[bin_op_idx + 1].kind) {
-            (FlatNodeKind::Num(_), FlatNodeKind::Num(_)) => {
-                let prio_inc = 5;
-                &ops[bin_op_idx].bin_op.prio * 10 + prio_inc
-            }
-            _ => &ops[bin_op_idx].bin_op.prio * 10,
-        };
+/// Returns the indices of the binary operators in the order they are applied. Operators
+/// are sorted by their priority after flattening. Among operators with equal priorities,
+/// the [`prioritized_pairs`](prioritized_pairs) between two numbers come first if
+/// [`prioritize_constant_pairs`](Optimizations::prioritize_constant_pairs) is set, and
+/// the remaining ties are applied from left to right. The order is part of the guaranteed
+/// behavior, since it determines the rounding of floats, see
+/// [`execution_order`](FlatEx::execution_order).
+fn prioritized_indices_flat<T: Copy>(
+    ops: &[FlatOp<T>],
+    nodes: &[FlatNode<T>],
+    op_reprs: &[&str],
+    optimizations: Optimizations,
+) -> ExprIdxVec {
+    let is_num_pair = |idx: usize| {
+        optimizations.prioritize_constant_pairs
+            && matches!(
+                (&nodes[idx].kind, &nodes[idx + 1].kind),
+                (FlatNodeKind::Num(_), FlatNodeKind::Num(_))
+            )
+    };
+    let prioritized = prioritized_pairs(
+        ops.len(),
+        is_num_pair,
+        |idx| ops[idx].prio,
+        |idx| op_reprs[ops[idx].bin_repr_id],
+    );
     let mut indices: ExprIdxVec = (0..ops.len()).collect();
-    indices.sort_by(|i1, i2| {
-        let prio_i1 = prio_increase(*i1);
-        let prio_i2 = prio_increase(*i2);
-        prio_i2.partial_cmp(&prio_i1).unwrap()
-    });
+    // keyed by the index such that the order does not depend on the stability of the sort
+    indices.sort_unstable_by_key(|&idx| (Reverse((ops[idx].prio, prioritized[idx])), idx));
     indices
 }
 
-/// Flattens a deep expression
-/// The result does not contain any recursive structures and is faster to evaluate.
-pub fn flatten<T: Copy + Debug>(deepex: DeepEx<T>) -> FlatEx<T> {
-    let (nodes, ops) = flatten_vecs(&deepex, 0);
-    let indices = prioritized_indices_flat(&ops, &nodes);
-    let n_unique_vars = deepex.n_vars();
-    FlatEx {
-        nodes,
-        ops,
-        prio_indices: indices,
-        n_unique_vars,
-        deepex: Some(deepex),
+/// One step of the evaluation of a flat expression. The binary operator `op_idx` is applied
+/// to the current values at the positions `left` and `right`, and the result is stored at
+/// `left`.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+struct EvalStep {
+    op_idx: usize,
+    left: usize,
+    right: usize,
+}
+
+type EvalPlan<const N: usize = N_NODES_ON_STACK> = SmallVec<[EvalStep; N]>;
+
+/// Resolves the operand positions of the prioritized operators once such that evaluation
+/// does not need to search for them.
+fn make_eval_plan<const N_NODES: usize>(
+    prio_indices: &ExprIdxVec,
+    n_nodes: usize,
+) -> EvalPlan<N_NODES> {
+    let mut ignore: SmallVec<[bool; N_NODES_ON_STACK]> = smallvec![false; n_nodes];
+    prio_indices
+        .iter()
+        .map(|&op_idx| {
+            let mut left = op_idx;
+            while ignore[left] {
+                left -= 1;
+            }
+            let mut right = op_idx + 1;
+            while ignore[right] {
+                right += 1;
+            }
+            ignore[right] = true;
+            EvalStep {
+                op_idx,
+                left,
+                right,
+            }
+        })
+        .collect()
+}
+
+/// Checks that the flat operators fit between the flat nodes, which evaluation relies on
+/// without further checks.
+fn check_flat_shape<T: Copy>(nodes: &[FlatNode<T>], ops: &[FlatOp<T>]) -> Result<(), ExError> {
+    if nodes.is_empty() || nodes.len() != ops.len() + 1 {
+        Err(ExError::Internal(format!(
+            "flat expression has {} nodes and {} binary operators",
+            nodes.len(),
+            ops.len()
+        )))
+    } else {
+        Ok(())
     }
 }
 
-/// This is the core data type representing a flattened expression and the result of
-/// parsing a string. We use flattened expressions to make efficient evaluation possible.
-/// Simplified, a flat expression consists of a [`SmallVec`](SmallVec) of nodes and a
-/// [`SmallVec`](SmallVec) of operators that are applied to the nodes in an order following
-/// operator priorities.
-///
-/// You create an expression with the `parse` function or one of its
-/// variants, namely `parse_with_default_ops` and `parse_with_number_pattern`.
-///
-/// ```rust
-/// # use std::error::Error;
-/// # fn main() -> Result<(), Box<dyn Error>> {
-/// #
-/// use exmex::{parse_with_default_ops};
-///
-/// // create an expression by parsing a string
-/// let expr = parse_with_default_ops::<f32>("sin(1+y)*x")?;
-/// assert!((expr.eval(&[1.5, 2.0])? - (1.0 + 2.0 as f32).sin() * 1.5).abs() < 1e-6);
-/// #
-/// #     Ok(())
-/// # }
-/// ```
-/// The second argument `&[1.5, 2.0]` in the call of [`eval`](FlatEx::eval) specifies the
-/// variable values in the alphabetical order of the variable names.
-/// In this example, we want to evaluate the expression for the varibale values `x=2.0` and `y=1.5`.
-/// Variables in the string to-be-parsed are all substrings that are no numbers, no
-/// operators, and no parentheses.
-///
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
-pub struct FlatEx<'a, T: Copy + Debug> {
-    nodes: FlatNodeVec<T>,
-    ops: FlatOpVec<T>,
-    prio_indices: ExprIdxVec,
-    n_unique_vars: usize,
-    deepex: Option<DeepEx<'a, T>>,
+/// Checks that the evaluation plan applies each operator once to existing nodes.
+fn check_eval_plan(plan: &[EvalStep], n_nodes: usize) -> Result<(), ExError> {
+    let mut applied: SmallVec<[bool; N_NODES_ON_STACK]> = smallvec![false; n_nodes - 1];
+    for step in plan {
+        if step.left >= step.right
+            || step.right >= n_nodes
+            || applied.get(step.op_idx).copied() != Some(false)
+        {
+            return Err(ExError::Internal(format!(
+                "inconsistent evaluation step {:?} for {} nodes",
+                step, n_nodes
+            )));
+        }
+        applied[step.op_idx] = true;
+    }
+    if applied.contains(&false) {
+        Err(ExError::Internal(
+            "evaluation plan does not apply all operators".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
 }
 
-impl<'a, T: Copy + Debug> FlatEx<'a, T> {
-    /// Evaluates an expression with the given variable values and returns the computed
-    /// result.
-    ///
-    /// # Arguments
-    ///
-    /// * `vars` - Values of the variables of the expression; the n-th value corresponds to
-    ///            the n-th variable as given in the string that has been parsed to this expression.
-    ///            Thereby, only the first occurrence of the variable in the string is relevant.
-    ///
-    /// # Errors
-    ///
-    /// If the number of variables in the parsed expression are different from the length of
-    /// the variable slice, we return an [`ExParseError`](ExParseError).
-    ///
-    pub fn eval(&self, vars: &[T]) -> Result<T, ExParseError> {
-        if self.n_unique_vars != vars.len() {
-            return Err(ExParseError {
-                msg: format!(
-                    "parsed expression contains {} vars but passed slice has {} elements",
-                    self.n_unique_vars,
-                    vars.len()
-                ),
-            });
+/// Collects the leaves of `deepex` that become flat nodes and the keys of its binary
+/// operators from left to right, i.e., the nesting depth and the priority, together with
+/// their representations.
+#[cfg(debug_assertions)]
+fn collect_order_keys<'b, 'a, T: Copy + Debug>(
+    deepex: &'b DeepEx<'a, T>,
+    leaves: &mut Vec<&'b DeepNode<'a, T>>,
+    keys: &mut Vec<((usize, i32), &'a str)>,
+) {
+    // expressions with their nesting depth and the index of the next node
+    let mut stack = vec![(deepex, 0, 0)];
+    while let Some((deepex, depth, node_idx)) = stack.last_mut() {
+        let (deepex, depth) = (*deepex, *depth);
+        let Some(node) = deepex.nodes().get(*node_idx) else {
+            stack.pop();
+            continue;
+        };
+        // the binary operator between two nodes follows the left node
+        if let Some(prev) = node_idx.checked_sub(1) {
+            let op = deepex.bin_ops().ops[prev];
+            keys.push(((depth, op.prio), deepex.bin_ops().reprs[prev]));
         }
-        let mut numbers = self
-            .nodes
-            .iter()
-            .map(|node| {
-                node.unary_op.apply(match node.kind {
-                    FlatNodeKind::Num(n) => n,
-                    FlatNodeKind::Var(idx) => vars[idx],
-                })
-            })
-            .collect::<SmallVec<[T; 32]>>();
-        let mut ignore: SmallVec<[bool; N_NODES_ON_STACK]> = smallvec![false; self.nodes.len()];
-        for (i, &bin_op_idx) in self.prio_indices.iter().enumerate() {
-            let num_idx = self.prio_indices[i];
-            let mut shift_left = 0usize;
-            while ignore[num_idx - shift_left] {
-                shift_left += 1usize;
-            }
-            let mut shift_right = 1usize;
-            while ignore[num_idx + shift_right] {
-                shift_right += 1usize;
-            }
-            let num_1 = numbers[num_idx - shift_left];
-            let num_2 = numbers[num_idx + shift_right];
-            numbers[num_idx - shift_left] = {
-                let bop_res = (self.ops[bin_op_idx].bin_op.apply)(num_1, num_2);
-                self.ops[bin_op_idx].unary_op.apply(bop_res)
-            };
-            ignore[num_idx + shift_right] = true;
+        *node_idx += 1;
+        match node {
+            DeepNode::Expr(e) => stack.push((e, depth + 1, 0)),
+            _ => leaves.push(node),
         }
-        Ok(numbers[0])
     }
+}
 
-    /// This method computes a `FlatEx` instance that is a partial derivative of `self` with default operators
-    /// as shown in the following example.
-    ///
-    /// ```rust
-    /// # use std::error::Error;
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// #
-    /// use exmex::{parse_with_default_ops};
-    ///
-    /// let expr = parse_with_default_ops::<f64>("sin(1+y^2)*x")?;
-    /// let d_x = expr.clone().partial(0)?;
-    /// let d_y = expr.partial(1)?;
-    ///
-    /// assert!((d_x.eval(&[9e5, 2.0])? - (5.0 as f64).sin()).abs() < 1e-12);
-    /// //                   |    
-    /// //             This partial derivative d_x does depend on x. Still, it expects
-    /// //             the same number of parameters as the corresponding
-    /// //             antiderivative. Hence, you can pass any number for x.  
-    ///
-    /// assert!((d_y.eval(&[2.5, 2.0])? - 10.0 * (5.0 as f64).cos()).abs() < 1e-12);
-    /// #
-    /// #     Ok(())
-    /// # }
-    /// ```
-    /// # Arguments
-    ///
-    /// * `var_idx` - variable with respect to which the partial derivative is computed
-    ///
-    /// # Errors
-    ///
-    /// * If `self` has been `clear_deepex`ed we cannot compute the partial derivative and return an [`ExParseError`](ExParseError).
-    /// * If you use none-default operators this might not work as expected. It could return an [`ExParseError`](ExParseError) if
-    ///   an operator is not found or compute a wrong result if an operator is defined in an un-expected way.
-    ///
-    pub fn partial(self, var_idx: usize) -> Result<Self, ExParseError>
-    where
-        T: Float,
-    {
-        let ops = make_default_operators();
+/// Deep expression with the flat expression and the plan it has been flattened to.
+#[cfg(debug_assertions)]
+type UncheckedOrder<'b, 'a, T> = (
+    &'b DeepEx<'a, T>,
+    &'b [FlatNode<T>],
+    &'b [FlatOp<T>],
+    &'b [EvalStep],
+);
 
-        let d_i = partial_deepex(
-            var_idx,
-            self.deepex.ok_or(ExParseError {
-                msg: "need deep expression for derivation, not possible after calling `clear`"
-                    .to_string(),
-            })?,
-            &ops,
-        )?;
-        Ok(flatten(d_i))
+/// Recomputes the evaluation plan of the flat expression `nodes` and `ops` from the deep
+/// expression it has been flattened from, independently of the priorities of the flat
+/// operators, and compares it to `plan`. The operators are stably sorted by nesting depth,
+/// priority, and whether the operator is one of the
+/// [`prioritized_pairs`](prioritized_pairs), and the operands are found in the list
+/// of nodes that have not been consumed yet. Arguments of n-ary operators are checked
+/// as well. This is only compiled into debug builds.
+///
+/// # Errors
+///
+/// An [`ExError::Internal`](ExError::Internal) is returned if the plans differ or if the
+/// flat nodes do not correspond to the leaves of `deepex`.
+#[cfg(debug_assertions)]
+fn check_eval_order<T: Copy + Debug>(
+    deepex: &DeepEx<T>,
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+) -> Result<(), ExError> {
+    let mut unchecked = vec![(deepex, nodes, ops, plan)];
+    while let Some((deepex, nodes, ops, plan)) = unchecked.pop() {
+        check_eval_order_of_one(deepex, nodes, ops, plan, &mut unchecked)?;
     }
+    Ok(())
+}
 
-    /// Creates an expression string that corresponds to the `FlatEx` instance. This is
-    /// not necessarily the input string. More precisely,
-    /// * variable names are forgotten,
-    /// * variables are put into curly braces, and
-    /// * expressions will be put between parentheses, e.g.,
-    /// ```rust
-    /// # use std::error::Error;
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// #
-    /// use exmex::parse_with_default_ops;
-    /// let flatex = parse_with_default_ops::<f64>("--sin(z)")?;
-    /// assert_eq!(format!("{}", flatex), "-(-(sin({z})))");
-    /// #
-    /// #     Ok(())
-    /// # }
-    /// ```
-    ///
-    pub fn unparse(&self) -> Result<String, ExParseError> {
-        match &self.deepex {
-            Some(deepex) => Ok(deepex.unparse()),
-            None => Err(ExParseError {
-                msg: "unparse impossible, since deep expression optimized away".to_string(),
-            }),
+/// Checks the evaluation plan of one flat expression like [`check_eval_order`](check_eval_order)
+/// and adds the arguments of its n-ary operators to `unchecked`.
+#[cfg(debug_assertions)]
+fn check_eval_order_of_one<'b, 'a, T: Copy + Debug>(
+    deepex: &'b DeepEx<'a, T>,
+    nodes: &'b [FlatNode<T>],
+    ops: &'b [FlatOp<T>],
+    plan: &'b [EvalStep],
+    unchecked: &mut Vec<UncheckedOrder<'b, 'a, T>>,
+) -> Result<(), ExError> {
+    let mut leaves = vec![];
+    let mut keys = vec![];
+    collect_order_keys(deepex, &mut leaves, &mut keys);
+    if leaves.len() != nodes.len() || keys.len() != ops.len() {
+        return Err(ExError::Internal(format!(
+            "{} leaves and {} binary operators have been flattened to {} nodes and {} operators",
+            leaves.len(),
+            keys.len(),
+            nodes.len(),
+            ops.len()
+        )));
+    }
+    for (leaf, node) in leaves.iter().zip(nodes.iter()) {
+        if let (DeepNode::NaryCall { args, .. }, FlatNodeKind::NaryCall(call)) = (leaf, &node.kind)
+        {
+            for (arg, flat_arg) in args.iter().zip(call.args.iter()) {
+                unchecked.push((arg, &flat_arg.nodes, &flat_arg.ops, &flat_arg.plan));
+            }
         }
     }
-    /// Usually, a `FlatEx` instance keeps a nested, deep structure of the expression
-    /// that is not necessary for evaluation. This functions removes the deep expression
-    /// to reduce memory consumption. [`unparse`](FlatEx::unparse) and the
-    /// [`Display`](std::fmt::Display) implementation will stop working after calling this function.
-    pub fn clear_deepex(&mut self) {
-        self.deepex = None;
+    let is_num_pair = |op_idx: usize| {
+        deepex.optimizations().prioritize_constant_pairs
+            && matches!(
+                (leaves[op_idx], leaves[op_idx + 1]),
+                (DeepNode::Num(..), DeepNode::Num(..))
+            )
+    };
+    let prioritized = prioritized_pairs(
+        keys.len(),
+        is_num_pair,
+        |idx| keys[idx].0,
+        |idx| keys[idx].1,
+    );
+    let mut order = (0..keys.len()).collect::<Vec<_>>();
+    // stable such that operators with equal keys stay in the order of the string
+    order.sort_by_key(|&op_idx| core::cmp::Reverse((keys[op_idx].0, prioritized[op_idx])));
+    let mut unconsumed = (0..nodes.len()).collect::<Vec<_>>();
+    let expected = order
+        .iter()
+        .map(|&op_idx| {
+            let right_pos = unconsumed.partition_point(|&node_idx| node_idx <= op_idx);
+            let step = EvalStep {
+                op_idx,
+                left: unconsumed[right_pos - 1],
+                right: unconsumed[right_pos],
+            };
+            unconsumed.remove(right_pos);
+            step
+        })
+        .collect::<Vec<_>>();
+    if plan == expected.as_slice() {
+        Ok(())
+    } else {
+        Err(ExError::Internal(format!(
+            "evaluation plan {:?} differs from its recomputation {:?}",
+            plan, expected
+        )))
     }
 }
 
-/// The expression is displayed as a string created by [`unparse`](FlatEx::unparse).
-impl<'a, T: Copy + Debug> Display for FlatEx<'a, T> {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let unparsed = self.unparse();
-        match unparsed {
-            Err(e) => write!(f, "{}", e.msg),
-            Ok(s) => write!(f, "{}", s),
+/// Flattens the nodes and operators of a deep expression and resolves the order of their
+/// evaluation.
+///
+/// # Errors
+///
+/// An [`ExError::Internal`](ExError::Internal) is returned if the result is inconsistent,
+/// which is checked here once such that evaluation can index without checks.
+fn flatten_with_plan<'a, T: Copy + Debug, const N_NODES: usize>(
+    deepex: &DeepEx<'a, T>,
+    op_reprs: &mut Vec<&'a str>,
+    tmp_slots: &TmpSlots,
+) -> Result<
+    (
+        FlatNodeVec<T, N_NODES>,
+        FlatOpVec<T, N_NODES>,
+        EvalPlan<N_NODES>,
+    ),
+    ExError,
+> {
+    let mut pending = vec![];
+    let (mut nodes, ops, plan) =
+        flatten_one_with_plan(deepex, op_reprs, tmp_slots, &mut pending, 0)?;
+    // arguments of n-ary operators are flattened after the expressions that contain them
+    let mut args = vec![];
+    while let Some(arg) = pending.get(args.len()) {
+        let (nodes, ops, plan) = flatten_one_with_plan(
+            arg.deepex,
+            op_reprs,
+            tmp_slots,
+            &mut pending,
+            args.len() + 1,
+        )?;
+        args.push(FlatArg { nodes, ops, plan });
+    }
+    // arguments are moved into their calls in reverse order such that their own arguments
+    // have been moved into them before
+    while let Some(arg) = args.pop() {
+        let PendingArg {
+            parent, node_idx, ..
+        } = pending[args.len()];
+        let parent_nodes = match parent.checked_sub(1) {
+            Some(parent) => &mut args[parent].nodes[..],
+            None => &mut nodes[..],
+        };
+        match &mut parent_nodes[node_idx].kind {
+            FlatNodeKind::NaryCall(call) => call.args.insert(0, arg),
+            _ => {
+                return Err(ExError::Internal(
+                    "argument of an n-ary operator without call".to_string(),
+                ))
+            }
         }
     }
+    Ok((nodes, ops, plan))
 }
 
-#[cfg(test)]
-use crate::{expression::deep::UnaryOpWithReprs, operators::VecOfUnaryFuncs};
-
-#[test]
-fn test_operate_unary() {
-    let lstr = "x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)";
-    let deepex = DeepEx::<f64>::from_str(lstr).unwrap();
-    let mut funcs = VecOfUnaryFuncs::new();
-    funcs.push(|x: f64| x * 1.23456);
-    let deepex = deepex.operate_unary(UnaryOpWithReprs {
-        reprs: vec!["eagle"],
-        op: UnaryOp::from_vec(funcs),
-    });
-    let flatex = flatten(deepex);
-    assert_float_eq_f64(
-        flatex.eval(&[1.0, 1.75, 2.25]).unwrap(),
-        -0.23148000000000002 * 8.0,
-    );
+/// Flattens a deep expression like [`flatten_with_plan`](flatten_with_plan) but without
+/// the arguments of n-ary operators, which are added to `pending`, see
+/// [`flatten_vecs`](flatten_vecs).
+fn flatten_one_with_plan<'b, 'a, T: Copy + Debug, const N_NODES: usize>(
+    deepex: &'b DeepEx<'a, T>,
+    op_reprs: &mut Vec<&'a str>,
+    tmp_slots: &TmpSlots,
+    pending: &mut Vec<PendingArg<'b, 'a, T>>,
+    parent: usize,
+) -> Result<
+    (
+        FlatNodeVec<T, N_NODES>,
+        FlatOpVec<T, N_NODES>,
+        EvalPlan<N_NODES>,
+    ),
+    ExError,
+> {
+    let mut nodes = FlatNodeVec::new();
+    let mut ops = FlatOpVec::new();
+    let mut prios = Vec::new();
+    collect_prios(deepex, &mut prios);
+    prios.sort_unstable();
+    prios.dedup();
+    flatten_vecs(
+        deepex, &prios, 0, op_reprs, tmp_slots, &mut nodes, &mut ops, pending, parent,
+    )?;
+    check_flat_shape(&nodes, &ops)?;
+    let indices = prioritized_indices_flat(&ops, &nodes, op_reprs, deepex.optimizations());
+    let plan = make_eval_plan(&indices, nodes.len());
+    check_eval_plan(&plan, nodes.len())?;
+    Ok((nodes, ops, plan))
 }
 
-#[cfg(test)]
-use crate::{parse_with_default_ops, util::assert_float_eq_f64};
+/// Flattens a [`DeepEx`](DeepEx) into a [`FlatEx`](FlatEx) that keeps the deep expression.
+/// The result does not contain any recursive structures and is faster to evaluate.
+/// Only the arguments of n-ary operators are flattened separately.
+///
+/// # Errors
+///
+/// An [`ExError::Internal`](ExError::Internal) is returned if the flattened expression is
+/// inconsistent.
+pub fn flatten<T: Copy + Debug>(deepex: DeepEx<T>) -> Result<FlatEx<T>, ExError> {
+    FlatEx::from_deepex(deepex)
+}
 
-#[test]
-fn test_flat_clear() {
-    let mut flatex = parse_with_default_ops::<f64>("x*(2*(2*(2*4*8)))").unwrap();
-    assert!(flatex.deepex.is_some());
-    flatex.clear_deepex();
-    assert!(flatex.deepex.is_none());
-    assert_float_eq_f64(flatex.eval(&[1.0]).unwrap(), 2.0 * 2.0 * 2.0 * 4.0 * 8.0);
-    assert_eq!(flatex.nodes.len(), 2);
+/// Returns whether one of the flat nodes, including the arguments of n-ary operators, is a
+/// variable.
+fn contains_var_nodes<T: Copy>(nodes: &[FlatNode<T>]) -> bool {
+    nodes.iter().any(|node| match &node.kind {
+        FlatNodeKind::Num(_) | FlatNodeKind::Tmp(_) => false,
+        FlatNodeKind::Var(_) => true,
+        FlatNodeKind::NaryCall(call) => call.args.iter().any(|arg| contains_var_nodes(&arg.nodes)),
+    })
 }
-#[test]
-fn test_flat_compile() {
-    let flatex = parse_with_default_ops::<f64>("1*sin(2-0.1)").unwrap();
-    assert_float_eq_f64(flatex.eval(&[]).unwrap(), 1.9f64.sin());
-    assert_eq!(flatex.nodes.len(), 1);
 
-    let flatex = parse_with_default_ops::<f64>("x*(2*(2*(2*4*8)))").unwrap();
-    assert_float_eq_f64(flatex.eval(&[1.0]).unwrap(), 2.0 * 2.0 * 2.0 * 4.0 * 8.0);
-    assert_eq!(flatex.nodes.len(), 2);
+/// Returns the values of the flat nodes including their unary operators.
+fn node_values<'b, T: Copy>(
+    nodes: &'b [FlatNode<T>],
+    vars: &'b [T],
+) -> impl Iterator<Item = T> + 'b {
+    nodes.iter().map(move |node| {
+        node.unary_op.apply(match &node.kind {
+            FlatNodeKind::Num(n) => *n,
+            FlatNodeKind::Var(idx) | FlatNodeKind::Tmp(idx) => vars[*idx],
+            FlatNodeKind::NaryCall(call) => call.eval(vars),
+        })
+    })
+}
 
-    let flatex = parse_with_default_ops::<f64>("1*sin(2-0.1) + x").unwrap();
-    assert_float_eq_f64(flatex.eval(&[0.0]).unwrap(), 1.9f64.sin());
-    assert_eq!(flatex.nodes.len(), 2);
-    match flatex.nodes[0].kind {
-        FlatNodeKind::Num(n) => assert_float_eq_f64(n, 1.9f64.sin()),
-        _ => assert!(false),
+/// Applies the binary operators in the order of the plan in place to the values of the
+/// nodes and returns the result.
+fn apply_plan<T: Copy>(numbers: &mut [T], ops: &[FlatOp<T>], plan: &[EvalStep]) -> T {
+    for step in plan {
+        let op = &ops[step.op_idx];
+        let bop_res = (op.bin_op)(numbers[step.left], numbers[step.right]);
+        numbers[step.left] = op.unary_op.apply(bop_res);
     }
-    match flatex.nodes[1].kind {
-        FlatNodeKind::Var(idx) => assert_eq!(idx, 0),
-        _ => assert!(false),
+    numbers[0]
+}
+
+/// Evaluates the flat nodes and operators of a [`FlatEx`](FlatEx) or an
+/// [`OwnedFlatEx`](OwnedFlatEx).
+fn eval_flat<T: Copy>(
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+    vars: &[T],
+) -> T {
+    let mut numbers = node_values(nodes, vars).collect::<SmallVec<[T; N_NODES_ON_STACK]>>();
+    apply_plan(&mut numbers, ops, plan)
+}
+
+/// Returns the values of the first `n_vars` variables followed by the values of the
+/// common sub-expressions `tmps`, see [`optimize_cse`](FlatEx::optimize_cse).
+fn tmp_values<T: Copy>(
+    tmps: &[FlatArg<T>],
+    vars: &[T],
+    n_vars: usize,
+) -> SmallVec<[T; N_VARS_ON_STACK]> {
+    let mut values = SmallVec::<[T; N_VARS_ON_STACK]>::from_slice(&vars[..n_vars]);
+    for tmp in tmps {
+        let value = eval_flat(&tmp.nodes, &tmp.ops, &tmp.plan, &values);
+        values.push(value);
     }
+    values
+}
 
-    let flatex = parse_with_default_ops::<f64>("y + 1 - cos(1/(1*sin(2-0.1))-2) + 2 + x").unwrap();
-    assert_eq!(flatex.nodes.len(), 3);
-    match flatex.nodes[0].kind {
-        FlatNodeKind::Var(idx) => assert_eq!(idx, 1),
-        _ => assert!(false),
+/// Evaluates like [`eval_flat`](eval_flat) but computes the common sub-expressions `tmps`
+/// first.
+fn eval_flat_with_tmps<T: Copy>(
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+    tmps: &[FlatArg<T>],
+    vars: &[T],
+    n_vars: usize,
+) -> T {
+    if tmps.is_empty() {
+        eval_flat(nodes, ops, plan, vars)
+    } else {
+        eval_flat(nodes, ops, plan, &tmp_values(tmps, vars, n_vars))
+    }
+}
+
+/// Returns the sub-expressions of `deepex` that occur more than once such that they are
+/// evaluated once each, see [`optimize_cse`](FlatEx::optimize_cse). Sub-expressions of
+/// larger ones come later.
+fn common_subexprs<'a, 'b, T: Copy + Debug>(
+    deepex: &'b DeepEx<'a, T>,
+) -> Vec<(String, &'b DeepEx<'a, T>)> {
+    type Counts<'a, 'b, T> = Vec<(String, usize, &'b DeepEx<'a, T>)>;
+    // occurrences within the selected sub-expressions are only counted once
+    fn count<'a, 'b, T: Copy + Debug>(
+        deepex: &'b DeepEx<'a, T>,
+        selected: &[(String, &'b DeepEx<'a, T>)],
+        counts: &mut Counts<'a, 'b, T>,
+    ) {
+        for node in deepex.nodes() {
+            match node {
+                DeepNode::Expr(e) => {
+                    let structure = e.structure_string(false);
+                    if selected.iter().any(|(s, _)| *s == structure) {
+                        continue;
+                    }
+                    count(e, selected, counts);
+                    let is_trivial = e.nodes().len() == 1
+                        && e.unary_op().op.len() == 0
+                        && matches!(e.nodes()[0], DeepNode::Num(..) | DeepNode::Var(_));
+                    if !is_trivial {
+                        match counts.iter_mut().find(|(s, _, _)| *s == structure) {
+                            Some((_, n, _)) => *n += 1,
+                            None => counts.push((structure, 1, e)),
+                        }
+                    }
+                }
+                DeepNode::NaryCall { args, .. } => {
+                    for arg in args {
+                        count(arg, selected, counts);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    let mut selected = vec![];
+    loop {
+        let mut counts = vec![];
+        count(deepex, &selected, &mut counts);
+        for (_, sub) in &selected {
+            count(sub, &selected, &mut counts);
+        }
+        // the largest repeated sub-expression saves the most
+        match counts
+            .into_iter()
+            .filter(|(_, n, _)| *n > 1)
+            .max_by_key(|(structure, _, _)| structure.len())
+        {
+            Some((structure, _, sub)) => selected.push((structure, sub)),
+            None => break,
+        }
+    }
+    selected
+}
+
+/// Numbers of the operations of one evaluation, see
+/// [`cost_estimate`](FlatEx::cost_estimate). Operators that have been evaluated during
+/// parsing, e.g., in constant sub-expressions, do not count.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CostEstimate {
+    /// Number of applied binary operators.
+    pub bin_ops: usize,
+    /// Number of applied unary operators summed over all chains.
+    pub unary_ops: usize,
+    /// Number of calls of n-ary operators.
+    pub nary_calls: usize,
+    /// Number of reads of variable values, i.e., a variable that occurs twice is read
+    /// twice.
+    pub var_reads: usize,
+}
+
+/// Storage for the intermediate values of an evaluation that can be reused across calls
+/// of [`eval_with_buffer`](FlatEx::eval_with_buffer). Expressions with many nodes
+/// otherwise allocate on each evaluation.
+#[derive(Clone, Debug)]
+pub struct EvalBuffer<T> {
+    numbers: Vec<T>,
+}
+
+impl<T> EvalBuffer<T> {
+    pub fn new() -> Self {
+        EvalBuffer { numbers: vec![] }
+    }
+}
+
+impl<T> Default for EvalBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns `result` if it is finite and an error naming the operator `repr_id` and its
+/// `operands` otherwise.
+#[cfg(feature = "std")]
+fn check_finite<T: Float + Debug>(
+    result: T,
+    repr_id: usize,
+    operands: &[T],
+    op_reprs: &[&str],
+) -> Result<T, ExError> {
+    if result.is_finite() {
+        Ok(result)
+    } else {
+        Err(ExError::NonFiniteResult {
+            op: op_reprs[repr_id].to_string(),
+            operands: operands.iter().map(|x| format!("{:?}", x)).collect(),
+            result: format!("{:?}", result),
+        })
+    }
+}
+
+/// Fallible operations by the indices of the operator representations of a flat expression,
+/// see [`FallibleOp`](FallibleOp). Empty if there are none such that evaluation can take
+/// the infallible path.
+type FallibleOps<T> = Vec<Option<FallibleOp<T>>>;
+
+fn fallible_by_repr_id<T: Copy>(
+    op_reprs: &[&str],
+    fallible_op: impl Fn(&str) -> Option<FallibleOp<T>>,
+) -> FallibleOps<T> {
+    let fallible = op_reprs
+        .iter()
+        .map(|repr| fallible_op(repr))
+        .collect::<FallibleOps<T>>();
+    if fallible.iter().all(Option::is_none) {
+        vec![]
+    } else {
+        fallible
+    }
+}
+
+fn find_op<'b, 'c, T: Copy>(
+    ops: &'b [Operator<'c, T>],
+    repr: &str,
+) -> Result<&'b Operator<'c, T>, ExError> {
+    ops.iter()
+        .find(|op| op.repr == repr)
+        .ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))
+}
+
+fn rebind_unary<T: Copy>(
+    repr_ids: &[usize],
+    ops: &[Operator<T>],
+    op_reprs: &[&str],
+) -> Result<UnaryOp<T>, ExError> {
+    repr_ids
+        .iter()
+        .map(|id| {
+            let repr = op_reprs[*id];
+            find_op(ops, repr)?
+                .unary_op
+                .ok_or_else(|| ExError::OperatorNotUnary(repr.to_string()))
+        })
+        .collect::<Result<_, _>>()
+        .map(UnaryOp::from_vec)
+}
+
+/// Replaces the operations of `nodes` and `flat_ops` by the ones of the operators in `ops`
+/// with the same representations, see [`with_ops`](FlatEx::with_ops).
+fn rebind_ops<T: Copy>(
+    nodes: &mut [FlatNode<T>],
+    flat_ops: &mut [FlatOp<T>],
+    ops: &[Operator<T>],
+    op_reprs: &[&str],
+) -> Result<(), ExError> {
+    for node in nodes.iter_mut() {
+        node.unary_op = rebind_unary(&node.unary_repr_ids, ops, op_reprs)?;
+        if let FlatNodeKind::NaryCall(call) = &mut node.kind {
+            let repr = op_reprs[call.repr_id];
+            let arity = call.op.arity;
+            call.op = find_op(ops, repr)?
+                .nary_op
+                .filter(|op| op.arity == arity)
+                .ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))?;
+            for arg in call.args.iter_mut() {
+                rebind_ops(&mut arg.nodes, &mut arg.ops, ops, op_reprs)?;
+            }
+        }
+    }
+    for op in flat_ops.iter_mut() {
+        let repr = op_reprs[op.bin_repr_id];
+        op.bin_op = find_op(ops, repr)?
+            .bin_op
+            .ok_or_else(|| ExError::OperatorNotBinary(repr.to_string()))?
+            .apply;
+        op.unary_op = rebind_unary(&op.unary_repr_ids, ops, op_reprs)?;
+    }
+    Ok(())
+}
+
+/// Operations and checks that are applied by [`eval_flat_checked`](eval_flat_checked).
+struct Checks<'b, T: Copy, C> {
+    fallible: &'b [Option<FallibleOp<T>>],
+    op_reprs: &'b [&'b str],
+    /// Checks the result of an operator given its representation index and its operands.
+    check: C,
+}
+
+impl<T, C> Checks<'_, T, C>
+where
+    T: Copy + Debug,
+    C: Fn(T, usize, &[T]) -> Result<T, ExError>,
+{
+    fn failed(&self, repr_id: usize, operands: &[T], reason: String) -> ExError {
+        ExError::OperatorFailed {
+            op: self.op_reprs[repr_id].to_string(),
+            operands: operands.iter().map(|x| format!("{:?}", x)).collect(),
+            reason,
+        }
+    }
+
+    fn apply_bin(&self, op: &FlatOp<T>, a: T, b: T) -> Result<T, ExError> {
+        let id = op.bin_repr_id;
+        let result = match self.fallible.get(id).and_then(|f| f.and_then(|f| f.bin_op)) {
+            Some(apply) => apply(a, b).map_err(|reason| self.failed(id, &[a, b], reason))?,
+            None => (op.bin_op)(a, b),
+        };
+        (self.check)(result, id, &[a, b])
+    }
+
+    fn apply_unary(&self, unary_op: &UnaryOp<T>, repr_ids: &[usize], x: T) -> Result<T, ExError> {
+        // rev, since the last uop is applied first by convention
+        unary_op
+            .funcs()
+            .iter()
+            .zip(repr_ids.iter())
+            .rev()
+            .try_fold(x, |x, (f, &id)| {
+                let result = match self
+                    .fallible
+                    .get(id)
+                    .and_then(|f| f.and_then(|f| f.unary_op))
+                {
+                    Some(apply) => apply(x).map_err(|reason| self.failed(id, &[x], reason))?,
+                    None => f(x),
+                };
+                (self.check)(result, id, &[x])
+            })
+    }
+}
+
+/// Evaluates like [`eval_flat`](eval_flat) but applies fallible operations and checks the
+/// result of each operator.
+fn eval_flat_checked<T, C>(
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+    vars: &[T],
+    checks: &Checks<T, C>,
+) -> Result<T, ExError>
+where
+    T: Copy + Debug,
+    C: Fn(T, usize, &[T]) -> Result<T, ExError>,
+{
+    let mut numbers = nodes
+        .iter()
+        .map(|node| {
+            let x = match &node.kind {
+                FlatNodeKind::Num(n) => *n,
+                FlatNodeKind::Var(idx) | FlatNodeKind::Tmp(idx) => vars[*idx],
+                FlatNodeKind::NaryCall(call) => call.eval_checked(vars, checks)?,
+            };
+            checks.apply_unary(&node.unary_op, &node.unary_repr_ids, x)
+        })
+        .collect::<Result<SmallVec<[T; N_NODES_ON_STACK]>, ExError>>()?;
+    for step in plan {
+        let op = &ops[step.op_idx];
+        let bop_res = checks.apply_bin(op, numbers[step.left], numbers[step.right])?;
+        numbers[step.left] = checks.apply_unary(&op.unary_op, &op.unary_repr_ids, bop_res)?;
+    }
+    Ok(numbers[0])
+}
+
+/// Contribution of an operand to the gradient of a result. Operands that do not depend on
+/// a variable do not contribute even if the local derivative is not finite, e.g., the
+/// logarithm of a negative base for a constant exponent.
+#[cfg(feature = "std")]
+fn chain<T: Float>(der: T, grad: T) -> T {
+    if grad == T::zero() {
+        T::zero()
+    } else {
+        der * grad
+    }
+}
+
+/// Applies the unary operators to `x` starting with the last one and updates its gradient
+/// `grad` with their local derivatives `dual_ops`, which are indexed by representation.
+#[cfg(feature = "std")]
+fn apply_unary_dual<T: Float>(
+    unary_op: &UnaryOp<T>,
+    repr_ids: &[usize],
+    dual_ops: &[DualOp<T>],
+    x: T,
+    grad: &mut [T],
+) -> Result<T, ExError> {
+    unary_op
+        .funcs()
+        .iter()
+        .zip(repr_ids.iter())
+        .rev()
+        .try_fold(x, |x, (f, &id)| {
+            let val = f(x);
+            let der = dual_ops[id]
+                .unary_op
+                .ok_or_else(|| ExError::DerivativeNotFound(dual_ops[id].repr.to_string()))?(
+                x, val
+            );
+            for g in grad.iter_mut() {
+                *g = chain(der, *g);
+            }
+            Ok(val)
+        })
+}
+
+/// Evaluates like [`eval_flat`](eval_flat) but propagates the gradients of the `vars`
+/// through the operators and writes the gradient of the result to `grad`. The gradients
+/// `var_grads` of the variables are stored one after another, each with the length of
+/// `grad`.
+#[cfg(feature = "std")]
+fn eval_flat_dual<T: Float>(
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+    vars: &[T],
+    var_grads: &[T],
+    dual_ops: &[DualOp<T>],
+    grad: &mut [T],
+) -> Result<T, ExError> {
+    let n_vars = grad.len();
+    // at least one entry per node such that expressions without variables have chunks
+    let stride = n_vars.max(1);
+    let mut grads: SmallVec<[T; 4 * N_NODES_ON_STACK]> = smallvec![T::zero(); nodes.len() * stride];
+    let mut numbers = SmallVec::<[T; N_NODES_ON_STACK]>::with_capacity(nodes.len());
+    for (node, node_grad) in nodes.iter().zip(grads.chunks_exact_mut(stride)) {
+        let x = match &node.kind {
+            FlatNodeKind::Num(n) => *n,
+            FlatNodeKind::Var(idx) | FlatNodeKind::Tmp(idx) => {
+                node_grad[..n_vars].copy_from_slice(&var_grads[idx * n_vars..(idx + 1) * n_vars]);
+                vars[*idx]
+            }
+            FlatNodeKind::NaryCall(call) => {
+                call.eval_dual(vars, var_grads, dual_ops, &mut node_grad[..n_vars])?
+            }
+        };
+        numbers.push(apply_unary_dual(
+            &node.unary_op,
+            &node.unary_repr_ids,
+            dual_ops,
+            x,
+            &mut node_grad[..n_vars],
+        )?);
+    }
+    for step in plan {
+        let op = &ops[step.op_idx];
+        let dual_op = &dual_ops[op.bin_repr_id];
+        let (a, b) = (numbers[step.left], numbers[step.right]);
+        let val = (op.bin_op)(a, b);
+        let (der_left, der_right) = dual_op
+            .bin_op
+            .ok_or_else(|| ExError::DerivativeNotFound(dual_op.repr.to_string()))?(
+            a, b, val
+        );
+        let (head, tail) = grads.split_at_mut(step.right * stride);
+        let left = &mut head[step.left * stride..step.left * stride + n_vars];
+        for (g_left, g_right) in left.iter_mut().zip(tail[..n_vars].iter()) {
+            *g_left = chain(der_left, *g_left) + chain(der_right, *g_right);
+        }
+        numbers[step.left] =
+            apply_unary_dual(&op.unary_op, &op.unary_repr_ids, dual_ops, val, left)?;
+    }
+    grad.copy_from_slice(&grads[..n_vars]);
+    Ok(numbers[0])
+}
+
+/// Number of points [`eval_vectorized`](FlatEx::eval_vectorized) evaluates at once.
+const VECTORIZED_CHUNK_LEN: usize = 256;
+
+/// Evaluates the flat nodes and operators for chunks of points. For each chunk, the values
+/// of a node are stored contiguously such that each step of the plan is applied in a tight
+/// loop.
+fn eval_flat_vectorized<T: Copy>(
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+    vars: &[&[T]],
+    out: &mut [T],
+) {
+    let mut numbers = Vec::with_capacity(nodes.len() * VECTORIZED_CHUNK_LEN.min(out.len()));
+    for (chunk_idx, out_chunk) in out.chunks_mut(VECTORIZED_CHUNK_LEN).enumerate() {
+        let start = chunk_idx * VECTORIZED_CHUNK_LEN;
+        let len = out_chunk.len();
+        numbers.clear();
+        for node in nodes {
+            match &node.kind {
+                FlatNodeKind::Num(n) => {
+                    numbers.extend(iter::repeat_n(node.unary_op.apply(*n), len));
+                }
+                FlatNodeKind::Var(idx) | FlatNodeKind::Tmp(idx) => numbers.extend(
+                    vars[*idx][start..start + len]
+                        .iter()
+                        .map(|v| node.unary_op.apply(*v)),
+                ),
+                // the arguments of n-ary operators are evaluated point by point
+                FlatNodeKind::NaryCall(call) => numbers.extend((start..start + len).map(|point| {
+                    let point_vars = vars
+                        .iter()
+                        .map(|var| var[point])
+                        .collect::<SmallVec<[T; N_VARS_ON_STACK]>>();
+                    node.unary_op.apply(call.eval(&point_vars))
+                })),
+            }
+        }
+        for step in plan {
+            let op = &ops[step.op_idx];
+            // the left operand is always stored before the right one
+            let (before_right, from_right) = numbers.split_at_mut(step.right * len);
+            let left = &mut before_right[step.left * len..(step.left + 1) * len];
+            for (l, r) in left.iter_mut().zip(from_right[..len].iter()) {
+                *l = op.unary_op.apply((op.bin_op)(*l, *r));
+            }
+        }
+        out_chunk.copy_from_slice(&numbers[..len]);
+    }
+}
+
+/// Computes the Jacobian matrix of a vector-valued function whose components are the passed
+/// expressions. The i-th row contains the [`grad`](FlatEx::grad)ient of the i-th expression
+/// with respect to the union of the variables of all expressions. Hence, all entries expect
+/// the same variable values in alphabetical order of the variable names. If all expressions
+/// have been parsed with [`VarOrder::Occurrence`](VarOrder::Occurrence), the variables
+/// are ordered as in the passed expressions one after another instead.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, jacobian};
+/// let f = parse_with_default_ops::<f64>("x*y")?;
+/// let g = parse_with_default_ops::<f64>("2*z")?;
+/// let jac = jacobian(&[f, g])?;
+/// assert_eq!(jac[1][0].var_names(), ["x", "y", "z"]);
+/// assert!((jac[0][1].eval(&[3.0, 2.0, 1.0])? - 3.0).abs() < 1e-12);
+/// assert!((jac[1][2].eval(&[3.0, 2.0, 1.0])? - 2.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// See [`partial`](FlatEx::partial).
+///
+#[cfg(feature = "std")]
+pub fn jacobian<'a, T: Float + Debug>(
+    exprs: &[FlatEx<'a, T>],
+) -> Result<Vec<Vec<FlatEx<'a, T>>>, ExError> {
+    let mut all_var_names = SmallVec::<[&str; N_VARS_ON_STACK]>::new();
+    for expr in exprs {
+        for name in expr.var_names() {
+            if !all_var_names.contains(name) {
+                all_var_names.push(*name);
+            }
+        }
+    }
+    if exprs
+        .iter()
+        .any(|expr| expr.var_order() == VarOrder::Alphabetical)
+    {
+        all_var_names.sort_unstable();
+    }
+    exprs
+        .iter()
+        .map(|expr| {
+            let mut deepex = unpack_deepex(expr)?.clone();
+            reset_vars(&mut deepex, all_var_names.clone());
+            flatten(deepex)?.grad()
+        })
+        .collect()
+}
+
+/// Compares the [`partial`](FlatEx::partial) derivative of `expr` with respect to the
+/// variable with index `var_idx` against central finite differences at each of the
+/// `points`, e.g., to validate the derivatives of custom operators. The deviation at a
+/// point is the absolute difference relative to the magnitude of the finite difference if
+/// the latter exceeds one. Points where the finite difference is not finite are skipped.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, check_partial};
+/// let expr = parse_with_default_ops::<f64>("sin(x)*y^2")?;
+/// let points: [&[f64]; 2] = [&[0.3, 1.5], &[-2.0, 0.7]];
+/// check_partial(&expr, 0, &points, 1e-6)?;
+/// check_partial(&expr, 1, &points, 1e-6)?;
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// If a deviation exceeds `eps`, we return an
+/// [`ExError::PartialMismatch`](ExError::PartialMismatch) with the maximal deviation and
+/// the first point where `eps` is exceeded. Errors of computing the derivative or of the
+/// evaluation, e.g., due to the wrong number of variables of a point, are passed on.
+///
+#[cfg(feature = "std")]
+pub fn check_partial<T: Float + Debug>(
+    expr: &FlatEx<T>,
+    var_idx: usize,
+    points: &[&[T]],
+    eps: T,
+) -> Result<(), ExError> {
+    let der = expr.clone().partial(var_idx)?;
+    let two = T::one() + T::one();
+    let mut max_deviation = T::zero();
+    let mut first_failure = None;
+    for point in points {
+        expr.check_vars(point.len())?;
+        let x = point[var_idx];
+        // balances the truncation error of the difference quotient and the rounding error
+        let h = T::epsilon().cbrt() * x.abs().max(T::one());
+        let mut shifted = SmallVec::<[T; N_VARS_ON_STACK]>::from_slice(point);
+        shifted[var_idx] = x + h;
+        let upper = expr.eval(&shifted)?;
+        shifted[var_idx] = x - h;
+        let lower = expr.eval(&shifted)?;
+        let numeric = (upper - lower) / (two * h);
+        if !numeric.is_finite() {
+            continue;
+        }
+        let symbolic = der.eval(point)?;
+        let deviation = (symbolic - numeric).abs() / numeric.abs().max(T::one());
+        // a symbolic derivative that is not finite deviates infinitely
+        let deviation = if deviation.is_finite() {
+            deviation
+        } else {
+            T::infinity()
+        };
+        max_deviation = max_deviation.max(deviation);
+        if deviation > eps && first_failure.is_none() {
+            first_failure = Some((point, symbolic, numeric));
+        }
+    }
+    match first_failure {
+        None => Ok(()),
+        Some((point, symbolic, numeric)) => Err(ExError::PartialMismatch {
+            var_idx,
+            point: point.iter().map(|x| format!("{:?}", x)).collect(),
+            symbolic: format!("{:?}", symbolic),
+            numeric: format!("{:?}", numeric),
+            max_deviation: format!("{:?}", max_deviation),
+        }),
+    }
+}
+
+/// Checks the flattened expression against the limits, see [`ParseLimits`](ParseLimits).
+pub fn check_limits<T: Copy + Debug, const N_NODES: usize>(
+    flatex: &FlatEx<T, N_NODES>,
+    text: &str,
+    limits: &ParseLimits,
+) -> Result<(), ExError> {
+    if let Some(max) = limits.max_unary_chain {
+        let longest = flatex
+            .nodes
+            .iter()
+            .map(|node| (node.unary_op.len(), node.span))
+            .chain(flatex.ops.iter().map(|op| (op.unary_op.len(), op.span)))
+            .filter(|(len, _)| *len > max)
+            .max_by_key(|(len, _)| *len);
+        if let Some((len, span)) = longest {
+            let span = span.unwrap_or(Span {
+                start: 0,
+                end: text.len(),
+            });
+            return Err(ExError::UnaryChainTooLong {
+                position: span.start,
+                end: span.end,
+                len,
+                max,
+                context: parser::context(text, span),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the deep expression of a flat expression unless it has been removed with
+/// [`clear_deepex`](FlatEx::clear_deepex).
+pub fn unpack_deepex<'a, 'b, T: Copy + Debug, const N_NODES: usize>(
+    flatex: &'b FlatEx<'a, T, N_NODES>,
+) -> Result<&'b DeepEx<'a, T>, ExError> {
+    flatex.deepex.as_ref().ok_or(ExError::DeepExMissing)
+}
+
+/// Node of the evaluation tree of a flat expression, see
+/// [`to_debug_tree`](FlatEx::to_debug_tree).
+#[derive(Default)]
+struct DebugNode {
+    label: String,
+    children: Vec<DebugNode>,
+}
+
+/// Names that the flat nodes and operators refer to by index.
+struct DebugNames<'b, 'a> {
+    var_names: &'b [&'a str],
+    op_reprs: &'b [&'a str],
+    n_vars: usize,
+}
+
+impl DebugNames<'_, '_> {
+    fn unary(&self, repr_ids: &[usize]) -> String {
+        if repr_ids.is_empty() {
+            String::new()
+        } else {
+            let reprs = repr_ids
+                .iter()
+                .map(|id| self.op_reprs[*id])
+                .collect::<Vec<_>>();
+            format!(" unary [{}]", reprs.join(", "))
+        }
+    }
+}
+
+/// Reconstructs the nesting of flat nodes and operators by replaying their evaluation plan.
+/// The plan has been checked during flattening, such that the tree ends up at the first
+/// node.
+fn debug_tree<T: Copy + Debug>(
+    nodes: &[FlatNode<T>],
+    ops: &[FlatOp<T>],
+    plan: &[EvalStep],
+    names: &DebugNames,
+) -> DebugNode {
+    let mut trees = nodes
+        .iter()
+        .map(|node| {
+            let unary = names.unary(&node.unary_repr_ids);
+            match &node.kind {
+                FlatNodeKind::Num(num) => DebugNode {
+                    label: format!("{:?}{}", num, unary),
+                    children: vec![],
+                },
+                FlatNodeKind::Var(idx) => DebugNode {
+                    label: format!("{}{}", names.var_names[*idx], unary),
+                    children: vec![],
+                },
+                FlatNodeKind::Tmp(idx) => DebugNode {
+                    label: format!("tmp{}{}", idx - names.n_vars, unary),
+                    children: vec![],
+                },
+                FlatNodeKind::NaryCall(call) => DebugNode {
+                    label: format!("{}(...){}", names.op_reprs[call.repr_id], unary),
+                    children: call
+                        .args
+                        .iter()
+                        .map(|arg| debug_tree(&arg.nodes, &arg.ops, &arg.plan, names))
+                        .collect(),
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    for (step_idx, step) in plan.iter().enumerate() {
+        let op = &ops[step.op_idx];
+        let right = mem::take(&mut trees[step.right]);
+        let left = mem::take(&mut trees[step.left]);
+        trees[step.left] = DebugNode {
+            label: format!(
+                "{} (op {}, prio {}, step {}){}",
+                names.op_reprs[op.bin_repr_id],
+                step.op_idx,
+                op.prio,
+                step_idx,
+                names.unary(&op.unary_repr_ids)
+            ),
+            children: vec![left, right],
+        };
+    }
+    trees.swap_remove(0)
+}
+
+fn write_debug_tree(node: &DebugNode, prefix: &str, out: &mut String) {
+    out.push_str(&node.label);
+    out.push('\n');
+    for (idx, child) in node.children.iter().enumerate() {
+        let (branch, indent) = if idx + 1 < node.children.len() {
+            ("|-- ", "|   ")
+        } else {
+            ("`-- ", "    ")
+        };
+        out.push_str(prefix);
+        out.push_str(branch);
+        write_debug_tree(child, &format!("{}{}", prefix, indent), out);
+    }
+}
+
+/// Writes the nodes and edges of `node` and returns the id of `node`.
+fn write_dot(node: &DebugNode, next_id: &mut usize, out: &mut String) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let label = node.label.replace('\\', "\\\\").replace('"', "\\\"");
+    out.push_str(&format!("    n{} [label=\"{}\"];\n", id, label));
+    for child in &node.children {
+        let child_id = write_dot(child, next_id, out);
+        out.push_str(&format!("    n{} -> n{};\n", id, child_id));
+    }
+    id
+}
+
+/// This is the core data type representing a flattened expression and the result of
+/// parsing a string. We use flattened expressions to make efficient evaluation possible.
+/// Simplified, a flat expression consists of a [`SmallVec`](SmallVec) of nodes and a
+/// [`SmallVec`](SmallVec) of operators that are applied to the nodes in an order following
+/// operator priorities.
+///
+/// You create an expression with the `parse` function or one of its
+/// variants, namely `parse_with_default_ops` and `parse_with_number_pattern`.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+///
+/// // create an expression by parsing a string
+/// let expr = parse_with_default_ops::<f32>("sin(1+y)*x")?;
+/// assert!((expr.eval(&[1.5, 2.0])? - (1.0 + 2.0 as f32).sin() * 1.5).abs() < 1e-6);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+/// The second argument `&[1.5, 2.0]` in the call of [`eval`](FlatEx::eval) specifies the
+/// variable values in the alphabetical order of the variable names.
+/// In this example, we want to evaluate the expression for the variable values `x=1.5` and `y=2.0`.
+/// The order can be changed to the order of occurrence with [`VarOrder`](VarOrder).
+/// Variables in the string to-be-parsed are all substrings that are no numbers, no
+/// operators, and no parentheses.
+///
+/// The derived comparisons consider the addresses of the operators' function pointers,
+/// which are not guaranteed to be unique or stable, whether the deep expression has been
+/// [cleared](FlatEx::clear_deepex), the string the expression has been parsed from, and
+/// the [factory](crate::MakeOperators) of the operators, if any.
+/// Hence, the same string parsed twice with the same operators is usually but not
+/// necessarily equal. To compare expressions by operator
+/// representations, numbers, and variables, use [`structure_eq`](FlatEx::structure_eq)
+/// and [`structure_hash`](FlatEx::structure_hash).
+///
+/// The const parameter `N_NODES` is the number of nodes and operators that are stored on
+/// the stack before the expression moves them to the heap, see [`LargeFlatEx`](LargeFlatEx).
+///
+/// Expressions contain only numbers, function pointers, and borrowed names. Hence, for
+/// number types that are [`Send`] and [`Sync`], they are as well and can, e.g., be shared
+/// between threads that evaluate them for different variable values.
+///
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct FlatEx<'a, T: Copy + Debug, const N_NODES: usize = N_NODES_ON_STACK> {
+    nodes: FlatNodeVec<T, N_NODES>,
+    ops: FlatOpVec<T, N_NODES>,
+    plan: EvalPlan<N_NODES>,
+    /// Common sub-expressions that are evaluated before the nodes, see
+    /// [`optimize_cse`](FlatEx::optimize_cse).
+    tmps: Vec<FlatArg<T>>,
+    n_unique_vars: usize,
+    var_names: SmallVec<[&'a str; N_VARS_ON_STACK]>,
+    var_order: VarOrder,
+    /// Representations of the operators the flat operators and nodes refer to.
+    op_reprs: Vec<&'a str>,
+    /// Fallible operations by representation index, empty if there are none.
+    fallible: FallibleOps<T>,
+    deepex: Option<DeepEx<'a, T>>,
+    /// The string the expression has been parsed from, if any.
+    text: Option<&'a str>,
+    /// Factory of the operators if the expression has been parsed with
+    /// [`parse_with_factory`](crate::parse_with_factory).
+    make_ops: Option<MakeOps<T>>,
+}
+
+impl<'a, T: Copy + Debug, const N_NODES: usize> FlatEx<'a, T, N_NODES> {
+    fn from_deepex(deepex: DeepEx<'a, T>) -> Result<Self, ExError> {
+        let mut op_reprs = vec![];
+        let (nodes, ops, plan) = flatten_with_plan(&deepex, &mut op_reprs, &[])?;
+        let n_unique_vars = deepex.n_vars();
+        let var_names = deepex.var_names().iter().copied().collect();
+        let var_order = deepex.var_order();
+        let fallible = fallible_by_repr_id(&op_reprs, |repr| deepex.fallible_op(repr));
+        Ok(FlatEx {
+            nodes,
+            ops,
+            plan,
+            tmps: vec![],
+            n_unique_vars,
+            var_names,
+            var_order,
+            op_reprs,
+            fallible,
+            deepex: Some(deepex),
+            text: None,
+            make_ops: None,
+        })
+    }
+
+    /// Keeps the factory of the operators, see [`MakeOperators`](crate::MakeOperators).
+    pub(crate) fn with_make_ops(self, make_ops: Option<MakeOps<T>>) -> Self {
+        FlatEx { make_ops, ..self }
+    }
+
+    /// Keeps the string the expression has been parsed from for
+    /// [`Display`](Display) and [`text`](FlatEx::text).
+    pub(crate) fn with_text(self, text: &'a str) -> Self {
+        FlatEx {
+            text: Some(text),
+            ..self
+        }
+    }
+
+    /// Returns the string the expression has been parsed from. Expressions that are
+    /// created otherwise, e.g., by combining or differentiating expressions, have none.
+    pub fn text(&self) -> Option<&'a str> {
+        self.text
+    }
+
+    /// Returns the expression with each operator replaced by the one with the same
+    /// representation in `ops`, e.g., to evaluate it with instrumented or checked
+    /// operations. Only the operations are replaced. The structure including the
+    /// priorities, common sub-expressions, and constants that have been folded during
+    /// parsing are kept.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("1/x")?;
+    /// assert_eq!(expr.eval(&[0.0])?, f64::INFINITY);
+    /// let mut ops = make_default_operators::<f64>().to_vec();
+    /// for op in ops.iter_mut().filter(|op| op.repr == "/") {
+    ///     if let Some(bin_op) = op.bin_op.as_mut() {
+    ///         bin_op.apply = |a, b| if b == 0.0 { f64::NAN } else { a / b };
+    ///     }
+    /// }
+    /// let checked = expr.with_ops(&ops)?;
+    /// assert!(checked.eval(&[0.0])?.is_nan());
+    /// assert_eq!(checked.eval(&[4.0])?, expr.eval(&[4.0])?);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If `ops` lacks an operator of the expression, we return an
+    /// [`ExError::OperatorNotFound`](ExError::OperatorNotFound). If the operator lacks the
+    /// binary or the unary operation that is used, we return an
+    /// [`ExError::OperatorNotBinary`](ExError::OperatorNotBinary) or an
+    /// [`ExError::OperatorNotUnary`](ExError::OperatorNotUnary). The same holds for n-ary
+    /// operations, which additionally need the same arity.
+    ///
+    pub fn with_ops(&self, ops: &[Operator<'a, T>]) -> Result<Self, ExError> {
+        let mut res = self.clone();
+        rebind_ops(&mut res.nodes, &mut res.ops, ops, &self.op_reprs)?;
+        for tmp in res.tmps.iter_mut() {
+            rebind_ops(&mut tmp.nodes, &mut tmp.ops, ops, &self.op_reprs)?;
+        }
+        res.fallible = fallible_by_repr_id(&self.op_reprs, |repr| {
+            find_op(ops, repr).ok().and_then(|op| op.fallible)
+        });
+        res.deepex = match &self.deepex {
+            Some(deepex) => Some(deepex.with_ops(ops, |x| x)?),
+            None => None,
+        };
+        res.make_ops = None;
+        Ok(res)
+    }
+
+    /// Evaluates an expression with the given variable values and returns the computed
+    /// result.
+    ///
+    /// # Arguments
+    ///
+    /// * `vars` - Values of the variables of the expression; the n-th value corresponds to
+    ///   the n-th variable of [`var_names`](FlatEx::var_names), i.e., by default in
+    ///   alphabetical order, see [`VarOrder`](VarOrder). The variables are the ones of the
+    ///   parsed string, also if the value does not depend on some of them, e.g., `x*0`
+    ///   expects a value for `x`. See [`is_constant`](FlatEx::is_constant) for expressions
+    ///   that do not depend on any of their variables.
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables in the parsed expression are different from the length of
+    /// the variable slice or a [fallible operation](FallibleOp) fails, we return an
+    /// [`ExError`](ExError).
+    ///
+    /// # Order of evaluation
+    ///
+    /// The order in which operators are applied determines the rounding of floats and is
+    /// guaranteed to stay the same across releases.
+    ///
+    /// * Unary operators are applied before the binary operators that use their results.
+    ///   The unary operators of a sub-expression in parentheses are applied right after
+    ///   its last binary operator.
+    /// * Binary operators in parentheses are applied before the binary operators around
+    ///   them, i.e., each level of nesting increases the priorities of its operators by the
+    ///   number of distinct priorities.
+    /// * Binary operators with a higher [priority](crate::BinOp::prio) are applied first,
+    ///   e.g., `^` is applied before `*` and `/`, which are applied before `+` and `-`.
+    /// * Among binary operators with equal priorities, operators between two numbers are
+    ///   applied first with [`Semantics::Fast`](crate::Semantics::Fast) where this does not
+    ///   change the value for real numbers, e.g., `x+1-2` is `x+(1-2)`. This never moves an
+    ///   operator before one with a higher priority.
+    /// * The remaining binary operators with equal priorities are applied from left to
+    ///   right, e.g., `a-b+c` is `(a-b)+c` and `a/b*c` is `(a/b)*c`.
+    ///
+    /// See also [`execution_order`](FlatEx::execution_order).
+    ///
+    /// Debug builds recompute the order independently on each evaluation and panic if it
+    /// differs.
+    ///
+    pub fn eval(&self, vars: &[T]) -> Result<T, ExError> {
+        self.check_vars(vars.len())?;
+        #[cfg(debug_assertions)]
+        self.assert_eval_order();
+        if self.fallible.is_empty() {
+            Ok(self.eval_unchecked(vars))
+        } else {
+            self.eval_fallible(vars)
+        }
+    }
+
+    /// Panics if the evaluation plan differs from its recomputation from the deep
+    /// expression, see [`check_eval_order`](check_eval_order). Expressions without deep
+    /// expression or with common sub-expressions are not checked. This is only compiled
+    /// into debug builds.
+    #[cfg(debug_assertions)]
+    fn assert_eval_order(&self) {
+        if let (Some(deepex), true) = (&self.deepex, self.tmps.is_empty()) {
+            if let Err(e) = check_eval_order(deepex, &self.nodes, &self.ops, &self.plan) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    /// Evaluates with the fallible operations and checks the result of each operator
+    /// with `check`.
+    fn eval_with_checks<C>(&self, vars: &[T], check: C) -> Result<T, ExError>
+    where
+        C: Fn(T, usize, &[T]) -> Result<T, ExError>,
+    {
+        let checks = Checks {
+            fallible: &self.fallible,
+            op_reprs: &self.op_reprs,
+            check,
+        };
+        let mut values = SmallVec::<[T; N_VARS_ON_STACK]>::from_slice(vars);
+        for tmp in &self.tmps {
+            let value = eval_flat_checked(&tmp.nodes, &tmp.ops, &tmp.plan, &values, &checks)?;
+            values.push(value);
+        }
+        eval_flat_checked(&self.nodes, &self.ops, &self.plan, &values, &checks)
+    }
+
+    fn eval_fallible(&self, vars: &[T]) -> Result<T, ExError> {
+        self.eval_with_checks(vars, |result, _, _| Ok(result))
+    }
+
+    /// Checks whether `n_vars` is the number of variables of the expression. Callers of
+    /// [`eval_unchecked`](FlatEx::eval_unchecked) can do this once up front.
+    ///
+    /// # Errors
+    ///
+    /// If the numbers do not match, we return an [`ExError`](ExError).
+    ///
+    pub fn check_vars(&self, n_vars: usize) -> Result<(), ExError> {
+        if self.n_unique_vars != n_vars {
+            Err(ExError::WrongNumberOfVars {
+                expected: self.n_unique_vars,
+                given: n_vars,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns whether the flattened expression does not contain any variable, e.g., after
+    /// constant sub-expressions have been folded during parsing or for the derivative `2`
+    /// of `2*x`. Its [`var_names`](FlatEx::var_names) are kept nonetheless such that
+    /// [`eval`](FlatEx::eval) still expects a value for each of them. Operators are not
+    /// simplified, e.g., `x*0` is not constant, since it is `NaN` for an infinite `x`.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("2*x")?;
+    /// assert!(!expr.is_constant());
+    /// let d_x = expr.partial(0)?;
+    /// assert!(d_x.is_constant());
+    /// assert_eq!(d_x.var_names(), ["x"]);
+    /// assert_eq!(d_x.constant_value(), Some(2.0));
+    /// assert_eq!(d_x.eval(&[123.0])?, 2.0);
+    /// assert!(d_x.eval(&[]).is_err());
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn is_constant(&self) -> bool {
+        !contains_var_nodes(&self.nodes)
+            && self.tmps.iter().all(|tmp| !contains_var_nodes(&tmp.nodes))
+    }
+
+    /// Returns the value of a [constant](FlatEx::is_constant) expression without passing
+    /// values for its variables, and `None` if the expression is not constant or a
+    /// [fallible operation](FallibleOp) fails.
+    pub fn constant_value(&self) -> Option<T> {
+        if !self.is_constant() {
+            return None;
+        }
+        // the values of the variables are not read, such that any value can be passed, e.g.,
+        // the one of the first part that is evaluated, which cannot refer to other parts
+        let any = match self.tmps.first() {
+            Some(tmp) => eval_flat(&tmp.nodes, &tmp.ops, &tmp.plan, &[]),
+            None => eval_flat(&self.nodes, &self.ops, &self.plan, &[]),
+        };
+        let vars: SmallVec<[T; N_VARS_ON_STACK]> = smallvec![any; self.n_unique_vars];
+        self.eval(&vars).ok()
+    }
+
+    /// Evaluates an expression like [`eval`](FlatEx::eval) but without checking the
+    /// number of passed variables, e.g., for hot loops where the number has been verified
+    /// once with [`check_vars`](FlatEx::check_vars). [Fallible operations](FallibleOp) are
+    /// not applied, their infallible counterparts are.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*y")?;
+    /// expr.check_vars(2)?;
+    /// let sum = (0..100).map(|i| expr.eval_unchecked(&[i as f64, 0.5])).sum::<f64>();
+    /// assert!((sum - 2475.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the slice is shorter than the number of variables of the expression. Additional
+    /// values at the end of a longer slice are ignored.
+    ///
+    pub fn eval_unchecked(&self, vars: &[T]) -> T {
+        eval_flat_with_tmps(
+            &self.nodes,
+            &self.ops,
+            &self.plan,
+            &self.tmps,
+            vars,
+            self.n_unique_vars,
+        )
+    }
+
+    /// Evaluates an expression like [`eval`](FlatEx::eval) but stores intermediate values
+    /// in `buffer` instead of allocating, which pays off for expressions with more than 32
+    /// nodes that are evaluated many times. The buffer can be shared by different
+    /// expressions. Only arguments of n-ary operators are still evaluated with their own
+    /// storage.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::{prelude::*, EvalBuffer};
+    /// let expr = parse_with_default_ops::<f64>("x*y")?;
+    /// let mut buffer = EvalBuffer::new();
+    /// let mut sum = 0.0;
+    /// for i in 0..100 {
+    ///     sum += expr.eval_with_buffer(&[i as f64, 0.5], &mut buffer)?;
+    /// }
+    /// assert!((sum - 2475.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables in the parsed expression are different from the length of
+    /// the variable slice, we return an [`ExError`](ExError).
+    ///
+    pub fn eval_with_buffer(&self, vars: &[T], buffer: &mut EvalBuffer<T>) -> Result<T, ExError> {
+        self.check_vars(vars.len())?;
+        if !self.fallible.is_empty() {
+            return self.eval_fallible(vars);
+        }
+        buffer.numbers.clear();
+        if self.tmps.is_empty() {
+            buffer.numbers.extend(node_values(&self.nodes, vars));
+        } else {
+            let values = tmp_values(&self.tmps, vars, self.n_unique_vars);
+            buffer.numbers.extend(node_values(&self.nodes, &values));
+        }
+        Ok(apply_plan(&mut buffer.numbers, &self.ops, &self.plan))
+    }
+
+    /// Evaluates an expression like [`eval`](FlatEx::eval) but checks the result of each
+    /// operator. Instead of silently propagating `NaN` or infinity, the first operator whose
+    /// result is not finite is reported together with its operands. Numbers that are not
+    /// finite after constant folding at parse time, e.g., `1/0`, are reported by the first
+    /// operator they are passed to.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("2 + 1/x")?;
+    /// assert!((expr.eval_checked(&[0.5])? - 4.0).abs() < 1e-12);
+    /// match expr.eval_checked(&[0.0]) {
+    ///     Err(ExError::NonFiniteResult { op, operands, .. }) => {
+    ///         assert_eq!(op, "/");
+    ///         assert_eq!(operands, ["1.0", "0.0"]);
+    ///     }
+    ///     _ => panic!("expected a non-finite result"),
+    /// }
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables in the parsed expression are different from the length of
+    /// the variable slice, a [fallible operation](FallibleOp) fails, or an operator produces
+    /// `NaN` or an infinite value, we return an [`ExError`](ExError).
+    ///
+    #[cfg(feature = "std")]
+    pub fn eval_checked(&self, vars: &[T]) -> Result<T, ExError>
+    where
+        T: Float,
+    {
+        self.check_vars(vars.len())?;
+        self.eval_with_checks(vars, |result, repr_id, operands| {
+            check_finite(result, repr_id, operands, &self.op_reprs)
+        })
+    }
+
+    /// Evaluates an expression like [`eval`](FlatEx::eval) with the variable values in an
+    /// array, e.g., in generic code with a const number of variables.
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables of the expression is not `N`, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn eval_arr<const N: usize>(&self, vars: &[T; N]) -> Result<T, ExError> {
+        self.eval(vars)
+    }
+
+    /// Evaluates an expression like [`eval`](FlatEx::eval) with variable values of another
+    /// type that are converted losslessly, e.g., `i32` or `f32` values for a `FlatEx<f64>`.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x/y")?;
+    /// assert_eq!(expr.eval_from(&[3, 2])?, 1.5);
+    /// assert_eq!(expr.eval_from(&[3.0f32, 2.0])?, 1.5);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables in the parsed expression are different from the length of
+    /// the variable slice, we return an [`ExError`](ExError).
+    ///
+    pub fn eval_from<V: Into<T> + Copy>(&self, vars: &[V]) -> Result<T, ExError> {
+        self.eval_iter(vars.iter().map(|v| (*v).into()))
+    }
+
+    /// Evaluates an expression like [`eval`](FlatEx::eval) with variable values from an
+    /// iterator such that no intermediate vector needs to be built by the caller. The
+    /// iterator is consumed entirely to report the number of values in case it does not
+    /// match.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*y")?;
+    /// let record = "2.5,4";
+    /// let values = record.split(',').map(|v| v.parse::<f64>().unwrap());
+    /// assert_eq!(expr.eval_iter(values)?, 10.0);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables in the parsed expression are different from the number
+    /// of values, we return an [`ExError`](ExError).
+    ///
+    pub fn eval_iter<I: IntoIterator<Item = T>>(&self, vars: I) -> Result<T, ExError> {
+        let mut vars = vars.into_iter();
+        let values = vars
+            .by_ref()
+            .take(self.n_unique_vars)
+            .collect::<SmallVec<[T; N_VARS_ON_STACK]>>();
+        self.check_vars(values.len() + vars.count())?;
+        if self.fallible.is_empty() {
+            Ok(self.eval_unchecked(&values))
+        } else {
+            self.eval_fallible(&values)
+        }
+    }
+
+    /// Checks once that the expression has `N` variables and returns a wrapper that
+    /// evaluates without further checks.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*y")?;
+    /// let bound = expr.try_bind_arity::<2>()?;
+    /// let sum = (0..100).map(|i| bound.eval(&[i as f64, 0.5])).sum::<f64>();
+    /// assert!((sum - 2475.0).abs() < 1e-12);
+    /// assert!(expr.try_bind_arity::<3>().is_err());
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables of the expression is not `N`, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn try_bind_arity<const N: usize>(
+        &self,
+    ) -> Result<BoundEx<'_, 'a, T, N, N_NODES>, ExError> {
+        self.check_vars(N)?;
+        Ok(BoundEx { flatex: self })
+    }
+
+    /// Turns the expression into a closure that evaluates it with the variable values in
+    /// an array. The number of variables is checked once during the conversion. The
+    /// closure owns an [`OwnedFlatEx`](OwnedFlatEx) and does not borrow the parsed string.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let text = "x*y+z".to_string();
+    /// let f = parse_with_default_ops::<f64>(&text)?.into_fn::<3>()?;
+    /// drop(text);
+    /// assert!((f([2.0, 3.0, 0.5]) - 6.5).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables of the expression is not `N`, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn into_fn<const N: usize>(
+        self,
+    ) -> Result<impl Fn([T; N]) -> T + Clone + Send + Sync + 'static, ExError>
+    where
+        T: Send + Sync + 'static,
+    {
+        OwnedFlatEx::from_flatex(self).into_fn()
+    }
+
+    /// Turns an expression with one variable into a closure, see
+    /// [`into_fn`](FlatEx::into_fn).
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let f = parse_with_default_ops::<f64>("x^2")?.into_func1()?;
+    /// let g = parse_with_default_ops::<f64>("sin(x)*y")?.into_func2()?;
+    /// assert!((f(3.0) - 9.0).abs() < 1e-12);
+    /// assert!((g(0.0, 2.0)).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the expression does not have exactly one variable, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn into_func1(self) -> Result<impl Fn(T) -> T + Clone + Send + Sync + 'static, ExError>
+    where
+        T: Send + Sync + 'static,
+    {
+        let f = self.into_fn::<1>()?;
+        Ok(move |x| f([x]))
+    }
+
+    /// Turns an expression with two variables into a closure, see
+    /// [`into_func1`](FlatEx::into_func1).
+    ///
+    /// # Errors
+    ///
+    /// If the expression does not have exactly two variables, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn into_func2(self) -> Result<impl Fn(T, T) -> T + Clone + Send + Sync + 'static, ExError>
+    where
+        T: Send + Sync + 'static,
+    {
+        let f = self.into_fn::<2>()?;
+        Ok(move |x, y| f([x, y]))
+    }
+
+    /// Turns an expression with three variables into a closure, see
+    /// [`into_func1`](FlatEx::into_func1).
+    ///
+    /// # Errors
+    ///
+    /// If the expression does not have exactly three variables, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn into_func3(
+        self,
+    ) -> Result<impl Fn(T, T, T) -> T + Clone + Send + Sync + 'static, ExError>
+    where
+        T: Send + Sync + 'static,
+    {
+        let f = self.into_fn::<3>()?;
+        Ok(move |x, y, z| f([x, y, z]))
+    }
+
+    /// Evaluates an expression with variable values that are passed together with the
+    /// variable names. The order of the passed pairs is irrelevant.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::{prelude::*, NameResolution};
+    /// let expr = parse_with_default_ops::<f64>("x^2 + y")?;
+    /// let result = expr.eval_named(&[("y", 0.5), ("x", 2.0)], &NameResolution::default())?;
+    /// assert!((result - 4.5).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if the passed names do not match the
+    /// variables of the expression under the given [`NameResolution`](NameResolution) policy.
+    ///
+    pub fn eval_named(
+        &self,
+        vars: &[(&str, T)],
+        resolution: &NameResolution,
+    ) -> Result<T, ExError> {
+        let names = vars
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        let positions = resolution.resolve_all(&self.var_names, &names)?;
+        let values = positions
+            .iter()
+            .map(|pos| vars[*pos].1)
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        self.eval(&values)
+    }
+
+    /// Evaluates an expression like [`eval_named`](FlatEx::eval_named) but variables
+    /// without a passed value are set to `default`, e.g., empty cells of a spreadsheet.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::{prelude::*, NameResolution};
+    /// let expr = parse_with_default_ops::<f64>("x + y*z")?;
+    /// let result = expr.eval_with_default(&[("y", 2.0)], 1.5, &NameResolution::default())?;
+    /// assert!((result - 4.5).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if a passed name is not a variable of the
+    /// expression, e.g., due to a typo, or if the passed names do not match the
+    /// variables otherwise under the given [`NameResolution`](NameResolution) policy.
+    /// Missing names are no errors.
+    ///
+    pub fn eval_with_default(
+        &self,
+        vars: &[(&str, T)],
+        default: T,
+        resolution: &NameResolution,
+    ) -> Result<T, ExError> {
+        let names = vars
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        let values = resolution
+            .resolve(&self.var_names, &names)?
+            .iter()
+            .map(|pos| pos.map_or(default, |pos| vars[pos].1))
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        self.eval(&values)
+    }
+
+    /// Evaluates an expression for many points at once, e.g., for plotting. The i-th slice
+    /// of `vars` contains the values of the i-th variable for all points, and the result
+    /// for the j-th point is written to `out[j]`. The results are the same as calling
+    /// [`eval`](FlatEx::eval) for each point but the evaluation is faster for many points.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*y+1")?;
+    /// let xs = [1.0, 2.0, 3.0];
+    /// let ys = [0.5, 0.5, 2.0];
+    /// let mut out = [0.0; 3];
+    /// expr.eval_vectorized(&[&xs, &ys], &mut out)?;
+    /// assert_eq!(out, [1.5, 2.0, 7.0]);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the number of slices is not the number of variables of the expression or if a
+    /// slice has a length different from `out`, we return an [`ExError`](ExError).
+    ///
+    pub fn eval_vectorized(&self, vars: &[&[T]], out: &mut [T]) -> Result<(), ExError> {
+        self.check_vars(vars.len())?;
+        if let Some((series, values)) = vars
+            .iter()
+            .enumerate()
+            .find(|(_, values)| values.len() != out.len())
+        {
+            return Err(ExError::SeriesLengthMismatch {
+                series,
+                expected: out.len(),
+                given: values.len(),
+            });
+        }
+        // the values of the common sub-expressions are computed as additional series
+        let mut tmp_series: Vec<Vec<T>> = Vec::with_capacity(self.tmps.len());
+        for tmp in &self.tmps {
+            let mut series = out.to_vec();
+            let mut all_vars = vars.to_vec();
+            all_vars.extend(tmp_series.iter().map(|s| s.as_slice()));
+            eval_flat_vectorized(&tmp.nodes, &tmp.ops, &tmp.plan, &all_vars, &mut series);
+            tmp_series.push(series);
+        }
+        let mut all_vars = vars.to_vec();
+        all_vars.extend(tmp_series.iter().map(|s| s.as_slice()));
+        eval_flat_vectorized(&self.nodes, &self.ops, &self.plan, &all_vars, out);
+        Ok(())
+    }
+
+    /// Evaluates an expression for each row of a table, e.g., read from a CSV file. The
+    /// columns are matched once against the variables of the expression by their names in
+    /// `header` under the given [`NameResolution`](NameResolution) policy. Columns
+    /// that do not correspond to a variable are not allowed.
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if the header does not match the
+    /// variables of the expression or if a row has a length different from the header.
+    ///
+    pub fn eval_rows<R: AsRef<[T]>>(
+        &self,
+        header: &[&str],
+        rows: &[R],
+        resolution: &NameResolution,
+    ) -> Result<Vec<T>, ExError> {
+        let positions = resolution.resolve_all(&self.var_names, header)?;
+        rows.iter()
+            .enumerate()
+            .map(|(row_idx, row)| {
+                let row = row.as_ref();
+                if row.len() != header.len() {
+                    return Err(ExError::RowLengthMismatch {
+                        row: row_idx,
+                        expected: header.len(),
+                        given: row.len(),
+                    });
+                }
+                let values = positions
+                    .iter()
+                    .map(|pos| row[*pos])
+                    .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+                self.eval(&values)
+            })
+            .collect()
+    }
+
+    /// Returns the names of the variables, where the n-th name belongs to the n-th value of
+    /// the slice passed to [`eval`](FlatEx::eval). All variables of the parsed string are
+    /// contained, also if they do not affect the value, e.g., `x` in `x*0 + y`. Only
+    /// operators with numbers as operands are folded during parsing.
+    pub fn var_names(&self) -> &[&'a str] {
+        &self.var_names
+    }
+
+    /// Returns the index of the variable named `var_name`.
+    fn var_idx(&self, var_name: &str) -> Result<usize, ExError> {
+        self.var_names
+            .iter()
+            .position(|name| *name == var_name)
+            .ok_or_else(|| ExError::UnknownVar {
+                name: var_name.to_string(),
+                available: self.var_names.iter().map(|name| name.to_string()).collect(),
+            })
+    }
+
+    /// Returns the order of the [`var_names`](FlatEx::var_names) as chosen by
+    /// [`ParseOptions::var_order`](crate::ParseOptions::var_order).
+    pub fn var_order(&self) -> VarOrder {
+        self.var_order
+    }
+
+    /// Returns the maximal number of unary operators that are applied in a row to a single
+    /// node or to the result of a single binary operator. Unary operators of nested
+    /// sub-expressions have been merged during flattening, e.g., for `-(-(-x)) + 1` the
+    /// result is 3. Unary operators applied to numbers have been evaluated during parsing
+    /// and do not count.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("-(-(-x)) + sin(y)")?;
+    /// assert_eq!(expr.max_unary_chain_len(), 3);
+    /// assert_eq!(expr.total_unary_applications(), 4);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn max_unary_chain_len(&self) -> usize {
+        self.unary_chain_lens().max().unwrap_or(0)
+    }
+
+    /// Returns the number of unary operators that are applied during one evaluation. See
+    /// also [`max_unary_chain_len`](FlatEx::max_unary_chain_len).
+    pub fn total_unary_applications(&self) -> usize {
+        self.unary_chain_lens().sum()
+    }
+
+    /// Returns the number of numbers, variables, and n-ary calls of the flattened
+    /// expression including the nodes of n-ary arguments. Constant sub-expressions have
+    /// been evaluated during parsing, e.g., `2*3+x` has 2 nodes.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("2*3 + sin(x)*(y - x)")?;
+    /// assert_eq!(expr.node_count(), 4);
+    /// assert_eq!(expr.op_count(), 4);
+    /// assert_eq!(expr.depth()?, 1);
+    /// assert_eq!(expr.operators_used(), ["*", "+", "-", "sin"]);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn node_count(&self) -> usize {
+        self.flat_parts().map(|(nodes, _)| nodes.len()).sum()
+    }
+
+    /// Returns the number of operators that are applied during one evaluation, i.e., of
+    /// binary operators, unary operators, and n-ary calls. See also
+    /// [`node_count`](FlatEx::node_count).
+    pub fn op_count(&self) -> usize {
+        self.flat_parts()
+            .map(|(nodes, ops)| {
+                let n_nary_calls = nodes
+                    .iter()
+                    .filter(|node| matches!(node.kind, FlatNodeKind::NaryCall(_)))
+                    .count();
+                let n_unary = nodes.iter().map(|node| node.unary_op.len()).sum::<usize>()
+                    + ops.iter().map(|op| op.unary_op.len()).sum::<usize>();
+                ops.len() + n_unary + n_nary_calls
+            })
+            .sum()
+    }
+
+    /// Returns the numbers of operations and variable reads of one evaluation including
+    /// those of n-ary arguments and common sub-expressions, e.g., to decide whether
+    /// evaluating the expression for each row is cheap enough. See also
+    /// [`cost_with_weights`](FlatEx::cost_with_weights).
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::{prelude::*, CostEstimate};
+    /// let expr = parse_with_default_ops::<f64>("sin(-x) * x + 2^3")?;
+    /// assert_eq!(
+    ///     expr.cost_estimate(),
+    ///     CostEstimate {
+    ///         bin_ops: 2,
+    ///         unary_ops: 2,
+    ///         nary_calls: 0,
+    ///         var_reads: 2,
+    ///     }
+    /// );
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn cost_estimate(&self) -> CostEstimate {
+        self.flat_parts()
+            .fold(CostEstimate::default(), |cost, (nodes, ops)| CostEstimate {
+                bin_ops: cost.bin_ops + ops.len(),
+                unary_ops: cost.unary_ops
+                    + nodes.iter().map(|node| node.unary_op.len()).sum::<usize>()
+                    + ops.iter().map(|op| op.unary_op.len()).sum::<usize>(),
+                nary_calls: cost.nary_calls
+                    + nodes
+                        .iter()
+                        .filter(|node| matches!(node.kind, FlatNodeKind::NaryCall(_)))
+                        .count(),
+                var_reads: cost.var_reads
+                    + nodes
+                        .iter()
+                        .filter(|node| matches!(node.kind, FlatNodeKind::Var(_)))
+                        .count(),
+            })
+    }
+
+    /// Returns the sum of the weights of the operators that are applied during one
+    /// evaluation. Operators are identified by their representations and weigh 1 if they
+    /// are not contained in `weights`. Numbers and variable reads weigh nothing, see
+    /// [`cost_estimate`](FlatEx::cost_estimate) for the latter.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use std::collections::HashMap;
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("sin(x) * x + y")?;
+    /// let weights = HashMap::from([("sin", 20), ("*", 2)]);
+    /// assert_eq!(expr.cost_with_weights(&weights), 23);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn cost_with_weights(&self, weights: &HashMap<&str, u32>) -> u64 {
+        let weight = |repr_id: usize| {
+            self.op_reprs
+                .get(repr_id)
+                .and_then(|repr| weights.get(repr))
+                .map_or(1, |weight| u64::from(*weight))
+        };
+        self.flat_parts()
+            .map(|(nodes, ops)| {
+                let nodes = nodes.iter().map(|node| {
+                    let nary = match &node.kind {
+                        FlatNodeKind::NaryCall(call) => weight(call.repr_id),
+                        _ => 0,
+                    };
+                    nary + node
+                        .unary_repr_ids
+                        .iter()
+                        .map(|id| weight(*id))
+                        .sum::<u64>()
+                });
+                let ops = ops.iter().map(|op| {
+                    weight(op.bin_repr_id)
+                        + op.unary_repr_ids.iter().map(|id| weight(*id)).sum::<u64>()
+                });
+                nodes.chain(ops).sum::<u64>()
+            })
+            .sum()
+    }
+
+    /// Returns the nesting depth of the sub-expressions of the deep expression, see
+    /// [`DeepEx::depth`](DeepEx::depth). See also
+    /// [`ParseLimits::max_depth`](crate::ParseLimits::max_depth).
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if `self` has been `clear_deepex`ed.
+    ///
+    pub fn depth(&self) -> Result<usize, ExError> {
+        Ok(unpack_deepex(self)?.depth())
+    }
+
+    /// Returns the sorted representations of the operators that are applied during
+    /// evaluation. Operators that have been evaluated during parsing are not contained.
+    pub fn operators_used(&self) -> Vec<&'a str> {
+        let mut reprs = self.op_reprs.clone();
+        reprs.sort_unstable();
+        reprs
+    }
+
+    /// Returns the indices of the binary operators in the order they are applied, whereby
+    /// the operators of the flattened expression are numbered from left to right as in
+    /// [`to_debug_tree`](FlatEx::to_debug_tree). Operators with higher priority are applied
+    /// first, operators in parentheses before the operators around them, and operators
+    /// with equal priorities from left to right, e.g., `a+b+c+d` is `((a+b)+c)+d`. With
+    /// [`Semantics::Fast`](crate::Semantics::Fast), an operator between two numbers is
+    /// applied before other operators with the same priority where regrouping keeps the
+    /// value for real numbers, e.g., in `x+1-2` but not in `x-1+2`, see
+    /// [`eval`](FlatEx::eval). This order is guaranteed to
+    /// stay the same across releases such that results are bit-identical. Common
+    /// sub-expressions of [`optimize_cse`](FlatEx::optimize_cse) are evaluated before.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("a-b+c*(d-e)")?;
+    /// assert_eq!(expr.execution_order(), [3, 2, 0, 1]);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn execution_order(&self) -> Vec<usize> {
+        self.plan.iter().map(|step| step.op_idx).collect()
+    }
+
+    /// Returns a printable tree of the evaluation order for debugging, i.e., how the flat
+    /// nodes are combined. Each binary operator is listed with its index, its effective
+    /// priority after flattening, and the step in which it is applied. Unary operators
+    /// of nodes and operators are listed outermost first and applied after the binary
+    /// operator. Arguments of n-ary operators are the children of their call, and common
+    /// sub-expressions of [`optimize_cse`](FlatEx::optimize_cse) are listed as `tmp0`,
+    /// `tmp1`, ... at the end. The format is meant for humans and may change.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*(y+1)")?;
+    /// let tree = expr.to_debug_tree();
+    /// assert!(tree.starts_with("execution order: [1, 0]\n"));
+    /// assert!(tree.contains("* (op 0, prio 1, step 1)"));
+    /// assert!(tree.contains("+ (op 1, prio 2, step 0)"));
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn to_debug_tree(&self) -> String {
+        let mut out = format!("execution order: {:?}\n", self.execution_order());
+        write_debug_tree(&self.debug_root(), "", &mut out);
+        for (idx, tmp) in self.tmps.iter().enumerate() {
+            out.push_str(&format!("tmp{} = ", idx));
+            let tree = debug_tree(&tmp.nodes, &tmp.ops, &tmp.plan, &self.debug_names());
+            write_debug_tree(&tree, "", &mut out);
+        }
+        out
+    }
+
+    /// Returns the tree of [`to_debug_tree`](FlatEx::to_debug_tree) in the dot language of
+    /// [Graphviz](https://graphviz.org), e.g., to be rendered with `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut out = "digraph {\n".to_string();
+        let mut next_id = 0;
+        write_dot(&self.debug_root(), &mut next_id, &mut out);
+        for (idx, tmp) in self.tmps.iter().enumerate() {
+            let tree = DebugNode {
+                label: format!("tmp{}", idx),
+                children: vec![debug_tree(
+                    &tmp.nodes,
+                    &tmp.ops,
+                    &tmp.plan,
+                    &self.debug_names(),
+                )],
+            };
+            write_dot(&tree, &mut next_id, &mut out);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn debug_names(&self) -> DebugNames<'_, 'a> {
+        DebugNames {
+            var_names: &self.var_names,
+            op_reprs: &self.op_reprs,
+            n_vars: self.n_unique_vars,
+        }
+    }
+
+    fn debug_root(&self) -> DebugNode {
+        debug_tree(&self.nodes, &self.ops, &self.plan, &self.debug_names())
+    }
+
+    /// Returns the nodes and operators of the expression, of its common sub-expressions,
+    /// and of all n-ary arguments.
+    fn flat_parts(&self) -> impl Iterator<Item = (&[FlatNode<T>], &[FlatOp<T>])> + '_ {
+        type Part<'b, T> = (&'b [FlatNode<T>], &'b [FlatOp<T>]);
+        fn collect<'b, T: Copy>(
+            nodes: &'b [FlatNode<T>],
+            ops: &'b [FlatOp<T>],
+            parts: &mut Vec<Part<'b, T>>,
+        ) {
+            parts.push((nodes, ops));
+            for node in nodes {
+                if let FlatNodeKind::NaryCall(call) = &node.kind {
+                    for arg in &call.args {
+                        collect(&arg.nodes, &arg.ops, parts);
+                    }
+                }
+            }
+        }
+        let mut parts = vec![];
+        collect(&self.nodes, &self.ops, &mut parts);
+        for tmp in &self.tmps {
+            collect(&tmp.nodes, &tmp.ops, &mut parts);
+        }
+        parts.into_iter()
+    }
+
+    fn unary_chain_lens(&self) -> impl Iterator<Item = usize> + '_ {
+        let nodes = iter::once(&self.nodes[..]).chain(self.tmps.iter().map(|tmp| &tmp.nodes[..]));
+        let ops = iter::once(&self.ops[..]).chain(self.tmps.iter().map(|tmp| &tmp.ops[..]));
+        nodes
+            .flat_map(|nodes| nodes.iter().map(|node| node.unary_op.len()))
+            .chain(ops.flat_map(|ops| ops.iter().map(|op| op.unary_op.len())))
+    }
+
+    /// This method computes a `FlatEx` instance that is a partial derivative of `self` with default operators
+    /// as shown in the following example. Expressions parsed with
+    /// [`parse_with_factory`](crate::parse_with_factory) use the operators of the factory instead.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    ///
+    /// let expr = parse_with_default_ops::<f64>("sin(1+y^2)*x")?;
+    /// let d_x = expr.clone().partial(0)?;
+    /// let d_y = expr.partial(1)?;
+    ///
+    /// assert!((d_x.eval(&[9e5, 2.0])? - (5.0 as f64).sin()).abs() < 1e-12);
+    /// //                   |    
+    /// //             This partial derivative d_x does depend on x. Still, it expects
+    /// //             the same number of parameters as the corresponding
+    /// //             antiderivative. Hence, you can pass any number for x.  
+    ///
+    /// assert!((d_y.eval(&[2.5, 2.0])? - 10.0 * (5.0 as f64).cos()).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    /// # Arguments
+    ///
+    /// * `var_idx` - variable with respect to which the partial derivative is computed
+    ///
+    /// # Errors
+    ///
+    /// * If `var_idx` is not smaller than the number of variables, we return an [`ExError`](ExError).
+    /// * If `self` has been `clear_deepex`ed we cannot compute the partial derivative and return an [`ExError`](ExError).
+    /// * If you use none-default operators this might not work as expected. It could return an [`ExError`](ExError) if
+    ///   an operator is not found or compute a wrong result if an operator is defined in an un-expected way.
+    ///
+    #[cfg(feature = "std")]
+    pub fn partial(self, var_idx: usize) -> Result<Self, ExError>
+    where
+        T: Float,
+    {
+        match self.make_ops {
+            Some(make_ops) => self.partial_with_ops(var_idx, &make_ops()),
+            None => self.partial_with_ops(var_idx, &make_default_operators()),
+        }
+    }
+
+    /// Computes the partial derivative like [`partial`](FlatEx::partial) with respect to
+    /// the variable named `var_name`.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x^2*y")?;
+    /// let d_y = expr.partial_by_name("y")?;
+    /// assert!((d_y.eval(&[3.0, 0.5])? - 9.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If `self` has no variable `var_name`, we return an [`ExError`](ExError). See also
+    /// [`partial`](FlatEx::partial).
+    ///
+    #[cfg(feature = "std")]
+    pub fn partial_by_name(self, var_name: &str) -> Result<Self, ExError>
+    where
+        T: Float,
+    {
+        let var_idx = self.var_idx(var_name)?;
+        self.partial(var_idx)
+    }
+
+    /// Computes the partial derivative like [`partial`](FlatEx::partial) with the operators
+    /// `ops` instead of the default operators, e.g., for complex numbers. The derivatives
+    /// are known for the operators `+`, `-`, `*`, `/`, `^`, and the unary operators of the
+    /// [default operators](crate::make_default_operators) with the same representations.
+    /// Further operators such as `log` for `^` or `cos` for `sin` are looked up in `ops`
+    /// when they are needed.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// use num::complex::Complex64;
+    /// let bin = |repr, apply, prio| Operator::make_bin(repr, BinOp { apply, prio });
+    /// let ops = [
+    ///     bin("+", |a, b| a + b, 0),
+    ///     Operator::make_bin_unary(
+    ///         "-",
+    ///         BinOp { apply: |a, b| a - b, prio: 0 },
+    ///         |a: Complex64| -a,
+    ///     ),
+    ///     bin("*", |a, b| a * b, 1),
+    ///     bin("/", |a, b| a / b, 1),
+    ///     bin("^", |a: Complex64, b| a.powc(b), 2),
+    ///     Operator::make_unary("exp", |a: Complex64| a.exp()),
+    /// ];
+    /// let expr = parse_with_number_pattern("exp(2i*z) + z^2", &ops, r"\d+\.?\d*i?")?;
+    /// let d_z = expr.partial_with_ops(0, &ops)?;
+    /// let z = Complex64::new(0.5, -1.0);
+    /// let reference = Complex64::new(0.0, 2.0) * (Complex64::new(0.0, 2.0) * z).exp() + 2.0 * z;
+    /// assert!((d_z.eval(&[z])? - reference).norm() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// See [`partial`](FlatEx::partial).
+    ///
+    #[cfg(feature = "std")]
+    pub fn partial_with_ops(self, var_idx: usize, ops: &[Operator<'a, T>]) -> Result<Self, ExError>
+    where
+        T: DiffNum,
+    {
+        self.partial_with_ops_and_rules(var_idx, ops, &[])
+    }
+
+    /// Computes the partial derivative like [`partial`](FlatEx::partial) with the rules
+    /// `rules` in addition to the built-in ones, e.g., for custom operators, see
+    /// [`PartialDerivative`](PartialDerivative). A rule replaces the built-in rule of the same
+    /// kind for the same operator representation.
+    ///
+    /// # Errors
+    ///
+    /// See [`partial`](FlatEx::partial). Errors of the rules are passed on.
+    ///
+    #[cfg(feature = "std")]
+    pub fn partial_with_rules(
+        self,
+        var_idx: usize,
+        rules: &[PartialDerivative<'a, T>],
+    ) -> Result<Self, ExError>
+    where
+        T: Float,
+    {
+        match self.make_ops {
+            Some(make_ops) => self.partial_with_ops_and_rules(var_idx, &make_ops(), rules),
+            None => self.partial_with_ops_and_rules(var_idx, &make_default_operators(), rules),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn partial_with_ops_and_rules(
+        self,
+        var_idx: usize,
+        ops: &[Operator<'a, T>],
+        rules: &[PartialDerivative<'a, T>],
+    ) -> Result<Self, ExError>
+    where
+        T: DiffNum,
+    {
+        if var_idx >= self.n_unique_vars {
+            return Err(ExError::VarIdxOutOfRange {
+                idx: var_idx,
+                n_vars: self.n_unique_vars,
+            });
+        }
+        let make_ops = self.make_ops;
+        let deepex = self.deepex.ok_or(ExError::DeepExMissing)?;
+        let var_names = deepex.var_names().iter().copied().collect();
+        let var_order = deepex.var_order();
+        let mut d_i = partial_deepex_with_rules(var_idx, deepex, ops, rules)?;
+        // the derivative expects the same variables as the expression in the same order
+        reset_vars(&mut d_i, var_names);
+        d_i.set_var_order(var_order);
+        Ok(Self::from_deepex(d_i)?.with_make_ops(make_ops))
+    }
+
+    /// Computes the gradient, i.e., the partial derivatives with respect to all variables
+    /// in the order of [`var_names`](FlatEx::var_names). All partial derivatives expect the
+    /// same variable values as `self`, also if they do not depend on some of the variables.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x^2 + y")?;
+    /// let grad = expr.grad()?;
+    /// assert!((grad[0].eval(&[3.0, 2.0])? - 6.0).abs() < 1e-12);
+    /// assert!((grad[1].eval(&[3.0, 2.0])? - 1.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// See [`partial`](FlatEx::partial).
+    ///
+    #[cfg(feature = "std")]
+    pub fn grad(&self) -> Result<Vec<Self>, ExError>
+    where
+        T: Float,
+    {
+        (0..self.n_unique_vars)
+            .map(|var_idx| self.clone().partial(var_idx))
+            .collect()
+    }
+
+    /// Evaluates the expression and its [`grad`](FlatEx::grad)ient at once, e.g., for
+    /// optimization loops. Instead of building the partial derivatives, each value is
+    /// evaluated together with its derivatives with respect to all variables in one pass
+    /// over the flat expression, i.e., by forward-mode differentiation. Hence, this also
+    /// works for [`clear_deepex`](FlatEx::clear_deepex)ed expressions.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x^2 * sin(y)")?;
+    /// let (val, grad) = expr.eval_with_grad(&[3.0, 0.0])?;
+    /// assert!(val.abs() < 1e-12);
+    /// assert!(grad[0].abs() < 1e-12);
+    /// assert!((grad[1] - 9.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if
+    /// * the number of variables does not match,
+    /// * a [fallible operation](FallibleOp) fails, or
+    /// * the expression contains an operator without derivative. Like
+    ///   [`partial`](FlatEx::partial), only the derivatives of the default operators are
+    ///   known.
+    ///
+    #[cfg(feature = "std")]
+    pub fn eval_with_grad(&self, vars: &[T]) -> Result<(T, SmallVec<[T; 8]>), ExError>
+    where
+        T: Float,
+    {
+        self.check_vars(vars.len())?;
+        if !self.fallible.is_empty() {
+            self.eval_fallible(vars)?;
+        }
+        let dual_ops = find_dual_ops(&self.op_reprs)?;
+        let n_vars = self.n_unique_vars;
+        // the gradients of the variables are unit vectors, the ones of the common
+        // sub-expressions follow
+        let mut values = SmallVec::<[T; N_VARS_ON_STACK]>::from_slice(vars);
+        let mut var_grads: SmallVec<[T; 4 * N_VARS_ON_STACK]> =
+            smallvec![T::zero(); (n_vars + self.tmps.len()) * n_vars];
+        for var_idx in 0..n_vars {
+            var_grads[var_idx * n_vars + var_idx] = T::one();
+        }
+        let mut grad: SmallVec<[T; 8]> = smallvec![T::zero(); n_vars];
+        for (tmp_idx, tmp) in self.tmps.iter().enumerate() {
+            let value = eval_flat_dual(
+                &tmp.nodes, &tmp.ops, &tmp.plan, &values, &var_grads, &dual_ops, &mut grad,
+            )?;
+            values.push(value);
+            let start = (n_vars + tmp_idx) * n_vars;
+            var_grads[start..start + n_vars].copy_from_slice(&grad);
+        }
+        let value = eval_flat_dual(
+            &self.nodes,
+            &self.ops,
+            &self.plan,
+            &values,
+            &var_grads,
+            &dual_ops,
+            &mut grad,
+        )?;
+        Ok((value, grad))
+    }
+
+    /// Extracts all maximal sub-expressions that fulfill the predicate as standalone
+    /// expressions, e.g., to precompute the parts of a formula that only depend on some of
+    /// the variables. Sub-expressions are the expression itself, parenthesized
+    /// expressions, and arguments of unary operators together with the operators.
+    /// If a sub-expression is extracted, none of its own sub-expressions are considered.
+    ///
+    /// The variables of each extracted expression are re-indexed densely. Its
+    /// accompanying vector contains for each of its variables the index of the
+    /// corresponding variable of `self`.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*sin(y) + x")?;
+    /// let extracted = expr.extract_subexprs(|info| info.var_names == ["y"])?;
+    /// let (sin_y, mapping) = &extracted[0];
+    /// assert_eq!(format!("{}", sin_y), "sin(y)");
+    /// assert_eq!(mapping, &vec![1]);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If `self` has been `clear_deepex`ed, an [`ExError`](ExError) is returned.
+    ///
+    pub fn extract_subexprs<F>(&self, predicate: F) -> Result<Vec<(Self, Vec<usize>)>, ExError>
+    where
+        F: Fn(&SubExprInfo) -> bool,
+    {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        deepex
+            .extract_subexprs(&predicate)
+            .into_iter()
+            .map(|(sub, mapping)| {
+                Ok((
+                    Self::from_deepex(sub)?.with_make_ops(self.make_ops),
+                    mapping,
+                ))
+            })
+            .collect()
+    }
+
+    /// Fixes some variables to values and returns the resulting expression in the
+    /// remaining variables, e.g., for repeated evaluations with mostly constant variables.
+    /// Each binding consists of the index of a variable in the order of
+    /// [`var_names`](FlatEx::var_names) and its value. Constants are folded after
+    /// substituting the values and the remaining variables are re-indexed densely
+    /// keeping their order.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x*y + sin(z)")?;
+    /// let bound = expr.bind(&[(1, 2.0), (2, 0.0)])?;
+    /// assert_eq!(bound.var_names(), ["x"]);
+    /// assert!((bound.eval(&[1.5])? - 3.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if
+    /// * a variable index is out of range,
+    /// * a variable is bound more than once, or
+    /// * `self` has been `clear_deepex`ed.
+    ///
+    pub fn bind(&self, bindings: &[(usize, T)]) -> Result<Self, ExError> {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        let mut values: SmallVec<[_; N_VARS_ON_STACK]> = smallvec![None; self.n_unique_vars];
+        for (pos, (var_idx, value)) in bindings.iter().enumerate() {
+            match values.get_mut(*var_idx) {
+                None => {
+                    return Err(ExError::VarIdxOutOfRange {
+                        idx: *var_idx,
+                        n_vars: self.n_unique_vars,
+                    })
+                }
+                Some(Some(_)) => {
+                    let name = self.var_names[*var_idx].to_string();
+                    return Err(ExError::DuplicateVar {
+                        passed: bindings[..=pos]
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, (idx, _))| idx == var_idx)
+                            .map(|(pos, _)| (name.clone(), pos))
+                            .collect(),
+                        name,
+                    });
+                }
+                Some(slot) => *slot = Some(*value),
+            }
+        }
+        Ok(Self::from_deepex(deepex.bind(&values))?.with_make_ops(self.make_ops))
+    }
+
+    /// Like [`bind`](FlatEx::bind) but the variables are identified by their names under the
+    /// given [`NameResolution`](NameResolution) policy.
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if the passed names do not match the
+    /// variables of the expression under the policy or if `self` has been
+    /// `clear_deepex`ed.
+    ///
+    pub fn bind_named(
+        &self,
+        bindings: &[(&str, T)],
+        resolution: &NameResolution,
+    ) -> Result<Self, ExError> {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        let names = bindings
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        let values = resolution
+            .resolve(&self.var_names, &names)?
+            .iter()
+            .map(|pos| pos.map(|pos| bindings[pos].1))
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        Ok(Self::from_deepex(deepex.bind(&values))?.with_make_ops(self.make_ops))
+    }
+
+    /// Replaces each occurrence of the variable `var_name` by the expression `sub`, e.g.,
+    /// to fill placeholders of a template without concatenating and re-parsing strings.
+    /// The result has the remaining variables of `self` together with the variables of
+    /// `sub`. The replacement happens once, i.e., `sub` may contain `var_name` itself.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let template = parse_with_default_ops::<f64>("a*g + b")?;
+    /// let g = parse_with_default_ops::<f64>("sin(x)")?;
+    /// let expr = template.substitute("g", &g)?;
+    /// assert_eq!(expr.var_names(), ["a", "b", "x"]);
+    /// assert!((expr.eval(&[2.0, 1.0, 0.0])? - 1.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if `self` has no variable `var_name` or if one
+    /// of the expressions has been `clear_deepex`ed.
+    ///
+    pub fn substitute(&self, var_name: &str, sub: &Self) -> Result<Self, ExError> {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        let sub = sub.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        let var_idx = self.var_idx(var_name)?;
+        Ok(Self::from_deepex(deepex.substitute(var_idx, sub))?.with_make_ops(self.make_ops))
+    }
+
+    /// Returns the expression with the variables `layout` in the given order, e.g., to
+    /// evaluate many expressions with different variables directly on the same record of
+    /// values. The n-th value passed to [`eval`](FlatEx::eval) belongs to the n-th name of
+    /// `layout`. Values of names that do not occur in the expression are ignored.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let layout = ["price", "qty", "x"];
+    /// let total = parse_with_default_ops::<f64>("qty*price")?.align_vars(&layout)?;
+    /// let shifted = parse_with_default_ops::<f64>("x+1")?.align_vars(&layout)?;
+    /// let record = [2.5, 4.0, 1.0];
+    /// assert!((total.eval(&record)? - 10.0).abs() < 1e-12);
+    /// assert!((shifted.eval(&record)? - 2.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if
+    /// * a variable of the expression is not in `layout`,
+    /// * a name occurs more than once in `layout`, or
+    /// * `self` has been `clear_deepex`ed.
+    ///
+    pub fn align_vars(&self, layout: &[&'a str]) -> Result<Self, ExError> {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        for (pos, name) in layout.iter().enumerate() {
+            if let Some(first) = layout[..pos].iter().position(|other| other == name) {
+                return Err(ExError::DuplicateVar {
+                    name: name.to_string(),
+                    passed: vec![(name.to_string(), first), (name.to_string(), pos)],
+                });
+            }
+        }
+        if let Some(name) = self.var_names.iter().find(|name| !layout.contains(name)) {
+            return Err(ExError::MissingVar(name.to_string()));
+        }
+        let mut deepex = deepex.clone();
+        reset_vars(&mut deepex, layout.iter().copied().collect());
+        let aligned = Self::from_deepex(deepex)?.with_make_ops(self.make_ops);
+        Ok(match self.text {
+            Some(text) => aligned.with_text(text),
+            None => aligned,
+        })
+    }
+
+    /// Combines two expressions with the binary operator `repr`, which is one of `+`, `-`,
+    /// `*`, `/`, or `^`, from the operators `self` has been parsed with. Variables are
+    /// identified by their names, i.e., the resulting expression has the union of both
+    /// variable sets.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let sum = parse_with_default_ops::<f64>("x+y")?;
+    /// let prod = parse_with_default_ops::<f64>("y*z")?;
+    /// let expr = sum.combine(prod, "^")?;
+    /// assert_eq!(expr.var_names(), ["x", "y", "z"]);
+    /// assert!((expr.eval(&[1.0, 2.0, 0.5])? - 3.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    /// For `+`, `-`, `*`, and `/`, the operators of [`std::ops`](std::ops) are implemented
+    /// as well, e.g., `sum + prod`. They panic in case of an error.
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if one of the expressions has been
+    /// `clear_deepex`ed or if the operators of `self` do not contain `repr`.
+    ///
+    pub fn combine(self, other: Self, repr: &str) -> Result<Self, ExError> {
+        let make_ops = self.make_ops;
+        let deepex = self.deepex.ok_or(ExError::DeepExMissing)?;
+        let other = other.deepex.ok_or(ExError::DeepExMissing)?;
+        Ok(Self::from_deepex(deepex.try_operate_overloaded(other, repr)?)?.with_make_ops(make_ops))
+    }
+
+    /// Computes bounds `(lo, hi)` of the expression's values for variables that are within
+    /// the passed ranges, i.e., the n-th variable is between `var_ranges[n].0` and
+    /// `var_ranges[n].1`. The bounds are guaranteed to contain all values but are not
+    /// necessarily tight, e.g., for `x-x` and `x` in `[0, 1]` we obtain `(-1, 1)`.
+    ///
+    /// Division by an interval that contains zero as well as operations without finite
+    /// bounds, e.g., `tan` around its poles, result in `(-inf, inf)`. Arguments outside of
+    /// the domain of an operator, e.g., negative numbers for `sqrt`, result in `NaN` and
+    /// are left out. If a range contains no argument within the domain, the result is
+    /// `(-inf, inf)` as well.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("x^2 + 1")?;
+    /// assert_eq!(expr.eval_interval(&[(-2.0, 3.0)])?, (1.0, 10.0));
+    /// let expr = parse_with_default_ops::<f64>("1/x")?;
+    /// assert_eq!(expr.eval_interval(&[(-1.0, 1.0)])?, (f64::NEG_INFINITY, f64::INFINITY));
+    /// let expr = parse_with_default_ops::<f64>("sqrt(x)")?;
+    /// assert_eq!(expr.eval_interval(&[(-1.0, 4.0)])?, (0.0, 2.0));
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if
+    /// * the number of ranges does not match the number of variables,
+    /// * a range has a lower bound larger than its upper bound,
+    /// * `self` has been `clear_deepex`ed, or
+    /// * the expression contains an operator that is not one of the default operators.
+    ///
+    #[cfg(feature = "std")]
+    pub fn eval_interval(&self, var_ranges: &[(T, T)]) -> Result<(T, T), ExError>
+    where
+        T: Float,
+    {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        eval_interval(deepex, var_ranges)
+    }
+
+    /// Creates an expression string that corresponds to the `FlatEx` instance. This is
+    /// not necessarily the input string. More precisely,
+    /// * variable names are forgotten,
+    /// * variables are put into curly braces unless their names consist of ASCII letters,
+    ///   digits, and underscores, do not start with a digit, and would not be read as an
+    ///   operator,
+    /// * numbers without a spelling are written with [`Debug`](Debug), see
+    ///   [`unparse_with_display`](FlatEx::unparse_with_display),
+    /// * symbolic unary operators are written directly next to variables, numbers, and
+    ///   other prefix operators if this does not form another operator, and
+    /// * sub-expressions are only put between parentheses where operator priorities
+    ///   require them, such that unparsing the re-parsed expression yields the same
+    ///   string. Prefix operators with binary variants, such as `-`, are parenthesized if
+    ///   they are followed by a binary operator with a higher priority, since they bind
+    ///   weaker with
+    ///   [`unary_minus_binds_weaker_than_pow`](crate::ParseOptions::unary_minus_binds_weaker_than_pow),
+    ///   e.g.,
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let flatex = parse_with_default_ops::<f64>("-(-(sin(z)))*(x+y)")?;
+    /// assert_eq!(flatex.unparse()?, "(--sin(z))*(x+y)");
+    /// let flatex = parse_with_default_ops::<f64>("a+(b*c)-(-d)^2")?;
+    /// assert_eq!(flatex.unparse()?, "a+b*c-(-d)^2.0");
+    /// let flatex = parse_with_default_ops::<f64>("{x y}*{signal}*{sin}")?;
+    /// assert_eq!(flatex.unparse()?, "{x y}*signal*{sin}");
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn unparse(&self) -> Result<String, ExError> {
+        match &self.deepex {
+            Some(deepex) => Ok(deepex.unparse()),
+            None => Err(ExError::DeepExMissing),
+        }
+    }
+
+    /// Creates an expression string like [`unparse`](FlatEx::unparse) but writes numbers
+    /// that have no spelling, e.g., folded constants, with [`Display`](Display) instead of
+    /// [`Debug`](Debug). This is meant for number types whose `Debug` representation cannot
+    /// be parsed, e.g., derived for a newtype. For the result to parse again, the
+    /// [`Display`](Display) implementation needs to write numbers that are matched by the
+    /// number pattern the expression has been parsed with and that are read back by
+    /// [`FromStr`](core::str::FromStr).
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let ops = make_default_operators_int::<i32>();
+    /// let flatex = parse_with_number_pattern::<i32>("x*(2+3)", &ops, "[0-9]+")?;
+    /// assert_eq!(flatex.unparse_with_display()?, "x*5");
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If `self` has been `clear_deepex`ed, we return an [`ExError`](ExError).
+    ///
+    pub fn unparse_with_display(&self) -> Result<String, ExError>
+    where
+        T: Display,
+    {
+        match &self.deepex {
+            Some(deepex) => Ok(deepex.unparse_with_display()),
+            None => Err(ExError::DeepExMissing),
+        }
+    }
+    /// Returns whether both expressions consist of the same operators with respect to their
+    /// representations, the same numbers, and the same variables in the same nesting of
+    /// parentheses. If `ignore_var_names` is true, variables are identified by the order
+    /// of their first occurrences, e.g., `x+1` and `y+1` are structure-equal but `{a}+{a}`
+    /// and `{a}+{b}` are not.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("sin(x)*y")?;
+    /// let renamed = parse_with_default_ops::<f64>("sin(a)*b")?;
+    /// assert!(expr.structure_eq(&renamed, true)?);
+    /// assert!(!expr.structure_eq(&renamed, false)?);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the deep expression of one of the expressions has been
+    /// [cleared](FlatEx::clear_deepex), we return an [`ExError`](ExError).
+    ///
+    pub fn structure_eq(
+        &self,
+        other: &FlatEx<T, N_NODES>,
+        ignore_var_names: bool,
+    ) -> Result<bool, ExError> {
+        Ok(unpack_deepex(self)?.structure_string(ignore_var_names)
+            == unpack_deepex(other)?.structure_string(ignore_var_names))
+    }
+
+    /// Returns a hash that is equal for [structure-equal](FlatEx::structure_eq) expressions,
+    /// e.g., to deduplicate expressions. The hash is not guaranteed to be stable across
+    /// versions of Rust or of this crate.
+    ///
+    /// # Errors
+    ///
+    /// If the deep expression has been [cleared](FlatEx::clear_deepex), we return an
+    /// [`ExError`](ExError).
+    ///
+    #[cfg(feature = "std")]
+    pub fn structure_hash(&self, ignore_var_names: bool) -> Result<u64, ExError> {
+        let mut hasher = DefaultHasher::new();
+        unpack_deepex(self)?
+            .structure_string(ignore_var_names)
+            .hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Evaluates sub-expressions that occur more than once, e.g., `sin(x)` in
+    /// `sin(x)*sin(x)+cos(x)*cos(x)`, only once per evaluation. Sub-expressions are
+    /// identified by their structure, i.e., by their numbers, variables, and operators. The
+    /// search takes time and the stored values have to be copied during evaluation.
+    /// Hence, the optimization is not applied by default and pays off for expressions with
+    /// repeated expensive parts. Expressions that are created from the result, e.g., with
+    /// [`partial`](FlatEx::partial) or [`bind`](FlatEx::bind), are not optimized.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("sin(x)*sin(x)+cos(x)*cos(x)")?;
+    /// assert_eq!(expr.total_unary_applications(), 4);
+    /// let expr = expr.optimize_cse()?;
+    /// assert_eq!(expr.total_unary_applications(), 2);
+    /// assert!((expr.eval(&[0.7])? - 1.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the deep expression has been [cleared](FlatEx::clear_deepex), we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn optimize_cse(self) -> Result<Self, ExError> {
+        let n_vars = self.n_unique_vars;
+        let deepex = self.deepex.ok_or(ExError::DeepExMissing)?;
+        let mut op_reprs = vec![];
+        let (nodes, ops, plan, tmps) = {
+            // sub-expressions of larger ones need to be evaluated first
+            let subexprs = common_subexprs(&deepex);
+            let tmp_slots = subexprs
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(i, (structure, _))| (structure.clone(), n_vars + i))
+                .collect::<Vec<_>>();
+            let tmps = subexprs
+                .iter()
+                .rev()
+                .map(|(_, sub)| {
+                    let (nodes, ops, plan) = flatten_with_plan(sub, &mut op_reprs, &tmp_slots)?;
+                    Ok(FlatArg { nodes, ops, plan })
+                })
+                .collect::<Result<_, ExError>>()?;
+            let (nodes, ops, plan) = flatten_with_plan(&deepex, &mut op_reprs, &tmp_slots)?;
+            (nodes, ops, plan, tmps)
+        };
+        let fallible = fallible_by_repr_id(&op_reprs, |repr| deepex.fallible_op(repr));
+        Ok(FlatEx {
+            nodes,
+            ops,
+            plan,
+            tmps,
+            op_reprs,
+            fallible,
+            deepex: Some(deepex),
+            ..self
+        })
+    }
+
+    /// Usually, a `FlatEx` instance keeps a nested, deep structure of the expression
+    /// that is not necessary for evaluation. This functions removes the deep expression
+    /// to reduce memory consumption. [`unparse`](FlatEx::unparse) will stop working after
+    /// calling this function, the [`Display`](std::fmt::Display) implementation only for
+    /// expressions that have not been parsed.
+    pub fn clear_deepex(&mut self) {
+        self.deepex = None;
+    }
+
+    /// Returns approximately the number of bytes the expression occupies including its
+    /// heap allocations, e.g., to measure the effect of
+    /// [`clear_deepex`](FlatEx::clear_deepex) or of the conversion to an
+    /// [`OwnedFlatEx`](OwnedFlatEx). Borrowed strings, i.e., the parsed string, the
+    /// variable names, and the operator representations, are not included.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let mut expr = parse_with_default_ops::<f64>("sin(x)*price+price^2")?;
+    /// let footprint = expr.memory_footprint();
+    /// expr.clear_deepex();
+    /// assert!(expr.memory_footprint() < footprint);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Self>()
+            + flat_heap_bytes(&self.nodes, &self.ops, &self.plan)
+            + args_heap_bytes(&self.tmps)
+            + spilled_bytes(&self.var_names)
+            + self.op_reprs.capacity() * mem::size_of::<&str>()
+            + self.fallible.capacity() * mem::size_of::<Option<FallibleOp<T>>>()
+            + self.deepex.as_ref().map_or(0, DeepEx::heap_bytes)
+    }
+
+    /// Moves the nodes and operators to vectors that keep up to `M` elements on the stack,
+    /// see [`LargeFlatEx`](LargeFlatEx). Elements that are already on the heap stay there.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::{prelude::*, LargeFlatEx};
+    /// let text = (0..100).map(|i| format!("{}*x", i)).collect::<Vec<_>>().join("+");
+    /// let expr: LargeFlatEx<f64> = parse_with_default_ops(&text)?.with_nodes_on_stack();
+    /// assert!((expr.eval(&[1.0])? - 4950.0).abs() < 1e-12);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_nodes_on_stack<const M: usize>(self) -> FlatEx<'a, T, M> {
+        FlatEx {
+            nodes: SmallVec::from_vec(self.nodes.into_vec()),
+            ops: SmallVec::from_vec(self.ops.into_vec()),
+            plan: SmallVec::from_vec(self.plan.into_vec()),
+            tmps: self.tmps,
+            n_unique_vars: self.n_unique_vars,
+            var_names: self.var_names,
+            var_order: self.var_order,
+            op_reprs: self.op_reprs,
+            fallible: self.fallible,
+            deepex: self.deepex,
+            text: self.text,
+            make_ops: self.make_ops,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, T: Copy + Debug, const N_NODES: usize> FlatEx<'a, T, N_NODES> {
+    /// Converts the numbers with `convert` and replaces the operators by the default
+    /// operators of `U` with the same representations.
+    fn with_default_ops<U: crate::DefaultOpsFloat + Debug>(
+        &self,
+        convert: fn(T) -> U,
+    ) -> Result<FlatEx<'a, U, N_NODES>, ExError> {
+        let deepex = self.deepex.as_ref().ok_or(ExError::DeepExMissing)?;
+        let converted =
+            FlatEx::from_deepex(deepex.with_ops(&make_default_operators::<U>(), convert)?)?;
+        Ok(match self.text {
+            Some(text) => converted.with_text(text),
+            None => converted,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const N_NODES: usize> FlatEx<'a, f64, N_NODES> {
+    /// Converts the expression to `f32`, e.g., for computations that are faster in single
+    /// precision. The operators are replaced by the
+    /// [default operators](make_default_operators) of `f32` with the same representations.
+    /// Common sub-expressions of [`optimize_cse`](FlatEx::optimize_cse) are not kept.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::prelude::*;
+    /// let expr = parse_with_default_ops::<f64>("2*sin(x)^2")?;
+    /// let expr_f32 = expr.to_f32()?;
+    /// assert!((expr_f32.eval(&[0.5f32])? - 0.45969772).abs() < 1e-6);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the expression contains an operator that is not one of the default operators,
+    /// we return an [`ExError::OperatorNotFound`](ExError::OperatorNotFound). Operators
+    /// that replace a default operator with the same representation are converted to the
+    /// default operator. Further, the deep expression is needed, see
+    /// [`clear_deepex`](FlatEx::clear_deepex).
+    ///
+    pub fn to_f32(&self) -> Result<FlatEx<'a, f32, N_NODES>, ExError> {
+        self.with_default_ops(|x| x as f32)
+    }
+
+    /// Converts the expression like [`to_f32`](FlatEx::to_f32) to SIMD vectors that evaluate
+    /// 4 values of each variable at once, see the module [`simd`](crate::simd).
+    ///
+    /// # Errors
+    ///
+    /// See [`to_f32`](FlatEx::to_f32).
+    ///
+    #[cfg(feature = "simd")]
+    pub fn to_f64x4(&self) -> Result<FlatEx<'a, F64x4, N_NODES>, ExError> {
+        self.with_default_ops(F64x4::splat)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const N_NODES: usize> FlatEx<'a, f32, N_NODES> {
+    /// Converts the expression to `f64` like [`to_f32`](FlatEx::to_f32) the other way
+    /// round.
+    ///
+    /// # Errors
+    ///
+    /// See [`to_f32`](FlatEx::to_f32).
+    ///
+    pub fn to_f64(&self) -> Result<FlatEx<'a, f64, N_NODES>, ExError> {
+        self.with_default_ops(f64::from)
+    }
+
+    /// Converts the expression to SIMD vectors with 8 lanes like
+    /// [`to_f64x4`](FlatEx::to_f64x4).
+    ///
+    /// # Errors
+    ///
+    /// See [`to_f32`](FlatEx::to_f32).
+    ///
+    #[cfg(feature = "simd")]
+    pub fn to_f32x8(&self) -> Result<FlatEx<'a, F32x8, N_NODES>, ExError> {
+        self.with_default_ops(F32x8::splat)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const N_NODES: usize> TryFrom<FlatEx<'a, f64, N_NODES>> for FlatEx<'a, f32, N_NODES> {
+    type Error = ExError;
+    fn try_from(flatex: FlatEx<'a, f64, N_NODES>) -> Result<Self, ExError> {
+        flatex.to_f32()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const N_NODES: usize> TryFrom<FlatEx<'a, f32, N_NODES>> for FlatEx<'a, f64, N_NODES> {
+    type Error = ExError;
+    fn try_from(flatex: FlatEx<'a, f32, N_NODES>) -> Result<Self, ExError> {
+        flatex.to_f64()
+    }
+}
+
+/// Flat expression that stores its nodes and operators on the heap. By default, a
+/// [`FlatEx`](FlatEx) keeps up to 32 nodes and operators on the stack, which makes small
+/// expressions fast to create and to evaluate but adds several kilobytes to each
+/// expression that are unused for larger ones. Expressions with hundreds of nodes, e.g.,
+/// generated ones, are cheaper to move and to clone as `LargeFlatEx`, see
+/// [`with_nodes_on_stack`](FlatEx::with_nodes_on_stack). The arguments of n-ary operators
+/// and the common sub-expressions of [`optimize_cse`](FlatEx::optimize_cse) are not
+/// affected.
+pub type LargeFlatEx<'a, T> = FlatEx<'a, T, 0>;
+
+/// Expression with a number of variables `N` that has been checked by
+/// [`try_bind_arity`](FlatEx::try_bind_arity).
+#[derive(Clone, Copy, Debug)]
+pub struct BoundEx<'b, 'a, T: Copy + Debug, const N: usize, const N_NODES: usize = N_NODES_ON_STACK>
+{
+    flatex: &'b FlatEx<'a, T, N_NODES>,
+}
+
+impl<'b, 'a, T: Copy + Debug, const N: usize, const N_NODES: usize> BoundEx<'b, 'a, T, N, N_NODES> {
+    /// Evaluates the expression, see [`eval`](FlatEx::eval). Since the number of variables
+    /// has been checked before, this cannot fail. Like
+    /// [`eval_unchecked`](FlatEx::eval_unchecked), it applies the infallible counterparts
+    /// of [fallible operations](FallibleOp).
+    pub fn eval(&self, vars: &[T; N]) -> T {
+        self.flatex.eval_unchecked(vars)
+    }
+}
+
+impl<'a, T: Copy + Debug, const N_NODES: usize> Add for FlatEx<'a, T, N_NODES> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        self.combine(other, "+").unwrap()
+    }
+}
+
+impl<'a, T: Copy + Debug, const N_NODES: usize> Sub for FlatEx<'a, T, N_NODES> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        self.combine(other, "-").unwrap()
+    }
+}
+
+impl<'a, T: Copy + Debug, const N_NODES: usize> Mul for FlatEx<'a, T, N_NODES> {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        self.combine(other, "*").unwrap()
+    }
+}
+
+impl<'a, T: Copy + Debug, const N_NODES: usize> Div for FlatEx<'a, T, N_NODES> {
+    type Output = Self;
+    fn div(self, other: Self) -> Self {
+        self.combine(other, "/").unwrap()
+    }
+}
+
+/// The expression is displayed as a string created by [`unparse`](FlatEx::unparse).
+/// Parsed expressions are displayed as the string they have been parsed from. Others, e.g.,
+/// combined expressions, are displayed as the string created by
+/// [`unparse`](FlatEx::unparse), or as the error message of `unparse` if the deep
+/// expression has been [cleared](FlatEx::clear_deepex).
+impl<'a, T: Copy + Debug, const N_NODES: usize> Display for FlatEx<'a, T, N_NODES> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match (self.text, &self.deepex) {
+            (Some(text), _) => f.write_str(text),
+            (None, Some(deepex)) => f.write_str(&deepex.unparse()),
+            (None, None) => write!(f, "{}", ExError::DeepExMissing),
+        }
+    }
+}
+
+/// Expression that can be evaluated like a [`FlatEx`](FlatEx) but does not borrow from the
+/// parsed string or the operators, e.g., to store expressions parsed from temporary
+/// strings. Variable names and the unparsed string are copied, the function pointers of
+/// the operators are kept as they are. Hence, evaluation is as fast as with a
+/// [`FlatEx`](FlatEx). Variable names are interned, i.e., all owned expressions that
+/// refer to a variable `price` share one allocation of its name. Clones share the unparsed
+/// string as well.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let exprs = ["x*2", "sin(y)"]
+///     .iter()
+///     .map(|text| {
+///         let text = text.to_string();
+///         let expr = OwnedFlatEx::from_flatex(parse_with_default_ops::<f64>(&text)?);
+///         Ok(expr)
+///     })
+///     .collect::<Result<Vec<_>, exmex::ExError>>()?;
+/// assert!((exprs[0].eval(&[1.5])? - 3.0).abs() < 1e-12);
+/// assert_eq!(exprs[1].var_names(), ["y"]);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// The deep structure of the expression is not kept. Hence, e.g., partial derivatives are
+/// not available and need to be computed before the conversion. Neither are
+/// [fallible operations](FallibleOp), evaluation applies their infallible counterparts.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct OwnedFlatEx<T: Copy + Debug, const N_NODES: usize = N_NODES_ON_STACK> {
+    nodes: FlatNodeVec<T, N_NODES>,
+    ops: FlatOpVec<T, N_NODES>,
+    plan: EvalPlan<N_NODES>,
+    tmps: Vec<FlatArg<T>>,
+    n_unique_vars: usize,
+    var_names: Vec<Arc<str>>,
+    unparsed: Option<Arc<str>>,
+}
+
+impl<T: Copy + Debug, const N_NODES: usize> OwnedFlatEx<T, N_NODES> {
+    /// Copies everything needed for evaluation from `flatex`.
+    pub fn from_flatex(flatex: FlatEx<T, N_NODES>) -> Self {
+        OwnedFlatEx {
+            unparsed: flatex.unparse().ok().map(Arc::from),
+            var_names: flatex.var_names.iter().map(|name| intern(name)).collect(),
+            nodes: flatex.nodes,
+            ops: flatex.ops,
+            plan: flatex.plan,
+            tmps: flatex.tmps,
+            n_unique_vars: flatex.n_unique_vars,
+        }
+    }
+
+    /// Evaluates the expression, see [`FlatEx::eval`](FlatEx::eval).
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables in the parsed expression are different from the length of
+    /// the variable slice, we return an [`ExError`](ExError).
+    ///
+    pub fn eval(&self, vars: &[T]) -> Result<T, ExError> {
+        if self.n_unique_vars != vars.len() {
+            return Err(ExError::WrongNumberOfVars {
+                expected: self.n_unique_vars,
+                given: vars.len(),
+            });
+        }
+        Ok(eval_flat_with_tmps(
+            &self.nodes,
+            &self.ops,
+            &self.plan,
+            &self.tmps,
+            vars,
+            self.n_unique_vars,
+        ))
+    }
+
+    /// Returns the names of the variables, see [`FlatEx::var_names`](FlatEx::var_names).
+    pub fn var_names(&self) -> Vec<&str> {
+        self.var_names.iter().map(|name| &**name).collect()
+    }
+
+    /// Returns the string of [`FlatEx::unparse`](FlatEx::unparse) at the time of the
+    /// conversion.
+    ///
+    /// # Errors
+    ///
+    /// If the deep expression had been removed before the conversion, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn unparse(&self) -> Result<String, ExError> {
+        self.unparsed
+            .as_deref()
+            .map(str::to_string)
+            .ok_or(ExError::DeepExMissing)
+    }
+
+    /// Returns approximately the number of bytes the expression occupies, see
+    /// [`FlatEx::memory_footprint`](FlatEx::memory_footprint). Names and the unparsed
+    /// string are shared. Each of their owners is accounted for an equal part of them such
+    /// that the footprints of many expressions add up to the memory they occupy together.
+    pub fn memory_footprint(&self) -> usize {
+        mem::size_of::<Self>()
+            + flat_heap_bytes(&self.nodes, &self.ops, &self.plan)
+            + args_heap_bytes(&self.tmps)
+            + self.var_names.capacity() * mem::size_of::<Arc<str>>()
+            + self.var_names.iter().map(shared_bytes).sum::<usize>()
+            + self.unparsed.as_ref().map_or(0, shared_bytes)
+    }
+
+    /// Turns the expression into a closure, see [`FlatEx::into_fn`](FlatEx::into_fn).
+    ///
+    /// # Errors
+    ///
+    /// If the number of variables of the expression is not `N`, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn into_fn<const N: usize>(
+        self,
+    ) -> Result<impl Fn([T; N]) -> T + Clone + Send + Sync + 'static, ExError>
+    where
+        T: Send + Sync + 'static,
+    {
+        if self.n_unique_vars != N {
+            return Err(ExError::WrongNumberOfVars {
+                expected: self.n_unique_vars,
+                given: N,
+            });
+        }
+        Ok(move |vars: [T; N]| {
+            eval_flat_with_tmps(&self.nodes, &self.ops, &self.plan, &self.tmps, &vars, N)
+        })
+    }
+}
+
+impl<'a, T: Copy + Debug, const N_NODES: usize> From<FlatEx<'a, T, N_NODES>>
+    for OwnedFlatEx<T, N_NODES>
+{
+    fn from(flatex: FlatEx<'a, T, N_NODES>) -> Self {
+        OwnedFlatEx::from_flatex(flatex)
+    }
+}
+
+/// The expression is displayed as a string created by [`unparse`](OwnedFlatEx::unparse).
+impl<T: Copy + Debug, const N_NODES: usize> Display for OwnedFlatEx<T, N_NODES> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.unparse() {
+            Err(e) => write!(f, "{}", e),
+            Ok(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{expression::deep::UnaryOpWithReprs, operators::VecOfUnaryFuncs};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_operate_unary() {
+    let lstr = "x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)+x+y+x+z*(-y)";
+    let deepex = DeepEx::<f64>::from_str(lstr).unwrap();
+    let mut funcs = VecOfUnaryFuncs::new();
+    funcs.push(|x: f64| x * 1.23456);
+    let deepex = deepex.operate_unary(UnaryOpWithReprs::prefix(
+        vec!["eagle"],
+        UnaryOp::from_vec(funcs),
+    ));
+    let flatex = flatten(deepex).unwrap();
+    assert_float_eq_f64(
+        flatex.eval(&[1.0, 1.75, 2.25]).unwrap(),
+        -0.23148000000000002 * 8.0,
+    );
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{
+    parse_with_default_ops, parse_with_options, util::assert_float_eq_f64, ExprBuilder,
+    ParseOptions,
+};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_flat_clear() {
+    let mut flatex = parse_with_default_ops::<f64>("x*(2*(2*(2*4*8)))").unwrap();
+    assert!(flatex.deepex.is_some());
+    flatex.clear_deepex();
+    assert!(flatex.deepex.is_none());
+    assert_float_eq_f64(flatex.eval(&[1.0]).unwrap(), 2.0 * 2.0 * 2.0 * 4.0 * 8.0);
+    assert_eq!(flatex.nodes.len(), 2);
+}
+#[cfg(feature = "std")]
+#[test]
+fn test_flat_compile() {
+    let flatex = parse_with_default_ops::<f64>("1*sin(2-0.1)").unwrap();
+    assert_float_eq_f64(flatex.eval(&[]).unwrap(), 1.9f64.sin());
+    assert_eq!(flatex.nodes.len(), 1);
+
+    let flatex = parse_with_default_ops::<f64>("x*(2*(2*(2*4*8)))").unwrap();
+    assert_float_eq_f64(flatex.eval(&[1.0]).unwrap(), 2.0 * 2.0 * 2.0 * 4.0 * 8.0);
+    assert_eq!(flatex.nodes.len(), 2);
+
+    let flatex = parse_with_default_ops::<f64>("1*sin(2-0.1) + x").unwrap();
+    assert_float_eq_f64(flatex.eval(&[0.0]).unwrap(), 1.9f64.sin());
+    assert_eq!(flatex.nodes.len(), 2);
+    match flatex.nodes[0].kind {
+        FlatNodeKind::Num(n) => assert_float_eq_f64(n, 1.9f64.sin()),
+        _ => unreachable!(),
+    }
+    match flatex.nodes[1].kind {
+        FlatNodeKind::Var(idx) => assert_eq!(idx, 0),
+        _ => unreachable!(),
+    }
+
+    let flatex = parse_with_default_ops::<f64>("y + 1 - cos(1/(1*sin(2-0.1))-2) + 2 + x").unwrap();
+    assert_eq!(flatex.nodes.len(), 3);
+    match flatex.nodes[0].kind {
+        FlatNodeKind::Var(idx) => assert_eq!(idx, 1),
+        _ => unreachable!(),
     }
     match flatex.nodes[1].kind {
         FlatNodeKind::Num(_) => (),
-        _ => assert!(false),
+        _ => unreachable!(),
+    }
+    match flatex.nodes[2].kind {
+        FlatNodeKind::Var(idx) => assert_eq!(idx, 0),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(all(debug_assertions, feature = "std"))]
+#[test]
+fn test_eval_order_cross_check() {
+    let strict = ParseOptions {
+        semantics: crate::Semantics::IeeeStrict,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    // equal priorities, nesting, and constant pairs of nested expressions
+    let texts = [
+        "a-b+c-d",
+        "a/b*c/d*e",
+        "a-(b-(c-d))*e",
+        "(a+b)*(c+d)^2-e/(a-b)",
+        "a+(b*2)+3-c",
+        "-(a+b)^2*-c",
+        "logb(a-b+c, (c*2)*3)+a",
+    ];
+    for text in texts.iter() {
+        for options in [ParseOptions::default(), strict].iter() {
+            let flatex = parse_with_options(text, &ops, options).unwrap();
+            let deepex = flatex.deepex.as_ref().unwrap();
+            check_eval_order(deepex, &flatex.nodes, &flatex.ops, &flatex.plan).unwrap();
+            let n_vars = flatex.var_names().len();
+            flatex.eval(&[0.5, 1.5, -2.0, 3.0, 0.25][..n_vars]).unwrap();
+        }
     }
-    match flatex.nodes[2].kind {
-        FlatNodeKind::Var(idx) => assert_eq!(idx, 0),
-        _ => assert!(false),
+    // the constant pair in the nested expression is applied first only with fast semantics
+    let order = |text, options| {
+        let flatex = parse_with_options(text, &ops, options).unwrap();
+        flatex.execution_order()
+    };
+    let fast = ParseOptions::default();
+    assert_eq!(order("a+(b*2)+3", &fast), [1, 2, 0]);
+    assert_eq!(order("a+(b*2)+3", &strict), [1, 0, 2]);
+    // constant pairs only break ties among equal priorities
+    assert_eq!(order("x^y*3*4", &fast), [0, 2, 1]);
+    assert_eq!(order("x^y*3*4", &strict), [0, 1, 2]);
+    assert_eq!(order("2*3^x+4", &fast), [1, 0, 2]);
+
+    // also for the highest priorities, where scaling the priority would overflow
+    let flatex = parse_with_options("x^y*3*4", &ops, &fast).unwrap();
+    let deepex = unpack_deepex(&flatex).unwrap();
+    let mut prios = vec![];
+    collect_prios(deepex, &mut prios);
+    prios.sort_unstable();
+    prios.dedup();
+    let mut nodes = FlatNodeVec::<_>::new();
+    let mut flat_ops = FlatOpVec::<_>::new();
+    let mut op_reprs = vec![];
+    let prio_offset = i64::MAX - prios.len() as i64;
+    flatten_vecs(
+        deepex,
+        &prios,
+        prio_offset,
+        &mut op_reprs,
+        &[],
+        &mut nodes,
+        &mut flat_ops,
+        &mut vec![],
+        0,
+    )
+    .unwrap();
+    let indices = prioritized_indices_flat(&flat_ops, &nodes, &op_reprs, deepex.optimizations());
+    assert_eq!(indices.as_slice(), [0, 2, 1]);
+
+    let mut swapped = parse_with_default_ops::<f64>("a-b+c").unwrap();
+    swapped.plan.swap(0, 1);
+    let deepex = swapped.deepex.as_ref().unwrap();
+    assert!(check_eval_order(deepex, &swapped.nodes, &swapped.ops, &swapped.plan).is_err());
+    let mut shifted = parse_with_default_ops::<f64>("a*(b+c)").unwrap();
+    shifted.plan[1].right += 1;
+    let deepex = shifted.deepex.as_ref().unwrap();
+    assert!(check_eval_order(deepex, &shifted.nodes, &shifted.ops, &shifted.plan).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_operator_overloading() {
+    fn from_str(text: &str) -> DeepEx<'_, f64> {
+        DeepEx::from_str(text).unwrap()
+    }
+    fn eval<'a>(deepex: &DeepEx<'a, f64>, vars: &[f64], val: f64) {
+        assert_float_eq_f64(flatten(deepex.clone()).unwrap().eval(vars).unwrap(), val);
+    }
+
+    let one = from_str("1");
+    let two = one.clone() + one.clone();
+    eval(&two, &[], 2.0);
+
+    let x_squared = from_str("x*x");
+    let two_x_squared = two.clone() * x_squared.clone();
+    eval(&two_x_squared, &[0.0], 0.0);
+    eval(&two_x_squared, &[1.0], 2.0);
+    eval(&two_x_squared, &[2.0], 8.0);
+    eval(&two_x_squared, &[3.0], 18.0);
+    let some_expr = from_str("x") + from_str("x") * from_str("2") / from_str("x^(.5)");
+    eval(&some_expr, &[4.0], 8.0);
+
+    let x_plus_y_plus_z = from_str("x+y+z");
+    let y_minus_z = from_str("y-z");
+    let prod_of_above = x_plus_y_plus_z.clone() * y_minus_z.clone();
+    eval(&prod_of_above, &[1.0, 4.0, 8.0], -52.0);
+    let div_of_above = x_plus_y_plus_z.clone() / y_minus_z.clone();
+    eval(&div_of_above, &[1.0, 4.0, 8.0], -3.25);
+    let sub_of_above = x_plus_y_plus_z.clone() - y_minus_z.clone();
+    eval(&sub_of_above, &[1.0, 4.0, 8.0], 17.0);
+    let add_of_above = x_plus_y_plus_z + y_minus_z.clone();
+    eval(&add_of_above, &[1.0, 4.0, 8.0], 9.0);
+    let x_plus_cossin_y_plus_z = from_str("x+cos(sin(y+z))");
+    let prod_of_above = x_plus_cossin_y_plus_z * y_minus_z;
+    eval(&prod_of_above, &[1.0, 4.0, 8.0], -7.4378625090980925);
+}
+#[cfg(feature = "std")]
+#[test]
+fn test_display() {
+    let mut flatex = flatten(DeepEx::<f64>::from_str("sin(var)/5").unwrap()).unwrap();
+    assert_eq!(format!("{}", flatex), "sin(var)/5.0");
+    flatex.clear_deepex();
+    assert_eq!(flatex.unparse(), Err(ExError::DeepExMissing));
+    assert_eq!(format!("{}", flatex), ExError::DeepExMissing.to_string());
+
+    // parsed expressions are displayed as their input
+    let text = "sin( var )/(5)";
+    let mut flatex = parse_with_default_ops::<f64>(text).unwrap();
+    assert_eq!(flatex.text(), Some(text));
+    assert_eq!(format!("{}", flatex), text);
+    assert_eq!(flatex.unparse().unwrap(), "sin(var)/5.0");
+    flatex.clear_deepex();
+    assert_eq!(format!("{}", flatex), text);
+
+    // combined expressions fall back to unparse
+    let composed = parse_with_default_ops::<f64>("x + 1").unwrap()
+        * parse_with_default_ops::<f64>("(y)").unwrap();
+    assert_eq!(composed.text(), None);
+    assert_eq!(format!("{}", composed), "(x+1.0)*y");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse() {
+    fn test(text: &str, text_ref: &str) {
+        let flatex = flatten(DeepEx::<f64>::from_str(text).unwrap()).unwrap();
+        let deepex = flatex.deepex.unwrap();
+        assert_eq!(deepex.unparse(), text_ref);
+        let mut flatex_reparsed = flatten(DeepEx::<f64>::from_str(text).unwrap()).unwrap();
+        assert_eq!(flatex_reparsed.unparse().unwrap(), text_ref);
+        flatex_reparsed.clear_deepex();
+        assert!(flatex_reparsed.unparse().is_err());
+    }
+    let text = "5+x";
+    let text_ref = "5.0+x";
+    test(text, text_ref);
+    let text = "sin(5+var)^(1/{y})+{var}";
+    let text_ref = "sin(5.0+var)^(1.0/y)+var";
+    test(text, text_ref);
+    let text = "-(5+var)^(1/{y})+{var}";
+    let text_ref = "(-(5.0+var))^(1.0/y)+var";
+    test(text, text_ref);
+    let text = "cos(sin(-(5+var)^(1/{y})))+{var}";
+    let text_ref = "cos(sin((-(5.0+var))^(1.0/y)))+var";
+    test(text, text_ref);
+    let text = "cos(sin(-5+var^(1/{y})))-{var}";
+    let text_ref = "cos(sin(-5.0+var^(1.0/y)))-var";
+    test(text, text_ref);
+    let text = "cos(sin(-z+var*(1/{y})))+{var}";
+    let text_ref = "cos(sin(-z+var*(1.0/y)))+var";
+    test(text, text_ref);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_derivatives_roundtrip() {
+    // prefix operators in front of powers are parenthesized such that the unparsed string
+    // has the same value if unary minus binds weaker than powers
+    let pow_first = ParseOptions {
+        unary_minus_binds_weaker_than_pow: true,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    for text in ["cos(x)^2", "-x^2", "-(x+1)^2", "-sin(x)^2", "2^-x^2"] {
+        let expr = parse_with_default_ops::<f64>(text).unwrap();
+        let d_x = expr.clone().partial(0).unwrap();
+        let dd_x = d_x.clone().partial(0).unwrap();
+        for der in [expr, d_x, dd_x] {
+            let unparsed = der.unparse().unwrap();
+            let reparsed = parse_with_default_ops::<f64>(&unparsed).unwrap();
+            let reparsed_pow_first = parse_with_options(&unparsed, &ops, &pow_first).unwrap();
+            assert_eq!(reparsed.unparse().unwrap(), unparsed);
+            // constant derivatives lose the variable when they are unparsed
+            let n_vars = reparsed.var_names().len();
+            for x in [-1.3, 0.4, 2.7] {
+                let value = der.eval(&[x]).unwrap();
+                assert_float_eq_f64(reparsed.eval(&[x][..n_vars]).unwrap(), value);
+                assert_float_eq_f64(reparsed_pow_first.eval(&[x][..n_vars]).unwrap(), value);
+            }
+        }
+    }
+    let d_x = parse_with_default_ops::<f64>("-x^3")
+        .unwrap()
+        .partial(0)
+        .unwrap();
+    assert!(d_x.unparse().unwrap().contains("(-x)^"));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_custom_prios() {
+    // parentheses around prefix operators depend on the priorities of the operators the
+    // expression has been parsed with and not on the default priorities
+    let ops = [
+        Operator::make_bin(
+            "^",
+            BinOp {
+                apply: |a: f64, b| a.powf(b),
+                prio: 2,
+            },
+        ),
+        Operator::make_bin(
+            "*",
+            BinOp {
+                apply: |a, b| a * b,
+                prio: 1,
+            },
+        ),
+        Operator::make_bin_unary(
+            "-",
+            BinOp {
+                apply: |a, b| a - b,
+                prio: 0,
+            },
+            |a| -a,
+        ),
+    ];
+    let pow_first = ParseOptions {
+        unary_minus_binds_weaker_than_pow: true,
+        ..ParseOptions::default()
+    };
+    let test = |text: &str, reference: &str, value: f64| {
+        let expr = parse_with_options(text, &ops, &pow_first).unwrap();
+        assert_float_eq_f64(expr.eval(&[3.0]).unwrap(), value);
+        let unparsed = expr.unparse().unwrap();
+        assert_eq!(unparsed, reference);
+        let reparsed = parse_with_options(&unparsed, &ops, &pow_first).unwrap();
+        assert_float_eq_f64(reparsed.eval(&[3.0]).unwrap(), value);
+    };
+    test("(2^-x)*3", "(2.0^-x)*3.0", 0.375);
+    test("2^-x*3", "2.0^-(x*3.0)", 2f64.powf(-9.0));
+    test("(-x)^2", "(-x)^2.0", 9.0);
+    test("2^(-x)*3", "2.0^(-x)*3.0", 0.375);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_var_names() {
+    let texts = [
+        "x^2+y",
+        "{x y}*sin(_a1)",
+        "{signal}+{2x}-{π}",
+        "-(-(x))+{exp_x}*{expx}",
+        "abs(x_1)/{x+y}",
+    ];
+    for text in texts {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        let unparsed = flatex.unparse().unwrap();
+        let reparsed = parse_with_default_ops::<f64>(&unparsed).unwrap();
+        assert!(flatex.structure_eq(&reparsed, false).unwrap());
+        assert_eq!(flatex.var_names(), reparsed.var_names());
+    }
+    let unparse = |text| {
+        parse_with_default_ops::<f64>(text)
+            .unwrap()
+            .unparse()
+            .unwrap()
+    };
+    assert_eq!(unparse("x^2+y"), "x^2.0+y");
+    assert_eq!(unparse("{x y}*_a1"), "{x y}*_a1");
+    // names of operators and names that start with a symbolic operator or a digit need
+    // braces, names that only start with the name of an operator do not
+    assert_eq!(unparse("{signal}+{2x}+{π}"), "signal+{2x}+{π}");
+    assert_eq!(unparse("{exp_x}*{expx}"), "exp_x*expx");
+    assert_eq!(unparse("{exp}*{-x}"), "{exp}*{-x}");
+
+    let der = parse_with_default_ops::<f64>("x^2+y")
+        .unwrap()
+        .partial(0)
+        .unwrap();
+    let unparsed = der.unparse().unwrap();
+    assert!(unparsed.contains('x') && !unparsed.contains('{'));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_priorities() {
+    fn test(text: &str, reference: &str) {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        let unparsed = flatex.unparse().unwrap();
+        assert_eq!(unparsed, reference);
+        let reparsed = parse_with_default_ops::<f64>(&unparsed).unwrap();
+        assert_eq!(reparsed.unparse().unwrap(), unparsed);
+        assert_eq!(flatex.var_names(), reparsed.var_names());
+        let n_vars = flatex.var_names().len();
+        for val in [-1.3, 0.4, 2.7] {
+            let vals = vec![val; n_vars];
+            let (a, b) = (flatex.eval(&vals).unwrap(), reparsed.eval(&vals).unwrap());
+            assert!(a == b || a.is_nan() && b.is_nan());
+        }
+    }
+    test("a+(b*c)", "a+b*c");
+    test("(a*b)+c", "a*b+c");
+    test("(a+b)+c", "a+b+c");
+    test("a*(b+c)", "a*(b+c)");
+    test("a-(b-c)", "a-(b-c)");
+    test("a/(b*c)", "a/(b*c)");
+    test("(a^b)^c", "a^b^c");
+    test("a^(b^c)", "a^(b^c)");
+    test("-(a)*(-b)", "(-a)*-b");
+    test("-(-x)", "--x");
+    test("-(x+y)", "-(x+y)");
+    test("-(x^2)", "-(x^2.0)");
+    test("(-x)^2", "(-x)^2.0");
+    test("-x^2", "(-x)^2.0");
+    test("-sin(x)^2", "(-sin(x))^2.0");
+    test("2^-x^2", "2.0^(-x)^2.0");
+    test("2^-x*3", "2.0^(-x)*3.0");
+    test("(2^-x)*3", "(2.0^-x)*3.0");
+    test("2^-x", "2.0^-x");
+    test("(-2)^x", "(-2.0)^x");
+    test("-2*x", "(-2.0)*x");
+    test("sin((x))+cos(x*y)", "sin(x)+cos(x*y)");
+    test("(2*x)*(3*y)", "2.0*x*(3.0*y)");
+    test("x*(2*3)", "x*6.0");
+    test("(x)", "x");
+    test("((x+y))*z", "(x+y)*z");
+    test("{x y}*(-{2x})", "{x y}*-{2x}");
+    // non-finite constants are not written as variables
+    test("floor((1e308^(3)))", "(1/0)");
+    test("log((1e-320^7))==cbrt(x)", "(-1/0)==cbrt(x)");
+    test("0.5/0+x", "(1/0)+x");
+    test("x^(0/0)", "x^(0/0)");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_idempotent() {
+    let texts = [
+        "5+x",
+        "sin(5+var)^(1/{y})+{var}",
+        "-(5+var)^(1/{y})+{var}",
+        "cos(sin(-(5+var)^(1/{y})))+{var}",
+        "cos(sin(-5+var^(1/{y})))-{var}",
+        "cos(sin(-z+var*(1/{y})))+{var}",
+        "((((x+y))))*((z))",
+        "(x-(y-(z-(x-y))))/((x/y)/z)",
+        "x^(y^(-z))^2",
+        "(2*x)*(3*y)+(x*(2*y))*3",
+        "--sin(-(x))*(-(x+y))",
+        "(x<(y+1))*(z>=x)",
+        "(x+1)/(y+1)^(z+(x))",
+    ];
+    for text in texts {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        let unparsed = flatex.unparse().unwrap();
+        let reparsed = parse_with_default_ops::<f64>(&unparsed).unwrap();
+        assert_eq!(reparsed.unparse().unwrap(), unparsed, "{}", text);
+        let n_vars = flatex.var_names().len();
+        for vals in [[0.3, -1.2, 2.5], [1.7, 0.4, -0.9], [-2.2, 3.1, 0.6]] {
+            let (a, b) = (
+                flatex.eval(&vals[..n_vars]).unwrap(),
+                reparsed.eval(&vals[..n_vars]).unwrap(),
+            );
+            assert!(
+                a == b || a.is_nan() && b.is_nan(),
+                "{}: {} != {}",
+                text,
+                a,
+                b
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{BinOp, UnaryPosition};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unparse_roundtrip() {
+    let ops = make_default_operators_int::<i32>();
+    for text in [
+        "!a|b",
+        "-(a)*b",
+        "-x+-(3*y)",
+        "(a<<2)|b%3",
+        "2*3-x",
+        "-(-(x))",
+        "!(a&b)",
+    ] {
+        let flatex = parse_with_number_pattern::<i32>(text, &ops, "[0-9]+").unwrap();
+        let unparsed = flatex.unparse().unwrap();
+        assert_eq!(unparsed, flatex.unparse_with_display().unwrap());
+        let reparsed = parse_with_number_pattern::<i32>(&unparsed, &ops, "[0-9]+").unwrap();
+        assert_eq!(reparsed.unparse().unwrap(), unparsed);
+        let vals = vec![-7; flatex.var_names().len()];
+        assert_eq!(flatex.eval(&vals), reparsed.eval(&vals));
+    }
+    let unparse = |text| {
+        parse_with_number_pattern::<i32>(text, &ops, "[0-9]+")
+            .unwrap()
+            .unparse()
+            .unwrap()
+    };
+    assert_eq!(unparse("!(a)|b"), "!a|b");
+    assert_eq!(unparse("-(3*y)"), "-(3*y)");
+
+    // the derived debug representation of the newtype cannot be parsed
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Cents(i64);
+    impl Display for Cents {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+    impl core::str::FromStr for Cents {
+        type Err = core::num::ParseIntError;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            s.parse().map(Cents)
+        }
+    }
+    fn op(
+        repr: &str,
+        bin_op: Option<BinOp<Cents>>,
+        unary_op: Option<fn(Cents) -> Cents>,
+    ) -> Operator<'_, Cents> {
+        Operator {
+            repr,
+            bin_op,
+            unary_op,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        }
+    }
+    let ops = [
+        op(
+            "+",
+            Some(BinOp {
+                apply: |a, b| Cents(a.0 + b.0),
+                prio: 0,
+            }),
+            None,
+        ),
+        op(
+            "*",
+            Some(BinOp {
+                apply: |a, b| Cents(a.0 * b.0),
+                prio: 1,
+            }),
+            None,
+        ),
+        op("-", None, Some(|a| Cents(-a.0))),
+    ];
+    let flatex = parse_with_number_pattern::<Cents>("x*(2+3) + -y", &ops, "[0-9]+").unwrap();
+    assert_eq!(flatex.unparse().unwrap(), "x*Cents(5)+-y");
+    let unparsed = flatex.unparse_with_display().unwrap();
+    assert_eq!(unparsed, "x*5+-y");
+    let reparsed = parse_with_number_pattern::<Cents>(&unparsed, &ops, "[0-9]+").unwrap();
+    assert!(flatex.structure_eq(&reparsed, false).unwrap());
+    assert_eq!(reparsed.eval(&[Cents(3), Cents(4)]), Ok(Cents(11)));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_checked() {
+    fn test(text: &str, vars: &[f64], op: &str, operands: &[&str], result: &str) {
+        let expr = parse_with_default_ops::<f64>(text).unwrap();
+        let err = expr.eval_checked(vars).unwrap_err();
+        assert_eq!(
+            err,
+            ExError::NonFiniteResult {
+                op: op.to_string(),
+                operands: operands.iter().map(|o| o.to_string()).collect(),
+                result: result.to_string(),
+            }
+        );
+        assert_eq!(err.code(), "E047");
+        assert!(!expr.eval(vars).unwrap().is_finite());
+    }
+    test("1/x", &[0.0], "/", &["1.0", "0.0"], "inf");
+    test("2+1/x", &[-0.0], "/", &["1.0", "-0.0"], "-inf");
+    test("log(x)", &[-1.0], "log", &["-1.0"], "NaN");
+    test("-log(x)+1", &[0.0], "log", &["0.0"], "-inf");
+    test("x^y", &[0.0, -1.0], "^", &["0.0", "-1.0"], "inf");
+    test("sqrt(x*y)", &[2.0, -2.0], "sqrt", &["-4.0"], "NaN");
+    test("exp(x*y)", &[100.0, 10.0], "exp", &["1000.0"], "inf");
+    // folded constants are reported by the first operator they are passed to
+    test("1/0+x", &[1.0], "+", &["inf", "1.0"], "inf");
+
+    let expr = parse_with_default_ops::<f64>("x^y").unwrap();
+    assert_float_eq_f64(expr.eval_checked(&[0.0, 0.0]).unwrap(), 1.0);
+    let expr = parse_with_default_ops::<f64>("sin(x)*(y+1)/cos(-x)^2").unwrap();
+    for vars in [[0.3, 1.2], [-2.0, 0.5], [4.1, -7.0]] {
+        assert_eq!(expr.eval_checked(&vars), expr.eval(&vars));
+    }
+    assert_eq!(
+        expr.eval_checked(&[1.0]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{make_default_operators_int, parse_with_number_pattern};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_fallible_ops() {
+    let mut ops = make_default_operators_int::<i64>();
+    for op in ops.iter_mut() {
+        op.fallible = match op.repr {
+            "/" => Some(FallibleOp {
+                bin_op: Some(|a: i64, b| a.checked_div(b).ok_or_else(|| "division by zero".into())),
+                unary_op: None,
+            }),
+            "+" => Some(FallibleOp {
+                bin_op: Some(|a: i64, b| a.checked_add(b).ok_or_else(|| "overflow".into())),
+                unary_op: None,
+            }),
+            _ => None,
+        };
+    }
+    let failed = |op: &str, operands: &[&str], reason: &str| ExError::OperatorFailed {
+        op: op.to_string(),
+        operands: operands.iter().map(|o| o.to_string()).collect(),
+        reason: reason.to_string(),
+    };
+    let parse = |text| parse_with_number_pattern::<i64>(text, &ops, "[0-9]+").unwrap();
+
+    let expr = parse("x/y");
+    assert_eq!(expr.eval(&[7, 2]), Ok(3));
+    let err = expr.eval(&[1, 0]).unwrap_err();
+    assert_eq!(err, failed("/", &["1", "0"], "division by zero"));
+    assert_eq!(err.code(), "E055");
+    assert_eq!(expr.eval_iter([1, 0]), Err(err.clone()));
+    assert_eq!(
+        expr.eval_with_buffer(&[1, 0], &mut EvalBuffer::new()),
+        Err(err)
+    );
+    // constants are not folded such that the failure is reported on evaluation
+    let expr = parse("1/0");
+    assert_eq!(
+        expr.eval(&[]),
+        Err(failed("/", &["1", "0"], "division by zero"))
+    );
+
+    let expr = parse("x + 1");
+    assert_eq!(expr.eval(&[41]), Ok(42));
+    assert_eq!(
+        expr.eval(&[i64::MAX]),
+        Err(failed("+", &[&i64::MAX.to_string(), "1"], "overflow"))
+    );
+    // unchecked evaluation applies the infallible operation
+    assert_eq!(expr.eval_unchecked(&[i64::MAX]), i64::MIN);
+
+    // failures within common sub-expressions and arguments of nested operators
+    let expr = parse("(x+y)*(x+y) - (x+y)").optimize_cse().unwrap();
+    assert_eq!(expr.eval(&[2, 3]), Ok(20));
+    assert!(expr.eval(&[i64::MAX, 1]).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_into_fn() {
+    fn integrate<F: Fn(f64) -> f64>(f: F, a: f64, b: f64) -> f64 {
+        let n = 1000;
+        let h = (b - a) / n as f64;
+        (0..n).map(|i| f(a + (i as f64 + 0.5) * h) * h).sum()
+    }
+    let f = {
+        let text = String::from("3*x^2");
+        parse_with_default_ops::<f64>(&text)
+            .unwrap()
+            .into_func1()
+            .unwrap()
+    };
+    assert!((integrate(&f, 0.0, 1.0) - 1.0).abs() < 1e-5);
+    assert!((integrate(f.clone(), 0.0, 2.0) - 8.0).abs() < 1e-4);
+
+    let g = parse_with_default_ops::<f64>("sin(x)*y")
+        .unwrap()
+        .into_func2()
+        .unwrap();
+    let handle = std::thread::spawn(move || g(0.5, 2.0));
+    assert_float_eq_f64(handle.join().unwrap(), 0.5f64.sin() * 2.0);
+
+    let h = parse_with_default_ops::<f64>("x-y/z")
+        .unwrap()
+        .into_func3()
+        .unwrap();
+    assert_float_eq_f64(h(1.0, 2.0, 4.0), 0.5);
+    let h = parse_with_default_ops::<f64>("x-y/z")
+        .unwrap()
+        .into_fn::<3>()
+        .unwrap();
+    assert_float_eq_f64(h([1.0, 2.0, 4.0]), 0.5);
+
+    assert!(matches!(
+        parse_with_default_ops::<f64>("x+y").unwrap().into_func1(),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    ));
+    assert!(parse_with_default_ops::<f64>("2")
+        .unwrap()
+        .into_func1()
+        .is_err());
+    let owned = OwnedFlatEx::from(parse_with_default_ops::<f64>("x+1").unwrap());
+    assert_float_eq_f64(owned.into_fn::<1>().unwrap()([2.0]), 3.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_substitute() {
+    let parse = |text| parse_with_default_ops::<f64>(text).unwrap();
+    let g = parse("sin(x)");
+    let substituted = parse("g^2 + g").substitute("g", &g).unwrap();
+    let reference = parse("sin(x)^2 + sin(x)");
+    assert_eq!(substituted.var_names(), ["x"]);
+    assert!(substituted.structure_eq(&reference, false).unwrap());
+    for x in [-1.2, 0.0, 0.7, 3.1] {
+        assert_float_eq_f64(
+            substituted.eval(&[x]).unwrap(),
+            reference.eval(&[x]).unwrap(),
+        );
+    }
+
+    // priorities of the replaced expression are kept
+    let expr = parse("a*p + b").substitute("p", &parse("x+y")).unwrap();
+    assert_eq!(expr.var_names(), ["a", "b", "x", "y"]);
+    assert_float_eq_f64(expr.eval(&[2.0, 1.0, 3.0, 4.0]).unwrap(), 15.0);
+    assert_eq!(expr.unparse().unwrap(), "a*(x+y)+b");
+
+    // one-shot replacement with the replaced variable in the substitute
+    let expr = parse("x^2 + y").substitute("x", &parse("x+1")).unwrap();
+    assert_eq!(expr.var_names(), ["x", "y"]);
+    assert_float_eq_f64(expr.eval(&[2.0, 0.5]).unwrap(), 9.5);
+
+    // constants are folded
+    let expr = parse("x*c + c").substitute("c", &parse("2*3")).unwrap();
+    assert_eq!(expr.var_names(), ["x"]);
+    assert_float_eq_f64(expr.eval(&[0.5]).unwrap(), 9.0);
+    let expr = parse("sin(c)").substitute("c", &parse("0")).unwrap();
+    assert_eq!(expr.unparse().unwrap(), "0.0");
+
+    let options = ParseOptions {
+        var_order: VarOrder::Occurrence,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    let template = parse_with_options("z + t*a", &ops, &options).unwrap();
+    let expr = template.substitute("t", &parse("y-b")).unwrap();
+    assert_eq!(expr.var_names(), ["z", "b", "y", "a"]);
+
+    assert_eq!(
+        parse("x+y").substitute("z", &g),
+        Err(ExError::UnknownVar {
+            name: "z".to_string(),
+            available: vec!["x".to_string(), "y".to_string()]
+        })
+    );
+    let mut cleared = g.clone();
+    cleared.clear_deepex();
+    assert_eq!(
+        parse("x+y").substitute("x", &cleared),
+        Err(ExError::DeepExMissing)
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_align_vars() {
+    let layout = ["x", "y", "z", "price", "qty"];
+    let texts = ["price*qty", "z - x^2", "sin(y)*qty + x"];
+    let exprs = texts
+        .iter()
+        .map(|text| parse_with_default_ops::<f64>(text).unwrap())
+        .collect::<Vec<_>>();
+    let aligned = exprs
+        .iter()
+        .map(|expr| expr.align_vars(&layout).unwrap())
+        .collect::<Vec<_>>();
+    let records = [[1.0, 0.5, 3.0, 2.5, 4.0], [-2.0, 1.5, 0.0, 10.0, 0.5]];
+    for record in records {
+        let [x, y, z, price, qty] = record;
+        let references = [price * qty, z - x * x, y.sin() * qty + x];
+        for ((expr, aligned), reference) in exprs.iter().zip(&aligned).zip(references) {
+            assert_eq!(aligned.var_names(), layout);
+            assert_float_eq_f64(aligned.eval(&record).unwrap(), reference);
+            // the same values in the order of the expression's own variables
+            let values = expr
+                .var_names()
+                .iter()
+                .map(|name| record[layout.iter().position(|l| l == name).unwrap()])
+                .collect::<Vec<_>>();
+            assert_float_eq_f64(expr.eval(&values).unwrap(), reference);
+        }
+    }
+    assert_eq!(aligned[0].to_string(), "price*qty");
+    assert_eq!(
+        aligned[0].eval(&[2.0, 3.0]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 5,
+            given: 2
+        })
+    );
+
+    // derivatives keep the layout
+    let d_x = aligned[1].clone().partial(0).unwrap();
+    assert_eq!(d_x.var_names(), layout);
+    assert_float_eq_f64(d_x.eval(&records[0]).unwrap(), -2.0);
+
+    assert_eq!(
+        exprs[0].align_vars(&["x", "price"]),
+        Err(ExError::MissingVar("qty".to_string()))
+    );
+    assert_eq!(
+        exprs[0].align_vars(&["qty", "price", "qty"]),
+        Err(ExError::DuplicateVar {
+            name: "qty".to_string(),
+            passed: vec![("qty".to_string(), 0), ("qty".to_string(), 2)],
+        })
+    );
+    let mut cleared = exprs[0].clone();
+    cleared.clear_deepex();
+    assert_eq!(cleared.align_vars(&layout), Err(ExError::DeepExMissing));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_extract_subexprs() {
+    let flatex = parse_with_default_ops::<f64>("sin(y)*x + cos(y^2) + x").unwrap();
+    let extracted = flatex
+        .extract_subexprs(|info| info.var_names == ["y"])
+        .unwrap();
+    assert_eq!(extracted.len(), 2);
+    let reprs = extracted
+        .iter()
+        .map(|(sub, _)| format!("{}", sub))
+        .collect::<Vec<_>>();
+    assert_eq!(reprs, ["sin(y)", "cos(y^2.0)"]);
+    for (x, y) in [(0.5, 0.3), (-2.0, 1.7), (3.0, -4.1)] {
+        let vars = [x, y];
+        let sub_values = extracted
+            .iter()
+            .map(|(sub, mapping)| {
+                assert_eq!(mapping, &vec![1]);
+                let sub_vars = mapping.iter().map(|i| vars[*i]).collect::<Vec<_>>();
+                sub.eval(&sub_vars).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_float_eq_f64(sub_values[0], y.sin());
+        assert_float_eq_f64(sub_values[1], (y * y).cos());
+        // substitute the extracted expressions by new variables a and b
+        let substituted = parse_with_default_ops::<f64>("a*x + b + x").unwrap();
+        assert_float_eq_f64(
+            flatex.eval(&vars).unwrap(),
+            substituted
+                .eval(&[sub_values[0], sub_values[1], x])
+                .unwrap(),
+        );
+    }
+
+    let flatex = parse_with_default_ops::<f64>("z*(x + 2*y) - exp(y)").unwrap();
+    let extracted = flatex
+        .extract_subexprs(|info| !info.var_names.contains(&"z") && info.n_nodes > 1)
+        .unwrap();
+    assert_eq!(extracted.len(), 1);
+    let (sub, mapping) = &extracted[0];
+    assert_eq!(sub.var_names(), ["x", "y"]);
+    assert_eq!(mapping, &vec![0, 1]);
+    assert_float_eq_f64(sub.eval(&[1.0, 2.0]).unwrap(), 5.0);
+
+    let extracted = flatex.extract_subexprs(|_| true).unwrap();
+    assert_eq!(extracted.len(), 1);
+    assert_eq!(extracted[0].1, vec![0, 1, 2]);
+    assert!(flatex.extract_subexprs(|_| false).unwrap().is_empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_unchecked() {
+    let flatex = parse_with_default_ops::<f64>("x*y+sin(z)").unwrap();
+    assert!(flatex.check_vars(3).is_ok());
+    assert_eq!(
+        flatex.check_vars(2),
+        Err(ExError::WrongNumberOfVars {
+            expected: 3,
+            given: 2
+        })
+    );
+    let vars = [1.5, 2.0, 0.3];
+    assert_float_eq_f64(flatex.eval_unchecked(&vars), flatex.eval(&vars).unwrap());
+    assert_float_eq_f64(
+        flatex.eval_unchecked(&[1.5, 2.0, 0.3, 7.0]),
+        3.0 + 0.3f64.sin(),
+    );
+    assert!(flatex.eval(&[1.5, 2.0, 0.3, 7.0]).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_with_buffer() {
+    let text = (0..50)
+        .map(|i| format!("x^{}*sin(y-{})", i % 4, i))
+        .collect::<Vec<_>>()
+        .join("-");
+    let long = parse_with_default_ops::<f64>(&text).unwrap();
+    assert!(long.nodes.len() > N_NODES_ON_STACK);
+    let short = parse_with_default_ops::<f64>("x/y+cos(x)").unwrap();
+    let mut buffer = EvalBuffer::new();
+    for (x, y) in [(1.0, 2.0), (-0.3, 0.7), (4.5, -2.25)] {
+        for flatex in [&long, &short] {
+            let res = flatex.eval_with_buffer(&[x, y], &mut buffer).unwrap();
+            assert_eq!(res.to_bits(), flatex.eval(&[x, y]).unwrap().to_bits());
+        }
+    }
+    assert_eq!(
+        short.eval_with_buffer(&[1.0], &mut buffer),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_optimize_cse() {
+    fn test(text: &str, n_tmps: usize) {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        let optimized = flatex.clone().optimize_cse().unwrap();
+        assert_eq!(optimized.tmps.len(), n_tmps);
+        assert_eq!(optimized.unparse().unwrap(), flatex.unparse().unwrap());
+        let points = [[0.3, -1.2, 2.0], [1.7, 0.5, -0.25], [-2.5, 4.0, 1.5]];
+        let n_vars = flatex.var_names().len();
+        let mut buffer = EvalBuffer::new();
+        for point in &points {
+            let vars = &point[..n_vars];
+            let reference = flatex.eval(vars).unwrap();
+            assert_float_eq_f64(optimized.eval(vars).unwrap(), reference);
+            assert_float_eq_f64(optimized.eval_unchecked(point), reference);
+            assert_float_eq_f64(
+                optimized.eval_with_buffer(vars, &mut buffer).unwrap(),
+                reference,
+            );
+            if let Ok(checked) = flatex.eval_checked(vars) {
+                assert_float_eq_f64(optimized.eval_checked(vars).unwrap(), checked);
+            }
+            let owned = OwnedFlatEx::from_flatex(optimized.clone());
+            assert_float_eq_f64(owned.eval(vars).unwrap(), reference);
+        }
+        let series = (0..n_vars)
+            .map(|i| points.iter().map(|p| p[i]).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let series = series.iter().map(|s| s.as_slice()).collect::<Vec<_>>();
+        let mut out = [0.0; 3];
+        optimized.eval_vectorized(&series, &mut out).unwrap();
+        for (o, point) in out.iter().zip(points.iter()) {
+            assert_float_eq_f64(*o, flatex.eval(&point[..n_vars]).unwrap());
+        }
+    }
+    test("sin(x)*sin(x)+cos(x)*cos(x)", 2);
+    test("x*y+z", 0);
+    test("sin(x+y)/z-sin(x+y)", 1);
+    // sin(x) is evaluated once in the first and once in the last summand
+    test("(sin(x)+y)*(sin(x)+y)+sin(x)", 2);
+    // the second product is not a sub-expression of its own
+    test("((sin(x)+y)*(sin(x)+y))^2-(sin(x)+y)*(sin(x)+y)", 1);
+    test("-(x*y)+exp(x*y)*(-(x*y))", 1);
+    test("sin(x)+sin(y)+sin(z)", 0);
+
+    let flatex = parse_with_default_ops::<f64>("sin(x)*sin(x)+cos(x)*cos(x)").unwrap();
+    assert_eq!(flatex.total_unary_applications(), 4);
+    let optimized = flatex.optimize_cse().unwrap();
+    assert_eq!(optimized.total_unary_applications(), 2);
+    assert!(matches!(optimized.nodes[0].kind, FlatNodeKind::Tmp(1)));
+    let f = optimized.into_fn::<1>().unwrap();
+    assert_float_eq_f64(f([0.7]), 1.0);
+
+    let mut cleared = parse_with_default_ops::<f64>("sin(x)*sin(x)").unwrap();
+    cleared.clear_deepex();
+    assert_eq!(cleared.optimize_cse(), Err(ExError::DeepExMissing));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_constant_folding() {
+    fn test(text: &str, n_nodes: usize, vars: &[f64], reference: f64) {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        assert_eq!(flatex.nodes.len(), n_nodes, "{}", text);
+        assert!(flatex
+            .nodes
+            .iter()
+            .all(|node| node.unary_op.len() == 0 || matches!(node.kind, FlatNodeKind::Var(_))));
+        assert_float_eq_f64(flatex.eval(vars).unwrap(), reference);
+    }
+    test("x + -(2+3)", 2, &[1.0], -4.0);
+    test("-(2+3)*x", 2, &[2.0], -10.0);
+    test("x + -(-(2+3))", 2, &[1.0], 6.0);
+    test("1*sin(2-0.1)", 1, &[], 1.9f64.sin());
+    test(
+        "sin(2-0.1)*1 + cos(0.5)",
+        1,
+        &[],
+        1.9f64.sin() + 0.5f64.cos(),
+    );
+    test(
+        "x*sin(2-0.1) + cos(-(0.5))",
+        3,
+        &[2.0],
+        2.0 * 1.9f64.sin() + 0.5f64.cos(),
+    );
+    test(
+        "sin(x)*(cos(1)+2)",
+        2,
+        &[0.5],
+        0.5f64.sin() * (1f64.cos() + 2.0),
+    );
+    test("-(-(2)+3)*x", 2, &[3.0], -3.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unused_vars() {
+    // variables that do not affect the value are kept such that the variable slice
+    // corresponds to the names in the string
+    for text in ["x*0 + y", "0*x^2 + y", "y + (x - x)", "x^0*y"] {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        assert_eq!(flatex.var_names(), ["x", "y"], "{}", text);
+        for x in [-3.0, 0.0, 7.5] {
+            assert_float_eq_f64(flatex.eval(&[x, 2.0]).unwrap(), 2.0);
+        }
+        assert!(flatex.eval(&[2.0]).is_err());
+        let optimized = flatex.clone().optimize_cse().unwrap();
+        assert_float_eq_f64(optimized.eval(&[1.0, 2.0]).unwrap(), 2.0);
+        let grad = flatex.grad().unwrap();
+        assert_float_eq_f64(grad[0].eval(&[1.5, 2.0]).unwrap(), 0.0);
+        let owned = OwnedFlatEx::from_flatex(flatex);
+        assert_eq!(owned.var_names(), ["x", "y"]);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unary_chains() {
+    fn test(text: &str, max_len: usize, total: usize) {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        assert_eq!(flatex.max_unary_chain_len(), max_len, "{}", text);
+        assert_eq!(flatex.total_unary_applications(), total, "{}", text);
+    }
+    test("x + 1", 0, 0);
+    test("-2 + sin(3)*x", 0, 0);
+    test("-(-(-x))", 3, 3);
+    test("sin(cos(x))*-y", 2, 3);
+    // unary operators of parents are merged into the binary operator of the child
+    test("-(x+-y)", 1, 2);
+    test("-(-(x*y))", 2, 2);
+    test("-(-(x*y) + 1)", 1, 2);
+    test("-(sin(x)*-(-(y+1)))", 2, 4);
+
+    // unary operators without a source are counted as well
+    let mut funcs = VecOfUnaryFuncs::new();
+    funcs.push(|x: f64| x * 2.0);
+    let deepex = DeepEx::<f64>::from_str("-(x*y)").unwrap();
+    let flatex = flatten(deepex.operate_unary(UnaryOpWithReprs::prefix(
+        vec!["double"],
+        UnaryOp::from_vec(funcs),
+    )))
+    .unwrap();
+    assert_eq!(flatex.max_unary_chain_len(), 2);
+    assert_float_eq_f64(flatex.eval(&[2.0, 3.0]).unwrap(), -12.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unary_chain_limit() {
+    let ops = make_default_operators::<f64>();
+    let options = |max_unary_chain| ParseOptions {
+        limits: ParseLimits {
+            max_unary_chain,
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+    let text = "y + -(-(-x))";
+    assert!(parse_with_options(text, &ops, &options(None)).is_ok());
+    assert!(parse_with_options(text, &ops, &options(Some(3))).is_ok());
+    assert_eq!(
+        parse_with_options(text, &ops, &options(Some(2))),
+        Err(ExError::UnaryChainTooLong {
+            position: 4,
+            end: 12,
+            len: 3,
+            max: 2,
+            context: "y + -(-(-x))".to_string(),
+        })
+    );
+    let text = "1 + 2*x + sin(-(y*z))";
+    match parse_with_options(text, &ops, &options(Some(1))) {
+        Err(ExError::UnaryChainTooLong {
+            position, end, len, ..
+        }) => {
+            assert_eq!(&text[position..end], "sin(-(y*z))");
+            assert_eq!(len, 2);
+        }
+        res => panic!("expected too long unary chain, got {:?}", res),
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_node_and_depth_limits() {
+    let ops = make_default_operators::<f64>();
+    let options = |max_nodes, max_depth| ParseOptions {
+        limits: ParseLimits {
+            max_nodes,
+            max_depth,
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+    let text = "x*(y + (2 - z))";
+    assert!(parse_with_options(text, &ops, &options(Some(4), Some(2))).is_ok());
+    assert_eq!(
+        parse_with_options(text, &ops, &options(Some(3), None)),
+        Err(ExError::TooManyNodes {
+            position: 12,
+            max: 3,
+            context: "(2 - z))".to_string(),
+        })
+    );
+    assert_eq!(
+        parse_with_options(text, &ops, &options(None, Some(1))),
+        Err(ExError::NestingTooDeep {
+            position: 7,
+            max: 1,
+            context: "(y + (2 - z".to_string(),
+        })
+    );
+    // the depth is the maximal nesting, not the number of parentheses
+    let text = "(x) + (y) * ((z))";
+    assert!(parse_with_options(text, &ops, &options(None, Some(2))).is_ok());
+    assert!(parse_with_options(text, &ops, &options(None, Some(1))).is_err());
+
+    // parsing stops at the limit also if the rest of the string is invalid
+    let deep = format!("{}x{}", "(".repeat(100_000), ")".repeat(99_999));
+    let err = parse_with_options(&deep, &ops, &options(None, Some(64))).unwrap_err();
+    assert_eq!(err.position(), Some(64));
+    assert_eq!(err.code(), "E048");
+    let long = "x+".repeat(100_000);
+    let err = parse_with_options(&long, &ops, &options(Some(1000), None)).unwrap_err();
+    assert_eq!(err.position(), Some(2000));
+    assert_eq!(err.code(), "E039");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_deep_nesting() {
+    // the maximal default depth and deeper composed expressions fit into the 2 MiB stack
+    // of spawned threads
+    let deep_nesting = || {
+        // each level has two priorities and is applied to the result of the nested levels
+        let ops = ["+", "-", "*", "/"];
+        let coef = |level: usize| (level % 5 + 1) as f64;
+        let apply = |op: &str, a: f64, b: f64| match op {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            _ => a / b,
+        };
+        let reference = |depth: usize, x: f64| {
+            (0..depth).fold(x, |inner, level| {
+                apply(ops[level % 4], coef(level) * x, inner)
+            })
+        };
+        let depth = 512;
+        let text = (0..depth).fold("x".to_string(), |inner, level| {
+            format!("{}*x{}({})", coef(level), ops[level % 4], inner)
+        });
+        let expr = parse_with_default_ops::<f64>(&text).unwrap();
+        assert_eq!(expr.depth(), Ok(depth));
+        for x in [-1.5, 0.5, 2.0] {
+            assert_float_eq_f64(expr.eval(&[x]).unwrap(), reference(depth, x));
+        }
+        let (lo, hi) = expr.eval_interval(&[(0.5, 2.0)]).unwrap();
+        assert!(lo <= reference(depth, 1.0) && reference(depth, 1.0) <= hi);
+        let deepex = unpack_deepex(&expr).unwrap();
+        assert_float_eq_f64(deepex.eval(&[0.5]).unwrap(), reference(depth, 0.5));
+        let extracted = expr.extract_subexprs(|info| info.n_nodes > 1).unwrap();
+        assert_eq!(extracted.len(), 1);
+        let bound = expr.bind(&[(0, 0.5)]).unwrap();
+        assert_float_eq_f64(bound.eval(&[]).unwrap(), reference(depth, 0.5));
+        let half = parse_with_default_ops::<f64>("y/2").unwrap();
+        let substituted = expr.substitute("x", &half).unwrap();
+        assert_float_eq_f64(substituted.eval(&[1.0]).unwrap(), reference(depth, 0.5));
+        assert!(crate::static_ex_source(&text).is_ok());
+        let builder = (0..depth).fold(ExprBuilder::var("x"), |inner, level| {
+            let outer = ExprBuilder::num(coef(level)).combine(ExprBuilder::var("x"), "*");
+            outer.combine(inner, ops[level % 4])
+        });
+        let built = builder.build(&make_default_operators::<f64>()).unwrap();
+        assert_float_eq_f64(built.eval(&[0.5]).unwrap(), reference(depth, 0.5));
+        let too_deep = format!("({})", text);
+        assert_eq!(
+            parse_with_default_ops::<f64>(&too_deep).unwrap_err().code(),
+            "E048"
+        );
+
+        // differentiation at the maximal depth, the derivatives of sums and differences
+        // keep their size while products would blow them up in each level
+        let text = (0..depth).fold("x".to_string(), |inner, level| {
+            format!("{}*x{}({})", coef(level), ops[level % 2], inner)
+        });
+        let expr = parse_with_default_ops::<f64>(&text).unwrap();
+        assert_eq!(expr.depth(), Ok(depth));
+        let reference_der = (0..depth).fold(1.0, |d_inner, level| {
+            apply(ops[level % 2], coef(level), d_inner)
+        });
+        assert_float_eq_f64(
+            expr.partial(0).unwrap().eval(&[0.5]).unwrap(),
+            reference_der,
+        );
+        let parens = format!("{}x{}", "(".repeat(300), ")".repeat(300));
+        let expr = parse_with_default_ops::<f64>(&parens).unwrap();
+        assert_float_eq_f64(expr.partial(0).unwrap().eval(&[0.5]).unwrap(), 1.0);
+        let sines = format!("{}x{}", "sin(".repeat(128), ")".repeat(128));
+        let expr = parse_with_default_ops::<f64>(&sines).unwrap();
+        let (_, reference_der) = (0..128).fold((0.5f64, 1.0), |(v, d), _| (v.sin(), v.cos() * d));
+        assert_float_eq_f64(
+            expr.partial(0).unwrap().eval(&[0.5]).unwrap(),
+            reference_der,
+        );
+        let products = format!("{}x+1{}", "(".repeat(128), ")*x".repeat(128));
+        let expr = parse_with_default_ops::<f64>(&products).unwrap();
+        // d/dx (x+1)*x^128 = x^128 + 128*(x+1)*x^127
+        assert_float_eq_f64(expr.partial(0).unwrap().eval(&[1.0]).unwrap(), 257.0);
+
+        // composed expressions are not limited by the parser
+        let depth = 1000;
+        let texts = (0..5).map(|level| format!("{}*x", coef(level)));
+        let texts = texts.collect::<Vec<_>>();
+        let outers = texts
+            .iter()
+            .map(|text| parse_with_default_ops::<f64>(text).unwrap())
+            .collect::<Vec<_>>();
+        let x = parse_with_default_ops::<f64>("x").unwrap();
+        let expr = (0..depth).fold(x, |inner, level| {
+            let outer = outers[level % 5].clone();
+            match ops[level % 4] {
+                "+" => outer + inner,
+                "-" => outer - inner,
+                "*" => outer * inner,
+                _ => outer / inner,
+            }
+        });
+        for x in [-1.5, 0.5, 2.0] {
+            assert_float_eq_f64(expr.eval(&[x]).unwrap(), reference(depth, x));
+        }
+
+        // the effective priorities of deeply nested operators may exceed the range of i32
+        let deepex = unpack_deepex(&expr).unwrap();
+        let mut prios = vec![];
+        collect_prios(deepex, &mut prios);
+        prios.sort_unstable();
+        prios.dedup();
+        let mut nodes = FlatNodeVec::<_>::new();
+        let mut ops = FlatOpVec::<_>::new();
+        let prio_offset = i64::from(i32::MAX);
+        let mut op_reprs = vec![];
+        flatten_vecs(
+            deepex,
+            &prios,
+            prio_offset,
+            &mut op_reprs,
+            &[],
+            &mut nodes,
+            &mut ops,
+            &mut vec![],
+            0,
+        )
+        .unwrap();
+        let indices = prioritized_indices_flat(&ops, &nodes, &op_reprs, deepex.optimizations());
+        let plan = make_eval_plan::<N_NODES_ON_STACK>(&indices, nodes.len());
+        let mut numbers = node_values(&nodes, &[0.5]).collect::<Vec<_>>();
+        let value = apply_plan(&mut numbers, &ops, &plan);
+        assert_float_eq_f64(value, reference(depth, 0.5));
+    };
+    std::thread::Builder::new()
+        .stack_size(2 << 20)
+        .spawn(deep_nesting)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_introspection() {
+    let expr = parse_with_default_ops::<f64>("x").unwrap();
+    assert_eq!(expr.node_count(), 1);
+    assert_eq!(expr.op_count(), 0);
+    assert_eq!(expr.depth(), Ok(0));
+    assert!(expr.operators_used().is_empty());
+
+    let mut expr = parse_with_default_ops::<f64>("-(-(2*3 + x)) * sqrt(y / x)").unwrap();
+    assert_eq!(expr.node_count(), 4);
+    assert_eq!(expr.op_count(), 6);
+    assert_eq!(expr.depth(), Ok(2));
+    assert_eq!(expr.operators_used(), ["*", "+", "-", "/", "sqrt"]);
+    expr.clear_deepex();
+    assert_eq!(expr.depth(), Err(ExError::DeepExMissing));
+    assert_eq!(expr.node_count(), 4);
+
+    // common sub-expressions are counted once
+    let expr = parse_with_default_ops::<f64>("sin(x*y) + sin(x*y)^2").unwrap();
+    assert_eq!((expr.node_count(), expr.op_count()), (5, 6));
+    let expr = expr.optimize_cse().unwrap();
+    assert_eq!((expr.node_count(), expr.op_count()), (5, 4));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_cost_estimate() {
+    let cost = |text| parse_with_default_ops::<f64>(text).unwrap().cost_estimate();
+    let estimate = |bin_ops, unary_ops, nary_calls, var_reads| CostEstimate {
+        bin_ops,
+        unary_ops,
+        nary_calls,
+        var_reads,
+    };
+    assert_eq!(cost("2.5"), estimate(0, 0, 0, 0));
+    assert_eq!(cost("x*x + y"), estimate(2, 0, 0, 3));
+    assert_eq!(cost("-(-(-x)) + sin(y)"), estimate(1, 4, 0, 2));
+    // folded constants do not count
+    assert_eq!(cost("sin(2*3)^2 + cos(4)*x"), estimate(2, 0, 0, 1));
+    assert_eq!(cost("x * (2 - -(3*4)) / sqrt(16)"), estimate(1, 0, 0, 1));
+    // unary operators of an expression are applied after its last binary operator
+    assert_eq!(cost("exp(-(x + y)) * 2"), estimate(2, 2, 0, 2));
+    // common sub-expressions are counted once
+    let expr = parse_with_default_ops::<f64>("sin(x*y) + sin(x*y)^2").unwrap();
+    assert_eq!(expr.cost_estimate(), estimate(4, 2, 0, 4));
+    assert_eq!(
+        expr.optimize_cse().unwrap().cost_estimate(),
+        estimate(3, 1, 0, 2)
+    );
+
+    let mut ops = crate::make_default_operators::<f64>().to_vec();
+    ops.push(crate::Operator {
+        repr: "clamp",
+        bin_op: None,
+        unary_op: None,
+        domain_check: None,
+        unary_position: crate::UnaryPosition::Prefix,
+        nary_op: Some(NaryOp {
+            apply: |args| args[0].max(args[1]).min(args[2]),
+            arity: 3,
+        }),
+        fallible: None,
+        aliases: &[],
+    });
+    let expr = crate::parse("clamp(-x, 0, y*2) + x", &ops).unwrap();
+    assert_eq!(expr.cost_estimate(), estimate(2, 1, 1, 3));
+
+    let weights = HashMap::from([("clamp", 10), ("*", 3), ("sin", 0)]);
+    assert_eq!(expr.cost_with_weights(&weights), 10 + 1 + 3 + 1);
+    assert_eq!(expr.cost_with_weights(&HashMap::new()), 4);
+    let expr = parse_with_default_ops::<f64>("sin(x)^2 + sin(2)*y").unwrap();
+    assert_eq!(expr.cost_with_weights(&weights), 5);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_combine() {
+    fn parse(text: &str) -> FlatEx<'_, f64> {
+        parse_with_default_ops::<f64>(text).unwrap()
     }
+    let sum = parse("x+y");
+    let prod = parse("y*z");
+    let combined = sum.clone() + prod.clone();
+    assert_eq!(combined.var_names(), ["x", "y", "z"]);
+    let (x, y, z) = (1.5, -2.0, 3.0);
+    assert_float_eq_f64(combined.eval(&[x, y, z]).unwrap(), x + y + y * z);
+    let combined = sum.clone() - prod.clone();
+    assert_float_eq_f64(combined.eval(&[x, y, z]).unwrap(), x + y - y * z);
+    let combined = prod.clone() * sum.clone();
+    assert_eq!(combined.var_names(), ["x", "y", "z"]);
+    assert_float_eq_f64(combined.eval(&[x, y, z]).unwrap(), y * z * (x + y));
+    let combined = sum.clone() / prod.clone();
+    assert_float_eq_f64(combined.eval(&[x, y, z]).unwrap(), (x + y) / (y * z));
+    let combined = prod.clone().combine(parse("2"), "^").unwrap();
+    assert_eq!(combined.var_names(), ["y", "z"]);
+    assert_float_eq_f64(combined.eval(&[y, z]).unwrap(), (y * z).powi(2));
+
+    // operator priorities within the operands are kept
+    let combined = parse("-a^2") * parse("sin(b) + 1") - parse("a");
+    assert_float_eq_f64(
+        combined.eval(&[2.0, 0.5]).unwrap(),
+        4.0 * (0.5f64.sin() + 1.0) - 2.0,
+    );
+    assert_eq!(format!("{}", combined), "(-a)^2.0*(sin(b)+1.0)-a");
+
+    let mut cleared = sum.clone();
+    cleared.clear_deepex();
+    assert_eq!(
+        cleared.combine(prod.clone(), "+"),
+        Err(ExError::DeepExMissing)
+    );
+    assert_eq!(
+        sum.combine(prod, "%"),
+        Err(ExError::OperatorNotFound("%".to_string()))
+    );
 }
 
+#[cfg(feature = "std")]
 #[test]
-fn test_operator_overloading() {
-    fn from_str(text: &str) -> DeepEx<f64> {
-        DeepEx::from_str(text).unwrap()
+fn test_grad() {
+    let expr = parse_with_default_ops::<f64>("x^2*y + sin(z)").unwrap();
+    let grad = expr.grad().unwrap();
+    assert_eq!(grad.len(), 3);
+    let (x, y, z) = (1.5, -2.0, 0.5);
+    let reference = [2.0 * x * y, x * x, z.cos()];
+    for (partial, reference) in grad.iter().zip(reference) {
+        assert_eq!(partial.var_names(), ["x", "y", "z"]);
+        assert_float_eq_f64(partial.eval(&[x, y, z]).unwrap(), reference);
     }
-    fn eval<'a>(deepex: &DeepEx<'a, f64>, vars: &[f64], val: f64) {
-        assert_float_eq_f64(flatten(deepex.clone()).eval(vars).unwrap(), val);
+
+    let jac = jacobian(&[
+        parse_with_default_ops::<f64>("x*y").unwrap(),
+        parse_with_default_ops::<f64>("cos(z) - y").unwrap(),
+        parse_with_default_ops::<f64>("3").unwrap(),
+    ])
+    .unwrap();
+    let reference = [[y, x, 0.0], [0.0, -1.0, -z.sin()], [0.0, 0.0, 0.0]];
+    for (row, reference_row) in jac.iter().zip(reference) {
+        assert_eq!(row.len(), 3);
+        for (entry, reference_entry) in row.iter().zip(reference_row) {
+            assert_eq!(entry.var_names(), ["x", "y", "z"]);
+            assert_float_eq_f64(entry.eval(&[x, y, z]).unwrap(), reference_entry);
+        }
     }
+    assert!(jacobian::<f64>(&[]).unwrap().is_empty());
+}
 
-    let one = from_str("1");
-    let two = one.clone() + one.clone();
-    eval(&two, &[], 2.0);
+#[cfg(feature = "std")]
+#[test]
+fn test_partial_by_name() {
+    let expr = parse_with_default_ops::<f64>("x^2*y").unwrap();
+    let (x, y) = (1.5, -2.0);
+    let d_x = expr.clone().partial_by_name("x").unwrap();
+    assert_float_eq_f64(d_x.eval(&[x, y]).unwrap(), 2.0 * x * y);
+    let d_y = expr.clone().partial_by_name("y").unwrap();
+    assert_eq!(d_y.var_names(), ["x", "y"]);
+    assert_float_eq_f64(d_y.eval(&[x, y]).unwrap(), x * x);
 
-    let x_squared = from_str("x*x");
-    let two_x_squared = two.clone() * x_squared.clone();
-    eval(&two_x_squared, &[0.0], 0.0);
-    eval(&two_x_squared, &[1.0], 2.0);
-    eval(&two_x_squared, &[2.0], 8.0);
-    eval(&two_x_squared, &[3.0], 18.0);
-    let some_expr = from_str("x") + from_str("x") * from_str("2") / from_str("x^(.5)");
-    eval(&some_expr, &[4.0], 8.0);
+    let err = expr.clone().partial_by_name("q").unwrap_err();
+    assert_eq!(
+        err,
+        ExError::UnknownVar {
+            name: "q".to_string(),
+            available: vec!["x".to_string(), "y".to_string()]
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "[E041] `q` is not a variable of the expression; available: x, y"
+    );
+    for var_idx in [2, 7] {
+        assert_eq!(
+            expr.clone().partial(var_idx),
+            Err(ExError::VarIdxOutOfRange {
+                idx: var_idx,
+                n_vars: 2
+            })
+        );
+    }
+    assert_eq!(
+        parse_with_default_ops::<f64>("2")
+            .unwrap()
+            .partial(0)
+            .map(|_| ()),
+        Err(ExError::VarIdxOutOfRange { idx: 0, n_vars: 0 })
+    );
+}
 
-    let x_plus_y_plus_z = from_str("x+y+z");
-    let y_minus_z = from_str("y-z");
-    let prod_of_above = x_plus_y_plus_z.clone() * y_minus_z.clone();
-    eval(&prod_of_above, &[1.0, 4.0, 8.0], -52.0);
-    let div_of_above = x_plus_y_plus_z.clone() / y_minus_z.clone();
-    eval(&div_of_above, &[1.0, 4.0, 8.0], -3.25);
-    let sub_of_above = x_plus_y_plus_z.clone() - y_minus_z.clone();
-    eval(&sub_of_above, &[1.0, 4.0, 8.0], 17.0);
-    let add_of_above = x_plus_y_plus_z + y_minus_z.clone();
-    eval(&add_of_above, &[1.0, 4.0, 8.0], 9.0);
-    let x_plus_cossin_y_plus_z = from_str("x+cos(sin(y+z))");
-    let prod_of_above = x_plus_cossin_y_plus_z * y_minus_z;
-    eval(&prod_of_above, &[1.0, 4.0, 8.0], -7.4378625090980925);
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_arr() {
+    let expr = parse_with_default_ops::<f64>("x^2 + y").unwrap();
+    assert_float_eq_f64(expr.eval_arr(&[2.0, 0.5]).unwrap(), 4.5);
+    assert_eq!(
+        expr.eval_arr(&[2.0]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+    let bound = expr.try_bind_arity::<2>().unwrap();
+    assert_float_eq_f64(bound.eval(&[2.0, 0.5]), 4.5);
+    assert_eq!(
+        expr.try_bind_arity::<3>().map(|_| ()),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 3
+        })
+    );
+
+    // a solver that only checks the number of variables once
+    fn minimize<const N: usize>(
+        f: &FlatEx<f64>,
+        grad: &[FlatEx<f64>],
+        mut x: [f64; N],
+    ) -> Result<[f64; N], ExError> {
+        let f = f.try_bind_arity::<N>()?;
+        let grad = grad
+            .iter()
+            .map(|partial| partial.try_bind_arity::<N>())
+            .collect::<Result<Vec<_>, ExError>>()?;
+        let mut step = 0.1;
+        for _ in 0..1000 {
+            let mut x_new = x;
+            for (x_new_i, partial) in x_new.iter_mut().zip(grad.iter()) {
+                *x_new_i -= step * partial.eval(&x);
+            }
+            if f.eval(&x_new) < f.eval(&x) {
+                x = x_new;
+            } else {
+                step *= 0.5;
+            }
+        }
+        Ok(x)
+    }
+    let f = parse_with_default_ops::<f64>("(x-1)^2 + (y+2)^2").unwrap();
+    let x = minimize(&f, &f.grad().unwrap(), [0.0, 0.0]).unwrap();
+    assert_float_eq_f64(x[0], 1.0);
+    assert_float_eq_f64(x[1], -2.0);
+    assert!(minimize(&f, &f.grad().unwrap(), [0.0]).is_err());
 }
+
+#[cfg(feature = "std")]
 #[test]
-fn test_display() {
-    let mut flatex = flatten(DeepEx::<f64>::from_str("sin(var)/5").unwrap());
-    assert_eq!(format!("{}", flatex), "sin({var})/5.0");
+fn test_eval_from() {
+    let expr = parse_with_default_ops::<f64>("x^2 + y").unwrap();
+    assert_eq!(expr.eval_from(&[3i32, -2]).unwrap(), 7.0);
+    assert_eq!(expr.eval_from(&[0.5f32, 0.25]).unwrap(), 0.5);
+    assert_eq!(expr.eval_from(&[2u8, 1]), expr.eval(&[2.0, 1.0]));
+    assert_eq!(
+        expr.eval_from(&[1i32]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+
+    assert_eq!(expr.eval_iter([2.0, 0.5]).unwrap(), 4.5);
+    assert_eq!(expr.eval_iter((1..3).map(f64::from)).unwrap(), 3.0);
+    let wrong_len = |n: i32| expr.eval_iter((0..n).map(f64::from));
+    for n in [0, 1, 3, 40] {
+        assert_eq!(wrong_len(n), expr.eval(&vec![0.0; n as usize]));
+        assert_eq!(
+            wrong_len(n),
+            Err(ExError::WrongNumberOfVars {
+                expected: 2,
+                given: n as usize
+            })
+        );
+    }
+    let constant = parse_with_default_ops::<f64>("2.5").unwrap();
+    assert_eq!(constant.eval_iter(iter::empty()).unwrap(), 2.5);
+    assert_eq!(constant.eval_from::<f32>(&[]).unwrap(), 2.5);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_owned() {
+    let parse_owned = |text: &str| {
+        let text = format!("{} + 1", text);
+        OwnedFlatEx::from(parse_with_default_ops::<f64>(&text).unwrap())
+    };
+    let exprs = [parse_owned("x*y"), parse_owned("-sin(z)")];
+    assert_eq!(exprs[0].var_names(), ["x", "y"]);
+    assert_float_eq_f64(exprs[0].eval(&[2.0, 3.0]).unwrap(), 7.0);
+    assert_float_eq_f64(exprs[1].eval(&[0.5]).unwrap(), 1.0 - 0.5f64.sin());
+    assert_eq!(format!("{}", exprs[1]), "-sin(z)+1.0");
+    assert_eq!(
+        exprs[1].eval(&[]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 1,
+            given: 0
+        })
+    );
+
+    let mut flatex = parse_with_default_ops::<f64>("x^2").unwrap();
     flatex.clear_deepex();
+    let owned = OwnedFlatEx::from_flatex(flatex);
+    assert_float_eq_f64(owned.eval(&[3.0]).unwrap(), 9.0);
+    assert_eq!(owned.unparse(), Err(ExError::DeepExMissing));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_memory_footprint() {
+    let text = "sin(price_of_product)*quantity+price_of_product^2".to_string();
+    let flatex = parse_with_default_ops::<f64>(&text).unwrap();
+    let owned = OwnedFlatEx::from(flatex.clone());
+    assert!(owned.memory_footprint() < flatex.memory_footprint());
+    let mut cleared = flatex.clone();
+    cleared.clear_deepex();
+    assert!(cleared.memory_footprint() < flatex.memory_footprint());
+
+    // names are shared by expressions parsed from different strings
+    let other_text = "price_of_product/2".to_string();
+    let other = OwnedFlatEx::from(parse_with_default_ops::<f64>(&other_text).unwrap());
+    assert_eq!(other.var_names()[0].as_ptr(), owned.var_names()[0].as_ptr());
+
+    let n_clones = 10_000;
+    let clones = vec![owned.clone(); n_clones];
+    let shared_len = owned
+        .var_names()
+        .iter()
+        .map(|name| name.len())
+        .sum::<usize>()
+        + owned.unparse().unwrap().len();
+    // each of the 3 shared allocations contains 2 reference counts
+    let shared_bytes = shared_len + 3 * 2 * mem::size_of::<usize>();
+    let unshared_bytes = mem::size_of::<OwnedFlatEx<f64>>() + 2 * mem::size_of::<Arc<str>>();
+    let total = clones
+        .iter()
+        .map(OwnedFlatEx::memory_footprint)
+        .sum::<usize>();
+    assert!(total <= n_clones * unshared_bytes + shared_bytes);
+    // without interning, each clone would own copies of the names and the unparsed string
+    let baseline = n_clones * (unshared_bytes + shared_bytes);
+    assert!(baseline - total >= (n_clones - 1) * shared_bytes);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_large() {
+    let text = (0..250)
+        .map(|i| format!("{}*sin(x{})", i % 7 + 1, i % 5))
+        .collect::<Vec<_>>()
+        .join(" - ");
+    let flatex = parse_with_default_ops::<f64>(&text).unwrap();
+    assert_eq!(flatex.node_count(), 500);
+    let large: LargeFlatEx<f64> = flatex.clone().with_nodes_on_stack();
+    assert!(core::mem::size_of_val(&large) < core::mem::size_of_val(&flatex));
+    assert_eq!(large.var_names(), flatex.var_names());
+    assert_eq!(large.unparse(), flatex.unparse());
+    let points = [[0.5, -1.0, 2.0, 0.0, 3.5], [1.0, 2.0, 3.0, 4.0, 5.0]];
+    let mut buffer = EvalBuffer::new();
+    for vars in &points {
+        let expected = flatex.eval(vars).unwrap();
+        assert_eq!(large.eval(vars).unwrap().to_bits(), expected.to_bits());
+        assert_eq!(
+            large.clone().eval(vars).unwrap().to_bits(),
+            expected.to_bits()
+        );
+        assert_eq!(
+            large.eval_with_buffer(vars, &mut buffer).unwrap().to_bits(),
+            expected.to_bits()
+        );
+        assert_eq!(
+            large.clone().partial(2).unwrap().eval(vars),
+            flatex.clone().partial(2).unwrap().eval(vars)
+        );
+    }
+    let series = (0..5)
+        .map(|var| points.iter().map(|vars| vars[var]).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let series = series.iter().map(|s| s.as_slice()).collect::<Vec<_>>();
+    let (mut out, mut out_large) = ([0.0; 2], [0.0; 2]);
+    flatex.eval_vectorized(&series, &mut out).unwrap();
+    large.eval_vectorized(&series, &mut out_large).unwrap();
+    assert_eq!(out, out_large);
+    let owned = OwnedFlatEx::from_flatex(large.clone().optimize_cse().unwrap());
+    assert_float_eq_f64(
+        owned.eval(&points[0]).unwrap(),
+        large.eval(&points[0]).unwrap(),
+    );
+
+    // small expressions can be moved back onto the stack
+    let small = parse_with_default_ops::<f64>("x*y + 1").unwrap();
+    let roundtrip = small
+        .clone()
+        .with_nodes_on_stack::<0>()
+        .with_nodes_on_stack::<N_NODES_ON_STACK>();
+    assert!(!roundtrip.nodes.spilled());
+    assert_eq!(roundtrip.eval(&[2.0, 3.0]), small.eval(&[2.0, 3.0]));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_plan() {
+    let flatex = parse_with_default_ops::<f64>("1-x*y^2+z").unwrap();
+    let steps = flatex
+        .plan
+        .iter()
+        .map(|step| (step.op_idx, step.left, step.right))
+        .collect::<Vec<_>>();
+    assert_eq!(steps, [(2, 2, 3), (1, 1, 2), (0, 0, 1), (3, 0, 4)]);
+
+    let text = "x*0.02*sin(-(3*(2*sin(x-1/(sin(y*5)+(5.0-1/z))))))";
+    let flatex = parse_with_default_ops::<f64>(text).unwrap();
+    let reference = |x: f64, y: f64, z: f64| {
+        x * 0.02 * (-(3.0 * (2.0 * (x - 1.0 / ((y * 5.0).sin() + (5.0 - 1.0 / z))).sin()))).sin()
+    };
+    for (x, y, z) in [(1.0, 2.0, 3.0), (-0.3, 0.7, 1.9), (4.5, -2.25, 0.1)] {
+        let res = flatex.eval(&[x, y, z]).unwrap();
+        assert_eq!(res.to_bits(), reference(x, y, z).to_bits());
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_wide_prios() {
+    let bin = |repr, apply, prio| Operator {
+        repr,
+        bin_op: Some(BinOp { apply, prio }),
+        unary_op: None,
+        domain_check: None,
+        unary_position: crate::UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    };
+    let ops = [
+        bin("+", |a: f64, b| a + b, i32::MIN),
+        bin("-", |a, b| a - b, 0),
+        bin("*", |a, b| a * b, i32::MAX),
+    ];
+    let flatex = crate::parse("2*(1+3*(2-x))+1-x*(x+1)", &ops).unwrap();
+    let flat_prios = flatex.ops.iter().map(|op| op.prio).collect::<Vec<_>>();
+    assert_eq!(flat_prios, [2, 3, 5, 7, 0, 1, 2, 3]);
+    let reference = |x: f64| 2.0 * (1.0 + 3.0 * (2.0 - x)) + (1.0 - x * (x + 1.0));
+    for x in [-1.5, 0.0, 2.25] {
+        assert_eq!(flatex.eval(&[x]).unwrap(), reference(x));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_consistency() {
+    let flatex = parse_with_default_ops::<f64>("x*2+1-sin(x)").unwrap();
+    let n_nodes = flatex.nodes.len();
+    assert_eq!(check_flat_shape(&flatex.nodes, &flatex.ops), Ok(()));
+    assert_eq!(check_eval_plan(&flatex.plan, n_nodes), Ok(()));
+    let is_internal = |res: Result<(), ExError>| matches!(res, Err(ExError::Internal(_)));
+    assert!(is_internal(check_flat_shape::<f64>(&[], &[])));
+    assert!(is_internal(check_flat_shape(
+        &flatex.nodes[1..],
+        &flatex.ops
+    )));
+    assert!(is_internal(check_flat_shape(
+        &flatex.nodes,
+        &flatex.ops[1..]
+    )));
+    let mut plan = flatex.plan.clone();
+    plan[1] = plan[0];
+    assert!(is_internal(check_eval_plan(&plan, n_nodes)));
+    assert!(is_internal(check_eval_plan(&flatex.plan[1..], n_nodes)));
+    let mut plan = flatex.plan.clone();
+    plan[0].right = n_nodes;
+    assert!(is_internal(check_eval_plan(&plan, n_nodes)));
+    let mut plan = flatex.plan.clone();
+    plan[0].left = plan[0].right;
+    assert!(is_internal(check_eval_plan(&plan, n_nodes)));
+
+    // degenerate inputs are rejected before a flat expression exists
+    for text in [
+        "", " ", "()", "(())", "x+", "+", "*x", "sin", "sin()", "x y", "(,)",
+    ] {
+        assert!(parse_with_default_ops::<f64>(text).is_err(), "{}", text);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_structure_eq() {
+    fn parse(text: &str) -> FlatEx<'_, f64> {
+        parse_with_default_ops::<f64>(text).unwrap()
+    }
+    fn test(text1: &str, text2: &str, ignore_var_names: bool, reference: bool) {
+        let (expr1, expr2) = (parse(text1), parse(text2));
+        assert_eq!(
+            expr1.structure_eq(&expr2, ignore_var_names).unwrap(),
+            reference
+        );
+        if reference {
+            assert_eq!(
+                expr1.structure_hash(ignore_var_names).unwrap(),
+                expr2.structure_hash(ignore_var_names).unwrap()
+            );
+        }
+    }
+    test("x*sin(y)+2.5", "x*sin(y)+2.5", false, true);
+    test("x + 1", "x+1", false, true);
+    test("x + 1", "y + 1", false, false);
+    test("x + 1", "y + 1", true, true);
+    test("{a}+{a}", "{a}+{b}", true, false);
+    test("{a}*{b}+{a}", "{x}*{y}+{x}", true, true);
+    test("{a}*{b}+{a}", "{y}*{x}+{y}", true, true);
+    test("{a}*{b}+{a}", "{x}*{y}+{y}", true, false);
+    test("x+1", "x-1", true, false);
+    test("sin(x)", "cos(x)", true, false);
+    test("x+1", "1+x", true, false);
+    test("(x+1)*2", "x+1*2", true, false);
+    test("x+1", "x+2", true, false);
+
+    let mut cleared = parse("x+1");
+    cleared.clear_deepex();
     assert_eq!(
-        format!("{}", flatex),
-        "unparse impossible, since deep expression optimized away"
+        cleared.structure_eq(&parse("x+1"), false),
+        Err(ExError::DeepExMissing)
     );
+    assert_eq!(cleared.structure_hash(true), Err(ExError::DeepExMissing));
 }
 
+#[cfg(all(test, feature = "std"))]
+use crate::Semantics;
+
+#[cfg(feature = "std")]
 #[test]
-fn test_unparse() {
-    fn test(text: &str, text_ref: &str) {
-        let flatex = flatten(DeepEx::<f64>::from_str(text).unwrap());
-        let deepex = flatex.deepex.unwrap();
-        assert_eq!(deepex.unparse(), text_ref);
-        let mut flatex_reparsed = flatten(DeepEx::<f64>::from_str(text).unwrap());
-        assert_eq!(flatex_reparsed.unparse().unwrap(), text_ref);
-        flatex_reparsed.clear_deepex();
-        assert!(flatex_reparsed.unparse().is_err());
+fn test_semantics() {
+    type Reference = fn(f64, f64) -> f64;
+    // expressions with references that apply the operators in the order of the string and
+    // whether fast results are close to the references
+    let corpus: [(&str, Reference, bool); 16] = [
+        ("2*3*x", |x, _| 2.0 * 3.0 * x, true),
+        ("x*0.1*3", |x, _| x * 0.1 * 3.0, true),
+        ("x+0.1+0.2-0.3", |x, _| x + 0.1 + 0.2 - 0.3, true),
+        ("x/y*2*3", |x, y| x / y * 2.0 * 3.0, true),
+        ("0*x", |x, _| 0.0 * x, true),
+        ("-(x*0)", |x, _| -(x * 0.0), true),
+        ("-0*x", |x, _| -0.0 * x, true),
+        ("1/x-1/x", |x, _| 1.0 / x - 1.0 / x, true),
+        ("sqrt(x-1)*0+y", |x, y| (x - 1.0).sqrt() * 0.0 + y, true),
+        (
+            "sin(x)^2+cos(y)^2",
+            |x, y| x.sin().powf(2.0) + y.cos().powf(2.0),
+            true,
+        ),
+        ("x-1+2", |x, _| x - 1.0 + 2.0, true),
+        ("x/2*4", |x, _| x / 2.0 * 4.0, true),
+        ("x-2-3", |x, _| x - 2.0 - 3.0, true),
+        ("x%2*3", |x, _| x % 2.0 * 3.0, true),
+        ("x*(10^308)*10", |x, _| x * 10f64.powf(308.0) * 10.0, false),
+        ("x*y*2*0.5", |x, y| x * y * 2.0 * 0.5, false),
+    ];
+    let values = [
+        (0.1, 0.2),
+        (-1.0, 3.0),
+        (0.0, -0.0),
+        (-0.0, 1.0),
+        (f64::INFINITY, 2.0),
+        (f64::NEG_INFINITY, f64::INFINITY),
+        (f64::NAN, 1.0),
+        (1e200, 1e108),
+    ];
+    let parse = |text, semantics| {
+        let options = ParseOptions {
+            semantics,
+            ..ParseOptions::default()
+        };
+        parse_with_options(text, &make_default_operators::<f64>(), &options).unwrap()
+    };
+    for (text, reference, fast_is_close) in corpus {
+        let strict = parse(text, Semantics::IeeeStrict);
+        let fast = parse(text, Semantics::Fast);
+        for (x, y) in values {
+            let vars = &[x, y][..strict.var_names().len()];
+            let reference = reference(x, y);
+            let strict_res = strict.eval(vars).unwrap();
+            assert_eq!(
+                strict_res.to_bits(),
+                reference.to_bits(),
+                "{} {:?}",
+                text,
+                vars
+            );
+            if fast_is_close {
+                let fast_res = fast.eval(vars).unwrap();
+                let close = (fast_res.is_nan() && reference.is_nan())
+                    || fast_res == reference
+                    || (fast_res - reference).abs() < 1e-12 * reference.abs().max(1.0);
+                assert!(close, "{} {:?}: {} vs {}", text, vars, fast_res, reference);
+            }
+        }
     }
-    let text = "5+x";
-    let text_ref = "5.0+{x}";
-    test(text, text_ref);
-    let text = "sin(5+var)^(1/{y})+{var}";
-    let text_ref = "sin(5.0+{var})^(1.0/{y})+{var}";
-    test(text, text_ref);
-    let text = "-(5+var)^(1/{y})+{var}";
-    let text_ref = "-(5.0+{var})^(1.0/{y})+{var}";
-    test(text, text_ref);
-    let text = "cos(sin(-(5+var)^(1/{y})))+{var}";
-    let text_ref = "cos(sin(-(5.0+{var})^(1.0/{y})))+{var}";
-    test(text, text_ref);
-    let text = "cos(sin(-5+var^(1/{y})))-{var}";
-    let text_ref = "cos(sin(-5.0+{var}^(1.0/{y})))-{var}";
-    test(text, text_ref);
-    let text = "cos(sin(-z+var*(1/{y})))+{var}";
-    let text_ref = "cos(sin(-({z})+{var}*(1.0/{y})))+{var}";
-    test(text, text_ref);
+
+    // documented differences of fast semantics
+    let regrouped = parse("x*0.1*3", Semantics::Fast);
+    assert_eq!(regrouped.eval(&[1.0]).unwrap(), 0.30000000000000004);
+    assert_eq!(regrouped.nodes.len(), 2);
+    // constant pairs after `-` or `/` of the same priority are not regrouped
+    for text in ["x-1+2", "x/2*4", "x-2-3", "x+y-1+2"] {
+        let fast = parse(text, Semantics::Fast);
+        assert_eq!(
+            fast.nodes.len(),
+            parse(text, Semantics::IeeeStrict).nodes.len()
+        );
+    }
+    let overflow = parse("x*(10^308)*10", Semantics::Fast);
+    assert_eq!(overflow.eval(&[0.1]).unwrap(), f64::INFINITY);
+
+    // the order of evaluation of strict expressions is kept by other operations
+    let strict = parse("x-1+2", Semantics::IeeeStrict);
+    assert_eq!(strict.eval_interval(&[(0.0, 0.0)]).unwrap(), (1.0, 1.0));
+    let combined = strict.clone() * parse("y", Semantics::Fast);
+    assert_float_eq_f64(combined.eval(&[0.0, 2.0]).unwrap(), 2.0);
+    let dx = parse("x*x-1+2", Semantics::IeeeStrict).partial(0).unwrap();
+    assert_float_eq_f64(dx.eval(&[3.0]).unwrap(), 6.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_vectorized() {
+    let text = "x*0.02*sin(-(3*(2*sin(x-1/(sin(y*5)+(5.0-1/z))))))";
+    let expr = parse_with_default_ops::<f64>(text).unwrap();
+    for n_points in [0, 1, 255, 256, 257, 1000] {
+        let xs = (0..n_points).map(|i| i as f64 * 0.01).collect::<Vec<_>>();
+        let ys = (0..n_points)
+            .map(|i| 1.0 - i as f64 * 0.5)
+            .collect::<Vec<_>>();
+        let zs = vec![4.0; n_points];
+        let mut out = vec![0.0; n_points];
+        expr.eval_vectorized(&[&xs, &ys, &zs], &mut out).unwrap();
+        for i in 0..n_points {
+            let reference = expr.eval(&[xs[i], ys[i], zs[i]]).unwrap();
+            assert_eq!(out[i].to_bits(), reference.to_bits());
+        }
+    }
+
+    let expr = parse_with_default_ops::<f64>("-(2^3)").unwrap();
+    let mut out = [0.0; 2];
+    expr.eval_vectorized(&[], &mut out).unwrap();
+    assert_eq!(out, [-8.0, -8.0]);
+
+    let expr = parse_with_default_ops::<f64>("x+y").unwrap();
+    let mut out = [0.0; 2];
+    assert_eq!(
+        expr.eval_vectorized(&[&[1.0, 2.0]], &mut out),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+    assert_eq!(
+        expr.eval_vectorized(&[&[1.0, 2.0], &[1.0]], &mut out),
+        Err(ExError::SeriesLengthMismatch {
+            series: 1,
+            expected: 2,
+            given: 1
+        })
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_bind() {
+    let expr = parse_with_default_ops::<f64>("x*y + sin(z)^2 + y/z").unwrap();
+    let (x, y, z) = (1.5, 2.0, 0.5);
+    let reference = expr.eval(&[x, y, z]).unwrap();
+
+    let bound_z = expr.bind(&[(2, z)]).unwrap();
+    assert_eq!(bound_z.var_names(), ["x", "y"]);
+    assert!(bound_z.nodes.len() < expr.nodes.len());
+    assert_float_eq_f64(bound_z.eval(&[x, y]).unwrap(), reference);
+
+    let bound = expr.bind(&[(2, z), (1, y)]).unwrap();
+    assert_eq!(bound.var_names(), ["x"]);
+    assert!(bound.nodes.len() < bound_z.nodes.len());
+    assert_float_eq_f64(bound.eval(&[x]).unwrap(), reference);
+    let bound_twice = expr.bind(&[(1, y)]).unwrap().bind(&[(1, z)]).unwrap();
+    assert_eq!(bound.unparse().unwrap(), bound_twice.unparse().unwrap());
+
+    // the remaining variables keep their order
+    let bound = expr.bind(&[(1, y)]).unwrap();
+    assert_eq!(bound.var_names(), ["x", "z"]);
+    assert_float_eq_f64(bound.eval(&[x, z]).unwrap(), reference);
+
+    let bound = expr.bind(&[(0, x), (1, y), (2, z)]).unwrap();
+    assert!(bound.var_names().is_empty());
+    assert_eq!(bound.nodes.len(), 1);
+    assert_float_eq_f64(bound.eval(&[]).unwrap(), reference);
+    assert_eq!(expr.bind(&[]).unwrap().unparse(), expr.unparse());
+
+    let names = NameResolution::default();
+    let bound = expr.bind_named(&[("z", z), ("x", x)], &names).unwrap();
+    assert_eq!(bound.var_names(), ["y"]);
+    assert_float_eq_f64(bound.eval(&[y]).unwrap(), reference);
+
+    assert_eq!(
+        expr.bind(&[(3, 1.0)]),
+        Err(ExError::VarIdxOutOfRange { idx: 3, n_vars: 3 })
+    );
+    assert_eq!(
+        expr.bind(&[(1, 1.0), (0, 1.0), (1, 2.0)]),
+        Err(ExError::DuplicateVar {
+            name: "y".to_string(),
+            passed: vec![("y".to_string(), 0), ("y".to_string(), 2)]
+        })
+    );
+    assert!(matches!(
+        expr.bind_named(&[("w", 1.0)], &names),
+        Err(ExError::UnknownVar { .. })
+    ));
+    let mut cleared = expr;
+    cleared.clear_deepex();
+    assert_eq!(cleared.bind(&[]), Err(ExError::DeepExMissing));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_debug_tree() {
+    let expr = parse_with_default_ops::<f64>("-sin(x)*2^y+1").unwrap();
+    let tree = expr.to_debug_tree();
+    assert_eq!(
+        tree,
+        "execution order: [1, 0, 2]
++ (op 2, prio 0, step 2)
+|-- * (op 0, prio 1, step 1)
+|   |-- x unary [-, sin]
+|   `-- ^ (op 1, prio 2, step 0)
+|       |-- 2.0
+|       `-- y
+`-- 1.0
+"
+    );
+    let dot = expr.to_dot();
+    assert!(dot.starts_with("digraph {\n"));
+    assert!(dot.contains("n1 [label=\"* (op 0, prio 1, step 1)\"];"));
+    assert!(dot.contains("n2 [label=\"x unary [-, sin]\"];"));
+    assert!(dot.contains("n0 -> n1;"));
+    assert_eq!(dot.matches("->").count(), 6);
+
+    // nested expressions are applied first and n-ary arguments have their own order
+    let tree = parse_with_default_ops::<f64>("logb(2, x+1)*-(x+y)")
+        .unwrap()
+        .to_debug_tree();
+    assert!(tree.contains("|-- logb(...)\n|   |-- 2.0\n|   `-- + (op 0, prio 0, step 0)"));
+    assert!(tree.contains("`-- + (op 1, prio 2, step 0) unary [-]"));
+
+    let expr = parse_with_default_ops::<f64>("sin(x)*sin(x)+cos(x)*cos(x)")
+        .unwrap()
+        .optimize_cse()
+        .unwrap();
+    let tree = expr.to_debug_tree();
+    assert!(tree.contains("tmp1\n"));
+    assert!(tree.contains("tmp0 = x unary [sin]\n"));
+    let dot = expr.to_dot();
+    assert!(dot.contains("[label=\"tmp0\"];"));
+    // common sub-expressions are part of the same graph
+    assert_eq!(dot.matches("digraph").count(), 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_float_conversion() {
+    use crate::{parse, util::assert_float_eq};
+    use core::convert::TryInto;
+    let texts = [
+        "2*sin(x)^2 + cos(y)/3",
+        "-(x+y)*exp(-x^2)",
+        "logb(2, x+2) + atan(y)",
+        "sqrt(x*x + y*y) - 0.1",
+    ];
+    let points = [[0.5, 1.5], [-1.25, 0.75], [2.0, -0.3]];
+    for text in texts {
+        let expr = parse_with_default_ops::<f64>(text).unwrap();
+        let expr_f32 = expr.to_f32().unwrap();
+        assert_eq!(expr_f32.var_names(), expr.var_names());
+        assert_eq!(expr_f32.to_string(), text);
+        let expr_f64 = FlatEx::try_from(expr_f32.clone()).unwrap();
+        for [x, y] in points {
+            let reference = expr.eval(&[x, y]).unwrap();
+            let value = expr_f32.eval(&[x as f32, y as f32]).unwrap();
+            assert_float_eq::<f32>(value, reference as f32, 1e-5, text);
+            let value = expr_f64.eval(&[x, y]).unwrap();
+            assert_float_eq::<f64>(value, reference, 1e-6, text);
+        }
+    }
+
+    // derivatives have no text but can be converted as well
+    let d_x = parse_with_default_ops::<f64>("x^3")
+        .unwrap()
+        .partial(0)
+        .unwrap();
+    let d_x: FlatEx<f32> = d_x.try_into().unwrap();
+    assert_float_eq::<f32>(d_x.eval(&[2.0]).unwrap(), 12.0, 1e-6, "");
+
+    // spellings of numbers are kept
+    let options = ParseOptions {
+        preserve_literal_text: true,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    let expr = parse_with_options("x*1.10", &ops, &options).unwrap();
+    assert_eq!(expr.to_f32().unwrap().unparse().unwrap(), "x*1.10");
+    for text in ["2*x", "(2.0)*x", "x*(1.50)"] {
+        let expr = parse_with_options(text, &ops, &options).unwrap();
+        let value = expr.eval(&[1.0]).unwrap();
+        let d_x = expr.partial(0).unwrap().unparse().unwrap();
+        let d_x = parse_with_default_ops::<f64>(&d_x).unwrap();
+        assert_float_eq_f64(d_x.eval(&[]).unwrap(), value);
+    }
+
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(Operator {
+        repr: "dbl",
+        bin_op: None,
+        unary_op: Some(|a| 2.0 * a),
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    });
+    let expr = parse("dbl(x)+1", &ops).unwrap();
+    assert_eq!(
+        expr.to_f32().unwrap_err(),
+        ExError::OperatorNotFound("dbl".to_string())
+    );
+    let mut expr = parse_with_default_ops::<f64>("x+1").unwrap();
+    expr.clear_deepex();
+    assert_eq!(expr.to_f32().unwrap_err(), ExError::DeepExMissing);
+}
+
+#[cfg(all(test, feature = "std"))]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_random_priorities() {
+    // operands are numbers, variables, or parenthesized sub-expressions
+    enum Operand {
+        Num(f64),
+        Var(usize),
+        Paren(Vec<Operand>, Vec<usize>),
+    }
+    fn make_expr(rng: &mut StdRng, n_ops: usize, depth: usize, with_nums: bool) -> Operand {
+        let n_operands = rng.gen_range(1..5);
+        let operands = (0..n_operands)
+            .map(|_| match rng.gen_range(0..4) {
+                0 if depth > 0 => make_expr(rng, n_ops, depth - 1, with_nums),
+                1 | 2 if with_nums => Operand::Num(f64::from(rng.gen_range(1..8)) * 0.25),
+                _ => Operand::Var(rng.gen_range(0..3)),
+            })
+            .collect::<Vec<_>>();
+        let ops = (1..n_operands).map(|_| rng.gen_range(0..n_ops)).collect();
+        Operand::Paren(operands, ops)
+    }
+    fn unparse(operand: &Operand, reprs: &[&str]) -> String {
+        match operand {
+            Operand::Num(n) => format!("{}", n),
+            Operand::Var(i) => ["x", "y", "z"][*i].to_string(),
+            Operand::Paren(operands, ops) => {
+                let mut s = unparse(&operands[0], reprs);
+                for (op, operand) in ops.iter().zip(operands[1..].iter()) {
+                    s.push_str(reprs[*op]);
+                    s.push_str(&unparse(operand, reprs));
+                }
+                format!("({})", s)
+            }
+        }
+    }
+    // straightforward reference that repeatedly applies the leftmost operator with the
+    // highest priority
+    fn eval(operand: &Operand, bin_ops: &[BinOp<f64>], vars: &[f64]) -> f64 {
+        match operand {
+            Operand::Num(n) => *n,
+            Operand::Var(i) => vars[*i],
+            Operand::Paren(operands, ops) => {
+                let mut vals = operands
+                    .iter()
+                    .map(|o| eval(o, bin_ops, vars))
+                    .collect::<Vec<_>>();
+                let mut ops = ops.clone();
+                while !ops.is_empty() {
+                    let max_prio = ops.iter().map(|op| bin_ops[*op].prio).max().unwrap();
+                    let idx = ops
+                        .iter()
+                        .position(|op| bin_ops[*op].prio == max_prio)
+                        .unwrap();
+                    let op = ops.remove(idx);
+                    let right = vals.remove(idx + 1);
+                    vals[idx] = (bin_ops[op].apply)(vals[idx], right);
+                }
+                vals[0]
+            }
+        }
+    }
+
+    let reprs = ["+", "-", "*", "/", "&", "#"];
+    let applies: [fn(f64, f64) -> f64; 6] = [
+        |a, b| a + b,
+        |a, b| a - b,
+        |a, b| a * b,
+        |a, b| a / b,
+        |a, b| a - 2.0 * b,
+        |a, b| 0.5 * a + b,
+    ];
+    let mut rng = StdRng::seed_from_u64(2080);
+    let prio_pool = [
+        i32::MIN,
+        i32::MIN + 1,
+        -100,
+        -1,
+        0,
+        1,
+        100,
+        i32::MAX - 1,
+        i32::MAX,
+    ];
+    for _ in 0..200 {
+        let bin_ops = applies
+            .iter()
+            .map(|apply| BinOp {
+                apply: *apply,
+                prio: if rng.gen_bool(0.5) {
+                    prio_pool[rng.gen_range(0..prio_pool.len())]
+                } else {
+                    rng.gen()
+                },
+            })
+            .collect::<Vec<_>>();
+        let ops = reprs
+            .iter()
+            .zip(bin_ops.iter())
+            .map(|(repr, bin_op)| Operator {
+                repr,
+                bin_op: Some(*bin_op),
+                unary_op: None,
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
+            })
+            .collect::<Vec<_>>();
+        // fast semantics may reorder pairs of constants, hence only variables are used
+        for (semantics, with_nums) in [(Semantics::IeeeStrict, true), (Semantics::Fast, false)] {
+            let options = ParseOptions {
+                semantics,
+                ..ParseOptions::default()
+            };
+            for _ in 0..5 {
+                let expr = make_expr(&mut rng, reprs.len(), 3, with_nums);
+                let text = unparse(&expr, &reprs);
+                let flatex = parse_with_options::<f64>(&text, &ops, &options).unwrap();
+                let vars = [0.5, -1.25, 3.0];
+                let mut flat_vars = vec![];
+                for name in flatex.var_names() {
+                    flat_vars.push(vars[["x", "y", "z"].iter().position(|v| v == name).unwrap()]);
+                }
+                let reference = eval(&expr, &bin_ops, &vars);
+                let res = flatex.eval(&flat_vars).unwrap();
+                assert!(
+                    res.to_bits() == reference.to_bits() || (res.is_nan() && reference.is_nan()),
+                    "{} with prios {:?}: {} vs {}",
+                    text,
+                    bin_ops.iter().map(|op| op.prio).collect::<Vec<_>>(),
+                    res,
+                    reference
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_execution_order() {
+    let order = |text| {
+        parse_with_default_ops::<f64>(text)
+            .unwrap()
+            .execution_order()
+    };
+    let vars = ('a'..='z').map(|c| c.to_string()).collect::<Vec<_>>();
+    let chain = vars.join("+");
+    assert_eq!(order(&chain), (0..25).collect::<Vec<_>>());
+    assert_eq!(order("a-b+c-d"), [0, 1, 2]);
+    assert_eq!(order("a*b+c*d-e/f"), [0, 2, 4, 1, 3]);
+    assert_eq!(order("a^b^c"), [0, 1]);
+    assert_eq!(order("(a+b)+(c+d)"), [0, 2, 1]);
+    assert_eq!(order("a-(b-(c-d))*e"), [2, 1, 3, 0]);
+
+    // equal priorities of left-associative operators are applied from left to right,
+    // also with fast semantics, where constant pairs after `-` or `/` are not regrouped
+    let strict = ParseOptions {
+        semantics: Semantics::IeeeStrict,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    let strict_order = |text| {
+        parse_with_options(text, &ops, &strict)
+            .unwrap()
+            .execution_order()
+    };
+    for text in ["x-1+2", "x/2*4", "x-2-3", "x/2/4", "x%2*3", "x+y-1+2"] {
+        let n_ops = text.matches(|c| "+-*/%".contains(c)).count();
+        let left_to_right = (0..n_ops).collect::<Vec<_>>();
+        assert_eq!(order(text), left_to_right, "{}", text);
+        assert_eq!(strict_order(text), left_to_right, "{}", text);
+    }
+    // constant pairs are evaluated first, i.e., folded, only with fast semantics and
+    // only where regrouping keeps the value for real numbers
+    assert_eq!(order("x+1-2"), [0]);
+    assert_eq!(order("x*2/4"), [0]);
+    assert_eq!(order("x-y+1-2"), [0, 1]);
+    assert_eq!(strict_order("x+1-2"), [0, 1]);
+    assert_eq!(strict_order("x*2/4"), [0, 1]);
+
+    // equal priorities are applied from left to right, which determines the rounding
+    let expr = parse_with_default_ops::<f64>(&chain).unwrap();
+    let values = (0..26).map(|i| 0.1 * f64::from(i + 1)).collect::<Vec<_>>();
+    let left_to_right = values.iter().fold(0.0, |acc, v| acc + v);
+    assert_eq!(
+        expr.eval(&values).unwrap().to_bits(),
+        left_to_right.to_bits()
+    );
+    let expr = parse_with_default_ops::<f64>("x+y+z").unwrap();
+    assert_eq!(expr.eval(&[0.1, 0.2, 0.3]).unwrap(), 0.6000000000000001);
+    let expr = parse_with_default_ops::<f64>("x+(y+z)").unwrap();
+    assert_eq!(expr.eval(&[0.1, 0.2, 0.3]).unwrap(), 0.6);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_with_grad() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let texts = [
+        "x^2 * sin(y)",
+        "x^3 - 2*x*y + y^2",
+        "exp(-(x^2 + y^2)) / (1 + abs(x))",
+        "sin(x*y)^2*cos(x-y) + sin(x*y)^2*z",
+        "logb(2, x^2 + 1) + logb(y + 3, 10)",
+        "-sqrt(x^2 + 1) * tanh(-y) + atan(z/2)",
+        "(x > y) * x + (x <= y) * y^2",
+        "cbrt(x) * exp2(y) - log10(z^2 + 1)",
+        "(3*x^2) % (y + 2.5) + x % -0.7",
+        "floor(x)*y^2 + round(y*3)*sign(x) - ceil(z)*z",
+        "7",
+    ];
+    for text in texts {
+        let expr = parse_with_default_ops::<f64>(text).unwrap();
+        let grad = expr.grad().unwrap();
+        let cse = expr.clone().optimize_cse().unwrap();
+        let mut cleared = expr.clone();
+        cleared.clear_deepex();
+        for _ in 0..20 {
+            let vars = (0..expr.var_names().len())
+                .map(|_| rng.gen_range(-2.0..2.0))
+                .collect::<Vec<f64>>();
+            for e in [&expr, &cse, &cleared] {
+                let (val, g) = e.eval_with_grad(&vars).unwrap();
+                assert_float_eq_f64(val, expr.eval(&vars).unwrap());
+                assert_eq!(g.len(), grad.len());
+                for (g_i, d_i) in g.iter().zip(grad.iter()) {
+                    let reference = d_i.eval(&vars).unwrap();
+                    if reference.is_finite() {
+                        assert!(
+                            (g_i - reference).abs() <= 1e-10 * reference.abs().max(1.0),
+                            "{} at {:?}: {} vs {}",
+                            text,
+                            vars,
+                            g_i,
+                            reference
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // constant exponents do not need the logarithm of a negative base
+    let expr = parse_with_default_ops::<f64>("x^3").unwrap();
+    assert_eq!(
+        expr.eval_with_grad(&[-2.0]).unwrap(),
+        (-8.0, smallvec![12.0])
+    );
+
+    let expr = parse_with_default_ops::<f64>("signum(x) * y").unwrap();
+    assert_eq!(
+        expr.eval_with_grad(&[1.0, 2.0]),
+        Err(ExError::DerivativeNotFound("signum".to_string()))
+    );
+    assert_eq!(
+        expr.eval_with_grad(&[1.0]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_constant() {
+    // the variables of the parsed string are kept, also if the value does not depend on them
+    for (text, value) in [("x*0", 0.0), ("0*x+1", 1.0)] {
+        let expr = parse_with_default_ops::<f64>(text).unwrap();
+        assert_eq!(expr.var_names(), ["x"]);
+        assert!(!expr.is_constant());
+        assert_eq!(expr.constant_value(), None);
+        assert_eq!(expr.eval(&[123.0]).unwrap(), value);
+        assert!(expr.eval(&[f64::INFINITY]).unwrap().is_nan());
+        assert_eq!(
+            expr.eval(&[]),
+            Err(ExError::WrongNumberOfVars {
+                expected: 1,
+                given: 0
+            })
+        );
+    }
+    let expr = parse_with_default_ops::<f64>("2*3").unwrap();
+    assert!(expr.var_names().is_empty());
+    assert!(expr.is_constant());
+    assert_eq!(expr.constant_value(), Some(6.0));
+    assert_eq!(expr.eval(&[]).unwrap(), 6.0);
+    assert_eq!(
+        expr.eval(&[123.0]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 0,
+            given: 1
+        })
+    );
+
+    // derivatives keep the variables of their antiderivatives
+    let d_x = parse_with_default_ops::<f64>("2*x + sin(y)")
+        .unwrap()
+        .partial(0)
+        .unwrap();
+    assert!(d_x.is_constant());
+    assert_eq!(d_x.var_names(), ["x", "y"]);
+    assert_eq!(d_x.constant_value(), Some(2.0));
+    assert_eq!(d_x.eval(&[123.0, 4.0]).unwrap(), 2.0);
+    assert!(d_x.eval(&[]).is_err());
+
+    // constant common sub-expressions and arguments of n-ary operators
+    let strict = ParseOptions {
+        semantics: Semantics::IeeeStrict,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    let expr = parse_with_options("sin(1+2)*sin(1+2) + 0*x", &ops, &strict)
+        .unwrap()
+        .partial(0)
+        .unwrap()
+        .optimize_cse()
+        .unwrap();
+    assert!(expr.is_constant());
+    assert_eq!(expr.constant_value(), Some(0.0));
+    let expr = parse_with_options("logb(2, x) + logb(2, 8)", &ops, &strict).unwrap();
+    assert!(!expr.is_constant());
+    let d_x = expr.partial(0).unwrap();
+    assert!(!d_x.is_constant());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_with_ops() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static N_DIVISIONS: AtomicUsize = AtomicUsize::new(0);
+    let mut counting_ops = make_default_operators::<f64>().to_vec();
+    for op in counting_ops.iter_mut().filter(|op| op.repr == "/") {
+        op.bin_op = Some(BinOp {
+            apply: |a, b| {
+                N_DIVISIONS.fetch_add(1, Ordering::Relaxed);
+                a / b
+            },
+            prio: op.bin_op.unwrap().prio,
+        });
+    }
+    let text = "x/y + sin(1/(x/2)) - y/(x/y)";
+    let expr = parse_with_default_ops::<f64>(text).unwrap();
+    let counted = expr.with_ops(&counting_ops).unwrap();
+    assert_eq!(counted.unparse().unwrap(), expr.unparse().unwrap());
+    for (i, vars) in [[1.0, 2.0], [0.5, -3.0], [7.0, 0.25]].iter().enumerate() {
+        assert_eq!(N_DIVISIONS.load(Ordering::Relaxed), 5 * i);
+        assert_float_eq_f64(counted.eval(vars).unwrap(), expr.eval(vars).unwrap());
+    }
+    assert_eq!(N_DIVISIONS.load(Ordering::Relaxed), 15);
+
+    // common sub-expressions, n-ary operators, and expressions without deep expression
+    let mut expr = parse_with_default_ops::<f64>("sin(x/y) * sin(x/y) + logb(2, y/x)")
+        .unwrap()
+        .optimize_cse()
+        .unwrap();
+    expr.clear_deepex();
+    let counted = expr.with_ops(&counting_ops).unwrap();
+    N_DIVISIONS.store(0, Ordering::Relaxed);
+    assert_float_eq_f64(
+        counted.eval(&[8.0, 2.0]).unwrap(),
+        expr.eval(&[8.0, 2.0]).unwrap(),
+    );
+    assert_eq!(N_DIVISIONS.load(Ordering::Relaxed), 2);
+
+    // the deep expression is rebound as well such that derivatives can be computed
+    let d_x = parse_with_default_ops::<f64>("x/y")
+        .unwrap()
+        .with_ops(&counting_ops)
+        .unwrap()
+        .partial(0)
+        .unwrap();
+    assert_float_eq_f64(d_x.eval(&[3.0, 4.0]).unwrap(), 0.25);
+
+    let expr = parse_with_default_ops::<f64>("-sin(x)/y + logb(2, x)").unwrap();
+    let without = |repr: &str| {
+        counting_ops
+            .iter()
+            .filter(|op| op.repr != repr)
+            .copied()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        expr.with_ops(&without("/")),
+        Err(ExError::OperatorNotFound("/".to_string()))
+    );
+    let mut ops = without("sin");
+    ops.push(Operator {
+        repr: "sin",
+        bin_op: Some(BinOp {
+            apply: |a, b| a * b,
+            prio: 0,
+        }),
+        unary_op: None,
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    });
+    assert_eq!(
+        expr.with_ops(&ops),
+        Err(ExError::OperatorNotUnary("sin".to_string()))
+    );
+    let mut ops = counting_ops.clone();
+    for op in ops.iter_mut().filter(|op| op.repr == "logb") {
+        op.nary_op = Some(NaryOp {
+            apply: |args| args[0],
+            arity: 1,
+        });
+    }
+    assert_eq!(
+        expr.with_ops(&ops),
+        Err(ExError::OperatorNotFound("logb".to_string()))
+    );
+
+    // fallible operations are taken from the new operators
+    let ops = make_default_operators_int::<i64>();
+    let expr = parse_with_number_pattern::<i64>("x/y", &ops, "[0-9]+").unwrap();
+    let mut checked_ops = ops.to_vec();
+    for op in checked_ops.iter_mut().filter(|op| op.repr == "/") {
+        op.fallible = Some(FallibleOp {
+            bin_op: Some(|a: i64, b| a.checked_div(b).ok_or_else(|| "division by zero".into())),
+            unary_op: None,
+        });
+    }
+    let checked = expr.with_ops(&checked_ops).unwrap();
+    assert_eq!(checked.eval(&[7, 2]), Ok(3));
+    assert_eq!(checked.eval(&[1, 0]).unwrap_err().code(), "E055");
 }
@@ -0,0 +1,783 @@
+use num::Float;
+use smallvec::SmallVec;
+use std::{fmt::Debug, mem};
+
+use super::{
+    deep::{DeepEx, DeepNode},
+    deep_details,
+};
+use crate::{definitions::N_NODES_ON_STACK, ExError};
+
+/// Closed interval `[lo, hi]` that contains all possible values of an expression.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Interval<T: Float> {
+    lo: T,
+    hi: T,
+}
+
+impl<T: Float> Interval<T> {
+    /// Creates the interval `[lo, hi]`, which is unbounded if an endpoint is undefined, e.g.,
+    /// for `inf-inf`.
+    fn new(lo: T, hi: T) -> Self {
+        if lo.is_nan() || hi.is_nan() {
+            Self::unbounded()
+        } else {
+            Interval { lo, hi }
+        }
+    }
+    fn point(x: T) -> Self {
+        Interval { lo: x, hi: x }
+    }
+    fn unbounded() -> Self {
+        Interval {
+            lo: T::neg_infinity(),
+            hi: T::infinity(),
+        }
+    }
+    fn contains(&self, x: T) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+    /// Smallest interval containing all passed values. An undefined value, e.g., of `0*inf`,
+    /// could be anything, hence, the interval is unbounded then.
+    fn hull(values: &[T]) -> Self {
+        if values.iter().any(|v| v.is_nan()) {
+            return Self::unbounded();
+        }
+        values
+            .iter()
+            .skip(1)
+            .fold(Self::point(values[0]), |res, v| {
+                Interval::new(res.lo.min(*v), res.hi.max(*v))
+            })
+    }
+    /// Applies a non-decreasing function to the endpoints.
+    fn map_increasing(self, f: fn(T) -> T) -> Self {
+        Interval::new(f(self.lo), f(self.hi))
+    }
+    /// Applies a non-increasing function to the endpoints.
+    fn map_decreasing(self, f: fn(T) -> T) -> Self {
+        Interval::new(f(self.hi), f(self.lo))
+    }
+    /// Returns the part of the interval within the domain `[lo, hi]` of a function, if any.
+    /// Arguments outside of the domain result in `NaN` and have no value to be bounded.
+    fn restrict(self, lo: T, hi: T) -> Option<Self> {
+        let restricted = Interval::new(self.lo.max(lo), self.hi.min(hi));
+        (restricted.lo <= restricted.hi).then_some(restricted)
+    }
+    /// Applies a non-decreasing function with the domain `[lo, hi]` to the endpoints of the
+    /// part of the interval within the domain. Without such a part, the interval is unbounded.
+    fn map_increasing_on(self, lo: T, hi: T, f: fn(T) -> T) -> Self {
+        self.restrict(lo, hi)
+            .map_or_else(Self::unbounded, |restricted| restricted.map_increasing(f))
+    }
+    /// Applies a non-increasing function with the domain `[lo, hi]` like
+    /// [`map_increasing_on`](Interval::map_increasing_on).
+    fn map_decreasing_on(self, lo: T, hi: T, f: fn(T) -> T) -> Self {
+        self.restrict(lo, hi)
+            .map_or_else(Self::unbounded, |restricted| restricted.map_decreasing(f))
+    }
+    /// Checks whether the interval contains `offset + k * period` for some integer `k`.
+    fn hits_grid(&self, offset: T, period: T) -> bool {
+        ((self.lo - offset) / period).ceil() <= ((self.hi - offset) / period).floor()
+    }
+}
+
+fn sin_interval<T: Float>(x: Interval<T>) -> Interval<T> {
+    let pi = T::from(std::f64::consts::PI).unwrap();
+    let two_pi = pi + pi;
+    let half_pi = pi / T::from(2.0).unwrap();
+    let one = T::one();
+    if x.hi - x.lo >= two_pi {
+        Interval::new(-one, one)
+    } else {
+        let endpoints = Interval::hull(&[x.lo.sin(), x.hi.sin()]);
+        let lo = if x.hits_grid(-half_pi, two_pi) {
+            -one
+        } else {
+            endpoints.lo
+        };
+        let hi = if x.hits_grid(half_pi, two_pi) {
+            one
+        } else {
+            endpoints.hi
+        };
+        Interval::new(lo, hi)
+    }
+}
+
+/// Results of a comparison that is `1` where it holds and `0` where it fails. Both are
+/// possible if neither is certain.
+fn indicator_interval<T: Float>(holds: bool, fails: bool) -> Interval<T> {
+    match (holds, fails) {
+        (true, _) => Interval::point(T::one()),
+        (_, true) => Interval::point(T::zero()),
+        _ => Interval::new(T::zero(), T::one()),
+    }
+}
+
+fn div_interval<T: Float>(a: Interval<T>, b: Interval<T>) -> Interval<T> {
+    if b.contains(T::zero()) {
+        Interval::unbounded()
+    } else {
+        Interval::hull(&[a.lo / b.lo, a.lo / b.hi, a.hi / b.lo, a.hi / b.hi])
+    }
+}
+
+fn rem_interval<T: Float>(a: Interval<T>, b: Interval<T>) -> Interval<T> {
+    if b.contains(T::zero()) {
+        Interval::unbounded()
+    } else {
+        // the result has the sign of the dividend and is smaller than the divisor in
+        // absolute value
+        let bound = b.lo.abs().max(b.hi.abs());
+        Interval::new(
+            a.lo.max(-bound).min(T::zero()),
+            a.hi.min(bound).max(T::zero()),
+        )
+    }
+}
+
+fn pow_interval<T: Float>(base: Interval<T>, exponent: Interval<T>) -> Interval<T> {
+    let corners = || {
+        Interval::hull(&[
+            base.lo.powf(exponent.lo),
+            base.lo.powf(exponent.hi),
+            base.hi.powf(exponent.lo),
+            base.hi.powf(exponent.hi),
+        ])
+    };
+    if base.lo > T::zero() {
+        // for positive bases, powf is monotonic in both arguments
+        corners()
+    } else if exponent.lo == exponent.hi && exponent.lo.fract() == T::zero() {
+        let n = exponent.lo;
+        let is_even = (n / T::from(2.0).unwrap()).fract() == T::zero();
+        if n == T::zero() {
+            Interval::point(T::one())
+        } else if !base.contains(T::zero()) {
+            Interval::hull(&[base.lo.powf(n), base.hi.powf(n)])
+        } else if n < T::zero() {
+            Interval::unbounded()
+        } else if is_even {
+            Interval::new(T::zero(), base.lo.powf(n).max(base.hi.powf(n)))
+        } else {
+            Interval::new(base.lo.powf(n), base.hi.powf(n))
+        }
+    } else {
+        // non-integer powers of negative numbers are not defined
+        Interval::unbounded()
+    }
+}
+
+type BinOpInterval<T> = fn(Interval<T>, Interval<T>) -> Interval<T>;
+type UnaryOpInterval<T> = fn(Interval<T>) -> Interval<T>;
+type NaryOpInterval<T> = fn(&[Interval<T>]) -> Interval<T>;
+
+/// Transfer function of an operator, i.e., maps intervals of operands to an interval
+/// that contains all results.
+struct IntervalOp<'a, T: Float> {
+    repr: &'a str,
+    bin_op: Option<BinOpInterval<T>>,
+    unary_op: Option<UnaryOpInterval<T>>,
+    nary_op: Option<NaryOpInterval<T>>,
+}
+
+fn make_interval_ops<'a, T: Float>() -> [IntervalOp<'a, T>; 41] {
+    [
+        IntervalOp {
+            repr: "^",
+            bin_op: Some(pow_interval),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "*",
+            bin_op: Some(|a, b| {
+                Interval::hull(&[a.lo * b.lo, a.lo * b.hi, a.hi * b.lo, a.hi * b.hi])
+            }),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "/",
+            bin_op: Some(div_interval),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "+",
+            bin_op: Some(|a, b| Interval::new(a.lo + b.lo, a.hi + b.hi)),
+            unary_op: Some(|a| a),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "-",
+            bin_op: Some(|a, b| Interval::new(a.lo - b.hi, a.hi - b.lo)),
+            unary_op: Some(|a| Interval::new(-a.hi, -a.lo)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "signum",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::signum)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "sin",
+            bin_op: None,
+            unary_op: Some(sin_interval),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "cos",
+            bin_op: None,
+            unary_op: Some(|a| {
+                let half_pi = T::from(std::f64::consts::FRAC_PI_2).unwrap();
+                sin_interval(Interval::new(a.lo + half_pi, a.hi + half_pi))
+            }),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "tan",
+            bin_op: None,
+            unary_op: Some(|a| {
+                let pi = T::from(std::f64::consts::PI).unwrap();
+                let half_pi = T::from(std::f64::consts::FRAC_PI_2).unwrap();
+                if a.hi - a.lo >= pi || a.hits_grid(half_pi, pi) {
+                    Interval::unbounded()
+                } else {
+                    a.map_increasing(T::tan)
+                }
+            }),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "asin",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(-T::one(), T::one(), T::asin)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "acos",
+            bin_op: None,
+            unary_op: Some(|a| a.map_decreasing_on(-T::one(), T::one(), T::acos)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "atan",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::atan)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "sinh",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::sinh)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "cosh",
+            bin_op: None,
+            unary_op: Some(|a| {
+                let endpoints = Interval::hull(&[a.lo.cosh(), a.hi.cosh()]);
+                if a.contains(T::zero()) {
+                    Interval::new(T::one(), endpoints.hi)
+                } else {
+                    endpoints
+                }
+            }),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "tanh",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::tanh)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "asinh",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::asinh)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "acosh",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(T::one(), T::infinity(), T::acosh)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "atanh",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(-T::one(), T::one(), T::atanh)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "floor",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::floor)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "ceil",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::ceil)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "trunc",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::trunc)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "fract",
+            bin_op: None,
+            unary_op: Some(|a| {
+                let one = T::one();
+                if a.lo.trunc() == a.hi.trunc() {
+                    a.map_increasing(T::fract)
+                } else if a.lo >= T::zero() {
+                    Interval::new(T::zero(), one)
+                } else if a.hi <= T::zero() {
+                    Interval::new(-one, T::zero())
+                } else {
+                    Interval::new(-one, one)
+                }
+            }),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "exp",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::exp)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "sqrt",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(T::zero(), T::infinity(), T::sqrt)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "log",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(T::zero(), T::infinity(), T::ln)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "log2",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(T::zero(), T::infinity(), T::log2)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "%",
+            bin_op: Some(rem_interval),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "sign",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(|x| if x == T::zero() { x } else { x.signum() })),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "abs",
+            bin_op: None,
+            unary_op: Some(|a| {
+                let endpoints = Interval::hull(&[a.lo.abs(), a.hi.abs()]);
+                if a.contains(T::zero()) {
+                    Interval::new(T::zero(), endpoints.hi)
+                } else {
+                    endpoints
+                }
+            }),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "round",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::round)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "exp2",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::exp2)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "cbrt",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing(T::cbrt)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "ln",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(T::zero(), T::infinity(), T::ln)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "log10",
+            bin_op: None,
+            unary_op: Some(|a| a.map_increasing_on(T::zero(), T::infinity(), T::log10)),
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "<",
+            bin_op: Some(|a, b| indicator_interval(a.hi < b.lo, a.lo >= b.hi)),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "<=",
+            bin_op: Some(|a, b| indicator_interval(a.hi <= b.lo, a.lo > b.hi)),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: ">",
+            bin_op: Some(|a, b| indicator_interval(a.lo > b.hi, a.hi <= b.lo)),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: ">=",
+            bin_op: Some(|a, b| indicator_interval(a.lo >= b.hi, a.hi < b.lo)),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "==",
+            bin_op: Some(|a, b| {
+                indicator_interval(
+                    a.lo == a.hi && b.lo == b.hi && a.lo == b.lo,
+                    a.hi < b.lo || b.hi < a.lo,
+                )
+            }),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "!=",
+            bin_op: Some(|a, b| {
+                indicator_interval(
+                    a.hi < b.lo || b.hi < a.lo,
+                    a.lo == a.hi && b.lo == b.hi && a.lo == b.lo,
+                )
+            }),
+            unary_op: None,
+            nary_op: None,
+        },
+        IntervalOp {
+            repr: "logb",
+            bin_op: None,
+            unary_op: None,
+            nary_op: Some(|args| {
+                let ln = |a: Interval<T>| a.map_increasing_on(T::zero(), T::infinity(), T::ln);
+                div_interval(ln(args[1]), ln(args[0]))
+            }),
+        },
+    ]
+}
+
+fn find_interval_op<'a, 'b, T: Float>(
+    repr: &str,
+    interval_ops: &'b [IntervalOp<'a, T>],
+) -> Result<&'b IntervalOp<'a, T>, ExError> {
+    interval_ops
+        .iter()
+        .find(|op| op.repr == repr)
+        .ok_or_else(|| ExError::IntervalOpNotFound(repr.to_string()))
+}
+
+/// Expression whose interval is computed on the explicit stack of
+/// [`eval_interval_deepex`](eval_interval_deepex).
+struct IntervalFrame<'b, 'a, T: Float + Debug> {
+    deepex: &'b DeepEx<'a, T>,
+    /// Intervals of the first nodes.
+    values: SmallVec<[Interval<T>; N_NODES_ON_STACK]>,
+    /// Intervals of the first arguments of the call at the next node.
+    args: SmallVec<[Interval<T>; N_NODES_ON_STACK]>,
+}
+
+impl<'b, 'a, T: Float + Debug> IntervalFrame<'b, 'a, T> {
+    fn new(deepex: &'b DeepEx<'a, T>) -> Self {
+        IntervalFrame {
+            deepex,
+            values: SmallVec::new(),
+            args: SmallVec::new(),
+        }
+    }
+}
+
+/// Applies the binary and unary operators of `deepex` to the intervals of its nodes.
+fn apply_interval_ops<T: Float + Debug>(
+    deepex: &DeepEx<T>,
+    mut values: SmallVec<[Interval<T>; N_NODES_ON_STACK]>,
+    interval_ops: &[IntervalOp<T>],
+) -> Result<Interval<T>, ExError> {
+    let prio_indices =
+        deep_details::prioritized_indices(deepex.bin_ops(), deepex.nodes(), deepex.optimizations());
+    let mut num_inds = prio_indices.clone();
+    for (i, &bin_op_idx) in prio_indices.iter().enumerate() {
+        let num_idx = num_inds[i];
+        let repr = deepex.bin_ops().reprs[bin_op_idx];
+        let bin_op = find_interval_op(repr, interval_ops)?
+            .bin_op
+            .ok_or_else(|| ExError::OperatorNotBinary(repr.to_string()))?;
+        values[num_idx] = bin_op(values[num_idx], values[num_idx + 1]);
+        values.remove(num_idx + 1);
+        // reduce indices after removed position
+        for num_idx_after in num_inds.iter_mut() {
+            if *num_idx_after > num_idx {
+                *num_idx_after -= 1;
+            }
+        }
+    }
+    // the last unary operator is applied first
+    deepex
+        .unary_op()
+        .reprs
+        .iter()
+        .rev()
+        .try_fold(values[0], |value, repr| {
+            let unary_op = find_interval_op(repr, interval_ops)?
+                .unary_op
+                .ok_or_else(|| ExError::OperatorNotUnary(repr.to_string()))?;
+            Ok(unary_op(value))
+        })
+}
+
+/// Computes the interval of `deepex`. Sub-expressions and arguments are evaluated on an
+/// explicit stack such that deeply nested expressions cannot overflow the call stack.
+fn eval_interval_deepex<T: Float + Debug>(
+    deepex: &DeepEx<T>,
+    var_ranges: &[Interval<T>],
+    interval_ops: &[IntervalOp<T>],
+) -> Result<Interval<T>, ExError> {
+    let mut stack = vec![IntervalFrame::new(deepex)];
+    while let Some(frame) = stack.last_mut() {
+        let deepex = frame.deepex;
+        match deepex.nodes().get(frame.values.len()) {
+            Some(DeepNode::Num(n, _)) => frame.values.push(Interval::point(*n)),
+            Some(DeepNode::Var((idx, _))) => frame.values.push(var_ranges[*idx]),
+            Some(DeepNode::Expr(e)) => stack.push(IntervalFrame::new(e)),
+            Some(DeepNode::NaryCall { repr, args, .. }) => match args.get(frame.args.len()) {
+                Some(arg) => stack.push(IntervalFrame::new(arg)),
+                None => {
+                    let nary_op = find_interval_op(repr, interval_ops)?
+                        .nary_op
+                        .ok_or_else(|| ExError::IntervalOpNotFound(repr.to_string()))?;
+                    let value = nary_op(&frame.args);
+                    frame.args.clear();
+                    frame.values.push(value);
+                }
+            },
+            None => {
+                let values = mem::take(&mut frame.values);
+                let value = apply_interval_ops(deepex, values, interval_ops)?;
+                stack.pop();
+                let Some(parent) = stack.last_mut() else {
+                    return Ok(value);
+                };
+                match parent.deepex.nodes().get(parent.values.len()) {
+                    Some(DeepNode::NaryCall { .. }) => parent.args.push(value),
+                    _ => parent.values.push(value),
+                }
+            }
+        }
+    }
+    Err(ExError::Internal(
+        "stack of interval evaluation ran empty".to_string(),
+    ))
+}
+
+/// Computes an interval that contains all values of the expression for variables in the
+/// given ranges. See [`eval_interval`](crate::FlatEx::eval_interval).
+pub fn eval_interval<T: Float + Debug>(
+    deepex: &DeepEx<T>,
+    var_ranges: &[(T, T)],
+) -> Result<(T, T), ExError> {
+    if deepex.n_vars() != var_ranges.len() {
+        return Err(ExError::WrongNumberOfVars {
+            expected: deepex.n_vars(),
+            given: var_ranges.len(),
+        });
+    }
+    let var_ranges = var_ranges
+        .iter()
+        .enumerate()
+        .map(|(var_idx, (lo, hi))| {
+            if lo <= hi {
+                Ok(Interval::new(*lo, *hi))
+            } else {
+                Err(ExError::InvalidInterval(var_idx))
+            }
+        })
+        .collect::<Result<SmallVec<[_; N_NODES_ON_STACK]>, ExError>>()?;
+    let res = eval_interval_deepex(deepex, &var_ranges, &make_interval_ops())?;
+    Ok((res.lo, res.hi))
+}
+
+#[cfg(test)]
+use {
+    crate::parse_with_default_ops,
+    rand::{thread_rng, Rng},
+};
+
+#[test]
+fn test_pow_interval() {
+    let i = Interval::new;
+    assert_eq!(pow_interval(i(-2.0, 3.0), i(2.0, 2.0)), i(0.0, 9.0));
+    assert_eq!(pow_interval(i(-2.0, 3.0), i(3.0, 3.0)), i(-8.0, 27.0));
+    assert_eq!(pow_interval(i(-3.0, -2.0), i(2.0, 2.0)), i(4.0, 9.0));
+    assert_eq!(
+        pow_interval(i(-3.0, -2.0), i(-1.0, -1.0)),
+        i(-0.5, -1.0 / 3.0)
+    );
+    assert_eq!(
+        pow_interval(i(-3.0, 2.0), i(-1.0, -1.0)),
+        Interval::unbounded()
+    );
+    assert_eq!(
+        pow_interval(i(-3.0, 2.0), i(0.5, 0.5)),
+        Interval::unbounded()
+    );
+    assert_eq!(pow_interval(i(1.0, 4.0), i(-1.0, 0.5)), i(0.25, 2.0));
+}
+
+#[test]
+fn test_eval_interval() {
+    fn test(text: &str, var_ranges: &[(f64, f64)], reference: (f64, f64)) {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        assert_eq!(
+            flatex.eval_interval(var_ranges).unwrap(),
+            reference,
+            "{}",
+            text
+        );
+    }
+    let inf = f64::INFINITY;
+    test("x^2 + 1", &[(-2.0, 3.0)], (1.0, 10.0));
+    test("sin(x)", &[(-10.0, 10.0)], (-1.0, 1.0));
+    test("cos(x)", &[(0.5, 1.0)], (1f64.cos(), 0.5f64.cos()));
+    test("sin(x)", &[(1.0, 2.0)], (1f64.sin(), 1.0));
+    test(
+        "-exp(x)*y",
+        &[(0.0, 1.0), (2.0, 3.0)],
+        (-3.0 * 1f64.exp(), -2.0),
+    );
+    test("x - x", &[(0.0, 1.0)], (-1.0, 1.0));
+    test("2 - 3*x", &[(0.0, 1.0)], (-1.0, 2.0));
+    test("7", &[], (7.0, 7.0));
+    test("(x > 1)*x", &[(2.0, 3.0)], (2.0, 3.0));
+    test("(x <= 1) + (x == 2)", &[(2.0, 3.0)], (0.0, 1.0));
+    test("x != 2", &[(2.0, 2.0)], (0.0, 0.0));
+    test("logb(2, x)", &[(1.0, 8.0)], (0.0, 3.0));
+    test("logb(x, 16)", &[(2.0, 4.0)], (2.0, 4.0));
+    test("sign(x)", &[(0.0, 2.0)], (0.0, 1.0));
+    test("sign(x)", &[(-3.0, 0.0)], (-1.0, 0.0));
+    test("signum(x)", &[(0.0, 2.0)], (1.0, 1.0));
+
+    // arguments outside of the domain of an operator have no value
+    test("log(x)*y", &[(-1.0, 2.0), (1.0, 1.0)], (-inf, 2f64.ln()));
+    test("sqrt(x)*y", &[(-1.0, 4.0), (1.0, 1.0)], (0.0, 2.0));
+    test("sqrt(x)+y", &[(-1.0, 4.0), (1.0, 1.0)], (1.0, 3.0));
+    test(
+        "acos(x)*y",
+        &[(-2.0, 0.5), (1.0, 1.0)],
+        (0.5f64.acos(), std::f64::consts::PI),
+    );
+    test("sqrt(x)", &[(-2.0, -1.0)], (-inf, inf));
+    test("0*(1/x)", &[(-1.0, 1.0)], (-inf, inf));
+
+    // dividing by an interval that contains zero results in an unbounded interval
+    test("1/x", &[(-1.0, 2.0)], (-inf, inf));
+    test("1/x", &[(0.0, 2.0)], (-inf, inf));
+    test("1/x", &[(0.5, 2.0)], (0.5, 2.0));
+
+    let flatex = parse_with_default_ops::<f64>("x+y").unwrap();
+    assert_eq!(
+        flatex.eval_interval(&[(0.0, 1.0)]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+    assert_eq!(
+        flatex.eval_interval(&[(0.0, 1.0), (1.0, 0.0)]),
+        Err(ExError::InvalidInterval(1))
+    );
+}
+
+#[test]
+fn test_eval_interval_sampling() {
+    let mut rng = thread_rng();
+    let texts = [
+        "x^2 + 1",
+        "sin(x)*cos(y) - x/(y^2+1)",
+        "exp(-x^2)*tanh(y)",
+        "sin(x+y)^2 + cos(x*y)",
+        "atan(x)*cosh(y) - fract(x*y)",
+        "asinh(x)*atanh(y/6) + acosh(x^2 + 1)",
+        "(x > y)*x + (x <= 1)*(y == y) - (x != 2) + (y < x^2 >= 0.5)",
+        "logb(x^2 + 2, y^2 + 0.5)*x",
+        "-(x-y)^3/(2+sin(x))",
+        "abs(x - 1)*round(y) + x % (y^2 + 1)",
+        "cbrt(x*y) - exp2(y)*sign(x)",
+        "sqrt(x)*y + log(y)",
+        "acos(x/2)*sqrt(y) - ln(x*y)",
+        "log2(x) + asin(y/3) - atanh(x/4)*acosh(y)",
+        "logb(x, y) + log10(x - y)",
+    ];
+    for text in texts {
+        let flatex = parse_with_default_ops::<f64>(text).unwrap();
+        let n_vars = flatex.var_names().len();
+        for _ in 0..20 {
+            let var_ranges = (0..n_vars)
+                .map(|_| {
+                    let a = rng.gen_range(-5.0..5.0);
+                    let b = rng.gen_range(-5.0..5.0);
+                    if a < b {
+                        (a, b)
+                    } else {
+                        (b, a)
+                    }
+                })
+                .collect::<Vec<_>>();
+            let (lo, hi) = flatex.eval_interval(&var_ranges).unwrap();
+            assert!(
+                !lo.is_nan() && !hi.is_nan(),
+                "{} for {:?}",
+                text,
+                var_ranges
+            );
+            for _ in 0..50 {
+                let vars = var_ranges
+                    .iter()
+                    .map(|(a, b)| rng.gen_range(*a..=*b))
+                    .collect::<Vec<_>>();
+                let val = flatex.eval(&vars).unwrap();
+                // arguments outside of the domain of an operator have no value
+                if val.is_nan() {
+                    continue;
+                }
+                let tol = 1e-9 * (1.0 + val.abs());
+                assert!(
+                    lo - tol <= val && val <= hi + tol,
+                    "{} = {} at {:?} is not in [{}, {}] for {:?}",
+                    text,
+                    val,
+                    vars,
+                    lo,
+                    hi,
+                    var_ranges
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,448 @@
+use core::{fmt::Debug, str::FromStr};
+
+use crate::{
+    alloc_prelude::*,
+    parser::{self, Paren, ParsedToken, Span, TokenKind},
+    ExError, Operator, ParseOptions,
+};
+
+use super::{deep::UnaryOpWithReprs, deep_details::make_expression, flat::flatten};
+
+/// Names of the built-in sums and products over index ranges together with the
+/// representations of the operators that combine their terms and the results of empty
+/// ranges.
+const LOOPS: [(&str, &str, &str); 2] = [("sum", "+", "0"), ("prod", "*", "1")];
+
+/// Number of arguments of a sum or a product, i.e., the loop variable, the lower and the
+/// upper bound, and the body.
+const LOOP_ARITY: usize = 4;
+
+/// Returns the name, the combining operator, and the result of an empty range of the sum
+/// or product whose call starts at `idx`. Names in curly braces are variables.
+fn loop_at<'a, T: Copy + FromStr>(
+    text: &str,
+    tokens: &[ParsedToken<'a, T>],
+    idx: usize,
+) -> Option<(&'static str, &'static str, &'static str)> {
+    match (
+        &tokens[idx].kind,
+        tokens.get(idx + 1).map(|next| &next.kind),
+    ) {
+        (TokenKind::Var(name), Some(TokenKind::Paren(Paren::Open)))
+            if text[tokens[idx].span.start..tokens[idx].span.end] == **name =>
+        {
+            LOOPS.iter().find(|(repr, _, _)| repr == name).copied()
+        }
+        _ => None,
+    }
+}
+
+/// Returns the arguments of the call whose opening parenthesis is at `open` and the index
+/// of its closing parenthesis, or `None` if the parenthesis is not closed.
+fn call_args<'a, 'b, T: Copy + FromStr>(
+    tokens: &'b [ParsedToken<'a, T>],
+    open: usize,
+) -> Option<(Vec<&'b [ParsedToken<'a, T>]>, usize)> {
+    let mut args = vec![];
+    let mut arg_start = open + 1;
+    let mut depth = 0usize;
+    for (idx, token) in tokens.iter().enumerate().skip(open + 1) {
+        match token.kind {
+            TokenKind::Paren(Paren::Open) => depth += 1,
+            TokenKind::Paren(Paren::Close) if depth == 0 => {
+                args.push(&tokens[arg_start..idx]);
+                return Some((args, idx));
+            }
+            TokenKind::Paren(Paren::Close) => depth -= 1,
+            TokenKind::Comma if depth == 0 => {
+                args.push(&tokens[arg_start..idx]);
+                arg_start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Returns the integer that `value` represents, e.g., `3` for `3.0`. Values are converted
+/// via their [`Debug`](Debug) representations since `T` is not necessarily numeric.
+fn to_integer<T: Debug>(value: T) -> Option<i64> {
+    let repr = format!("{:?}", value);
+    repr.parse::<i64>().ok().or_else(|| {
+        let value = repr.parse::<f64>().ok()?;
+        let int = value as i64;
+        (int as f64 == value).then_some(int)
+    })
+}
+
+/// Replaces the calls of the built-in sums and products such as `sum(i, 1, 5, i^2)` by
+/// their terms, i.e., by the body for each integer from the lower to the upper bound
+/// substituted for the loop variable, combined with `+` or `*`, respectively. The bounds
+/// need to fold to integer constants. Bounds of inner sums and products can depend on the
+/// loop variables of outer ones.
+///
+/// # Errors
+///
+/// An [`ExError`](ExError) is returned if a call does not have 4 arguments, its first
+/// argument is not a variable, a bound is not an integer constant, the combining operator
+/// is missing, or the total number of terms exceeds
+/// [`max_loop_terms`](crate::ParseLimits::max_loop_terms).
+pub fn expand_loops<'a, T>(
+    text: &'a str,
+    tokens: Vec<ParsedToken<'a, T>>,
+    ops: &[Operator<'a, T>],
+    options: &ParseOptions,
+) -> Result<Vec<ParsedToken<'a, T>>, ExError>
+where
+    <T as FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    if (0..tokens.len()).all(|idx| loop_at(text, &tokens, idx).is_none()) {
+        return Ok(tokens);
+    }
+    let mut n_terms = 0;
+    expand(text, &tokens, ops, options, &mut n_terms)
+}
+
+fn expand<'a, T>(
+    text: &'a str,
+    tokens: &[ParsedToken<'a, T>],
+    ops: &[Operator<'a, T>],
+    options: &ParseOptions,
+    n_terms: &mut usize,
+) -> Result<Vec<ParsedToken<'a, T>>, ExError>
+where
+    <T as FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let mut res = Vec::with_capacity(tokens.len());
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let call = loop_at(text, tokens, idx)
+            .and_then(|lp| call_args(tokens, idx + 1).map(|(args, close)| (lp, args, close)));
+        match call {
+            Some(((name, op_repr, neutral), args, close)) => {
+                let span = Span {
+                    start: tokens[idx].span.start,
+                    end: tokens[close].span.end,
+                };
+                let call = Call {
+                    text,
+                    name,
+                    span,
+                    ops,
+                    options,
+                };
+                call.expand(&args, op_repr, neutral, n_terms, &mut res)?;
+                idx = close + 1;
+            }
+            // unclosed calls are reported by the checks of the token order
+            None => {
+                res.push(tokens[idx].clone());
+                idx += 1;
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// A call of a sum or a product that spans `span` of `text`.
+struct Call<'a, 'b, T: Copy> {
+    text: &'a str,
+    name: &'static str,
+    span: Span,
+    ops: &'b [Operator<'a, T>],
+    options: &'b ParseOptions,
+}
+
+impl<'a, T> Call<'a, '_, T>
+where
+    <T as FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    fn arg_span(&self, arg: &[ParsedToken<'a, T>]) -> Span {
+        match (arg.first(), arg.last()) {
+            (Some(first), Some(last)) => Span {
+                start: first.span.start,
+                end: last.span.end,
+            },
+            _ => self.span,
+        }
+    }
+
+    fn invalid_bound(&self, arg: &[ParsedToken<'a, T>]) -> ExError {
+        let span = self.arg_span(arg);
+        ExError::InvalidLoopBound {
+            position: span.start,
+            op: self.name.to_string(),
+            context: parser::context(self.text, span),
+        }
+    }
+
+    /// Returns the integer the bound `arg` folds to.
+    fn bound(&self, arg: &[ParsedToken<'a, T>], n_terms: &mut usize) -> Result<i64, ExError> {
+        if arg.is_empty() {
+            return Err(self.invalid_bound(arg));
+        }
+        let mut tokens = expand(self.text, arg, self.ops, self.options, n_terms)?;
+        if tokens
+            .iter()
+            .any(|token| matches!(token.kind, TokenKind::Var(_)))
+        {
+            return Err(self.invalid_bound(arg));
+        }
+        if self.options.implicit_multiplication {
+            tokens = parser::insert_implicit_muls(tokens, self.ops)?;
+        }
+        parser::check_preconditions(self.text, &tokens)?;
+        let (deepex, _) = make_expression(
+            self.text,
+            &tokens,
+            &[],
+            &[],
+            self.options,
+            self.options.semantics.optimizations(),
+            UnaryOpWithReprs::new(),
+        )?;
+        let value = flatten(deepex)?.eval(&[])?;
+        to_integer(value).ok_or_else(|| self.invalid_bound(arg))
+    }
+
+    /// Pushes the terms of the call with the arguments `args` to `res`.
+    fn expand(
+        &self,
+        args: &[&[ParsedToken<'a, T>]],
+        op_repr: &str,
+        neutral: &str,
+        n_terms: &mut usize,
+        res: &mut Vec<ParsedToken<'a, T>>,
+    ) -> Result<(), ExError> {
+        let [var_arg, from_arg, to_arg, body] = args else {
+            return Err(ExError::WrongNumberOfArgs {
+                position: self.span.start,
+                op: self.name.to_string(),
+                expected: LOOP_ARITY,
+                given: args.len(),
+                context: parser::context(self.text, self.span),
+            });
+        };
+        let var = match var_arg {
+            [ParsedToken {
+                kind: TokenKind::Var(var),
+                ..
+            }] => *var,
+            _ => {
+                let span = self.arg_span(var_arg);
+                return Err(ExError::InvalidLoopVar {
+                    position: span.start,
+                    op: self.name.to_string(),
+                    context: parser::context(self.text, span),
+                });
+            }
+        };
+        let from = self.bound(from_arg, n_terms)?;
+        let to = self.bound(to_arg, n_terms)?;
+        // generated numbers have empty spans such that they have no literal text
+        let generated = |kind, start| ParsedToken {
+            kind,
+            span: Span { start, end: start },
+        };
+        if from > to {
+            let value = neutral
+                .parse::<T>()
+                .map_err(|_| self.invalid_bound(from_arg))?;
+            res.push(generated(TokenKind::Num(value), self.span.start));
+            return Ok(());
+        }
+        let op = *self
+            .ops
+            .iter()
+            .find(|op| op.repr == op_repr && op.bin_op.is_some())
+            .ok_or_else(|| ExError::OperatorNotFound(op_repr.to_string()))?;
+        let paren = |paren, span| ParsedToken {
+            kind: TokenKind::Paren(paren),
+            span,
+        };
+        res.push(paren(Paren::Open, self.span));
+        for k in from..=to {
+            *n_terms += 1;
+            if let Some(max) = self
+                .options
+                .limits
+                .max_loop_terms
+                .filter(|max| *n_terms > *max)
+            {
+                return Err(ExError::TooManyLoopTerms {
+                    position: self.span.start,
+                    max,
+                    context: parser::context(self.text, self.span),
+                });
+            }
+            let value = k
+                .to_string()
+                .parse::<T>()
+                .map_err(|_| self.invalid_bound(if k == from { from_arg } else { to_arg }))?;
+            let mut term = Vec::with_capacity(body.len());
+            for token in body.iter() {
+                match token.kind {
+                    TokenKind::Var(name) if name == var => {
+                        term.push(paren(Paren::Open, token.span));
+                        term.push(generated(TokenKind::Num(value), token.span.start));
+                        term.push(paren(Paren::Close, token.span));
+                    }
+                    _ => term.push(token.clone()),
+                }
+            }
+            if k > from {
+                res.push(ParsedToken {
+                    kind: TokenKind::Op(op),
+                    span: self.span,
+                });
+            }
+            res.push(paren(Paren::Open, self.span));
+            res.extend(expand(self.text, &term, self.ops, self.options, n_terms)?);
+            res.push(paren(Paren::Close, self.span));
+        }
+        res.push(paren(Paren::Close, self.span));
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{
+    operators::{make_default_operators, make_default_operators_int},
+    parse, parse_with_default_ops, parse_with_options,
+    util::assert_float_eq_f64,
+    ParseLimits,
+};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sum_prod() {
+    let eval = |text: &str, vars: &[f64]| {
+        parse_with_default_ops::<f64>(text)
+            .unwrap()
+            .eval(vars)
+            .unwrap()
+    };
+    assert_eq!(eval("sum(i, 1, 5, i^2)", &[]), 55.0);
+    assert_eq!(eval("prod(k, 1, 5, k)", &[]), 120.0);
+    assert_eq!(eval("2*sum(i, -2, 2, i) + prod(i, 1, 3, i + 1)", &[]), 24.0);
+    assert_eq!(eval("sum(i, 3, 2, i) + prod(i, 1, 0, i)", &[]), 1.0);
+    assert_eq!(eval("sum(i, 2*2 - 1, 2^2, i)", &[]), 7.0);
+
+    // discounted cash flows with the variables of the body
+    let expr = parse_with_default_ops::<f64>("sum(i, 1, 3, x / (1 + r)^i)").unwrap();
+    assert_eq!(expr.var_names(), ["r", "x"]);
+    let reference = (1..=3).map(|i| 100.0 / 1.05f64.powi(i)).sum::<f64>();
+    assert_float_eq_f64(expr.eval(&[0.05, 100.0]).unwrap(), reference);
+
+    // nested sums whose inner bounds depend on the outer loop variable
+    assert_eq!(eval("sum(i, 1, 3, sum(j, 1, 2, i*j))", &[]), 18.0);
+    assert_eq!(eval("sum(i, 1, 4, sum(j, 1, i, 1))", &[]), 10.0);
+    assert_eq!(eval("sum(i, 1, 3, sum(j, i, 3, x))", &[2.0]), 12.0);
+    assert_eq!(eval("sum(i, 1, sum(j, 1, 2, j), i)", &[]), 6.0);
+
+    // only names without curly braces are sums
+    let expr = parse_with_default_ops::<f64>("{sum} * 2").unwrap();
+    assert_eq!(expr.var_names(), ["sum"]);
+    assert!(parse_with_default_ops::<f64>("{sum}(i, 1, 5, i)").is_err());
+
+    let options = ParseOptions {
+        implicit_multiplication: true,
+        ..ParseOptions::default()
+    };
+    let ops = make_default_operators::<f64>();
+    let expr = parse_with_options("2sum(i, 1, 3, 2i x)", &ops, &options).unwrap();
+    assert_eq!(expr.eval(&[0.5]).unwrap(), 12.0);
+
+    // substituted numbers have no literal text
+    let options = ParseOptions {
+        preserve_literal_text: true,
+        ..ParseOptions::default()
+    };
+    let expr = parse_with_options("sum(i, 1, 2, 1.50*x + i)", &ops, &options).unwrap();
+    let unparsed = expr.unparse().unwrap();
+    assert!(unparsed.contains("1.50") && !unparsed.contains('i'));
+    assert_eq!(expr.eval(&[2.0]).unwrap(), 9.0);
+
+    assert!(crate::check("sum(i, 1, 5, i^2) * x", &ops).is_ok());
+    let errors = crate::check("sum(i, 1, n, i) $", &ops).unwrap_err();
+    let codes = errors.iter().map(|e| e.code()).collect::<Vec<_>>();
+    assert_eq!(codes, ["E059", "E002"]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_loop_errors() {
+    let ops = make_default_operators::<f64>();
+    let err = |text| parse_with_default_ops::<f64>(text).unwrap_err();
+    assert_eq!(
+        err("x + sum(i, 1, n, i)"),
+        ExError::InvalidLoopBound {
+            position: 14,
+            op: "sum".to_string(),
+            context: ", 1, n, i)".to_string()
+        }
+    );
+    assert_eq!(err("x + sum(i, 1, n, i)").code(), "E059");
+    for text in ["prod(i, 0.5, 2, i)", "sum(i, , 2, i)", "sum(i, 1, 2+, i)"] {
+        assert!(matches!(
+            err(text),
+            ExError::InvalidLoopBound { .. } | ExError::TrailingOperator { .. }
+        ));
+    }
+    assert!(matches!(
+        err("sum(2, 1, 5, i)"),
+        ExError::InvalidLoopVar { position: 4, .. }
+    ));
+    assert!(matches!(
+        err("sum(i, 1, 5)"),
+        ExError::WrongNumberOfArgs {
+            expected: 4,
+            given: 3,
+            ..
+        }
+    ));
+    assert!(matches!(
+        err("sum(i, 1, 5, i +)"),
+        ExError::InvalidPair { .. }
+    ));
+    let without_plus = ops
+        .iter()
+        .filter(|op| op.repr != "+")
+        .copied()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        parse("sum(i, 0, 10, i)", &without_plus),
+        Err(ExError::OperatorNotFound("+".to_string()))
+    );
+    let int_ops = make_default_operators_int::<u32>();
+    assert_eq!(
+        parse("sum(i, 1, 4, i*i)", &int_ops).unwrap().eval(&[]),
+        Ok(30)
+    );
+
+    let options = |max_loop_terms| ParseOptions {
+        limits: ParseLimits {
+            max_loop_terms,
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+    let text = "sum(i, 1, 3, sum(j, 1, i, j))";
+    assert!(parse_with_options(text, &ops, &options(Some(9))).is_ok());
+    assert_eq!(
+        parse_with_options(text, &ops, &options(Some(8))),
+        Err(ExError::TooManyLoopTerms {
+            position: 13,
+            max: 8,
+            context: ", 3, sum(j, 1, i, j))".to_string()
+        })
+    );
+    assert!(matches!(
+        parse_with_default_ops::<f64>("sum(i, 1, 1000000000, i)"),
+        Err(ExError::TooManyLoopTerms { max: 10_000, .. })
+    ));
+    assert!(parse_with_options("sum(i, 1, 10001, i)", &ops, &options(None)).is_ok());
+}
@@ -1,4 +1,13 @@
+pub mod builder;
 pub mod deep;
 mod deep_details;
+#[cfg(feature = "std")]
+mod dual;
 pub mod flat;
-mod partial_derivatives;
+#[cfg(feature = "std")]
+mod interval;
+pub mod loops;
+#[cfg(feature = "std")]
+pub mod partial_derivatives;
+#[cfg(feature = "std")]
+pub mod static_ex;
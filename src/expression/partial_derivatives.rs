@@ -1,6 +1,6 @@
-use num::Float;
+use num::{One, Zero};
 use smallvec::{smallvec, SmallVec};
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::Neg};
 
 use super::{
     deep::{BinOpsWithReprs, DeepEx, ExprIdxVec},
@@ -10,13 +10,22 @@ use crate::{
     definitions::N_BINOPS_OF_DEEPEX_ON_STACK,
     expression::deep::{DeepNode, UnaryOpWithReprs},
     operators::{Operator, UnaryOp},
-    ExParseError,
+    ExError,
 };
 
-#[derive(Clone)]
-struct ValueDerivative<'a, T: Copy + Debug> {
-    val: DeepEx<'a, T>,
-    der: DeepEx<'a, T>,
+/// Numbers whose expressions can be differentiated, e.g., floats or complex numbers. The
+/// derivatives of the operators are computed with the rules of real analysis, which also
+/// hold for holomorphic functions of complex numbers.
+pub trait DiffNum: Copy + Debug + PartialEq + Zero + One + Neg<Output = Self> {}
+
+impl<T> DiffNum for T where T: Copy + Debug + PartialEq + Zero + One + Neg<Output = T> {}
+
+/// An expression and its partial derivative that are passed to the rules of a
+/// [`PartialDerivative`](PartialDerivative).
+#[derive(Clone, Debug)]
+pub struct ValueDerivative<'a, T: Copy + Debug> {
+    pub val: DeepEx<'a, T>,
+    pub der: DeepEx<'a, T>,
 }
 
 pub fn find_op<'a, T: Copy + Debug>(
@@ -28,154 +37,242 @@ pub fn find_op<'a, T: Copy + Debug>(
         bin_op: op.bin_op,
         unary_op: op.unary_op,
         repr,
+        domain_check: op.domain_check,
+        unary_position: op.unary_position,
+        nary_op: op.nary_op,
+        fallible: op.fallible,
+        aliases: op.aliases,
     })
 }
 
+/// Receives the values and derivatives of both operands of a binary operator and returns
+/// the derivative of the operator applied to them.
+pub type BinOpPartial<'a, T> = fn(
+    &ValueDerivative<'a, T>,
+    &ValueDerivative<'a, T>,
+    &[Operator<'a, T>],
+) -> Result<DeepEx<'a, T>, ExError>;
+
+/// Receives the values and derivatives of the arguments of an n-ary operator and returns
+/// the derivative of the call.
+pub type NaryOpPartial<'a, T> =
+    fn(&[ValueDerivative<'a, T>], &[Operator<'a, T>]) -> Result<DeepEx<'a, T>, ExError>;
+
+/// Receives an expression `f(u)` whose only unary operator is `f` and returns `f'(u)`.
+pub type UnaryOpOuter<'a, T> =
+    fn(DeepEx<'a, T>, &[Operator<'a, T>]) -> Result<DeepEx<'a, T>, ExError>;
+
+/// Rules to differentiate the operator with the representation `repr`, e.g., to teach
+/// [`partial_with_rules`](crate::FlatEx::partial_with_rules) custom operators. Each rule
+/// also receives the operators the derivative is computed with to look up further
+/// operators, e.g., `cos` for the derivative of `sin`.
+///
+/// * `bin_op` receives the values `f` and `g` of both operands and their derivatives
+///   `f'` and `g'` and returns the derivative of `f op g`, e.g., `f'*g + f*g'` for `*`.
+/// * `unary_op` receives the expression `f(u)` whose only unary operator is the one to be
+///   differentiated and returns the outer derivative `f'(u)`. The chain rule is applied
+///   by the caller, i.e., the result is multiplied by `u'`.
+/// * `nary_op` receives the values and derivatives of the arguments and returns the
+///   derivative of the call, where the chain rule is up to the rule.
+///
+/// Numbers can be created with [`DeepEx::num_like`](DeepEx::num_like) and combined with
+/// the arithmetic operators of [`DeepEx`](DeepEx).
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, PartialDerivative};
+/// let mut ops = make_default_operators::<f64>().to_vec();
+/// ops.push(Operator::make_unary("sigmoid", |x| 1.0 / (1.0 + (-x).exp())));
+/// // the derivative of the sigmoid s is s*(1-s)
+/// let rules = [PartialDerivative {
+///     repr: "sigmoid",
+///     bin_op: None,
+///     unary_op: Some(|s, _| Ok(s.clone() * (s.num_like(1.0) - s))),
+///     nary_op: None,
+/// }];
+/// let expr = parse("sigmoid(x)", &ops)?;
+/// let d_x = expr.partial_with_rules(0, &rules)?;
+/// assert!((d_x.eval(&[0.0])? - 0.25).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy)]
 pub struct PartialDerivative<'a, T: Copy + Debug> {
-    repr: &'a str,
-    bin_op: Option<
-        fn(
-            ValueDerivative<'a, T>,
-            ValueDerivative<'a, T>,
-            &[Operator<'a, T>],
-        ) -> Result<ValueDerivative<'a, T>, ExParseError>,
-    >,
-    unary_op: Option<fn(DeepEx<'a, T>, &[Operator<'a, T>]) -> Result<DeepEx<'a, T>, ExParseError>>,
+    pub repr: &'a str,
+    pub bin_op: Option<BinOpPartial<'a, T>>,
+    pub unary_op: Option<UnaryOpOuter<'a, T>>,
+    pub nary_op: Option<NaryOpPartial<'a, T>>,
 }
 
 fn find_as_bin_op_with_reprs<'a, T: Copy + Debug>(
     repr: &'a str,
     ops: &[Operator<'a, T>],
-) -> Result<BinOpsWithReprs<'a, T>, ExParseError> {
-    let op = find_op(repr, ops).ok_or(ExParseError {
-        msg: format!("did not find operator {}", repr),
-    })?;
+) -> Result<BinOpsWithReprs<'a, T>, ExError> {
+    let op = find_op(repr, ops).ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))?;
     Ok(BinOpsWithReprs {
         reprs: vec![op.repr],
-        ops: smallvec![op.bin_op.ok_or(ExParseError {
-            msg: format!("operater {} is not binary", op.repr)
-        })?],
+        ops: smallvec![op
+            .bin_op
+            .ok_or_else(|| ExError::OperatorNotBinary(op.repr.to_string()))?],
     })
 }
 
 fn find_as_unary_op_with_reprs<'a, T: Copy + Debug>(
     repr: &'a str,
     ops: &[Operator<'a, T>],
-) -> Result<UnaryOpWithReprs<'a, T>, ExParseError> {
-    let op = find_op(repr, ops).ok_or(ExParseError {
-        msg: format!("did not find operator {}", repr),
-    })?;
-    Ok(UnaryOpWithReprs {
-        reprs: vec![op.repr],
-        op: UnaryOp::from_vec(smallvec![op.unary_op.ok_or(ExParseError {
-            msg: format!("operater {} is not unary", op.repr)
-        })?]),
-    })
-}
-
-fn make_op_missing_err(repr: &str) -> ExParseError {
-    ExParseError {
-        msg: format!("operator {} needed for outer partial derivative", repr),
-    }
+) -> Result<UnaryOpWithReprs<'a, T>, ExError> {
+    let op = find_op(repr, ops).ok_or_else(|| ExError::OperatorNotFound(repr.to_string()))?;
+    Ok(UnaryOpWithReprs::prefix(
+        vec![op.repr],
+        UnaryOp::from_vec(smallvec![op
+            .unary_op
+            .ok_or_else(|| ExError::OperatorNotUnary(op.repr.to_string()))?]),
+    ))
 }
 
-fn partial_derivative_outer<'a, T: Float + Debug>(
+fn partial_derivative_outer<'a, T: DiffNum>(
     deepex: DeepEx<'a, T>,
     partial_derivative_ops: &[PartialDerivative<'a, T>],
     overloaded_ops: OverloadedOps<'a, T>,
     ops: &[Operator<'a, T>],
-) -> Result<DeepEx<'a, T>, ExParseError> {
-    let factorexes =
-        deepex
-            .unary_op()
-            .reprs
+) -> Result<DeepEx<'a, T>, ExError> {
+    let unary_op = deepex.unary_op();
+    let n_uops = unary_op.reprs.len();
+    // chain rule, each unary operator is derived at the result of the operators that are
+    // applied before it, i.e., the ones with higher indices
+    let mut factorexes = (0..n_uops).map(|i| -> Result<DeepEx<'a, T>, ExError> {
+        let repr = unary_op.reprs[i];
+        let unary_deri_op = partial_derivative_ops
             .iter()
-            .map(|repr| -> Result<DeepEx<'a, T>, ExParseError> {
-                let op = partial_derivative_ops
-                    .iter()
-                    .find(|pdo| &pdo.repr == repr)
-                    .ok_or_else(|| make_op_missing_err(repr))?;
-                let unary_deri_op = op.unary_op.ok_or_else(|| make_op_missing_err(repr))?;
-
-                unary_deri_op(deepex.clone(), ops)
-            });
-    let resex = factorexes.fold(
-        Ok(DeepEx::one(overloaded_ops)),
-        |dp1, dp2| -> Result<DeepEx<T>, ExParseError> { mul_num(dp1?, dp2?) },
-    );
-    resex
+            .filter(|pdo| pdo.repr == repr)
+            .find_map(|pdo| pdo.unary_op)
+            .ok_or_else(|| ExError::DerivativeNotFound(repr.to_string()))?;
+
+        let inner = deepex
+            .clone()
+            .with_new_unary_op(unary_op.slice(i + 1..n_uops));
+        let inner = if i + 1 < n_uops {
+            DeepEx::from_node(DeepNode::expr(inner), overloaded_ops.clone())
+        } else {
+            inner
+        };
+        unary_deri_op(inner.with_new_unary_op(unary_op.slice(i..i + 1)), ops)
+    });
+    factorexes.try_fold(
+        DeepEx::one(overloaded_ops.clone()),
+        |dp1, dp2| -> Result<DeepEx<T>, ExError> { mul_num(dp1, dp2?) },
+    )
 }
 
-fn partial_derivative_inner<'a, T: Float + Debug>(
+/// Returns the sub-expressions of `deepex` whose partial derivatives are combined by
+/// [`partial_derivative_inner`](partial_derivative_inner), i.e., the nodes or the arguments
+/// of a single call that contains the variable.
+fn sub_deepexes<'a, T: DiffNum>(
+    var_idx: usize,
+    deepex: &DeepEx<'a, T>,
+    overloaded_ops: &OverloadedOps<'a, T>,
+) -> Vec<DeepEx<'a, T>> {
+    let make_deepex = |node: &DeepNode<'a, T>| match node {
+        DeepNode::Expr(e) => {
+            let mut e = (**e).clone();
+            e.set_overloaded_ops(Some(overloaded_ops.clone()));
+            e
+        }
+        // the spelling refers to the literal texts of deepex
+        DeepNode::Num(n, _) => DeepEx::from_node(DeepNode::Num(*n, None), overloaded_ops.clone()),
+        _ => DeepEx::from_node(node.clone(), overloaded_ops.clone()),
+    };
+    match deepex.nodes().as_slice() {
+        [DeepNode::Num(..) | DeepNode::Var(_)] => vec![],
+        // n-ary operators without derivatives are only a problem if the variable occurs in
+        // one of the arguments
+        [DeepNode::NaryCall { args, .. }] if deepex.contains_var(var_idx) => args
+            .iter()
+            .map(|arg| {
+                let mut arg = arg.clone();
+                arg.set_overloaded_ops(Some(overloaded_ops.clone()));
+                arg
+            })
+            .collect(),
+        [DeepNode::NaryCall { .. }] => vec![],
+        nodes => nodes.iter().map(make_deepex).collect(),
+    }
+}
+
+/// Combines the partial derivatives `sub_ders` of the sub-expressions `subs` of `deepex`,
+/// see [`sub_deepexes`](sub_deepexes), to the derivative of `deepex` without its unary
+/// operators.
+fn partial_derivative_inner<'a, T: DiffNum>(
     var_idx: usize,
     deepex: DeepEx<'a, T>,
+    subs: Vec<DeepEx<'a, T>>,
+    sub_ders: Vec<DeepEx<'a, T>>,
     partial_derivative_ops: &[PartialDerivative<'a, T>],
     overloaded_ops: OverloadedOps<'a, T>,
     ops: &[Operator<'a, T>],
-) -> Result<DeepEx<'a, T>, ExParseError> {
+) -> Result<DeepEx<'a, T>, ExError> {
+    let mut sub_ders = sub_ders.into_iter();
     // special case, partial derivative of only 1 node
     if deepex.nodes().len() == 1 {
-        let res = match deepex.nodes()[0].clone() {
-            DeepNode::Num(_) => DeepEx::zero(overloaded_ops.clone()),
+        let res = match &deepex.nodes()[0] {
+            DeepNode::Num(..) => DeepEx::zero(overloaded_ops.clone()),
             DeepNode::Var((var_i, _)) => {
-                if var_i == var_idx {
+                if *var_i == var_idx {
                     DeepEx::one(overloaded_ops.clone())
                 } else {
                     DeepEx::zero(overloaded_ops.clone())
                 }
             }
-            DeepNode::Expr(mut e) => {
-                e.set_overloaded_ops(Some(overloaded_ops.clone()));
-                partial_deepex(var_idx, e, ops)?
+            DeepNode::Expr(_) => sub_ders.next().ok_or_else(|| {
+                ExError::Internal("missing derivative of sub-expression".to_string())
+            })?,
+            DeepNode::NaryCall { repr, .. } => {
+                if subs.is_empty() {
+                    DeepEx::zero(overloaded_ops.clone())
+                } else {
+                    let nary_deri_op = partial_derivative_ops
+                        .iter()
+                        .filter(|pdo| pdo.repr == *repr)
+                        .find_map(|pdo| pdo.nary_op)
+                        .ok_or_else(|| ExError::DerivativeNotFound(repr.to_string()))?;
+                    let args = subs
+                        .into_iter()
+                        .zip(sub_ders)
+                        .map(|(val, der)| ValueDerivative { val, der })
+                        .collect::<Vec<_>>();
+                    nary_deri_op(&args, ops)?
+                }
             }
         };
         let (res, _) = res.var_names_union(deepex);
         return Ok(res);
     }
 
-    let prio_indices = deep_details::prioritized_indices(&deepex.bin_ops().ops, deepex.nodes());
+    let prio_indices =
+        deep_details::prioritized_indices(deepex.bin_ops(), deepex.nodes(), deepex.optimizations());
 
-    let make_deepex = |node: DeepNode<'a, T>| match node {
-        DeepNode::Expr(mut e) => {
-            e.set_overloaded_ops(Some(overloaded_ops.clone()));
-            e
-        }
-        _ => DeepEx::from_node(node, overloaded_ops.clone()),
-    };
+    let mut nodes = subs
+        .into_iter()
+        .zip(sub_ders)
+        .map(|(val, der)| Some(ValueDerivative { val, der }))
+        .collect::<Vec<_>>();
 
-    let mut nodes = deepex
-        .nodes()
+    let partial_bin_ops_of_deepex = deepex
+        .bin_ops()
+        .reprs
         .iter()
-        .map(|node| -> Result<_, ExParseError> {
-            let deepex_val = make_deepex(node.clone());
-            let deepex_der = partial_deepex(var_idx, deepex_val.clone(), ops)?;
-            Ok(Some(ValueDerivative {
-                val: deepex_val,
-                der: deepex_der,
-            }))
+        .map(|repr| -> Result<BinOpPartial<'a, T>, ExError> {
+            partial_derivative_ops
+                .iter()
+                .filter(|pdo| &pdo.repr == repr)
+                .find_map(|pdo| pdo.bin_op)
+                .ok_or_else(|| ExError::DerivativeNotFound(repr.to_string()))
         })
-        .collect::<Result<Vec<_>, ExParseError>>()?;
-
-    let partial_bin_ops_of_deepex =
-        deepex
-            .bin_ops()
-            .reprs
-            .iter()
-            .map(|repr| -> Result<&PartialDerivative<'a, T>, ExParseError> {
-                partial_derivative_ops
-                    .iter()
-                    .find(|pdo| &pdo.repr == repr)
-                    .ok_or(ExParseError {
-                        msg: format!(
-                            "derivative operator of {} needed for partial derivative",
-                            repr
-                        ),
-                    })
-            })
-            .collect::<Result<
-                SmallVec<[&PartialDerivative<'a, T>; N_BINOPS_OF_DEEPEX_ON_STACK]>,
-                ExParseError,
-            >>()?;
+        .collect::<Result<SmallVec<[BinOpPartial<'a, T>; N_BINOPS_OF_DEEPEX_ON_STACK]>, ExError>>(
+        )?;
 
     let mut num_inds = prio_indices.clone();
     let mut used_prio_indices = ExprIdxVec::new();
@@ -186,14 +283,17 @@ fn partial_derivative_inner<'a, T: Float + Debug>(
         let node_2 = nodes[num_idx + 1].take();
 
         let pd_deepex = if let (Some(n1), Some(n2)) = (node_1, node_2) {
-            let pdo = &partial_bin_ops_of_deepex[bin_op_idx];
-            pdo.bin_op.ok_or(ExParseError {
-                msg: format!("cannot find binary op for {}", pdo.repr),
-            })?(n1, n2, ops)
+            let bin_op = BinOpsWithReprs {
+                reprs: vec![deepex.bin_ops().reprs[bin_op_idx]],
+                ops: smallvec![deepex.bin_ops().ops[bin_op_idx]],
+            };
+            let der = partial_bin_ops_of_deepex[bin_op_idx](&n1, &n2, ops)?;
+            let val = n1.val.operate_bin(n2.val, bin_op);
+            Ok(ValueDerivative { val, der })
         } else {
-            Err(ExParseError {
-                msg: "nodes do not contain values in partial derivative".to_string(),
-            })
+            Err(ExError::Internal(
+                "nodes do not contain values in partial derivative".to_string(),
+            ))
         }?;
         nodes[num_idx] = Some(pd_deepex);
         nodes.remove(num_idx + 1);
@@ -207,8 +307,10 @@ fn partial_derivative_inner<'a, T: Float + Debug>(
     }
     let mut res = nodes[0]
         .take()
-        .ok_or(ExParseError {
-            msg: "node 0 needs to contain valder at the end of partial derviative".to_string(),
+        .ok_or_else(|| {
+            ExError::Internal(
+                "node 0 needs to contain valder at the end of partial derviative".to_string(),
+            )
         })?
         .der;
     res.set_overloaded_ops(Some(overloaded_ops));
@@ -216,35 +318,103 @@ fn partial_derivative_inner<'a, T: Float + Debug>(
     Ok(res)
 }
 
-pub fn partial_deepex<'a, T: Float + Debug>(
+#[cfg(test)]
+pub fn partial_deepex<'a, T: DiffNum>(
     var_idx: usize,
     deepex: DeepEx<'a, T>,
     ops: &[Operator<'a, T>],
-) -> Result<DeepEx<'a, T>, ExParseError> {
-    let partial_derivative_ops = make_partial_derivative_ops::<T>();
-    let overloaded_ops = find_overloaded_ops(ops).ok_or(ExParseError {
-        msg: "one of overloaded ops not found".to_string(),
-    })?;
-
-    let inner = partial_derivative_inner(
-        var_idx,
-        deepex.clone(),
-        &partial_derivative_ops,
-        overloaded_ops.clone(),
-        ops,
-    )?;
-    let outer =
-        partial_derivative_outer(deepex, &partial_derivative_ops, overloaded_ops.clone(), ops)?;
-    let mut res = mul_num(inner, outer)?;
-    res.compile();
-    res.set_overloaded_ops(Some(overloaded_ops));
-    Ok(res)
+) -> Result<DeepEx<'a, T>, ExError> {
+    partial_deepex_with_rules(var_idx, deepex, ops, &[])
+}
+
+/// Computes the partial derivative with the built-in rules and `rules`. Rules in `rules`
+/// replace the built-in ones of the same kind for the same representation.
+pub fn partial_deepex_with_rules<'a, T: DiffNum>(
+    var_idx: usize,
+    deepex: DeepEx<'a, T>,
+    ops: &[Operator<'a, T>],
+    rules: &[PartialDerivative<'a, T>],
+) -> Result<DeepEx<'a, T>, ExError> {
+    let partial_derivative_ops = rules
+        .iter()
+        .copied()
+        .chain(make_partial_derivative_ops())
+        .collect::<Vec<_>>();
+    partial_derivative(var_idx, deepex, &partial_derivative_ops, ops)
+}
+
+/// Expression whose partial derivative is computed on the explicit stack of
+/// [`partial_derivative`](partial_derivative).
+struct DerivativeFrame<'a, T: Copy + Debug> {
+    deepex: DeepEx<'a, T>,
+    /// Sub-expressions whose derivatives are needed, see [`sub_deepexes`](sub_deepexes).
+    subs: Vec<DeepEx<'a, T>>,
+    /// Derivatives of the first sub-expressions.
+    sub_ders: Vec<DeepEx<'a, T>>,
+}
+
+fn partial_derivative<'a, T: DiffNum>(
+    var_idx: usize,
+    deepex: DeepEx<'a, T>,
+    partial_derivative_ops: &[PartialDerivative<'a, T>],
+    ops: &[Operator<'a, T>],
+) -> Result<DeepEx<'a, T>, ExError> {
+    let overloaded_ops = find_overloaded_ops(ops)
+        .ok_or_else(|| ExError::OperatorNotFound("one of +, -, *, /".to_string()))?;
+
+    let new_frame = |deepex: DeepEx<'a, T>| DerivativeFrame {
+        subs: sub_deepexes(var_idx, &deepex, &overloaded_ops),
+        deepex,
+        sub_ders: vec![],
+    };
+    // sub-expressions are derived on an explicit stack such that deeply nested expressions
+    // cannot overflow the call stack, a frame is finished once the derivatives of all its
+    // sub-expressions are known
+    let mut stack = vec![new_frame(deepex)];
+    while let Some(frame) = stack.pop() {
+        if let Some(sub) = frame.subs.get(frame.sub_ders.len()) {
+            let sub_frame = new_frame(sub.clone());
+            stack.push(frame);
+            stack.push(sub_frame);
+            continue;
+        }
+        let DerivativeFrame {
+            deepex,
+            subs,
+            sub_ders,
+        } = frame;
+        let inner = partial_derivative_inner(
+            var_idx,
+            deepex.clone(),
+            subs,
+            sub_ders,
+            partial_derivative_ops,
+            overloaded_ops.clone(),
+            ops,
+        )?;
+        let outer =
+            partial_derivative_outer(deepex, partial_derivative_ops, overloaded_ops.clone(), ops)?;
+        let mut res = mul_num(inner, outer)?;
+        res.compile();
+        // the shortcuts of mul_num and friends only see operands that are numbers while the
+        // derivative is built, e.g., not the exponent 2-1 of the derivative of x^2
+        res.prune_neutral_operands();
+        res.set_overloaded_ops(Some(overloaded_ops.clone()));
+        res.set_op_reprs(ops);
+        match stack.last_mut() {
+            Some(parent) => parent.sub_ders.push(res),
+            None => return Ok(res),
+        }
+    }
+    Err(ExError::Internal(
+        "stack of partial derivative ran empty".to_string(),
+    ))
 }
 
-fn add_num<'a, T: Float + Debug>(
+fn add_num<'a, T: DiffNum>(
     summand_1: DeepEx<'a, T>,
     summand_2: DeepEx<'a, T>,
-) -> Result<DeepEx<'a, T>, ExParseError> {
+) -> Result<DeepEx<'a, T>, ExError> {
     let (summand_1, summand_2) = summand_1.var_names_union(summand_2);
     Ok(if summand_1.is_zero() {
         summand_2
@@ -255,10 +425,10 @@ fn add_num<'a, T: Float + Debug>(
     })
 }
 
-fn sub_num<'a, T: Float + Debug>(
+fn sub_num<'a, T: DiffNum>(
     sub_1: DeepEx<'a, T>,
     sub_2: DeepEx<'a, T>,
-) -> Result<DeepEx<'a, T>, ExParseError> {
+) -> Result<DeepEx<'a, T>, ExError> {
     let (sub_1, sub_2) = sub_1.var_names_union(sub_2);
     Ok(if sub_2.is_zero() {
         sub_1
@@ -267,10 +437,10 @@ fn sub_num<'a, T: Float + Debug>(
     })
 }
 
-fn mul_num<'a, T: Float + Debug>(
+fn mul_num<'a, T: DiffNum>(
     factor_1: DeepEx<'a, T>,
     factor_2: DeepEx<'a, T>,
-) -> Result<DeepEx<'a, T>, ExParseError> {
+) -> Result<DeepEx<'a, T>, ExError> {
     let zero = DeepEx::zero(factor_1.unpack_and_clone_overloaded_ops()?);
     let (factor_1, factor_2) = factor_1.var_names_union(factor_2);
     let zero = zero.var_names_like_other(&factor_1);
@@ -285,10 +455,10 @@ fn mul_num<'a, T: Float + Debug>(
     })
 }
 
-fn div_num<'a, T: Float + Debug>(
+fn div_num<'a, T: DiffNum>(
     numerator: DeepEx<'a, T>,
     denominator: DeepEx<'a, T>,
-) -> Result<DeepEx<'a, T>, ExParseError> {
+) -> Result<DeepEx<'a, T>, ExError> {
     let zero = DeepEx::zero(numerator.unpack_and_clone_overloaded_ops()?);
     let (numerator, denominator) = numerator.var_names_union(denominator);
     let zero = zero.var_names_like_other(&numerator);
@@ -297,28 +467,27 @@ fn div_num<'a, T: Float + Debug>(
     } else if denominator.is_one() {
         Ok(numerator)
     } else if denominator.is_zero() {
-        Err(ExParseError {
-            msg: format!("division by zero, {}/{}", numerator, denominator),
+        Err(ExError::DivisionByZero {
+            numerator: numerator.to_string(),
+            denominator: denominator.to_string(),
         })
     } else {
         Ok(numerator / denominator)
     }
 }
 
-fn pow_num<'a, T: Float + Debug>(
+fn pow_num<'a, T: DiffNum>(
     base: DeepEx<'a, T>,
     exponent: DeepEx<'a, T>,
     power_op: BinOpsWithReprs<'a, T>,
-) -> Result<DeepEx<'a, T>, ExParseError> {
+) -> Result<DeepEx<'a, T>, ExError> {
     let zero = DeepEx::zero(base.unpack_and_clone_overloaded_ops()?);
     let one = DeepEx::one(base.unpack_and_clone_overloaded_ops()?);
     let (base, exponent) = base.var_names_union(exponent);
     let zero = zero.var_names_like_other(&base);
     let one = one.var_names_like_other(&base);
     Ok(if base.is_zero() && exponent.is_zero() {
-        return Err(ExParseError {
-            msg: "base and exponent both zero. help. fatal. ah. help.".to_string(),
-        })
+        return Err(ExError::ZeroPowZero);
     } else if base.is_zero() {
         zero
     } else if exponent.is_zero() {
@@ -328,17 +497,62 @@ fn pow_num<'a, T: Float + Debug>(
     })
 }
 
-pub fn make_partial_derivative_ops<'a, T: Float + Debug>() -> Vec<PartialDerivative<'a, T>> {
+/// Returns the argument of the outermost unary operator, i.e., `f` without its unary
+/// operators.
+fn unary_arg<'a, T: DiffNum>(f: DeepEx<'a, T>) -> DeepEx<'a, T> {
+    f.with_new_unary_op(UnaryOpWithReprs::new())
+}
+
+/// Returns the natural logarithm of `x` computed with the operator `log`.
+fn ln<'a, T: DiffNum>(x: T, ops: &[Operator<'a, T>]) -> Result<T, ExError> {
+    let op = find_op("log", ops).ok_or_else(|| ExError::OperatorNotFound("log".to_string()))?;
+    let log = op
+        .unary_op
+        .ok_or_else(|| ExError::OperatorNotUnary("log".to_string()))?;
+    Ok(log(x))
+}
+
+/// Returns the small integer `n` as sum of ones, since `T` cannot be converted from floats.
+fn int<T: DiffNum>(n: u8) -> T {
+    (0..n).fold(T::zero(), |acc, _| acc + T::one())
+}
+
+fn num<'a, T: DiffNum>(x: T, like: &DeepEx<'a, T>) -> Result<DeepEx<'a, T>, ExError> {
+    Ok(DeepEx::from_node(
+        DeepNode::Num(x, None),
+        like.unpack_and_clone_overloaded_ops()?,
+    )
+    .var_names_like_other(like))
+}
+
+/// Comparisons are piecewise constant, hence, their derivative is zero where it exists.
+fn compare<'a, T: DiffNum>(
+    f: &ValueDerivative<'a, T>,
+    g: &ValueDerivative<'a, T>,
+) -> Result<DeepEx<'a, T>, ExError> {
+    let (f_val, _) = f.val.clone().var_names_union(g.val.clone());
+    num(T::zero(), &f_val)
+}
+
+/// Derivative of the piecewise constant operators `floor`, `ceil`, `round`, and `sign`, which
+/// is zero where it exists.
+fn piecewise_constant<'a, T: DiffNum>(
+    f: DeepEx<'a, T>,
+    _: &[Operator<'a, T>],
+) -> Result<DeepEx<'a, T>, ExError> {
+    num(T::zero(), &f)
+}
+
+pub fn make_partial_derivative_ops<'a, T: DiffNum>() -> Vec<PartialDerivative<'a, T>> {
     vec![
         PartialDerivative {
             repr: "^",
             bin_op: Some(
-                |f: ValueDerivative<T>,
-                 g: ValueDerivative<T>,
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
                  ops: &[Operator<'a, T>]|
-                 -> Result<ValueDerivative<T>, ExParseError> {
+                 -> Result<DeepEx<'a, T>, ExError> {
                     let power_op = find_as_bin_op_with_reprs("^", ops)?;
-                    let log_op = find_as_unary_op_with_reprs("log", ops)?;
 
                     let one = DeepEx::one(f.val.unpack_and_clone_overloaded_ops()?);
                     let val = pow_num(f.val.clone(), g.val.clone(), power_op.clone())?;
@@ -350,124 +564,486 @@ pub fn make_partial_derivative_ops<'a, T: Float + Debug>() -> Vec<PartialDerivat
                         )?,
                         f.der.clone(),
                     )?;
+                    // for constant exponents, the logarithm of the base is not needed and
+                    // would be NaN for negative bases
+                    if g.der.is_zero() {
+                        return Ok(der_1);
+                    }
 
+                    let log_op = find_as_unary_op_with_reprs("log", ops)?;
                     let der_2 = mul_num(
-                        mul_num(val.clone(), f.val.operate_unary(log_op))?,
+                        mul_num(val, f.val.clone().operate_unary(log_op))?,
                         g.der.clone(),
                     )?;
 
-                    let der = add_num(der_1, der_2)?;
-                    Ok(ValueDerivative { val, der })
+                    add_num(der_1, der_2)
                 },
             ),
             unary_op: None,
+            nary_op: None,
         },
         PartialDerivative {
             repr: "+",
             bin_op: Some(
-                |f: ValueDerivative<T>,
-                 g: ValueDerivative<T>,
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
                  _: &[Operator<'a, T>]|
-                 -> Result<ValueDerivative<T>, ExParseError> {
-                    Ok(ValueDerivative {
-                        val: add_num(f.val, g.val)?,
-                        der: add_num(f.der, g.der)?,
-                    })
+                 -> Result<DeepEx<'a, T>, ExError> {
+                    add_num(f.der.clone(), g.der.clone())
                 },
             ),
             unary_op: Some(
-                |f: DeepEx<T>, _: &[Operator<'a, T>]| -> Result<DeepEx<T>, ExParseError> {
-                    Ok(f.clone())
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    num(T::one(), &f)
                 },
             ),
+            nary_op: None,
         },
         PartialDerivative {
             repr: "-",
             bin_op: Some(
-                |f: ValueDerivative<T>,
-                 g: ValueDerivative<T>,
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
                  _: &[Operator<'a, T>]|
-                 -> Result<ValueDerivative<T>, ExParseError> {
-                    Ok(ValueDerivative {
-                        val: sub_num(f.val, g.val)?,
-                        der: sub_num(f.der, g.der)?,
-                    })
+                 -> Result<DeepEx<'a, T>, ExError> {
+                    sub_num(f.der.clone(), g.der.clone())
                 },
             ),
             unary_op: Some(
-                |f: DeepEx<'a, T>,
-                 ops: &[Operator<'a, T>]|
-                 -> Result<DeepEx<'a, T>, ExParseError> {
-                    let minus = find_as_unary_op_with_reprs("-", ops)?;
-                    Ok(f.with_new_unary_op(minus))
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    num(-T::one(), &f)
                 },
             ),
+            nary_op: None,
         },
         PartialDerivative {
             repr: "*",
             bin_op: Some(
-                |f: ValueDerivative<T>,
-                 g: ValueDerivative<T>,
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
                  _: &[Operator<'a, T>]|
-                 -> Result<ValueDerivative<T>, ExParseError> {
-                    let val = mul_num(f.val.clone(), g.val.clone())?;
-
-                    let der_1 = mul_num(g.val, f.der)?;
-                    let der_2 = mul_num(g.der, f.val)?;
-                    let der = add_num(der_1, der_2)?;
-                    Ok(ValueDerivative { val, der })
+                 -> Result<DeepEx<'a, T>, ExError> {
+                    let der_1 = mul_num(g.val.clone(), f.der.clone())?;
+                    let der_2 = mul_num(g.der.clone(), f.val.clone())?;
+                    add_num(der_1, der_2)
                 },
             ),
             unary_op: None,
+            nary_op: None,
         },
         PartialDerivative {
             repr: "/",
             bin_op: Some(
-                |f: ValueDerivative<T>,
-                 g: ValueDerivative<T>,
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
                  _: &[Operator<'a, T>]|
-                 -> Result<ValueDerivative<T>, ExParseError> {
-                    let val = div_num(f.val.clone(), g.val.clone())?;
-
-                    let numerator =
-                        sub_num(mul_num(f.der, g.val.clone())?, mul_num(g.der, f.val)?)?;
-                    let denominator = mul_num(g.val.clone(), g.val)?;
-                    Ok(ValueDerivative {
-                        val,
-                        der: div_num(numerator, denominator)?,
-                    })
+                 -> Result<DeepEx<'a, T>, ExError> {
+                    let numerator = sub_num(
+                        mul_num(f.der.clone(), g.val.clone())?,
+                        mul_num(g.der.clone(), f.val.clone())?,
+                    )?;
+                    let denominator = mul_num(g.val.clone(), g.val.clone())?;
+                    div_num(numerator, denominator)
                 },
             ),
             unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "%",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 ops: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> {
+                    // f % g is f - g * trunc(f / g) and trunc is piecewise constant
+                    if g.der.is_zero() {
+                        return Ok(f.der.clone());
+                    }
+                    let trunc_op = find_as_unary_op_with_reprs("trunc", ops)?;
+                    let quotient = div_num(f.val.clone(), g.val.clone())?.operate_unary(trunc_op);
+                    sub_num(f.der.clone(), mul_num(quotient, g.der.clone())?)
+                },
+            ),
+            unary_op: None,
+            nary_op: None,
         },
         PartialDerivative {
             repr: "sin",
             bin_op: None,
             unary_op: Some(
-                |f: DeepEx<T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<T>, ExParseError> {
+                |f: DeepEx<T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<T>, ExError> {
                     let unary_op = find_as_unary_op_with_reprs("cos", ops)?;
                     Ok(f.with_new_unary_op(unary_op))
                 },
             ),
+            nary_op: None,
         },
         PartialDerivative {
             repr: "cos",
             bin_op: None,
             unary_op: Some(
-                |f: DeepEx<T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<T>, ExParseError> {
+                |f: DeepEx<T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<T>, ExError> {
                     let mut unary_op = find_as_unary_op_with_reprs("sin", ops)?;
                     let mut minus = find_as_unary_op_with_reprs("-", ops)?;
                     unary_op.append_front(&mut minus);
                     Ok(f.with_new_unary_op(unary_op))
                 },
             ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "tan",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let cos_op = find_as_unary_op_with_reprs("cos", ops)?;
+                    let one = num(T::one(), &f)?;
+                    let cos = f.with_new_unary_op(cos_op);
+                    div_num(one, mul_num(cos.clone(), cos)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "asin",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let sqrt_op = find_as_unary_op_with_reprs("sqrt", ops)?;
+                    let one = num(T::one(), &f)?;
+                    let arg = unary_arg(f);
+                    let radicand = sub_num(one.clone(), mul_num(arg.clone(), arg)?)?;
+                    div_num(one, radicand.operate_unary(sqrt_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "acos",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let sqrt_op = find_as_unary_op_with_reprs("sqrt", ops)?;
+                    let one = num(T::one(), &f)?;
+                    let minus_one = num(-T::one(), &f)?;
+                    let arg = unary_arg(f);
+                    let radicand = sub_num(one, mul_num(arg.clone(), arg)?)?;
+                    div_num(minus_one, radicand.operate_unary(sqrt_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "atan",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    let arg = unary_arg(f);
+                    div_num(one.clone(), add_num(one, mul_num(arg.clone(), arg)?)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "sinh",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let cosh_op = find_as_unary_op_with_reprs("cosh", ops)?;
+                    Ok(f.with_new_unary_op(cosh_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "cosh",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let sinh_op = find_as_unary_op_with_reprs("sinh", ops)?;
+                    Ok(f.with_new_unary_op(sinh_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "tanh",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    sub_num(one, mul_num(f.clone(), f)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "asinh",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let sqrt_op = find_as_unary_op_with_reprs("sqrt", ops)?;
+                    let one = num(T::one(), &f)?;
+                    let arg = unary_arg(f);
+                    let radicand = add_num(mul_num(arg.clone(), arg)?, one.clone())?;
+                    div_num(one, radicand.operate_unary(sqrt_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "acosh",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let sqrt_op = find_as_unary_op_with_reprs("sqrt", ops)?;
+                    let one = num(T::one(), &f)?;
+                    let arg = unary_arg(f);
+                    let radicand = sub_num(mul_num(arg.clone(), arg)?, one.clone())?;
+                    div_num(one, radicand.operate_unary(sqrt_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "atanh",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    let arg = unary_arg(f);
+                    div_num(one.clone(), sub_num(one, mul_num(arg.clone(), arg)?)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "exp",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    Ok(f)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "sqrt",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    let two = num(int(2), &f)?;
+                    div_num(one, mul_num(two, f)?)
+                },
+            ),
+            nary_op: None,
         },
         PartialDerivative {
             repr: "log",
             bin_op: None,
             unary_op: Some(
-                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExParseError> {
-                    Ok(DeepEx::one(f.unpack_and_clone_overloaded_ops()?) / f)
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    div_num(one, unary_arg(f))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "ln",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, _: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    div_num(one, unary_arg(f))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "log2",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    let ln_2 = num(ln(int(2), ops)?, &f)?;
+                    div_num(one, mul_num(unary_arg(f), ln_2)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "log10",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let one = num(T::one(), &f)?;
+                    let ln_10 = num(ln(int(10), ops)?, &f)?;
+                    div_num(one, mul_num(unary_arg(f), ln_10)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "exp2",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let exp2_op = find_as_unary_op_with_reprs("exp2", ops)?;
+                    let ln_2 = num(ln(int(2), ops)?, &f)?;
+                    mul_num(f.with_new_unary_op(exp2_op), ln_2)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "cbrt",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let cbrt_op = find_as_unary_op_with_reprs("cbrt", ops)?;
+                    let power_op = find_as_bin_op_with_reprs("^", ops)?;
+                    let one = num(T::one(), &f)?;
+                    let two = num(int(2), &f)?;
+                    let three = num(int(3), &f)?;
+                    let cbrt_squared = pow_num(f.with_new_unary_op(cbrt_op), two, power_op)?;
+                    div_num(one, mul_num(three, cbrt_squared)?)
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "abs",
+            bin_op: None,
+            unary_op: Some(
+                |f: DeepEx<'a, T>, ops: &[Operator<'a, T>]| -> Result<DeepEx<'a, T>, ExError> {
+                    let signum_op = find_as_unary_op_with_reprs("signum", ops)?;
+                    Ok(f.with_new_unary_op(signum_op))
+                },
+            ),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "floor",
+            bin_op: None,
+            unary_op: Some(piecewise_constant),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "ceil",
+            bin_op: None,
+            unary_op: Some(piecewise_constant),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "round",
+            bin_op: None,
+            unary_op: Some(piecewise_constant),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "sign",
+            bin_op: None,
+            unary_op: Some(piecewise_constant),
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "<",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 _: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> { compare(f, g) },
+            ),
+            unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "<=",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 _: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> { compare(f, g) },
+            ),
+            unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: ">",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 _: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> { compare(f, g) },
+            ),
+            unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: ">=",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 _: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> { compare(f, g) },
+            ),
+            unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "==",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 _: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> { compare(f, g) },
+            ),
+            unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "!=",
+            bin_op: Some(
+                |f: &ValueDerivative<T>,
+                 g: &ValueDerivative<T>,
+                 _: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> { compare(f, g) },
+            ),
+            unary_op: None,
+            nary_op: None,
+        },
+        PartialDerivative {
+            repr: "logb",
+            bin_op: None,
+            unary_op: None,
+            nary_op: Some(
+                |args: &[ValueDerivative<'a, T>],
+                 ops: &[Operator<'a, T>]|
+                 -> Result<DeepEx<'a, T>, ExError> {
+                    let (base, x) = match args {
+                        [base, x] => (base, x),
+                        _ => {
+                            return Err(ExError::Internal(format!(
+                                "logb expects 2 arguments, got {}",
+                                args.len()
+                            )))
+                        }
+                    };
+                    let log_op = find_as_unary_op_with_reprs("log", ops)?;
+                    let ln_base = base.val.clone().operate_unary(log_op.clone());
+                    let ln_x = x.val.clone().operate_unary(log_op);
+                    // logb(b, x) = ln(x)/ln(b), hence, the derivative is
+                    // x'/(x ln(b)) - ln(x) b'/(b ln(b)^2)
+                    let der_x = div_num(x.der.clone(), mul_num(x.val.clone(), ln_base.clone())?)?;
+                    let der_base = div_num(
+                        mul_num(ln_x, base.der.clone())?,
+                        mul_num(base.val.clone(), mul_num(ln_base.clone(), ln_base)?)?,
+                    )?;
+                    sub_num(der_x, der_base)
                 },
             ),
         },
@@ -477,7 +1053,10 @@ pub fn make_partial_derivative_ops<'a, T: Float + Debug>() -> Vec<PartialDerivat
 #[cfg(test)]
 use {
     super::flat::flatten,
-    crate::{operators::make_default_operators, util::assert_float_eq_f64},
+    crate::{
+        operators::{make_default_operators, UnaryPosition},
+        util::{assert_float_eq, assert_float_eq_f64},
+    },
 };
 
 #[test]
@@ -485,7 +1064,7 @@ fn test_partial() {
     let ops = make_default_operators::<f64>();
     let dut = DeepEx::<f64>::from_str("z*sin(x)+cos(y)^(sin(z))").unwrap();
     let d_z = partial_deepex(2, dut.clone(), &ops).unwrap();
-    let flat = flatten(d_z);
+    let flat = flatten(d_z).unwrap();
     assert_float_eq_f64(
         flat.eval(&[-0.18961918881278095, -6.383306547710852, 3.1742139703464503])
             .unwrap(),
@@ -493,22 +1072,49 @@ fn test_partial() {
     );
     let dut = DeepEx::<f64>::from_str("sin(x)/x^2").unwrap();
     let d_x = partial_deepex(0, dut, &ops).unwrap();
-    let flat = flatten(d_x);
+    let flat = flatten(d_x).unwrap();
     assert_float_eq_f64(
         flat.eval(&[-0.18961918881278095]).unwrap(),
         -27.977974668662565,
     );
 
     let dut = DeepEx::<f64>::from_str("x^y").unwrap();
-    let d_x = partial_deepex(0, dut, &ops).unwrap();
-    let flat = flatten(d_x);
+    let d_x = partial_deepex(0, dut.clone(), &ops).unwrap();
+    let flat = flatten(d_x).unwrap();
     assert_float_eq_f64(flat.eval(&[7.5, 3.5]).unwrap(), 539.164392544148);
+    let d_y = flatten(partial_deepex(1, dut, &ops).unwrap()).unwrap();
+    assert!(d_y.unparse().unwrap().contains("log"));
+    assert_float_eq_f64(d_y.eval(&[2.0, 3.0]).unwrap(), 8.0 * 2f64.ln());
+}
+
+#[test]
+fn test_partial_constant_exponent() {
+    let ops = make_default_operators::<f64>();
+    let dut = DeepEx::<f64>::from_str("x^2").unwrap();
+    let d_x = flatten(partial_deepex(0, dut, &ops).unwrap()).unwrap();
+    assert!(!d_x.unparse().unwrap().contains("log"));
+    assert_float_eq_f64(d_x.eval(&[-3.0]).unwrap(), -6.0);
+    let dut = DeepEx::<f64>::from_str("(2*x-y)^3").unwrap();
+    let d_x = flatten(partial_deepex(0, dut, &ops).unwrap()).unwrap();
+    assert_float_eq_f64(d_x.eval(&[-1.0, 1.0]).unwrap(), 54.0);
+
+    // the logarithm is only required for exponents that depend on the variable
+    let ops_without_log = ops
+        .iter()
+        .filter(|op| op.repr != "log")
+        .cloned()
+        .collect::<Vec<_>>();
+    let dut = DeepEx::<f64>::from_str("x^2").unwrap();
+    let d_x = flatten(partial_deepex(0, dut, &ops_without_log).unwrap()).unwrap();
+    assert_float_eq_f64(d_x.eval(&[-3.0]).unwrap(), -6.0);
+    let dut = DeepEx::<f64>::from_str("x^y").unwrap();
+    assert!(partial_deepex(1, dut, &ops_without_log).is_err());
 }
 
 #[test]
 fn test_partial_3_vars() {
     fn eval(deepex: DeepEx<f64>, vars: &[f64]) -> f64 {
-        flatten(deepex).eval(vars).unwrap()
+        flatten(deepex).unwrap().eval(vars).unwrap()
     }
     fn assert(s: &str, vars: &[f64], ref_vals: &[f64]) {
         let ops = make_default_operators::<f64>();
@@ -533,9 +1139,9 @@ fn test_partial_x2x() {
     let ops = make_default_operators::<f64>();
     let deepex = DeepEx::<f64>::from_str("x * 2 * x").unwrap();
     let derivative = partial_deepex(0, deepex.clone(), &ops).unwrap();
-    let result = flatten(derivative.clone()).eval(&[0.0]).unwrap();
+    let result = flatten(derivative.clone()).unwrap().eval(&[0.0]).unwrap();
     assert_float_eq_f64(result, 0.0);
-    let result = flatten(derivative).eval(&[1.0]).unwrap();
+    let result = flatten(derivative).unwrap().eval(&[1.0]).unwrap();
     assert_float_eq_f64(result, 4.0);
 }
 
@@ -544,9 +1150,9 @@ fn test_partial_cos_squared() {
     let ops = make_default_operators::<f64>();
     let deepex = DeepEx::<f64>::from_str("cos(y) ^ 2").unwrap();
     let derivative = partial_deepex(0, deepex.clone(), &ops).unwrap();
-    let result = flatten(derivative.clone()).eval(&[0.0]).unwrap();
+    let result = flatten(derivative.clone()).unwrap().eval(&[0.0]).unwrap();
     assert_float_eq_f64(result, 0.0);
-    let result = flatten(derivative).eval(&[1.0]).unwrap();
+    let result = flatten(derivative).unwrap().eval(&[1.0]).unwrap();
     assert_float_eq_f64(result, -0.9092974268256818);
 }
 
@@ -555,14 +1161,14 @@ fn test_partial_combined() {
     let ops = make_default_operators::<f64>();
     let deepex = DeepEx::<f64>::from_str("sin(x) + cos(y) ^ 2").unwrap();
     let d_y = partial_deepex(1, deepex.clone(), &ops).unwrap();
-    let result = flatten(d_y.clone()).eval(&[231.431, 0.0]).unwrap();
+    let result = flatten(d_y.clone()).unwrap().eval(&[231.431, 0.0]).unwrap();
     assert_float_eq_f64(result, 0.0);
-    let result = flatten(d_y).eval(&[-12.0, 1.0]).unwrap();
+    let result = flatten(d_y).unwrap().eval(&[-12.0, 1.0]).unwrap();
     assert_float_eq_f64(result, -0.9092974268256818);
     let d_x = partial_deepex(0, deepex.clone(), &ops).unwrap();
-    let result = flatten(d_x.clone()).eval(&[231.431, 0.0]).unwrap();
+    let result = flatten(d_x.clone()).unwrap().eval(&[231.431, 0.0]).unwrap();
     assert_float_eq_f64(result, 0.5002954462477305);
-    let result = flatten(d_x).eval(&[-12.0, 1.0]).unwrap();
+    let result = flatten(d_x).unwrap().eval(&[-12.0, 1.0]).unwrap();
     assert_float_eq_f64(result, 0.8438539587324921);
 }
 
@@ -571,9 +1177,12 @@ fn test_partial_derivative_second_var() {
     let ops = make_default_operators::<f64>();
     let deepex = DeepEx::<f64>::from_str("sin(x) + cos(y)").unwrap();
     let derivative = partial_deepex(1, deepex.clone(), &ops).unwrap();
-    let result = flatten(derivative.clone()).eval(&[231.431, 0.0]).unwrap();
+    let result = flatten(derivative.clone())
+        .unwrap()
+        .eval(&[231.431, 0.0])
+        .unwrap();
     assert_float_eq_f64(result, 0.0);
-    let result = flatten(derivative).eval(&[-12.0, 1.0]).unwrap();
+    let result = flatten(derivative).unwrap().eval(&[-12.0, 1.0]).unwrap();
     assert_float_eq_f64(result, -0.8414709848078965);
 }
 
@@ -583,9 +1192,12 @@ fn test_partial_derivative_first_var() {
 
     let deepex = DeepEx::<f64>::from_str("sin(x) + cos(y)").unwrap();
     let derivative = partial_deepex(0, deepex.clone(), &ops).unwrap();
-    let result = flatten(derivative.clone()).eval(&[0.0, 2345.03]).unwrap();
+    let result = flatten(derivative.clone())
+        .unwrap()
+        .eval(&[0.0, 2345.03])
+        .unwrap();
     assert_float_eq_f64(result, 1.0);
-    let result = flatten(derivative).eval(&[1.0, 43212.43]).unwrap();
+    let result = flatten(derivative).unwrap().eval(&[1.0, 43212.43]).unwrap();
     assert_float_eq_f64(result, 0.5403023058681398);
 }
 
@@ -598,16 +1210,26 @@ fn test_partial_inner() {
         let ovops = find_overloaded_ops(&ops).unwrap();
         match deepex_1.nodes()[0].clone() {
             DeepNode::Expr(e) => {
+                let subs = sub_deepexes(var_idx, &e, &ovops);
+                let sub_ders = subs
+                    .iter()
+                    .map(|sub| {
+                        partial_derivative(var_idx, sub.clone(), &partial_derivative_ops, &ops)
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
                 let deri = partial_derivative_inner(
                     var_idx,
-                    e.clone(),
+                    (*e).clone(),
+                    subs,
+                    sub_ders,
                     &partial_derivative_ops,
                     ovops,
                     &ops,
                 )
                 .unwrap();
 
-                let flatex = flatten(deri);
+                let flatex = flatten(deri).unwrap();
                 for i in 0..vals.len() {
                     assert_float_eq_f64(flatex.eval(&[vals[i]]).unwrap(), ref_vals[i]);
                 }
@@ -628,17 +1250,13 @@ fn test_partial_outer() {
         let deepex = deepex_1.nodes()[0].clone();
         let ovops = find_overloaded_ops(&ops).unwrap();
 
-        match deepex {
-            DeepNode::Expr(e) => {
-                let deri =
-                    partial_derivative_outer(e.clone(), &partial_derivative_ops, ovops, &ops)
-                        .unwrap();
-                let flatex = flatten(deri);
-                for i in 0..vals.len() {
-                    assert_float_eq_f64(flatex.eval(&[vals[i]]).unwrap(), ref_vals[i]);
-                }
+        if let DeepNode::Expr(e) = deepex {
+            let deri = partial_derivative_outer((*e).clone(), &partial_derivative_ops, ovops, &ops)
+                .unwrap();
+            let flatex = flatten(deri).unwrap();
+            for i in 0..vals.len() {
+                assert_float_eq_f64(flatex.eval(&[vals[i]]).unwrap(), ref_vals[i]);
             }
-            _ => (),
         }
     }
     test("x", &[1.0, 0.0, 2.0], &[1.0, 0.0, 2.0]);
@@ -659,27 +1277,385 @@ fn test_partial_derivative_simple() {
     assert_eq!(derivative.nodes().len(), 1);
     assert_eq!(derivative.bin_ops().ops.len(), 0);
     match derivative.nodes()[0] {
-        DeepNode::Num(n) => assert_float_eq_f64(n, 0.0),
-        _ => assert!(false),
+        DeepNode::Num(n, _) => assert_float_eq_f64(n, 0.0),
+        _ => unreachable!(),
     }
     let deepex = DeepEx::<f64>::from_str("x").unwrap();
     let derivative = partial_deepex(0, deepex, &ops).unwrap();
     assert_eq!(derivative.nodes().len(), 1);
     assert_eq!(derivative.bin_ops().ops.len(), 0);
     match derivative.nodes()[0] {
-        DeepNode::Num(n) => assert_float_eq_f64(n, 1.0),
-        _ => assert!(false),
+        DeepNode::Num(n, _) => assert_float_eq_f64(n, 1.0),
+        _ => unreachable!(),
     }
     let deepex = DeepEx::<f64>::from_str("x^2").unwrap();
     let derivative = partial_deepex(0, deepex, &ops).unwrap();
-    let result = flatten(derivative).eval(&[4.5]).unwrap();
+    let result = flatten(derivative).unwrap().eval(&[4.5]).unwrap();
     assert_float_eq_f64(result, 9.0);
 
     let deepex = DeepEx::<f64>::from_str("sin(x)").unwrap();
 
     let derivative = partial_deepex(0, deepex.clone(), &ops).unwrap();
-    let result = flatten(derivative.clone()).eval(&[0.0]).unwrap();
+    let result = flatten(derivative.clone()).unwrap().eval(&[0.0]).unwrap();
     assert_float_eq_f64(result, 1.0);
-    let result = flatten(derivative).eval(&[1.0]).unwrap();
+    let result = flatten(derivative).unwrap().eval(&[1.0]).unwrap();
     assert_float_eq_f64(result, 0.5403023058681398);
 }
+
+#[test]
+fn test_partial_unary() {
+    fn test(text: &str, x: f64, reference: f64) {
+        let ops = make_default_operators::<f64>();
+        let deepex = DeepEx::<f64>::from_str(text).unwrap();
+        let d_x = flatten(partial_deepex(0, deepex, &ops).unwrap()).unwrap();
+        assert_float_eq_f64(d_x.eval(&[x]).unwrap(), reference);
+    }
+    test("abs(x)", 2.0, 1.0);
+    test("abs(x)", -2.0, -1.0);
+    test("log(x)", 2.0, 0.5);
+    test("ln(x)", 4.0, 0.25);
+    test("ln(x^2)", 4.0, 0.5);
+    test("log10(x)", 5.0, 1.0 / (5.0 * std::f64::consts::LN_10));
+    test("exp2(x)", 3.0, 8.0 * std::f64::consts::LN_2);
+    test("cbrt(x)", 8.0, 1.0 / 12.0);
+    test("3*cbrt(2*x)", 4.0, 0.5);
+    // piecewise constant operators
+    test("floor(x^2)", 1.5, 0.0);
+    test("ceil(x^2)", 1.5, 0.0);
+    test("round(x)*x", 2.3, 2.0);
+    test("sign(x)*x", -2.0, -1.0);
+}
+
+#[test]
+fn test_partial_closed_form() {
+    type Reference = fn(f64) -> f64;
+    fn test(text: &str, reference: Reference, xs: &[f64]) {
+        let ops = make_default_operators::<f64>();
+        let deepex = DeepEx::<f64>::from_str(text).unwrap();
+        let d_x = flatten(partial_deepex(0, deepex, &ops).unwrap()).unwrap();
+        for x in xs {
+            assert_float_eq::<f64>(
+                d_x.eval(&[*x]).unwrap(),
+                reference(*x),
+                1e-12,
+                &format!("d/dx {} at {}", text, x),
+            );
+        }
+    }
+    let xs = [-0.7, -0.2, 0.3, 0.9];
+    let pos = [0.2, 1.0, 2.5, 7.0];
+    test("tan(x)", |x| 1.0 / x.cos().powi(2), &xs);
+    test("exp(x)", |x| x.exp(), &xs);
+    test("sqrt(x)", |x| 0.5 / x.sqrt(), &pos);
+    test("sinh(x)", |x| x.cosh(), &xs);
+    test("cosh(x)", |x| x.sinh(), &xs);
+    test("tanh(x)", |x| 1.0 - x.tanh().powi(2), &xs);
+    test("asin(x)", |x| 1.0 / (1.0 - x * x).sqrt(), &xs);
+    test("acos(x)", |x| -1.0 / (1.0 - x * x).sqrt(), &xs);
+    test("atan(x)", |x| 1.0 / (1.0 + x * x), &xs);
+    test("asinh(x)", |x| 1.0 / (x * x + 1.0).sqrt(), &xs);
+    test("acosh(x)", |x| 1.0 / (x * x - 1.0).sqrt(), &[1.5, 2.5, 7.0]);
+    test("atanh(x)", |x| 1.0 / (1.0 - x * x), &xs);
+    // comparisons are piecewise constant
+    test("(x > 0)*x + (x <= 0)*(-x)", |x| x.signum(), &xs);
+    test(
+        "(x >= 0.5)*x^2 - (x != 0.3)",
+        |x| if x >= 0.5 { 2.0 * x } else { 0.0 },
+        &xs,
+    );
+    test("x < 2*x", |_| 0.0, &xs);
+    test("log2(x)", |x| 1.0 / (x * std::f64::consts::LN_2), &pos);
+    test("log10(x)", |x| 1.0 / (x * std::f64::consts::LN_10), &pos);
+    test("ln(x)", |x| 1.0 / x, &pos);
+    test("logb(2, x)", |x| 1.0 / (x * std::f64::consts::LN_2), &pos);
+    // the base 1 is excluded
+    let bases = [0.2, 2.5, 7.0];
+    test(
+        "logb(x, 8)",
+        |x| -(8f64.ln()) / (x * x.ln().powi(2)),
+        &bases,
+    );
+    test("logb(x, x^3)", |_| 0.0, &bases);
+    // unary operators bind stronger than binary ones, i.e., this is (-logb(..))^2
+    test(
+        "-logb(x + 1, 3*x)^2",
+        |x| {
+            let (b, y) = (x + 1.0, 3.0 * x);
+            2.0 * y.log(b) * (1.0 / (x * b.ln()) - y.ln() / (b * b.ln().powi(2)))
+        },
+        &pos,
+    );
+    test("x-3*x^2", |x| 1.0 - 6.0 * x, &xs);
+    test(
+        "sin(x)/(x^2+1)",
+        |x| (x.cos() * (x * x + 1.0) - 2.0 * x * x.sin()) / (x * x + 1.0).powi(2),
+        &xs,
+    );
+    test("1/x", |x| -1.0 / (x * x), &xs);
+    // the remainder has the sign of the dividend like `%` of f64
+    test("x%0.25", |_| 1.0, &xs);
+    test("-5%x", |x| -(-5.0 / x).trunc(), &[0.3, 0.7, 2.0, 3.0]);
+    // chains of unary operators
+    test("-x", |_| -1.0, &xs);
+    test("+x", |_| 1.0, &xs);
+    test("-(x^2)", |x| -2.0 * x, &xs);
+    test("-sin(x)", |x| -x.cos(), &xs);
+    test("exp(-x)", |x| -(-x).exp(), &xs);
+    test("sqrt(x^2+1)", |x| x / (x * x + 1.0).sqrt(), &xs);
+    test(
+        "tan(sinh(2*x))",
+        |x| 2.0 * (2.0 * x).cosh() / (2.0 * x).sinh().cos().powi(2),
+        &xs,
+    );
+    test("log2(cosh(x))", |x| x.tanh() / std::f64::consts::LN_2, &xs);
+    test("atan(exp(x))", |x| x.exp() / (1.0 + (2.0 * x).exp()), &xs);
+}
+
+#[test]
+fn test_check_partial() {
+    use crate::{check_partial, parse_with_default_ops};
+    let points: [&[f64]; 4] = [&[0.3, 0.4], &[0.55, 1.3], &[0.8, 0.7], &[0.45, 1.1]];
+    for pd in make_partial_derivative_ops::<f64>() {
+        let mut texts = vec![];
+        if pd.bin_op.is_some() {
+            texts.push(format!("x{}y", pd.repr));
+        }
+        if pd.unary_op.is_some() {
+            // the argument of acosh needs to exceed 1, the ones of asin, acos, and atanh
+            // need to be below 1
+            let arg = if pd.repr == "acosh" { "x+y+1" } else { "x*y" };
+            texts.push(format!("{}({})", pd.repr, arg));
+        }
+        if pd.nary_op.is_some() {
+            texts.push(format!("{}(x,y)", pd.repr));
+        }
+        assert!(!texts.is_empty(), "{}", pd.repr);
+        for text in texts {
+            let expr = parse_with_default_ops::<f64>(&text).unwrap();
+            for var_idx in 0..expr.var_names().len() {
+                if let Err(e) = check_partial(&expr, var_idx, &points, 1e-6) {
+                    panic!("{}: {}", text, e);
+                }
+            }
+        }
+    }
+
+    // a wrong derivative is reported with the first failing point
+    let mut ops = make_default_operators::<f64>().to_vec();
+    let sin = ops.iter_mut().find(|op| op.repr == "sin").unwrap();
+    sin.unary_op = Some(|a| a.sin() + a * a);
+    let expr = crate::parse("sin(x)", &ops).unwrap();
+    check_partial(&expr, 0, &[&[0.0]], 1e-6).unwrap();
+    let err = check_partial(&expr, 0, &[&[0.0], &[0.5], &[-0.5]], 1e-6).unwrap_err();
+    match &err {
+        ExError::PartialMismatch {
+            var_idx,
+            point,
+            max_deviation,
+            ..
+        } => {
+            assert_eq!(*var_idx, 0);
+            assert_eq!(point, &["0.5"]);
+            // the deviation at -0.5 is larger than at the first failing point
+            assert_float_eq::<f64>(max_deviation.parse().unwrap(), 1.0, 1e-6, "");
+        }
+        _ => panic!("expected a mismatch, got {:?}", err),
+    }
+    assert_eq!(err.code(), "E057");
+    assert_eq!(
+        check_partial(&expr, 1, &[&[0.0]], 1e-6),
+        Err(ExError::VarIdxOutOfRange { idx: 1, n_vars: 1 })
+    );
+}
+
+#[test]
+fn test_partial_not_differentiable() {
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(Operator {
+        repr: "dbl",
+        bin_op: None,
+        unary_op: Some(|a| 2.0 * a),
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    });
+    let deepex = DeepEx::from_ops("y*dbl(x)", &ops).unwrap();
+    let err = partial_deepex(0, deepex, &ops).unwrap_err();
+    assert_eq!(err, ExError::DerivativeNotFound("dbl".to_string()));
+    assert!(err.msg().contains("`dbl`"));
+}
+
+#[test]
+fn test_partial_with_rules() {
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(Operator {
+        repr: "sigmoid",
+        bin_op: None,
+        unary_op: Some(|a| 1.0 / (1.0 + (-a).exp())),
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    });
+    let sigmoid = PartialDerivative {
+        repr: "sigmoid",
+        bin_op: None,
+        unary_op: Some(|s, _| Ok(s.clone() * (s.num_like(1.0) - s))),
+        nary_op: None,
+    };
+    let expr = crate::parse("sigmoid(2*x)", &ops).unwrap();
+    assert!(expr.clone().partial(0).is_err());
+    let d_x = expr.clone().partial_with_rules(0, &[sigmoid]).unwrap();
+    let h = 1e-5;
+    for x in [-3.0, -0.5, 0.0, 0.7, 2.5] {
+        let numeric = (expr.eval(&[x + h]).unwrap() - expr.eval(&[x - h]).unwrap()) / (2.0 * h);
+        assert_float_eq::<f64>(d_x.eval(&[x]).unwrap(), numeric, 1e-8, "");
+    }
+
+    // user rules take precedence over the built-in ones
+    let times_three = PartialDerivative {
+        repr: "*",
+        bin_op: Some(|f, g, _| Ok((f.der.clone() * g.val.clone()) * f.val.num_like(3.0))),
+        unary_op: None,
+        nary_op: None,
+    };
+    let expr = crate::parse("x*2", &ops).unwrap();
+    let d_x = expr.partial_with_rules(0, &[times_three]).unwrap();
+    assert_float_eq_f64(d_x.eval(&[1.0]).unwrap(), 6.0);
+}
+
+#[test]
+fn test_partial_complex() {
+    use crate::{parse_with_number_pattern, BinOp};
+    use num::complex::Complex64;
+    fn bin(
+        repr: &str,
+        apply: fn(Complex64, Complex64) -> Complex64,
+        prio: i32,
+    ) -> Operator<'_, Complex64> {
+        Operator {
+            repr,
+            bin_op: Some(BinOp { apply, prio }),
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        }
+    }
+    let ops = [
+        bin("+", |a, b| a + b, 0),
+        Operator {
+            unary_op: Some(|a| -a),
+            ..bin("-", |a, b| a - b, 0)
+        },
+        bin("*", |a, b| a * b, 1),
+        bin("/", |a, b| a / b, 1),
+        bin("^", |a, b| a.powc(b), 2),
+        Operator {
+            repr: "exp",
+            bin_op: None,
+            unary_op: Some(|a| a.exp()),
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        },
+    ];
+    type Reference = fn(Complex64, Complex64) -> Complex64;
+    let test = |text, reference: Reference, d_reference: Reference| {
+        let expr = parse_with_number_pattern(text, &ops, r"\d+\.?\d*i?").unwrap();
+        let d_z = expr.clone().partial_with_ops(1, &ops).unwrap();
+        for (w, z) in [(1.0, 0.5), (-0.25, 0.0), (2.0, -1.5)] {
+            let (w, z) = (Complex64::new(w, 0.5), Complex64::new(0.3, z));
+            let assert_close = |value: Complex64, reference: Complex64| {
+                assert!(
+                    (value - reference).norm() < 1e-12,
+                    "{} {}",
+                    value,
+                    reference
+                );
+            };
+            assert_close(expr.eval(&[w, z]).unwrap(), reference(w, z));
+            assert_close(d_z.eval(&[w, z]).unwrap(), d_reference(w, z));
+        }
+    };
+    const I: Complex64 = Complex64::new(0.0, 1.0);
+    test("z^2 + w*z", |w, z| z * z + w * z, |w, z| 2.0 * z + w);
+    test(
+        "exp(2i*z)/w - 1.5/z",
+        |w, z| (2.0 * I * z).exp() / w - 1.5 / z,
+        |w, z| 2.0 * I * (2.0 * I * z).exp() / w + 1.5 / (z * z),
+    );
+    test(
+        "w*exp(z^3 - z)",
+        |w, z| w * (z.powu(3) - z).exp(),
+        |w, z| w * (3.0 * z * z - 1.0) * (z.powu(3) - z).exp(),
+    );
+    // the derivative of variable exponents needs the logarithm
+    let expr = parse_with_number_pattern("z^w", &ops, r"\d+\.?\d*i?").unwrap();
+    assert!(expr.partial_with_ops(1, &ops).is_ok());
+    let expr = parse_with_number_pattern("z^w", &ops, r"\d+\.?\d*i?").unwrap();
+    assert_eq!(
+        expr.partial_with_ops(0, &ops).unwrap_err(),
+        ExError::OperatorNotFound("log".to_string())
+    );
+}
+
+#[test]
+fn test_partial_large_polynomial() {
+    use crate::parse_with_default_ops;
+    // about 2000 nodes, the sums of the derivative must not nest as deep as the number
+    // of terms
+    let n_terms = 667;
+    let coef = |i: usize| (i % 9 + 1) as f64;
+    let text = (0..n_terms)
+        .map(|i| format!("{}*x^{}", coef(i), i % 4))
+        .collect::<Vec<_>>()
+        .join("+");
+    let expr = parse_with_default_ops::<f64>(&text).unwrap();
+    assert!(expr.node_count() >= 2000);
+    let d_x = expr.partial(0).unwrap();
+    for x in [-1.5f64, 0.0, 0.5, 2.0] {
+        let reference = (0..n_terms)
+            .map(|i| {
+                let exponent = (i % 4) as i32;
+                coef(i) * f64::from(exponent) * x.powi((exponent - 1).max(0))
+            })
+            .sum::<f64>();
+        let tol = 1e-12 * reference.abs().max(1.0);
+        assert_float_eq::<f64>(d_x.eval(&[x]).unwrap(), reference, tol, "");
+    }
+}
+
+#[test]
+fn test_partial_minimal_forms() {
+    use crate::parse_with_default_ops;
+    let d_x = |text| {
+        parse_with_default_ops::<f64>(text)
+            .unwrap()
+            .partial(0)
+            .unwrap()
+    };
+    let d = d_x("2*sin(x)");
+    assert_eq!(d.node_count(), 2);
+    assert_float_eq_f64(d.eval(&[0.5]).unwrap(), 2.0 * 0.5f64.cos());
+    let d = d_x("x*y");
+    assert_eq!(d.node_count(), 1);
+    assert_float_eq_f64(d.eval(&[3.0, 7.0]).unwrap(), 7.0);
+    let d = d_x("x^3");
+    assert_eq!(d.node_count(), 3);
+    assert_float_eq_f64(d.eval(&[-2.0]).unwrap(), 12.0);
+    let d = d_x("x^2*y");
+    assert_eq!(d.node_count(), 3);
+    assert_eq!(d.unparse().unwrap(), "y*(x*2.0)");
+    assert_float_eq_f64(d.eval(&[3.0, 5.0]).unwrap(), 30.0);
+    let d = d_x("(x+1)^2");
+    assert_eq!(d.node_count(), 3);
+    assert_float_eq_f64(d.eval(&[2.0]).unwrap(), 6.0);
+}
@@ -0,0 +1,362 @@
+use super::{
+    deep::{reset_vars, BinOpsWithReprs, DeepEx, DeepNode, UnaryOpWithReprs},
+    deep_details::find_overloaded_ops,
+    flat::{self, FlatEx},
+};
+use crate::{
+    make_default_operators,
+    operators::{Operator, UnaryOp, UnaryPosition},
+    parse_with_default_ops, ExError,
+};
+use std::{fmt::Write, mem};
+
+/// An expression that has been parsed at compile time with the macro `static_expr!` of the
+/// crate `exmex-macros` against the [default operators](make_default_operators) for `f64`.
+/// A `StaticEx` is a constant and can be turned into a [`FlatEx`](FlatEx) without parsing.
+///
+/// ```rust,ignore
+/// use exmex::StaticEx;
+/// use exmex_macros::static_expr;
+///
+/// const EXPR: StaticEx = static_expr!("x^2 + sin(y)");
+/// const VAR_NAMES: &[&str] = EXPR.var_names();
+/// assert_eq!(VAR_NAMES, ["x", "y"]);
+/// let flatex = EXPR.to_flatex()?;
+/// assert!((flatex.eval(&[2.0, 0.0])? - 4.0).abs() < 1e-12);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StaticEx {
+    #[doc(hidden)]
+    pub var_names: &'static [&'static str],
+    #[doc(hidden)]
+    pub root: StaticDeepEx,
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StaticDeepEx {
+    pub nodes: &'static [StaticNode],
+    pub bin_ops: &'static [&'static str],
+    pub unary_ops: &'static [(&'static str, UnaryPosition)],
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StaticNode {
+    Num(f64),
+    Var(&'static str),
+    Expr(StaticDeepEx),
+    NaryCall {
+        repr: &'static str,
+        args: &'static [StaticDeepEx],
+    },
+}
+
+impl StaticEx {
+    /// Returns the names of the variables in alphabetical order, i.e., in the order
+    /// expected by [`FlatEx::eval`](FlatEx::eval).
+    pub const fn var_names(&self) -> &'static [&'static str] {
+        self.var_names
+    }
+
+    /// Constructs the expression from the pre-parsed structure.
+    ///
+    /// # Errors
+    ///
+    /// If the structure refers to an operator that is not one of the default operators,
+    /// which does not happen for structures created by `static_expr!`, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn to_flatex(&self) -> Result<FlatEx<'static, f64>, ExError> {
+        let ops = make_default_operators::<f64>();
+        let mut deepex = to_deepex(&self.root, &ops)?;
+        reset_vars(&mut deepex, self.var_names.iter().copied().collect());
+        deepex.set_overloaded_ops(find_overloaded_ops(&ops));
+        deepex.set_op_reprs(&ops);
+        flat::flatten(deepex)
+    }
+}
+
+fn to_deepex(
+    static_ex: &StaticDeepEx,
+    ops: &[Operator<'static, f64>],
+) -> Result<DeepEx<'static, f64>, ExError> {
+    // nested expressions and arguments of n-ary calls are converted on an explicit stack
+    // such that deeply nested structures cannot overflow the call stack
+    let mut stack = vec![(static_ex, vec![], vec![])];
+    while let Some((static_ex, nodes, args)) = stack.last_mut() {
+        let static_ex = *static_ex;
+        match static_ex.nodes.get(nodes.len()) {
+            Some(StaticNode::Num(n)) => nodes.push(DeepNode::Num(*n, None)),
+            Some(StaticNode::Var(name)) => nodes.push(DeepNode::Var((0, *name))),
+            Some(StaticNode::Expr(e)) => stack.push((e, vec![], vec![])),
+            Some(StaticNode::NaryCall {
+                repr,
+                args: static_args,
+            }) => match static_args.get(args.len()) {
+                Some(arg) => stack.push((arg, vec![], vec![])),
+                None => {
+                    let args = mem::take(args);
+                    nodes.push(make_nary_call(repr, args, ops)?);
+                }
+            },
+            None => {
+                let nodes = mem::take(nodes);
+                let deepex = make_deepex(static_ex, nodes, ops)?;
+                stack.pop();
+                match stack.last_mut() {
+                    Some((parent, parent_nodes, parent_args)) => {
+                        match parent.nodes[parent_nodes.len()] {
+                            StaticNode::NaryCall { .. } => parent_args.push(deepex),
+                            _ => parent_nodes.push(DeepNode::expr(deepex)),
+                        }
+                    }
+                    None => return Ok(deepex),
+                }
+            }
+        }
+    }
+    Err(ExError::Internal(
+        "stack of static expression ran empty".to_string(),
+    ))
+}
+
+/// Creates the expression of `static_ex` with its converted `nodes`.
+fn make_deepex(
+    static_ex: &StaticDeepEx,
+    nodes: Vec<DeepNode<'static, f64>>,
+    ops: &[Operator<'static, f64>],
+) -> Result<DeepEx<'static, f64>, ExError> {
+    let find_op = |repr: &str, is_binary: bool| {
+        ops.iter()
+            .find(|op| {
+                op.repr == repr
+                    && if is_binary {
+                        op.bin_op.is_some()
+                    } else {
+                        op.unary_op.is_some()
+                    }
+            })
+            .ok_or_else(|| ExError::Internal(format!("unknown operator {}", repr)))
+    };
+    let mut bin_ops = BinOpsWithReprs::new();
+    for repr in static_ex.bin_ops {
+        let op = find_op(repr, true)?;
+        bin_ops.reprs.push(op.repr);
+        bin_ops.ops.push(op.bin_op.unwrap());
+    }
+    let mut unary_ops = vec![];
+    for (repr, _) in static_ex.unary_ops {
+        unary_ops.push(find_op(repr, false)?);
+    }
+    let unary_op = UnaryOpWithReprs {
+        reprs: unary_ops.iter().map(|op| op.repr).collect(),
+        positions: static_ex.unary_ops.iter().map(|(_, pos)| *pos).collect(),
+        op: UnaryOp::from_vec(unary_ops.iter().map(|op| op.unary_op.unwrap()).collect()),
+    };
+    DeepEx::new(nodes, bin_ops, unary_op)
+}
+
+/// Creates the call of the n-ary operator `repr` with its converted `args`.
+fn make_nary_call(
+    repr: &str,
+    args: Vec<DeepEx<'static, f64>>,
+    ops: &[Operator<'static, f64>],
+) -> Result<DeepNode<'static, f64>, ExError> {
+    let (repr, op) = ops
+        .iter()
+        .find_map(|op| match op.nary_op {
+            Some(nary_op) if op.repr == repr => Some((op.repr, nary_op)),
+            _ => None,
+        })
+        .ok_or_else(|| ExError::Internal(format!("unknown operator {}", repr)))?;
+    if args.len() != op.arity {
+        return Err(ExError::Internal(format!(
+            "operator {} expects {} arguments",
+            repr, op.arity
+        )));
+    }
+    Ok(DeepNode::NaryCall { repr, op, args })
+}
+
+/// Parses `text` with the default operators for `f64` and returns Rust source code that
+/// evaluates to the corresponding [`StaticEx`](StaticEx). Used by `static_expr!`.
+#[doc(hidden)]
+pub fn static_ex_source(text: &str) -> Result<String, ExError> {
+    let flatex = parse_with_default_ops::<f64>(text)?;
+    let deepex = flat::unpack_deepex(&flatex)?;
+    let mut source = String::new();
+    write!(
+        source,
+        "::exmex::StaticEx {{ var_names: &{:?}, root: ",
+        deepex.var_names()
+    )
+    .unwrap();
+    write_deepex(deepex, &mut source);
+    source.push_str(" }");
+    Ok(source)
+}
+
+fn write_deepex(deepex: &DeepEx<f64>, source: &mut String) {
+    // nested expressions are written on an explicit stack of expressions, the indices of
+    // their next nodes and of the next arguments of n-ary calls, and the texts that close
+    // them such that deeply nested expressions cannot overflow the call stack
+    let mut stack = vec![(deepex, 0, 0, "")];
+    source.push_str("::exmex::StaticDeepEx { nodes: &[");
+    while let Some((deepex, node_idx, arg_idx, closing)) = stack.last_mut() {
+        let deepex = *deepex;
+        let Some(node) = deepex.nodes().get(*node_idx) else {
+            write!(
+                source,
+                "], bin_ops: &{:?}, unary_ops: &[",
+                deepex.bin_ops().reprs
+            )
+            .unwrap();
+            let unary_op = deepex.unary_op();
+            for (repr, pos) in unary_op.reprs.iter().zip(unary_op.positions.iter()) {
+                write!(source, "({:?}, ::exmex::UnaryPosition::{:?}), ", repr, pos).unwrap();
+            }
+            source.push_str("] }");
+            source.push_str(closing);
+            stack.pop();
+            continue;
+        };
+        match node {
+            DeepNode::Num(n, _) => {
+                let n_str = if n.is_nan() {
+                    "::std::f64::NAN".to_string()
+                } else if n.is_infinite() && *n > 0.0 {
+                    "::std::f64::INFINITY".to_string()
+                } else if n.is_infinite() {
+                    "::std::f64::NEG_INFINITY".to_string()
+                } else {
+                    format!("{:?}f64", n)
+                };
+                write!(source, "::exmex::StaticNode::Num({}), ", n_str).unwrap();
+                *node_idx += 1;
+            }
+            DeepNode::Var((_, name)) => {
+                write!(source, "::exmex::StaticNode::Var({:?}), ", name).unwrap();
+                *node_idx += 1;
+            }
+            DeepNode::Expr(e) => {
+                source.push_str("::exmex::StaticNode::Expr(::exmex::StaticDeepEx { nodes: &[");
+                *node_idx += 1;
+                stack.push((e, 0, 0, "), "));
+            }
+            DeepNode::NaryCall { repr, args, .. } => {
+                if *arg_idx == 0 {
+                    write!(
+                        source,
+                        "::exmex::StaticNode::NaryCall {{ repr: {:?}, args: &[",
+                        repr
+                    )
+                    .unwrap();
+                }
+                match args.get(*arg_idx) {
+                    Some(arg) => {
+                        source.push_str("::exmex::StaticDeepEx { nodes: &[");
+                        *arg_idx += 1;
+                        stack.push((arg, 0, 0, ", "));
+                    }
+                    None => {
+                        source.push_str("] }, ");
+                        *node_idx += 1;
+                        *arg_idx = 0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::parse_with_default_ops as parse;
+
+#[test]
+fn test_static_ex() {
+    const EXPR: StaticEx = StaticEx {
+        var_names: &["x", "y"],
+        root: StaticDeepEx {
+            nodes: &[
+                StaticNode::Var("x"),
+                StaticNode::Num(2.0),
+                StaticNode::Expr(StaticDeepEx {
+                    nodes: &[StaticNode::Var("y")],
+                    bin_ops: &[],
+                    unary_ops: &[("sin", UnaryPosition::Prefix)],
+                }),
+            ],
+            bin_ops: &["^", "+"],
+            unary_ops: &[],
+        },
+    };
+    assert_eq!(EXPR.var_names(), ["x", "y"]);
+    let flatex = EXPR.to_flatex().unwrap();
+    let reference = parse("x^2+sin(y)").unwrap();
+    assert_eq!(flatex.unparse().unwrap(), reference.unparse().unwrap());
+    assert_eq!(
+        flatex.eval(&[1.5, 0.3]).unwrap(),
+        reference.eval(&[1.5, 0.3]).unwrap()
+    );
+    assert!(static_ex_source("x^2+sin(y)")
+        .unwrap()
+        .starts_with("::exmex::StaticEx { var_names: &[\"x\", \"y\"]"));
+    assert!(static_ex_source("x+").is_err());
+    let unknown = StaticEx {
+        var_names: &[],
+        root: StaticDeepEx {
+            nodes: &[StaticNode::Num(1.0)],
+            bin_ops: &[],
+            unary_ops: &[("nope", UnaryPosition::Prefix)],
+        },
+    };
+    assert!(unknown.to_flatex().is_err());
+
+    const CALL: StaticEx = StaticEx {
+        var_names: &["x"],
+        root: StaticDeepEx {
+            nodes: &[
+                StaticNode::NaryCall {
+                    repr: "logb",
+                    args: &[
+                        StaticDeepEx {
+                            nodes: &[StaticNode::Num(2.0)],
+                            bin_ops: &[],
+                            unary_ops: &[],
+                        },
+                        StaticDeepEx {
+                            nodes: &[StaticNode::Var("x"), StaticNode::Num(3.0)],
+                            bin_ops: &["*"],
+                            unary_ops: &[],
+                        },
+                    ],
+                },
+                StaticNode::Num(1.0),
+            ],
+            bin_ops: &["+"],
+            unary_ops: &[],
+        },
+    };
+    let flatex = CALL.to_flatex().unwrap();
+    let reference = parse("logb(2, x*3)+1").unwrap();
+    assert_eq!(flatex.unparse().unwrap(), reference.unparse().unwrap());
+    assert_eq!(
+        flatex.eval(&[4.0]).unwrap(),
+        reference.eval(&[4.0]).unwrap()
+    );
+    let source = static_ex_source("logb(logb(2, 8), -x)^2").unwrap();
+    assert!(source.contains("::exmex::StaticNode::NaryCall { repr: \"logb\", args: &["));
+    let wrong_arity = StaticEx {
+        var_names: &[],
+        root: StaticDeepEx {
+            nodes: &[StaticNode::NaryCall {
+                repr: "logb",
+                args: &[],
+            }],
+            bin_ops: &[],
+            unary_ops: &[],
+        },
+    };
+    assert!(wrong_arity.to_flatex().is_err());
+}
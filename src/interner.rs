@@ -0,0 +1,98 @@
+//! Shared storage of the names that owned expressions refer to.
+#[cfg(feature = "std")]
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::{Mutex, PoisonError};
+
+/// Minimal number of names after which names that are not referred to by any expression
+/// are removed.
+#[cfg(feature = "std")]
+const MIN_PURGE_LEN: usize = 64;
+
+/// Set of names that hands out shared handles such that equal names are stored once.
+#[cfg(feature = "std")]
+pub struct Interner {
+    names: BTreeSet<Arc<str>>,
+    /// Number of names at which unreferenced names are removed before inserting another.
+    purge_len: usize,
+}
+
+#[cfg(feature = "std")]
+impl Interner {
+    pub const fn new() -> Self {
+        Interner {
+            names: BTreeSet::new(),
+            purge_len: MIN_PURGE_LEN,
+        }
+    }
+
+    /// Returns the handle of `name` and adds it if necessary.
+    pub fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return interned.clone();
+        }
+        if self.names.len() >= self.purge_len {
+            self.names.retain(|name| Arc::strong_count(name) > 1);
+            self.purge_len = (2 * self.names.len()).max(MIN_PURGE_LEN);
+        }
+        let interned: Arc<str> = Arc::from(name);
+        self.names.insert(interned.clone());
+        interned
+    }
+}
+
+/// Returns the handle of `name` in the interner that is shared by all expressions.
+#[cfg(feature = "std")]
+pub fn intern(name: &str) -> Arc<str> {
+    static INTERNER: Mutex<Interner> = Mutex::new(Interner::new());
+    INTERNER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .intern(name)
+}
+
+/// Without `std` there is no lock for a shared interner. Clones of an expression still
+/// share their names.
+#[cfg(not(feature = "std"))]
+pub fn intern(name: &str) -> Arc<str> {
+    Arc::from(name)
+}
+
+/// Returns the number of bytes of the allocation of `shared` divided by the number of its
+/// owners, such that the sum over all owners is the size of the allocation.
+pub fn shared_bytes(shared: &Arc<str>) -> usize {
+    // the allocation contains the strong and the weak count besides the string
+    (2 * core::mem::size_of::<usize>() + shared.len()) / Arc::strong_count(shared)
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::alloc_prelude::*;
+
+#[cfg(feature = "std")]
+#[test]
+fn test_interner() {
+    let mut interner = Interner::new();
+    let a = interner.intern("price");
+    let price = String::from("price");
+    let b = interner.intern(&price);
+    assert!(Arc::ptr_eq(&a, &b));
+    assert_eq!(Arc::strong_count(&a), 3);
+    assert_eq!(
+        shared_bytes(&a),
+        (2 * core::mem::size_of::<usize>() + 5) / 3
+    );
+
+    // unreferenced names are removed once the interner grows
+    let names = (1..MIN_PURGE_LEN)
+        .map(|i| format!("x{}", i))
+        .collect::<Vec<_>>();
+    for name in &names {
+        interner.intern(name);
+    }
+    assert_eq!(interner.names.len(), MIN_PURGE_LEN);
+    interner.intern("y");
+    assert_eq!(interner.names.len(), 2);
+    assert!(Arc::ptr_eq(&a, &interner.intern("price")));
+    assert!(Arc::ptr_eq(&intern("x"), &intern("x")));
+}
@@ -1,17 +1,18 @@
 #![doc(html_root_url = "https://docs.rs/exmex/0.8.4")]
+#![cfg_attr(not(feature = "std"), no_std)]
 //! Exmex is a fast, simple, and **ex**tendable **m**athematical **ex**pression evaluator.  
 //! ```rust
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::eval_str;
+//! use exmex::prelude::*;
 //! assert!((eval_str("1.5 * ((cos(0) + 23.0) / 2.0)")? - 18.0).abs() < 1e-12);
 //! #
 //! #     Ok(())
 //! # }
 //! ```
 //! For floats, we have a list of predifined operators containing
-//! `^`, `*`, `/`, `+`, `-`, `sin`, `cos`, `tan`, `exp`, `log`, and `log2`. The full list is
+//! `^`, `*`, `/`, `%`, `+`, `-`, `abs`, `sin`, `cos`, `tan`, `exp`, `log`, and `log2`. The full list is
 //! defined in [`make_default_operators`](make_default_operators).
 //!
 //! ## Variables
@@ -24,7 +25,7 @@
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::{make_default_operators, parse};
+//! use exmex::prelude::*;
 //! let to_be_parsed = "log(z) + 2* (-z^2 + sin(4*y))";
 //! let expr = parse::<f64>(to_be_parsed, &make_default_operators::<f64>())?;
 //! assert!((expr.eval(&[3.7, 2.5])? - 14.992794866624788 as f64).abs() < 1e-12);
@@ -33,14 +34,28 @@
 //! # }
 //! ```
 //! The `n`-th number in the slice corresponds to the `n`-th variable. Thereby, the
-//! alphatical order of the variables is relevant. In this example, we have `y=3.7` and `z=2.5`.
+//! alphabetical order of the variables is relevant. In this example, we have `y=3.7` and `z=2.5`.
+//! To order the variables by their first occurrences in the string instead, pass
+//! [`VarOrder::Occurrence`](VarOrder::Occurrence) to [`parse_with_options`](parse_with_options).
+//! Alternatively, values can be passed together with the variable names to
+//! [`eval_named`](FlatEx::eval_named), or to
+//! [`eval_with_default`](FlatEx::eval_with_default) if variables without a value should
+//! get a default value. How passed names are matched, e.g., whether
+//! whitespace or case matter and what happens to duplicates, is defined by a
+//! [`NameResolution`](NameResolution) policy.
 //! If variables are between curly brackets, they can have arbitrary names, e.g.,
 //! `{456/549*(}`, `{x}`, and confusingly even `{x+y}` are valid variable names as shown in the following.
+//! The brackets are not part of the name, i.e., `{x}` and `x` are the same variable. A missing
+//! closing bracket and an empty name `{}` are errors. Within the brackets, a backslash escapes
+//! the next character such that, e.g., `{x\}y}` is a variable. Since names are borrowed from the
+//! parsed string, escapes are kept in the name, i.e., its name is `x\}y`.
+//! Curly brackets can be switched off with the option
+//! [`curly_vars`](ParseOptions::curly_vars), e.g., for operators that start with `{`.
 //! ```rust
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::{make_default_operators, parse};
+//! use exmex::prelude::*;
 //! let x = 2.1f64;
 //! let y = 0.1f64;
 //! let to_be_parsed = "log({x+y})";  // {x+y} is the name of one(!) variable 😕.
@@ -50,24 +65,19 @@
 //! #     Ok(())
 //! # }
 //! ```
+//! Names without brackets match [`VAR_REGEX_PATTERN`](VAR_REGEX_PATTERN). Other names such as
+//! `$price` or `obj.field` can be allowed with a custom pattern, see
+//! [`parse_with_var_pattern`](parse_with_var_pattern).
 //! ## Extendability
 //! Library users can define their own set of operators as shown in the following.
 //! ```rust
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::{parse, BinOp, Operator};
+//! use exmex::prelude::*;
 //! let ops = [
-//!     Operator {
-//!         repr: "%",
-//!         bin_op: Some(BinOp{ apply: |a: i32, b: i32| a % b, prio: 1 }),
-//!         unary_op: None,
-//!     },
-//!     Operator {
-//!         repr: "/",
-//!         bin_op: Some(BinOp{ apply: |a: i32, b: i32| a / b, prio: 1 }),
-//!         unary_op: None,
-//!     },
+//!     Operator::make_bin("%", BinOp{ apply: |a: i32, b: i32| a % b, prio: 1 }),
+//!     Operator::make_bin("/", BinOp{ apply: |a: i32, b: i32| a / b, prio: 1 }),
 //! ];
 //! let to_be_parsed = "19 % 5 / 2 / a";
 //! let expr = parse::<i32>(to_be_parsed, &ops)?;
@@ -80,7 +90,9 @@
 //! ### Operators
 //!
 //! Operators are instances of the struct
-//! [`Operator`](Operator) that has its representation in the field
+//! [`Operator`](Operator), which are usually created with constructors such as
+//! [`Operator::make_bin`](Operator::make_bin), see its documentation. It has its
+//! representation in the field
 //! [`repr`](Operator::repr), a binary and a unary operator of
 //! type [`Option<BinOp<T>>`](Operator::bin_op) and
 //! [`Option<fn(T) -> T>`](Operator::unary_op), respectively, as
@@ -88,38 +100,61 @@
 //! contains in addition to the function pointer [`apply`](BinOp::apply) of type `fn(T, T) -> T` an
 //! integer [`prio`](BinOp::prio). Operators
 //! can be both, binary and unary. See, e.g.,  `-` defined in the list of default
-//! operators. Note that we expect a unary operator to be always on the left of a
-//! number.
+//! operators. Such an operator is binary if the token on its left ends an operand, i.e.,
+//! is a number, a variable, a closing parenthesis, or a postfix operator, and unary
+//! otherwise. Hence, `3---x` is `3-(-(-x))` and `3-*2` is an error. A unary operator is on the left of its operand unless its
+//! [`unary_position`](Operator::unary_position) is
+//! [`UnaryPosition::Postfix`](UnaryPosition::Postfix) as for a factorial `3!`.
+//! Operators with more than one argument such as `clamp(x, 0, 1)` are defined by an
+//! [`NaryOp`](NaryOp) in the field [`nary_op`](Operator::nary_op). Its function pointer
+//! of type `fn(&[T]) -> T` receives the arguments in the order they are written. Calls with
+//! a number of arguments different from its [`arity`](NaryOp::arity) are parse errors.
+//! Instead of a slice of operators, [`parse_with_factory`](parse_with_factory) takes the
+//! type of a [`MakeOperators`](MakeOperators) factory, which the expression keeps to create
+//! the operators again, e.g., for partial derivatives.
+//!
+//! Sums and products over integer ranges are built in. The call `sum(i, 1, 5, i^2)` is
+//! expanded while parsing into `1^2+2^2+3^2+4^2+5^2` and `prod` combines the terms with `*`.
+//! The bounds need to fold to integer constants, empty ranges result in `0` and `1`,
+//! respectively, and the number of terms is limited by
+//! [`ParseLimits::max_loop_terms`](ParseLimits::max_loop_terms). Operators named `sum` or
+//! `prod` take precedence, and `{sum}` is a variable.
+//! ```rust
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! #
+//! use exmex::prelude::*;
+//! let ops = make_default_operators::<f64>();
+//! let expr = parse::<f64>("sum(i, 1, 3, x / (1 + r)^i)", &ops)?;
+//! assert_eq!(expr.var_names(), ["r", "x"]);
+//! assert!((expr.eval(&[0.0, 100.0])? - 300.0).abs() < 1e-12);
+//! assert_eq!(parse::<f64>("prod(k, 1, 4, sum(j, 1, k, 1))", &ops)?.eval(&[])?, 24.0);
+//! #
+//! #     Ok(())
+//! # }
+//! ```
 //!
 //! ### Data Types of Numbers
 //!
 //! You can use any type that implements [`Copy`](core::marker::Copy) and
-//! [`FromStr`](std::str::FromStr). In case the representation of your data type in the
-//! string does not match the number regex `r"\.?[0-9]+(\.[0-9]+)?"`, you have to pass a
-//! suitable regex and use the function
+//! [`FromStr`](std::str::FromStr). Numbers such as `2`, `.5`, `1_000.5`, or `2.5E+10`
+//! are recognized, see [`NUMBER_REGEX_PATTERN`](NUMBER_REGEX_PATTERN). In case the
+//! representation of your data type in the string does not match this pattern, you
+//! have to pass a suitable regex and use the function
 //! [`parse_with_number_pattern`](parse_with_number_pattern) instead of
-//! [`parse`](parse). Here is an example for `bool`.
+//! [`parse`](parse). Here is an example for `bool` with hand-written operators. Default
+//! operators for primitive integers and `bool` are available via
+//! [`make_default_operators_int`](make_default_operators_int) and
+//! [`make_default_operators_bool`](make_default_operators_bool), respectively.
 //! ```rust
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::{parse_with_number_pattern, BinOp, Operator};
+//! use exmex::prelude::*;
 //! let ops = [
-//!     Operator {
-//!         repr: "&&",
-//!         bin_op: Some(BinOp{ apply: |a: bool, b: bool| a && b, prio: 1 }),
-//!         unary_op: None,
-//!     },
-//!     Operator {
-//!         repr: "||",
-//!         bin_op: Some(BinOp{ apply: |a: bool, b: bool| a || b, prio: 1 }),
-//!         unary_op: None,
-//!     },
-//!     Operator {
-//!         repr: "!",
-//!         bin_op: None,
-//!         unary_op: Some(|a: bool| !a),
-//!     },
+//!     Operator::make_bin("&&", BinOp{ apply: |a: bool, b: bool| a && b, prio: 1 }),
+//!     Operator::make_bin("||", BinOp{ apply: |a: bool, b: bool| a || b, prio: 1 }),
+//!     Operator::make_unary("!", |a: bool| !a),
 //! ];
 //! let to_be_parsed = "!(true && false) || (!false || (true && false))";
 //! let expr = parse_with_number_pattern::<bool>(to_be_parsed, &ops, "true|false")?;
@@ -137,13 +172,15 @@
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::eval_str;
+//! use exmex::prelude::*;
 //! assert_eq!(eval_str("---1")?, -1.0);
 //! #
 //! #     Ok(())
 //! # }
 //! ```
-//! If you want to be on the safe side, we suggest using parentheses.
+//! If you want to be on the safe side, we suggest using parentheses. Moreover, operators
+//! need to be written explicitly. To parse, e.g., `2x` as `2*x`, pass
+//! [`ParseOptions`](ParseOptions) to [`parse_with_options`](parse_with_options).
 //!
 //! ## Partial Derivatives
 //!
@@ -153,7 +190,7 @@
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::{parse_with_default_ops};
+//! use exmex::prelude::*;
 //!
 //! let expr = parse_with_default_ops::<f64>("x^2 + y^2")?;
 //! let d_x = expr.clone().partial(0)?;
@@ -164,55 +201,217 @@
 //! #     Ok(())
 //! # }
 //! ```
+//! Expressions of other number types such as complex numbers can be differentiated with
+//! custom operators that have the representations of the default operators, see
+//! [`partial_with_ops`](FlatEx::partial_with_ops).
 //!
 //! ## Display
 //!
-//! An instance of [`FlatEx`](FlatEx) can be displayed as string. Note that this
-//! [`unparse`](FlatEx::unparse)d string does not necessarily coincide with the original
-//! string, since curly brackets are added.
+//! An instance of [`FlatEx`](FlatEx) that has been parsed is displayed as the string it
+//! has been parsed from. A normalized string is created by [`unparse`](FlatEx::unparse),
+//! which is also used to display expressions that have not been parsed, e.g., combined
+//! ones. The [`unparse`](FlatEx::unparse)d string does not necessarily coincide with the
+//! original string, since, e.g., redundant parentheses are dropped and numbers are
+//! written with their [`Debug`](std::fmt::Debug) representation. Curly brackets are only
+//! added to variable names that could not be parsed without them.
 //!
 //! ```rust
 //! # use std::error::Error;
 //! # fn main() -> Result<(), Box<dyn Error>> {
 //! #
-//! use exmex::parse_with_default_ops;
-//! let flatex = parse_with_default_ops::<f64>("-sin(z)/cos(mother_of_names)")?;
-//! assert_eq!(format!("{}", flatex), "-(sin({z}))/cos({mother_of_names})");
+//! use exmex::prelude::*;
+//! let flatex = parse_with_default_ops::<f64>("-sin(z) / cos(mother_of_names)^2")?;
+//! assert_eq!(format!("{}", flatex), "-sin(z) / cos(mother_of_names)^2");
+//! assert_eq!(flatex.unparse()?, "(-sin(z))/cos(mother_of_names)^2.0");
 //! #
 //! #     Ok(())
 //! # }
 //! ```
 //!
+//! ## `no_std`
+//!
+//! With the default feature `std` disabled, Exmex only depends on `core` and `alloc`, e.g.,
+//! to evaluate expressions on embedded targets. Parsing with user-defined operators, the
+//! [`ExprBuilder`](ExprBuilder), and evaluation are available. Everything that needs
+//! floating point functions or regexes is not, e.g., the default operators for floats,
+//! number patterns, partial derivatives, and interval evaluation.
+//!
+//! ## Parallel Parsing
+//!
+//! With the feature `rayon`, [`parse_batch`](parse_batch) and
+//! [`parse_batch_owned`](parse_batch_owned) parse many strings in parallel, e.g., a large
+//! set of formulas loaded at startup.
+//!
+//! ## SIMD
+//!
+//! With the feature `simd`, the default operators are available for the vectors of the
+//! module [`simd`](simd), which evaluate an expression for 4 or 8 values at once.
+//!
 //! ## Unicode
 //! Unicode input strings are currently not supported 😕 but might be added in the
 //! future 😀.
 //!
 
+extern crate alloc;
+
 mod definitions;
 mod expression;
+mod interner;
+mod names;
 mod operators;
 mod parser;
+mod result;
+mod script;
+#[cfg(feature = "simd")]
+pub mod simd;
 mod util;
+mod validation;
+
+use core::{fmt::Debug, str::FromStr};
 
-use std::{fmt::Debug, str::FromStr};
+/// Items of the standard prelude that are not part of the prelude of `core`.
+mod alloc_prelude {
+    pub use alloc::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+        vec,
+        vec::Vec,
+    };
+}
+use alloc_prelude::*;
 
-pub use expression::flat::FlatEx;
-use expression::{deep::DeepEx, flat};
+use expression::flat;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use expression::static_ex::{static_ex_source, StaticDeepEx, StaticNode};
+pub use expression::{
+    builder::ExprBuilder,
+    deep::{DeepEx, SubExprInfo},
+    flat::{flatten, BoundEx, CostEstimate, EvalBuffer, FlatEx, LargeFlatEx, OwnedFlatEx},
+};
+#[cfg(feature = "std")]
+pub use expression::{
+    flat::{check_partial, jacobian},
+    partial_derivatives::{
+        BinOpPartial, DiffNum, NaryOpPartial, PartialDerivative, UnaryOpOuter, ValueDerivative,
+    },
+    static_ex::StaticEx,
+};
 
+pub use names::{NameResolution, OnDuplicate};
+#[cfg(feature = "std")]
 use num::Float;
-pub use parser::ExParseError;
+pub use parser::{
+    ParseLimits, ParseOptions, Semantics, Span, Token, TokenCategory, TokenPatterns, VarOrder,
+    NUMBER_REGEX_PATTERN, VAR_REGEX_PATTERN,
+};
+pub use result::{
+    ExError, ExParseError, PairViolation, ParseWarning, Result, ValidationIssue, ERROR_CODES,
+};
+pub use script::{parse_program, parse_script, ScriptEx};
+
+#[cfg(feature = "std")]
+pub use operators::{
+    make_default_constants, make_default_operators, DefaultOpsFactory, DefaultOpsFloat,
+};
+pub use operators::{
+    make_default_operators_bool, make_default_operators_int, BinOp, FallibleOp, MakeOperators,
+    NaryOp, Operator, UnaryPosition, PRIO_ADD, PRIO_CMP, PRIO_MUL, PRIO_POW,
+};
 
-pub use operators::{make_default_operators, BinOp, Operator};
+/// Commonly used items that can be imported at once via `use exmex::prelude::*;`.
+/// All of them are also available at the crate root. The alias [`Result`](crate::Result)
+/// is not part of the prelude to not shadow the `Result` of the standard library.
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let expr = parse_with_default_ops::<f64>("2*x^2")?;
+/// assert!((expr.eval(&[3.0])? - 18.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+pub mod prelude {
+    #[cfg(feature = "std")]
+    pub use crate::{
+        eval_str, make_default_constants, make_default_operators, parse_with_default_ops,
+        parse_with_number_pattern, parse_with_patterns, parse_with_var_pattern, DefaultOpsFactory,
+        DiffNum,
+    };
+    pub use crate::{
+        make_default_operators_bool, make_default_operators_int, parse, parse_with_constants,
+        parse_with_definitions, parse_with_factory, parse_with_options, BinOp, DeepEx, ExError,
+        ExParseError, ExprBuilder, FallibleOp, FlatEx, MakeOperators, NaryOp, Operator,
+        OwnedFlatEx, ParseLimits, ParseOptions, Semantics, UnaryPosition,
+    };
+}
 
-/// Parses a string, evaluates a string, and returns the resulting number.
+/// Parses a string, evaluates a string, and returns the resulting number. The
+/// [default constants](make_default_constants) such as `pi` can be used.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// assert!((eval_str("cos(pi)")? + 1.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
 ///
 /// # Errrors
 ///
 /// In case the parsing went wrong, e.g., due to an invalid input string, an
-/// [`ExParseError`](ExParseError) is returned.
+/// [`ExError`](ExError) is returned.
 ///
-pub fn eval_str(text: &str) -> Result<f64, ExParseError> {
-    let flatex = parse_with_default_ops(text)?;
+#[cfg(feature = "std")]
+pub fn eval_str(text: &str) -> Result<f64, ExError> {
+    let ops = make_default_operators::<f64>();
+    let flatex = parse_with_constants(text, &ops, &make_default_constants())?;
+    flatex.eval(&[])
+}
+
+/// Like [`eval_str`](eval_str) but with [`ParseLimits`](ParseLimits) for strings from
+/// untrusted sources, e.g., in services. The length of the string is checked before
+/// tokenizing and the numbers of tokens and nodes while tokenizing, such that
+/// parsing stops early and huge inputs are never materialized as tokens.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, eval_str_with_limits};
+/// let limits = ParseLimits {
+///     max_len: Some(1000),
+///     max_tokens: Some(100),
+///     max_nodes: Some(50),
+///     ..ParseLimits::default()
+/// };
+/// assert!((eval_str_with_limits("2*sin(pi/2)", limits)? - 2.0).abs() < 1e-12);
+/// let res = eval_str_with_limits(&"1+".repeat(1000), limits);
+/// assert!(matches!(res, Err(ExError::InputTooLong { len: 2000, max: 1000 })));
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errrors
+///
+/// An [`ExError`](ExError) is returned if [`eval_str`](eval_str) returns one or a limit
+/// is exceeded.
+///
+#[cfg(feature = "std")]
+pub fn eval_str_with_limits(text: &str, limits: ParseLimits) -> Result<f64, ExError> {
+    let ops = make_default_operators::<f64>();
+    let options = ParseOptions {
+        limits,
+        ..ParseOptions::default()
+    };
+    let flatex = parse_with_constants_and_options(text, &ops, &make_default_constants(), &options)?;
     flatex.eval(&[])
 }
 
@@ -222,13 +421,200 @@ pub fn eval_str(text: &str) -> Result<f64, ExParseError> {
 ///
 /// An error is returned in case [`parse_with_number_pattern`](parse_with_number_pattern)
 /// returns one.
-pub fn parse<'a, T>(text: &'a str, ops: &[Operator<'a, T>]) -> Result<FlatEx<'a, T>, ExParseError>
+pub fn parse<'a, T>(text: &'a str, ops: &[Operator<'a, T>]) -> Result<FlatEx<'a, T>, ExError>
 where
-    <T as std::str::FromStr>::Err: Debug,
+    <T as core::str::FromStr>::Err: Debug,
     T: Copy + FromStr + Debug,
 {
     let deepex = DeepEx::from_ops(text, ops)?;
-    Ok(flat::flatten(deepex))
+    Ok(flat::flatten(deepex)?.with_text(text))
+}
+
+/// Parses a string and a vector of operators into an expression like [`parse`](parse) but
+/// with the given options, e.g., to allow implicit multiplication.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let options = ParseOptions {
+///     implicit_multiplication: true,
+///     ..ParseOptions::default()
+/// };
+/// let expr = parse_with_options::<f64>("2x + 3sin(y)", &make_default_operators(), &options)?;
+/// assert!((expr.eval(&[1.5, 0.0])? - 3.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse`](parse) returns one for the string with all
+/// implicit operators written explicitly, if an implicit multiplication is found but
+/// there is no operator `*`, or if the expression exceeds one of the
+/// [`limits`](ParseOptions::limits).
+pub fn parse_with_options<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    options: &ParseOptions,
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    parse_with_diagnostics(text, ops, options).map(|(flatex, _)| flatex)
+}
+
+/// Parses a string like [`parse_with_options`](parse_with_options) and additionally returns
+/// warnings about potential problems of the parsed string. For instance, with custom
+/// operators `<` and `<-`, the string `a<-b` is parsed as `a <- b`, but `a < -b` might have
+/// been meant.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, parse_with_diagnostics};
+/// // `**` is an alias of `^` and could also be read as `*` followed by `*`
+/// let ops = make_default_operators::<f64>();
+/// let (expr, warnings) = parse_with_diagnostics("2**x", &ops, &ParseOptions::default())?;
+/// assert!((expr.eval(&[3.0])? - 8.0).abs() < 1e-12);
+/// assert_eq!(warnings[0].position(), 1);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse_with_options`](parse_with_options) returns one.
+pub fn parse_with_diagnostics<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    options: &ParseOptions,
+) -> Result<(FlatEx<'a, T>, Vec<ParseWarning>), ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let (deepex, warnings) = DeepEx::from_options(text, ops, options, &[])?;
+    let flatex = flat::flatten(deepex)?.with_text(text);
+    flat::check_limits(&flatex, text, &options.limits)?;
+    Ok((flatex, warnings))
+}
+
+/// Parses a string and a vector of operators into an expression like [`parse`](parse)
+/// whereby each variable that is named like one of the definitions is replaced by the
+/// defining expression. Thereby, the defining expressions are not parsed again. Their
+/// variables become variables of the resulting expression, and variables with the same
+/// name are identified. Variables without a definition remain variables.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let area = parse_with_default_ops::<f64>("r^2*3.14")?;
+/// let height = parse_with_default_ops::<f64>("2*r")?;
+/// let ops = make_default_operators::<f64>();
+/// let volume = parse_with_definitions(
+///     "area*height*s",
+///     &ops,
+///     &[("area", &area), ("height", &height)],
+/// )?;
+/// assert_eq!(volume.var_names(), ["r", "s"]);
+/// assert!((volume.eval(&[1.0, 0.5])? - 3.14).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// Since definitions are parsed before they are passed, they can only refer to definitions
+/// that have been passed to their own parsing. Hence, definitions cannot be cyclic.
+///
+/// # Errors
+///
+/// An error is returned in case [`parse`](parse) returns one or if a definition has been
+/// [`clear_deepex`](FlatEx::clear_deepex)ed.
+pub fn parse_with_definitions<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    defs: &[(&str, &FlatEx<'a, T>)],
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let deep_defs = defs
+        .iter()
+        .map(|(name, def)| Ok((*name, flat::unpack_deepex(def)?)))
+        .collect::<Result<Vec<_>, ExError>>()?;
+    let (deepex, _) = DeepEx::from_options(text, ops, &ParseOptions::default(), &deep_defs)?;
+    Ok(flat::flatten(deepex)?.with_text(text))
+}
+
+/// Parses a string and a vector of operators into an expression like [`parse`](parse)
+/// whereby each variable that is named like one of the constants is replaced by its value.
+/// Hence, constants are not variables of the resulting expression. If several constants
+/// have the same name, the first one is used such that default constants can be shadowed
+/// by prepending constants.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let ops = make_default_operators::<f64>();
+/// let expr = parse_with_constants("sin(pi/2)*x", &ops, &make_default_constants())?;
+/// assert_eq!(expr.var_names(), ["x"]);
+/// assert!((expr.eval(&[2.0])? - 2.0).abs() < 1e-12);
+/// let expr = parse_with_constants("g*t^2/2", &ops, &[("g", 9.81)])?;
+/// assert!((expr.eval(&[2.0])? - 19.62).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse`](parse) returns one.
+pub fn parse_with_constants<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    constants: &[(&str, T)],
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    parse_with_constants_and_options(text, ops, constants, &ParseOptions::default())
+}
+
+fn parse_with_constants_and_options<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    constants: &[(&str, T)],
+    options: &ParseOptions,
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let deep_constants = constants
+        .iter()
+        .map(|(_, value)| DeepEx::from_num(*value))
+        .collect::<Vec<_>>();
+    let defs = constants
+        .iter()
+        .zip(deep_constants.iter())
+        .map(|((name, _), deepex)| (*name, deepex))
+        .collect::<Vec<_>>();
+    let (deepex, _) = DeepEx::from_options(text, ops, options, &defs)?;
+    let flatex = flat::flatten(deepex)?.with_text(text);
+    flat::check_limits(&flatex, text, &options.limits)?;
+    Ok(flatex)
 }
 
 /// Parses a string and a vector of operators and a regex pattern that defines the looks
@@ -236,9 +622,15 @@ where
 ///
 /// # Errors
 ///
-/// An [`ExParseError`](ExParseError) is returned, if
+/// An [`ExError`](ExError) is returned, if
 ///
 //
+// from check_operators
+//
+/// * two operators have the same [`repr`](Operator::repr), an operator has an empty
+///   `repr`, or an operator has no operation at all,
+/// * `ops` is empty and `text` contains more than numbers and parentheses,
+//
 // from apply_regexes
 //
 /// * the argument `number_regex_pattern` cannot be compiled,
@@ -251,7 +643,7 @@ where
 /// * the to-be-parsed string is empty,
 /// * a number or variable is next to another one, e.g., `2 {x}`,
 /// * wlog a number or variable is on the right of a closing parenthesis, e.g., `)5`,
-/// * a binary operator is next to another binary operator, e.g., `2*/4`,
+/// * a binary operator is next to another binary operator, e.g., `2*/4` or `3-*2`,
 /// * wlog a closing parenthesis is next to an opening one, e.g., `)(` or `()`,
 /// * too many closing parentheses at some position, e.g., `(4+6) - 5)*2`,
 /// * the last element is an operator, e.g., `1+`,
@@ -262,17 +654,97 @@ where
 /// * in `parsed_tokens` a closing parentheses is directly following an operator, e.g., `+)`, or
 /// * a unary operator is followed directly by a binary operator, e.g., `sin*`.
 ///
+#[cfg(feature = "std")]
 pub fn parse_with_number_pattern<'a, T>(
     text: &'a str,
     ops: &[Operator<'a, T>],
     number_regex_pattern: &str,
-) -> Result<FlatEx<'a, T>, ExParseError>
+) -> Result<FlatEx<'a, T>, ExError>
 where
-    <T as std::str::FromStr>::Err: Debug,
+    <T as core::str::FromStr>::Err: Debug,
     T: Copy + FromStr + Debug,
 {
     let deepex = DeepEx::from_pattern(text, ops, number_regex_pattern)?;
-    Ok(flat::flatten(deepex))
+    Ok(flat::flatten(deepex)?.with_text(text))
+}
+
+/// Parses a string and a vector of operators into an expression like [`parse`](parse) but
+/// variable names are the matches of the regex pattern `var_regex_pattern` instead of
+/// [`VAR_REGEX_PATTERN`](VAR_REGEX_PATTERN), e.g., to allow prefixes or dots in names. See
+/// [`TokenPatterns`](TokenPatterns) for the precedence of numbers, operators, and variables.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let ops = make_default_operators::<f64>();
+/// let expr = parse_with_var_pattern("$price*(1+$tax.rate)", &ops, r"\$[a-z]+(\.[a-z]+)*")?;
+/// assert_eq!(expr.var_names(), ["$price", "$tax.rate"]);
+/// assert!((expr.eval(&[10.0, 0.5])? - 15.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse_with_patterns`](parse_with_patterns) returns one.
+#[cfg(feature = "std")]
+pub fn parse_with_var_pattern<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    var_regex_pattern: &str,
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let patterns = TokenPatterns {
+        var: var_regex_pattern,
+        ..TokenPatterns::default()
+    };
+    parse_with_patterns(text, ops, &patterns)
+}
+
+/// Parses a string and a vector of operators into an expression whereby numbers and
+/// variable names are the matches of the regex patterns `patterns`. Each pattern is compiled
+/// once per call.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, TokenPatterns};
+/// let patterns = TokenPatterns {
+///     number: "[0-9]+",
+///     var: r"[a-z]+(\[[0-9]+\])?",
+/// };
+/// let ops = make_default_operators::<f64>();
+/// let expr = parse_with_patterns("a[0]*2 + a[1]", &ops, &patterns)?;
+/// assert_eq!(expr.var_names(), ["a[0]", "a[1]"]);
+/// assert!((expr.eval(&[3.0, 1.0])? - 7.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse_with_number_pattern`](parse_with_number_pattern)
+/// returns one or if the variable pattern cannot be compiled or matches the empty string.
+#[cfg(feature = "std")]
+pub fn parse_with_patterns<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    patterns: &TokenPatterns,
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let deepex = DeepEx::from_patterns(text, ops, patterns)?;
+    Ok(flat::flatten(deepex)?.with_text(text))
 }
 
 /// Parses a string into an expression that can be evaluated using default operators.
@@ -281,30 +753,304 @@ where
 ///
 /// An error is returned in case [`parse`](parse)
 /// returns one.
-pub fn parse_with_default_ops<'a, T>(text: &'a str) -> Result<FlatEx<'a, T>, ExParseError>
+#[cfg(feature = "std")]
+pub fn parse_with_default_ops<'a, T>(text: &'a str) -> Result<FlatEx<'a, T>, ExError>
 where
-    <T as std::str::FromStr>::Err: Debug,
+    <T as core::str::FromStr>::Err: Debug,
     T: Float + FromStr + Debug,
 {
-    Ok(flat::flatten(DeepEx::from_str(text)?))
+    Ok(flat::flatten(DeepEx::from_str(text)?)?.with_text(text))
+}
+
+/// Parses a string with the operators of the factory `F` like [`parse`](parse). The
+/// expression keeps the factory instead of the operators to create them again if needed,
+/// e.g., for [`partial`](FlatEx::partial) derivatives, see [`MakeOperators`](MakeOperators).
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let expr = parse_with_factory::<f64, DefaultOpsFactory<f64>>("x^3")?;
+/// let d_x = expr.partial(0)?;
+/// assert!((d_x.eval(&[2.0])? - 12.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse`](parse) returns one.
+pub fn parse_with_factory<'a, T, F>(text: &'a str) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+    F: MakeOperators<T>,
+{
+    Ok(parse(text, &F::make())?.with_make_ops(Some(F::make)))
+}
+
+/// Parses each of the strings like [`parse`](parse) in parallel. The results are in the
+/// order of the strings, and an error of one string does not stop the others from being
+/// parsed.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, parse_batch};
+/// let texts = ["x*2", "sin(y", "x+y"];
+/// let exprs = parse_batch::<f64>(&texts, &make_default_operators());
+/// assert!(exprs[1].is_err());
+/// let exprs = exprs.into_iter().filter_map(Result::ok).collect::<Vec<_>>();
+/// assert_eq!(exprs[0].eval(&[1.5])?, 3.0);
+/// assert_eq!(exprs[1].var_names(), ["x", "y"]);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[cfg(feature = "rayon")]
+pub fn parse_batch<'a, T>(
+    texts: &'a [&'a str],
+    ops: &[Operator<'a, T>],
+) -> Vec<Result<FlatEx<'a, T>, ExParseError>>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug + Send + Sync,
+{
+    use rayon::prelude::*;
+    texts.par_iter().map(|text| parse(text, ops)).collect()
+}
+
+/// Parses each of the strings like [`parse_batch`](parse_batch) but returns expressions
+/// that do not borrow from the strings or the operators, see [`OwnedFlatEx`](OwnedFlatEx).
+#[cfg(feature = "rayon")]
+pub fn parse_batch_owned<T>(
+    texts: &[&str],
+    ops: &[Operator<'_, T>],
+) -> Vec<Result<OwnedFlatEx<T>, ExParseError>>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug + Send + Sync,
+{
+    use rayon::prelude::*;
+    texts
+        .par_iter()
+        .map(|text| parse(text, ops).map(OwnedFlatEx::from_flatex))
+        .collect()
+}
+
+/// Splits a string into tokens with their byte ranges without building an expression,
+/// e.g., for syntax highlighting. In contrast to parsing, the order of the tokens is not
+/// checked, see [`check`](check).
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, tokenize, TokenCategory};
+/// let tokens = tokenize("sin({x y}) * 2", &make_default_operators::<f64>())?;
+/// let categories = tokens.iter().map(|t| t.category).collect::<Vec<_>>();
+/// assert_eq!(
+///     categories,
+///     [
+///         TokenCategory::Operator,
+///         TokenCategory::OpenParen,
+///         TokenCategory::Variable,
+///         TokenCategory::CloseParen,
+///         TokenCategory::Operator,
+///         TokenCategory::Number,
+///     ]
+/// );
+/// assert_eq!(tokens[2].text, "{x y}");
+/// assert_eq!((tokens[2].span.start, tokens[2].span.end), (4, 9));
+/// // the order is not checked
+/// assert_eq!(tokenize("2 * * (", &make_default_operators::<f64>())?.len(), 4);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An [`ExError`](ExError) is returned for the first character that does not start a
+/// token, for an unterminated curly brace or an empty name `{}`, or if the operators are invalid.
+///
+pub fn tokenize<'a, T>(text: &'a str, ops: &[Operator<'a, T>]) -> Result<Vec<Token<'a>>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let (tokens, errors) = parser::lex(
+        text,
+        ops,
+        &parser::is_numeric_text,
+        &parser::is_name_text,
+        &ParseOptions::default(),
+    )?;
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(tokens.iter().map(|token| token.to_token(text)).collect()),
+    }
 }
 
-#[cfg(test)]
+/// Checks the tokens of a string and their order with the same rules as [`parse`](parse)
+/// but reports all problems sorted by their positions instead of only the first one, e.g.,
+/// for an editor.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, check};
+/// let ops = make_default_operators::<f64>();
+/// assert!(check("sin(x) * 2", &ops).is_ok());
+/// let errors = check("(2 */ x) $ 3 +", &ops).unwrap_err();
+/// let codes = errors.iter().map(|e| e.code()).collect::<Vec<_>>();
+/// assert_eq!(codes, ["E017", "E002", "E004"]);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// All problems are returned as [`ExError`](ExError)s. Characters that do not start a
+/// token are skipped and their neighbors are not checked against each other. If the
+/// operators are invalid, only this is reported.
+///
+pub fn check<'a, T>(text: &'a str, ops: &[Operator<'a, T>]) -> Result<(), Vec<ExError>>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let issues = validate(text, ops);
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(issues.into_iter().map(|issue| issue.error).collect())
+    }
+}
+
+/// Checks a string like [`check`](check) and describes each problem by its message, the
+/// indices of the involved tokens of [`tokenize`](tokenize), and their byte range. The
+/// issues are sorted by their positions and empty if the string is fine.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, validate, Span};
+/// let ops = make_default_operators::<f64>();
+/// let issues = validate("2 x + (y", &ops);
+/// assert_eq!(issues.len(), 2);
+/// assert_eq!(issues[0].tokens, Some((0, 1)));
+/// assert_eq!(issues[0].span, Some(Span { start: 0, end: 3 }));
+/// assert_eq!(issues[1].error.code(), "E003");
+/// assert_eq!(issues[1].tokens, Some((3, 3)));
+/// assert_eq!(issues[1].message, issues[1].error.to_string());
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+pub fn validate<'a, T>(text: &'a str, ops: &[Operator<'a, T>]) -> Vec<ValidationIssue>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let issue =
+        |error: ExError, tokens: Option<(usize, usize)>, span: Option<Span>| ValidationIssue {
+            message: error.to_string(),
+            error,
+            tokens,
+            span,
+        };
+    let (tokens, errors) = match parser::lex(
+        text,
+        ops,
+        &parser::is_numeric_text,
+        &parser::is_name_text,
+        &ParseOptions::default(),
+    ) {
+        Ok(lexed) => lexed,
+        Err(err) => return vec![issue(err, None, None)],
+    };
+    // errors of the lexer refer to the character at their position
+    let char_span = |err: &ExError| {
+        err.position().map(|start| Span {
+            start,
+            end: start + text[start..].chars().next().map_or(0, char::len_utf8),
+        })
+    };
+    let gaps = errors
+        .iter()
+        .filter_map(|err| err.position())
+        .collect::<Vec<_>>();
+    let mut issues = errors
+        .into_iter()
+        .map(|err| {
+            let span = char_span(&err);
+            issue(err, None, span)
+        })
+        .collect::<Vec<_>>();
+    // sums and products are expanded, hence the indices of the tokens are recovered from
+    // their positions
+    let starts = tokens
+        .iter()
+        .map(|token| token.span.start)
+        .collect::<Vec<_>>();
+    match expression::loops::expand_loops(text, tokens, ops, &ParseOptions::default()) {
+        Ok(expanded) => {
+            issues.extend(
+                parser::find_violations(text, &expanded, &gaps)
+                    .into_iter()
+                    .map(|violation| {
+                        let span = violation.tokens.map(|(first, last)| Span {
+                            start: expanded[first].span.start,
+                            end: expanded[last].span.end,
+                        });
+                        let tokens = span.map(|span| {
+                            let first = starts.partition_point(|start| *start < span.start);
+                            let last = starts.partition_point(|start| *start < span.end);
+                            (first, last.max(first + 1) - 1)
+                        });
+                        issue(violation.error, tokens, span)
+                    }),
+            );
+        }
+        Err(err) => {
+            let span = char_span(&err);
+            issues.push(issue(err, None, span));
+        }
+    }
+    issues.sort_by_key(|issue| issue.error.position());
+    issues
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
 
-    use std::iter::once;
+    use std::{iter::once, sync::Arc, thread};
 
     use crate::{
-        eval_str,
-        operators::{make_default_operators, BinOp, Operator},
-        parse, parse_with_default_ops,
+        check, eval_str, eval_str_with_limits, jacobian,
+        operators::{
+            make_default_constants, make_default_operators, BinOp, NaryOp, Operator, UnaryOp,
+            UnaryPosition,
+        },
+        parse, parse_with_constants, parse_with_default_ops, parse_with_definitions,
+        parse_with_number_pattern, parse_with_options, parse_with_patterns, parse_with_var_pattern,
+        tokenize,
         util::{assert_float_eq_f32, assert_float_eq_f64},
-        ExParseError,
+        validate, BoundEx, ExError, FlatEx, OwnedFlatEx, PairViolation, ParseLimits, ParseOptions,
+        ScriptEx, Span, StaticEx, TokenCategory, TokenPatterns, VarOrder, NUMBER_REGEX_PATTERN,
+        VAR_REGEX_PATTERN,
     };
 
     #[test]
     fn test_readme() {
-        fn readme() -> Result<f64, ExParseError> {
+        fn readme() -> Result<f64, ExError> {
             let result = eval_str("sin(73)")?;
             assert_float_eq_f64(result, 73f64.sin());
             let expr = parse_with_default_ops::<f64>("2*x^3-4/z")?;
@@ -312,7 +1058,7 @@ mod tests {
             assert_float_eq_f64(value, 289.75399999999996);
             Ok(value)
         }
-        fn readme_int() -> Result<u32, ExParseError> {
+        fn readme_int() -> Result<u32, ExError> {
             let ops = vec![
                 Operator {
                     repr: "|",
@@ -321,11 +1067,21 @@ mod tests {
                         prio: 0,
                     }),
                     unary_op: None,
+                    domain_check: None,
+                    unary_position: UnaryPosition::Prefix,
+                    nary_op: None,
+                    fallible: None,
+                    aliases: &[],
                 },
                 Operator {
                     repr: "!",
                     bin_op: None,
                     unary_op: Some(|a: u32| !a),
+                    domain_check: None,
+                    unary_position: UnaryPosition::Prefix,
+                    nary_op: None,
+                    fallible: None,
+                    aliases: &[],
                 },
             ];
             let expr = parse::<u32>("!(a|b)", &ops)?;
@@ -333,8 +1089,66 @@ mod tests {
             assert_eq!(result, u32::MAX - 1);
             Ok(result)
         }
-        assert!(!readme().is_err());
-        assert!(!readme_int().is_err());
+        assert!(readme().is_ok());
+        assert!(readme_int().is_ok());
+    }
+    #[test]
+    fn test_deepex_overloading() {
+        use crate::{flatten, DeepEx};
+        fn from_str(text: &str) -> DeepEx<'_, f64> {
+            DeepEx::from_str(text).unwrap()
+        }
+        fn eval(deepex: &DeepEx<'_, f64>, vars: &[f64], val: f64) {
+            assert_float_eq_f64(flatten(deepex.clone()).unwrap().eval(vars).unwrap(), val);
+        }
+
+        let one = from_str("1");
+        let two = one.clone() + one.clone();
+        assert!(one.is_one() && !two.is_one());
+        eval(&two, &[], 2.0);
+        let x_squared = from_str("x*x");
+        let two_x_squared = two * x_squared;
+        eval(&two_x_squared, &[3.0], 18.0);
+        let some_expr = from_str("x") + from_str("x") * from_str("2") / from_str("x^(.5)");
+        eval(&some_expr, &[4.0], 8.0);
+
+        // variables are unified by name
+        let x_plus_y_plus_z = from_str("x+y+z");
+        let y_minus_z = from_str("y-z");
+        eval(
+            &(x_plus_y_plus_z.clone() * y_minus_z.clone()),
+            &[1.0, 4.0, 8.0],
+            -52.0,
+        );
+        eval(
+            &(x_plus_y_plus_z.clone() / y_minus_z.clone()),
+            &[1.0, 4.0, 8.0],
+            -3.25,
+        );
+        eval(
+            &(x_plus_y_plus_z.clone() - y_minus_z.clone()),
+            &[1.0, 4.0, 8.0],
+            17.0,
+        );
+        eval(
+            &(x_plus_y_plus_z + y_minus_z.clone()),
+            &[1.0, 4.0, 8.0],
+            9.0,
+        );
+        let prod = from_str("x+cos(sin(y+z))") * y_minus_z;
+        eval(&prod, &[1.0, 4.0, 8.0], -7.4378625090980925);
+        let prod = from_str("z") * from_str("a");
+        assert_eq!(prod.var_names(), ["a", "z"]);
+        assert_eq!(prod.n_vars(), 2);
+        assert_eq!(prod.unparse(), "z*a");
+
+        let power = from_str("x").pow(from_str("y-1")).unwrap();
+        eval(&power, &[2.0, 4.0], 8.0);
+        let no_pow = DeepEx::<f64>::from_ops("x", &make_default_operators()[1..]).unwrap();
+        assert!(no_pow.pow(from_str("2")).is_err());
+        let mut folded = from_str("2*x");
+        folded.compile();
+        assert_eq!(flatten(folded).unwrap().unparse().unwrap(), "2.0*x");
     }
     #[test]
     fn test_variables_curly() {
@@ -347,11 +1161,59 @@ mod tests {
 
         let sut = "sin({myvwmlf4i58eo;w/-😕+sin(a)r_25})";
         let expr = parse_with_default_ops::<f64>(sut).unwrap();
-        assert_float_eq_f64(expr.eval(&[1.5707963267948966]).unwrap(), 1.0);
+        assert_float_eq_f64(expr.eval(&[std::f64::consts::FRAC_PI_2]).unwrap(), 1.0);
 
         let sut = "((sin({myvar_25})))";
         let expr = parse_with_default_ops::<f64>(sut).unwrap();
-        assert_float_eq_f64(expr.eval(&[1.5707963267948966]).unwrap(), 1.0);
+        assert_float_eq_f64(expr.eval(&[std::f64::consts::FRAC_PI_2]).unwrap(), 1.0);
+
+        let expr = parse_with_default_ops::<f64>("{x}*x").unwrap();
+        assert_eq!(expr.var_names(), ["x"]);
+        assert_float_eq_f64(expr.eval(&[3.0]).unwrap(), 9.0);
+        let expr = parse_with_default_ops::<f64>("sin({x}) + x").unwrap();
+        assert_eq!(expr.var_names(), ["x"]);
+
+        for (sut, position) in [
+            ("{x", 0),
+            ("2*({x}+{y)", 7),
+            ("{", 0),
+            ("{foo", 0),
+            ("{x\\}", 0),
+        ] {
+            let err = parse_with_default_ops::<f64>(sut).unwrap_err();
+            assert!(matches!(err, ExError::UnterminatedBrace { .. }));
+            assert_eq!(err.position(), Some(position));
+            assert!(err.msg().contains("missing closing brace"));
+        }
+
+        // whitespace and escaped braces
+        let expr = parse_with_default_ops::<f64>("{a b}*2 + {x\\}y} + {\\\\}").unwrap();
+        assert_eq!(expr.var_names(), ["\\\\", "a b", "x\\}y"]);
+        assert_float_eq_f64(expr.eval(&[1.0, 2.0, 3.0]).unwrap(), 8.0);
+        let unparsed = expr.unparse().unwrap();
+        assert_eq!(unparsed, "{a b}*2.0+{x\\}y}+{\\\\}");
+        let reparsed = parse_with_default_ops::<f64>(&unparsed).unwrap();
+        assert_eq!(reparsed.var_names(), expr.var_names());
+        assert_eq!(reparsed.unparse().unwrap(), unparsed);
+
+        for (sut, position) in [("{}", 0), ("2*{}", 2), ("{x}+{}", 4)] {
+            let err = parse_with_default_ops::<f64>(sut).unwrap_err();
+            assert_eq!(
+                err,
+                ExError::EmptyVarName {
+                    position,
+                    context: sut.to_string()
+                }
+            );
+            assert_eq!(err.code(), "E036");
+            assert!(err.msg().contains("empty variable name"));
+        }
+        assert_eq!(
+            check("{}+{}", &make_default_operators::<f64>())
+                .unwrap_err()
+                .len(),
+            2
+        );
     }
     #[test]
     fn test_variables() {
@@ -415,16 +1277,17 @@ mod tests {
 
         let sut = "sin(myvar_25)";
         let expr = parse_with_default_ops::<f64>(sut).unwrap();
-        assert_float_eq_f64(expr.eval(&[1.5707963267948966]).unwrap(), 1.0);
+        assert_float_eq_f64(expr.eval(&[std::f64::consts::FRAC_PI_2]).unwrap(), 1.0);
 
         let sut = "((sin(myvar_25)))";
         let expr = parse_with_default_ops::<f64>(sut).unwrap();
-        assert_float_eq_f64(expr.eval(&[1.5707963267948966]).unwrap(), 1.0);
+        assert_float_eq_f64(expr.eval(&[std::f64::consts::FRAC_PI_2]).unwrap(), 1.0);
 
         let sut = "(0 * myvar_25 + cos(x))";
         let expr = parse_with_default_ops::<f64>(sut).unwrap();
         assert_float_eq_f64(
-            expr.eval(&[1.5707963267948966, 3.141592653589793]).unwrap(),
+            expr.eval(&[std::f64::consts::FRAC_PI_2, std::f64::consts::PI])
+                .unwrap(),
             -1.0,
         );
 
@@ -464,11 +1327,21 @@ mod tests {
                 repr: "invert",
                 bin_op: None,
                 unary_op: Some(|a: f32| 1.0 / a),
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
             },
             Operator {
                 repr: "sqrt",
                 bin_op: None,
                 unary_op: Some(|a: f32| a.sqrt()),
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
             },
         ];
         let expr = parse("sqrt(invert(a))", &ops).unwrap();
@@ -485,6 +1358,11 @@ mod tests {
                     prio: 2,
                 }),
                 unary_op: None,
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
             },
             Operator {
                 repr: "*",
@@ -493,11 +1371,21 @@ mod tests {
                     prio: 1,
                 }),
                 unary_op: None,
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
             },
             Operator {
                 repr: "invert",
                 bin_op: None,
                 unary_op: Some(|a: f32| 1.0 / a),
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
             },
         ];
         let expr = parse("2**2*invert(3)", &custom_ops).unwrap();
@@ -511,6 +1399,11 @@ mod tests {
                 prio: 2,
             }),
             unary_op: Some(|_| 0.0),
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
         };
         let extended_operators = make_default_operators::<f32>()
             .iter()
@@ -554,10 +1447,7 @@ mod tests {
             2.256637061435916,
         );
         assert_float_eq_f64(eval_str("((2-4)/5)*2").unwrap(), -0.8);
-        assert_float_eq_f64(
-            eval_str("-(-1+(sin(-3.14159265358979)/5)*2)").unwrap(),
-            1.0,
-        );
+        assert_float_eq_f64(eval_str("-(-1+(sin(-3.14159265358979)/5)*2)").unwrap(), 1.0);
         assert_float_eq_f64(
             eval_str("-(-1+sin(cos(-3.14159265358979)/5)*2)").unwrap(),
             1.3973386615901224,
@@ -586,6 +1476,15 @@ mod tests {
             1.4715655294841483,
         );
         assert_float_eq_f64(eval_str("--(1)").unwrap(), 1.0);
+        assert_float_eq_f64(eval_str("7 % 3").unwrap(), 1.0);
+        assert_float_eq_f64(eval_str("-7.5 % 2").unwrap(), -1.5);
+        assert_float_eq_f64(eval_str("2*7 % 4").unwrap(), 2.0);
+        assert_float_eq_f64(eval_str("abs(-4) + log10(100)").unwrap(), 6.0);
+        assert_float_eq_f64(eval_str("sign(-2) + signum(3)").unwrap(), 0.0);
+        assert_float_eq_f64(eval_str("sign(0) + sign(-0) + signum(0)").unwrap(), 1.0);
+        assert_float_eq_f64(eval_str("round(2.5) + round(-1.4)").unwrap(), 2.0);
+        assert_float_eq_f64(eval_str("cbrt(-27) * exp2(3)").unwrap(), -24.0);
+        assert_float_eq_f64(eval_str("ln(exp(2)) - log(exp(2))").unwrap(), 0.0);
         assert_float_eq_f64(eval_str("--1").unwrap(), 1.0);
         assert_float_eq_f64(eval_str("----1").unwrap(), 1.0);
         assert_float_eq_f64(eval_str("---1").unwrap(), -1.0);
@@ -599,16 +1498,1070 @@ mod tests {
             4.4816890703380645,
         );
         assert_float_eq_f64(eval_str("log2(2)").unwrap(), 1.0);
+        assert_float_eq_f64(eval_str("log10(1000)").unwrap(), 3.0);
+        assert_float_eq_f64(eval_str("ln(e^2)").unwrap(), 2.0);
+        assert_float_eq_f64(eval_str("ln(exp(2))").unwrap(), 2.0);
+        assert_float_eq_f64(eval_str("logb(2, 8) + logb(10, 0.01)").unwrap(), 1.0);
         assert_float_eq_f64(eval_str("2^log2(2)").unwrap(), 2.0);
         assert_float_eq_f64(eval_str("2^(cos(0)+2)").unwrap(), 8.0);
         assert_float_eq_f64(eval_str("2^cos(0)+2").unwrap(), 4.0);
     }
 
+    #[test]
+    fn test_number_formats() {
+        assert_float_eq_f64(eval_str("1e-3 + 2.5E2").unwrap(), 250.001);
+        assert_float_eq_f64(eval_str("2.5E+1 * 1_000.5").unwrap(), 25012.5);
+        assert_float_eq_f64(eval_str(".5e1 - 4.e-1").unwrap(), 4.6);
+        assert_float_eq_f64(eval_str("1_0e1_0").unwrap(), 1e11);
+
+        // `e` without digits is a variable
+        let expr = parse_with_default_ops::<f64>("xe+1").unwrap();
+        assert_eq!(expr.var_names(), ["xe"]);
+        assert_float_eq_f64(expr.eval(&[2.0]).unwrap(), 3.0);
+        let expr = parse_with_default_ops::<f64>("x e y").unwrap_err();
+        assert!(matches!(expr, ExError::InvalidPair { .. }));
+        let expr = parse_with_default_ops::<f64>("e^2-e+1").unwrap();
+        assert_eq!(expr.var_names(), ["e"]);
+        let ops = make_default_operators::<f64>();
+        let implicit = ParseOptions {
+            implicit_multiplication: true,
+            ..ParseOptions::default()
+        };
+        let expr = parse_with_options::<f64>("2e+x", &ops, &implicit).unwrap();
+        assert_eq!(expr.var_names(), ["e", "x"]);
+        assert_float_eq_f64(expr.eval(&[3.0, 1.0]).unwrap(), 7.0);
+        let expr = parse_with_options::<f64>("2e3x_1", &ops, &implicit).unwrap();
+        assert_eq!(expr.var_names(), ["x_1"]);
+        assert_float_eq_f64(expr.eval(&[0.5]).unwrap(), 1000.0);
+
+        // the pattern of the regex path coincides
+        let expr = parse_with_number_pattern::<f64>("1e-3 + 2_5.0E1*x", &ops, NUMBER_REGEX_PATTERN)
+            .unwrap();
+        assert_float_eq_f64(expr.eval(&[2.0]).unwrap(), 500.001);
+        let expr = parse_with_number_pattern::<f64>("xe+1", &ops, NUMBER_REGEX_PATTERN).unwrap();
+        assert_eq!(expr.var_names(), ["xe"]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parse_batch() {
+        use crate::{parse_batch, parse_batch_owned};
+        let ops = make_default_operators::<f64>();
+        let texts = (0..100)
+            .map(|i| match i % 3 {
+                0 => format!("x*{}", i),
+                1 => format!("sin(x)+{}", i),
+                _ => format!("(x+{}", i),
+            })
+            .collect::<Vec<_>>();
+        let texts = texts.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+        let exprs = parse_batch(&texts, &ops);
+        assert_eq!(exprs.len(), texts.len());
+        for (text, expr) in texts.iter().zip(exprs.iter()) {
+            assert_eq!(expr, &parse(text, &ops));
+        }
+        assert_eq!(exprs.iter().filter(|expr| expr.is_err()).count(), 33);
+
+        let owned = {
+            let texts = texts.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+            let texts = texts.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+            parse_batch_owned(&texts, &ops)
+        };
+        for (i, expr) in owned.iter().enumerate() {
+            match expr {
+                Ok(expr) => assert_float_eq_f64(
+                    expr.eval(&[2.0]).unwrap(),
+                    exprs[i].as_ref().unwrap().eval(&[2.0]).unwrap(),
+                ),
+                Err(e) => assert_eq!(Err(e), exprs[i].as_ref()),
+            }
+        }
+        assert!(parse_batch::<f64>(&[], &ops).is_empty());
+    }
+
+    #[test]
+    fn test_var_patterns() {
+        let ops = make_default_operators::<f64>();
+        let dollar = r"\$[a-zA-Z_]+";
+        let expr =
+            parse_with_var_pattern::<f64>("$price * (1 + $tax) - sin($x)", &ops, dollar).unwrap();
+        assert_eq!(expr.var_names(), ["$price", "$tax", "$x"]);
+        assert_float_eq_f64(expr.eval(&[10.0, 0.5, 0.0]).unwrap(), 15.0);
+        // names that do not match the pattern are errors
+        assert!(parse_with_var_pattern::<f64>("$price * x", &ops, dollar).is_err());
+
+        let dotted = r"[a-z]+(\.[a-z]+)*";
+        let expr = parse_with_var_pattern::<f64>("obj.field^2/obj.other", &ops, dotted).unwrap();
+        assert_eq!(expr.var_names(), ["obj.field", "obj.other"]);
+        assert_float_eq_f64(expr.eval(&[3.0, 2.0]).unwrap(), 4.5);
+        // numbers and operators take precedence
+        let expr = parse_with_var_pattern::<f64>(".5*cos.x", &ops, r"[a-z.]+").unwrap();
+        assert_eq!(expr.var_names(), [".x"]);
+        let expr = parse_with_var_pattern::<f64>("x1 + 2", &ops, r"[a-z0-9]+").unwrap();
+        assert_eq!(expr.var_names(), ["x1"]);
+        // unparsed names are braced and can be parsed with the default pattern
+        assert_eq!(expr.unparse().unwrap(), "x1+2.0");
+        let expr = parse_with_var_pattern::<f64>("a.b*2", &ops, dotted).unwrap();
+        assert_eq!(expr.unparse().unwrap(), "{a.b}*2.0");
+        let reparsed = parse_with_default_ops::<f64>("{a.b}*2.0").unwrap();
+        assert!(expr.structure_eq(&reparsed, false).unwrap());
+
+        let patterns = TokenPatterns {
+            number: "[0-9]+",
+            var: r"[a-z]+\[[0-9]+\]",
+        };
+        let expr = parse_with_patterns::<f64>("a[2]*b[10] + 3", &ops, &patterns).unwrap();
+        assert_eq!(expr.var_names(), ["a[2]", "b[10]"]);
+        assert_float_eq_f64(expr.eval(&[2.0, 5.0]).unwrap(), 13.0);
+        assert_eq!(
+            TokenPatterns::default(),
+            TokenPatterns {
+                number: NUMBER_REGEX_PATTERN,
+                var: VAR_REGEX_PATTERN
+            }
+        );
+        let expr =
+            parse_with_patterns::<f64>("x_1*2.5e1", &ops, &TokenPatterns::default()).unwrap();
+        assert_float_eq_f64(expr.eval(&[2.0]).unwrap(), 50.0);
+
+        for invalid in ["(", "[a-z]*", "x?"] {
+            assert_eq!(
+                parse_with_var_pattern::<f64>("x", &ops, invalid).map(|_| ()),
+                Err(ExError::InvalidVarPattern(invalid.to_string()))
+            );
+        }
+    }
+
     #[test]
     fn test_error_handling() {
-        assert!(eval_str("").is_err());
-        assert!(eval_str("5+5-(").is_err());
-        assert!(eval_str(")2*(5+5)*3-2)*2").is_err());
-        assert!(eval_str("2*(5+5))").is_err());
+        assert_eq!(eval_str(""), Err(ExError::EmptyInput));
+        assert_eq!(
+            eval_str("5+5-("),
+            Err(ExError::ParenMismatch {
+                position: 4,
+                context: "5+5-(".to_string()
+            })
+        );
+        assert_eq!(
+            eval_str(")2*(5+5)*3-2)*2"),
+            Err(ExError::InvalidPair {
+                position: 1,
+                violation: PairViolation::OperandNextToParen,
+                context: ")2*(5+5".to_string()
+            })
+        );
+        assert_eq!(
+            eval_str("2*(5+5))"),
+            Err(ExError::ParenMismatch {
+                position: 7,
+                context: "(5+5))".to_string()
+            })
+        );
+        let expr = parse_with_default_ops::<f64>("x+y").unwrap();
+        assert_eq!(
+            expr.eval(&[1.0]),
+            Err(ExError::WrongNumberOfVars {
+                expected: 2,
+                given: 1
+            })
+        );
+        let expr = parse_with_default_ops::<f64>("trunc(x)").unwrap();
+        assert_eq!(
+            expr.partial(0).map(|_| ()),
+            Err(ExError::DerivativeNotFound("trunc".to_string()))
+        );
+        assert_eq!(
+            parse_with_number_pattern::<f64>("1", &make_default_operators(), "(").map(|_| ()),
+            Err(ExError::InvalidNumberPattern("(".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // must fail with an error instead of overflowing the stack
+        let deep = format!("{}x{}", "(".repeat(100_000), ")".repeat(100_000));
+        let err = parse_with_default_ops::<f64>(&deep).unwrap_err();
+        assert_eq!(err.code(), "E048");
+        assert_eq!(err.position(), Some(512));
+        assert_eq!(eval_str(&deep).unwrap_err().code(), "E048");
+        let shallow = format!("{}2{}", "(".repeat(16), ")".repeat(16));
+        assert_eq!(eval_str(&shallow), Ok(2.0));
+    }
+
+    #[test]
+    fn test_default_depth_on_small_stack() {
+        // parsing, flattening, and unparsing do not recurse into sub-expressions, such that
+        // the default limit fits into the 2 MiB stacks of spawned threads also in debug builds
+        let at_default_limit = || {
+            let depth = 512;
+            let nest = |prefix: &str, inner: &str| {
+                format!("{}{}{}", prefix.repeat(depth), inner, ")".repeat(depth))
+            };
+            let max = Operator::make_nary(
+                "max",
+                NaryOp {
+                    apply: |args: &[f64]| args[0].max(args[1]),
+                    arity: 2,
+                },
+            );
+            let mut ops = make_default_operators::<f64>().to_vec();
+            ops.push(max);
+            let options = ParseOptions {
+                unary_minus_binds_weaker_than_pow: true,
+                ..ParseOptions::default()
+            };
+            let reference = |f: fn(f64) -> f64| (0..depth).fold(0.5, |x, _| f(x));
+            let cases = [
+                (nest("sin(", "x"), f64::sin as fn(f64) -> f64),
+                (nest("(1+", "x"), |x| 1.0 + x),
+                (nest("max(-1,", "x"), |x| x.max(-1.0)),
+                (nest("-(0.5*", "x"), |x| -0.5 * x),
+                (nest("-1^(", "x"), |x| -(1.0f64.powf(x))),
+            ];
+            for (text, f) in cases.iter() {
+                let expr = parse_with_options::<f64>(text, &ops, &options).unwrap();
+                assert_float_eq_f64(expr.eval(&[0.5]).unwrap(), reference(*f));
+                let unparsed = expr.unparse().unwrap();
+                let reparsed = parse_with_options::<f64>(&unparsed, &ops, &options).unwrap();
+                assert_float_eq_f64(reparsed.eval(&[0.5]).unwrap(), reference(*f));
+            }
+            let too_deep = format!("({})", cases[0].0);
+            let err = parse_with_default_ops::<f64>(&too_deep).unwrap_err();
+            assert_eq!(err.code(), "E048");
+        };
+        thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(at_default_limit)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let ops = make_default_operators::<f64>();
+        let implicit = ParseOptions {
+            implicit_multiplication: true,
+            ..ParseOptions::default()
+        };
+        let test = |text: &str, explicit: &str, vars: &[f64]| {
+            let expr = parse_with_options::<f64>(text, &ops, &implicit).unwrap();
+            let reference = parse::<f64>(explicit, &ops).unwrap();
+            assert_eq!(expr.var_names(), reference.var_names());
+            assert_float_eq_f64(expr.eval(vars).unwrap(), reference.eval(vars).unwrap());
+            assert_eq!(expr.unparse().unwrap(), reference.unparse().unwrap());
+        };
+        test("2x", "2*x", &[1.5]);
+        test("2(x+1)", "2*(x+1)", &[1.5]);
+        test("(a+b)(a-b)", "(a+b)*(a-b)", &[3.0, 2.0]);
+        test("3sin(x)", "3*sin(x)", &[0.5]);
+        test("2x^2 + 3", "2*x^2 + 3", &[-1.5]);
+        test("1/2x", "1/2*x", &[4.0]);
+        test("-2x(y - 1)", "-2*x*(y - 1)", &[3.0, 0.5]);
+
+        // strict by default
+        assert_eq!(
+            parse::<f64>("2x", &ops).map(|_| ()),
+            parse_with_options::<f64>("2x", &ops, &ParseOptions::default()).map(|_| ())
+        );
+        assert!(parse_with_options::<f64>("2x", &ops, &ParseOptions::default()).is_err());
+        assert!(parse_with_options::<f64>("3 4", &ops, &implicit).is_err());
+    }
+
+    #[test]
+    fn test_unary_prio() {
+        let ops = make_default_operators::<f64>();
+        let weaker = ParseOptions {
+            unary_minus_binds_weaker_than_pow: true,
+            ..ParseOptions::default()
+        };
+        let test = |text: &str, vars: &[f64], reference: f64| {
+            let expr = parse_with_options::<f64>(text, &ops, &weaker).unwrap();
+            assert_float_eq_f64(expr.eval(vars).unwrap(), reference);
+        };
+        test("-2^2", &[], -4.0);
+        test("-2^2+1", &[], -3.0);
+        test("2 - -2^2", &[], 6.0);
+        test("-2^-2", &[], -0.25);
+        test("--2^2", &[], 4.0);
+        test("-(1+1)^2", &[], -4.0);
+        test("-2*3+1", &[], -5.0);
+        test("-x^2*y", &[3.0, 2.0], -18.0);
+        test("-sin(x)^2", &[0.5], -0.5f64.sin().powi(2));
+        test("exp(-x^2)", &[2.0], (-4.0f64).exp());
+
+        // unary operators bind stronger by default
+        assert_float_eq_f64(eval_str("-2^2").unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_unary_binary_mix() {
+        let ops = make_default_operators::<f64>();
+        let weaker = ParseOptions {
+            unary_minus_binds_weaker_than_pow: true,
+            ..ParseOptions::default()
+        };
+        // text, value with x=2 and y=5, value with unary minus weaker than powers
+        let table = [
+            ("(+3)", 3.0, 3.0),
+            ("(-x)*y", -10.0, -10.0),
+            ("(- x) * y", -10.0, -10.0),
+            ("3 - -x", 5.0, 5.0),
+            ("3--x", 5.0, 5.0),
+            ("3---x", 1.0, 1.0),
+            ("3----x", 5.0, 5.0),
+            ("3+-x", 1.0, 1.0),
+            ("3-+x", 1.0, 1.0),
+            ("3-+-+x", 5.0, 5.0),
+            ("x - - - 2", 0.0, 0.0),
+            ("--x", 2.0, 2.0),
+            ("+-x", -2.0, -2.0),
+            ("-+-x", 2.0, 2.0),
+            ("-(-(-x))", -2.0, -2.0),
+            ("++x", 2.0, 2.0),
+            ("x++y", 7.0, 7.0),
+            ("2*-x", -4.0, -4.0),
+            ("2*--x", 4.0, 4.0),
+            ("-x*-y", 10.0, 10.0),
+            ("2^-x", 0.25, 0.25),
+            ("2^--x", 4.0, 4.0),
+            ("-x^2", 4.0, -4.0),
+            ("--x^2", 4.0, 4.0),
+            ("---x^2", 4.0, -4.0),
+            ("y--x^2", 1.0, 9.0),
+            ("y---x^2", 1.0, 1.0),
+            ("-sin(x)--x", -(2.0f64.sin()) + 2.0, -(2.0f64.sin()) + 2.0),
+            ("sin --x", 2.0f64.sin(), 2.0f64.sin()),
+            ("logb(2, -(-8))*-1", -3.0, -3.0),
+        ];
+        for (text, reference, reference_weaker) in table.iter() {
+            let expr = parse_with_default_ops::<f64>(text).unwrap();
+            let vars = &[2.0, 5.0][..expr.var_names().len()];
+            assert_float_eq_f64(expr.eval(vars).unwrap(), *reference);
+            let unparsed = expr.unparse().unwrap();
+            let reparsed = parse_with_default_ops::<f64>(&unparsed).unwrap();
+            assert_float_eq_f64(reparsed.eval(vars).unwrap(), *reference);
+            let expr = parse_with_options(text, &ops, &weaker).unwrap();
+            assert_float_eq_f64(expr.eval(vars).unwrap(), *reference_weaker);
+        }
+
+        // an operator that is binary in its position cannot be followed by a binary operator
+        let errors = [
+            ("3-*2", 2, PairViolation::AdjacentBinaryOps),
+            ("x+/y", 2, PairViolation::AdjacentBinaryOps),
+            ("x-^2", 2, PairViolation::AdjacentBinaryOps),
+            ("-*2", 1, PairViolation::BinaryAfterUnary),
+            ("3*-*2", 3, PairViolation::BinaryAfterUnary),
+            ("x--*2", 3, PairViolation::BinaryAfterUnary),
+            ("(+*3)", 2, PairViolation::BinaryAfterUnary),
+        ];
+        for (text, position, violation) in errors.iter() {
+            match parse_with_default_ops::<f64>(text) {
+                Err(ExError::InvalidPair {
+                    position: p,
+                    violation: v,
+                    ..
+                }) => assert_eq!((p, v), (*position, *violation), "{}", text),
+                res => panic!("{} should be an invalid pair, got {:?}", text, res),
+            }
+        }
+        for text in ["(-)(3)", "(-)", "x-(-)", "3 - - -"].iter() {
+            assert!(parse_with_default_ops::<f64>(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_strict_domains() {
+        let ops = make_default_operators::<f64>();
+        let strict = ParseOptions {
+            strict_domains: true,
+            ..ParseOptions::default()
+        };
+        let test_violation = |text: &str, op: &str| {
+            match parse_with_options::<f64>(text, &ops, &strict) {
+                Err(ExError::DomainViolation { op: found, .. }) => assert_eq!(found, op),
+                res => panic!("expected domain violation for {}, got {:?}", text, res),
+            }
+            // lenient by default
+            let val = parse_with_options::<f64>(text, &ops, &ParseOptions::default())
+                .unwrap()
+                .eval(&[])
+                .unwrap();
+            assert!(val.is_nan() || val.is_infinite(), "{}", text);
+        };
+        test_violation("log(0)", "log");
+        test_violation("log(2-2)", "log");
+        test_violation("1 + log2(-1)", "log2");
+        test_violation("ln(0)*2", "ln");
+        test_violation("log10(-3)", "log10");
+        test_violation("sqrt(-4)", "sqrt");
+        test_violation("sqrt-4", "sqrt");
+        test_violation("asin(2)", "asin");
+        test_violation("acos(-1.5)", "acos");
+        test_violation("acosh(0.5)", "acosh");
+        test_violation("atanh(-1)", "atanh");
+        test_violation("sqrt(log(0.5))", "sqrt");
+
+        assert_eq!(
+            parse_with_options::<f64>("1 + log(2-2)", &ops, &strict),
+            Err(ExError::DomainViolation {
+                position: 4,
+                end: 12,
+                op: "log".to_string(),
+                value: "0.0".to_string(),
+                reason: "the logarithm is only defined for positive numbers".to_string(),
+                context: "1 + log(2-2)".to_string(),
+            })
+        );
+
+        let test_ok = |text: &str, vars: &[f64], reference: f64| {
+            let expr = parse_with_options::<f64>(text, &ops, &strict).unwrap();
+            assert_float_eq_f64(expr.eval(vars).unwrap(), reference);
+        };
+        test_ok("log(1) + sqrt(4)", &[], 2.0);
+        test_ok("asin(0.5)*acos(1)", &[], 0.0);
+        test_ok("acosh(1) + atanh(0) + asinh(0)", &[], 0.0);
+        test_ok("sqrt(2-1) + log2(8)", &[], 4.0);
+        test_ok("-sqrt(4)", &[], -2.0);
+        // variables are not checked during parsing
+        let expr = parse_with_options::<f64>("log(x) + sqrt(0*y - 1)", &ops, &strict).unwrap();
+        assert!(expr.eval(&[0.0, 1.0]).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_postfix() {
+        fn factorial(n: u64) -> u64 {
+            (1..=n).product()
+        }
+        let ops = [
+            Operator {
+                repr: "+",
+                bin_op: Some(BinOp {
+                    apply: |a: u64, b: u64| a + b,
+                    prio: 0,
+                }),
+                unary_op: None,
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
+            },
+            Operator {
+                repr: "*",
+                bin_op: Some(BinOp {
+                    apply: |a: u64, b: u64| a * b,
+                    prio: 1,
+                }),
+                unary_op: None,
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
+            },
+            Operator {
+                repr: "!",
+                bin_op: None,
+                unary_op: Some(factorial),
+                domain_check: None,
+                unary_position: UnaryPosition::Postfix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
+            },
+            Operator {
+                repr: "dbl",
+                bin_op: None,
+                unary_op: Some(|a: u64| 2 * a),
+                domain_check: None,
+                unary_position: UnaryPosition::Prefix,
+                nary_op: None,
+                fallible: None,
+                aliases: &[],
+            },
+        ];
+        let eval = |text: &str, vars: &[u64]| parse::<u64>(text, &ops).unwrap().eval(vars).unwrap();
+        assert_eq!(eval("3!+2", &[]), 8);
+        assert_eq!(eval("(2+1)!", &[]), 6);
+        assert_eq!(eval("2*3!", &[]), 12);
+        assert_eq!(eval("3!!", &[]), 720);
+        assert_eq!(eval("dbl 3!", &[]), 12);
+        assert_eq!(eval("dbl(x!)*2", &[3]), 24);
+        assert_eq!(eval("(x+1)!*y", &[3, 2]), 48);
+
+        let expr = parse::<u64>("x!+dbl(y+1)!", &ops).unwrap();
+        assert_eq!(expr.eval(&[3, 1]).unwrap(), 10);
+        assert_eq!(expr.unparse().unwrap(), "x!+dbl((y+1)!)");
+        let expr = parse::<u64>("(x*2)!", &ops).unwrap();
+        assert_eq!(expr.unparse().unwrap(), "(x*2)!");
+
+        let test_misplaced = |text: &str, position: usize| {
+            assert!(
+                matches!(
+                    parse::<u64>(text, &ops),
+                    Err(ExError::InvalidPair {
+                        position: p,
+                        violation: PairViolation::MisplacedPostfixUnary,
+                        ..
+                    }) if p == position
+                ),
+                "{}",
+                text
+            );
+        };
+        test_misplaced("!3", 0);
+        test_misplaced("2+!3", 2);
+        test_misplaced("(!3)", 1);
+        test_misplaced("3!2", 2);
+        test_misplaced("x!(1)", 2);
+
+        // operators that are postfix and binary are binary if an operand follows
+        let mut ops = make_default_operators::<f64>().to_vec();
+        ops.retain(|op| op.repr != "%");
+        ops.push(Operator {
+            repr: "%",
+            bin_op: Some(BinOp {
+                apply: |a: f64, b: f64| a % b,
+                prio: 1,
+            }),
+            unary_op: Some(|a: f64| a / 100.0),
+            domain_check: None,
+            unary_position: UnaryPosition::Postfix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        });
+        let eval = |text: &str| parse::<f64>(text, &ops).unwrap().eval(&[]).unwrap();
+        assert_float_eq_f64(eval("50% + 1"), 1.5);
+        assert_float_eq_f64(eval("7%4"), 3.0);
+        assert_float_eq_f64(eval("-50%"), -0.5);
+        assert_float_eq_f64(eval("2*(50%)"), 1.0);
+        assert_float_eq_f64(eval("10%%"), 0.001);
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_float_eq_f64(eval_str("cos(pi)").unwrap(), -1.0);
+        assert_float_eq_f64(eval_str("tau/pi + log(e)").unwrap(), 3.0);
+        assert!(matches!(
+            eval_str("pi*r"),
+            Err(ExError::WrongNumberOfVars {
+                expected: 1,
+                given: 0
+            })
+        ));
+
+        let ops = make_default_operators::<f64>();
+        let expr =
+            parse_with_constants("sin(pi/2)*x + e", &ops, &make_default_constants()).unwrap();
+        assert_eq!(expr.var_names(), ["x"]);
+        assert_float_eq_f64(expr.eval(&[2.0]).unwrap(), 2.0 + std::f64::consts::E);
+        // prepended constants shadow later ones
+        let constants = once(("pi", 3.0))
+            .chain(make_default_constants())
+            .collect::<Vec<_>>();
+        let expr = parse_with_constants("2*pi*r", &ops, &constants).unwrap();
+        assert_float_eq_f64(expr.eval(&[1.0]).unwrap(), 6.0);
+        // without constants, pi is a variable
+        assert_eq!(
+            parse_with_default_ops::<f64>("2*pi").unwrap().var_names(),
+            ["pi"]
+        );
+
+        let ops = [Operator {
+            repr: "+",
+            bin_op: Some(BinOp {
+                apply: |a: i32, b: i32| a + b,
+                prio: 0,
+            }),
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        }];
+        let expr = parse_with_constants("answer + x", &ops, &[("answer", 42)]).unwrap();
+        assert_eq!(expr.var_names(), ["x"]);
+        assert_eq!(expr.eval(&[-2]).unwrap(), 40);
+    }
+
+    #[test]
+    fn test_definitions() {
+        let ops = make_default_operators::<f64>();
+        let capital = parse_with_default_ops::<f64>("tier_1 + 0.5*tier_2").unwrap();
+        let risk = parse_with_default_ops::<f64>("amount*weight").unwrap();
+        let defs = [("capital", &capital), ("risk", &risk)];
+        let expr = parse_with_definitions("-capital/risk + 2*(tier_1 - c)", &ops, &defs).unwrap();
+        let inlined = parse::<f64>(
+            "-(tier_1 + 0.5*tier_2)/(amount*weight) + 2*(tier_1 - c)",
+            &ops,
+        )
+        .unwrap();
+        assert_eq!(expr.var_names(), inlined.var_names());
+        assert_eq!(
+            expr.var_names(),
+            ["amount", "c", "tier_1", "tier_2", "weight"]
+        );
+        let vars = [2.0, 0.3, 1.5, 4.0, 0.25];
+        assert_float_eq_f64(expr.eval(&vars).unwrap(), inlined.eval(&vars).unwrap());
+        assert_float_eq_f64(
+            expr.clone().partial(2).unwrap().eval(&vars).unwrap(),
+            inlined.partial(2).unwrap().eval(&vars).unwrap(),
+        );
+        let expr = parse_with_definitions("sin(risk)^2", &ops, &defs).unwrap();
+        assert_float_eq_f64(expr.eval(&[2.0, 0.25]).unwrap(), 0.5f64.sin().powi(2));
+
+        // names without definition remain variables
+        let expr = parse_with_definitions("capital*unknown", &ops, &defs).unwrap();
+        assert_eq!(expr.var_names(), ["tier_1", "tier_2", "unknown"]);
+        assert_float_eq_f64(expr.eval(&[1.0, 2.0, 3.0]).unwrap(), 6.0);
+
+        let mut cleared = capital.clone();
+        cleared.clear_deepex();
+        assert_eq!(
+            parse_with_definitions("capital", &ops, &[("capital", &cleared)]).map(|_| ()),
+            Err(ExError::DeepExMissing)
+        );
+    }
+
+    #[test]
+    fn test_nary_ops() {
+        let mut ops = make_default_operators::<f64>().to_vec();
+        ops.push(Operator {
+            repr: "clamp",
+            bin_op: None,
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: Some(NaryOp {
+                apply: |args| args[0].max(args[1]).min(args[2]),
+                arity: 3,
+            }),
+            fallible: None,
+            aliases: &[],
+        });
+        ops.push(Operator {
+            repr: "if",
+            bin_op: None,
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: Some(NaryOp {
+                apply: |args| if args[0] != 0.0 { args[1] } else { args[2] },
+                arity: 3,
+            }),
+            fallible: None,
+            aliases: &[],
+        });
+        let eval = |text: &str, vars: &[f64]| parse::<f64>(text, &ops).unwrap().eval(vars).unwrap();
+        assert_float_eq_f64(eval("clamp(x, 0, 1)", &[1.5]), 1.0);
+        assert_float_eq_f64(eval("clamp(x, 0, 1)", &[-0.5]), 0.0);
+        assert_float_eq_f64(eval("clamp(x, 0, 1)", &[0.3]), 0.3);
+        assert_float_eq_f64(eval("2*clamp(x^2, -1, 1)+1", &[0.5]), 1.5);
+        // unary operators bind stronger than binary ones as for `-x^2`
+        assert_float_eq_f64(eval("-clamp(x, 0, 1)^2", &[0.5]), 0.25);
+        assert_float_eq_f64(eval("sin clamp(x, 0, 1)", &[2.0]), 1f64.sin());
+        assert_float_eq_f64(eval("clamp(clamp(x, 0, 2), y, (1))", &[3.0, 1.5]), 1.0);
+        assert_float_eq_f64(eval("if(b, a, c)", &[1.0, 0.0, 3.0]), 3.0);
+        assert_float_eq_f64(eval("if(b, a, c)", &[1.0, 2.0, 3.0]), 1.0);
+
+        let expr = parse::<f64>("clamp(x, -y, y) + 1", &ops).unwrap();
+        assert_eq!(expr.var_names(), ["x", "y"]);
+        assert_eq!(expr.unparse().unwrap(), "clamp(x,-y,y)+1.0");
+        let reparsed = parse::<f64>("clamp({x},-({y}),{y})+1.0", &ops).unwrap();
+        assert!(expr.structure_eq(&reparsed, false).unwrap());
+        let mut out = [0.0; 3];
+        expr.eval_vectorized(&[&[-2.0, 0.5, 2.0], &[1.0, 1.0, 1.5]], &mut out)
+            .unwrap();
+        assert_eq!(out, [0.0, 1.5, 2.5]);
+
+        // calls with constant arguments are folded
+        let expr = parse::<f64>("clamp(3, 0, 2*0.5) * x", &ops).unwrap();
+        assert_eq!(expr.unparse().unwrap(), "1.0*x");
+        let expr = parse::<f64>("clamp(x, -y, y) + 1", &ops).unwrap();
+        let bound = expr.bind(&[(0, 3.0), (1, 2.0)]).unwrap();
+        assert_eq!(bound.unparse().unwrap(), "3.0");
+        let implicit = ParseOptions {
+            implicit_multiplication: true,
+            ..ParseOptions::default()
+        };
+        let expr = parse_with_options::<f64>("2clamp(x, 0, 1)", &ops, &implicit).unwrap();
+        assert_float_eq_f64(expr.eval(&[0.25]).unwrap(), 0.5);
+
+        // custom n-ary operators have no derivatives and no interval arithmetic
+        let expr = parse::<f64>("clamp(x, 0, 1)*y", &ops).unwrap();
+        assert_float_eq_f64(
+            expr.clone().partial(1).unwrap().eval(&[0.5, 3.0]).unwrap(),
+            0.5,
+        );
+        assert_eq!(
+            expr.clone().partial(0),
+            Err(ExError::DerivativeNotFound("clamp".to_string()))
+        );
+        assert_eq!(
+            expr.eval_interval(&[(0.0, 1.0), (0.0, 1.0)]),
+            Err(ExError::IntervalOpNotFound("clamp".to_string()))
+        );
+
+        let test_args = |text: &str, position: usize, given: usize, context: &str| {
+            assert_eq!(
+                parse::<f64>(text, &ops).unwrap_err(),
+                ExError::WrongNumberOfArgs {
+                    position,
+                    op: "clamp".to_string(),
+                    expected: 3,
+                    given,
+                    context: context.to_string(),
+                },
+                "{}",
+                text
+            );
+        };
+        test_args("clamp(x, 1)", 0, 2, "clamp(x, 1)");
+        test_args("1 + clamp(x, 0, 1, 2)", 4, 4, "1 + clamp(x, 0, 1, 2)");
+        test_args("clamp x", 0, 0, "clamp x");
+        let err = parse::<f64>("clamp(x, 1)", &ops).unwrap_err();
+        assert_eq!(err.code(), "E024");
+        assert_eq!(err.position(), Some(0));
+
+        let test_comma = |text: &str, position: usize| {
+            assert!(
+                matches!(
+                    parse::<f64>(text, &ops),
+                    Err(ExError::InvalidPair {
+                        position: p,
+                        violation: PairViolation::MisplacedComma,
+                        ..
+                    }) if p == position
+                ),
+                "{}",
+                text
+            );
+        };
+        test_comma("x, 1", 1);
+        test_comma("(x, 1)", 2);
+        test_comma("clamp(x,,1)", 8);
+        test_comma("clamp(x, *1, 2)", 9);
+        test_comma("clamp(,x, 1, 2)", 6);
+        test_comma("clamp(x, 1, 2,)", 14);
+        test_comma("clamp((x, 1), 0, 1)", 8);
+        assert!(matches!(
+            parse::<f64>("clamp()", &ops),
+            Err(ExError::InvalidPair {
+                violation: PairViolation::EmptyParens,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FlatEx<'static, f64>>();
+        assert_send_sync::<OwnedFlatEx<f64>>();
+        assert_send_sync::<BoundEx<'static, 'static, f64, 2>>();
+        assert_send_sync::<ScriptEx<'static, f64>>();
+        assert_send_sync::<StaticEx>();
+        assert_send_sync::<Operator<'static, f64>>();
+        assert_send_sync::<BinOp<f64>>();
+        assert_send_sync::<UnaryOp<f64>>();
+        assert_send_sync::<NaryOp<f64>>();
+        assert_send_sync::<ExError>();
+
+        let expr = Arc::new(parse_with_default_ops::<f64>("x^2 + sin(y)*z - x/y").unwrap());
+        let point = |thread_idx: usize, i: usize| {
+            let t = (thread_idx * 1000 + i) as f64;
+            [t * 1e-3, 1.0 + t * 1e-4, -t * 1e-2]
+        };
+        let handles = (0..8)
+            .map(|thread_idx| {
+                let expr = Arc::clone(&expr);
+                thread::spawn(move || {
+                    (0..1000)
+                        .map(|i| expr.eval(&point(thread_idx, i)).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (thread_idx, handle) in handles.into_iter().enumerate() {
+            let values = handle.join().unwrap();
+            for (i, value) in values.iter().enumerate() {
+                let [x, y, z] = point(thread_idx, i);
+                assert_float_eq_f64(*value, x.powi(2) + y.sin() * z - x / y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_var_order() {
+        let ops = make_default_operators::<f64>();
+        let occurrence = ParseOptions {
+            var_order: VarOrder::Occurrence,
+            ..ParseOptions::default()
+        };
+        let text = "z + (a*z) - b^2";
+        let alphabetical = parse_with_options::<f64>(text, &ops, &ParseOptions::default()).unwrap();
+        assert_eq!(alphabetical.var_order(), VarOrder::Alphabetical);
+        assert_eq!(alphabetical.var_names(), ["a", "b", "z"]);
+        assert_float_eq_f64(alphabetical.eval(&[2.0, 3.0, 5.0]).unwrap(), 6.0);
+        let expr = parse_with_options::<f64>(text, &ops, &occurrence).unwrap();
+        assert_eq!(expr.var_order(), VarOrder::Occurrence);
+        assert_eq!(expr.var_names(), ["z", "a", "b"]);
+        let (z, a, b) = (5.0, 2.0, 3.0);
+        assert_float_eq_f64(expr.eval(&[z, a, b]).unwrap(), 6.0);
+        assert_eq!(expr.unparse(), alphabetical.unparse());
+
+        // derived expressions keep the order
+        let grad = expr.grad().unwrap();
+        assert_float_eq_f64(grad[0].eval(&[z, a, b]).unwrap(), 1.0 + a);
+        assert_float_eq_f64(grad[1].eval(&[z, a, b]).unwrap(), z);
+        assert_float_eq_f64(grad[2].eval(&[z, a, b]).unwrap(), -2.0 * b);
+        assert!(grad
+            .iter()
+            .all(|d| d.var_names() == ["z", "a", "b"] && d.var_order() == VarOrder::Occurrence));
+        let bound = expr.bind(&[(1, a)]).unwrap();
+        assert_eq!(bound.var_names(), ["z", "b"]);
+        assert_float_eq_f64(bound.eval(&[z, b]).unwrap(), 6.0);
+        let extracted = expr.extract_subexprs(|info| info.n_nodes == 2).unwrap();
+        let (a_z, mapping) = &extracted[0];
+        assert_eq!(a_z.var_names(), ["z", "a"]);
+        assert_eq!(mapping, &vec![0, 1]);
+
+        // variables of other come after the ones of self
+        let other = parse_with_options::<f64>("y*a", &ops, &occurrence).unwrap();
+        let combined = expr.clone().combine(other.clone(), "+").unwrap();
+        assert_eq!(combined.var_names(), ["z", "a", "b", "y"]);
+        assert_float_eq_f64(combined.eval(&[z, a, b, 0.5]).unwrap(), 7.0);
+        let combined = alphabetical.combine(other.clone(), "+").unwrap();
+        assert_eq!(combined.var_names(), ["a", "b", "y", "z"]);
+        let jac = jacobian(&[expr, other]).unwrap();
+        assert_float_eq_f64(jac[1][1].eval(&[z, a, b, 0.5]).unwrap(), 0.5);
+        assert_float_eq_f64(jac[1][3].eval(&[z, a, b, 0.5]).unwrap(), a);
+    }
+
+    #[test]
+    fn test_tokenize_and_check() {
+        let ops = make_default_operators::<f64>();
+        let tokens = tokenize("log2(x_1)^-.5, {a b}", &ops).unwrap();
+        let summary = tokens
+            .iter()
+            .map(|t| (t.category, t.text, t.span.start, t.span.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (TokenCategory::Operator, "log2", 0, 4),
+                (TokenCategory::OpenParen, "(", 4, 5),
+                (TokenCategory::Variable, "x_1", 5, 8),
+                (TokenCategory::CloseParen, ")", 8, 9),
+                (TokenCategory::Operator, "^", 9, 10),
+                (TokenCategory::Operator, "-", 10, 11),
+                (TokenCategory::Number, ".5", 11, 13),
+                (TokenCategory::Comma, ",", 13, 14),
+                (TokenCategory::Variable, "{a b}", 15, 20),
+            ]
+        );
+        assert!(tokenize("", &ops).unwrap().is_empty());
+        assert_eq!(tokenize("x § y", &ops).unwrap_err().code(), "E002");
+        assert_eq!(tokenize("x + {y", &ops).unwrap_err().code(), "E009");
+
+        let codes_and_positions = |text| match check(text, &ops) {
+            Ok(()) => vec![],
+            Err(errors) => errors
+                .iter()
+                .map(|err| (err.code(), err.position()))
+                .collect::<Vec<_>>(),
+        };
+        assert_eq!(codes_and_positions("sin(x)*(y+2)"), []);
+        assert_eq!(codes_and_positions(""), [("E001", None)]);
+        assert_eq!(
+            codes_and_positions("2 x + (*y) + )"),
+            [
+                ("E014", Some(2)),
+                ("E011", Some(7)),
+                ("E012", Some(13)),
+                ("E003", Some(13)),
+            ]
+        );
+        assert_eq!(
+            codes_and_positions("((x # y) + sin ~ 3"),
+            [("E003", Some(0)), ("E002", Some(4)), ("E002", Some(15))]
+        );
+        assert_eq!(codes_and_positions("x + {y"), [("E009", Some(4))]);
+        // the first error is the one parsing reports
+        for text in ["2 x + (*y) + )", "((x # y) + sin ~ 3", "x + {y", "1 +"] {
+            let parse_err = parse(text, &ops).unwrap_err();
+            let check_errs = check(text, &ops).unwrap_err();
+            assert!(check_errs.contains(&parse_err));
+        }
+        let mut dup_ops = ops.to_vec();
+        dup_ops.push(ops[0]);
+        assert_eq!(check("2 x )", &dup_ops).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        let ops = make_default_operators::<f64>();
+        let text = "sin(x) y + (2 * ) + 3 +";
+        let summary = validate(text, &ops)
+            .iter()
+            .map(|issue| {
+                let span = issue.span.unwrap();
+                assert_eq!(issue.message, issue.error.to_string());
+                (
+                    issue.error.code(),
+                    issue.tokens,
+                    &text[span.start..span.end],
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("E015", Some((3, 4)), ") y"),
+                ("E012", Some((8, 9)), "* )"),
+                ("E004", Some((12, 12)), "+"),
+            ]
+        );
+        // parsing still fails fast with the first issue
+        assert_eq!(
+            parse(text, &ops).unwrap_err(),
+            validate(text, &ops)[0].error
+        );
+
+        // unclosed parentheses and skipped characters are reported at their positions
+        let text = "((x # y) + sin ~ 3";
+        let summary = validate(text, &ops)
+            .iter()
+            .map(|issue| (issue.error.code(), issue.tokens, issue.span))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("E003", Some((0, 0)), Some(Span { start: 0, end: 1 })),
+                ("E002", None, Some(Span { start: 4, end: 5 })),
+                ("E002", None, Some(Span { start: 15, end: 16 })),
+            ]
+        );
+        let issues = validate("logb(2) + 1", &ops);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].tokens, Some((0, 3)));
+        assert_eq!(validate("", &ops)[0].span, None);
+        assert!(validate("sin(x) * y", &ops).is_empty());
+    }
+
+    #[test]
+    fn test_factory() {
+        use crate::{parse_with_factory, DefaultOpsFactory, MakeOperators};
+        struct Extended;
+        impl MakeOperators<f64> for Extended {
+            fn make() -> Vec<Operator<'static, f64>> {
+                let mut ops = make_default_operators::<f64>().to_vec();
+                ops.push(Operator {
+                    repr: "sq",
+                    bin_op: None,
+                    unary_op: Some(|a| a * a),
+                    domain_check: None,
+                    unary_position: UnaryPosition::Prefix,
+                    nary_op: None,
+                    fallible: None,
+                    aliases: &[],
+                });
+                ops
+            }
+        }
+        struct WithoutCos;
+        impl MakeOperators<f64> for WithoutCos {
+            fn make() -> Vec<Operator<'static, f64>> {
+                make_default_operators::<f64>()
+                    .iter()
+                    .filter(|op| op.repr != "cos")
+                    .copied()
+                    .collect()
+            }
+        }
+
+        let expr = parse_with_factory::<f64, DefaultOpsFactory<f64>>("sin(x)*y").unwrap();
+        let default = parse_with_default_ops("sin(x)*y").unwrap();
+        assert_eq!(expr.structure_eq(&default, false), Ok(true));
+        let d_xx = expr.partial(0).unwrap().partial(0).unwrap();
+        assert_float_eq_f64(d_xx.eval(&[0.5, 2.0]).unwrap(), -2.0 * 0.5f64.sin());
+
+        let expr = parse_with_factory::<f64, Extended>("sq(x) + 2*y").unwrap();
+        assert_float_eq_f64(expr.eval(&[3.0, 0.5]).unwrap(), 10.0);
+        assert!(parse_with_default_ops::<f64>("sq(x) + 2*y").is_err());
+        let expr = parse_with_factory::<f64, Extended>("sq(3)*y^2").unwrap();
+        let d_y = expr.partial(0).unwrap();
+        assert_float_eq_f64(d_y.eval(&[0.5]).unwrap(), 9.0);
+
+        // derivatives and derived expressions use the operators of the factory
+        let expr = parse_with_factory::<f64, WithoutCos>("sin(x)*y").unwrap();
+        assert_eq!(
+            expr.clone().partial(0),
+            Err(ExError::OperatorNotFound("cos".to_string()))
+        );
+        let bound = expr.bind(&[(1, 2.0)]).unwrap();
+        assert_eq!(
+            bound.partial(0),
+            Err(ExError::OperatorNotFound("cos".to_string()))
+        );
+        let d_y = expr.partial_with_ops(1, &make_default_operators()).unwrap();
+        assert_float_eq_f64(d_y.eval(&[0.5, 3.0]).unwrap(), 0.5f64.sin());
+        assert_eq!(
+            d_y.partial(0),
+            Err(ExError::OperatorNotFound("cos".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_eval_str_with_limits() {
+        let limits = ParseLimits {
+            max_len: Some(1 << 20),
+            max_tokens: Some(10_000),
+            max_nodes: Some(1000),
+            ..ParseLimits::default()
+        };
+        for (text, reference) in [
+            ("sin(pi/2)+2*3", 7.0),
+            ("(1+2)^2-e^0", 8.0),
+            ("sum(i, 1, 4, i)", 10.0),
+        ]
+        .iter()
+        {
+            assert_float_eq_f64(eval_str_with_limits(text, limits).unwrap(), *reference);
+            assert_float_eq_f64(eval_str(text).unwrap(), *reference);
+        }
+
+        // 10 MB of input
+        let huge = format!("{}1", "1+".repeat(5_000_000));
+        assert_eq!(
+            eval_str_with_limits(&huge, limits),
+            Err(ExError::InputTooLong {
+                len: 10_000_001,
+                max: 1 << 20
+            })
+        );
+        // without a length limit, tokenizing stops at the first token beyond the limit
+        let only_tokens = ParseLimits {
+            max_tokens: Some(10_000),
+            ..ParseLimits::default()
+        };
+        match eval_str_with_limits(&huge, only_tokens) {
+            Err(ExError::TooManyTokens { position, max, .. }) => {
+                assert_eq!(position, 10_000);
+                assert_eq!(max, 10_000);
+            }
+            res => panic!("expected too many tokens, got {:?}", res),
+        }
+        let only_nodes = ParseLimits {
+            max_nodes: Some(1000),
+            ..ParseLimits::default()
+        };
+        match eval_str_with_limits(&huge, only_nodes) {
+            Err(err @ ExError::TooManyNodes { .. }) => assert_eq!(err.code(), "E039"),
+            res => panic!("expected too many nodes, got {:?}", res),
+        }
     }
 }
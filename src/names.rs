@@ -0,0 +1,319 @@
+use alloc::borrow::Cow;
+
+use smallvec::SmallVec;
+
+use crate::{alloc_prelude::*, definitions::N_VARS_ON_STACK, ExError};
+
+/// Decides what happens if a name-based API receives the same variable more than once.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// An error is returned that lists all conflicting entries.
+    Error,
+    /// The last of the conflicting entries is used.
+    LastWins,
+    /// The first of the conflicting entries is used.
+    FirstWins,
+}
+
+/// Policy that defines how names passed by the user are matched against the variable names
+/// of an expression. All name-based APIs such as [`eval_named`](crate::FlatEx::eval_named) and
+/// [`eval_rows`](crate::FlatEx::eval_rows) share this policy.
+///
+/// Independent of the policy, curly braces around a passed name are removed before matching,
+/// i.e., `{x}` and `x` denote the same variable. By default, the remainder needs to match
+/// exactly and passing a variable twice is an error.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, NameResolution, OnDuplicate};
+/// let expr = parse_with_default_ops::<f64>("x/y")?;
+/// let resolution = NameResolution {
+///     trim_whitespace: true,
+///     case_insensitive: true,
+///     on_duplicate: OnDuplicate::LastWins,
+/// };
+/// let header = [" X", "y ", "x"];
+/// let rows = [[1.0, 4.0, 2.0], [1.0, 8.0, 4.0]];
+/// assert_eq!(expr.eval_rows(&header, &rows, &resolution)?, vec![0.5, 0.5]);
+/// assert!(expr.eval_rows(&header, &rows, &NameResolution::default()).is_err());
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NameResolution {
+    /// Leading and trailing whitespace of passed names is ignored, e.g., for CSV headers.
+    pub trim_whitespace: bool,
+    /// Names differing only by case denote the same variable.
+    pub case_insensitive: bool,
+    /// What to do if a variable is passed more than once.
+    pub on_duplicate: OnDuplicate,
+}
+
+impl Default for NameResolution {
+    fn default() -> Self {
+        NameResolution {
+            trim_whitespace: false,
+            case_insensitive: false,
+            on_duplicate: OnDuplicate::Error,
+        }
+    }
+}
+
+impl NameResolution {
+    /// Returns the form of `name` that is used for matching.
+    pub fn normalize<'b>(&self, name: &'b str) -> Cow<'b, str> {
+        let name = if self.trim_whitespace {
+            name.trim()
+        } else {
+            name
+        };
+        let name = if name.len() > 2 && name.starts_with('{') && name.ends_with('}') {
+            &name[1..name.len() - 1]
+        } else {
+            name
+        };
+        if self.case_insensitive {
+            Cow::Owned(name.to_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Finds for each of the `var_names` of an expression the position of the matching
+    /// entry in `passed_names`. Variables without a matching entry are `None`.
+    ///
+    /// # Errors
+    ///
+    /// An [`ExError`](ExError) is returned if
+    /// * two variables of the expression cannot be distinguished under this policy,
+    /// * a passed name is not a variable of the expression, or
+    /// * a variable is passed more than once and duplicates are errors.
+    pub fn resolve(
+        &self,
+        var_names: &[&str],
+        passed_names: &[&str],
+    ) -> Result<SmallVec<[Option<usize>; N_VARS_ON_STACK]>, ExError> {
+        let normalized_vars = var_names
+            .iter()
+            .map(|name| self.normalize(name))
+            .collect::<SmallVec<[_; N_VARS_ON_STACK]>>();
+        for (i, name) in normalized_vars.iter().enumerate() {
+            if let Some(j) = normalized_vars[..i].iter().position(|other| other == name) {
+                return Err(ExError::IndistinguishableVars {
+                    first: var_names[j].to_string(),
+                    second: var_names[i].to_string(),
+                });
+            }
+        }
+
+        let mut positions: SmallVec<[SmallVec<[usize; 2]>; N_VARS_ON_STACK]> =
+            (0..var_names.len()).map(|_| SmallVec::new()).collect();
+        for (pos, passed) in passed_names.iter().enumerate() {
+            let passed_normalized = self.normalize(passed);
+            match normalized_vars
+                .iter()
+                .position(|name| *name == passed_normalized)
+            {
+                Some(var_idx) => positions[var_idx].push(pos),
+                None => {
+                    return Err(ExError::UnknownVar {
+                        name: passed.to_string(),
+                        available: var_names.iter().map(|name| name.to_string()).collect(),
+                    })
+                }
+            }
+        }
+
+        positions
+            .iter()
+            .enumerate()
+            .map(|(var_idx, found)| match (found.len(), self.on_duplicate) {
+                (0, _) => Ok(None),
+                (1, _) | (_, OnDuplicate::FirstWins) => Ok(Some(found[0])),
+                (_, OnDuplicate::LastWins) => Ok(Some(found[found.len() - 1])),
+                (_, OnDuplicate::Error) => Err(ExError::DuplicateVar {
+                    name: var_names[var_idx].to_string(),
+                    passed: found
+                        .iter()
+                        .map(|pos| (passed_names[*pos].to_string(), *pos))
+                        .collect(),
+                }),
+            })
+            .collect()
+    }
+
+    /// Like [`resolve`](NameResolution::resolve) but each variable of the expression needs a
+    /// matching entry.
+    ///
+    /// # Errors
+    ///
+    /// Additionally to the errors of [`resolve`](NameResolution::resolve), an
+    /// [`ExError`](ExError) is returned if a variable has not been passed.
+    pub fn resolve_all(
+        &self,
+        var_names: &[&str],
+        passed_names: &[&str],
+    ) -> Result<SmallVec<[usize; N_VARS_ON_STACK]>, ExError> {
+        self.resolve(var_names, passed_names)?
+            .iter()
+            .enumerate()
+            .map(|(var_idx, pos)| {
+                pos.ok_or_else(|| ExError::MissingVar(var_names[var_idx].to_string()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{parse_with_default_ops, util::assert_float_eq_f64};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_normalize() {
+    let exact = NameResolution::default();
+    assert_eq!(exact.normalize("{x}"), "x");
+    assert_eq!(exact.normalize(" X "), " X ");
+    assert_eq!(exact.normalize("{}"), "{}");
+    let relaxed = NameResolution {
+        trim_whitespace: true,
+        case_insensitive: true,
+        on_duplicate: OnDuplicate::Error,
+    };
+    assert_eq!(relaxed.normalize(" {X} "), "x");
+    assert_eq!(relaxed.normalize("Alpha\t"), "alpha");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_resolution_flags() {
+    let flatex = parse_with_default_ops::<f64>("x - y").unwrap();
+    let eval_both = |names: &[&str], values: &[f64], resolution: &NameResolution| {
+        let named = names
+            .iter()
+            .copied()
+            .zip(values.iter().copied())
+            .collect::<Vec<_>>();
+        let res_named = flatex.eval_named(&named, resolution);
+        let res_rows = flatex
+            .eval_rows(names, &[values], resolution)
+            .map(|res| res[0]);
+        match (res_named, res_rows) {
+            (Ok(a), Ok(b)) => {
+                assert_float_eq_f64(a, b);
+                Ok(a)
+            }
+            (Err(a), Err(b)) => {
+                assert_eq!(a, b);
+                Err(a)
+            }
+            (a, b) => panic!("eval_named {:?} and eval_rows {:?} disagree", a, b),
+        }
+    };
+
+    let exact = NameResolution::default();
+    assert_float_eq_f64(eval_both(&["y", "x"], &[1.0, 3.0], &exact).unwrap(), 2.0);
+    assert_float_eq_f64(eval_both(&["{y}", "x"], &[1.0, 3.0], &exact).unwrap(), 2.0);
+    let err = eval_both(&["y", "X"], &[1.0, 3.0], &exact).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::UnknownVar {
+            name: "X".to_string(),
+            available: vec!["x".to_string(), "y".to_string()]
+        }
+    );
+    assert_eq!(err.code(), "E041");
+    assert!(eval_both(&["y", " x"], &[1.0, 3.0], &exact).is_err());
+    let err = eval_both(&["y"], &[1.0], &exact).unwrap_err();
+    assert_eq!(err, ExError::MissingVar("x".to_string()));
+
+    let trim = NameResolution {
+        trim_whitespace: true,
+        ..NameResolution::default()
+    };
+    assert_float_eq_f64(eval_both(&["y ", "\tx"], &[1.0, 3.0], &trim).unwrap(), 2.0);
+    assert!(eval_both(&["Y", "x"], &[1.0, 3.0], &trim).is_err());
+
+    let case = NameResolution {
+        case_insensitive: true,
+        ..NameResolution::default()
+    };
+    assert_float_eq_f64(eval_both(&["Y", "{X}"], &[1.0, 3.0], &case).unwrap(), 2.0);
+    assert!(eval_both(&["Y ", "X"], &[1.0, 3.0], &case).is_err());
+
+    let names = ["x", "y", "x"];
+    let values = [3.0, 1.0, 5.0];
+    let err = eval_both(&names, &values, &exact).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::DuplicateVar {
+            name: "x".to_string(),
+            passed: vec![("x".to_string(), 0), ("x".to_string(), 2)]
+        }
+    );
+    assert_eq!(err.code(), "E042");
+    let first = NameResolution {
+        on_duplicate: OnDuplicate::FirstWins,
+        ..NameResolution::default()
+    };
+    assert_float_eq_f64(eval_both(&names, &values, &first).unwrap(), 2.0);
+    let last = NameResolution {
+        on_duplicate: OnDuplicate::LastWins,
+        ..NameResolution::default()
+    };
+    assert_float_eq_f64(eval_both(&names, &values, &last).unwrap(), 4.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_eval_with_default() {
+    let flatex = parse_with_default_ops::<f64>("x + y + z").unwrap();
+    let exact = NameResolution::default();
+    let eval = |vars: &[(&str, f64)], default: f64| flatex.eval_with_default(vars, default, &exact);
+    assert_float_eq_f64(eval(&[("x", 2.5)], 0.0).unwrap(), 2.5);
+    assert_float_eq_f64(eval(&[("z", 1.0), ("x", 2.5)], 0.5).unwrap(), 4.0);
+    assert_float_eq_f64(eval(&[], 1.0).unwrap(), 3.0);
+    assert!(eval(&[("x", 2.5)], f64::NAN).unwrap().is_nan());
+    let err = eval(&[("x", 2.5), ("w", 1.0)], 0.0).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::UnknownVar {
+            name: "w".to_string(),
+            available: vec!["x".to_string(), "y".to_string(), "z".to_string()]
+        }
+    );
+    assert!(eval(&[("x", 2.5), ("x", 1.0)], 0.0).is_err());
+    let case = NameResolution {
+        case_insensitive: true,
+        ..NameResolution::default()
+    };
+    let res = flatex.eval_with_default(&[("Y", 2.0)], 0.0, &case);
+    assert_float_eq_f64(res.unwrap(), 2.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_indistinguishable_vars() {
+    let flatex = parse_with_default_ops::<f64>("x + X").unwrap();
+    assert_float_eq_f64(
+        flatex
+            .eval_named(&[("x", 1.0), ("X", 2.0)], &NameResolution::default())
+            .unwrap(),
+        3.0,
+    );
+    let case = NameResolution {
+        case_insensitive: true,
+        ..NameResolution::default()
+    };
+    let err = flatex.eval_named(&[("x", 1.0)], &case).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::IndistinguishableVars {
+            first: "X".to_string(),
+            second: "x".to_string()
+        }
+    );
+    assert!(flatex.eval_rows(&["x"], &[[1.0]], &case).is_err());
+}
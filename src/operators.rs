@@ -1,38 +1,209 @@
+use crate::alloc_prelude::*;
+#[cfg(feature = "std")]
+use core::{
+    marker::PhantomData,
+    ops::{Add, Div, Mul, Neg, Range, Rem, Sub},
+};
+#[cfg(feature = "std")]
 use num::Float;
+use num::{
+    traits::{CheckedRem, WrappingAdd, WrappingMul, WrappingNeg, WrappingSub},
+    PrimInt,
+};
 use smallvec::{smallvec, SmallVec};
 
-/// Operators can be custom-defined by the library-user in terms of this struct.
+/// Operators can be custom-defined by the library-user in terms of this struct. The
+/// constructors [`make_bin`](Operator::make_bin), [`make_unary`](Operator::make_unary),
+/// [`make_bin_unary`](Operator::make_bin_unary), and [`make_nary`](Operator::make_nary)
+/// leave all other fields at their defaults. Further fields can be set with the struct
+/// update syntax, which also works with [`Default::default()`].
 ///
 /// # Examples
 ///
 /// ```
-/// use exmex::{BinOp, Operator};
+/// use exmex::prelude::*;
 /// let ops = vec![
-///     Operator {
-///         repr: "-",
-///         bin_op: Some(BinOp {
+///     Operator::make_bin_unary(
+///         "-",
+///         BinOp {
 ///             apply: |a, b| a - b,
 ///             prio: 0,
-///         }),
-///         unary_op: Some(|a: f32| (-a)),
+///         },
+///         |a: f32| -a,
+///     ),
+///     Operator::make_unary("sin", |a: f32| a.sin()),
+///     Operator::make_nary(
+///         "clamp",
+///         NaryOp {
+///             apply: |args: &[f32]| args[0].max(args[1]).min(args[2]),
+///             arity: 3,
+///         },
+///     ),
+///     Operator {
+///         aliases: &["**"],
+///         ..Operator::make_bin(
+///             "^",
+///             BinOp {
+///                 apply: |a: f32, b| a.powf(b),
+///                 prio: 1,
+///             },
+///         )
 ///     },
 ///     Operator {
-///         repr: "sin",
-///         bin_op: None,
-///         unary_op: Some(|a: f32| a.sin()),
-///     }
+///         repr: "!",
+///         unary_op: Some(|a: f32| if a == 0.0 { 1.0 } else { 0.0 }),
+///         unary_position: UnaryPosition::Postfix,
+///         ..Default::default()
+///     },
 /// ];
+/// let expr = parse::<f32>("-clamp(sin(x), 0, 1)**2", &ops)?;
+/// assert_eq!(expr.eval(&[0.0])?, -0.0);
+/// # Ok::<(), ExError>(())
 /// ```
 ///
+// The derived comparisons compare the function pointers by address, which can differ for
+// the same function across codegen units. This is fine since operators are only compared
+// to copies of themselves, e.g., in expressions parsed with the same operator list.
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct Operator<'a, T> {
     /// Representation of the operator in the string to be parsed, e.g., `-` or `sin`.
+    /// Representations that consist of ASCII letters, digits, and underscores are only found
+    /// if they are not followed by such a character, e.g., an operator `e` is not the
+    /// beginning of a variable `energy`.
     pub repr: &'a str,
     /// Binary operator that contains a priority besides a function pointer, if available.
     pub bin_op: Option<BinOp<T>>,
     /// Unary operator that does not have an explicit priority. Unary operators have
     /// higher priority than binary opertors, e.g., `-1^2 == 1`.
     pub unary_op: Option<fn(T) -> T>,
+    /// Returns the reason why a value is not in the domain of the unary operator, if so.
+    /// Applied to constant arguments during parsing with
+    /// [`strict_domains`](crate::ParseOptions::strict_domains).
+    pub domain_check: Option<fn(T) -> Option<String>>,
+    /// Side of its operand the unary operator is written on.
+    pub unary_position: UnaryPosition,
+    /// Operator with a fixed number of arguments that is called like a function, e.g.,
+    /// `clamp(x, 0, 1)`. An operator with an n-ary variant is always parsed as such a
+    /// call, hence, its binary and unary variants should be `None`.
+    pub nary_op: Option<NaryOp<T>>,
+    /// Variants of the binary and the unary operation that report errors, e.g., on
+    /// overflow, see [`FallibleOp`](FallibleOp).
+    pub fallible: Option<FallibleOp<T>>,
+    /// Further representations of the operator in the string to be parsed, e.g., `**` for
+    /// `^`. They are found like [`repr`](Operator::repr), also longer ones first, whereas
+    /// unparsing and derivatives always use [`repr`](Operator::repr).
+    pub aliases: &'a [&'a str],
+}
+
+impl<'a, T> Operator<'a, T> {
+    const fn make_empty(repr: &'a str) -> Self {
+        Operator {
+            repr,
+            bin_op: None,
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        }
+    }
+
+    /// Creates an operator that is only binary.
+    pub const fn make_bin(repr: &'a str, bin_op: BinOp<T>) -> Self {
+        Operator {
+            bin_op: Some(bin_op),
+            ..Self::make_empty(repr)
+        }
+    }
+
+    /// Creates an operator that is only unary and written on the left of its operand.
+    pub const fn make_unary(repr: &'a str, unary_op: fn(T) -> T) -> Self {
+        Operator {
+            unary_op: Some(unary_op),
+            ..Self::make_empty(repr)
+        }
+    }
+
+    /// Creates an operator that is binary and unary, e.g., `-`.
+    pub const fn make_bin_unary(repr: &'a str, bin_op: BinOp<T>, unary_op: fn(T) -> T) -> Self {
+        Operator {
+            bin_op: Some(bin_op),
+            unary_op: Some(unary_op),
+            ..Self::make_empty(repr)
+        }
+    }
+
+    /// Creates an operator that is called with a fixed number of arguments, e.g.,
+    /// `clamp(x, 0, 1)`.
+    pub const fn make_nary(repr: &'a str, nary_op: NaryOp<T>) -> Self {
+        Operator {
+            nary_op: Some(nary_op),
+            ..Self::make_empty(repr)
+        }
+    }
+
+    /// Returns whether the operator can be used as postfix unary operator.
+    pub fn is_postfix(&self) -> bool {
+        self.unary_op.is_some() && self.unary_position == UnaryPosition::Postfix
+    }
+
+    /// Sets the priority of the binary operator to the midpoint of `lower` and `upper`,
+    /// e.g., between [`PRIO_MUL`](PRIO_MUL) and [`PRIO_POW`](PRIO_POW) for an operator
+    /// that binds stronger than `*` but weaker than `^`. The midpoint is rounded down,
+    /// hence, it is only strictly between the bounds if they differ by at least 2.
+    /// Operators without binary variant are returned unchanged.
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// #
+    /// use exmex::{prelude::*, PRIO_MUL, PRIO_POW};
+    /// let mut ops = make_default_operators::<f64>().to_vec();
+    /// ops.push(
+    ///     Operator::make_bin("max", BinOp { apply: f64::max, prio: 0 })
+    ///         .with_prio_between(PRIO_MUL, PRIO_POW),
+    /// );
+    /// // 2 * (1 max 3)
+    /// assert_eq!(parse("2 * 1 max 3", &ops)?.eval(&[])?, 6.0);
+    /// // 2 max (1^3)
+    /// assert_eq!(parse("2 max 1^3", &ops)?.eval(&[])?, 2.0);
+    /// #
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_prio_between(mut self, lower: i32, upper: i32) -> Self {
+        if let Some(bin_op) = &mut self.bin_op {
+            let sum = i64::from(lower) + i64::from(upper);
+            bin_op.prio = sum.div_euclid(2) as i32;
+        }
+        self
+    }
+}
+
+/// An operator without representation and operations, such that only the fields that
+/// differ need to be set, e.g., `Operator { repr: "!", unary_op: Some(|a: u32| !a),
+/// ..Default::default() }`.
+impl<'a, T> Default for Operator<'a, T> {
+    fn default() -> Self {
+        Self::make_empty("")
+    }
+}
+
+/// Side of its operand a unary operator is written on.
+///
+/// A postfix operator, e.g., a factorial `3!`, is applied to the directly preceding number,
+/// variable, or parenthesized expression before prefix operators are applied, e.g., `-3!` is
+/// `-(3!)`. An operator that is postfix and binary is used as postfix operator if it is not
+/// followed by an operand, e.g., `3!-2` is `(3!)-2` but `3!2` uses the binary operator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnaryPosition {
+    /// The operator is on the left of its operand, e.g., `-x`.
+    #[default]
+    Prefix,
+    /// The operator is on the right of its operand, e.g., `x!`.
+    Postfix,
 }
 
 pub type VecOfUnaryFuncs<T> = SmallVec<[fn(T) -> T; 8]>;
@@ -63,7 +234,7 @@ impl<T> UnaryOp<T> {
             .funcs_to_be_composed
             .iter()
             .chain(self.funcs_to_be_composed.iter())
-            .map(|f| *f)
+            .copied()
             .collect::<SmallVec<_>>();
     }
 
@@ -86,9 +257,23 @@ impl<T> UnaryOp<T> {
     pub fn clear(&mut self) {
         self.funcs_to_be_composed.clear();
     }
+
+    /// Returns the functions in the order they are composed, i.e., the last one is applied
+    /// first.
+    pub fn funcs(&self) -> &[fn(T) -> T] {
+        &self.funcs_to_be_composed
+    }
+
+    /// Returns the operators with indices in `range`, which are composed in the same order.
+    #[cfg(feature = "std")]
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        Self::from_vec(self.funcs_to_be_composed[range].iter().copied().collect())
+    }
 }
 
 /// A binary operator that consists of a function pointer and a priority.
+// compared by address like the function pointers of `Operator`
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct BinOp<T> {
     /// Implementation of the binary operation, e.g., `|a, b| a * b` for multiplication.
@@ -96,142 +281,865 @@ pub struct BinOp<T> {
     /// Priority of the binary operation. A binary operation with a
     /// higher number will be executed first. For instance, in a sane world `*`
     /// has a higher priority than `+`. However, in Exmex land you could also define
-    /// this differently.
+    /// this differently. Any `i32` is a valid priority, including zero and negative
+    /// values, since only the order of the priorities matters. Operators with equal
+    /// priorities are applied from left to right.
     pub prio: i32,
 }
 
-/// Returns the default operators.
-pub fn make_default_operators<'a, T: Float>() -> [Operator<'a, T>; 23] {
+/// Fallible variants of the operations of an [`Operator`](Operator) that return the reason
+/// of a failure instead of a value, e.g., for integer division by zero or overflow. A
+/// failure is reported by [`eval`](crate::FlatEx::eval) and the other evaluation methods
+/// that return a [`Result`] as [`ExError::OperatorFailed`](crate::ExError::OperatorFailed).
+///
+/// Each fallible variant needs the corresponding infallible operation of the operator,
+/// which defines the priority and is used wherever an error cannot be reported, e.g., by
+/// [`eval_unchecked`](crate::FlatEx::eval_unchecked),
+/// [`eval_vectorized`](crate::FlatEx::eval_vectorized), [`into_fn`](crate::FlatEx::into_fn),
+/// or [`OwnedFlatEx`](crate::OwnedFlatEx).
+/// Constants of expressions with fallible operators are not folded during parsing such that
+/// failures of constant sub-expressions are reported on evaluation, too.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let mut ops = make_default_operators_int::<i64>();
+/// for op in ops.iter_mut().filter(|op| op.repr == "*") {
+///     op.fallible = Some(FallibleOp {
+///         bin_op: Some(|a: i64, b| a.checked_mul(b).ok_or_else(|| "overflow".to_string())),
+///         unary_op: None,
+///     });
+/// }
+/// let expr = parse_with_number_pattern::<i64>("a * b", &ops, "[0-9]+")?;
+/// assert_eq!(expr.eval(&[7, 2])?, 14);
+/// match expr.eval(&[i64::MAX, 2]) {
+///     Err(ExError::OperatorFailed { op, reason, .. }) => {
+///         assert_eq!(op, "*");
+///         assert_eq!(reason, "overflow");
+///     }
+///     _ => panic!("expected a failure"),
+/// }
+/// // unchecked evaluation uses the infallible, wrapping multiplication
+/// assert_eq!(expr.eval_unchecked(&[i64::MAX, 2]), -2);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+// compared by address like the function pointers of `Operator`
+#[allow(unpredictable_function_pointer_comparisons, clippy::type_complexity)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct FallibleOp<T> {
+    /// Fallible variant of [`Operator::bin_op`](Operator::bin_op).
+    pub bin_op: Option<fn(T, T) -> Result<T, String>>,
+    /// Fallible variant of [`Operator::unary_op`](Operator::unary_op).
+    pub unary_op: Option<fn(T) -> Result<T, String>>,
+}
+
+/// Priority of the comparisons `<`, `<=`, `>`, `>=`, `==`, and `!=` of the
+/// [default operators](make_default_operators).
+pub const PRIO_CMP: i32 = 0;
+/// Priority of `+` and `-` of the [default operators](make_default_operators).
+pub const PRIO_ADD: i32 = 10;
+/// Priority of `*`, `/`, and `%` of the [default operators](make_default_operators).
+pub const PRIO_MUL: i32 = 20;
+/// Priority of `^` of the [default operators](make_default_operators). The priorities are
+/// spaced apart such that custom operators fit in between, see
+/// [`Operator::with_prio_between`](Operator::with_prio_between).
+pub const PRIO_POW: i32 = 30;
+
+/// An operator with a fixed number of arguments, see [`Operator::nary_op`](Operator::nary_op).
+// compared by address like the function pointers of `Operator`
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct NaryOp<T> {
+    /// Implementation of the operation. The slice contains the evaluated arguments in the
+    /// order they are written and has length `arity`.
+    pub apply: fn(&[T]) -> T,
+    /// Number of arguments the operator expects. Calls with a different number of
+    /// arguments are parse errors.
+    pub arity: usize,
+}
+
+/// Number types the [default operators](make_default_operators) are defined for. It is
+/// implemented for all types that implement [`Float`](num::Float) and, with the feature
+/// `simd`, for the vectors of [`simd`](crate::simd) that apply each operator lane-wise.
+///
+/// The methods are named like the ones of [`Float`](num::Float). Hence, importing this
+/// trait in a scope with `Float`-bounded generics makes method calls ambiguous.
+#[cfg(feature = "std")]
+pub trait DefaultOpsFloat:
+    Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + Neg<Output = Self>
+{
+    /// Converts a number, e.g., a constant, to this type.
+    fn from_f64(x: f64) -> Self;
+    /// Returns one where `self < other` holds and zero otherwise.
+    fn indicator_lt(self, other: Self) -> Self;
+    /// Returns one where `self <= other` holds and zero otherwise.
+    fn indicator_le(self, other: Self) -> Self;
+    /// Returns one where `self == other` holds and zero otherwise.
+    fn indicator_eq(self, other: Self) -> Self;
+    /// Returns whether `self`, or one of its lanes, is not zero.
+    fn any_nonzero(self) -> bool;
+    fn powf(self, exponent: Self) -> Self;
+    fn log(self, base: Self) -> Self;
+    fn signum(self) -> Self;
+    fn abs(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn asin(self) -> Self;
+    fn acos(self) -> Self;
+    fn atan(self) -> Self;
+    fn sinh(self) -> Self;
+    fn cosh(self) -> Self;
+    fn tanh(self) -> Self;
+    fn asinh(self) -> Self;
+    fn acosh(self) -> Self;
+    fn atanh(self) -> Self;
+    fn floor(self) -> Self;
+    fn ceil(self) -> Self;
+    fn trunc(self) -> Self;
+    fn fract(self) -> Self;
+    fn round(self) -> Self;
+    fn exp(self) -> Self;
+    fn exp2(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn cbrt(self) -> Self;
+    fn ln(self) -> Self;
+    fn log2(self) -> Self;
+    fn log10(self) -> Self;
+}
+
+/// Implements unary methods of [`DefaultOpsFloat`](DefaultOpsFloat) by the equally named
+/// methods of `$trait_`.
+#[cfg(feature = "std")]
+macro_rules! forward_unary {
+    ($trait_:ident; $($name:ident),*) => {
+        $(
+            fn $name(self) -> Self {
+                $trait_::$name(self)
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "std")]
+impl<T: Float> DefaultOpsFloat for T {
+    fn from_f64(x: f64) -> Self {
+        T::from(x).unwrap()
+    }
+    fn indicator_lt(self, other: Self) -> Self {
+        indicator(self < other)
+    }
+    fn indicator_le(self, other: Self) -> Self {
+        indicator(self <= other)
+    }
+    fn indicator_eq(self, other: Self) -> Self {
+        indicator(self == other)
+    }
+    fn any_nonzero(self) -> bool {
+        self != T::zero()
+    }
+    fn powf(self, exponent: Self) -> Self {
+        Float::powf(self, exponent)
+    }
+    fn log(self, base: Self) -> Self {
+        Float::log(self, base)
+    }
+    forward_unary!(
+        Float; signum, abs, sin, cos, tan, asin, acos, atan, sinh, cosh, tanh, asinh,
+        acosh, atanh, floor, ceil, trunc, fract, round, exp, exp2, sqrt, cbrt, ln, log2, log10
+    );
+}
+
+#[cfg(feature = "std")]
+fn domain_log<T: DefaultOpsFloat>(a: T) -> Option<String> {
+    if a.indicator_le(T::from_f64(0.0)).any_nonzero() {
+        Some("the logarithm is only defined for positive numbers".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+fn domain_sqrt<T: DefaultOpsFloat>(a: T) -> Option<String> {
+    if a.indicator_lt(T::from_f64(0.0)).any_nonzero() {
+        Some("the square root is only defined for non-negative numbers".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+fn domain_asin_acos<T: DefaultOpsFloat>(a: T) -> Option<String> {
+    if T::from_f64(1.0).indicator_lt(a.abs()).any_nonzero() {
+        Some("the argument needs to be between -1 and 1".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+fn domain_acosh<T: DefaultOpsFloat>(a: T) -> Option<String> {
+    if a.indicator_lt(T::from_f64(1.0)).any_nonzero() {
+        Some("the argument needs to be at least 1".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+fn domain_atanh<T: DefaultOpsFloat>(a: T) -> Option<String> {
+    if T::from_f64(1.0).indicator_le(a.abs()).any_nonzero() {
+        Some("the argument needs to be strictly between -1 and 1".to_string())
+    } else {
+        None
+    }
+}
+
+/// Maps the result of a comparison to one or zero.
+#[cfg(feature = "std")]
+fn indicator<T: Float>(holds: bool) -> T {
+    if holds {
+        T::one()
+    } else {
+        T::zero()
+    }
+}
+
+/// Returns the default constants `pi`, `e`, and `tau` that can be passed to
+/// [`parse_with_constants`](crate::parse_with_constants).
+#[cfg(feature = "std")]
+pub fn make_default_constants<T: DefaultOpsFloat>() -> [(&'static str, T); 3] {
+    [
+        ("pi", T::from_f64(std::f64::consts::PI)),
+        ("e", T::from_f64(std::f64::consts::E)),
+        ("tau", T::from_f64(std::f64::consts::TAU)),
+    ]
+}
+
+/// Returns the default operators. The priorities of the binary operators are
+/// [`PRIO_CMP`](PRIO_CMP), [`PRIO_ADD`](PRIO_ADD), [`PRIO_MUL`](PRIO_MUL), and
+/// [`PRIO_POW`](PRIO_POW).
+///
+/// The comparisons `<`, `<=`, `>`, `>=`, `==`, and `!=` bind weaker than `+` and result in
+/// one if they hold and zero otherwise, e.g., for piecewise definitions.
+///
+/// Unlike `signum`, `sign` maps zero to zero.
+///
+/// Both `log` and `ln` are the natural logarithm, `log2` and `log10` have the bases 2 and
+/// 10, and `logb(base, x)` has an arbitrary base.
+///
+/// The [aliases](Operator::aliases) `**`, `×`, and `÷` can be used instead of `^`, `*`, and
+/// `/`.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let ops = make_default_operators::<f64>();
+/// let expr = parse("(x > 0) * x + (x <= 0) * -x", &ops)?;
+/// assert_eq!(expr.eval(&[-3.0])?, 3.0);
+/// let expr = parse("logb(3, x) - log10(x)", &ops)?;
+/// assert!((expr.eval(&[9.0])? - (2.0 - 9f64.log10())).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+pub fn make_default_operators<'a, T: DefaultOpsFloat>() -> [Operator<'a, T>; 41] {
     [
         Operator {
-            repr: "^",
-            bin_op: Some(BinOp {
-                apply: |a: T, b| a.powf(b),
-                prio: 2,
-            }),
-            unary_op: None,
+            aliases: &["**"],
+            ..Operator::make_bin(
+                "^",
+                BinOp {
+                    apply: |a: T, b| a.powf(b),
+                    prio: PRIO_POW,
+                },
+            )
         },
         Operator {
-            repr: "*",
-            bin_op: Some(BinOp {
-                apply: |a, b| a * b,
-                prio: 1,
-            }),
-            unary_op: None,
+            aliases: &["×"],
+            ..Operator::make_bin(
+                "*",
+                BinOp {
+                    apply: |a, b| a * b,
+                    prio: PRIO_MUL,
+                },
+            )
         },
         Operator {
-            repr: "/",
-            bin_op: Some(BinOp {
-                apply: |a, b| a / b,
-                prio: 1,
-            }),
-            unary_op: None,
+            aliases: &["÷"],
+            ..Operator::make_bin(
+                "/",
+                BinOp {
+                    apply: |a, b| a / b,
+                    prio: PRIO_MUL,
+                },
+            )
         },
-        Operator {
-            repr: "+",
-            bin_op: Some(BinOp {
+        Operator::make_bin_unary(
+            "+",
+            BinOp {
                 apply: |a, b| a + b,
-                prio: 0,
-            }),
-            unary_op: Some(|a: T| a),
-        },
-        Operator {
-            repr: "-",
-            bin_op: Some(BinOp {
+                prio: PRIO_ADD,
+            },
+            |a: T| a,
+        ),
+        Operator::make_bin_unary(
+            "-",
+            BinOp {
                 apply: |a, b| a - b,
-                prio: 0,
-            }),
-            unary_op: Some(|a: T| (-a)),
-        },
-        Operator {
-            repr: "signum",
-            bin_op: None,
-            unary_op: Some(|a: T| a.signum()),
-        },
+                prio: PRIO_ADD,
+            },
+            |a: T| -a,
+        ),
+        Operator::make_unary("signum", |a: T| a.signum()),
+        Operator::make_unary("sin", |a: T| a.sin()),
+        Operator::make_unary("cos", |a: T| a.cos()),
+        Operator::make_unary("tan", |a: T| a.tan()),
         Operator {
-            repr: "sin",
-            bin_op: None,
-            unary_op: Some(|a: T| a.sin()),
+            domain_check: Some(domain_asin_acos),
+            ..Operator::make_unary("asin", |a: T| a.asin())
         },
         Operator {
-            repr: "cos",
-            bin_op: None,
-            unary_op: Some(|a: T| a.cos()),
+            domain_check: Some(domain_asin_acos),
+            ..Operator::make_unary("acos", |a: T| a.acos())
         },
+        Operator::make_unary("atan", |a: T| a.atan()),
+        Operator::make_unary("sinh", |a: T| a.sinh()),
+        Operator::make_unary("cosh", |a: T| a.cosh()),
+        Operator::make_unary("tanh", |a: T| a.tanh()),
+        Operator::make_unary("asinh", |a: T| a.asinh()),
         Operator {
-            repr: "tan",
-            bin_op: None,
-            unary_op: Some(|a: T| a.tan()),
+            domain_check: Some(domain_acosh),
+            ..Operator::make_unary("acosh", |a: T| a.acosh())
         },
         Operator {
-            repr: "asin",
-            bin_op: None,
-            unary_op: Some(|a: T| a.asin()),
+            domain_check: Some(domain_atanh),
+            ..Operator::make_unary("atanh", |a: T| a.atanh())
         },
+        Operator::make_unary("floor", |a: T| a.floor()),
+        Operator::make_unary("ceil", |a: T| a.ceil()),
+        Operator::make_unary("trunc", |a: T| a.trunc()),
+        Operator::make_unary("fract", |a: T| a.fract()),
+        Operator::make_unary("exp", |a: T| a.exp()),
         Operator {
-            repr: "acos",
-            bin_op: None,
-            unary_op: Some(|a: T| a.acos()),
+            domain_check: Some(domain_sqrt),
+            ..Operator::make_unary("sqrt", |a: T| a.sqrt())
         },
         Operator {
-            repr: "atan",
-            bin_op: None,
-            unary_op: Some(|a: T| a.atan()),
+            domain_check: Some(domain_log),
+            ..Operator::make_unary("log", |a: T| a.ln())
         },
         Operator {
-            repr: "sinh",
-            bin_op: None,
-            unary_op: Some(|a: T| a.sinh()),
+            domain_check: Some(domain_log),
+            ..Operator::make_unary("log2", |a: T| a.log2())
         },
+        Operator::make_bin(
+            "%",
+            BinOp {
+                apply: |a, b| a % b,
+                prio: PRIO_MUL,
+            },
+        ),
+        // zero where a is zero
+        Operator::make_unary("sign", |a: T| {
+            a.signum() * (T::from_f64(1.0) - a.indicator_eq(T::from_f64(0.0)))
+        }),
+        Operator::make_unary("abs", |a: T| a.abs()),
+        Operator::make_unary("round", |a: T| a.round()),
+        Operator::make_unary("exp2", |a: T| a.exp2()),
+        Operator::make_unary("cbrt", |a: T| a.cbrt()),
         Operator {
-            repr: "cosh",
-            bin_op: None,
-            unary_op: Some(|a: T| a.cosh()),
-        },
-        Operator {
-            repr: "tanh",
-            bin_op: None,
-            unary_op: Some(|a: T| a.tanh()),
+            domain_check: Some(domain_log),
+            ..Operator::make_unary("ln", |a: T| a.ln())
         },
         Operator {
-            repr: "floor",
-            bin_op: None,
-            unary_op: Some(|a: T| a.floor()),
-        },
-        Operator {
-            repr: "ceil",
-            bin_op: None,
-            unary_op: Some(|a: T| a.ceil()),
-        },
-        Operator {
-            repr: "trunc",
-            bin_op: None,
-            unary_op: Some(|a: T| a.trunc()),
-        },
-        Operator {
-            repr: "fract",
-            bin_op: None,
-            unary_op: Some(|a: T| a.fract()),
-        },
-        Operator {
-            repr: "exp",
-            bin_op: None,
-            unary_op: Some(|a: T| a.exp()),
-        },
-        Operator {
-            repr: "sqrt",
-            bin_op: None,
-            unary_op: Some(|a: T| a.sqrt()),
+            domain_check: Some(domain_log),
+            ..Operator::make_unary("log10", |a: T| a.log10())
         },
+        Operator::make_bin(
+            "<",
+            BinOp {
+                apply: |a: T, b| a.indicator_lt(b),
+                prio: PRIO_CMP,
+            },
+        ),
+        Operator::make_bin(
+            "<=",
+            BinOp {
+                apply: |a: T, b| a.indicator_le(b),
+                prio: PRIO_CMP,
+            },
+        ),
+        Operator::make_bin(
+            ">",
+            BinOp {
+                apply: |a: T, b| b.indicator_lt(a),
+                prio: PRIO_CMP,
+            },
+        ),
+        Operator::make_bin(
+            ">=",
+            BinOp {
+                apply: |a: T, b| b.indicator_le(a),
+                prio: PRIO_CMP,
+            },
+        ),
+        Operator::make_bin(
+            "==",
+            BinOp {
+                apply: |a: T, b| a.indicator_eq(b),
+                prio: PRIO_CMP,
+            },
+        ),
+        Operator::make_bin(
+            "!=",
+            BinOp {
+                apply: |a: T, b| T::from_f64(1.0) - a.indicator_eq(b),
+                prio: PRIO_CMP,
+            },
+        ),
+        Operator::make_nary(
+            "logb",
+            NaryOp {
+                apply: |args: &[T]| args[1].log(args[0]),
+                arity: 2,
+            },
+        ),
+    ]
+}
+
+fn div_int<T: PrimInt + WrappingNeg>(a: T, b: T) -> T {
+    if b.is_zero() {
+        T::zero()
+    } else {
+        // the only remaining case without result is the minimum divided by -1
+        a.checked_div(&b).unwrap_or_else(|| a.wrapping_neg())
+    }
+}
+
+fn rem_int<T: PrimInt + CheckedRem>(a: T, b: T) -> T {
+    a.checked_rem(&b).unwrap_or_else(T::zero)
+}
+
+fn nonzero_divisor<T: PrimInt>(b: T) -> Result<(), String> {
+    if b.is_zero() {
+        Err("division by zero".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+fn shift_amount<T: PrimInt>(b: T) -> usize {
+    let n_bits = core::mem::size_of::<T>() * 8;
+    (b & T::from(n_bits - 1).unwrap()).to_usize().unwrap()
+}
+
+/// Returns default operators for primitive integers, i.e., `+`, `-`, `*`, `/`, `%`, `<<`,
+/// `>>`, `&`, `|`, and `^` as binary operators as well as `-`, `+`, and `!` as unary
+/// operators. Similar to Rust, `^` is the bitwise exclusive or and not a power and `!` is
+/// the bitwise negation. The priorities from low to high are `|`, `^`, `&`, shifts, `+`
+/// and `-`, and `*`, `/`, and `%`.
+///
+/// Evaluation never panics. Arithmetic wraps around on overflow and the shift amount is
+/// taken modulo the number of bits as in [`wrapping_shl`](u32::wrapping_shl). Division and
+/// remainder by zero are [fallible](FallibleOp), i.e., [`eval`](crate::FlatEx::eval)
+/// returns an [`ExError::OperatorFailed`](crate::ExError::OperatorFailed) while the
+/// evaluation methods that cannot report errors, e.g.,
+/// [`eval_unchecked`](crate::FlatEx::eval_unchecked), result in zero. Since the default
+/// number pattern matches decimals, expressions should be parsed with an integer pattern.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let ops = make_default_operators_int::<u32>();
+/// let expr = parse_with_number_pattern::<u32>("(!(a|b)) << 2", &ops, "[0-9]+")?;
+/// assert_eq!(expr.eval(&[0, 1])?, (u32::MAX - 1) << 2);
+/// let expr = parse_with_number_pattern::<u8>("x * 2 + 1", &make_default_operators_int(), "[0-9]+")?;
+/// assert_eq!(expr.eval(&[200])?, 145);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+pub fn make_default_operators_int<'a, T>() -> [Operator<'a, T>; 11]
+where
+    T: PrimInt + WrappingAdd + WrappingSub + WrappingMul + WrappingNeg + CheckedRem,
+{
+    [
+        Operator::make_bin(
+            "|",
+            BinOp {
+                apply: |a: T, b| a | b,
+                prio: 0,
+            },
+        ),
+        Operator::make_bin(
+            "^",
+            BinOp {
+                apply: |a: T, b| a ^ b,
+                prio: 1,
+            },
+        ),
+        Operator::make_bin(
+            "&",
+            BinOp {
+                apply: |a: T, b| a & b,
+                prio: 2,
+            },
+        ),
+        Operator::make_bin(
+            "<<",
+            BinOp {
+                apply: |a: T, b| a << shift_amount(b),
+                prio: 3,
+            },
+        ),
+        Operator::make_bin(
+            ">>",
+            BinOp {
+                apply: |a: T, b| a >> shift_amount(b),
+                prio: 3,
+            },
+        ),
+        Operator::make_bin_unary(
+            "+",
+            BinOp {
+                apply: |a: T, b| a.wrapping_add(&b),
+                prio: 4,
+            },
+            |a: T| a,
+        ),
+        Operator::make_bin_unary(
+            "-",
+            BinOp {
+                apply: |a: T, b| a.wrapping_sub(&b),
+                prio: 4,
+            },
+            |a: T| a.wrapping_neg(),
+        ),
+        Operator::make_bin(
+            "*",
+            BinOp {
+                apply: |a: T, b| a.wrapping_mul(&b),
+                prio: 5,
+            },
+        ),
         Operator {
-            repr: "log",
-            bin_op: None,
-            unary_op: Some(|a: T| a.ln()),
+            fallible: Some(FallibleOp {
+                bin_op: Some(|a: T, b| nonzero_divisor(b).map(|_| div_int(a, b))),
+                unary_op: None,
+            }),
+            ..Operator::make_bin(
+                "/",
+                BinOp {
+                    apply: div_int,
+                    prio: 5,
+                },
+            )
         },
         Operator {
-            repr: "log2",
-            bin_op: None,
-            unary_op: Some(|a: T| a.log2()),
+            fallible: Some(FallibleOp {
+                bin_op: Some(|a: T, b| nonzero_divisor(b).map(|_| rem_int(a, b))),
+                unary_op: None,
+            }),
+            ..Operator::make_bin(
+                "%",
+                BinOp {
+                    apply: rem_int,
+                    prio: 5,
+                },
+            )
         },
+        Operator::make_unary("!", |a: T| !a),
     ]
 }
+
+/// Returns default operators for `bool`, i.e., `||`, `&&`, `==`, and `!=` as binary
+/// operators from low to high priority and `!` as unary operator. Literals are parsed with
+/// the number pattern `true|false`.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// let ops = make_default_operators_bool();
+/// let expr = parse_with_number_pattern::<bool>("x && !y || z == false", &ops, "true|false")?;
+/// assert_eq!(expr.eval(&[true, true, true])?, false);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+pub fn make_default_operators_bool<'a>() -> [Operator<'a, bool>; 5] {
+    [
+        Operator::make_bin(
+            "||",
+            BinOp {
+                apply: |a, b| a || b,
+                prio: 0,
+            },
+        ),
+        Operator::make_bin(
+            "&&",
+            BinOp {
+                apply: |a, b| a && b,
+                prio: 1,
+            },
+        ),
+        Operator::make_bin(
+            "==",
+            BinOp {
+                apply: |a, b| a == b,
+                prio: 2,
+            },
+        ),
+        Operator::make_bin(
+            "!=",
+            BinOp {
+                apply: |a, b| a != b,
+                prio: 2,
+            },
+        ),
+        Operator::make_unary("!", |a| !a),
+    ]
+}
+
+/// Creates a set of operators without any state, such that an expression can be parsed
+/// with [`parse_with_factory`](crate::parse_with_factory) by naming the type of the
+/// factory instead of passing a slice of operators. Expressions remember the factory and
+/// create the operators again if they need them, e.g., for
+/// [`partial`](crate::FlatEx::partial) derivatives.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// struct WithMax;
+/// impl MakeOperators<f64> for WithMax {
+///     fn make() -> Vec<Operator<'static, f64>> {
+///         let mut ops = make_default_operators::<f64>().to_vec();
+///         ops.push(Operator::make_bin(
+///             "max",
+///             BinOp { apply: f64::max, prio: 25 },
+///         ));
+///         ops
+///     }
+/// }
+/// let expr = parse_with_factory::<f64, WithMax>("x max 2 * y")?;
+/// // (x max 2) * y
+/// assert_eq!(expr.eval(&[3.0, 4.0])?, 12.0);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+pub trait MakeOperators<T> {
+    /// Returns the operators of this factory.
+    fn make() -> Vec<Operator<'static, T>>;
+}
+
+/// Factory of the [default operators](make_default_operators), see
+/// [`MakeOperators`](MakeOperators).
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultOpsFactory<T>(PhantomData<T>);
+
+#[cfg(feature = "std")]
+impl<T: DefaultOpsFloat> MakeOperators<T> for DefaultOpsFactory<T> {
+    fn make() -> Vec<Operator<'static, T>> {
+        make_default_operators().to_vec()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{eval_str, parse, parse_with_number_pattern, ExError};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_prio_between() {
+    let concat = Operator {
+        repr: "#",
+        bin_op: Some(BinOp {
+            apply: |a: f64, b| a * 10.0 + b,
+            prio: 0,
+        }),
+        unary_op: None,
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    }
+    .with_prio_between(PRIO_MUL, PRIO_POW);
+    assert_eq!(concat.bin_op.unwrap().prio, 25);
+    let sin = make_default_operators::<f64>()[6].with_prio_between(PRIO_MUL, PRIO_POW);
+    assert_eq!(sin.repr, "sin");
+    assert_eq!(sin.bin_op, None);
+    let extreme = concat.with_prio_between(i32::MIN, i32::MAX);
+    assert_eq!(extreme.bin_op.unwrap().prio, -1);
+
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(concat);
+    let eval = |text: &str| parse(text, &ops).unwrap().eval(&[]).unwrap();
+    // binds stronger than `*` and weaker than `^`
+    assert_eq!(eval("2*3#4"), 68.0);
+    assert_eq!(eval("3#4*2"), 68.0);
+    assert_eq!(eval("3#4^2"), 46.0);
+    assert_eq!(eval("3^2#4"), 94.0);
+    assert_eq!(eval("1+2#3-4"), 20.0);
+    // left to right among equal priorities and nested expressions first
+    assert_eq!(eval("2#3#4"), 234.0);
+    assert_eq!(eval("(2*3)#4"), 64.0);
+    assert_eq!(eval("2^(1#0)/(2#(2*2))"), 1024.0 / 24.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_default_comparisons() {
+    let ops = make_default_operators::<f64>();
+    let abs = parse("(x > 0) * x + (x <= 0) * (-x)", &ops).unwrap();
+    assert_eq!(abs.eval(&[3.0]).unwrap(), 3.0);
+    assert_eq!(abs.eval(&[-3.0]).unwrap(), 3.0);
+    assert_eq!(abs.eval(&[0.0]).unwrap(), 0.0);
+    let eval = |text: &str| parse(text, &ops).unwrap().eval(&[]).unwrap();
+    // longer representations are preferred and comparisons bind weaker than `+`
+    assert_eq!(eval("1<=1"), 1.0);
+    assert_eq!(eval("1<1"), 0.0);
+    assert_eq!(eval("2>=1+1"), 1.0);
+    assert_eq!(eval("2>1+1"), 0.0);
+    assert_eq!(eval("2==1+1"), 1.0);
+    assert_eq!(eval("2!=1+1"), 0.0);
+    assert_eq!(eval("1<-1"), 0.0);
+    assert_eq!(eval("3 > 2 > 1"), 0.0);
+    assert_eq!(eval("(0/0) == (0/0)"), 0.0);
+    assert_eq!(eval("(0/0) != (0/0)"), 1.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_aliases() {
+    assert_eq!(eval_str("2**3").unwrap(), 8.0);
+    assert_eq!(eval_str("2 ** 3 ** 2").unwrap(), eval_str("2^3^2").unwrap());
+    assert_eq!(eval_str("6÷3×2").unwrap(), 4.0);
+    assert_eq!(eval_str("-2**2").unwrap(), 4.0);
+
+    // unparsing and derivatives use the canonical representation
+    let ops = make_default_operators::<f64>();
+    let expr = parse("x**2×y", &ops).unwrap();
+    assert_eq!(
+        expr.unparse().unwrap(),
+        parse("x^2*y", &ops).unwrap().unparse().unwrap()
+    );
+    assert!(expr.unparse().unwrap().starts_with("x^2"));
+    assert_eq!(expr.eval(&[3.0, 2.0]).unwrap(), 18.0);
+    let dx = expr.partial(0).unwrap();
+    assert!(!dx.unparse().unwrap().contains("**"));
+    assert_eq!(dx.eval(&[3.0, 2.0]).unwrap(), 12.0);
+    assert_eq!(parse("x÷y", &ops).unwrap().unparse().unwrap(), "x/y");
+
+    let mut ops = ops.to_vec();
+    ops.push(Operator {
+        repr: "mod",
+        bin_op: Some(BinOp {
+            apply: |a: f64, b| a % b,
+            prio: PRIO_MUL,
+        }),
+        unary_op: None,
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &["%%", "rem"],
+    });
+    let expr = parse("x rem 4 + x%%3 - x mod 2", &ops).unwrap();
+    assert_eq!(expr.eval(&[7.0]).unwrap(), 3.0);
+    assert_eq!(
+        expr.unparse().unwrap(),
+        parse("x mod 4 + x mod 3 - x mod 2", &ops)
+            .unwrap()
+            .unparse()
+            .unwrap()
+    );
+    // aliases of names do not match prefixes of variables
+    assert_eq!(parse("remainder", &ops).unwrap().var_names(), ["remainder"]);
+
+    // aliases must be unique among all representations
+    ops.last_mut().unwrap().aliases = &["**"];
+    assert_eq!(
+        parse("x", &ops).unwrap_err(),
+        ExError::DuplicateOperator("**".to_string())
+    );
+    ops.last_mut().unwrap().aliases = &[""];
+    assert_eq!(parse("x", &ops).unwrap_err(), ExError::EmptyOperatorRepr);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_default_operators_int() {
+    let ops = make_default_operators_int::<u32>();
+    let expr = parse_with_number_pattern("(!(a|b)) << 2", &ops, "[0-9]+").unwrap();
+    assert_eq!(expr.eval(&[0, 1]).unwrap(), (u32::MAX - 1) << 2);
+    let eval = |text: &str, vars: &[i8]| {
+        let ops = make_default_operators_int::<i8>();
+        parse_with_number_pattern(text, &ops, "[0-9]+")
+            .unwrap()
+            .eval(vars)
+            .unwrap()
+    };
+    assert_eq!(eval("x | 2 ^ 7 & 6 << 1 + 0 * 2", &[8]), 14);
+    assert_eq!(eval("7 - 2 - 1", &[]), 4);
+    assert_eq!(eval("-x % 3", &[7]), -1);
+    // overflow wraps
+    assert_eq!(eval("x + 1", &[i8::MAX]), i8::MIN);
+    assert_eq!(eval("x * 2", &[100]), -56);
+    assert_eq!(eval("-x", &[i8::MIN]), i8::MIN);
+    assert_eq!(eval("x / (0 - 1)", &[i8::MIN]), i8::MIN);
+    assert_eq!(eval("x % (0 - 1)", &[i8::MIN]), 0);
+    // division by zero fails, only unchecked evaluation results in zero
+    let ops = make_default_operators_int::<i8>();
+    for text in ["x / 0", "x % 0", "x / (x - 5)", "5 % 0 * x"] {
+        let expr = parse_with_number_pattern(text, &ops, "[0-9]+").unwrap();
+        match expr.eval(&[5]) {
+            Err(ExError::OperatorFailed { reason, .. }) => assert_eq!(reason, "division by zero"),
+            res => panic!("expected a failure for {}, got {:?}", text, res),
+        }
+        assert_eq!(expr.eval_unchecked(&[5]), 0);
+    }
+    // no panics for large shifts
+    assert_eq!(eval("1 << 9", &[]), 2);
+    assert_eq!(eval("x >> 1", &[-4]), -2);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_default_operators_bool() {
+    let ops = make_default_operators_bool();
+    let expr = parse_with_number_pattern("x && !y || z", &ops, "true|false").unwrap();
+    for &(x, y, z) in &[
+        (false, false, false),
+        (true, false, false),
+        (true, true, false),
+        (false, true, true),
+    ] {
+        assert_eq!(expr.eval(&[x, y, z]).unwrap(), x && !y || z);
+    }
+    let expr = parse_with_number_pattern("a != b == !c", &ops, "true|false").unwrap();
+    assert!(expr.eval(&[true, false, false]).unwrap());
+    assert!(!expr.eval(&[true, true, false]).unwrap());
+    let expr = parse_with_number_pattern("!true || false && true", &ops, "true|false").unwrap();
+    assert!(!expr.eval(&[]).unwrap());
+}
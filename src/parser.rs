@@ -1,58 +1,471 @@
+use crate::alloc_prelude::*;
 use crate::definitions::N_NODES_ON_STACK;
+use crate::expression::loops::expand_loops;
 use crate::operators::Operator;
-use lazy_static::lazy_static;
+use crate::result::{ExError, PairViolation, ParseWarning};
+use crate::validation;
+use core::fmt::Debug;
+use core::str::FromStr;
+#[cfg(feature = "std")]
 use regex::Regex;
 use smallvec::SmallVec;
-use std::error::Error;
-use std::fmt::{self, Debug};
-use std::str::FromStr;
 
-/// This will be thrown at you if the parsing went wrong. Ok, obviously it is not an
-/// exception, so thrown needs to be understood figuratively.
-#[derive(Debug, Clone)]
-pub struct ExParseError {
-    pub msg: String,
-}
-impl fmt::Display for ExParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.msg)
-    }
-}
-impl Error for ExParseError {}
-
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Paren {
     Open,
     Close,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum ParsedToken<'a, T: Copy + FromStr> {
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind<'a, T: Copy + FromStr> {
     Num(T),
     Paren(Paren),
     Op(Operator<'a, T>),
     Var(&'a str),
+    /// Separates the arguments of an n-ary operator. It is written `;` with
+    /// [`ParseOptions::decimal_comma`](ParseOptions::decimal_comma).
+    Comma,
+}
+
+/// Range of bytes a token occupies in the parsed string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsedToken<'a, T: Copy + FromStr> {
+    pub kind: TokenKind<'a, T>,
+    pub span: Span,
+}
+
+/// Category of a [`Token`](Token).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenCategory {
+    Number,
+    Operator,
+    Variable,
+    OpenParen,
+    CloseParen,
+    /// Separates the arguments of an n-ary operator.
+    Comma,
+}
+
+/// A token of a string as returned by [`tokenize`](crate::tokenize).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub category: TokenCategory,
+    /// Text of the token including the curly braces of variable names.
+    pub text: &'a str,
+    pub span: Span,
+}
+
+impl<'a, T: Copy + FromStr> ParsedToken<'a, T> {
+    pub fn to_token(&self, text: &'a str) -> Token<'a> {
+        let category = match self.kind {
+            TokenKind::Num(_) => TokenCategory::Number,
+            TokenKind::Paren(Paren::Open) => TokenCategory::OpenParen,
+            TokenKind::Paren(Paren::Close) => TokenCategory::CloseParen,
+            TokenKind::Op(_) => TokenCategory::Operator,
+            TokenKind::Var(_) => TokenCategory::Variable,
+            TokenKind::Comma => TokenCategory::Comma,
+        };
+        Token {
+            category,
+            text: &text[self.span.start..self.span.end],
+            span: self.span,
+        }
+    }
+}
+
+/// Options that change how strings are parsed, see
+/// [`parse_with_options`](crate::parse_with_options). The default is the strict behavior of
+/// [`parse`](crate::parse).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// A multiplication is inserted between a number, a variable, or a closing parenthesis
+    /// and a subsequent variable, opening parenthesis, or unary operator, e.g., `2x` is
+    /// parsed as `2*x` and `(a+b)(a-b)` as `(a+b)*(a-b)`. Thereby, the operator with
+    /// representation `*` is used.
+    pub implicit_multiplication: bool,
+    /// An operator that could also be read as a shorter operator followed by another
+    /// operator or a sign, e.g., `<-` as `<` and `-`, is an error unless it is surrounded by
+    /// whitespace, e.g., `a <- b` is fine but `a<-b` is not. Without this option, such
+    /// operators are only reported as [`ParseWarning`](crate::ParseWarning)s.
+    pub require_spaces_around_ambiguous_ops: bool,
+    /// Unary operators that are also binary, such as `-`, are applied after binary operators
+    /// with a higher priority than their binary variant, e.g., `-2^2` is `-(2^2) = -4`
+    /// instead of `(-2)^2 = 4`. With the default operators, only powers are affected in
+    /// value.
+    pub unary_minus_binds_weaker_than_pow: bool,
+    /// Constant arguments of unary operators are checked with the operators'
+    /// [`domain_check`](crate::Operator::domain_check)s, e.g., `log(2-2)` is an error
+    /// instead of `-inf`. Without this option, the operators are simply applied.
+    pub strict_domains: bool,
+    /// Numbers keep the text they have been parsed from, which is used by
+    /// [`unparse`](crate::FlatEx::unparse), e.g., `x * 1.50` is unparsed as `{x}*1.50`
+    /// instead of `{x}*1.5`. Numbers that result from constant folding are written with
+    /// [`Debug`](std::fmt::Debug) as without this option.
+    pub preserve_literal_text: bool,
+    /// Line comments from `#` to the end of the line and block comments between `/*` and
+    /// `*/` are ignored like whitespace, e.g., for formulas in configuration files. Comments
+    /// take precedence over operators that start with `#` or `/*`. Block comments cannot be
+    /// nested.
+    pub comments: bool,
+    /// Variable names can be put into curly braces such as `{x}` or `{a b}`, see the
+    /// [crate documentation](crate#variables). An operator whose representation starts with
+    /// `{` takes precedence over a variable in curly braces at the same position. Without
+    /// this option, `{` is an unexpected character unless it starts an operator. The
+    /// default is `true`.
+    pub curly_vars: bool,
+    /// Numbers are written with a decimal comma instead of a decimal point, e.g., `3,5`.
+    /// Then, the arguments of n-ary operators are separated by `;` instead of `,`, e.g.,
+    /// `logb(2; 8)`, since `logb(2,8)` would be ambiguous. The option only affects the
+    /// built-in recognition of numbers. Parse functions with a custom number pattern, e.g.,
+    /// [`parse_with_number_pattern`](crate::parse_with_number_pattern), take numbers as the
+    /// pattern matches them and pass them to [`FromStr`] unchanged. Unparsed expressions
+    /// contain decimal points unless
+    /// [`preserve_literal_text`](ParseOptions::preserve_literal_text) is set.
+    pub decimal_comma: bool,
+    /// Limits on the size of the parsed expression.
+    pub limits: ParseLimits,
+    /// Which optimizations may change the result compared to evaluating the operators in
+    /// the order given by the string and the operator priorities.
+    pub semantics: Semantics,
+    /// The order of the variables that defines, e.g., which value of the slice passed to
+    /// [`eval`](crate::FlatEx::eval) belongs to which variable.
+    pub var_order: VarOrder,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            implicit_multiplication: false,
+            require_spaces_around_ambiguous_ops: false,
+            unary_minus_binds_weaker_than_pow: false,
+            strict_domains: false,
+            preserve_literal_text: false,
+            comments: false,
+            curly_vars: true,
+            decimal_comma: false,
+            limits: ParseLimits::default(),
+            semantics: Semantics::default(),
+            var_order: VarOrder::default(),
+        }
+    }
+}
+
+/// Order of the variables of an expression, see
+/// [`ParseOptions::var_order`](ParseOptions::var_order). The n-th value of a slice passed to,
+/// e.g., [`eval`](crate::FlatEx::eval) belongs to the n-th variable in this order, which is
+/// also the order of [`var_names`](crate::FlatEx::var_names).
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, VarOrder};
+/// let ops = make_default_operators::<f64>();
+/// let expr = parse_with_options::<f64>("z + a*2", &ops, &ParseOptions::default())?;
+/// assert_eq!(expr.var_names(), ["a", "z"]);
+/// assert_eq!(expr.eval(&[1.0, 5.0])?, 7.0);
+/// let options = ParseOptions {
+///     var_order: VarOrder::Occurrence,
+///     ..ParseOptions::default()
+/// };
+/// let expr = parse_with_options::<f64>("z + a*2", &ops, &options)?;
+/// assert_eq!(expr.var_names(), ["z", "a"]);
+/// assert_eq!(expr.eval(&[5.0, 1.0])?, 7.0);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VarOrder {
+    /// Variables are ordered alphabetically by their names.
+    #[default]
+    Alphabetical,
+    /// Variables are ordered by their first occurrences in the parsed string.
+    Occurrence,
+}
+
+/// Guarantees of an expression regarding its optimizations, see
+/// [`ParseOptions::semantics`](ParseOptions::semantics). Optimizations happen while parsing
+/// and when expressions are combined, e.g., by [`partial`](crate::FlatEx::partial).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Semantics {
+    /// All optimizations are applied, including ones that can change results of
+    /// floating point computations. Currently, binary operators with two numbers as
+    /// operands are evaluated before other operators of the same priority where this
+    /// regrouping is valid for real numbers, e.g., `x*0.1*3` is evaluated as
+    /// `x*0.30000000000000004` and `x+1-2` as `x+(-1)`, whereas `x-1+2` and `x/2*4` keep
+    /// their order. The results of such reordered operators can also overflow differently,
+    /// e.g., `x*(10^308)*10` is `inf` for `x=0.1`. Future versions might add further
+    /// optimizations.
+    #[default]
+    Fast,
+    /// The result is bit-exactly the result of applying the operators in the order of the
+    /// string and the operator priorities, including NaNs, infinities, and signed zeros.
+    /// Operators whose operands are all numbers are still evaluated while parsing, e.g.,
+    /// `2*3*x` is `6*x`, since this does not change the order of evaluation.
+    IeeeStrict,
+}
+
+impl Semantics {
+    pub(crate) fn optimizations(self) -> Optimizations {
+        match self {
+            Semantics::Fast => Optimizations {
+                fold_constants: true,
+                prioritize_constant_pairs: true,
+            },
+            Semantics::IeeeStrict => Optimizations {
+                fold_constants: true,
+                prioritize_constant_pairs: false,
+            },
+        }
+    }
+}
+
+/// Optimizations that are allowed by the [`Semantics`](Semantics) of an expression. Each
+/// place that evaluates or rearranges operators ahead of time checks the corresponding flag
+/// such that a new optimization needs a new flag and a decision for each semantics.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Optimizations {
+    /// Operators are evaluated while compiling if all their operands are numbers and if
+    /// they are next in the order of evaluation.
+    pub fold_constants: bool,
+    /// Binary operators with two numbers as operands are evaluated before the other
+    /// operators of the same priority if the regrouping does not change the value for real
+    /// numbers, e.g., not after a `-` or `/` of the same priority.
+    pub prioritize_constant_pairs: bool,
+}
+
+impl Optimizations {
+    /// Returns the optimizations allowed by both, e.g., for an expression that is combined
+    /// from two others.
+    pub fn both(self, other: Optimizations) -> Optimizations {
+        Optimizations {
+            fold_constants: self.fold_constants && other.fold_constants,
+            prioritize_constant_pairs: self.prioritize_constant_pairs
+                && other.prioritize_constant_pairs,
+        }
+    }
+}
+
+impl Default for Optimizations {
+    fn default() -> Self {
+        Semantics::default().optimizations()
+    }
+}
+
+/// Default of [`ParseLimits::max_depth`](ParseLimits::max_depth).
+const DEFAULT_MAX_DEPTH: usize = 512;
+
+/// Default of [`ParseLimits::max_loop_terms`](ParseLimits::max_loop_terms).
+const DEFAULT_MAX_LOOP_TERMS: usize = 10_000;
+
+/// Limits that are checked during parsing, e.g., for strings from untrusted sources. `None`
+/// means unlimited, which is the default of all limits except
+/// [`max_depth`](ParseLimits::max_depth) and [`max_loop_terms`](ParseLimits::max_loop_terms).
+/// See [`eval_str_with_limits`](crate::eval_str_with_limits) for an example.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximal length of the string in bytes. It is checked before tokenizing.
+    pub max_len: Option<usize>,
+    /// Maximal number of tokens, i.e., of numbers, variables, operators, parentheses, and
+    /// commas, in the string. Like [`max_nodes`](ParseLimits::max_nodes), it is checked
+    /// while tokenizing.
+    pub max_tokens: Option<usize>,
+    /// Maximal number of unary operators that are applied to a single node or binary
+    /// operator of the flattened expression, see
+    /// [`max_unary_chain_len`](crate::FlatEx::max_unary_chain_len). Unary operators of nested
+    /// sub-expressions are merged during flattening, e.g., `-(-(-x))` results in one chain
+    /// of length 3.
+    pub max_unary_chain: Option<usize>,
+    /// Maximal number of numbers and variables in the string. Unlike the other limits, it
+    /// is checked while tokenizing such that parsing stops at the first node beyond the
+    /// limit.
+    pub max_nodes: Option<usize>,
+    /// Maximal nesting depth of parentheses, e.g., `((x))` has depth 2. Like
+    /// [`max_nodes`](ParseLimits::max_nodes), it is checked while tokenizing. Parsing,
+    /// flattening, unparsing, evaluation, and differentiation keep nested sub-expressions
+    /// on the heap instead of recursing into them. Dropping, cloning, and comparing
+    /// expressions recurse with small frames, such that the default of 512 fits into the
+    /// 2 MiB stack of spawned threads.
+    pub max_depth: Option<usize>,
+    /// Maximal total number of terms that sums and products such as `sum(i, 1, 5, i^2)`
+    /// expand to, including the terms of nested ones, e.g., `sum(i, 1, 3, sum(j, 1, i, j))`
+    /// has 9 terms. Like [`max_depth`](ParseLimits::max_depth), it is checked while
+    /// tokenizing such that large ranges cannot exhaust the memory. The default is 10000.
+    pub max_loop_terms: Option<usize>,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_len: None,
+            max_tokens: None,
+            max_unary_chain: None,
+            max_nodes: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_loop_terms: Some(DEFAULT_MAX_LOOP_TERMS),
+        }
+    }
 }
 
-pub fn is_numeric_text<'a>(text: &'a str) -> Option<&'a str> {
-    let mut n_dots = 0;
-    let n_num_chars = text
-        .chars()
-        .take_while(|c| {
-            let is_dot = *c == '.';
-            if is_dot {
-                n_dots += 1;
+/// Maximal number of bytes on each side of a span that is shown in error messages.
+const CONTEXT_LEN: usize = 5;
+
+/// Returns the span extended by a few characters to each side.
+pub fn context(text: &str, span: Span) -> String {
+    let mut start = span.start.saturating_sub(CONTEXT_LEN);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (span.end + CONTEXT_LEN).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    text[start..end].to_string()
+}
+
+/// Regex pattern of numbers as recognized by [`parse`](crate::parse) for floats, i.e.,
+/// decimals such as `2`, `.5`, or `4.` with an optional exponent such as `1e-3` or
+/// `2.5E+10`. Digits can be separated by underscores as in `1_000.5`. The pattern can be
+/// passed to [`parse_with_number_pattern`](crate::parse_with_number_pattern).
+pub const NUMBER_REGEX_PATTERN: &str =
+    r"([0-9]+(_[0-9]+)*(\.([0-9]+(_[0-9]+)*)?)?|\.[0-9]+(_[0-9]+)*)([eE][+-]?[0-9]+(_[0-9]+)*)?";
+
+/// Regex pattern of variable names as recognized by [`parse`](crate::parse), i.e., ASCII
+/// letters, digits, and underscores starting with a letter or an underscore. The pattern
+/// can be passed to [`parse_with_var_pattern`](crate::parse_with_var_pattern).
+pub const VAR_REGEX_PATTERN: &str = r"[a-zA-Z_][a-zA-Z_0-9]*";
+
+/// Regex patterns that define the looks of numbers and variable names, see
+/// [`parse_with_patterns`](crate::parse_with_patterns). The default patterns are the ones of
+/// [`parse`](crate::parse).
+///
+/// At each position of the parsed string, a number takes precedence over an operator and
+/// an operator over a variable name. Hence, a variable pattern can match operator
+/// representations and digits but such names are only found where no number and no
+/// operator starts, e.g., with the pattern `[a-z0-9]+`, the string `2x` is still `2*x` with
+/// implicit multiplication. A variable name ends where the match of the pattern ends, even
+/// if an operator representation is part of the match. Names in curly braces do not need
+/// to match the pattern.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TokenPatterns<'p> {
+    /// Pattern of numbers, see [`NUMBER_REGEX_PATTERN`](NUMBER_REGEX_PATTERN).
+    pub number: &'p str,
+    /// Pattern of variable names, see [`VAR_REGEX_PATTERN`](VAR_REGEX_PATTERN). The pattern
+    /// must not match the empty string.
+    pub var: &'p str,
+}
+
+impl Default for TokenPatterns<'_> {
+    fn default() -> Self {
+        TokenPatterns {
+            number: NUMBER_REGEX_PATTERN,
+            var: VAR_REGEX_PATTERN,
+        }
+    }
+}
+
+/// Returns the end of the sequence of digits starting at `start`. Underscores are only
+/// part of the sequence if they are followed by a digit.
+fn end_of_digits(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_digit() {
+            i += 1;
+        } else if bytes[i] == b'_' && i > start && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+        {
+            i += 2;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+/// Returns whether `name` is tokenized as a variable without curly braces, i.e., it
+/// matches the pattern of plain variable names and does not start with one of the operator
+/// representations `op_reprs`, see [`starts_with_op`](starts_with_op).
+pub fn is_plain_var_name(name: &str, op_reprs: &[&str]) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !op_reprs.iter().any(|repr| starts_with_op(name, repr))
+}
+
+pub fn is_numeric_text(text: &str) -> Option<&str> {
+    numeric_prefix(text, b'.')
+}
+
+/// Recognizes numbers like [`is_numeric_text`](is_numeric_text) but with a decimal comma,
+/// see [`ParseOptions::decimal_comma`](ParseOptions::decimal_comma).
+pub fn is_numeric_text_decimal_comma(text: &str) -> Option<&str> {
+    numeric_prefix(text, b',')
+}
+
+fn numeric_prefix(text: &str, separator: u8) -> Option<&str> {
+    let bytes = text.as_bytes();
+    let end_int = end_of_digits(bytes, 0);
+    let mut end = end_int;
+    if bytes.get(end) == Some(&separator) {
+        end = end_of_digits(bytes, end + 1);
+        if end_int == 0 && end == 1 {
+            return None;
+        }
+    } else if end_int == 0 {
+        return None;
+    }
+    if bytes.get(end) == Some(&separator) {
+        return None;
+    }
+    // the exponent is only part of the number if it contains digits such that, e.g.,
+    // `2e` is still a number followed by the variable `e`
+    if let Some(b'e' | b'E') = bytes.get(end) {
+        let start_exp = match bytes.get(end + 1) {
+            Some(b'+' | b'-') => end + 2,
+            _ => end + 1,
+        };
+        let end_exp = end_of_digits(bytes, start_exp);
+        if end_exp > start_exp {
+            end = end_exp;
+        }
+    }
+    Some(&text[0..end])
+}
+
+/// Parses the text of a number token at `span`. Underscores are removed if the text
+/// cannot be parsed otherwise, since they serve as digit separators. The number pattern
+/// can match text the number type does not accept, e.g., `2.5` for integers, which
+/// results in an [`InvalidNumber`](ExError::InvalidNumber) error.
+fn parse_num<T: FromStr>(num_str: &str, text: &str, span: Span) -> Result<T, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+{
+    num_str
+        .parse::<T>()
+        .or_else(|e| {
+            if num_str.contains('_') {
+                num_str.replace('_', "").parse::<T>()
+            } else {
+                Err(e)
             }
-            c.is_digit(10) || is_dot
         })
-        .count();
-    if (n_num_chars > 1 && n_dots < 2) || (n_num_chars == 1 && n_dots == 0) {
-        Some(&text[0..n_num_chars])
-    } else {
-        None
-    }
+        .map_err(|e| ExError::InvalidNumber {
+            position: span.start,
+            found: text[span.start..span.end].to_string(),
+            reason: format!("{:?}", e),
+            context: context(text, span),
+        })
 }
 
+#[cfg(feature = "std")]
 pub fn is_numeric_regex<'a>(re: &Regex, text: &'a str) -> Option<&'a str> {
     let maybe_num = re.find(text);
     match maybe_num {
@@ -61,294 +474,867 @@ pub fn is_numeric_regex<'a>(re: &Regex, text: &'a str) -> Option<&'a str> {
     }
 }
 
-/// Parses tokens of a text with regexes and returns them as a vector
+/// Returns the variable name matched by `re` at the beginning of `text`. Empty matches are
+/// no names.
+#[cfg(feature = "std")]
+pub fn is_name_regex<'a>(re: &Regex, text: &'a str) -> Option<&'a str> {
+    re.find(text)
+        .map(|m| m.as_str())
+        .filter(|name| !name.is_empty())
+}
+
+/// Parses tokens of a text with regexes and returns them as a vector, see
+/// [`tokenize_with_diagnostics`](tokenize_with_diagnostics).
+#[cfg(all(test, feature = "std"))]
+pub fn tokenize_and_analyze<'a, T: Copy + FromStr + Debug, F: Fn(&'a str) -> Option<&'a str>>(
+    text: &'a str,
+    ops_in: &[Operator<'a, T>],
+    is_numeric: F,
+    options: &ParseOptions,
+) -> Result<Vec<ParsedToken<'a, T>>, ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+{
+    tokenize_with_diagnostics(text, ops_in, is_numeric, options).map(|(tokens, _)| tokens)
+}
+
+/// Parses tokens of a text with regexes and returns them as a vector together with
+/// warnings about potential problems
 ///
 /// # Arguments
 ///
 /// * `text` - text to be parsed
 /// * `ops_in` - slice of operator-pairs
 /// * `is_numeric` - closure that decides whether the current rest of the text starts with a number
+/// * `options` - options that change how the text is parsed
 ///
 /// # Errors
 ///
 /// See [`parse_with_number_pattern`](parse_with_number_pattern)
 ///
-pub fn tokenize_and_analyze<'a, T: Copy + FromStr + Debug, F: Fn(&'a str) -> Option<&'a str>>(
+pub fn tokenize_with_diagnostics<'a, T: Copy + FromStr + Debug, F: Fn(&'a str) -> Option<&'a str>>(
     text: &'a str,
     ops_in: &[Operator<'a, T>],
     is_numeric: F,
-) -> Result<Vec<ParsedToken<'a, T>>, ExParseError>
+    options: &ParseOptions,
+) -> Result<(Vec<ParsedToken<'a, T>>, Vec<ParseWarning>), ExError>
 where
-    <T as std::str::FromStr>::Err: Debug,
+    <T as core::str::FromStr>::Err: Debug,
 {
-    // We sort operators inverse alphabetically such that log2 has higher priority than log (wlog :D).
+    tokenize_with_names(text, ops_in, is_numeric, is_name_text, options)
+}
 
-    let mut ops_tmp = ops_in.iter().clone().collect::<SmallVec<[_; 64]>>();
-    ops_tmp.sort_by(|o1, o2| o2.repr.partial_cmp(o1.repr).unwrap());
-    let ops = ops_tmp; // from now on const
+/// Parses tokens like [`tokenize_with_diagnostics`](tokenize_with_diagnostics) but with the
+/// closure `is_name` that decides whether the current rest of the text starts with a
+/// variable name.
+pub fn tokenize_with_names<'a, T, F, G>(
+    text: &'a str,
+    ops_in: &[Operator<'a, T>],
+    is_numeric: F,
+    is_name: G,
+    options: &ParseOptions,
+) -> Result<(Vec<ParsedToken<'a, T>>, Vec<ParseWarning>), ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+    F: Fn(&'a str) -> Option<&'a str>,
+    G: Fn(&'a str) -> Option<&'a str>,
+{
+    let (res, errors) = lex(text, ops_in, &is_numeric, &is_name, options)?;
+    if let Some(err) = errors.into_iter().next() {
+        return Err(err);
+    }
+    let ops = sorted_ops(ops_in);
+    let warnings = find_ambiguous_ops(text, &res, &ops);
+    if options.require_spaces_around_ambiguous_ops {
+        let is_space_or_end = |c: Option<char>| c.filter(|c| !c.is_whitespace()).is_none();
+        for warning in &warnings {
+            let ParseWarning::AmbiguousOperator {
+                position,
+                chosen,
+                shorter,
+                following,
+                context,
+            } = warning;
+            let end = position + chosen.len();
+            if !is_space_or_end(text[..*position].chars().next_back())
+                || !is_space_or_end(text[end..].chars().next())
+            {
+                return Err(ExError::AmbiguousOperator {
+                    position: *position,
+                    chosen: chosen.clone(),
+                    shorter: shorter.clone(),
+                    following: following.clone(),
+                    context: context.clone(),
+                });
+            }
+        }
+    }
+    let mut res = expand_loops(text, res, ops_in, options)?;
+    if options.implicit_multiplication {
+        res = insert_implicit_muls(res, ops_in)?;
+    }
+    check_preconditions(text, &res)?;
+    Ok((res, warnings))
+}
 
-    lazy_static! {
-        static ref RE_NAME: Regex = Regex::new(r"^[a-zA-Z_]+[a-zA-Z_0-9]*").unwrap();
+/// Returns the representations and aliases of the operators together with the operators
+/// sorted inverse alphabetically such that, e.g., `log2` is found before `log` and `**`
+/// before `*`.
+fn sorted_ops<'a, 'b, T: Copy>(
+    ops_in: &'b [Operator<'a, T>],
+) -> SmallVec<[(&'a str, &'b Operator<'a, T>); 64]> {
+    let mut ops = ops_in
+        .iter()
+        .flat_map(|op| {
+            core::iter::once(op.repr)
+                .chain(op.aliases.iter().copied())
+                .map(move |repr| (repr, op))
+        })
+        .collect::<SmallVec<[_; 64]>>();
+    ops.sort_by(|(r1, _), (r2, _)| r2.cmp(r1));
+    ops
+}
+
+/// Returns the position of the closing brace of a variable name in curly braces that starts
+/// with the opening brace of `text`. A backslash escapes the next character, e.g., `\}`
+/// does not close the name.
+pub fn end_of_braced_name(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '}' => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Returns the name of a variable or an unknown operator that `text` starts with. Names
+/// consist of ASCII letters, digits, and underscores and start with a letter or an
+/// underscore, see [`VAR_REGEX_PATTERN`](VAR_REGEX_PATTERN).
+pub fn is_name_text(text: &str) -> Option<&str> {
+    match text.as_bytes().first() {
+        Some(b) if b.is_ascii_alphabetic() || *b == b'_' => {
+            Some(&text[..text.bytes().take_while(|b| is_name_byte(*b)).count()])
+        }
+        _ => None,
     }
+}
+
+fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Returns whether `text` starts with the operator `repr`. Representations that consist of
+/// name characters, e.g., `exp`, only match if no further name character follows such that
+/// `e` does not match the beginning of the variable `energy`. Symbolic representations such
+/// as `**` match greedily.
+fn starts_with_op(text: &str, repr: &str) -> bool {
+    text.starts_with(repr)
+        && !(repr.bytes().all(is_name_byte)
+            && text
+                .as_bytes()
+                .get(repr.len())
+                .is_some_and(|b| is_name_byte(*b)))
+}
+
+/// Returns the length of the comment that `text` starts with and whether it is terminated.
+/// Line comments end before the next newline, block comments after `*/` or at the end of
+/// the text if they are unterminated.
+fn comment_len(text: &str) -> Option<(usize, bool)> {
+    if text.starts_with('#') {
+        Some((text.find('\n').unwrap_or(text.len()), true))
+    } else {
+        text.strip_prefix("/*").map(|rest| match rest.find("*/") {
+            Some(end) => (end + 4, true),
+            None => (text.len(), false),
+        })
+    }
+}
+
+/// Validates the operators and splits `text` into tokens without checking their order.
+/// Unexpected characters and numbers the number type cannot parse are skipped and
+/// returned as errors together with the tokens, such that all of them can be reported. An unterminated curly brace makes the rest of
+/// the text a variable and an unterminated block comment a comment.
+///
+/// # Errors
+///
+/// An [`ExError`](ExError) is returned if the operators are invalid, see
+/// [`check_operators`](validation::check_operators), if there are no operators but
+/// `text` contains more than numbers and parentheses, if `text` is longer than allowed,
+/// or as soon as the number of tokens, the number of nodes, or the nesting depth exceeds
+/// the [`ParseLimits`](ParseLimits).
+///
+#[allow(clippy::type_complexity)]
+pub fn lex<'a, T, F, G>(
+    text: &'a str,
+    ops_in: &[Operator<'a, T>],
+    is_numeric: &F,
+    is_name: &G,
+    options: &ParseOptions,
+) -> Result<(Vec<ParsedToken<'a, T>>, Vec<ExError>), ExError>
+where
+    <T as core::str::FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+    F: Fn(&'a str) -> Option<&'a str>,
+    G: Fn(&'a str) -> Option<&'a str>,
+{
+    if let Some(max) = options.limits.max_len.filter(|max| text.len() > *max) {
+        return Err(ExError::InputTooLong {
+            len: text.len(),
+            max,
+        });
+    }
+    validation::check_operators(ops_in)?;
+    if ops_in.is_empty() {
+        validation::check_text_without_ops(text, is_numeric)?;
+    }
+    let ops = sorted_ops(ops_in);
 
     let mut cur_offset = 0usize;
     let find_ops = |offset: usize| {
-        ops.iter().find(|op| {
-            let range_end = offset + op.repr.chars().count();
-            if range_end > text.len() {
-                false
-            } else {
-                op.repr == &text[offset..range_end]
-            }
-        })
+        ops.iter()
+            .find(|(repr, _)| starts_with_op(&text[offset..], repr))
     };
 
-    let mut res = Vec::new();
-    res.reserve(2 * N_NODES_ON_STACK);
+    let arg_separator = if options.decimal_comma { ';' } else { ',' };
+    let mut res = Vec::with_capacity(2 * N_NODES_ON_STACK);
+    let mut errors = vec![];
+    let mut n_nodes = 0usize;
+    let mut depth = 0usize;
 
-    for (i, c) in text.chars().enumerate() {
-        if c == ' ' {
-            cur_offset += 1;
-        }
-        if i == cur_offset && cur_offset < text.len() && c != ' ' {
-            let maybe_op;
-            let maybe_num;
-            let maybe_name;
+    for (i, c) in text.char_indices() {
+        if i == cur_offset && c.is_whitespace() {
+            cur_offset += c.len_utf8();
+        } else if i == cur_offset {
             let text_rest = &text[cur_offset..];
-            let next_parsed_token = if c == '(' {
-                cur_offset += 1;
-                ParsedToken::<T>::Paren(Paren::Open)
+            if options.comments {
+                if let Some((n_bytes, is_terminated)) = comment_len(text_rest) {
+                    if !is_terminated {
+                        let span = Span {
+                            start: cur_offset,
+                            end: text.len(),
+                        };
+                        errors.push(ExError::UnterminatedComment {
+                            position: cur_offset,
+                            context: context(text, span),
+                        });
+                    }
+                    cur_offset += n_bytes;
+                    continue;
+                }
+            }
+            let (kind, n_bytes) = if c == '(' {
+                (TokenKind::<T>::Paren(Paren::Open), 1)
             } else if c == ')' {
-                cur_offset += 1;
-                ParsedToken::<T>::Paren(Paren::Close)
-            } else if c == '{' {
-                let n_count = text_rest.chars().take_while(|c| *c != '}').count();
-                cur_offset += n_count + 1;
-                ParsedToken::<T>::Var(&text_rest[1..n_count])
-            } else if {
-                maybe_num = is_numeric(text_rest);
-                maybe_num.is_some()
-            } {
-                let num_str = maybe_num.unwrap();
-                let n_chars = num_str.chars().count();
-                cur_offset += n_chars;
-                ParsedToken::<T>::Num(num_str.parse::<T>().unwrap())
-            } else if {
-                maybe_op = find_ops(cur_offset);
-                maybe_op.is_some()
-            } {
-                let op = **maybe_op.unwrap();
-                let n_chars = op.repr.chars().count();
-                cur_offset += n_chars;
-                ParsedToken::<T>::Op(op)
-            } else if {
-                maybe_name = RE_NAME.find(text_rest);
-                maybe_name.is_some()
-            } {
-                let var_str = maybe_name.unwrap().as_str();
-                let n_chars = var_str.chars().count();
-                cur_offset += n_chars;
-                ParsedToken::<T>::Var(maybe_name.unwrap().as_str())
+                (TokenKind::<T>::Paren(Paren::Close), 1)
+            } else if c == '{' && options.curly_vars && find_ops(cur_offset).is_none() {
+                // the braces are not part of the name such that `{x}` and `x` are the same
+                match end_of_braced_name(text_rest) {
+                    Some(1) => {
+                        let span = Span {
+                            start: cur_offset,
+                            end: cur_offset + 2,
+                        };
+                        errors.push(ExError::EmptyVarName {
+                            position: cur_offset,
+                            context: context(text, span),
+                        });
+                        (TokenKind::<T>::Var(""), 2)
+                    }
+                    Some(n_bytes) => (TokenKind::<T>::Var(&text_rest[1..n_bytes]), n_bytes + 1),
+                    None => {
+                        let span = Span {
+                            start: cur_offset,
+                            end: text.len(),
+                        };
+                        errors.push(ExError::UnterminatedBrace {
+                            position: cur_offset,
+                            context: context(text, span),
+                        });
+                        (TokenKind::<T>::Var(&text_rest[1..]), text_rest.len())
+                    }
+                }
+            } else if let Some(num_str) = is_numeric(text_rest) {
+                let span = Span {
+                    start: cur_offset,
+                    end: cur_offset + num_str.len(),
+                };
+                let num = if options.decimal_comma {
+                    parse_num(&num_str.replace(',', "."), text, span)
+                } else {
+                    parse_num(num_str, text, span)
+                };
+                match num {
+                    Ok(num) => (TokenKind::<T>::Num(num), num_str.len()),
+                    Err(e) => {
+                        errors.push(e);
+                        cur_offset = span.end;
+                        continue;
+                    }
+                }
+            } else if let Some((repr, op)) = find_ops(cur_offset) {
+                (TokenKind::<T>::Op(**op), repr.len())
+            } else if c == arg_separator {
+                (TokenKind::<T>::Comma, 1)
+            } else if let Some(name) = is_name(text_rest) {
+                (TokenKind::<T>::Var(name), name.len())
             } else {
-                let msg = format!("how to parse the beginning of {}", text_rest);
-                return Err(ExParseError { msg });
+                let span = Span {
+                    start: cur_offset,
+                    end: cur_offset + c.len_utf8(),
+                };
+                errors.push(ExError::UnexpectedToken {
+                    position: cur_offset,
+                    found: c.to_string(),
+                    context: context(text, span),
+                });
+                cur_offset = span.end;
+                continue;
+            };
+            let span = Span {
+                start: cur_offset,
+                end: cur_offset + n_bytes,
             };
-            res.push(next_parsed_token);
+            let limits = &options.limits;
+            if let Some(max) = limits.max_tokens.filter(|max| res.len() >= *max) {
+                return Err(ExError::TooManyTokens {
+                    position: span.start,
+                    max,
+                    context: context(text, span),
+                });
+            }
+            match kind {
+                TokenKind::Num(_) | TokenKind::Var(_) => {
+                    n_nodes += 1;
+                    if let Some(max) = limits.max_nodes.filter(|max| n_nodes > *max) {
+                        return Err(ExError::TooManyNodes {
+                            position: span.start,
+                            max,
+                            context: context(text, span),
+                        });
+                    }
+                }
+                TokenKind::Paren(Paren::Open) => {
+                    depth += 1;
+                    if let Some(max) = limits.max_depth.filter(|max| depth > *max) {
+                        return Err(ExError::NestingTooDeep {
+                            position: span.start,
+                            max,
+                            context: context(text, span),
+                        });
+                    }
+                }
+                TokenKind::Paren(Paren::Close) => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            cur_offset = span.end;
+            res.push(ParsedToken { kind, span });
         }
     }
-    check_preconditions(&res)?;
+    Ok((res, errors))
+}
+
+/// Finds operators that could also be read as a shorter operator followed by another
+/// operator or a sign. The representations `ops` are expected to be sorted inverse
+/// alphabetically, such that longer operators come first.
+fn find_ambiguous_ops<T: Copy + FromStr>(
+    text: &str,
+    tokens: &[ParsedToken<T>],
+    ops: &[(&str, &Operator<T>)],
+) -> Vec<ParseWarning> {
+    tokens
+        .iter()
+        .filter_map(|token| match &token.kind {
+            // the text of the token is the alias the operator has been found by, if any
+            TokenKind::Op(_) => Some((&text[token.span.start..token.span.end], token.span)),
+            _ => None,
+        })
+        .filter_map(|(chosen, span)| {
+            ops.iter()
+                .map(|(repr, _)| *repr)
+                .filter(|shorter| {
+                    !shorter.is_empty()
+                        && shorter.len() < chosen.len()
+                        && chosen.starts_with(shorter)
+                        && starts_with_op(&text[span.start..], shorter)
+                })
+                .find_map(|shorter| {
+                    let rest = &text[span.start + shorter.len()..];
+                    let following = ops
+                        .iter()
+                        .map(|(repr, _)| *repr)
+                        .chain(["-", "+"])
+                        .find(|following| !following.is_empty() && rest.starts_with(following))?;
+                    Some(ParseWarning::AmbiguousOperator {
+                        position: span.start,
+                        chosen: chosen.to_string(),
+                        shorter: shorter.to_string(),
+                        following: following.to_string(),
+                        context: context(text, span),
+                    })
+                })
+        })
+        .collect()
+}
+
+/// Inserts the multiplication operator between tokens that are multiplied implicitly such
+/// as `2x`. The inserted tokens have empty spans in front of their right neighbors.
+pub fn insert_implicit_muls<'a, T: Copy + FromStr>(
+    tokens: Vec<ParsedToken<'a, T>>,
+    ops: &[Operator<'a, T>],
+) -> Result<Vec<ParsedToken<'a, T>>, ExError> {
+    let is_implicit_mul = |left: &TokenKind<T>, right: &TokenKind<T>| {
+        let left_fits = match left {
+            TokenKind::Num(_) | TokenKind::Var(_) | TokenKind::Paren(Paren::Close) => true,
+            TokenKind::Op(op) => op.is_postfix() && op.bin_op.is_none(),
+            _ => false,
+        };
+        let right_fits = match right {
+            TokenKind::Var(_) | TokenKind::Paren(Paren::Open) => true,
+            TokenKind::Op(op) => op.bin_op.is_none() && !op.is_postfix(),
+            _ => false,
+        };
+        left_fits && right_fits
+    };
+    if !tokens
+        .windows(2)
+        .any(|pair| is_implicit_mul(&pair[0].kind, &pair[1].kind))
+    {
+        return Ok(tokens);
+    }
+    let mul_op = *ops
+        .iter()
+        .find(|op| op.repr == "*" && op.bin_op.is_some())
+        .ok_or_else(|| ExError::OperatorNotFound("*".to_string()))?;
+    let mut res: Vec<ParsedToken<'a, T>> = Vec::with_capacity(2 * tokens.len());
+    for token in tokens {
+        if let Some(left) = res.last() {
+            if is_implicit_mul(&left.kind, &token.kind) {
+                let span = Span {
+                    start: token.span.start,
+                    end: token.span.start,
+                };
+                res.push(ParsedToken {
+                    kind: TokenKind::Op(mul_op),
+                    span,
+                });
+            }
+        }
+        res.push(token);
+    }
     Ok(res)
 }
 
-struct PairPreCondition<'a, 'b, T: Copy + FromStr> {
-    apply: fn(&ParsedToken<'a, T>, &ParsedToken<'a, T>) -> bool,
-    error_msg: &'b str,
+struct PairPreCondition<'a, T: Copy + FromStr> {
+    apply: fn(&TokenKind<'a, T>, &TokenKind<'a, T>) -> bool,
+    violation: PairViolation,
 }
 
-fn make_pair_pre_conditions<'a, 'b, T: Copy + FromStr>() -> Vec<PairPreCondition<'a, 'b, T>> {
+fn make_pair_pre_conditions<'a, T: Copy + FromStr>() -> Vec<PairPreCondition<'a, T>> {
     vec![
         PairPreCondition {
             apply: |left, right| {
                 !matches!(
                     (left, right),
-                    (ParsedToken::Num(_), ParsedToken::Var(_))
-                        | (ParsedToken::Var(_), ParsedToken::Num(_))
-                        | (ParsedToken::Num(_), ParsedToken::Num(_))
-                        | (ParsedToken::Var(_), ParsedToken::Var(_))
+                    (TokenKind::Num(_), TokenKind::Var(_))
+                        | (TokenKind::Var(_), TokenKind::Num(_))
+                        | (TokenKind::Num(_), TokenKind::Num(_))
+                        | (TokenKind::Var(_), TokenKind::Var(_))
                 )
             },
-            error_msg: "a number/variable cannot be next to a number/variable",
+            violation: PairViolation::AdjacentOperands,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Paren(_p @ Paren::Close), ParsedToken::Num(_))
-                | (ParsedToken::Paren(_p @ Paren::Close), ParsedToken::Var(_))
-                | (ParsedToken::Num(_), ParsedToken::Paren(_p @ Paren::Open))
-                | (ParsedToken::Var(_), ParsedToken::Paren(_p @ Paren::Open)) => false,
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Paren(Paren::Close), TokenKind::Num(_))
+                        | (TokenKind::Paren(Paren::Close), TokenKind::Var(_))
+                        | (TokenKind::Num(_), TokenKind::Paren(Paren::Open))
+                        | (TokenKind::Var(_), TokenKind::Paren(Paren::Open))
+                )
             },
-            error_msg: "wlog a number/variable cannot be on the right of a closing parenthesis",
+            violation: PairViolation::OperandNextToParen,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Num(_), ParsedToken::Op(op))
-                | (ParsedToken::Var(_), ParsedToken::Op(op))
-                    if op.bin_op.is_none() =>
-                {
-                    false
-                }
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Num(_), TokenKind::Op(op))
+                        | (TokenKind::Var(_), TokenKind::Op(op))
+                    if op.bin_op.is_none() && !op.is_postfix()
+                )
             },
-            error_msg: "a number/variable cannot be on the left of a unary operator",
+            violation: PairViolation::OperandBeforeUnary,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Op(op_l), ParsedToken::Op(op_r))
-                    if op_l.unary_op.is_none() && op_r.unary_op.is_none() =>
-                {
-                    false
-                }
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Op(op_l), TokenKind::Op(op_r))
+                    if op_l.unary_op.is_none() && op_r.unary_op.is_none() && op_r.nary_op.is_none()
+                )
             },
-            error_msg: "a binary operator cannot be next to a binary operator",
+            violation: PairViolation::AdjacentBinaryOps,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Op(op_l), ParsedToken::Op(op_r))
-                    if op_l.bin_op.is_none() && op_r.unary_op.is_none() =>
-                {
-                    false
-                }
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Op(op_l), TokenKind::Op(op_r))
+                    if op_l.bin_op.is_none()
+                        && !op_l.is_postfix()
+                        && op_r.unary_op.is_none()
+                        && op_r.nary_op.is_none()
+                )
             },
-            error_msg: "a binary operator cannot be on the right of a unary",
+            violation: PairViolation::BinaryAfterUnary,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Op(_), ParsedToken::Paren(_p @ Paren::Close)) => false,
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Op(op), TokenKind::Paren(Paren::Close))
+                    if !op.is_postfix()
+                )
             },
-            error_msg: "an operator cannot be on the left of a closing paren",
+            violation: PairViolation::OpBeforeClosingParen,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Paren(_p @ Paren::Close), ParsedToken::Op(op))
-                    if op.bin_op.is_none() =>
-                {
-                    false
-                }
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Paren(Paren::Close), TokenKind::Op(op))
+                    if op.bin_op.is_none() && !op.is_postfix()
+                )
             },
-            error_msg: "a unary operator cannot be on the right of a closing paren",
+            violation: PairViolation::UnaryAfterClosingParen,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (ParsedToken::Paren(_p @ Paren::Open), ParsedToken::Op(op))
-                    if op.unary_op.is_none() =>
-                {
-                    false
-                }
-                _ => true,
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (TokenKind::Paren(Paren::Open), TokenKind::Op(op))
+                    if op.unary_op.is_none() && op.nary_op.is_none()
+                )
+            },
+            violation: PairViolation::BinaryAfterOpeningParen,
+        },
+        PairPreCondition {
+            apply: |left, right| {
+                !matches!(
+                    (left, right),
+                    (
+                        TokenKind::Paren(Paren::Open),
+                        TokenKind::Paren(Paren::Close)
+                    )
+                )
             },
-            error_msg: "a binary operator cannot be on the right of an opening paren",
+            violation: PairViolation::EmptyParens,
         },
         PairPreCondition {
-            apply: |left, right| match (left, right) {
-                (
-                    ParsedToken::Paren(_p_l @ Paren::Open),
-                    ParsedToken::Paren(_p_r @ Paren::Close),
-                ) => false,
-                _ => true,
+            apply: |left, right| {
+                let postfix_after_non_operand = matches!(right, TokenKind::Op(op) if op.is_postfix())
+                    && !is_end_of_operand(left);
+                let operand_after_postfix = matches!(
+                    (left, right),
+                    (
+                        TokenKind::Op(op),
+                        TokenKind::Num(_) | TokenKind::Var(_) | TokenKind::Paren(Paren::Open)
+                    ) if op.is_postfix() && op.bin_op.is_none()
+                );
+                !postfix_after_non_operand && !operand_after_postfix
             },
-            error_msg: "wlog an opening paren cannot be next to a closing paren",
+            violation: PairViolation::MisplacedPostfixUnary,
+        },
+        PairPreCondition {
+            apply: |left, right| {
+                let is_start_of_operand = match right {
+                    TokenKind::Num(_) | TokenKind::Var(_) | TokenKind::Paren(Paren::Open) => true,
+                    TokenKind::Op(op) => {
+                        (op.unary_op.is_some() && !op.is_postfix()) || op.nary_op.is_some()
+                    }
+                    _ => false,
+                };
+                let comma_after_non_operand =
+                    matches!(right, TokenKind::Comma) && !is_end_of_operand(left);
+                let non_operand_after_comma =
+                    matches!(left, TokenKind::Comma) && !is_start_of_operand;
+                !comma_after_non_operand && !non_operand_after_comma
+            },
+            violation: PairViolation::MisplacedComma,
         },
     ]
 }
 
+/// Returns whether a postfix unary operator can be applied to the left of `token`, which
+/// is the case for the ends of numbers, variables, parenthesized expressions and
+/// postfix operators.
+fn is_end_of_operand<T: Copy + FromStr>(token: &TokenKind<T>) -> bool {
+    match token {
+        TokenKind::Num(_) | TokenKind::Var(_) | TokenKind::Paren(Paren::Close) => true,
+        TokenKind::Op(op) => op.is_postfix(),
+        TokenKind::Paren(Paren::Open) | TokenKind::Comma => false,
+    }
+}
+
+/// Returns `token` where an operator that is prefix unary and binary, e.g., `-`, only keeps
+/// the variant it is used as. Like in
+/// [`make_expression`](crate::expression::deep_details::make_expression), it is binary if
+/// and only if the `previous` token is the end of an operand. Hence, `3-*2` is reported as
+/// adjacent binary operators and `3*-*2` as a binary operator after a unary one.
+fn with_used_variant<'a, T: Copy + FromStr>(
+    previous: Option<&TokenKind<'a, T>>,
+    token: &TokenKind<'a, T>,
+) -> TokenKind<'a, T> {
+    match token {
+        TokenKind::Op(op) if op.bin_op.is_some() && op.unary_op.is_some() && !op.is_postfix() => {
+            let mut op = *op;
+            if previous.is_some_and(is_end_of_operand) {
+                op.unary_op = None;
+            } else {
+                op.bin_op = None;
+            }
+            TokenKind::Op(op)
+        }
+        _ => token.clone(),
+    }
+}
+
 /// Tries to give useful error messages for invalid constellations of the parsed tokens
 ///
 /// # Arguments
 ///
+/// * `text` - text the tokens have been parsed from
 /// * `parsed_tokens` - parsed tokens
 ///
 /// # Errors
 ///
 /// See [`parse_with_number_pattern`](parse_with_number_pattern)
 ///
-pub fn check_preconditions<T>(parsed_tokens: &[ParsedToken<T>]) -> Result<u8, ExParseError>
+pub fn check_preconditions<T>(text: &str, parsed_tokens: &[ParsedToken<T>]) -> Result<u8, ExError>
 where
-    T: Copy + FromStr + std::fmt::Debug,
+    T: Copy + FromStr + core::fmt::Debug,
 {
-    if parsed_tokens.len() == 0 {
-        return Err(ExParseError {
-            msg: "cannot parse empty string".to_string(),
-        });
+    match collect_violations(text, parsed_tokens, &[], true)
+        .into_iter()
+        .next()
+    {
+        Some(violation) => Err(violation.error),
+        None => Ok(0),
+    }
+}
+
+/// Invalid constellation of parsed tokens.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub error: ExError,
+    /// Indices of the first and the last involved token, if any.
+    pub tokens: Option<(usize, usize)>,
+}
+
+/// Returns all invalid constellations of the parsed tokens sorted by their positions.
+/// Pairs of tokens that are separated by one of the byte offsets `gaps`, e.g., skipped
+/// characters, are not checked.
+pub fn find_violations<T>(
+    text: &str,
+    parsed_tokens: &[ParsedToken<T>],
+    gaps: &[usize],
+) -> Vec<Violation>
+where
+    T: Copy + FromStr + core::fmt::Debug,
+{
+    let mut violations = collect_violations(text, parsed_tokens, gaps, false);
+    violations.sort_by_key(|violation| violation.error.position());
+    violations.dedup();
+    violations
+}
+
+/// Collects invalid constellations of the parsed tokens. If `first_only` is true, at most
+/// one violation is returned, namely the one [`check_preconditions`](check_preconditions)
+/// reports.
+fn collect_violations<T>(
+    text: &str,
+    parsed_tokens: &[ParsedToken<T>],
+    gaps: &[usize],
+    first_only: bool,
+) -> Vec<Violation>
+where
+    T: Copy + FromStr + core::fmt::Debug,
+{
+    let mut violations = vec![];
+    macro_rules! report {
+        ($err:expr, $tokens:expr) => {{
+            violations.push(Violation {
+                error: $err,
+                tokens: $tokens,
+            });
+            if first_only {
+                return violations;
+            }
+        }};
+    }
+    if parsed_tokens.is_empty() {
+        report!(ExError::EmptyInput, None);
+        return violations;
     };
 
+    let first = &parsed_tokens[0];
+    if matches!(first.kind, TokenKind::Op(op) if op.is_postfix()) {
+        report!(
+            ExError::InvalidPair {
+                position: first.span.start,
+                violation: PairViolation::MisplacedPostfixUnary,
+                context: context(text, first.span),
+            },
+            Some((0, 0))
+        );
+    }
     let pair_pre_conditions = make_pair_pre_conditions::<T>();
-    (0..parsed_tokens.len() - 1)
-        .map(|i| -> Result<(), ExParseError> {
-            let failed = pair_pre_conditions
-                .iter()
-                .map(|ppc| (ppc, (ppc.apply)(&parsed_tokens[i], &parsed_tokens[i + 1])))
-                .find(|(_, ppc_passed)| !ppc_passed);
-            match failed {
-                Some((failed_ppc, _)) => Err(ExParseError {
-                    msg: failed_ppc.error_msg.to_string(),
-                }),
-                None => Ok(()),
-            }
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+    for (i, pair) in parsed_tokens.windows(2).enumerate() {
+        let (left, right) = (&pair[0], &pair[1]);
+        if gaps
+            .iter()
+            .any(|gap| (left.span.end..right.span.start).contains(gap))
+        {
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|i_prev| &parsed_tokens[i_prev].kind);
+        let left_kind = with_used_variant(previous, &left.kind);
+        let failed = pair_pre_conditions
+            .iter()
+            .find(|ppc| !(ppc.apply)(&left_kind, &right.kind));
+        if let Some(failed_ppc) = failed {
+            report!(
+                ExError::InvalidPair {
+                    position: right.span.start,
+                    violation: failed_ppc.violation,
+                    context: context(text, right.span),
+                },
+                Some((i, i + 1))
+            );
+        }
+    }
 
-    let mut open_paren_cnt = 0i32;
-    parsed_tokens
-        .iter()
-        .enumerate()
-        .map(|(i, expr_elt)| -> Result<(), ExParseError> {
-            match expr_elt {
-                ParsedToken::Paren(p) => {
-                    open_paren_cnt += match p {
-                        Paren::Close => -1,
-                        Paren::Open => 1,
-                    };
-                    if open_paren_cnt < 0 {
-                        return Err(ExParseError {
-                            msg: format!("too many closing parentheses until position {}", i)
-                                ,
-                        });
-                    }
-                    Ok(())
+    let paren_mismatch = |span: Span| ExError::ParenMismatch {
+        position: span.start,
+        context: context(text, span),
+    };
+    let nary_arity = |token: &ParsedToken<T>| match token.kind {
+        TokenKind::Op(op) => op.nary_op.map(|nary_op| nary_op.arity),
+        _ => None,
+    };
+    let wrong_number_of_args = |op_idx: usize, end: usize, given: usize| {
+        let op_token = &parsed_tokens[op_idx];
+        let span = Span {
+            start: op_token.span.start,
+            end,
+        };
+        ExError::WrongNumberOfArgs {
+            position: span.start,
+            op: text[op_token.span.start..op_token.span.end].to_string(),
+            expected: nary_arity(op_token).unwrap_or(0),
+            given,
+            context: context(text, span),
+        }
+    };
+    // indices of the open parentheses and of the n-ary operators they belong to, if any,
+    // and the number of commas they contain so far
+    let mut open_parens = SmallVec::<[(usize, Option<usize>, usize); N_NODES_ON_STACK]>::new();
+    for (i, token) in parsed_tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Op(_) if nary_arity(token).is_some() => {
+                let next = parsed_tokens.get(i + 1).map(|next| &next.kind);
+                if !matches!(next, Some(TokenKind::Paren(Paren::Open))) {
+                    report!(wrong_number_of_args(i, token.span.end, 0), Some((i, i)));
                 }
-                _ => Ok(()),
             }
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    if open_paren_cnt != 0 {
-        Err(ExParseError {
-            msg: "parentheses mismatch".to_string(),
-        })
-    } else if match parsed_tokens[parsed_tokens.len() - 1] {
-        ParsedToken::Op(_) => true,
-        _ => false,
-    } {
-        Err(ExParseError {
-            msg: "the last element cannot be an operator".to_string(),
-        })
-    } else {
-        Ok(0)
+            TokenKind::Paren(Paren::Open) => {
+                let call = i
+                    .checked_sub(1)
+                    .filter(|i_op| nary_arity(&parsed_tokens[*i_op]).is_some());
+                open_parens.push((i, call, 0));
+            }
+            TokenKind::Paren(Paren::Close) => match open_parens.pop() {
+                Some((_, Some(op_idx), n_commas))
+                    if nary_arity(&parsed_tokens[op_idx]) != Some(n_commas + 1) =>
+                {
+                    report!(
+                        wrong_number_of_args(op_idx, token.span.end, n_commas + 1),
+                        Some((op_idx, i))
+                    );
+                }
+                Some(_) => (),
+                None => report!(paren_mismatch(token.span), Some((i, i))),
+            },
+            TokenKind::Comma => match open_parens.last_mut() {
+                Some((_, Some(_), n_commas)) => *n_commas += 1,
+                _ => {
+                    // hint at the option for a comma between digits such as `3,5`
+                    let bytes = text.as_bytes();
+                    let is_digit = |idx: Option<usize>| {
+                        idx.and_then(|idx| bytes.get(idx))
+                            .is_some_and(u8::is_ascii_digit)
+                    };
+                    let violation = if bytes[token.span.start] == b','
+                        && is_digit(token.span.start.checked_sub(1))
+                        && is_digit(Some(token.span.end))
+                    {
+                        PairViolation::DecimalComma
+                    } else {
+                        PairViolation::MisplacedComma
+                    };
+                    report!(
+                        ExError::InvalidPair {
+                            position: token.span.start,
+                            violation,
+                            context: context(text, token.span),
+                        },
+                        Some((i, i))
+                    )
+                }
+            },
+            _ => (),
+        }
+    }
+    // the innermost unclosed parenthesis is reported first
+    for &(paren_idx, _, _) in open_parens.iter().rev() {
+        report!(
+            paren_mismatch(parsed_tokens[paren_idx].span),
+            Some((paren_idx, paren_idx))
+        );
+    }
+    let last_idx = parsed_tokens.len() - 1;
+    let last = &parsed_tokens[last_idx];
+    if matches!(last.kind, TokenKind::Op(op) if !op.is_postfix()) {
+        report!(
+            ExError::TrailingOperator {
+                position: last.span.start,
+                context: context(text, last.span),
+            },
+            Some((last_idx, last_idx))
+        );
     }
+    violations
 }
-#[cfg(test)]
-use crate::operators;
+
+#[cfg(all(test, feature = "std"))]
+use crate::operators::{self, BinOp, UnaryPosition};
+#[cfg(feature = "std")]
 #[test]
 fn test_apply_regexes() {
     let text = r"5\6";
     let ops = operators::make_default_operators::<f32>();
-    let elts = tokenize_and_analyze(text, &ops, is_numeric_text);
+    let elts = tokenize_and_analyze(text, &ops, is_numeric_text, &ParseOptions::default());
     assert!(elts.is_err());
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_is_numeric() {
     assert_eq!(is_numeric_text("5/6").unwrap(), "5");
@@ -358,61 +1344,658 @@ fn test_is_numeric() {
     assert_eq!(is_numeric_text("4.").unwrap(), "4.");
     assert_eq!(is_numeric_text(".4").unwrap(), ".4");
     assert_eq!(is_numeric_text("23.414").unwrap(), "23.414");
+    assert_eq!(is_numeric_text("1e-3+x").unwrap(), "1e-3");
+    assert_eq!(is_numeric_text("2.5E+10").unwrap(), "2.5E+10");
+    assert_eq!(is_numeric_text(".5e2").unwrap(), ".5e2");
+    assert_eq!(is_numeric_text("2e").unwrap(), "2");
+    assert_eq!(is_numeric_text("2e+x").unwrap(), "2");
+    assert_eq!(is_numeric_text("2E-").unwrap(), "2");
+    assert_eq!(is_numeric_text("1_000.5_5").unwrap(), "1_000.5_5");
+    assert_eq!(is_numeric_text("1__0").unwrap(), "1");
+    assert_eq!(is_numeric_text("1_x").unwrap(), "1");
+    assert_eq!(is_numeric_text("1e1_0_").unwrap(), "1e1_0");
+    assert!(is_numeric_text("_1").is_none());
+    assert!(is_numeric_text("e5").is_none());
+    assert!(is_numeric_text("1.2.3").is_none());
+    let re = Regex::new(&format!("^({})", NUMBER_REGEX_PATTERN)).unwrap();
+    for text in [
+        "5/6",
+        ".4",
+        "4.",
+        "1e-3+x",
+        "2.5E+10",
+        "2e+x",
+        "1_000.5_5",
+        "1__0",
+        "1e1_0_",
+        "_1",
+    ] {
+        assert_eq!(is_numeric_regex(&re, text), is_numeric_text(text));
+    }
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn test_preconditions() {
-    fn test(text: &str, msg_part: &str) {
-        fn check_err_msg<V>(err: Result<V, ExParseError>, msg_part: &str) {
-            match err {
-                Ok(_) => {
-                    println!("expected an error that should contain '{}'", msg_part);
-
-                    assert!(false)
-                }
-                Err(e) => {
-                    println!("msg '{}' should contain '{}'", e.msg, msg_part);
-                    assert!(e.msg.contains(msg_part));
-                }
-            }
-        }
+    fn test(text: &str, expected: ExError) {
         let ops = operators::make_default_operators::<f32>();
-        let elts = tokenize_and_analyze(text, &ops, is_numeric_text);
-        match elts {
-            Ok(elts_unwr) => {
-                let err = check_preconditions(&elts_unwr[..]);
-                check_err_msg(err, msg_part);
-            }
-            Err(_) => check_err_msg(elts, msg_part),
+        match tokenize_and_analyze(text, &ops, is_numeric_text, &ParseOptions::default()) {
+            Ok(_) => panic!("expected error {:?} for '{}'", expected, text),
+            Err(e) => assert_eq!(e, expected),
         }
     }
-    test("xo-17-(((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((expWW-tr-3746-4+sinnex-nn--nnexpWW-tr-7492-4+4-nsqrnexq+---------282)-384", "parentheses mismatch");
-    test("fi.g", "parse the beginning of .g");
-    test("(nc7)sqrtE", "unary operator cannot be on the right");
-    test("", "empty string");
-    test("++", "the last element cannot be an operator");
-    test(
-        "a12 (1)",
-        "wlog a number/variable cannot be on the right of a closing paren",
+    fn test_pair(text: &str, position: usize, violation: PairViolation, context: &str) {
+        test(
+            text,
+            ExError::InvalidPair {
+                position,
+                violation,
+                context: context.to_string(),
+            },
+        );
+    }
+    fn test_paren(text: &str, position: usize, context: &str) {
+        test(
+            text,
+            ExError::ParenMismatch {
+                position,
+                context: context.to_string(),
+            },
+        );
+    }
+    fn test_unexpected(text: &str, position: usize, found: &str) {
+        test(
+            text,
+            ExError::UnexpectedToken {
+                position,
+                found: found.to_string(),
+                context: text.to_string(),
+            },
+        );
+    }
+    let ops = operators::make_default_operators::<f32>();
+    let long = "xo-17-(((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((((expWW-tr-3746-4+sinnex-nn--nnexpWW-tr-7492-4+4-nsqrnexq+---------282)-384";
+    let unlimited = ParseOptions {
+        limits: ParseLimits {
+            max_depth: None,
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+    assert!(matches!(
+        tokenize_and_analyze(long, &ops, is_numeric_text, &unlimited),
+        Err(ExError::ParenMismatch { .. })
+    ));
+    assert!(matches!(
+        tokenize_and_analyze(long, &ops, is_numeric_text, &ParseOptions::default()),
+        Err(ExError::ParenMismatch { .. })
+    ));
+    let limited = ParseOptions {
+        limits: ParseLimits {
+            max_depth: Some(128),
+            ..ParseLimits::default()
+        },
+        ..ParseOptions::default()
+    };
+    assert!(matches!(
+        tokenize_and_analyze(long, &ops, is_numeric_text, &limited),
+        Err(ExError::NestingTooDeep { max: 128, .. })
+    ));
+    test_unexpected("fi.g", 2, ".");
+    test_pair(
+        "(nc7)sqrt E",
+        5,
+        PairViolation::UnaryAfterClosingParen,
+        "(nc7)sqrt E",
     );
-    test("++)", "operator cannot be on the left of a closing");
-    test(")+12-(1+1) / (", "closing parentheses until position");
-    test("12-()+(", "wlog an opening paren");
-    test("12-() ())", "wlog an opening paren");
-    test("12-(3-4)*2+ (1/2))", "closing parentheses until");
-    test("12-(3-4)*2+ ((1/2)", "parentheses mismatch");
-    test(r"5\6", r"how to parse the beginning of \");
-    test(
-        r"3 * log2 * 5",
-        r"a binary operator cannot be on the right of a unary",
+    // without the space, sqrtE is a variable
+    test_pair(
+        "(nc7)sqrtE",
+        5,
+        PairViolation::OperandNextToParen,
+        "(nc7)sqrtE",
     );
-    test(r"3.4.", r"how to parse the beginning of 3.4.");
+    test("", ExError::EmptyInput);
     test(
-        r"3. .4",
-        r"a number/variable cannot be next to a number/variable",
+        "++",
+        ExError::TrailingOperator {
+            position: 1,
+            context: "++".to_string(),
+        },
     );
-    test(
+    test_pair("a12 (1)", 4, PairViolation::OperandNextToParen, "a12 (1)");
+    test_pair("++)", 2, PairViolation::OpBeforeClosingParen, "++)");
+    test_paren(")+12-(1+1) / (", 0, ")+12-(");
+    test_pair("12-()+(", 4, PairViolation::EmptyParens, "12-()+(");
+    test_pair("12-() ())", 4, PairViolation::EmptyParens, "12-() ())");
+    test_paren("12-(3-4)*2+ (1/2))", 17, "(1/2))");
+    test_paren("12-(3-4)*2+ ((1/2)", 12, ")*2+ ((1/2)");
+    test_unexpected(r"5\6", 1, r"\");
+    test_pair(
+        r"3 * log2 * 5",
+        9,
+        PairViolation::BinaryAfterUnary,
+        "log2 * 5",
+    );
+    test_unexpected(r"3.4.", 0, "3");
+    test_pair(r"3. .4", 3, PairViolation::AdjacentOperands, "3. .4");
+    test_pair(
         r"2sin({x})",
-        r"number/variable cannot be on the left of a unary",
+        1,
+        PairViolation::OperandBeforeUnary,
+        "2sin({x})",
+    );
+    // operators do not span whitespace
+    test_pair("2 * * 3", 4, PairViolation::AdjacentBinaryOps, "2 * * 3");
+    test_pair("2 / / 3", 4, PairViolation::AdjacentBinaryOps, "2 / / 3");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_error_positions() {
+    let ops = operators::make_default_operators::<f64>();
+    let text = "1 + 2 */ 3 + 4";
+    let err =
+        tokenize_and_analyze(text, &ops, is_numeric_text, &ParseOptions::default()).unwrap_err();
+    assert_eq!(err.position(), Some(7));
+    assert_eq!(
+        err,
+        ExError::InvalidPair {
+            position: 7,
+            violation: PairViolation::AdjacentBinaryOps,
+            context: "+ 2 */ 3 + ".to_string()
+        }
+    );
+    assert_eq!(err.code(), "E017");
+
+    let err = tokenize_and_analyze("((1+2)", &ops, is_numeric_text, &ParseOptions::default())
+        .unwrap_err();
+    assert_eq!(err.position(), Some(0));
+    assert!(matches!(err, ExError::ParenMismatch { .. }));
+
+    // positions are byte offsets also after multi-byte characters
+    let text = "{αβ} + 2 ++ ((1)*)";
+    let err =
+        tokenize_and_analyze(text, &ops, is_numeric_text, &ParseOptions::default()).unwrap_err();
+    assert_eq!(err.position(), Some(text.find("*)").unwrap() + 1));
+    let err = tokenize_and_analyze(
+        "{a b} + 3 ~",
+        &ops,
+        is_numeric_text,
+        &ParseOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(err.position(), Some(10));
+    assert!(
+        tokenize_and_analyze("{a b} + 3", &ops, is_numeric_text, &ParseOptions::default()).is_ok()
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_invalid_numbers() {
+    // the default number pattern matches floats that integers cannot be parsed from
+    let ops = operators::make_default_operators_int::<i64>();
+    for (text, position, found) in [("1e3+1", 0, "1e3"), ("x * 2.5", 4, "2.5")] {
+        let err = crate::parse::<i64>(text, &ops).unwrap_err();
+        assert_eq!(err.code(), "E064");
+        assert_eq!(err.position(), Some(position));
+        assert!(matches!(&err, ExError::InvalidNumber { found: f, .. } if f == found));
+    }
+    let errs = crate::check::<i64>("2.5 + 1.5", &ops).unwrap_err();
+    let positions = errs
+        .iter()
+        .filter(|err| err.code() == "E064")
+        .map(|err| err.position())
+        .collect::<Vec<_>>();
+    assert_eq!(positions, [Some(0), Some(6)]);
+    assert_eq!(
+        crate::parse::<i64>("1_000 + 1", &ops)
+            .unwrap()
+            .eval(&[])
+            .unwrap(),
+        1001
+    );
+    let ops = operators::make_default_operators::<f64>();
+    let err = crate::parse_with_number_pattern::<f64>("1..2", &ops, r"[0-9.]+").unwrap_err();
+    assert_eq!(err.position(), Some(0));
+    assert_eq!(err.code(), "E064");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_implicit_muls() {
+    let ops = operators::make_default_operators::<f64>();
+    let implicit = ParseOptions {
+        implicit_multiplication: true,
+        ..ParseOptions::default()
+    };
+    let reprs = |text| {
+        tokenize_and_analyze(text, &ops, is_numeric_text, &implicit)
+            .unwrap()
+            .iter()
+            .map(|token| match &token.kind {
+                TokenKind::Op(op) => op.repr.to_string(),
+                TokenKind::Num(n) => n.to_string(),
+                TokenKind::Var(name) => name.to_string(),
+                TokenKind::Paren(Paren::Open) => "(".to_string(),
+                TokenKind::Paren(Paren::Close) => ")".to_string(),
+                TokenKind::Comma => ",".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(reprs("2x"), "2 * x");
+    assert_eq!(reprs("2(x+1)"), "2 * ( x + 1 )");
+    assert_eq!(reprs("(a+b)(a-b)"), "( a + b ) * ( a - b )");
+    assert_eq!(reprs("3sin(x)"), "3 * sin ( x )");
+    assert_eq!(reprs("2-x"), "2 - x");
+    assert_eq!(reprs("x y"), "x * y");
+
+    // numbers on the right are never multiplied implicitly
+    let err = tokenize_and_analyze("(x)2", &ops, is_numeric_text, &implicit).unwrap_err();
+    assert!(matches!(
+        err,
+        ExError::InvalidPair {
+            position: 3,
+            violation: PairViolation::OperandNextToParen,
+            ..
+        }
+    ));
+
+    let no_mul = [Operator {
+        repr: "-",
+        bin_op: None,
+        unary_op: Some(|a: f64| -a),
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    }];
+    assert_eq!(
+        tokenize_and_analyze("2x", &no_mul, is_numeric_text, &implicit).unwrap_err(),
+        ExError::OperatorNotFound("*".to_string())
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_ambiguous_ops() {
+    let ops = [
+        Operator {
+            repr: "<",
+            bin_op: Some(BinOp {
+                apply: |a: f64, b| if a < b { 1.0 } else { 0.0 },
+                prio: 0,
+            }),
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        },
+        Operator {
+            repr: "<-",
+            bin_op: Some(BinOp {
+                apply: |_, b| b,
+                prio: 0,
+            }),
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        },
+        Operator {
+            repr: "-",
+            bin_op: Some(BinOp {
+                apply: |a, b| a - b,
+                prio: 1,
+            }),
+            unary_op: Some(|a| -a),
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        },
+        Operator {
+            repr: "*",
+            bin_op: Some(BinOp {
+                apply: |a, b| a * b,
+                prio: 2,
+            }),
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        },
+        Operator {
+            repr: "**",
+            bin_op: Some(BinOp {
+                apply: |a, b| a.powf(b),
+                prio: 3,
+            }),
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        },
+    ];
+    let lenient = ParseOptions::default();
+    let strict = ParseOptions {
+        require_spaces_around_ambiguous_ops: true,
+        ..ParseOptions::default()
+    };
+    let tokenize = |text, options| tokenize_with_diagnostics(text, &ops, is_numeric_text, options);
+    fn reprs<'a>(tokens: &[ParsedToken<'a, f64>]) -> Vec<&'a str> {
+        tokens
+            .iter()
+            .filter_map(|token| match &token.kind {
+                TokenKind::Op(op) => Some(op.repr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    let (tokens, warnings) = tokenize("a <-b", &lenient).unwrap();
+    assert_eq!(reprs(&tokens), ["<-"]);
+    assert_eq!(
+        warnings,
+        [ParseWarning::AmbiguousOperator {
+            position: 2,
+            chosen: "<-".to_string(),
+            shorter: "<".to_string(),
+            following: "-".to_string(),
+            context: "a <-b".to_string(),
+        }]
+    );
+    assert_eq!(warnings[0].position(), 2);
+    assert_eq!(
+        tokenize("a <-b", &strict).unwrap_err(),
+        ExError::AmbiguousOperator {
+            position: 2,
+            chosen: "<-".to_string(),
+            shorter: "<".to_string(),
+            following: "-".to_string(),
+            context: "a <-b".to_string(),
+        }
     );
+    assert_eq!(tokenize("a<-b", &strict).unwrap_err().code(), "E006");
+    // whitespace disambiguates in both directions
+    let (tokens, warnings) = tokenize("a <- b", &strict).unwrap();
+    assert_eq!(reprs(&tokens), ["<-"]);
+    assert_eq!(warnings.len(), 1);
+    let (tokens, warnings) = tokenize("a < -b", &strict).unwrap();
+    assert_eq!(reprs(&tokens), ["<", "-"]);
+    assert!(warnings.is_empty());
+
+    let (tokens, warnings) = tokenize("2**3", &lenient).unwrap();
+    assert_eq!(reprs(&tokens), ["**"]);
+    assert!(warnings[0].to_string().contains("`**`"));
+    assert!(tokenize("2**3", &strict).is_err());
+    assert_eq!(reprs(&tokenize("2 ** 3", &strict).unwrap().0), ["**"]);
+    let (tokens, warnings) = tokenize("2*-3", &strict).unwrap();
+    assert_eq!(reprs(&tokens), ["*", "-"]);
+    assert!(warnings.is_empty());
+
+    // default operators are not ambiguous
+    let default_ops = operators::make_default_operators::<f64>();
+    let text = "log2(x) + sinh(y) - signum(-z) * exp2(log10(2))";
+    let (_, warnings) =
+        tokenize_with_diagnostics(text, &default_ops, is_numeric_text, &strict).unwrap();
+    assert!(warnings.is_empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_ops_as_name_prefixes() {
+    let mut ops = operators::make_default_operators::<f64>().to_vec();
+    ops.push(Operator {
+        repr: "e",
+        bin_op: None,
+        unary_op: Some(|a| a * std::f64::consts::E),
+        domain_check: None,
+        unary_position: UnaryPosition::Postfix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    });
+    let kinds = |text| {
+        let (tokens, errors) = lex(
+            text,
+            &ops,
+            &is_numeric_text,
+            &is_name_text,
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert!(errors.is_empty());
+        tokens
+            .iter()
+            .map(|token| match token.kind {
+                TokenKind::Op(op) => format!("op {}", op.repr),
+                TokenKind::Var(name) => format!("var {}", name),
+                _ => token.to_token(text).text.to_string(),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        kinds("exp(energy) + 2e"),
+        ["op exp", "(", "var energy", ")", "op +", "2", "op e"]
+    );
+    assert_eq!(
+        kinds("sinx*e_1-sin(x)e"),
+        ["var sinx", "op *", "var e_1", "op -", "op sin", "(", "var x", ")", "op e"]
+    );
+    // symbolic operators and aliases are still matched greedily
+    assert_eq!(kinds("2**x"), ["2", "op ^", "var x"]);
+    assert_eq!(kinds("2*+x"), ["2", "op *", "op +", "var x"]);
+    assert_eq!(kinds("-exp"), ["op -", "op exp"]);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_whitespace_between_ops() {
+    // `**` is an alias of `^`
+    let ops = operators::make_default_operators::<f64>();
+    let eval = |text: &str| crate::parse(text, &ops).and_then(|expr| expr.eval(&[]));
+    assert_eq!(eval("2**3"), Ok(8.0));
+    assert_eq!(eval("2 ** 3"), Ok(8.0));
+    assert_eq!(
+        eval("2 * * 3"),
+        Err(ExError::InvalidPair {
+            position: 4,
+            violation: PairViolation::AdjacentBinaryOps,
+            context: "2 * * 3".to_string()
+        })
+    );
+    assert_eq!(eval("- -1"), Ok(1.0));
+    assert_eq!(eval("5 -  -  5"), eval("5--5"));
+    assert_eq!(eval("5 -  -  5"), Ok(10.0));
+
+    // inputs with interleaved whitespace evaluate like their compact versions
+    for (spaced, compact) in [
+        ("- - - 2", "---2"),
+        ("2 - - -3", "2---3"),
+        ("1 + + 2", "1++2"),
+        ("2 ^ - 1", "2^-1"),
+        ("\t-\n(\r- 4 )", "-(-4)"),
+        ("3 * - sin ( 0 )", "3*-sin(0)"),
+        ("2 ** - 1", "2**-1"),
+        ("( ( 1 ) ) - - ( 2 )", "((1))--(2)"),
+    ] {
+        assert_eq!(eval(spaced), eval(compact), "{:?}", spaced);
+    }
+    for text in [
+        "2 * * 3", "2 * *3", "2* * 3", "1 + * 2", "2 ** * 3", "4 / * 2",
+    ] {
+        assert!(eval(text).is_err(), "{:?}", text);
+    }
+
+    // operators cannot contain whitespace
+    let mut ops_with_space = ops.to_vec();
+    ops_with_space.push(Operator {
+        repr: "+ +",
+        ..ops[ops.len() - 1]
+    });
+    assert_eq!(
+        crate::parse("1 + + 2", &ops_with_space).map(|_| ()),
+        Err(ExError::WhitespaceInOperatorRepr("+ +".to_string()))
+    );
+    ops_with_space.last_mut().unwrap().repr = "++";
+    ops_with_space.last_mut().unwrap().aliases = &["+ +"];
+    assert_eq!(
+        crate::parse("1 + + 2", &ops_with_space).map(|_| ()),
+        Err(ExError::WhitespaceInOperatorRepr("+ +".to_string()))
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_comments_and_whitespace() {
+    let ops = operators::make_default_operators::<f64>();
+    let with_comments = ParseOptions {
+        comments: true,
+        ..ParseOptions::default()
+    };
+    let eval = |text: &str, vars: &[f64]| {
+        crate::parse_with_options(text, &ops, &with_comments)
+            .unwrap()
+            .eval(vars)
+            .unwrap()
+    };
+    assert_eq!(eval("2*x  # scale factor", &[1.5]), 3.0);
+    assert_eq!(eval("y + 1 /* offset */", &[1.0]), 2.0);
+    assert_eq!(eval("/* start */ x*/**/2", &[2.0]), 4.0);
+    assert_eq!(
+        eval("# first line\nx\n  # second line\n  + 1 # end", &[2.0]),
+        3.0
+    );
+    assert_eq!(eval("\tx\r\n*\u{a0}2\n", &[2.0]), 4.0);
+
+    // whitespace does not need the option
+    let expr = crate::parse("x\n+\t1", &ops).unwrap();
+    assert_eq!(expr.eval(&[1.0]).unwrap(), 2.0);
+    assert!(crate::parse("2*x # comment", &ops).is_err());
+    assert!(crate::parse("2*x /* comment */", &ops).is_err());
+
+    // positions refer to the original string
+    let err = crate::parse_with_options("x /* a */ + ", &ops, &with_comments).unwrap_err();
+    assert_eq!(err.position(), Some(10));
+    let err = crate::parse_with_options("x + 1 /* offset", &ops, &with_comments).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::UnterminatedComment {
+            position: 6,
+            context: " + 1 /* offset".to_string()
+        }
+    );
+    assert_eq!(err.code(), "E037");
+    assert!(crate::parse_with_options("# nothing", &ops, &with_comments).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_curly_vars() {
+    let ops = operators::make_default_operators::<f64>();
+    let no_curly = ParseOptions {
+        curly_vars: false,
+        ..ParseOptions::default()
+    };
+    let err = crate::parse_with_options("2*{x}", &ops, &no_curly).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::UnexpectedToken {
+            position: 2,
+            found: "{".to_string(),
+            context: "2*{x}".to_string()
+        }
+    );
+    let expr = crate::parse_with_options("2*x", &ops, &no_curly).unwrap();
+    assert_eq!(expr.eval(&[1.5]).unwrap(), 3.0);
+
+    // operators that start with a curly brace take precedence over variables
+    let mut ops = ops.to_vec();
+    let unary = |repr, unary_op: fn(f64) -> f64, unary_position| Operator {
+        repr,
+        bin_op: None,
+        unary_op: Some(unary_op),
+        domain_check: None,
+        unary_position,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    };
+    ops.push(unary("{{", |a| 2.0 * a, UnaryPosition::Prefix));
+    ops.push(unary("}}", |a| a + 1.0, UnaryPosition::Postfix));
+    for options in [ParseOptions::default(), no_curly] {
+        let tokens = tokenize_and_analyze("{{3}}", &ops, is_numeric_text, &options).unwrap();
+        let reprs = tokens
+            .iter()
+            .map(|token| token.to_token("{{3}}").text)
+            .collect::<Vec<_>>();
+        assert_eq!(reprs, ["{{", "3", "}}"]);
+        assert!(matches!(tokens[0].kind, TokenKind::Op(op) if op.repr == "{{"));
+        let expr = crate::parse_with_options("{{x}} * {{(y+1)}}", &ops, &options).unwrap();
+        assert_eq!(expr.var_names(), ["x", "y"]);
+        assert_eq!(expr.eval(&[1.0, 2.0]).unwrap(), 32.0);
+    }
+    let expr = crate::parse("{x} + {{x}}", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["x"]);
+    assert_eq!(expr.eval(&[2.0]).unwrap(), 8.0);
+    assert!(crate::parse_with_options("{x} + {{x}}", &ops, &no_curly).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_decimal_comma() {
+    let ops = operators::make_default_operators::<f64>();
+    let decimal_comma = ParseOptions {
+        decimal_comma: true,
+        ..ParseOptions::default()
+    };
+    let eval = |text| {
+        crate::parse_with_options::<f64>(text, &ops, &decimal_comma)
+            .unwrap()
+            .eval(&[])
+            .unwrap()
+    };
+    assert_eq!(eval("3,5+1"), 4.5);
+    assert_eq!(eval(",5 * 1,25e2"), 62.5);
+    assert_eq!(eval("logb(2; 8)"), 3.0);
+    assert_eq!(eval("sum(i; 1; 3; i*0,5)"), 3.0);
+    assert_eq!(is_numeric_text_decimal_comma("1,5,2"), None);
+    assert_eq!(is_numeric_text_decimal_comma("1,5+2"), Some("1,5"));
+    assert_eq!(is_numeric_text_decimal_comma("1.5"), Some("1"));
+    // commas are no argument separators, `2,8` is a number
+    let err = crate::parse_with_options::<f64>("logb(2,8)", &ops, &decimal_comma).unwrap_err();
+    assert_eq!(err.code(), "E024");
+    let err = crate::parse_with_options::<f64>("1.5", &ops, &decimal_comma).unwrap_err();
+    assert_eq!(err.code(), "E002");
+
+    // without the option, a comma between digits hints at it
+    let err = crate::parse("3,5+1", &ops).unwrap_err();
+    assert_eq!(err.code(), "E061");
+    assert_eq!(err.position(), Some(1));
+    assert!(err.to_string().contains("decimal_comma"));
+    assert_eq!(crate::check("x*3,5", &ops).unwrap_err()[0].code(), "E061");
+    assert_eq!(crate::parse("(1, 2)", &ops).unwrap_err().code(), "E025");
+    assert_eq!(
+        crate::parse("logb(2,8)", &ops).unwrap().eval(&[]).unwrap(),
+        3.0
+    );
+
+    // custom number patterns are used as given, hence, commas still separate arguments
+    let err = crate::parse_with_number_pattern::<f64>("3,5+1", &ops, "[0-9]+").unwrap_err();
+    assert_eq!(err.code(), "E061");
+    let expr = crate::parse_with_number_pattern::<f64>("logb(2,8)", &ops, "[0-9]+").unwrap();
+    assert_eq!(expr.eval(&[]).unwrap(), 3.0);
 }
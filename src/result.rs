@@ -0,0 +1,1183 @@
+use crate::alloc_prelude::*;
+use crate::parser::Span;
+use core::fmt;
+
+/// Describes which rule is violated by two neighboring tokens of the parsed string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PairViolation {
+    /// A number or variable is next to another one, e.g., `2 {x}`.
+    AdjacentOperands,
+    /// Wlog a number or variable is on the right of a closing parenthesis, e.g., `)5`.
+    OperandNextToParen,
+    /// A number or variable is on the left of a unary operator, e.g., `2sin(x)`.
+    OperandBeforeUnary,
+    /// A binary operator is next to another binary operator, e.g., `2*/4`.
+    AdjacentBinaryOps,
+    /// A binary operator is on the right of a unary operator, e.g., `sin*`.
+    BinaryAfterUnary,
+    /// An operator is on the left of a closing parenthesis, e.g., `+)`.
+    OpBeforeClosingParen,
+    /// A unary operator is on the right of a closing parenthesis, e.g., `)sin`.
+    UnaryAfterClosingParen,
+    /// A binary operator is on the right of an opening parenthesis, e.g., `(*`.
+    BinaryAfterOpeningParen,
+    /// Wlog an opening parenthesis is next to a closing one, e.g., `)(` or `()`.
+    EmptyParens,
+    /// A postfix unary operator is not on the right of a number, a variable, a closing
+    /// parenthesis, or another postfix operator, e.g., `(!`, or a postfix operator that is
+    /// not binary is followed by an operand, e.g., `3!2`.
+    MisplacedPostfixUnary,
+    /// A comma does not separate two arguments of an n-ary operator, e.g., `(,`, `,*`, or
+    /// `1, 2` outside of a call.
+    MisplacedComma,
+    /// A comma between digits outside of a call looks like a decimal comma, e.g., `3,5`,
+    /// see [`ParseOptions::decimal_comma`](crate::ParseOptions::decimal_comma).
+    DecimalComma,
+}
+
+impl PairViolation {
+    /// Returns the stable error code of an [`InvalidPair`](ExError::InvalidPair) error with
+    /// this violation, see [`ERROR_CODES`](ERROR_CODES).
+    pub fn code(&self) -> &'static str {
+        match self {
+            PairViolation::BinaryAfterOpeningParen => "E011",
+            PairViolation::OpBeforeClosingParen => "E012",
+            PairViolation::EmptyParens => "E013",
+            PairViolation::AdjacentOperands => "E014",
+            PairViolation::OperandNextToParen => "E015",
+            PairViolation::OperandBeforeUnary => "E016",
+            PairViolation::AdjacentBinaryOps => "E017",
+            PairViolation::BinaryAfterUnary => "E018",
+            PairViolation::UnaryAfterClosingParen => "E019",
+            PairViolation::MisplacedPostfixUnary => "E010",
+            PairViolation::MisplacedComma => "E025",
+            PairViolation::DecimalComma => "E061",
+        }
+    }
+}
+
+impl fmt::Display for PairViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            PairViolation::AdjacentOperands => {
+                "a number/variable cannot be next to a number/variable"
+            }
+            PairViolation::OperandNextToParen => {
+                "wlog a number/variable cannot be on the right of a closing parenthesis"
+            }
+            PairViolation::OperandBeforeUnary => {
+                "a number/variable cannot be on the left of a unary operator"
+            }
+            PairViolation::AdjacentBinaryOps => {
+                "a binary operator cannot be next to a binary operator"
+            }
+            PairViolation::BinaryAfterUnary => {
+                "a binary operator cannot be on the right of a unary"
+            }
+            PairViolation::OpBeforeClosingParen => {
+                "an operator cannot be on the left of a closing paren"
+            }
+            PairViolation::UnaryAfterClosingParen => {
+                "a unary operator cannot be on the right of a closing paren"
+            }
+            PairViolation::BinaryAfterOpeningParen => {
+                "a binary operator cannot be on the right of an opening paren"
+            }
+            PairViolation::EmptyParens => "wlog an opening paren cannot be next to a closing paren",
+            PairViolation::MisplacedPostfixUnary => {
+                "a postfix unary operator needs an operand on its left and none on its right"
+            }
+            PairViolation::MisplacedComma => {
+                "a comma can only separate the arguments of an n-ary operator"
+            }
+            PairViolation::DecimalComma => {
+                "a comma can only separate the arguments of an n-ary operator, set \
+                 `ParseOptions::decimal_comma` for numbers with a decimal comma"
+            }
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+/// Stable error codes with canonical short descriptions. Each kind of error, i.e., each
+/// variant of [`ExError`](ExError) and each [`PairViolation`](PairViolation), has its own
+/// code that is returned by [`ExError::code`](ExError::code) and is the prefix of the
+/// error message.
+///
+/// Codes are stable. Once assigned, a code keeps its meaning and is never reused, whereas
+/// the wording of error messages and descriptions might change. Hence, match on codes or
+/// variants instead of messages.
+pub const ERROR_CODES: [(&str, &str); 64] = [
+    ("E001", "empty input"),
+    ("E002", "unexpected token"),
+    ("E003", "parentheses mismatch"),
+    ("E004", "trailing operator"),
+    ("E005", "invalid number pattern"),
+    ("E006", "ambiguous operator"),
+    ("E007", "unary chain too long"),
+    ("E008", "domain violation"),
+    ("E009", "unterminated brace"),
+    ("E010", "misplaced postfix unary operator"),
+    ("E011", "binary operator after opening parenthesis"),
+    ("E012", "operator before closing parenthesis"),
+    ("E013", "empty parentheses"),
+    ("E014", "adjacent operands"),
+    ("E015", "operand next to parenthesis"),
+    ("E016", "operand before unary operator"),
+    ("E017", "adjacent binary operators"),
+    ("E018", "binary operator after unary operator"),
+    ("E019", "unary operator after closing parenthesis"),
+    ("E020", "wrong number of variables"),
+    ("E021", "operator not found"),
+    ("E022", "operator not binary"),
+    ("E023", "operator not unary"),
+    ("E024", "wrong number of arguments"),
+    ("E025", "misplaced comma"),
+    ("E026", "duplicate operator"),
+    ("E027", "empty operator representation"),
+    ("E028", "operator without operation"),
+    ("E029", "no operators"),
+    ("E030", "derivative not found"),
+    ("E031", "deep expression missing"),
+    ("E032", "division by zero"),
+    ("E033", "zero to the power of zero"),
+    ("E034", "interval operator not found"),
+    ("E035", "invalid interval"),
+    ("E036", "empty variable name"),
+    ("E037", "unterminated comment"),
+    ("E038", "whitespace in operator representation"),
+    ("E039", "too many nodes"),
+    ("E040", "indistinguishable variables"),
+    ("E041", "unknown variable"),
+    ("E042", "duplicate variable"),
+    ("E043", "missing variable"),
+    ("E044", "row length mismatch"),
+    ("E045", "series length mismatch"),
+    ("E046", "variable index out of range"),
+    ("E047", "non-finite result"),
+    ("E048", "nesting too deep"),
+    ("E050", "local redefined"),
+    ("E051", "local used before definition"),
+    ("E052", "missing result"),
+    ("E053", "unused statement"),
+    ("E054", "invalid variable pattern"),
+    ("E055", "operator failed"),
+    ("E056", "fallible operation without operation"),
+    ("E057", "partial derivative mismatch"),
+    ("E058", "invalid loop variable"),
+    ("E059", "invalid loop bound"),
+    ("E060", "too many loop terms"),
+    ("E061", "decimal comma"),
+    ("E062", "input too long"),
+    ("E063", "too many tokens"),
+    ("E064", "invalid number"),
+    ("E099", "internal error"),
+];
+
+/// This will be thrown at you if something went wrong. Ok, obviously it is not an
+/// exception, so thrown needs to be understood figuratively.
+///
+/// Positions are byte offsets into the parsed string. Errors with a position also contain
+/// the surrounding text as context. To distinguish failures, match on the variants or on
+/// the stable [`code`](ExError::code) instead of the message.
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::prelude::*;
+/// match parse_with_default_ops::<f64>("(2 + x") {
+///     Err(ExError::ParenMismatch { position, context }) => {
+///         assert_eq!(position, 0);
+///         assert_eq!(context, "(2 + x");
+///     }
+///     _ => panic!("expected a parenthesis mismatch"),
+/// }
+/// let err = parse_with_default_ops::<f64>("2 {x}").unwrap_err();
+/// assert_eq!(err.code(), "E014");
+/// assert!(err.msg().starts_with("[E014] "));
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExError {
+    /// The to-be-parsed string is empty.
+    EmptyInput,
+    /// The character `found` is not the beginning of a number, a variable, or an
+    /// operator.
+    UnexpectedToken {
+        position: usize,
+        found: String,
+        context: String,
+    },
+    /// The number pattern matches `found` at `position` but the number type cannot
+    /// parse it, e.g., `2.5` or `1e3` for integers. `reason` is the message of the
+    /// parse error.
+    InvalidNumber {
+        position: usize,
+        found: String,
+        reason: String,
+        context: String,
+    },
+    /// The token at `position` and its left neighbor violate a rule.
+    InvalidPair {
+        position: usize,
+        violation: PairViolation,
+        context: String,
+    },
+    /// The parenthesis at `position` has no counterpart.
+    ParenMismatch { position: usize, context: String },
+    /// The last element of the parsed string is an operator, e.g., `1+`.
+    TrailingOperator { position: usize, context: String },
+    /// The opening curly brace of a variable name at `position` has no closing
+    /// counterpart, e.g., `{x`.
+    UnterminatedBrace { position: usize, context: String },
+    /// The curly braces at `position` enclose an empty variable name, i.e., `{}`.
+    EmptyVarName { position: usize, context: String },
+    /// The block comment starting at `position` has no closing `*/`, see
+    /// [`ParseOptions::comments`](crate::ParseOptions::comments).
+    UnterminatedComment { position: usize, context: String },
+    /// The n-ary operator `op` at `position` expects `expected` arguments in parentheses
+    /// but is called with `given` arguments, e.g., `clamp(x, 1)` for an operator with
+    /// arity 3.
+    WrongNumberOfArgs {
+        position: usize,
+        op: String,
+        expected: usize,
+        given: usize,
+        context: String,
+    },
+    /// The first argument of the sum or product `op` at `position` is not a variable, e.g.,
+    /// `sum(2, 1, 5, x)`.
+    InvalidLoopVar {
+        position: usize,
+        op: String,
+        context: String,
+    },
+    /// The bound of the sum or product `op` at `position` does not fold to an integer
+    /// constant, e.g., `sum(i, 1, n, i)` where `n` is a variable.
+    InvalidLoopBound {
+        position: usize,
+        op: String,
+        context: String,
+    },
+    /// The sum or product at `position` expands to terms beyond the limit `max` of all
+    /// terms of sums and products, see [`ParseLimits`](crate::ParseLimits).
+    TooManyLoopTerms {
+        position: usize,
+        max: usize,
+        context: String,
+    },
+    /// The string has `len` bytes, which exceeds the limit `max`, see
+    /// [`ParseLimits`](crate::ParseLimits). Nothing of the string has been parsed.
+    InputTooLong { len: usize, max: usize },
+    /// The token at `position` exceeds the limit `max` of tokens, see
+    /// [`ParseLimits`](crate::ParseLimits). Parsing stops at this position.
+    TooManyTokens {
+        position: usize,
+        max: usize,
+        context: String,
+    },
+    /// Several of the passed operators have this representation. Binary, unary, and n-ary
+    /// variants of an operator need to be defined by a single [`Operator`](crate::Operator).
+    DuplicateOperator(String),
+    /// One of the passed operators has an empty representation.
+    EmptyOperatorRepr,
+    /// The representation of one of the passed operators contains whitespace. Operators
+    /// are matched against contiguous non-whitespace characters, e.g., `* *` is not `**`.
+    WhitespaceInOperatorRepr(String),
+    /// The passed operator with this representation has neither a binary, a unary, nor an
+    /// n-ary operation.
+    OperatorWithoutOperation(String),
+    /// The passed operator with this representation has a fallible binary or unary
+    /// operation but not the corresponding infallible one, see
+    /// [`FallibleOp`](crate::FallibleOp).
+    FallibleWithoutOperation(String),
+    /// No operators have been passed but the to-be-parsed string contains a token at
+    /// `position` that is not a number.
+    NoOperators { position: usize, context: String },
+    /// The passed number regex cannot be compiled.
+    InvalidNumberPattern(String),
+    /// The passed variable name regex cannot be compiled or matches the empty string.
+    InvalidVarPattern(String),
+    /// The operator `chosen` at `position` could also be read as the shorter operator
+    /// `shorter` followed by `following` and is not surrounded by whitespace. Only returned
+    /// if [`require_spaces_around_ambiguous_ops`](crate::ParseOptions::require_spaces_around_ambiguous_ops)
+    /// is set.
+    AmbiguousOperator {
+        position: usize,
+        chosen: String,
+        shorter: String,
+        following: String,
+        context: String,
+    },
+    /// After flattening, `len` unary operators are applied in a row, which is more than the
+    /// limit `max`, see [`ParseLimits`](crate::ParseLimits). The outermost expression
+    /// contributing to the chain ranges from `position` to `end`.
+    UnaryChainTooLong {
+        position: usize,
+        end: usize,
+        len: usize,
+        max: usize,
+        context: String,
+    },
+    /// The number or variable at `position` exceeds the limit `max` of numbers and
+    /// variables, see [`ParseLimits`](crate::ParseLimits). Parsing stops at this position.
+    TooManyNodes {
+        position: usize,
+        max: usize,
+        context: String,
+    },
+    /// The parenthesis at `position` is nested deeper than the limit `max`, see
+    /// [`ParseLimits`](crate::ParseLimits). Parsing stops at this position.
+    NestingTooDeep {
+        position: usize,
+        max: usize,
+        context: String,
+    },
+    /// The unary operator `op` at `position` is applied to the constant `value` that is
+    /// not in its domain. The argument ends at `end`. Only returned if
+    /// [`strict_domains`](crate::ParseOptions::strict_domains) is set.
+    DomainViolation {
+        position: usize,
+        end: usize,
+        op: String,
+        value: String,
+        reason: String,
+        context: String,
+    },
+    /// The expression has `expected` variables but `given` values have been passed.
+    WrongNumberOfVars { expected: usize, given: usize },
+    /// No operator with this representation is available.
+    OperatorNotFound(String),
+    /// The operator with this representation has no binary variant.
+    OperatorNotBinary(String),
+    /// The operator with this representation has no unary variant.
+    OperatorNotUnary(String),
+    /// There is no derivative for the operator with this representation.
+    DerivativeNotFound(String),
+    /// The deep expression has been removed with [`clear_deepex`](crate::FlatEx::clear_deepex).
+    DeepExMissing,
+    /// The derivative leads to a division by zero.
+    DivisionByZero {
+        numerator: String,
+        denominator: String,
+    },
+    /// The derivative leads to `0^0`.
+    ZeroPowZero,
+    /// There is no interval arithmetic for the operator with this representation.
+    IntervalOpNotFound(String),
+    /// The range of the variable with this index has a lower bound larger than its upper
+    /// bound.
+    InvalidInterval(usize),
+    /// Two variables of the expression have the same name under a name resolution policy.
+    IndistinguishableVars { first: String, second: String },
+    /// A passed name does not belong to any variable of the expression.
+    UnknownVar {
+        name: String,
+        available: Vec<String>,
+    },
+    /// A variable is passed several times. Contains the passed names and their positions.
+    DuplicateVar {
+        name: String,
+        passed: Vec<(String, usize)>,
+    },
+    /// No value has been passed for a variable.
+    MissingVar(String),
+    /// A row of values does not match the length of the header.
+    RowLengthMismatch {
+        row: usize,
+        expected: usize,
+        given: usize,
+    },
+    /// The series of values of the variable with index `series` passed to
+    /// [`eval_vectorized`](crate::FlatEx::eval_vectorized) has a length different from the
+    /// output.
+    SeriesLengthMismatch {
+        series: usize,
+        expected: usize,
+        given: usize,
+    },
+    /// A variable index passed to, e.g., [`bind`](crate::FlatEx::bind) is not smaller
+    /// than the number of variables `n_vars`.
+    VarIdxOutOfRange { idx: usize, n_vars: usize },
+    /// The operator `op` applied to `operands` results in `NaN` or an infinite `result`
+    /// during [`eval_checked`](crate::FlatEx::eval_checked).
+    NonFiniteResult {
+        op: String,
+        operands: Vec<String>,
+        result: String,
+    },
+    /// The fallible variant of the operator `op` applied to `operands` fails for `reason`,
+    /// see [`FallibleOp`](crate::FallibleOp).
+    OperatorFailed {
+        op: String,
+        operands: Vec<String>,
+        reason: String,
+    },
+    /// The local of a [script](crate::parse_script) or the definition of a
+    /// [program](crate::parse_program) at `position` has already been defined before.
+    LocalRedefined { local: String, position: usize },
+    /// The local of a [script](crate::parse_script) or the definition of a
+    /// [program](crate::parse_program) is used by the statement at `position` before it is
+    /// defined.
+    LocalUsedBeforeDefinition { local: String, position: usize },
+    /// The last statement of a [script](crate::parse_script) or a
+    /// [program](crate::parse_program) defines the local at `position` instead of being the
+    /// result expression.
+    MissingResult { local: String, position: usize },
+    /// The statement at `position` neither defines a local nor is it the last statement, so
+    /// its value would be discarded.
+    UnusedStatement { position: usize },
+    /// The partial derivative with respect to the variable `var_idx` deviates from central
+    /// finite differences by up to `max_deviation`, first at `point` with the value
+    /// `symbolic` instead of `numeric`, see [`check_partial`](crate::check_partial).
+    PartialMismatch {
+        var_idx: usize,
+        point: Vec<String>,
+        symbolic: String,
+        numeric: String,
+        max_deviation: String,
+    },
+    /// An invariant of the internal data structures is violated. This is a bug.
+    Internal(String),
+}
+
+impl ExError {
+    /// Returns the stable code of the error, see [`ERROR_CODES`](ERROR_CODES).
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExError::EmptyInput => "E001",
+            ExError::UnexpectedToken { .. } => "E002",
+            ExError::ParenMismatch { .. } => "E003",
+            ExError::TrailingOperator { .. } => "E004",
+            ExError::InvalidNumberPattern(_) => "E005",
+            ExError::InvalidVarPattern(_) => "E054",
+            ExError::AmbiguousOperator { .. } => "E006",
+            ExError::UnaryChainTooLong { .. } => "E007",
+            ExError::TooManyNodes { .. } => "E039",
+            ExError::NestingTooDeep { .. } => "E048",
+            ExError::DomainViolation { .. } => "E008",
+            ExError::UnterminatedBrace { .. } => "E009",
+            ExError::EmptyVarName { .. } => "E036",
+            ExError::UnterminatedComment { .. } => "E037",
+            ExError::InvalidPair { violation, .. } => violation.code(),
+            ExError::WrongNumberOfVars { .. } => "E020",
+            ExError::OperatorNotFound(_) => "E021",
+            ExError::OperatorNotBinary(_) => "E022",
+            ExError::OperatorNotUnary(_) => "E023",
+            ExError::WrongNumberOfArgs { .. } => "E024",
+            ExError::InvalidLoopVar { .. } => "E058",
+            ExError::InvalidLoopBound { .. } => "E059",
+            ExError::TooManyLoopTerms { .. } => "E060",
+            ExError::InputTooLong { .. } => "E062",
+            ExError::TooManyTokens { .. } => "E063",
+            ExError::InvalidNumber { .. } => "E064",
+            ExError::DuplicateOperator(_) => "E026",
+            ExError::EmptyOperatorRepr => "E027",
+            ExError::WhitespaceInOperatorRepr(_) => "E038",
+            ExError::OperatorWithoutOperation(_) => "E028",
+            ExError::FallibleWithoutOperation(_) => "E056",
+            ExError::NoOperators { .. } => "E029",
+            ExError::DerivativeNotFound(_) => "E030",
+            ExError::DeepExMissing => "E031",
+            ExError::DivisionByZero { .. } => "E032",
+            ExError::ZeroPowZero => "E033",
+            ExError::IntervalOpNotFound(_) => "E034",
+            ExError::InvalidInterval(_) => "E035",
+            ExError::IndistinguishableVars { .. } => "E040",
+            ExError::UnknownVar { .. } => "E041",
+            ExError::DuplicateVar { .. } => "E042",
+            ExError::MissingVar(_) => "E043",
+            ExError::RowLengthMismatch { .. } => "E044",
+            ExError::SeriesLengthMismatch { .. } => "E045",
+            ExError::VarIdxOutOfRange { .. } => "E046",
+            ExError::NonFiniteResult { .. } => "E047",
+            ExError::OperatorFailed { .. } => "E055",
+            ExError::LocalRedefined { .. } => "E050",
+            ExError::LocalUsedBeforeDefinition { .. } => "E051",
+            ExError::MissingResult { .. } => "E052",
+            ExError::UnusedStatement { .. } => "E053",
+            ExError::PartialMismatch { .. } => "E057",
+            ExError::Internal(_) => "E099",
+        }
+    }
+
+    /// Returns the byte offset into the parsed string the error refers to, if available.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            ExError::UnexpectedToken { position, .. }
+            | ExError::InvalidNumber { position, .. }
+            | ExError::InvalidPair { position, .. }
+            | ExError::ParenMismatch { position, .. }
+            | ExError::TrailingOperator { position, .. }
+            | ExError::UnterminatedBrace { position, .. }
+            | ExError::EmptyVarName { position, .. }
+            | ExError::UnterminatedComment { position, .. }
+            | ExError::WrongNumberOfArgs { position, .. }
+            | ExError::InvalidLoopVar { position, .. }
+            | ExError::InvalidLoopBound { position, .. }
+            | ExError::TooManyLoopTerms { position, .. }
+            | ExError::TooManyTokens { position, .. }
+            | ExError::NoOperators { position, .. }
+            | ExError::AmbiguousOperator { position, .. }
+            | ExError::UnaryChainTooLong { position, .. }
+            | ExError::TooManyNodes { position, .. }
+            | ExError::NestingTooDeep { position, .. }
+            | ExError::DomainViolation { position, .. }
+            | ExError::LocalRedefined { position, .. }
+            | ExError::LocalUsedBeforeDefinition { position, .. }
+            | ExError::MissingResult { position, .. }
+            | ExError::UnusedStatement { position } => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// Returns the error message, i.e., the same as the [`Display`](fmt::Display)
+    /// implementation.
+    pub fn msg(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for ExError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] ", self.code())?;
+        match self {
+            ExError::EmptyInput => write!(f, "cannot parse empty string"),
+            ExError::UnexpectedToken {
+                position,
+                found,
+                context,
+            } => write!(
+                f,
+                "how to parse `{}` at position {}, near `{}`",
+                found, position, context
+            ),
+            ExError::InvalidNumber {
+                position,
+                found,
+                reason,
+                context,
+            } => write!(
+                f,
+                "cannot parse number `{}` at position {}, {}, near `{}`",
+                found, position, reason, context
+            ),
+            ExError::InvalidPair {
+                position,
+                violation,
+                context,
+            } => write!(
+                f,
+                "{} at position {}, near `{}`",
+                violation, position, context
+            ),
+            ExError::ParenMismatch { position, context } => write!(
+                f,
+                "parentheses mismatch at position {}, near `{}`",
+                position, context
+            ),
+            ExError::TrailingOperator { position, context } => write!(
+                f,
+                "the last element cannot be an operator, found one at position {}, near `{}`",
+                position, context
+            ),
+            ExError::UnterminatedBrace { position, context } => write!(
+                f,
+                "missing closing brace `}}` of the variable name starting at position {}, near `{}`",
+                position, context
+            ),
+            ExError::EmptyVarName { position, context } => write!(
+                f,
+                "empty variable name `{{}}` at position {}, near `{}`",
+                position, context
+            ),
+            ExError::UnterminatedComment { position, context } => write!(
+                f,
+                "missing `*/` of the block comment starting at position {}, near `{}`",
+                position, context
+            ),
+            ExError::WrongNumberOfArgs {
+                position,
+                op,
+                expected,
+                given,
+                context,
+            } => write!(
+                f,
+                "operator `{}` at position {} expects {} arguments in parentheses but got {}, near `{}`",
+                op, position, expected, given, context
+            ),
+            ExError::InvalidLoopVar {
+                position,
+                op,
+                context,
+            } => write!(
+                f,
+                "the first argument of `{}` at position {} needs to be a variable, near `{}`",
+                op, position, context
+            ),
+            ExError::InvalidLoopBound {
+                position,
+                op,
+                context,
+            } => write!(
+                f,
+                "the bound of `{}` at position {} does not fold to an integer constant, near `{}`",
+                op, position, context
+            ),
+            ExError::TooManyLoopTerms {
+                position,
+                max,
+                context,
+            } => write!(
+                f,
+                "sums and products expand to more than {} terms at position {}, near `{}`",
+                max, position, context
+            ),
+            ExError::InputTooLong { len, max } => write!(
+                f,
+                "the string has {} bytes, which exceeds the limit of {} bytes",
+                len, max
+            ),
+            ExError::TooManyTokens {
+                position,
+                max,
+                context,
+            } => write!(
+                f,
+                "more than {} tokens at position {}, near `{}`",
+                max, position, context
+            ),
+            ExError::DuplicateOperator(repr) => write!(
+                f,
+                "operator `{}` is passed more than once, define its binary, unary, and n-ary variants in one operator",
+                repr
+            ),
+            ExError::EmptyOperatorRepr => {
+                write!(f, "operators need a non-empty representation")
+            }
+            ExError::WhitespaceInOperatorRepr(repr) => write!(
+                f,
+                "representation `{}` of an operator must not contain whitespace",
+                repr
+            ),
+            ExError::OperatorWithoutOperation(repr) => write!(
+                f,
+                "operator `{}` has neither a binary, a unary, nor an n-ary operation",
+                repr
+            ),
+            ExError::FallibleWithoutOperation(repr) => write!(
+                f,
+                "operator `{}` has a fallible operation without the infallible one",
+                repr
+            ),
+            ExError::NoOperators { position, context } => write!(
+                f,
+                "no operators passed but found a token that is not a number at position {}, near `{}`",
+                position, context
+            ),
+            ExError::InvalidNumberPattern(pattern) => {
+                write!(f, "cannot compile the passed number regex {}", pattern)
+            }
+            ExError::InvalidVarPattern(pattern) => write!(
+                f,
+                "the passed variable name regex {} cannot be compiled or matches the empty string",
+                pattern
+            ),
+            ExError::AmbiguousOperator {
+                position,
+                chosen,
+                shorter,
+                following,
+                context,
+            } => write!(
+                f,
+                "{} at position {}, near `{}`; use whitespace to disambiguate",
+                ambiguity_msg(chosen, shorter, following),
+                position,
+                context
+            ),
+            ExError::UnaryChainTooLong {
+                position,
+                end,
+                len,
+                max,
+                context,
+            } => write!(
+                f,
+                "{} unary operators in a row exceed the limit of {} at positions {}..{}, near `{}`",
+                len, max, position, end, context
+            ),
+            ExError::TooManyNodes {
+                position,
+                max,
+                context,
+            } => write!(
+                f,
+                "more than {} numbers and variables at position {}, near `{}`",
+                max, position, context
+            ),
+            ExError::NestingTooDeep {
+                position,
+                max,
+                context,
+            } => write!(
+                f,
+                "parentheses nested deeper than {} at position {}, near `{}`",
+                max, position, context
+            ),
+            ExError::DomainViolation {
+                position,
+                end,
+                op,
+                value,
+                reason,
+                context,
+            } => write!(
+                f,
+                "operator `{}` cannot be applied to {} at positions {}..{}, near `{}`; {}",
+                op, value, position, end, context, reason
+            ),
+            ExError::WrongNumberOfVars { expected, given } => write!(
+                f,
+                "parsed expression contains {} vars but passed slice has {} elements",
+                expected, given
+            ),
+            ExError::OperatorNotFound(repr) => write!(f, "did not find operator {}", repr),
+            ExError::OperatorNotBinary(repr) => write!(f, "operator {} is not binary", repr),
+            ExError::OperatorNotUnary(repr) => write!(f, "operator {} is not unary", repr),
+            ExError::DerivativeNotFound(repr) => write!(
+                f,
+                "operator `{}` has no derivative, which is needed for the partial derivative",
+                repr
+            ),
+            ExError::DeepExMissing => write!(
+                f,
+                "deep expression needed but not available after calling `clear_deepex`"
+            ),
+            ExError::DivisionByZero {
+                numerator,
+                denominator,
+            } => write!(f, "division by zero, {}/{}", numerator, denominator),
+            ExError::ZeroPowZero => write!(f, "base and exponent both zero"),
+            ExError::IntervalOpNotFound(repr) => write!(
+                f,
+                "interval arithmetic of operator {} needed for interval evaluation",
+                repr
+            ),
+            ExError::InvalidInterval(var_idx) => write!(
+                f,
+                "range of variable {} has a lower bound larger than its upper bound",
+                var_idx
+            ),
+            ExError::IndistinguishableVars { first, second } => write!(
+                f,
+                "variables `{}` and `{}` cannot be distinguished under the name resolution policy",
+                first, second
+            ),
+            ExError::UnknownVar { name, available } => {
+                write!(
+                    f,
+                    "`{}` is not a variable of the expression; available: ",
+                    name
+                )?;
+                for (i, var_name) in available.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}", sep, var_name)?;
+                }
+                Ok(())
+            }
+            ExError::DuplicateVar { name, passed } => {
+                write!(f, "variable `{}` is passed more than once: ", name)?;
+                for (i, (passed_name, pos)) in passed.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{}`{}` at position {}", sep, passed_name, pos)?;
+                }
+                Ok(())
+            }
+            ExError::MissingVar(name) => write!(f, "no value passed for variable `{}`", name),
+            ExError::RowLengthMismatch {
+                row,
+                expected,
+                given,
+            } => write!(
+                f,
+                "row {} has {} elements but header has {}",
+                row, given, expected
+            ),
+            ExError::SeriesLengthMismatch {
+                series,
+                expected,
+                given,
+            } => write!(
+                f,
+                "series {} has {} elements but the output has {}",
+                series, given, expected
+            ),
+            ExError::VarIdxOutOfRange { idx, n_vars } => write!(
+                f,
+                "variable index {} is out of range for {} variables",
+                idx, n_vars
+            ),
+            ExError::NonFiniteResult {
+                op,
+                operands,
+                result,
+            } => write!(
+                f,
+                "operator `{}` applied to {} results in {}",
+                op,
+                operands.join(", "),
+                result
+            ),
+            ExError::OperatorFailed {
+                op,
+                operands,
+                reason,
+            } => write!(
+                f,
+                "operator `{}` applied to {} failed, {}",
+                op,
+                operands.join(", "),
+                reason
+            ),
+            ExError::LocalRedefined { local, position } => write!(
+                f,
+                "local `{}` at position {} has already been defined",
+                local, position
+            ),
+            ExError::LocalUsedBeforeDefinition { local, position } => write!(
+                f,
+                "local `{}` is used by the statement at position {} before its definition",
+                local, position
+            ),
+            ExError::MissingResult { local, position } => write!(
+                f,
+                "the last statement defines local `{}` at position {} but needs to be an expression",
+                local, position
+            ),
+            ExError::UnusedStatement { position } => write!(
+                f,
+                "the statement at position {} neither defines a local nor is the last one",
+                position
+            ),
+            ExError::PartialMismatch {
+                var_idx,
+                point,
+                symbolic,
+                numeric,
+                max_deviation,
+            } => write!(
+                f,
+                "partial derivative with respect to variable {} deviates by up to {} from finite differences, first at ({}) with {} instead of {}",
+                var_idx,
+                max_deviation,
+                point.join(", "),
+                symbolic,
+                numeric
+            ),
+            ExError::Internal(msg) => write!(f, "internal error, {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ExError {}
+
+fn ambiguity_msg(chosen: &str, shorter: &str, following: &str) -> String {
+    format!(
+        "operator `{}` could also be read as `{}` followed by `{}`",
+        chosen, shorter, following
+    )
+}
+
+/// Problem of a string found by [`validate`](crate::validate).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    /// Error that describes the problem. The first issue is the error
+    /// [`parse`](crate::parse) returns.
+    pub error: ExError,
+    /// Message of `error`.
+    pub message: String,
+    /// Indices of the first and the last involved token of [`tokenize`](crate::tokenize),
+    /// e.g., of the left and the right token of an invalid pair. There are none for
+    /// characters that do not start a token and for problems of the whole string or of the
+    /// operators.
+    pub tokens: Option<(usize, usize)>,
+    /// Byte range of the involved tokens or characters in the validated string.
+    pub span: Option<Span>,
+}
+
+/// Potential problems of a successfully parsed string, see
+/// [`parse_with_diagnostics`](crate::parse_with_diagnostics).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// The operator `chosen` at `position` has been read as one operator but could also be
+    /// read as the shorter operator `shorter` followed by `following`, e.g., `<-` as `<`
+    /// followed by `-`.
+    AmbiguousOperator {
+        position: usize,
+        chosen: String,
+        shorter: String,
+        following: String,
+        context: String,
+    },
+}
+
+impl ParseWarning {
+    /// Returns the byte offset into the parsed string the warning refers to.
+    pub fn position(&self) -> usize {
+        match self {
+            ParseWarning::AmbiguousOperator { position, .. } => *position,
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseWarning::AmbiguousOperator {
+                position,
+                chosen,
+                shorter,
+                following,
+                context,
+            } => write!(
+                f,
+                "{} at position {}, near `{}`; read as `{}`",
+                ambiguity_msg(chosen, shorter, following),
+                position,
+                context,
+                chosen
+            ),
+        }
+    }
+}
+
+/// Former name of [`ExError`](ExError), kept for backwards compatibility.
+pub type ExParseError = ExError;
+
+/// Result type of the fallible functions of this crate, i.e., with the error
+/// [`ExError`](ExError) unless specified otherwise.
+pub type Result<T, E = ExError> = core::result::Result<T, E>;
+
+#[test]
+fn test_codes() {
+    let violations = [
+        PairViolation::AdjacentOperands,
+        PairViolation::OperandNextToParen,
+        PairViolation::OperandBeforeUnary,
+        PairViolation::AdjacentBinaryOps,
+        PairViolation::BinaryAfterUnary,
+        PairViolation::OpBeforeClosingParen,
+        PairViolation::UnaryAfterClosingParen,
+        PairViolation::BinaryAfterOpeningParen,
+        PairViolation::EmptyParens,
+        PairViolation::MisplacedPostfixUnary,
+        PairViolation::MisplacedComma,
+        PairViolation::DecimalComma,
+    ];
+    let s = || String::new();
+    let mut errors = vec![
+        ExError::EmptyInput,
+        ExError::UnexpectedToken {
+            position: 0,
+            found: s(),
+            context: s(),
+        },
+        ExError::InvalidNumber {
+            position: 0,
+            found: s(),
+            reason: s(),
+            context: s(),
+        },
+        ExError::ParenMismatch {
+            position: 0,
+            context: s(),
+        },
+        ExError::TrailingOperator {
+            position: 0,
+            context: s(),
+        },
+        ExError::UnterminatedBrace {
+            position: 0,
+            context: s(),
+        },
+        ExError::EmptyVarName {
+            position: 0,
+            context: s(),
+        },
+        ExError::UnterminatedComment {
+            position: 0,
+            context: s(),
+        },
+        ExError::WrongNumberOfArgs {
+            position: 0,
+            op: s(),
+            expected: 0,
+            given: 0,
+            context: s(),
+        },
+        ExError::InvalidLoopVar {
+            position: 0,
+            op: s(),
+            context: s(),
+        },
+        ExError::InvalidLoopBound {
+            position: 0,
+            op: s(),
+            context: s(),
+        },
+        ExError::TooManyLoopTerms {
+            position: 0,
+            max: 0,
+            context: s(),
+        },
+        ExError::InputTooLong { len: 0, max: 0 },
+        ExError::TooManyTokens {
+            position: 0,
+            max: 0,
+            context: s(),
+        },
+        ExError::DuplicateOperator(s()),
+        ExError::EmptyOperatorRepr,
+        ExError::WhitespaceInOperatorRepr(s()),
+        ExError::OperatorWithoutOperation(s()),
+        ExError::FallibleWithoutOperation(s()),
+        ExError::NoOperators {
+            position: 0,
+            context: s(),
+        },
+        ExError::InvalidNumberPattern(s()),
+        ExError::InvalidVarPattern(s()),
+        ExError::AmbiguousOperator {
+            position: 0,
+            chosen: s(),
+            shorter: s(),
+            following: s(),
+            context: s(),
+        },
+        ExError::UnaryChainTooLong {
+            position: 0,
+            end: 0,
+            len: 0,
+            max: 0,
+            context: s(),
+        },
+        ExError::TooManyNodes {
+            position: 0,
+            max: 0,
+            context: s(),
+        },
+        ExError::NestingTooDeep {
+            position: 0,
+            max: 0,
+            context: s(),
+        },
+        ExError::DomainViolation {
+            position: 0,
+            end: 0,
+            op: s(),
+            value: s(),
+            reason: s(),
+            context: s(),
+        },
+        ExError::WrongNumberOfVars {
+            expected: 0,
+            given: 0,
+        },
+        ExError::OperatorNotFound(s()),
+        ExError::OperatorNotBinary(s()),
+        ExError::OperatorNotUnary(s()),
+        ExError::DerivativeNotFound(s()),
+        ExError::DeepExMissing,
+        ExError::DivisionByZero {
+            numerator: s(),
+            denominator: s(),
+        },
+        ExError::ZeroPowZero,
+        ExError::IntervalOpNotFound(s()),
+        ExError::InvalidInterval(0),
+        ExError::IndistinguishableVars {
+            first: s(),
+            second: s(),
+        },
+        ExError::UnknownVar {
+            name: s(),
+            available: vec![],
+        },
+        ExError::DuplicateVar {
+            name: s(),
+            passed: vec![],
+        },
+        ExError::MissingVar(s()),
+        ExError::RowLengthMismatch {
+            row: 0,
+            expected: 0,
+            given: 0,
+        },
+        ExError::SeriesLengthMismatch {
+            series: 0,
+            expected: 0,
+            given: 0,
+        },
+        ExError::VarIdxOutOfRange { idx: 0, n_vars: 0 },
+        ExError::NonFiniteResult {
+            op: s(),
+            operands: vec![],
+            result: s(),
+        },
+        ExError::OperatorFailed {
+            op: s(),
+            operands: vec![],
+            reason: s(),
+        },
+        ExError::LocalRedefined {
+            local: s(),
+            position: 0,
+        },
+        ExError::LocalUsedBeforeDefinition {
+            local: s(),
+            position: 0,
+        },
+        ExError::MissingResult {
+            local: s(),
+            position: 0,
+        },
+        ExError::UnusedStatement { position: 0 },
+        ExError::PartialMismatch {
+            var_idx: 0,
+            point: vec![],
+            symbolic: s(),
+            numeric: s(),
+            max_deviation: s(),
+        },
+        ExError::Internal(s()),
+    ];
+    errors.extend(violations.iter().map(|violation| ExError::InvalidPair {
+        position: 0,
+        violation: *violation,
+        context: s(),
+    }));
+    assert_eq!(errors.len(), ERROR_CODES.len());
+    for (i, err) in errors.iter().enumerate() {
+        let code = err.code();
+        assert!(
+            ERROR_CODES.iter().any(|(c, _)| *c == code),
+            "{} not in table",
+            code
+        );
+        assert!(
+            errors[..i].iter().all(|other| other.code() != code),
+            "{} not unique",
+            code
+        );
+        assert!(err.msg().starts_with(&format!("[{}] ", code)));
+    }
+    assert_eq!(
+        ExError::EmptyInput.msg(),
+        "[E001] cannot parse empty string"
+    );
+}
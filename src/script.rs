@@ -0,0 +1,693 @@
+use core::{fmt::Debug, str::FromStr};
+
+use smallvec::SmallVec;
+
+use crate::{
+    alloc_prelude::*,
+    definitions::N_VARS_ON_STACK,
+    expression::{deep::DeepEx, flat},
+    parse, parser, ExError, FlatEx, Operator, ParseOptions,
+};
+
+/// Token that separates a local name from its defining expression.
+const BINDING_REPR: &str = ":=";
+/// Token that separates a name from its definition in a program.
+const DEFINITION_REPR: &str = "=";
+/// Token that separates statements.
+const SEPARATOR_REPR: char = ';';
+
+/// Statement of a script together with the slots of its variables.
+#[derive(Clone, Debug)]
+struct Statement<'a, T: Copy + Debug> {
+    expr: FlatEx<'a, T>,
+    /// The n-th variable of `expr` is the value in the slot `slots[n]`.
+    slots: SmallVec<[usize; N_VARS_ON_STACK]>,
+}
+
+impl<'a, T: Copy + Debug> Statement<'a, T> {
+    fn eval(
+        &self,
+        slot_values: &[T],
+        vars: &mut SmallVec<[T; N_VARS_ON_STACK]>,
+    ) -> Result<T, ExError> {
+        vars.clear();
+        vars.extend(self.slots.iter().map(|slot| slot_values[*slot]));
+        self.expr.eval(vars)
+    }
+}
+
+/// Sequence of statements created by [`parse_script`](parse_script). The value of a script
+/// is the value of its last statement. All statements before define locals that can be used
+/// in subsequent statements.
+#[derive(Clone, Debug)]
+pub struct ScriptEx<'a, T: Copy + Debug> {
+    bindings: Vec<Statement<'a, T>>,
+    result: Statement<'a, T>,
+    var_names: SmallVec<[&'a str; N_VARS_ON_STACK]>,
+    local_names: SmallVec<[&'a str; N_VARS_ON_STACK]>,
+}
+
+impl<'a, T: Copy + Debug> ScriptEx<'a, T> {
+    /// Evaluates the locals in the order of their definition and returns the value of the
+    /// last statement. Each local is evaluated exactly once.
+    ///
+    /// # Arguments
+    ///
+    /// * `vars` - Values of the free variables of all statements in the order of
+    ///   [`var_names`](ScriptEx::var_names).
+    ///
+    /// # Errors
+    ///
+    /// If the number of free variables is different from the length of the variable slice or
+    /// a [fallible operation](crate::FallibleOp) of a statement fails, we return an
+    /// [`ExError`](ExError).
+    ///
+    pub fn eval(&self, vars: &[T]) -> Result<T, ExError> {
+        if vars.len() != self.var_names.len() {
+            return Err(ExError::WrongNumberOfVars {
+                expected: self.var_names.len(),
+                given: vars.len(),
+            });
+        }
+        // the slots contain the free variables followed by the locals
+        let mut slot_values = vars
+            .iter()
+            .copied()
+            .collect::<SmallVec<[T; N_VARS_ON_STACK]>>();
+        let mut statement_vars = SmallVec::new();
+        for binding in &self.bindings {
+            let value = binding.eval(&slot_values, &mut statement_vars)?;
+            slot_values.push(value);
+        }
+        self.result.eval(&slot_values, &mut statement_vars)
+    }
+
+    /// Returns the names of the free variables of all statements in alphabetical order.
+    pub fn var_names(&self) -> &[&'a str] {
+        &self.var_names
+    }
+
+    /// Returns the names of the locals in the order of their definition.
+    pub fn local_names(&self) -> &[&'a str] {
+        &self.local_names
+    }
+}
+
+/// Statement of a script or a program before its expression is parsed.
+struct RawStatement<'a> {
+    /// The local or definition and its position.
+    local: Option<(&'a str, usize)>,
+    expr_text: &'a str,
+    expr_offset: usize,
+    offset: usize,
+}
+
+/// Splits `text` at the separators of statements that are not part of a variable name in
+/// curly braces, e.g., `{p;q}`.
+fn split_at_separators(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut idx = 0;
+    while let Some(c) = text[idx..].chars().next() {
+        match c {
+            '{' => idx += parser::end_of_braced_name(&text[idx..]).unwrap_or(0),
+            SEPARATOR_REPR => {
+                parts.push(&text[start..idx]);
+                start = idx + SEPARATOR_REPR.len_utf8();
+            }
+            _ => (),
+        }
+        idx += c.len_utf8();
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Splits `text` into statements. The position of the token `binding_repr` that separates
+/// a local from its expression is determined by `find_binding`.
+fn split_statements<'a, T: Copy + Debug>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+    binding_repr: &str,
+    find_binding: impl Fn(&str) -> Option<usize>,
+) -> Result<Vec<RawStatement<'a>>, ExError> {
+    // a single trailing separator is allowed
+    let text_wo_trailing = match text.trim_end().strip_suffix(SEPARATOR_REPR) {
+        Some(stripped) => stripped,
+        None => text,
+    };
+    let mut offset = 0;
+    let mut statements = Vec::new();
+    for statement in split_at_separators(text_wo_trailing) {
+        let (local, expr_text, expr_offset) = match find_binding(statement) {
+            Some(idx) => {
+                let name = statement[..idx].trim();
+                let name_offset = offset + statement[..idx].find(name).unwrap_or(0);
+                if !is_name(name) || ops.iter().any(|op| op.repr == name) {
+                    return Err(ExError::UnexpectedToken {
+                        position: name_offset,
+                        found: name.to_string(),
+                        context: parser::context(
+                            text,
+                            parser::Span {
+                                start: name_offset,
+                                end: name_offset + name.len(),
+                            },
+                        ),
+                    });
+                }
+                let expr_start = idx + binding_repr.len();
+                (
+                    Some((name, name_offset)),
+                    &statement[expr_start..],
+                    offset + expr_start,
+                )
+            }
+            None => (None, statement, offset),
+        };
+        statements.push(RawStatement {
+            local,
+            expr_text,
+            expr_offset,
+            offset,
+        });
+        offset += statement.len() + SEPARATOR_REPR.len_utf8();
+    }
+    let n_statements = statements.len();
+    if let Some(unused) = statements[..n_statements - 1]
+        .iter()
+        .find(|statement| statement.local.is_none() && !statement.expr_text.trim().is_empty())
+    {
+        let leading_ws = unused.expr_text.len() - unused.expr_text.trim_start().len();
+        return Err(ExError::UnusedStatement {
+            position: unused.offset + leading_ws,
+        });
+    }
+    Ok(statements)
+}
+
+/// Finds the `=` that separates a name from its definition, i.e., the first one that is
+/// not part of an operator such as `==` or `<=`.
+fn find_definition<T: Copy + Debug>(statement: &str, ops: &[Operator<'_, T>]) -> Option<usize> {
+    let bytes = statement.as_bytes();
+    let is_part_of_op = |idx: usize| {
+        ops.iter()
+            .filter(|op| op.repr != DEFINITION_REPR)
+            .any(|op| {
+                op.repr
+                    .bytes()
+                    .enumerate()
+                    .filter(|(_, b)| *b == b'=')
+                    .any(|(k, _)| idx >= k && bytes[idx - k..].starts_with(op.repr.as_bytes()))
+            })
+    };
+    statement
+        .match_indices(DEFINITION_REPR)
+        .map(|(idx, _)| idx)
+        .find(|idx| !is_part_of_op(*idx))
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Positions of errors from parsing a statement refer to the statement. This moves them to
+/// the script.
+fn shift_positions(err: ExError, offset: usize) -> ExError {
+    match err {
+        ExError::UnexpectedToken {
+            position,
+            found,
+            context,
+        } => ExError::UnexpectedToken {
+            position: position + offset,
+            found,
+            context,
+        },
+        ExError::InvalidPair {
+            position,
+            violation,
+            context,
+        } => ExError::InvalidPair {
+            position: position + offset,
+            violation,
+            context,
+        },
+        ExError::ParenMismatch { position, context } => ExError::ParenMismatch {
+            position: position + offset,
+            context,
+        },
+        ExError::TrailingOperator { position, context } => ExError::TrailingOperator {
+            position: position + offset,
+            context,
+        },
+        ExError::AmbiguousOperator {
+            position,
+            chosen,
+            shorter,
+            following,
+            context,
+        } => ExError::AmbiguousOperator {
+            position: position + offset,
+            chosen,
+            shorter,
+            following,
+            context,
+        },
+        ExError::UnaryChainTooLong {
+            position,
+            end,
+            len,
+            max,
+            context,
+        } => ExError::UnaryChainTooLong {
+            position: position + offset,
+            end: end + offset,
+            len,
+            max,
+            context,
+        },
+        ExError::DomainViolation {
+            position,
+            end,
+            op,
+            value,
+            reason,
+            context,
+        } => ExError::DomainViolation {
+            position: position + offset,
+            end: end + offset,
+            op,
+            value,
+            reason,
+            context,
+        },
+        _ => err,
+    }
+}
+
+/// Parses a script of statements separated by `;`. Each statement but the last one
+/// defines a local with `name := expression` that can be used in subsequent statements.
+/// The last statement is the expression whose value is the value of the script. All
+/// remaining variables are free variables of the script.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, parse_script};
+/// let script = parse_script::<f64>("a := x*2; a + y", &make_default_operators())?;
+/// assert_eq!(script.var_names(), ["x", "y"]);
+/// assert!((script.eval(&[1.5, 1.0])? - 4.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// An error is returned in case [`parse`](crate::parse) returns one for a statement, if a
+/// local is defined twice or used before its definition including its own definition, e.g.,
+/// `a := a + 1; a`, if a local is not named like a variable, if a statement other than the last one does not define a local, or if the
+/// last statement defines a local.
+///
+pub fn parse_script<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+) -> Result<ScriptEx<'a, T>, ExError>
+where
+    <T as FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let mut parsed = Vec::<(Option<(&'a str, usize)>, FlatEx<'a, T>, usize)>::new();
+    for statement in split_statements(text, ops, BINDING_REPR, |s| s.find(BINDING_REPR))? {
+        let expr = parse(statement.expr_text, ops)
+            .map_err(|e| shift_positions(e, statement.expr_offset))?;
+        parsed.push((statement.local, expr, statement.offset));
+    }
+
+    let (result_local, result_expr, _) = parsed.pop().ok_or(ExError::EmptyInput)?;
+    if let Some((name, position)) = result_local {
+        return Err(ExError::MissingResult {
+            local: name.to_string(),
+            position,
+        });
+    }
+
+    let mut local_names = SmallVec::<[&'a str; N_VARS_ON_STACK]>::new();
+    for (idx, (local, _, _)) in parsed.iter().enumerate() {
+        let (name, position) = local.expect("only the last statement can lack a local");
+        if local_names.contains(&name) {
+            return Err(ExError::LocalRedefined {
+                local: name.to_string(),
+                position,
+            });
+        }
+        // later statements might use the local but earlier ones and its definition must not
+        if let Some((_, _, statement_offset)) = parsed[..=idx]
+            .iter()
+            .find(|(_, expr, _)| expr.var_names().contains(&name))
+        {
+            return Err(ExError::LocalUsedBeforeDefinition {
+                local: name.to_string(),
+                position: *statement_offset,
+            });
+        }
+        local_names.push(name);
+    }
+
+    let mut var_names = SmallVec::<[&'a str; N_VARS_ON_STACK]>::new();
+    for expr in parsed
+        .iter()
+        .map(|(_, expr, _)| expr)
+        .chain(core::iter::once(&result_expr))
+    {
+        for name in expr.var_names() {
+            if !local_names.contains(name) && !var_names.contains(name) {
+                var_names.push(*name);
+            }
+        }
+    }
+    var_names.sort_unstable();
+
+    let make_statement = |expr: FlatEx<'a, T>| {
+        let slots = expr
+            .var_names()
+            .iter()
+            .map(|name| match var_names.iter().position(|n| n == name) {
+                Some(slot) => slot,
+                None => {
+                    let local_idx = local_names.iter().position(|n| n == name).unwrap();
+                    var_names.len() + local_idx
+                }
+            })
+            .collect();
+        Statement { expr, slots }
+    };
+    let bindings = parsed
+        .into_iter()
+        .map(|(_, expr, _)| make_statement(expr))
+        .collect();
+    let result = make_statement(result_expr);
+    Ok(ScriptEx {
+        bindings,
+        result,
+        var_names,
+        local_names,
+    })
+}
+
+/// Parses a program of statements separated by `;` into a single expression. Each
+/// statement but the last one defines a name with `name = expression`. Subsequent
+/// statements can use the name and it is replaced by its definition as in
+/// [`parse_with_definitions`](crate::parse_with_definitions). The last statement is the
+/// expression that is returned. Its variables are all remaining variables of the program.
+/// In contrast to [`parse_script`](parse_script), definitions are inlined, i.e., the result
+/// is a regular [`FlatEx`](FlatEx) that can, e.g., be unparsed or differentiated.
+///
+/// ```rust
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// #
+/// use exmex::{prelude::*, parse_program};
+/// let expr = parse_program::<f64>("a = x*2; b = a + 1; a*b", &make_default_operators())?;
+/// assert_eq!(expr.var_names(), ["x"]);
+/// assert_eq!(expr.unparse()?, "x*2.0*((x*2.0)+1.0)");
+/// assert!((expr.eval(&[1.5])? - 12.0).abs() < 1e-12);
+/// #
+/// #     Ok(())
+/// # }
+/// ```
+///
+/// A definition can refer to a variable with its own name, which is shadowed in all
+/// subsequent statements. For instance, `"x = x + 1; x^2"` is equivalent to `"(x + 1)^2"`.
+/// An `=` that is part of an operator such as `==` does not define a name.
+///
+/// # Errors
+///
+/// An error is returned in case [`parse`](crate::parse) returns one for a statement, if a
+/// name is defined twice or used by a statement before its definition, if a name is not
+/// named like a variable, if a statement other than the last one does not define a name,
+/// or if the last statement defines a name.
+///
+pub fn parse_program<'a, T>(
+    text: &'a str,
+    ops: &[Operator<'a, T>],
+) -> Result<FlatEx<'a, T>, ExError>
+where
+    <T as FromStr>::Err: Debug,
+    T: Copy + FromStr + Debug,
+{
+    let statements = split_statements(text, ops, DEFINITION_REPR, |s| find_definition(s, ops))?;
+    if let Some((name, position)) = statements.last().and_then(|statement| statement.local) {
+        return Err(ExError::MissingResult {
+            local: name.to_string(),
+            position,
+        });
+    }
+    let mut definitions = Vec::<(&'a str, DeepEx<'a, T>)>::new();
+    // remaining variables of the definitions and the offsets of their statements
+    let mut used = Vec::<(usize, SmallVec<[&'a str; N_VARS_ON_STACK]>)>::new();
+    for statement in statements {
+        let defs = definitions
+            .iter()
+            .map(|(name, def)| (*name, def))
+            .collect::<Vec<_>>();
+        let (deepex, _) =
+            DeepEx::from_options(statement.expr_text, ops, &ParseOptions::default(), &defs)
+                .map_err(|e| shift_positions(e, statement.expr_offset))?;
+        let (name, position) = match statement.local {
+            Some(local) => local,
+            None => return flat::flatten(deepex),
+        };
+        if definitions.iter().any(|(defined, _)| *defined == name) {
+            return Err(ExError::LocalRedefined {
+                local: name.to_string(),
+                position,
+            });
+        }
+        if let Some((statement_offset, _)) =
+            used.iter().find(|(_, var_names)| var_names.contains(&name))
+        {
+            return Err(ExError::LocalUsedBeforeDefinition {
+                local: name.to_string(),
+                position: *statement_offset,
+            });
+        }
+        used.push((
+            statement.offset,
+            deepex.var_names().iter().copied().collect(),
+        ));
+        definitions.push((name, deepex));
+    }
+    Err(ExError::Internal(
+        "the last statement of a program needs to be an expression".to_string(),
+    ))
+}
+
+#[cfg(all(test, feature = "std"))]
+use {
+    crate::{
+        make_default_operators, make_default_operators_int, util::assert_float_eq_f64,
+        UnaryPosition,
+    },
+    std::sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_script() {
+    let ops = make_default_operators::<f64>();
+    let script = parse_script("a := x*2; a + y", &ops).unwrap();
+    assert_eq!(script.var_names(), ["x", "y"]);
+    assert_eq!(script.local_names(), ["a"]);
+    assert_float_eq_f64(script.eval(&[1.5, 1.0]).unwrap(), 4.0);
+    assert_eq!(
+        script.eval(&[1.5]),
+        Err(ExError::WrongNumberOfVars {
+            expected: 2,
+            given: 1
+        })
+    );
+
+    let script = parse_script("b := z - 1; c := b^2 + b; sin(c) * x;", &ops).unwrap();
+    assert_eq!(script.var_names(), ["x", "z"]);
+    let (x, z) = (0.5, 3.0);
+    let b: f64 = z - 1.0;
+    assert_float_eq_f64(script.eval(&[x, z]).unwrap(), (b * b + b).sin() * x);
+
+    let script = parse_script("2 + 3", &ops).unwrap();
+    assert_float_eq_f64(script.eval(&[]).unwrap(), 5.0);
+
+    // fallible operations are checked in each statement
+    let ops = make_default_operators_int::<i64>();
+    let script = parse_script("a := x/y; a", &ops).unwrap();
+    assert_eq!(script.eval(&[6, 3]), Ok(2));
+    match script.eval(&[1, 0]) {
+        Err(ExError::OperatorFailed { reason, .. }) => assert_eq!(reason, "division by zero"),
+        res => panic!("expected a failure, got {:?}", res),
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_script_eval_once() {
+    static N_CALLS: AtomicUsize = AtomicUsize::new(0);
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(Operator {
+        repr: "counted",
+        bin_op: None,
+        unary_op: Some(|a| {
+            N_CALLS.fetch_add(1, Ordering::SeqCst);
+            a
+        }),
+        domain_check: None,
+        unary_position: UnaryPosition::Prefix,
+        nary_op: None,
+        fallible: None,
+        aliases: &[],
+    });
+    let script = parse_script("a := counted(x) + 1; a * a + a", &ops).unwrap();
+    assert_float_eq_f64(script.eval(&[2.0]).unwrap(), 12.0);
+    assert_eq!(N_CALLS.load(Ordering::SeqCst), 1);
+    assert_float_eq_f64(script.eval(&[1.0]).unwrap(), 6.0);
+    assert_eq!(N_CALLS.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_script_errors() {
+    let ops = make_default_operators::<f64>();
+    let parse_err = |text| parse_script::<f64>(text, &ops).unwrap_err();
+    assert_eq!(
+        parse_err("a := x; a := 2*a; a"),
+        ExError::LocalRedefined {
+            local: "a".to_string(),
+            position: 8
+        }
+    );
+    assert_eq!(
+        parse_err("b := a + 1; a := x; a + b"),
+        ExError::LocalUsedBeforeDefinition {
+            local: "a".to_string(),
+            position: 0
+        }
+    );
+    assert_eq!(
+        parse_err("a := a + 1; a"),
+        ExError::LocalUsedBeforeDefinition {
+            local: "a".to_string(),
+            position: 0
+        }
+    );
+    assert_eq!(
+        parse_err("a := x; b := a*2"),
+        ExError::MissingResult {
+            local: "b".to_string(),
+            position: 8
+        }
+    );
+    assert_eq!(parse_err("a := x; 2 {a}").code(), "E014");
+    assert_eq!(parse_err("a := x; 2 {a}").position(), Some(10));
+    assert_eq!(parse_err("2*a := x; a").code(), "E002");
+    assert_eq!(parse_err("sin := x; sin").code(), "E002");
+    assert_eq!(parse_err("a := x;; a").code(), "E001");
+    assert_eq!(
+        parse_err("a := x; 2*a; a"),
+        ExError::UnusedStatement { position: 8 }
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_program_errors() {
+    let ops = make_default_operators::<f64>();
+    let parse_err = |text| parse_program::<f64>(text, &ops).unwrap_err();
+    assert_eq!(
+        parse_err("a = x; a = 2*a; a"),
+        ExError::LocalRedefined {
+            local: "a".to_string(),
+            position: 7
+        }
+    );
+    assert_eq!(
+        parse_err("b = a + 1; a = x; a + b"),
+        ExError::LocalUsedBeforeDefinition {
+            local: "a".to_string(),
+            position: 0
+        }
+    );
+    assert_eq!(
+        parse_err("a = x; b = a*2"),
+        ExError::MissingResult {
+            local: "b".to_string(),
+            position: 7
+        }
+    );
+    assert_eq!(
+        parse_err("a = x; 2*a; a"),
+        ExError::UnusedStatement { position: 7 }
+    );
+    assert_eq!(parse_err("a = x; 2 {a}").code(), "E014");
+    assert_eq!(parse_err("a = x; 2 {a}").position(), Some(9));
+    assert_eq!(parse_err("2*a = x; a").code(), "E002");
+    assert_eq!(parse_err("").code(), "E001");
+    assert_eq!(parse_err("a = x;; a").code(), "E001");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_separators_in_braced_names() {
+    let ops = make_default_operators::<f64>();
+    let script = parse_script("a := {p;q}*2; a", &ops).unwrap();
+    assert_eq!(script.var_names(), ["p;q"]);
+    assert_float_eq_f64(script.eval(&[1.5]).unwrap(), 3.0);
+    let expr = parse_program("a = {p;q}*2; a", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["p;q"]);
+    assert_float_eq_f64(expr.eval(&[1.5]).unwrap(), 3.0);
+
+    // escaped braces do not end the name
+    let script = parse_script(r"a := {p\};q} + 1; a*x", &ops).unwrap();
+    assert_eq!(script.var_names(), [r"p\};q", "x"]);
+    assert_float_eq_f64(script.eval(&[1.0, 2.0]).unwrap(), 4.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_program() {
+    let ops = make_default_operators::<f64>();
+    let expr = parse_program("a = x*2; b = a + 1; a*b", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["x"]);
+    assert_eq!(expr.unparse().unwrap(), "x*2.0*((x*2.0)+1.0)");
+    assert_float_eq_f64(expr.eval(&[1.5]).unwrap(), 12.0);
+    assert_float_eq_f64(expr.partial(0).unwrap().eval(&[1.5]).unwrap(), 14.0);
+
+    let expr = parse_program("c = z - y; d = c^2 + c; e = sin(d); e*c;", &ops).unwrap();
+    let inlined = parse("sin((z - y)^2 + (z - y))*(z - y)", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["y", "z"]);
+    assert_float_eq_f64(
+        expr.eval(&[0.5, 3.0]).unwrap(),
+        inlined.eval(&[0.5, 3.0]).unwrap(),
+    );
+
+    // definitions shadow variables with the same name
+    let expr = parse_program("x = x + 1; y = x*y; x^2 + y", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["x", "y"]);
+    assert_float_eq_f64(expr.eval(&[2.0, 5.0]).unwrap(), 24.0);
+    let expr = parse_program("y = 2*x; x*y", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["x"]);
+    assert_float_eq_f64(expr.eval(&[3.0]).unwrap(), 18.0);
+
+    // comparisons are not definitions
+    let expr = parse_program("a = x == 2; a >= (y == 1)", &ops).unwrap();
+    assert_eq!(expr.var_names(), ["x", "y"]);
+    assert_float_eq_f64(expr.eval(&[2.0, 3.0]).unwrap(), 1.0);
+    assert_float_eq_f64(expr.eval(&[3.0, 1.0]).unwrap(), 0.0);
+
+    assert_float_eq_f64(
+        parse_program("2 + 3", &ops).unwrap().eval(&[]).unwrap(),
+        5.0,
+    );
+}
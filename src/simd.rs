@@ -0,0 +1,355 @@
+//! SIMD vectors of floats that implement [`DefaultOpsFloat`](crate::DefaultOpsFloat),
+//! available with the feature `simd`. Hence, the
+//! [default operators](crate::make_default_operators) exist for them and a parsed
+//! expression evaluates 4 `f64`s or 8 `f32`s at once, where each variable holds one
+//! batch of values.
+//!
+//! ```rust
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! #
+//! use exmex::simd::{self, F64x4};
+//! let (expr, expr_simd) = simd::parse_f64x4("sin(x) + y^2")?;
+//! let xs = [0.0, 0.5, 1.0, 1.5];
+//! let res = expr_simd.eval(&[F64x4::from(xs), F64x4::splat(2.0)])?;
+//! for (x, r) in xs.iter().zip(res.to_array().iter()) {
+//!     assert!((expr.eval(&[*x, 2.0])? - r).abs() < 1e-12);
+//! }
+//! #
+//! #     Ok(())
+//! # }
+//! ```
+//!
+//! The functions are the ones of the crate [`wide`](https://docs.rs/wide) and might
+//! deviate from their scalar counterparts in the last digits. Functions that `wide` does
+//! not vectorize, e.g., `sinh` or `%`, are applied lane by lane. Comparisons result in
+//! one or zero per lane. The speedup over scalar evaluation depends on the
+//! available instructions, e.g., AVX can be enabled with `RUSTFLAGS="-C target-cpu=native"`.
+use core::{
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+    str::FromStr,
+};
+
+use wide::{f32x8, f64x4, CmpEq, CmpLe, CmpLt, CmpNe};
+
+use crate::{parse_with_default_ops, DefaultOpsFloat, ExError, FlatEx};
+
+macro_rules! simd_float {
+    ($(#[$meta:meta])* $name:ident, $wide:ident, $elem:ident, $lanes:literal, $pow:ident) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, Default, PartialEq)]
+        pub struct $name(pub $wide);
+
+        impl $name {
+            /// Returns a vector with all lanes set to `x`.
+            pub fn splat(x: $elem) -> Self {
+                $name($wide::splat(x))
+            }
+            pub fn to_array(self) -> [$elem; $lanes] {
+                self.0.to_array()
+            }
+            fn map(self, f: fn($elem) -> $elem) -> Self {
+                let mut lanes = self.to_array();
+                for x in lanes.iter_mut() {
+                    *x = f(*x);
+                }
+                $name::from(lanes)
+            }
+            fn zip_map(self, other: Self, f: fn($elem, $elem) -> $elem) -> Self {
+                let mut lanes = self.to_array();
+                for (x, y) in lanes.iter_mut().zip(other.to_array().iter()) {
+                    *x = f(*x, *y);
+                }
+                $name::from(lanes)
+            }
+            fn indicator(mask: $wide) -> Self {
+                $name(mask.blend($wide::splat(1.0), $wide::splat(0.0)))
+            }
+        }
+
+        impl From<[$elem; $lanes]> for $name {
+            fn from(lanes: [$elem; $lanes]) -> Self {
+                $name($wide::new(lanes))
+            }
+        }
+
+        /// Parses a number that is assigned to all lanes.
+        impl FromStr for $name {
+            type Err = <$elem as FromStr>::Err;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok($name::splat(s.parse()?))
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, other: Self) -> Self {
+                $name(self.0 + other.0)
+            }
+        }
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, other: Self) -> Self {
+                $name(self.0 - other.0)
+            }
+        }
+        impl Mul for $name {
+            type Output = Self;
+            fn mul(self, other: Self) -> Self {
+                $name(self.0 * other.0)
+            }
+        }
+        impl Div for $name {
+            type Output = Self;
+            fn div(self, other: Self) -> Self {
+                $name(self.0 / other.0)
+            }
+        }
+        impl Rem for $name {
+            type Output = Self;
+            fn rem(self, other: Self) -> Self {
+                self.zip_map(other, |a, b| a % b)
+            }
+        }
+        impl Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self {
+                $name(-self.0)
+            }
+        }
+
+        impl DefaultOpsFloat for $name {
+            fn from_f64(x: f64) -> Self {
+                $name::splat(x as $elem)
+            }
+            fn indicator_lt(self, other: Self) -> Self {
+                $name::indicator(self.0.cmp_lt(other.0))
+            }
+            fn indicator_le(self, other: Self) -> Self {
+                $name::indicator(self.0.cmp_le(other.0))
+            }
+            fn indicator_eq(self, other: Self) -> Self {
+                $name::indicator(self.0.cmp_eq(other.0))
+            }
+            fn any_nonzero(self) -> bool {
+                self.0.cmp_ne($wide::splat(0.0)).any()
+            }
+            // the power of wide is wrong for negative bases, hence, we take the power of
+            // the absolute value and fix the sign for integral exponents
+            fn powf(self, exponent: Self) -> Self {
+                let (base, exponent) = (self.0, exponent.0);
+                let power = base.abs().$pow(exponent);
+                let half = exponent * $wide::splat(0.5);
+                let is_odd = half.floor().cmp_ne(half);
+                let power_neg_base = exponent.floor().cmp_eq(exponent).blend(
+                    is_odd.blend(-power, power),
+                    $wide::splat($elem::NAN),
+                );
+                $name(base.cmp_lt($wide::splat(0.0)).blend(power_neg_base, power))
+            }
+            fn log(self, base: Self) -> Self {
+                $name(self.0.ln() / base.0.ln())
+            }
+            fn abs(self) -> Self {
+                $name(self.0.abs())
+            }
+            fn sin(self) -> Self {
+                $name(self.0.sin())
+            }
+            fn cos(self) -> Self {
+                $name(self.0.cos())
+            }
+            fn tan(self) -> Self {
+                $name(self.0.tan())
+            }
+            fn asin(self) -> Self {
+                $name(self.0.asin())
+            }
+            fn acos(self) -> Self {
+                $name(self.0.acos())
+            }
+            fn atan(self) -> Self {
+                $name(self.0.atan())
+            }
+            fn floor(self) -> Self {
+                $name(self.0.floor())
+            }
+            fn ceil(self) -> Self {
+                $name(self.0.ceil())
+            }
+            fn exp(self) -> Self {
+                $name(self.0.exp())
+            }
+            fn sqrt(self) -> Self {
+                $name(self.0.sqrt())
+            }
+            fn ln(self) -> Self {
+                $name(self.0.ln())
+            }
+            fn log2(self) -> Self {
+                $name(self.0.log2())
+            }
+            fn log10(self) -> Self {
+                $name(self.0.log10())
+            }
+            // rounds half-way cases away from zero like the scalar version, which the
+            // vectorized round of wide does not
+            fn round(self) -> Self {
+                self.map($elem::round)
+            }
+            fn signum(self) -> Self {
+                self.map($elem::signum)
+            }
+            fn sinh(self) -> Self {
+                self.map($elem::sinh)
+            }
+            fn cosh(self) -> Self {
+                self.map($elem::cosh)
+            }
+            fn tanh(self) -> Self {
+                self.map($elem::tanh)
+            }
+            fn asinh(self) -> Self {
+                self.map($elem::asinh)
+            }
+            fn acosh(self) -> Self {
+                self.map($elem::acosh)
+            }
+            fn atanh(self) -> Self {
+                self.map($elem::atanh)
+            }
+            fn trunc(self) -> Self {
+                self.map($elem::trunc)
+            }
+            fn fract(self) -> Self {
+                self.map($elem::fract)
+            }
+            fn exp2(self) -> Self {
+                self.map($elem::exp2)
+            }
+            fn cbrt(self) -> Self {
+                self.map($elem::cbrt)
+            }
+        }
+    };
+}
+
+simd_float!(
+    /// Four `f64` lanes.
+    F64x4,
+    f64x4,
+    f64,
+    4,
+    pow_f64x4
+);
+simd_float!(
+    /// Eight `f32` lanes.
+    F32x8,
+    f32x8,
+    f32,
+    8,
+    pow_f32x8
+);
+
+/// Parses a string with the default operators once and returns the expression for `f64`
+/// together with its counterpart for batches of 4 values, see
+/// [`to_f64x4`](FlatEx::to_f64x4).
+///
+/// # Errors
+///
+/// An error is returned if the string cannot be parsed, see
+/// [`parse_with_default_ops`](crate::parse_with_default_ops).
+///
+pub fn parse_f64x4(text: &str) -> Result<(FlatEx<'_, f64>, FlatEx<'_, F64x4>), ExError> {
+    let expr = parse_with_default_ops::<f64>(text)?;
+    let expr_simd = expr.to_f64x4()?;
+    Ok((expr, expr_simd))
+}
+
+/// Parses a string like [`parse_f64x4`](parse_f64x4) for `f32` and batches of 8 values.
+///
+/// # Errors
+///
+/// See [`parse_f64x4`](parse_f64x4).
+///
+pub fn parse_f32x8(text: &str) -> Result<(FlatEx<'_, f32>, FlatEx<'_, F32x8>), ExError> {
+    let expr = parse_with_default_ops::<f32>(text)?;
+    let expr_simd = expr.to_f32x8()?;
+    Ok((expr, expr_simd))
+}
+
+#[cfg(test)]
+use crate::{make_default_constants, parse_with_constants};
+
+#[cfg(test)]
+fn assert_lanes_close(scalar: &[f64], lanes: &[f64], tol: f64) {
+    for (s, l) in scalar.iter().zip(lanes.iter()) {
+        if s.is_nan() {
+            assert!(l.is_nan(), "expected NaN, got {}", l);
+        } else {
+            assert!(
+                (s - l).abs() <= tol * s.abs().max(1.0),
+                "scalar {}, simd {}",
+                s,
+                l
+            );
+        }
+    }
+}
+
+#[test]
+fn test_simd_default_ops() -> Result<(), ExError> {
+    let xs = [0.3, -0.7, 0.5, 0.9];
+    let ys = [1.5, 2.0, -3.0, 0.25];
+    let texts = [
+        "sin(x)+cos(y)*tan(x)-asin(x)/acos(x)+atan(y)",
+        "sinh(x)*cosh(y)-tanh(y)+asinh(y)+atanh(x)",
+        "acosh(abs(y)+1)+abs(x)*signum(y)",
+        "sign(x-0.5)*y+sign(y)",
+        "floor(y)+ceil(x)+trunc(y)+fract(y)+round(y*2)+round(x)",
+        "exp(x)+exp2(y)+sqrt(abs(y))+cbrt(y)+ln(abs(y))+log(abs(y))",
+        "log2(abs(y))+log10(abs(x))+logb(3, abs(y))",
+        "x^2+abs(y)^x+y^3+y^-2+y^x+x^y+y%x",
+        "(x<y)+(x<=y)*2+(x>y)*4+(x>=y)*8+(x==0.5)*16+(x!=0.5)*32",
+        "-x+y*x/(y-x)",
+    ];
+    for text in texts.iter() {
+        let (expr, expr_simd) = parse_f64x4(text)?;
+        let scalar = xs
+            .iter()
+            .zip(ys.iter())
+            .map(|(x, y)| expr.eval(&[*x, *y]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let res = expr_simd.eval(&[F64x4::from(xs), F64x4::from(ys)])?;
+        assert_lanes_close(&scalar, &res.to_array(), 1e-12);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_simd_f32() -> Result<(), ExError> {
+    let xs = [0.1, 0.2, -0.3, 0.4, 0.5, -0.6, 0.7, 0.8];
+    let (expr, expr_simd) = parse_f32x8("sin(x)+sinh(x)*x^2-(x<0.45)")?;
+    let res = expr_simd.eval(&[F32x8::from(xs)])?;
+    for (x, r) in xs.iter().zip(res.to_array().iter()) {
+        assert!((expr.eval(&[*x])? - r).abs() < 1e-6);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_simd_constants() -> Result<(), ExError> {
+    let constants = make_default_constants::<F64x4>();
+    let ops = crate::make_default_operators::<F64x4>();
+    let expr = parse_with_constants("2*pi+x", &ops, &constants)?;
+    let res = expr.eval(&[F64x4::from([0.0, 1.0, 2.0, 3.0])])?;
+    let pi2 = 2.0 * core::f64::consts::PI;
+    assert_lanes_close(
+        &[pi2, pi2 + 1.0, pi2 + 2.0, pi2 + 3.0],
+        &res.to_array(),
+        1e-12,
+    );
+    assert_eq!("2.5".parse::<F64x4>(), Ok(F64x4::splat(2.5)));
+    assert!("a".parse::<F32x8>().is_err());
+    Ok(())
+}
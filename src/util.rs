@@ -1,15 +1,24 @@
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 pub fn assert_float_eq<T: num::Float + std::fmt::Display>(f1: T, f2: T, tol: T, msg: &str) {
     if (f1 - f2).abs() >= tol {
-        println!("Floats not almost equal. {}\nf1: {}\nf2: {}\n", msg, f1, f2);
-        assert!(false);
+        panic!("Floats not almost equal. {}\nf1: {}\nf2: {}\n", msg, f1, f2);
     }
 }
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 pub fn assert_float_eq_f32(f1: f32, f2: f32) {
     assert_float_eq(f1, f2, 1e-6, "");
 }
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 pub fn assert_float_eq_f64(f1: f64, f2: f64) {
     assert_float_eq(f1, f2, 1e-12, "");
 }
+
+/// Returns the number of bytes the elements of `v` occupy on the heap, which is zero if
+/// they fit onto the stack.
+pub fn spilled_bytes<A: smallvec::Array>(v: &smallvec::SmallVec<A>) -> usize {
+    if v.spilled() {
+        v.capacity() * core::mem::size_of::<A::Item>()
+    } else {
+        0
+    }
+}
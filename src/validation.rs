@@ -0,0 +1,174 @@
+use smallvec::SmallVec;
+
+use crate::{alloc_prelude::*, operators::Operator, parser, ExError};
+
+/// Checks that the operators can be used for parsing, i.e., each representation and alias
+/// of an operator is non-empty, without whitespace, and unique among the operators, each
+/// operator has at least one operation, and an infallible operation for each fallible one.
+///
+/// # Errors
+///
+/// An [`ExError`](ExError) is returned for the first operator that violates one of the
+/// conditions.
+pub fn check_operators<T>(ops: &[Operator<T>]) -> Result<(), ExError> {
+    for op in ops {
+        for repr in core::iter::once(&op.repr).chain(op.aliases) {
+            if repr.is_empty() {
+                return Err(ExError::EmptyOperatorRepr);
+            }
+            if repr.contains(char::is_whitespace) {
+                return Err(ExError::WhitespaceInOperatorRepr(repr.to_string()));
+            }
+        }
+        if op.bin_op.is_none() && op.unary_op.is_none() && op.nary_op.is_none() {
+            return Err(ExError::OperatorWithoutOperation(op.repr.to_string()));
+        }
+        if let Some(fallible) = &op.fallible {
+            if (fallible.bin_op.is_some() && op.bin_op.is_none())
+                || (fallible.unary_op.is_some() && op.unary_op.is_none())
+            {
+                return Err(ExError::FallibleWithoutOperation(op.repr.to_string()));
+            }
+        }
+    }
+    let mut reprs = ops
+        .iter()
+        .flat_map(|op| core::iter::once(op.repr).chain(op.aliases.iter().copied()))
+        .collect::<SmallVec<[_; 64]>>();
+    reprs.sort_unstable();
+    match reprs.windows(2).find(|w| w[0] == w[1]) {
+        Some(w) => Err(ExError::DuplicateOperator(w[0].to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `text` consists only of numbers and parentheses, which is all that can be
+/// parsed without operators.
+///
+/// # Errors
+///
+/// An [`ExError`](ExError) is returned at the first token that is not a number or a
+/// parenthesis.
+pub fn check_text_without_ops<'a, F: Fn(&'a str) -> Option<&'a str>>(
+    text: &'a str,
+    is_numeric: &F,
+) -> Result<(), ExError> {
+    let mut offset = 0;
+    while let Some(c) = text[offset..].chars().next() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            offset += c.len_utf8();
+        } else if let Some(num_str) = is_numeric(&text[offset..]).filter(|n| !n.is_empty()) {
+            offset += num_str.len();
+        } else {
+            let span = parser::Span {
+                start: offset,
+                end: offset + c.len_utf8(),
+            };
+            return Err(ExError::NoOperators {
+                position: offset,
+                context: parser::context(text, span),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+use crate::{
+    operators::{make_default_operators, BinOp, FallibleOp, UnaryPosition},
+    parse, parse_with_number_pattern,
+    util::assert_float_eq_f64,
+};
+
+#[cfg(feature = "std")]
+#[test]
+fn test_check_operators() {
+    fn make_op(repr: &str, with_bin_op: bool) -> Operator<'_, f64> {
+        Operator {
+            repr,
+            bin_op: if with_bin_op {
+                Some(BinOp {
+                    apply: |a, b| a * b,
+                    prio: 1,
+                })
+            } else {
+                None
+            },
+            unary_op: None,
+            domain_check: None,
+            unary_position: UnaryPosition::Prefix,
+            nary_op: None,
+            fallible: None,
+            aliases: &[],
+        }
+    }
+    assert_eq!(check_operators(&make_default_operators::<f64>()), Ok(()));
+    assert_eq!(check_operators::<f64>(&[]), Ok(()));
+
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(make_op("*", true));
+    assert_eq!(
+        parse("2*x", &ops),
+        Err(ExError::DuplicateOperator("*".to_string()))
+    );
+    let ops = [make_op("", true)];
+    assert_eq!(parse("2", &ops), Err(ExError::EmptyOperatorRepr));
+    let ops = [make_op("*", true), make_op("<\u{a0}=", true)];
+    let err = parse("2*3", &ops).unwrap_err();
+    assert_eq!(
+        err,
+        ExError::WhitespaceInOperatorRepr("<\u{a0}=".to_string())
+    );
+    assert_eq!(err.code(), "E038");
+    let ops = [make_op("*", true), make_op("dup", false)];
+    let err = parse("2*3", &ops).unwrap_err();
+    assert_eq!(err, ExError::OperatorWithoutOperation("dup".to_string()));
+    assert!(err.msg().contains("`dup`"));
+
+    // fallible variants need the infallible operation
+    let mut op = make_op("dup", false);
+    op.unary_op = Some(|a| a);
+    op.fallible = Some(FallibleOp {
+        bin_op: Some(|a, b| Ok(a * b)),
+        unary_op: None,
+    });
+    let ops = [make_op("*", true), op];
+    let err = parse("2*3", &ops).unwrap_err();
+    assert_eq!(err, ExError::FallibleWithoutOperation("dup".to_string()));
+    assert_eq!(err.code(), "E056");
+
+    // the number pattern path validates as well
+    let ops = [make_op("*", true), make_op("*", true)];
+    assert_eq!(
+        parse_with_number_pattern("2*3", &ops, "[0-9]+"),
+        Err(ExError::DuplicateOperator("*".to_string()))
+    );
+
+    // an operator can be binary and unary at the same time
+    let ops = make_default_operators::<f64>();
+    let minus = ops.iter().find(|op| op.repr == "-").unwrap();
+    assert!(minus.bin_op.is_some() && minus.unary_op.is_some());
+    assert_float_eq_f64(parse("-2-x", &ops).unwrap().eval(&[1.0]).unwrap(), -3.0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_no_operators() {
+    let no_ops: [Operator<f64>; 0] = [];
+    assert_float_eq_f64(parse(" (2.5) ", &no_ops).unwrap().eval(&[]).unwrap(), 2.5);
+    assert_eq!(
+        parse("2*x", &no_ops),
+        Err(ExError::NoOperators {
+            position: 1,
+            context: "2*x".to_string()
+        })
+    );
+    assert!(matches!(
+        parse("x", &no_ops),
+        Err(ExError::NoOperators { position: 0, .. })
+    ));
+    assert!(matches!(
+        parse_with_number_pattern("(1) {x}", &no_ops, "[0-9]+"),
+        Err(ExError::NoOperators { position: 4, .. })
+    ));
+}
@@ -0,0 +1,80 @@
+//! Uses only the parts of the crate that are available without the default feature `std`,
+//! e.g., with `cargo test --no-default-features --test no_std`.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use exmex::{
+    make_default_operators_int, parse, BinOp, EvalBuffer, ExError, ExprBuilder, Operator,
+    OwnedFlatEx,
+};
+
+fn ops<'a>() -> [Operator<'a, f64>; 3] {
+    [
+        Operator::make_bin(
+            "+",
+            BinOp {
+                apply: |a, b| a + b,
+                prio: 0,
+            },
+        ),
+        Operator::make_bin_unary(
+            "-",
+            BinOp {
+                apply: |a, b| a - b,
+                prio: 0,
+            },
+            |a| -a,
+        ),
+        Operator::make_bin(
+            "*",
+            BinOp {
+                apply: |a, b| a * b,
+                prio: 1,
+            },
+        ),
+    ]
+}
+
+#[test]
+fn test_eval_built() -> Result<(), ExError> {
+    let ops = ops();
+    // -(x*x) + 2*y
+    let expr = ExprBuilder::var("x")
+        .combine(ExprBuilder::var("x"), "*")
+        .apply_unary("-")
+        .combine(
+            ExprBuilder::num(2.0).combine(ExprBuilder::var("y"), "*"),
+            "+",
+        )
+        .build(&ops)?;
+    assert_eq!(expr.var_names(), ["x", "y"]);
+    assert_eq!(expr.eval(&[3.0, 0.5])?, -8.0);
+    let mut buffer = EvalBuffer::new();
+    assert_eq!(expr.eval_with_buffer(&[-1.0, 2.0], &mut buffer)?, 3.0);
+    let mut out = [0.0; 2];
+    expr.eval_vectorized(&[&[3.0, -1.0], &[0.5, 2.0]], &mut out)?;
+    assert_eq!(out, [-8.0, 3.0]);
+    let owned = OwnedFlatEx::from_flatex(expr);
+    assert_eq!(owned.eval(&[0.0, 1.0])?, 2.0);
+    Ok(())
+}
+
+#[test]
+fn test_eval_parsed() -> Result<(), ExError> {
+    let ops = ops();
+    let expr = parse::<f64>("x*(y - 1.5) + -x", &ops)?;
+    assert_eq!(expr.eval(&[2.0, 3.0])?, 1.0);
+    assert_eq!(expr.unparse()?, "x*(y-1.5)+-x");
+    assert!(parse::<f64>("x +", &ops).is_err());
+
+    let int_ops = make_default_operators_int::<u32>();
+    let expr = parse::<u32>("(a << 2) | b % 3", &int_ops)?;
+    let results = [(1, 5), (2, 7)]
+        .iter()
+        .map(|(a, b)| expr.eval(&[*a, *b]))
+        .collect::<Result<Vec<_>, ExError>>()?;
+    assert_eq!(results, [6, 9]);
+    Ok(())
+}
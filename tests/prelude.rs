@@ -0,0 +1,61 @@
+//! Uses only the items of `exmex::prelude`.
+#![cfg(feature = "std")]
+
+use exmex::prelude::*;
+
+fn parse_default(text: &str) -> Result<FlatEx<'_, f64>, ExError> {
+    parse_with_default_ops::<f64>(text)
+}
+
+#[test]
+fn test_prelude() -> Result<(), ExError> {
+    assert_eq!(eval_str("2*pi/pi")?, 2.0);
+
+    let expr = parse_default("x^2 + sin(y)")?;
+    assert_eq!(expr.eval(&[3.0, 0.0])?, 9.0);
+    let deep = DeepEx::<f64>::from_str("x^2 + sin(y)")?;
+    assert_eq!(deep.var_names(), ["x", "y"]);
+    assert_eq!(expr.clone().partial(0)?.eval(&[3.0, 0.0])?, 6.0);
+    let owned = OwnedFlatEx::from_flatex(expr);
+    assert_eq!(owned.eval(&[1.0, 0.0])?, 1.0);
+
+    let mut ops = make_default_operators::<f64>().to_vec();
+    ops.push(Operator::make_unary("invert", |a| 1.0 / a));
+    assert_eq!(
+        ops.last().map(|op| op.unary_position),
+        Some(UnaryPosition::Prefix)
+    );
+    ops.push(Operator::make_nary(
+        "max",
+        NaryOp {
+            apply: |args| args.iter().copied().fold(f64::MIN, f64::max),
+            arity: 3,
+        },
+    ));
+    let expr = parse("invert(max(1, x, 4))", &ops)?;
+    assert_eq!(expr.eval(&[2.0])?, 0.25);
+    let options = ParseOptions {
+        semantics: Semantics::IeeeStrict,
+        limits: ParseLimits::default(),
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        parse_with_options("x*0", &ops, &options)?.eval(&[1.0])?,
+        0.0
+    );
+
+    let expr = parse_with_factory::<f64, DefaultOpsFactory<f64>>("cos(x)")?;
+    assert_eq!(expr.eval(&[0.0])?, 1.0);
+    assert_eq!(DefaultOpsFactory::<f64>::make().len(), ops.len() - 2);
+
+    let int_ops = make_default_operators_int::<i64>();
+    assert_eq!(
+        parse_with_number_pattern("7 % 4", &int_ops, "[0-9]+")?.eval(&[])?,
+        3
+    );
+    assert!(matches!(
+        parse_default("(x + y"),
+        Err(ExParseError::ParenMismatch { .. })
+    ));
+    Ok(())
+}